@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use russh_keys::agent::client::AgentClient;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::offline;
+
+/// SSH agent 连接使用的流（Unix 域套接字或 Windows 命名管道）
+pub trait AgentStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AgentStream for T {}
+
+pub type Agent = AgentClient<Box<dyn AgentStream>>;
+
+/// Windows OpenSSH agent 的默认命名管道
+#[cfg(windows)]
+pub const OPENSSH_AGENT_PIPE: &str = r"\\.\pipe\openssh-ssh-agent";
+
+/// 描述期望的 agent 位置，用于错误提示
+pub fn expected_location() -> String {
+    #[cfg(windows)]
+    {
+        match std::env::var("SSH_AUTH_SOCK") {
+            Ok(sock) if !sock.is_empty() => format!("SSH_AUTH_SOCK 指向的命名管道 {}", sock),
+            _ => format!("命名管道 {} 或 Pageant", OPENSSH_AGENT_PIPE),
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        match std::env::var("SSH_AUTH_SOCK") {
+            Ok(sock) if !sock.is_empty() => format!("SSH_AUTH_SOCK={}", sock),
+            _ => "SSH_AUTH_SOCK 环境变量（未设置）".to_string(),
+        }
+    }
+}
+
+/// 连接到本机的 SSH agent
+///
+/// Unix 上使用 `SSH_AUTH_SOCK`；Windows 上优先使用 `SSH_AUTH_SOCK` 指定的命名管道
+/// （例如 Pageant 的 `--openssh-config` 模式），否则使用 Windows OpenSSH agent 的管道。
+pub async fn connect() -> Result<Agent> {
+    offline::ensure_network("连接 SSH agent")?;
+    let stream = open_stream()
+        .await
+        .with_context(|| format!("无法连接到 SSH agent（期望: {}）", expected_location()))?;
+    Ok(AgentClient::connect(stream))
+}
+
+#[cfg(unix)]
+async fn open_stream() -> Result<Box<dyn AgentStream>> {
+    let path = std::env::var("SSH_AUTH_SOCK").context("未设置 SSH_AUTH_SOCK")?;
+    let stream = tokio::net::UnixStream::connect(&path).await?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(windows)]
+async fn open_stream() -> Result<Box<dyn AgentStream>> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    let mut candidates = Vec::new();
+    if let Ok(sock) = std::env::var("SSH_AUTH_SOCK") {
+        if !sock.is_empty() {
+            candidates.push(sock);
+        }
+    }
+    candidates.push(OPENSSH_AGENT_PIPE.to_string());
+
+    let mut last_error = None;
+    for pipe in candidates {
+        match ClientOptions::new().open(&pipe) {
+            Ok(stream) => {
+                log::debug!("已连接到 SSH agent: {}", pipe);
+                return Ok(Box::new(stream));
+            }
+            Err(e) => last_error = Some(anyhow::Error::new(e).context(pipe)),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("没有可用的 agent")))
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn open_stream() -> Result<Box<dyn AgentStream>> {
+    anyhow::bail!("当前平台不支持 SSH agent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 仅在本机有 agent 时运行的冒烟测试
+    #[tokio::test]
+    async fn test_agent_smoke() {
+        let mut agent = match connect().await {
+            Ok(agent) => agent,
+            Err(e) => {
+                eprintln!("跳过: {:#}", e);
+                return;
+            }
+        };
+        agent.request_identities().await.unwrap();
+    }
+}
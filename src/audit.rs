@@ -0,0 +1,329 @@
+//! 操作审计日志
+//!
+//! 配置 `[defaults] audit_log` 后，每次连接、执行命令、上传、下载、删除和重命名都向该文件追加一行 JSON，
+//! 记录时间、连接、路径、字节数和结果。文件只追加写入，新建时权限为 0600；写入失败只给出一次警告，
+//! 不影响操作本身。
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use colored::Color;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::credentials;
+use crate::glyphs;
+use crate::outcome;
+use crate::paths;
+use crate::ssh::SshConfig;
+use crate::table::{Align, Cell, Table};
+
+/// 本次运行的审计设置，未配置 `audit_log` 时为空
+static AUDIT: OnceLock<AuditLog> = OnceLock::new();
+
+/// 写入失败的警告只显示一次
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+struct AuditLog {
+    path: PathBuf,
+    commands: bool,
+    /// 用于把 `user@host:port` 对应到保存的连接名称
+    config: AppConfig,
+}
+
+/// 按配置开启审计日志，在处理命令前调用一次
+pub fn init(config: &AppConfig) {
+    if let Some(ref path) = config.defaults.audit_log {
+        let _ = AUDIT.set(AuditLog {
+            path: PathBuf::from(paths::expand(path)),
+            commands: config.defaults.audit_log_commands,
+            config: config.clone(),
+        });
+    }
+}
+
+/// 审计的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Connect,
+    Exec,
+    Upload,
+    Download,
+    Remove,
+    Rename,
+}
+
+impl Operation {
+    fn label(self) -> &'static str {
+        match self {
+            Operation::Connect => "连接",
+            Operation::Exec => "执行",
+            Operation::Upload => "上传",
+            Operation::Download => "下载",
+            Operation::Remove => "删除",
+            Operation::Rename => "重命名",
+        }
+    }
+}
+
+/// 审计日志中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// RFC 3339 格式的本地时间
+    pub time: String,
+    pub operation: Operation,
+    /// 保存的连接名称，不是保存的连接时为 `user@host`
+    pub connection: String,
+    /// 实际连接的 `user@host:port`
+    pub server: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// 执行的命令（开启 `audit_log_commands` 时记录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 一次待记录的操作
+pub struct Event {
+    operation: Operation,
+    username: String,
+    host: String,
+    port: u16,
+    paths: Vec<String>,
+    bytes: Option<u64>,
+    command: Option<String>,
+    exit_code: Option<i32>,
+}
+
+impl Event {
+    pub fn new(operation: Operation, username: &str, host: &str, port: u16) -> Self {
+        Self {
+            operation,
+            username: username.to_string(),
+            host: host.to_string(),
+            port,
+            paths: Vec::new(),
+            bytes: None,
+            command: None,
+            exit_code: None,
+        }
+    }
+
+    /// 以连接参数中的用户、主机和端口记录
+    pub fn for_config(operation: Operation, config: &SshConfig) -> Self {
+        Self::new(operation, &config.username, &config.host, config.port)
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.paths.push(path.to_string());
+        self
+    }
+
+    pub fn bytes(mut self, bytes: Option<u64>) -> Self {
+        self.bytes = bytes;
+        self
+    }
+
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = Some(command.to_string());
+        self
+    }
+
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.exit_code = Some(code);
+        self
+    }
+
+    fn into_record(self, config: &AppConfig, commands: bool, error: Option<String>) -> AuditRecord {
+        let connection = config
+            .connection_name_for(&self.username, &self.host, self.port)
+            .map_or_else(|| format!("{}@{}", self.username, self.host), str::to_string);
+        // 命令中可能带有本次输入过的密码
+        let command = self
+            .command
+            .filter(|_| commands)
+            .map(|command| credentials::redacted(&command));
+        AuditRecord {
+            time: Local::now().to_rfc3339(),
+            operation: self.operation,
+            connection,
+            server: format!("{}@{}:{}", self.username, self.host, self.port),
+            paths: self.paths,
+            bytes: self.bytes,
+            command,
+            exit_code: self.exit_code,
+            ok: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// 记录操作及其结果；未开启审计日志时什么也不做
+pub fn record<T>(event: Event, result: &Result<T>) {
+    // 成功的传输同时计入运行结果（--json-result）
+    if let (Operation::Upload | Operation::Download, Some(bytes), Ok(_)) = (event.operation, event.bytes, result) {
+        outcome::transferred(bytes);
+    }
+    let Some(audit) = AUDIT.get() else {
+        return;
+    };
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    let record = event.into_record(&audit.config, audit.commands, error);
+    if let Err(e) = append(&audit.path, &record) {
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            warn!("无法写入审计日志 {}: {:#}", audit.path.display(), e);
+        }
+    }
+}
+
+/// 追加一行；整行一次写入，多个进程同时追加时不会交错
+fn append(path: &Path, record: &AuditRecord) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// 读取审计日志，跳过无法解析的行
+pub fn read(path: &Path) -> Result<Vec<AuditRecord>> {
+    let file = std::fs::File::open(path).context(format!("无法打开审计日志: {}", path.display()))?;
+    let mut records = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context(format!("无法读取审计日志: {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("审计日志第 {} 行无法解析: {}", index + 1, e),
+        }
+    }
+    Ok(records)
+}
+
+/// 按时间和连接筛选记录
+pub fn filter(records: Vec<AuditRecord>, since: Option<Duration>, connection: Option<&str>, now: DateTime<Local>) -> Vec<AuditRecord> {
+    let cutoff = since.and_then(|age| chrono::Duration::from_std(age).ok()).map(|age| now - age);
+    records
+        .into_iter()
+        .filter(|record| connection.is_none_or(|name| record.connection == name))
+        .filter(|record| match cutoff {
+            Some(cutoff) => DateTime::parse_from_rfc3339(&record.time).is_ok_and(|time| time >= cutoff),
+            None => true,
+        })
+        .collect()
+}
+
+/// 以表格打印记录
+pub fn print(records: &[AuditRecord]) {
+    let mut list = Table::new()
+        .column("时间", Align::Left)
+        .column("操作", Align::Left)
+        .truncated_column("连接", Align::Left)
+        .column("字节", Align::Right)
+        .column("结果", Align::Left)
+        .truncated_column("路径/命令", Align::Left);
+    for record in records {
+        let time = DateTime::parse_from_rfc3339(&record.time)
+            .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| record.time.clone());
+        let bytes = record.bytes.map_or_else(|| "-".to_string(), |bytes| bytes.to_string());
+        let result = if record.ok { Cell::new(glyphs::ok()).color(Color::Green) } else { Cell::new(glyphs::fail()).color(Color::Red) };
+        let mut detail = match record.command {
+            Some(ref command) => command.clone(),
+            None => record.paths.join(" -> "),
+        };
+        if let Some(code) = record.exit_code {
+            detail.push_str(&format!(" (退出码 {})", code));
+        }
+        let detail = match record.error {
+            Some(ref error) => Cell::new(format!("{} {}", detail, error)).color(Color::Red),
+            None => Cell::new(detail),
+        };
+        list.row(vec![
+            time.into(),
+            record.operation.label().into(),
+            record.connection.as_str().into(),
+            bytes.into(),
+            result,
+            detail,
+        ]);
+    }
+    list.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SavedConnection;
+
+    #[test]
+    fn test_append_and_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/audit.jsonl");
+        let mut config = AppConfig::default();
+        config.add_connection(SavedConnection::new_password("web".into(), "web.example".into(), 22, "deploy".into()));
+
+        let upload = Event::new(Operation::Upload, "deploy", "web.example", 22).path("a.txt").path("/srv/a.txt").bytes(Some(42));
+        append(&path, &upload.into_record(&config, false, None)).unwrap();
+        let exec = Event::new(Operation::Exec, "root", "db.example", 2222).command("uptime").exit_code(1);
+        append(&path, &exec.into_record(&config, false, Some("退出码 1".to_string()))).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].connection, "web");
+        assert_eq!(records[0].paths, ["a.txt", "/srv/a.txt"]);
+        assert_eq!(records[0].bytes, Some(42));
+        assert!(records[0].ok);
+        assert_eq!(records[1].connection, "root@db.example");
+        assert_eq!(records[1].server, "root@db.example:2222");
+        // 未开启 audit_log_commands 时不记录命令文本
+        assert_eq!(records[1].command, None);
+        assert!(!records[1].ok);
+
+        let now = Local::now();
+        assert_eq!(filter(records.clone(), None, Some("web"), now).len(), 1);
+        assert_eq!(filter(records.clone(), Some(Duration::from_secs(60)), None, now).len(), 2);
+        let later = now + chrono::Duration::hours(2);
+        assert!(filter(records, Some(Duration::from_secs(3600)), None, later).is_empty());
+    }
+
+    #[test]
+    fn test_command_redacted() {
+        credentials::remember(credentials::Credential::secret("audit", "token"), "s3cr3t-token");
+        let exec = Event::new(Operation::Exec, "root", "db.example", 22).command("deploy --token s3cr3t-token");
+        let record = exec.into_record(&AppConfig::default(), true, None);
+        assert_eq!(record.command.as_deref(), Some("deploy --token ******"));
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::info;
+use std::time::{Duration, Instant};
+
+use crate::glyphs;
+use crate::session::{self, RemoteSession};
+use crate::sftp::{self, SftpClient};
+
+/// 批处理文件中的一条命令（`put` 会自动创建远程父目录）
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchCommand {
+    Put { local: String, remote: String },
+    Get { remote: String, local: String },
+    Ls { remote: String },
+    Mkdir { remote: String },
+    Rm { remote: String },
+    Rmdir { remote: String },
+}
+
+/// 带行号的命令
+#[derive(Debug, Clone)]
+pub struct BatchLine {
+    pub line_no: usize,
+    pub text: String,
+    pub command: BatchCommand,
+}
+
+/// 每行的执行结果
+#[derive(Debug, Clone)]
+pub struct LineResult {
+    pub line_no: usize,
+    pub text: String,
+    pub elapsed: Duration,
+    /// 因连接断开而重试过
+    pub retried: bool,
+}
+
+impl BatchCommand {
+    /// 解析一行命令
+    fn parse(text: &str) -> Result<Self> {
+        let args: Vec<&str> = text.split_whitespace().collect();
+        let arg = |i: usize| -> Result<String> {
+            args.get(i)
+                .map(|s| s.to_string())
+                .context(format!("参数不足: {}", text))
+        };
+
+        let command = match args[0] {
+            "put" => Self::Put { local: arg(1)?, remote: arg(2)? },
+            "get" => Self::Get { remote: arg(1)?, local: arg(2)? },
+            "ls" => Self::Ls { remote: arg(1)? },
+            "mkdir" => Self::Mkdir { remote: arg(1)? },
+            "rm" => Self::Rm { remote: arg(1)? },
+            "rmdir" => Self::Rmdir { remote: arg(1)? },
+            other => anyhow::bail!("未知的批处理命令: {}", other),
+        };
+        Ok(command)
+    }
+
+    /// 对命令中的远程路径做转换（例如解析书签）
+    pub fn map_remote<F: Fn(&str) -> Result<String>>(self, f: F) -> Result<Self> {
+        Ok(match self {
+            Self::Put { local, remote } => Self::Put { local, remote: f(&remote)? },
+            Self::Get { remote, local } => Self::Get { remote: f(&remote)?, local },
+            Self::Ls { remote } => Self::Ls { remote: f(&remote)? },
+            Self::Mkdir { remote } => Self::Mkdir { remote: f(&remote)? },
+            Self::Rm { remote } => Self::Rm { remote: f(&remote)? },
+            Self::Rmdir { remote } => Self::Rmdir { remote: f(&remote)? },
+        })
+    }
+
+    fn execute(&self, sftp: &SftpClient) -> Result<()> {
+        match self {
+            Self::Put { local, remote } => {
+                if let Some(parent) = sftp::remote_parent(remote) {
+                    sftp.mkdir_p(parent)?;
+                }
+                sftp.upload_file(local, remote, None)?;
+            }
+            Self::Get { remote, local } => {
+                sftp.download_file(remote, local, None)?;
+            }
+            Self::Ls { remote } => {
+                for file in sftp.list_dir(remote)? {
+                    let suffix = if file.is_dir { "/" } else { "" };
+                    println!("  {}{}", file.display_name(), suffix);
+                }
+            }
+            Self::Mkdir { remote } => sftp.mkdir(remote)?,
+            Self::Rm { remote } => sftp.remove_file(remote)?,
+            Self::Rmdir { remote } => sftp.remove_dir(remote)?,
+        }
+        Ok(())
+    }
+}
+
+/// 解析批处理文件内容（空行和 `#` 开头的行被忽略）
+pub fn parse(content: &str) -> Result<Vec<BatchLine>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(i, line)| {
+            let text = line.trim().to_string();
+            let command = BatchCommand::parse(&text).context(format!("第 {} 行", i + 1))?;
+            Ok(BatchLine {
+                line_no: i + 1,
+                text,
+                command,
+            })
+        })
+        .collect()
+}
+
+/// 在同一个会话中依次执行所有命令
+///
+/// 某行因连接断开失败时，重新连接一次并重试该行；其他错误立即中止。
+pub fn run(session: &mut RemoteSession, lines: &[BatchLine]) -> Result<Vec<LineResult>> {
+    let mut results = Vec::with_capacity(lines.len());
+    let mut index = 0;
+    let mut retrying = false;
+
+    while index < lines.len() {
+        let sftp = SftpClient::new(session.client())?;
+        let mut failed = None;
+
+        while index < lines.len() {
+            let line = &lines[index];
+            println!("{} [{}] {}", glyphs::arrow().cyan(), line.line_no, line.text);
+            let started = Instant::now();
+
+            match line.command.execute(&sftp) {
+                Ok(()) => {
+                    results.push(LineResult {
+                        line_no: line.line_no,
+                        text: line.text.clone(),
+                        elapsed: started.elapsed(),
+                        retried: retrying,
+                    });
+                    retrying = false;
+                    index += 1;
+                }
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let Some(err) = failed else {
+            break;
+        };
+        drop(sftp);
+
+        let line = &lines[index];
+        if retrying || !session::is_connection_error(&err) {
+            return Err(err.context(format!("第 {} 行执行失败: {}", line.line_no, line.text)));
+        }
+
+        info!("第 {} 行因连接断开失败，重连后重试", line.line_no);
+        session.reconnect()?;
+        retrying = true;
+    }
+
+    Ok(results)
+}
+
+/// 打印每行耗时汇总
+pub fn print_summary(results: &[LineResult], reconnects: u32) {
+    println!("\n{}", "批处理完成:".green().bold());
+    for result in results {
+        let marker = if result.retried { " (重连后重试)" } else { "" };
+        println!(
+            "  [{:>3}] {:>8.2}s  {}{}",
+            result.line_no,
+            result.elapsed.as_secs_f64(),
+            result.text,
+            marker.yellow()
+        );
+    }
+    let total: Duration = results.iter().map(|r| r.elapsed).sum();
+    println!("  共 {} 行，耗时 {:.2} 秒", results.len(), total.as_secs_f64());
+    if reconnects > 0 {
+        println!("  {} 期间重新连接 {} 次", glyphs::warn().yellow(), reconnects);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_parse_batch_file() {
+        let lines = parse("# 部署\n\nmkdir /app\nput ./a.txt /app/a.txt\n  ls /app  \n").unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].line_no, 3);
+        assert_eq!(
+            lines[1].command,
+            BatchCommand::Put { local: "./a.txt".to_string(), remote: "/app/a.txt".to_string() }
+        );
+        assert_eq!(lines[2].text, "ls /app");
+
+        assert!(parse("put only-one-arg").is_err());
+        assert!(parse("chmod 644 /x").is_err());
+    }
+
+    #[test]
+    fn test_run_batch_in_one_session() {
+        let server = TestServer::start();
+        let config = server.password_config();
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("a.txt");
+        std::fs::write(&src, b"hello").unwrap();
+        let dst = local.path().join("b.txt");
+
+        let script = format!(
+            "put {} /app/a.txt\nget /app/a.txt {}\nrm /app/a.txt\nrmdir /app\n",
+            src.display(),
+            dst.display()
+        );
+        let lines = parse(&script).unwrap();
+        let mut session = RemoteSession::connect(config, None).unwrap();
+        let results = run(&mut session, &lines).unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(std::fs::read(&dst).unwrap(), b"hello");
+        assert!(!server.local_path("/app").exists());
+        assert_eq!(session.reconnects(), 0);
+
+        // 普通错误不会触发重连
+        let lines = parse("rm /missing.txt").unwrap();
+        assert!(run(&mut session, &lines).is_err());
+        assert_eq!(session.reconnects(), 0);
+    }
+}
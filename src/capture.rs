@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use log::debug;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::credentials;
+use crate::ssh::{self, CommandTimedOut, SshClient};
+
+/// 输出超过 `--max-output` 时的退出码
+pub const EXIT_CODE: i32 = 125;
+
+/// 没有新数据时的等待间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 进度刷新间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 远程命令的输出超过上限，已停止读取
+#[derive(Debug, thiserror::Error)]
+#[error("输出超过上限 {}，已停止读取并关闭通道", HumanBytes(*.limit))]
+pub struct OutputLimitExceeded {
+    pub limit: u64,
+}
+
+/// 标准错误输出的文件路径：`out.log` -> `out.log.err`
+pub fn stderr_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_os_string();
+    path.push(".err");
+    PathBuf::from(path)
+}
+
+/// 执行远程命令，把输出边读边写入本地文件，返回远程退出码
+///
+/// 标准错误写入 `<output>.err`，`merge` 为 true 时与标准输出写入同一个文件。两者合计超过
+/// `max_output` 字节时只写入到上限，关闭通道并返回 [`OutputLimitExceeded`]；超过客户端的命令超时时
+/// 关闭通道并返回 [`CommandTimedOut`](crate::ssh::CommandTimedOut)。
+pub fn exec_to_file(
+    client: &SshClient,
+    command: &str,
+    output: &Path,
+    merge: bool,
+    max_output: Option<u64>,
+) -> Result<i32> {
+    let mut stdout = BufWriter::new(
+        File::create(output).context(format!("无法创建输出文件: {}", output.display()))?,
+    );
+    let mut stderr = if merge {
+        None
+    } else {
+        let path = stderr_path(output);
+        let file = File::create(&path).context(format!("无法创建输出文件: {}", path.display()))?;
+        Some(BufWriter::new(file))
+    };
+
+    let _io = client.lock_io();
+    let session = client.session();
+    let mut channel = session.channel_session().context("无法创建通道")?;
+    channel.exec(command).context("命令执行失败")?;
+    debug!("执行命令并写入文件: {} -> {}", credentials::redacted(command), output.display());
+
+    // 非阻塞读取，交替处理两个数据流，避免一个流的窗口占满后另一个流永远读不到数据
+    session.set_blocking(false);
+    let result = copy_streams(&mut channel, &mut stdout, stderr.as_mut(), max_output, client.command_timeout());
+    session.set_blocking(true);
+
+    stdout.flush().context("写入输出文件失败")?;
+    if let Some(ref mut stderr) = stderr {
+        stderr.flush().context("写入输出文件失败")?;
+    }
+    if let Err(e) = result {
+        ssh::abort_channel(session, channel);
+        return Err(e);
+    }
+
+    channel.wait_close().context("等待通道关闭失败")?;
+    channel.exit_status().context("获取退出状态失败")
+}
+
+/// 读取通道的标准输出和标准错误直到结束，返回写入的总字节数
+fn copy_streams(
+    channel: &mut ssh2::Channel,
+    stdout: &mut dyn Write,
+    mut stderr: Option<&mut BufWriter<File>>,
+    limit: Option<u64>,
+    timeout: Option<Duration>,
+) -> Result<u64> {
+    let show_progress = io::stderr().is_terminal();
+    let started = Instant::now();
+    let mut last_progress = Instant::now();
+    let mut written = 0u64;
+    let mut buffer = [0u8; 32 * 1024];
+
+    loop {
+        let mut idle = true;
+        for stream_id in [0, 1] {
+            let n = match channel.stream(stream_id).read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(anyhow::Error::new(e).context("读取命令输出失败")),
+            };
+            if n == 0 {
+                continue;
+            }
+            idle = false;
+
+            let allowed = match limit {
+                Some(limit) => (limit - written).min(n as u64) as usize,
+                None => n,
+            };
+            let target: &mut dyn Write = match (stream_id, stderr.as_deref_mut()) {
+                (1, Some(stderr)) => stderr,
+                _ => &mut *stdout,
+            };
+            target.write_all(&buffer[..allowed]).context("写入输出文件失败")?;
+            written += allowed as u64;
+
+            if let Some(limit) = limit.filter(|_| allowed < n) {
+                finish_progress(show_progress);
+                return Err(OutputLimitExceeded { limit }.into());
+            }
+        }
+
+        if show_progress && last_progress.elapsed() >= PROGRESS_INTERVAL {
+            eprint!("\r已写入 {}", HumanBytes(written));
+            last_progress = Instant::now();
+        }
+        if idle {
+            if channel.eof() {
+                break;
+            }
+            if let Some(timeout) = timeout.filter(|timeout| started.elapsed() >= *timeout) {
+                finish_progress(show_progress);
+                return Err(CommandTimedOut { timeout, received: written }.into());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    finish_progress(show_progress);
+    Ok(written)
+}
+
+fn finish_progress(show_progress: bool) {
+    if show_progress {
+        eprintln!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_exec_to_file_separates_streams() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.log");
+
+        let code = exec_to_file(&client, "echo out; echo err >&2; exit 4", &output, false, None).unwrap();
+        assert_eq!(code, 4);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "out\n");
+        assert_eq!(std::fs::read_to_string(stderr_path(&output)).unwrap(), "err\n");
+
+        let merged = dir.path().join("merged.log");
+        let code = exec_to_file(&client, "echo out; echo err >&2", &merged, true, None).unwrap();
+        assert_eq!(code, 0);
+        let content = std::fs::read_to_string(&merged).unwrap();
+        assert!(content.contains("out\n") && content.contains("err\n"));
+        assert!(!stderr_path(&merged).exists());
+    }
+
+    #[test]
+    fn test_exec_to_file_stops_at_limit() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.sql");
+
+        let err = exec_to_file(&client, "head -c 200000 /dev/zero", &output, false, Some(1000)).unwrap_err();
+        assert_eq!(err.downcast_ref::<OutputLimitExceeded>().unwrap().limit, 1000);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), 1000);
+
+        // 会话在超限后仍可继续使用
+        assert_eq!(client.exec_command("echo ok").unwrap(), "ok\n");
+    }
+
+    #[test]
+    fn test_exec_to_file_timeout() {
+        let server = TestServer::start();
+        let mut client = server.connect();
+        client.set_command_timeout(Some(Duration::from_millis(500)));
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.log");
+
+        let err = exec_to_file(&client, "echo partial; sleep 5", &output, false, None).unwrap_err();
+        assert_eq!(err.downcast_ref::<CommandTimedOut>().unwrap().received, 8);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "partial\n");
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use colored::{Color, Colorize};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::config::SavedConnection;
+use crate::glyphs;
+use crate::offline;
+use crate::paths;
+use crate::session::RemoteSession;
+use crate::ssh::{ServerInfo, SshConfig};
+use crate::table::{Align, Cell, Table};
+
+/// 端口检查的连接超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 实际登录检查使用的认证信息
+pub struct AuthCheck {
+    pub config: SshConfig,
+    pub jump: Option<SshConfig>,
+}
+
+/// 检查结果
+pub struct CheckResult {
+    /// 所有检查项都通过
+    pub passed: bool,
+    /// 实际登录成功时获取的服务器信息
+    pub server: Option<ServerInfo>,
+}
+
+/// 检查连接配置，逐项打印结果
+///
+/// `auth` 为 Some 时用其中的认证信息实际登录一次，并打印服务器标识和协商的算法。
+/// 经跳板机连接时目标主机可能只在跳板机一侧可达，跳过本地的 DNS 和端口检查。
+pub fn run(connection: &SavedConnection, auth: Option<Result<AuthCheck>>) -> CheckResult {
+    let mut passed = true;
+
+    if let Some(ref jump) = connection.jump_host {
+        println!("{} 经跳板机 {} 连接，跳过 DNS 和端口检查", glyphs::arrow().cyan(), jump);
+    } else {
+        match resolve_host(&connection.host, connection.port) {
+            Ok(addrs) => {
+                let list: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+                passed &= report("DNS 解析", Ok(format!("{} -> {}", connection.host, list.join(", "))));
+                passed &= report("端口可达", check_port(&addrs));
+            }
+            Err(e) => {
+                passed &= report("DNS 解析", Err(e));
+            }
+        }
+    }
+
+    if connection.auth_type == "publickey" {
+        let result = connection
+            .private_key_path
+            .as_deref()
+            .context("公钥认证需要提供私钥路径")
+            .and_then(check_private_key);
+        passed &= report("私钥", result);
+    }
+
+    let mut server = None;
+    if let Some(auth) = auth {
+        let result = auth
+            .and_then(|auth| RemoteSession::connect(auth.config, auth.jump))
+            .map(|session| {
+                let mut info = session.client().server_info();
+                info.os = Some(session.client().remote_info().describe());
+                server = Some(info);
+                format!("{}@{}", connection.username, connection.host)
+            });
+        passed &= report("认证", result);
+    }
+    if let Some(ref info) = server {
+        print_server_info(info);
+    }
+
+    CheckResult { passed, server }
+}
+
+/// 打印服务器标识和协商的算法，已不推荐的算法标红
+pub fn print_server_info(info: &ServerInfo) {
+    server_info_table(info).print();
+}
+
+/// 服务器信息的键值表，`diag` 在其后追加自己的行，使两部分对齐
+pub fn server_info_table(info: &ServerInfo) -> Table {
+    let weak = info.weak_algorithms();
+    let mut table = Table::without_header().indent(2).column("项", Align::Left).truncated_column("值", Align::Left);
+    let mut show = |label: &str, value: &str| {
+        let value = if weak.contains(&value) {
+            Cell::new(format!("{} (弱)", value)).color(Color::Red)
+        } else {
+            Cell::new(value)
+        };
+        table.row(vec![label.into(), value]);
+    };
+    show("服务器:", info.banner.as_deref().unwrap_or("-"));
+    show("密钥交换:", &info.kex);
+    show("主机密钥:", &info.host_key_type);
+    show("指纹:", &info.fingerprint);
+    show("加密:", &info.cipher);
+    show("MAC:", &info.mac);
+    if let Some(ref os) = info.os {
+        show("系统:", os);
+    }
+    table
+}
+
+/// 打印一项检查的结果
+fn report(label: &str, result: Result<String>) -> bool {
+    match result {
+        Ok(detail) => {
+            println!("{} {}: {}", glyphs::ok().green(), label, detail);
+            true
+        }
+        Err(e) => {
+            println!("{} {}: {:#}", glyphs::fail().red(), label, e);
+            false
+        }
+    }
+}
+
+fn resolve_host(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    offline::ensure_network(&format!("解析 {}", host))?;
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .context(format!("无法解析主机名: {}", host))?
+        .collect();
+    if addrs.is_empty() {
+        anyhow::bail!("主机名没有可用的地址: {}", host);
+    }
+    Ok(addrs)
+}
+
+/// 依次尝试解析出的地址，任一可连接即通过
+fn check_port(addrs: &[SocketAddr]) -> Result<String> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(addr.to_string()),
+            Err(e) => last_error = Some(anyhow::Error::new(e).context(format!("无法连接 {}", addr))),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("没有可连接的地址")))
+}
+
+/// 私钥文件存在且能被解析（加密的私钥无需密码即可确认格式）
+fn check_private_key(raw: &str) -> Result<String> {
+    let path = paths::resolve_key_path(raw)?;
+    match russh_keys::load_secret_key(&path, None) {
+        Ok(_) => Ok(path),
+        Err(russh_keys::Error::KeyIsEncrypted) => Ok(format!("{}（已加密）", path)),
+        Err(e) => Err(anyhow::anyhow!("无法解析私钥 {}: {}", path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_check_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(check_port(&[addr]).is_ok());
+
+        drop(listener);
+        assert!(check_port(&[addr]).is_err());
+        assert!(resolve_host("localhost", 22).is_ok());
+    }
+
+    #[test]
+    fn test_check_private_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("id_ed25519");
+        let keypair = ssh_key::private::Ed25519Keypair::from_seed(&[7u8; 32]);
+        let private_key = ssh_key::PrivateKey::from(keypair);
+        std::fs::write(&key, private_key.to_openssh(ssh_key::LineEnding::LF).unwrap().as_bytes()).unwrap();
+        assert!(check_private_key(&key.to_string_lossy()).is_ok());
+
+        std::fs::write(&key, "not a key").unwrap();
+        let err = check_private_key(&key.to_string_lossy()).unwrap_err().to_string();
+        assert!(err.contains("无法解析私钥"));
+        assert!(check_private_key("/no/such/key").is_err());
+    }
+}
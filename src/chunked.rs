@@ -0,0 +1,560 @@
+//! 单个大文件的分块并行传输（`--parallel-chunks`）
+//!
+//! libssh2 的会话同一时间只能进行一个读写，因此每个分块使用独立的连接（复用已认证会话的配置，
+//! 不再询问密码）。数据先写入目标目录下的临时文件：传输前把它设为最终大小，各分块定位到自己的字节范围后
+//! 读写，互不覆盖。失败的分块单独重试，从该分块已确认写入的位置继续，全部完成后核对文件大小，再重命名为
+//! 目标文件，可选核对 SHA-256。失败或取消时只删除临时文件，已存在的目标文件保持不变。
+//!
+//! [`bench`] 比较 1 个分块和 N 个分块的吞吐量（`sftp bench`）。
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rand::RngCore;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audit::{self, Operation};
+use crate::interrupt::Cancelled;
+use crate::relay;
+use crate::remote_os;
+use crate::session::RemoteSession;
+use crate::sftp::{self, ProgressCallback, SftpClient, TransferProgress, TransferStats};
+
+/// 单个分块失败后的重试次数
+const CHUNK_RETRIES: usize = 2;
+
+/// 分块的最小大小，较小的文件使用较少的分块
+const MIN_CHUNK_SIZE: u64 = 2 * 1024 * 1024;
+
+/// 汇总各分块进度的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 分块传输选项
+pub struct ChunkOptions {
+    /// 分块数（同时使用的连接数）
+    pub chunks: usize,
+    /// 完成后比较本地和远程文件的 SHA-256
+    pub verify: bool,
+    pub cancel: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Upload,
+    Download,
+}
+
+/// 分块并行上传，远程路径是已存在的目录时上传到该目录下的同名文件
+pub fn upload(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    local_path: &str,
+    remote_path: &str,
+    options: &ChunkOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let result = upload_chunks(session, sftp, local_path, remote_path, options, progress);
+    let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+    audit::record(sftp.audit_event(Operation::Upload).path(local_path).path(remote_path).bytes(bytes), &result);
+    result
+}
+
+fn upload_chunks(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    local_path: &str,
+    remote_path: &str,
+    options: &ChunkOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let local = Path::new(local_path);
+    let metadata = std::fs::metadata(local).context(format!("无法读取本地文件: {}", local_path))?;
+    if metadata.is_dir() {
+        anyhow::bail!("本地路径是目录: {}（只能上传单个文件）", local_path);
+    }
+    let size = metadata.len();
+    let remote = match sftp.stat(remote_path) {
+        Ok(info) if info.is_dir => {
+            let file_name = local.file_name().context(format!("无法确定本地文件名: {}", local_path))?;
+            format!("{}/{}", remote_path.trim_end_matches('/'), file_name.to_string_lossy())
+        }
+        _ => remote_path.to_string(),
+    };
+    info!("分块上传: {} -> {} ({} 字节)", local_path, remote, size);
+
+    // 先创建临时文件并扩展到最终大小，各分块只写入自己的范围
+    let temp = relay::temp_path(&remote);
+    let result = sftp
+        .create_writer(&temp)
+        .and_then(|writer| {
+            drop(writer);
+            sftp.set_size(&temp, size)
+        })
+        .and_then(|_| run(session, Direction::Upload, local, &temp, size, options, progress))
+        .and_then(|stats| {
+            check_size(sftp.stat(&temp)?.size, size, &temp)?;
+            Ok(stats)
+        });
+    if result.is_err() {
+        if let Err(e) = sftp.remove_file(&temp) {
+            warn!("无法删除临时文件 {}: {:#}", temp, e);
+        }
+    }
+    let stats = result?;
+    relay::replace(sftp, &temp, &remote)?;
+    if options.verify {
+        verify(session, sftp, local, &remote)?;
+    }
+    Ok(stats)
+}
+
+/// 分块并行下载
+pub fn download(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    remote_path: &str,
+    local_path: &str,
+    options: &ChunkOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let result = download_chunks(session, sftp, remote_path, local_path, options, progress);
+    let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+    audit::record(sftp.audit_event(Operation::Download).path(remote_path).path(local_path).bytes(bytes), &result);
+    result
+}
+
+fn download_chunks(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    remote_path: &str,
+    local_path: &str,
+    options: &ChunkOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let info = sftp.stat(remote_path)?;
+    if info.is_dir {
+        anyhow::bail!("远程路径是目录: {}（只能下载单个文件）", remote_path);
+    }
+    let size = info.size;
+    let local = Path::new(local_path);
+    info!("分块下载: {} -> {} ({} 字节)", remote_path, local_path, size);
+
+    let temp = local_temp_path(local)?;
+    let result = File::create(&temp)
+        .and_then(|file| file.set_len(size))
+        .context(format!("无法创建本地文件: {}", temp.display()))
+        .and_then(|_| run(session, Direction::Download, &temp, remote_path, size, options, progress))
+        .and_then(|stats| {
+            check_size(std::fs::metadata(&temp)?.len(), size, &temp.to_string_lossy())?;
+            Ok(stats)
+        })
+        .and_then(|stats| {
+            std::fs::rename(&temp, local).context(format!("无法用临时文件替换本地文件: {}", local_path))?;
+            Ok(stats)
+        });
+    if result.is_err() && temp.exists() {
+        if let Err(e) = std::fs::remove_file(&temp) {
+            warn!("无法删除临时文件 {}: {}", temp.display(), e);
+        }
+    }
+    let stats = result?;
+    if options.verify {
+        verify(session, sftp, local, remote_path)?;
+    }
+    Ok(stats)
+}
+
+/// 一种分块数的基准测试结果
+#[derive(Debug, Serialize)]
+pub struct BenchResult {
+    /// 实际使用的分块数（受 [`MIN_CHUNK_SIZE`] 限制，可能少于请求的数量）
+    pub chunks: usize,
+    pub upload: TransferStats,
+    pub download: TransferStats,
+}
+
+/// 用 `size` 字节的随机数据分别以 1 个和 `chunks` 个分块上传、下载，比较吞吐量
+///
+/// 测试文件写在本地临时目录和远程 `remote_dir` 下，结束后（包括失败时）都会删除。
+pub fn bench(session: &RemoteSession, sftp: &SftpClient, remote_dir: &str, size: u64, chunks: usize, cancel: Arc<AtomicBool>) -> Result<Vec<BenchResult>> {
+    let name = format!("rust-ssh-sftp-bench-{}", std::process::id());
+    let local = LocalFile(std::env::temp_dir().join(&name));
+    let copy = LocalFile(std::env::temp_dir().join(format!("{}.copy", name)));
+    write_random(&local.0, size)?;
+    let local_path = local.0.to_string_lossy();
+    let copy_path = copy.0.to_string_lossy();
+    let remote = format!("{}/{}", remote_dir.trim_end_matches('/'), name);
+
+    let mut counts = vec![1];
+    if chunks > 1 {
+        counts.push(chunks);
+    }
+    let results = counts
+        .into_iter()
+        .map(|chunks| {
+            info!("基准测试: {} 个分块", chunks);
+            let options = ChunkOptions { chunks, verify: false, cancel: cancel.clone() };
+            let upload = upload_chunks(session, sftp, &local_path, &remote, &options, None)?;
+            let download = download_chunks(session, sftp, &remote, &copy_path, &options, None)?;
+            Ok(BenchResult { chunks: split(size, chunks).len(), upload, download })
+        })
+        .collect::<Result<Vec<_>>>();
+    if sftp.stat(&remote).is_ok() {
+        if let Err(e) = sftp.remove_file(&remote) {
+            warn!("无法删除远程测试文件 {}: {:#}", remote, e);
+        }
+    }
+    results
+}
+
+/// 写入 `size` 字节的随机数据（不可压缩，SSH 压缩不会影响结果）
+fn write_random(path: &Path, size: u64) -> Result<()> {
+    let mut file = File::create(path).context(format!("无法创建本地文件: {}", path.display()))?;
+    let mut block = vec![0u8; 1024 * 1024];
+    let mut remaining = size;
+    while remaining > 0 {
+        let n = remaining.min(block.len() as u64) as usize;
+        rand::thread_rng().fill_bytes(&mut block[..n]);
+        file.write_all(&block[..n]).context(format!("写入本地文件失败: {}", path.display()))?;
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+/// 离开作用域时删除的本地文件
+struct LocalFile(PathBuf);
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// 并行传输所有分块，在当前线程汇总进度
+fn run(
+    session: &RemoteSession,
+    direction: Direction,
+    local: &Path,
+    remote: &str,
+    size: u64,
+    options: &ChunkOptions,
+    mut progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let started = Instant::now();
+    let ranges = split(size, options.chunks);
+    info!("使用 {} 个分块并行传输", ranges.len());
+    let done: Vec<AtomicU64> = ranges.iter().map(|_| AtomicU64::new(0)).collect();
+    let finished = AtomicUsize::new(0);
+    let mut report = |force: bool| {
+        let Some(callback) = progress.as_mut() else { return };
+        let transferred = done.iter().map(|d| d.load(Ordering::Relaxed)).sum();
+        let flow = callback(TransferProgress { transferred, total: size, elapsed: started.elapsed() });
+        if flow.is_break() && !force {
+            options.cancel.store(true, Ordering::SeqCst);
+        }
+    };
+
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        let handles: Vec<_> = ranges
+            .iter()
+            .enumerate()
+            .map(|(index, &(start, len))| {
+                let (done, finished) = (&done[index], &finished);
+                scope.spawn(move || {
+                    let result = resume_with_retry(index, done, |offset| {
+                        transfer_chunk(session, direction, local, remote, start + offset, len - offset, offset, done, &options.cancel)
+                    });
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    result
+                })
+            })
+            .collect();
+        while finished.load(Ordering::SeqCst) < handles.len() {
+            thread::sleep(POLL_INTERVAL);
+            report(false);
+        }
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow::anyhow!("分块传输线程异常退出"))))
+            .collect()
+    });
+
+    if options.cancel.load(Ordering::SeqCst) {
+        return Err(Cancelled.into());
+    }
+    for result in results {
+        result?;
+    }
+    report(true);
+    Ok(TransferStats::single_file(size, started.elapsed()))
+}
+
+/// 传输一个分块，失败时重新连接并从 `done` 记录的已确认字节数处继续，最多重试 [`CHUNK_RETRIES`] 次
+///
+/// `attempt` 的参数是本次传输从分块内的哪个位置开始。
+fn resume_with_retry(index: usize, done: &AtomicU64, mut attempt: impl FnMut(u64) -> Result<()>) -> Result<()> {
+    let mut retries = 0;
+    loop {
+        match attempt(done.load(Ordering::Relaxed)) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.downcast_ref::<Cancelled>().is_some() => return Err(e),
+            Err(e) if retries < CHUNK_RETRIES => {
+                retries += 1;
+                warn!(
+                    "分块 {} 传输失败，从第 {} 字节处继续（{}/{}）: {:#}",
+                    index + 1, done.load(Ordering::Relaxed), retries, CHUNK_RETRIES, e
+                );
+            }
+            Err(e) => {
+                return Err(e.context(format!("分块 {} 传输失败（已重试 {} 次）", index + 1, CHUNK_RETRIES)));
+            }
+        }
+    }
+}
+
+/// 传输 `start` 起的 `len` 字节，`resumed` 是分块内此前已确认写入的字节数，进度从它开始累计
+///
+/// 写入都已完成的数据才计入进度，重试时可以从 `done` 记录的位置继续。
+#[allow(clippy::too_many_arguments)]
+fn transfer_chunk(
+    session: &RemoteSession,
+    direction: Direction,
+    local: &Path,
+    remote: &str,
+    start: u64,
+    len: u64,
+    resumed: u64,
+    done: &AtomicU64,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let client = session.connect_another()?;
+    let sftp = SftpClient::new(&client)?;
+    let mut progress = |p: TransferProgress| {
+        done.store(resumed + p.transferred, Ordering::Relaxed);
+        ControlFlow::Continue(())
+    };
+    let cancelled = || cancel.load(Ordering::SeqCst);
+    let display = local.display();
+
+    let copied = match direction {
+        Direction::Upload => {
+            let mut reader = File::open(local).context(format!("无法打开本地文件: {}", display))?;
+            reader.seek(SeekFrom::Start(start))?;
+            let mut writer = sftp.open_writer(remote)?;
+            writer.seek(SeekFrom::Start(start)).context(format!("无法定位远程文件: {}", remote))?;
+            let copied = sftp::copy_with_progress(
+                &mut reader.take(len),
+                &mut writer,
+                ("读取本地文件失败", "写入远程文件失败"),
+                len,
+                Some(&mut progress),
+                cancelled,
+            )?;
+            writer.flush().context("写入远程文件失败")?;
+            copied
+        }
+        Direction::Download => {
+            let mut reader = sftp.open_reader(Path::new(remote))?;
+            reader.seek(SeekFrom::Start(start)).context(format!("无法定位远程文件: {}", remote))?;
+            let mut writer = OpenOptions::new()
+                .write(true)
+                .open(local)
+                .context(format!("无法打开本地文件: {}", display))?;
+            writer.seek(SeekFrom::Start(start))?;
+            sftp::copy_with_progress(
+                &mut reader.take(len),
+                &mut writer,
+                ("读取远程文件失败", "写入本地文件失败"),
+                len,
+                Some(&mut progress),
+                cancelled,
+            )?
+        }
+    };
+    if copied != len {
+        anyhow::bail!("分块数据不完整: 收到 {} 字节，应为 {} 字节", copied, len);
+    }
+    Ok(())
+}
+
+/// 把 `size` 字节分成最多 `chunks` 个连续的 (起始位置, 长度)，分块不小于 [`MIN_CHUNK_SIZE`]（最后一块除外）
+fn split(size: u64, chunks: usize) -> Vec<(u64, u64)> {
+    let count = (chunks as u64).clamp(1, size.div_ceil(MIN_CHUNK_SIZE).max(1));
+    let chunk = size.div_ceil(count).max(1);
+    let ranges: Vec<(u64, u64)> = (0..count)
+        .map(|i| i * chunk)
+        .take_while(|start| *start < size)
+        .map(|start| (start, chunk.min(size - start)))
+        .collect();
+    if ranges.is_empty() {
+        vec![(0, 0)]
+    } else {
+        ranges
+    }
+}
+
+/// 本地目标文件同目录下的临时文件，命名方式与远程的 [`relay::temp_path`] 相同
+fn local_temp_path(local: &Path) -> Result<PathBuf> {
+    let name = local.file_name().context(format!("无法确定本地文件名: {}", local.display()))?;
+    Ok(local.with_file_name(format!(".{}.{}.part", name.to_string_lossy(), std::process::id())))
+}
+
+fn check_size(actual: u64, expected: u64, path: &str) -> Result<()> {
+    if actual != expected {
+        anyhow::bail!("传输后文件大小不一致: {} 为 {} 字节，应为 {} 字节", path, actual, expected);
+    }
+    Ok(())
+}
+
+fn verify(session: &RemoteSession, sftp: &SftpClient, local: &Path, remote: &str) -> Result<()> {
+    let local_digest = sftp::sha256_local(local)?;
+    let remote_digest = remote_os::sha256_or_read(session.client(), sftp, remote)?;
+    if local_digest != remote_digest {
+        anyhow::bail!("校验和不一致: 本地 {} 远程 {}", local_digest, remote_digest);
+    }
+    info!("校验和一致: {}", local_digest);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(0, 4), vec![(0, 0)]);
+        assert_eq!(split(100, 4), vec![(0, 100)]);
+        assert_eq!(split(8 * MIB, 4), vec![(0, 2 * MIB), (2 * MIB, 2 * MIB), (4 * MIB, 2 * MIB), (6 * MIB, 2 * MIB)]);
+        // 分块数受最小分块大小限制，各分块首尾相接并覆盖整个文件
+        for (size, chunks, expected) in [(5 * MIB, 8, 3), (10 * MIB + 1, 3, 3)] {
+            let ranges = split(size, chunks);
+            assert_eq!(ranges.len(), expected);
+            assert!(ranges.windows(2).all(|w| w[0].0 + w[0].1 == w[1].0));
+            assert_eq!(ranges.iter().map(|(_, len)| len).sum::<u64>(), size);
+        }
+    }
+
+    #[test]
+    fn test_resume_with_retry() {
+        // 前两次尝试各确认写入 100 字节后失败，之后从已确认的位置继续
+        let done = AtomicU64::new(0);
+        let mut offsets = Vec::new();
+        resume_with_retry(0, &done, |offset| {
+            offsets.push(offset);
+            if offsets.len() <= CHUNK_RETRIES {
+                done.store(offset + 100, Ordering::Relaxed);
+                anyhow::bail!("连接中断");
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(offsets, [0, 100, 200]);
+
+        let err = resume_with_retry(1, &AtomicU64::new(0), |_| anyhow::bail!("连接中断")).unwrap_err();
+        assert_eq!(err.to_string(), format!("分块 2 传输失败（已重试 {} 次）", CHUNK_RETRIES));
+        let cancelled = resume_with_retry(0, &AtomicU64::new(0), |_| Err(Cancelled.into())).unwrap_err();
+        assert!(cancelled.downcast_ref::<Cancelled>().is_some());
+    }
+
+    #[test]
+    fn test_chunk_resumes_from_offset() {
+        let server = TestServer::start();
+        let session = RemoteSession::connect(server.password_config(), None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("big.bin");
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&src, &data).unwrap();
+
+        // 临时文件中前 400 字节已经写入，只传输剩余部分
+        let temp = server.local_path("/.big.bin.part");
+        std::fs::write(&temp, [&data[..400], &[0u8; 600][..]].concat()).unwrap();
+        let (done, cancel) = (AtomicU64::new(400), AtomicBool::new(false));
+        transfer_chunk(&session, Direction::Upload, &src, "/.big.bin.part", 400, 600, 400, &done, &cancel).unwrap();
+        assert_eq!(std::fs::read(&temp).unwrap(), data);
+        assert_eq!(done.load(Ordering::Relaxed), 1000);
+
+        let dest = dir.path().join("copy.bin");
+        std::fs::write(&dest, [&data[..700], &[0u8; 300][..]].concat()).unwrap();
+        let done = AtomicU64::new(700);
+        transfer_chunk(&session, Direction::Download, &dest, "/.big.bin.part", 700, 300, 700, &done, &cancel).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        assert_eq!(done.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn test_chunked_round_trip() {
+        let server = TestServer::start();
+        let config = server.password_config();
+        let session = RemoteSession::connect(config, None).unwrap();
+        let sftp = SftpClient::new(session.client()).unwrap();
+        let options = ChunkOptions { chunks: 3, verify: true, cancel: Arc::new(AtomicBool::new(false)) };
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("big.bin");
+        let data: Vec<u8> = (0..4 * MIB + 123).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&src, &data).unwrap();
+
+        let mut last = 0;
+        let stats = upload(&session, &sftp, src.to_str().unwrap(), "/big.bin", &options, Some(&mut |p| {
+            last = p.transferred;
+            ControlFlow::Continue(())
+        }))
+        .unwrap();
+        assert_eq!((stats.files_transferred, stats.total_bytes, last), (1, data.len() as u64, data.len() as u64));
+        assert_eq!(std::fs::read(server.local_path("/big.bin")).unwrap(), data);
+
+        let dest = dir.path().join("copy.bin");
+        download(&session, &sftp, "/big.bin", dest.to_str().unwrap(), &options, None).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+    }
+
+    #[test]
+    fn test_failed_transfer_keeps_existing_target() {
+        let server = TestServer::start();
+        let session = RemoteSession::connect(server.password_config(), None).unwrap();
+        let sftp = SftpClient::new(session.client()).unwrap();
+        // 已取消：分块一开始就停止，相当于传输到一半失败
+        let options = ChunkOptions { chunks: 2, verify: false, cancel: Arc::new(AtomicBool::new(true)) };
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("big.bin");
+        std::fs::write(&src, vec![7u8; 5 * MIB as usize]).unwrap();
+        std::fs::write(server.local_path("/big.bin"), b"old remote").unwrap();
+        let err = upload(&session, &sftp, src.to_str().unwrap(), "/big.bin", &options, None).unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert_eq!(std::fs::read(server.local_path("/big.bin")).unwrap(), b"old remote");
+        assert_eq!(std::fs::read_dir(server.local_path("/")).unwrap().count(), 1);
+
+        let dest = dir.path().join("copy.bin");
+        std::fs::write(&dest, b"old local").unwrap();
+        assert!(download(&session, &sftp, "/big.bin", dest.to_str().unwrap(), &options, None).is_err());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"old local");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_bench() {
+        let server = TestServer::start();
+        let session = RemoteSession::connect(server.password_config(), None).unwrap();
+        let sftp = SftpClient::new(session.client()).unwrap();
+
+        let results = bench(&session, &sftp, "/", 8 * MIB, 4, Arc::new(AtomicBool::new(false))).unwrap();
+        let chunks: Vec<_> = results.iter().map(|r| r.chunks).collect();
+        assert_eq!(chunks, [1, 4]);
+        assert!(results.iter().all(|r| r.upload.total_bytes == 8 * MIB && r.download.total_bytes == 8 * MIB));
+        // 测试文件都已删除
+        assert_eq!(std::fs::read_dir(server.local_path("/")).unwrap().count(), 0);
+        let name = format!("rust-ssh-sftp-bench-{}", std::process::id());
+        assert!(!std::env::temp_dir().join(name).exists());
+    }
+}
@@ -1,12 +1,158 @@
-use clap::{Parser, Subcommand};
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+
+use crate::progress::ProgressMode;
+use crate::sftp::{Links, SkipExisting};
+use crate::ssh::AuthMode;
 
 /// Rust SSH/SFTP 客户端 - 类似 FinalShell 的跨平台终端工具
 #[derive(Parser, Debug)]
 #[command(name = "rust-ssh-sftp")]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// 输出 SSH 库的调试日志和协商的算法（用于排查握手问题）
+    #[arg(long, global = true)]
+    pub ssh_debug: bool,
+    
+    /// 非交互模式：主密码等输错时立即失败，不重试也不等待
+    #[arg(long, global = true)]
+    pub batch: bool,
+    
+    /// 列表输出为制表符分隔的纯文本（不对齐、不着色、不截断），便于脚本处理
+    #[arg(long, global = true)]
+    pub no_table: bool,
+    
+    /// 只使用 ASCII 符号（[ok]、[locked] 等），不使用 ✓、🔒 等；默认按终端能力自动选择
+    #[arg(long, global = true, conflicts_with = "unicode")]
+    pub ascii: bool,
+    
+    /// 即使终端看起来不支持，也使用 Unicode 符号
+    #[arg(long, global = true)]
+    pub unicode: bool,
+    
+    /// 认证方式：auto 在密钥被拒绝时询问密码重试（与 OpenSSH 相同），key 只使用密钥或 agent
+    #[arg(long, global = true, value_enum, default_value_t = AuthMode::Auto)]
+    pub auth: AuthMode,
+    
+    /// 从文件的第一行读取登录密码（密码不会出现在命令行参数和 shell 历史中）
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "password_stdin")]
+    pub password_file: Option<String>,
+    
+    /// 启动时从标准输入读取一行作为登录密码（用于 CI 注入密文，与 docker login --password-stdin 相同）
+    #[arg(long, global = true)]
+    pub password_stdin: bool,
+    
+    /// 退出时输出一个 JSON 对象描述运行结果（子命令、服务器、认证方式、指纹、字节数、退出码、错误类别）
+    #[arg(long, global = true)]
+    pub json_result: bool,
+    
+    /// --json-result 的输出位置：文件路径，或 fd:N 写入已打开的文件描述符（默认为标准错误）
+    #[arg(long, global = true, value_name = "PATH|fd:N", requires = "json_result")]
+    pub result_file: Option<String>,
+    
+    /// 命令失败时把诊断报告（版本、参数、连接配置、握手信息、错误链、最近的日志，密码已脱敏）写到该文件
+    #[arg(long, global = true, value_name = "PATH")]
+    pub debug_report: Option<String>,
+    
     #[command(subcommand)]
     pub command: Commands,
+    
+    /// 子命令路径，例如 `sftp upload`
+    #[arg(skip)]
+    pub command_path: String,
+    
+    /// 命令行上的目标（连接名称或 user@host）
+    #[arg(skip)]
+    pub command_target: Option<String>,
+}
+
+/// 不提供的密文参数：密码写在命令行上会留在 shell 历史和进程列表中
+const SECRET_FLAGS: &[&str] = &["--password", "--passwd", "--master-password", "--passphrase"];
+
+impl Cli {
+    /// 解析命令行，并检查 clap 无法表达的参数组合
+    pub fn parse_checked() -> Self {
+        Self::try_parse_checked(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    pub fn try_parse_checked<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().try_get_matches_from(args).map_err(explain_secret_flag)?;
+        let mut cli = Self::from_arg_matches(&matches).map_err(|e| e.format(&mut Self::command()))?;
+        (cli.command_path, cli.command_target) = invocation(&matches);
+        if cli.password_stdin {
+            if let Some(reader) = cli.stdin_reader() {
+                return Err(Cli::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--password-stdin 不能与 {} 同时使用：两者都要读取标准输入", reader),
+                ));
+            }
+        }
+        Ok(cli)
+    }
+
+    /// 会读取标准输入的参数，用于说明与 `--password-stdin` 的冲突
+    fn stdin_reader(&self) -> Option<&'static str> {
+        match &self.command {
+            Commands::Sftp { action: SftpCommands::Upload { files_from: Some(list), .. }, .. } if list == "-" => {
+                Some("--files-from -")
+            }
+            Commands::Sftp { action: SftpCommands::Upload { local_path, files_from: None, from_manifest: None, .. }, .. }
+                if local_path == "-" =>
+            {
+                Some("本地路径 -")
+            }
+            Commands::Sftp { action: SftpCommands::Download { files_from: Some(list), .. }, .. } if list == "-" => {
+                Some("--files-from -")
+            }
+            Commands::Config { action: ConfigCommands::Secret { action: SecretCommands::Set { stdin: true, .. } } } => {
+                Some("secret set --stdin")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 子命令路径和其中的 `target` 参数
+fn invocation(matches: &ArgMatches) -> (String, Option<String>) {
+    let mut names = Vec::new();
+    let mut target = None;
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        if let Ok(Some(value)) = sub.try_get_one::<String>("target") {
+            target = Some(value.clone());
+        }
+        current = sub;
+    }
+    (names.join(" "), target)
+}
+
+/// 把 `--password xxx` 之类的未知参数错误换成说明替代方式的提示（不回显参数的值）
+fn explain_secret_flag(error: clap::Error) -> clap::Error {
+    if error.kind() != ErrorKind::UnknownArgument {
+        return error;
+    }
+    let Some(ContextValue::String(arg)) = error.get(ContextKind::InvalidArg) else {
+        return error;
+    };
+    let flag = arg.split('=').next().unwrap_or_default();
+    if !SECRET_FLAGS.contains(&flag) {
+        return error;
+    }
+    Cli::command().error(
+        ErrorKind::UnknownArgument,
+        format!(
+            "不支持在命令行中传递密码（{}）：密码会留在 shell 历史和进程列表中。\n\
+             请改用 --password-file、--password-stdin、环境变量 {} 或在提示时输入；主密码只能在提示时输入",
+            flag,
+            crate::credentials::PASSWORD_ENV
+        ),
+    )
 }
 
 #[derive(Subcommand, Debug)]
@@ -16,9 +162,9 @@ pub enum Commands {
         /// 连接名称（从配置中）或 user@host 格式。如果不提供，将显示交互式选择菜单
         target: Option<String>,
 
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
 
         /// 使用交互式 shell（原始模式）
         #[arg(short = 'I', long)]
@@ -28,13 +174,33 @@ pub enum Commands {
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
 
-        /// 保存密码（加密保存到配置文件）
+        /// 连接成功后加密保存本次输入的密码（需要主密码）；目标不是保存的连接时同时保存连接，名称为 --save-as 或 user@host
         #[arg(long)]
         save_password: bool,
 
-        /// 保存为新的连接配置
+        /// 目标不是保存的连接时，连接成功后以此名称保存连接（不含密码，除非同时指定 --save-password）
         #[arg(long)]
         save_as: Option<String>,
+
+        /// 将会话录制为 asciicast v2 文件（隐含 -I）
+        #[arg(long)]
+        record: Option<String>,
+
+        /// 录制时同时记录键盘输入
+        #[arg(long, requires = "record")]
+        record_input: bool,
+
+        /// 不执行连接前/断开后钩子
+        #[arg(long)]
+        no_hooks: bool,
+
+        /// 交互式会话的转义字符，none 表示禁用（所有按键都发送到远程）
+        #[arg(long, value_name = "CHAR")]
+        escape: Option<String>,
+
+        /// 在后台保持已认证的连接（控制主连接），之后对该名称的 exec/sftp 操作复用它而不再认证
+        #[arg(long, requires = "target", conflicts_with_all = ["interactive", "record", "save_as", "save_password"])]
+        master: bool,
     },
     
     /// 执行远程命令
@@ -45,17 +211,88 @@ pub enum Commands {
         /// 要执行的命令
         command: String,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 分配伪终端（用于需要 TTY 的命令，例如 docker exec -it）
+        #[arg(short = 't', long, conflicts_with = "output_file")]
+        tty: bool,
+        
+        /// 把标准输出边读边写入本地文件（标准错误写入 <文件>.err），不在内存中缓存
+        #[arg(short = 'o', long, value_name = "FILE")]
+        output_file: Option<String>,
+        
+        /// 输出上限（如 50M、2G），超过时停止读取并以退出码 125 结束
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "output_file")]
+        max_output: Option<u64>,
+        
+        /// 标准错误与标准输出写入同一个文件
+        #[arg(long, requires = "output_file")]
+        merge_output: bool,
+        
+        /// 用 sudo 执行（使用连接保存的 sudo 密文，没有时询问密码）
+        #[arg(long, conflicts_with_all = ["tty", "output_file"])]
+        sudo: bool,
+        
+        /// 命令超过指定秒数未结束时关闭通道并以退出码 124 结束（0 表示不限制）
+        #[arg(long, value_name = "SECONDS", conflicts_with = "tty")]
+        timeout: Option<u64>,
+    },
+    
+    /// 执行保存的命令片段
+    Run {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 命令片段名称
+        snippet: String,
+        
+        /// 片段中 {{var}} 占位符的值，格式 key=value（可多次指定）
+        #[arg(long = "var")]
+        vars: Vec<String>,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+    },
+    
+    /// 诊断远程环境：服务器版本、算法、延迟、shell、常用工具、磁盘空间和时钟偏差
+    Diag {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 以 JSON 格式输出报告
+        #[arg(long)]
+        json: bool,
     },
     
     /// SFTP 文件传输
     Sftp {
+        /// 每隔指定秒数发送保活包，防止长时间传输时连接被防火墙断开
+        #[arg(long, global = true, value_name = "SECS")]
+        keepalive: Option<u32>,
+        
         #[command(subcommand)]
         action: SftpCommands,
     },
@@ -66,6 +303,30 @@ pub enum Commands {
         action: ConfigCommands,
     },
 
+    /// 管理已知主机密钥（known_hosts）
+    Hostkey {
+        #[command(subcommand)]
+        action: HostkeyCommands,
+    },
+
+    /// 查看和结束远程进程
+    Proc {
+        #[command(subcommand)]
+        action: ProcCommands,
+    },
+
+    /// 管理控制主连接（connect --master 启动）
+    Master {
+        #[command(subcommand)]
+        action: MasterCommands,
+    },
+
+    /// 查看操作审计日志（配置 [defaults] 中的 audit_log）
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommands,
+    },
+
     /// 启动图形界面
     Gui,
 }
@@ -77,23 +338,104 @@ pub enum SftpCommands {
         /// 连接名称或 user@host 格式
         target: String,
         
-        /// 本地文件路径
+        /// 本地文件路径，- 表示从标准输入读取（使用 --files-from 或 --from-manifest 时为远程目标目录）
         local_path: String,
         
-        /// 远程文件路径
-        remote_path: String,
+        /// 远程文件路径（可使用 @书签）
+        #[arg(required_unless_present_any = ["files_from", "from_manifest", "targets"])]
+        remote_path: Option<String>,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// 同时上传到多个目标（逗号分隔），此时位置参数依次为本地文件和远程路径
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["files_from", "from_manifest"])]
+        targets: Vec<String>,
+        
+        /// 多目标上传时同时进行的连接数
+        #[arg(long, default_value = "4", value_name = "N")]
+        parallel: usize,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
         
-        /// 不显示进度条
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
+        
+        /// 远程父目录不存在时自动逐级创建
+        #[arg(long)]
+        create_dirs: bool,
+        
+        /// 上传后设置远程文件权限（八进制，如 755 或 0644）
+        #[arg(long, value_parser = parse_mode)]
+        chmod: Option<u32>,
+        
+        /// 新建远程目录的权限（八进制，默认使用配置 [defaults] 中的 dir_mode，否则为 755）
+        #[arg(long, value_parser = parse_mode)]
+        chmod_dirs: Option<u32>,
+        
+        /// 追加到远程文件末尾而不是覆盖（文件不存在时创建），适合分段推送日志
+        #[arg(long, conflicts_with = "targets")]
+        append: bool,
+        
+        /// 远程已有同名文件时：never（总是上传）、size（大小相同时跳过）、checksum（大小和 SHA-256 都相同时跳过）
+        #[arg(long, value_enum, default_value_t = SkipExisting::Never, conflicts_with = "append")]
+        skip_existing: SkipExisting,
+        
+        /// 从文件读取要上传的本地文件列表（- 表示标准输入），全部上传到目标目录
         #[arg(long)]
+        files_from: Option<String>,
+        
+        /// 文件列表以 NUL 分隔（配合 find -print0）
+        #[arg(long, requires = "files_from")]
+        null: bool,
+        
+        /// 在目标目录下保留列表中的相对路径
+        #[arg(long, requires = "files_from")]
+        relative: bool,
+        
+        /// 上传 download 生成的元数据清单中的文件（保留相对路径），并在远程恢复权限位和修改时间
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["files_from", "append"])]
+        from_manifest: Option<String>,
+        
+        /// 把单个大文件分成 N 块，各用一个连接并行上传
+        #[arg(long, value_name = "N", conflicts_with_all = ["targets", "files_from", "from_manifest", "append"])]
+        parallel_chunks: Option<usize>,
+        
+        /// 分块上传完成后比较本地和远程文件的 SHA-256
+        #[arg(long, requires = "parallel_chunks")]
+        verify: bool,
+        
+        /// 递归上传本地目录，远程路径为对应的目标目录（保留相对结构，空目录也会创建）
+        #[arg(short, long, conflicts_with_all = ["targets", "files_from", "from_manifest", "append", "parallel_chunks"])]
+        recursive: bool,
+        
+        /// 递归上传时的符号链接：preserve（在远程重建链接）、follow（上传链接目标的内容）、skip（跳过并提示）
+        #[arg(long, value_enum, default_value_t = Links::Skip)]
+        links: Links,
+        
+        /// 进度显示方式：bar（进度条）、json（在 stderr 输出 JSON 事件）、none
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar)]
+        progress: ProgressMode,
+        
+        /// 不显示进度条（等同于 --progress none）
+        #[arg(long, conflicts_with = "progress")]
         no_progress: bool,
+        
+        /// 以 JSON 格式输出传输统计
+        #[arg(long)]
+        json: bool,
+        
+        /// 静默模式，不显示进度条和传输统计
+        #[arg(short, long)]
+        quiet: bool,
     },
     
     /// 下载文件
@@ -101,23 +443,118 @@ pub enum SftpCommands {
         /// 连接名称或 user@host 格式
         target: String,
         
-        /// 远程文件路径
+        /// 远程文件路径（可使用 @书签；使用 --files-from 时为本地目标目录）
         remote_path: String,
         
-        /// 本地文件路径
-        local_path: String,
+        /// 本地文件路径，- 表示写到标准输出（进度显示在标准错误上）
+        #[arg(required_unless_present = "files_from")]
+        local_path: Option<String>,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
         
-        /// 不显示进度条
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
+        
+        /// 从文件读取要下载的远程文件列表（- 表示标准输入），全部下载到目标目录
+        #[arg(long)]
+        files_from: Option<String>,
+        
+        /// 文件列表以 NUL 分隔（配合 find -print0）
+        #[arg(long, requires = "files_from")]
+        null: bool,
+        
+        /// 在目标目录下保留列表中的相对路径
+        #[arg(long, requires = "files_from")]
+        relative: bool,
+        
+        /// 把远程文件的权限应用到下载的本地文件（以及 --relative 新建的本地目录），Windows 上忽略
+        #[arg(long)]
+        apply_remote_perms: bool,
+        
+        /// 把远程元数据（属主、权限位、修改时间）清单写入指定文件，用于 --files-from 和 --recursive
+        /// （与 --apply-remote-perms 同用时默认写入目标目录下的 .sftp-manifest.json）
+        #[arg(long, value_name = "PATH", conflicts_with = "no_manifest")]
+        manifest: Option<String>,
+        
+        /// 不写入元数据清单
+        #[arg(long)]
+        no_manifest: bool,
+        
+        /// 把单个大文件分成 N 块，各用一个连接并行下载
+        #[arg(long, value_name = "N", conflicts_with_all = ["files_from", "apply_remote_perms"])]
+        parallel_chunks: Option<usize>,
+        
+        /// 分块下载完成后比较本地和远程文件的 SHA-256
+        #[arg(long, requires = "parallel_chunks")]
+        verify: bool,
+        
+        /// 递归下载远程目录，本地路径为对应的目标目录（保留相对结构，空目录也会创建）
+        #[arg(short, long, conflicts_with_all = ["files_from", "parallel_chunks"])]
+        recursive: bool,
+        
+        /// 递归下载时的符号链接：preserve（在本地重建链接，Windows 上跳过）、follow（下载链接目标的内容）、skip（跳过并提示）
+        #[arg(long, value_enum, default_value_t = Links::Skip)]
+        links: Links,
+        
+        /// 进度显示方式：bar（进度条）、json（在 stderr 输出 JSON 事件）、none
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar)]
+        progress: ProgressMode,
+        
+        /// 不显示进度条（等同于 --progress none）
+        #[arg(long, conflicts_with = "progress")]
+        no_progress: bool,
+        
+        /// 以 JSON 格式输出传输统计
+        #[arg(long)]
+        json: bool,
+        
+        /// 静默模式，不显示进度条和传输统计
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    
+    /// 在两台主机之间直接传输文件（经本机内存中转，不写入本地磁盘）
+    Transfer {
+        /// 源文件，格式 <连接名称或 user@host>:<远程路径>（路径可使用 @书签）
+        source: String,
+        
+        /// 目标路径，格式同上，已存在的目录表示传输到其中的同名文件
+        dest: String,
+        
+        /// 先写入临时文件，完成后重命名，中断时不留下不完整的目标文件
         #[arg(long)]
+        atomic: bool,
+        
+        /// 目标文件的权限（八进制，如 644）
+        #[arg(long, value_parser = parse_mode)]
+        chmod: Option<u32>,
+        
+        /// 进度显示方式：bar（进度条）、json（在 stderr 输出 JSON 事件）、none
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar)]
+        progress: ProgressMode,
+        
+        /// 不显示进度条（等同于 --progress none）
+        #[arg(long, conflicts_with = "progress")]
         no_progress: bool,
+        
+        /// 以 JSON 格式输出传输统计
+        #[arg(long)]
+        json: bool,
+        
+        /// 静默模式，不显示进度条和传输统计
+        #[arg(short, long)]
+        quiet: bool,
     },
     
     /// 列出远程目录
@@ -125,16 +562,32 @@ pub enum SftpCommands {
         /// 连接名称或 user@host 格式
         target: String,
         
-        /// 远程目录路径
+        /// 远程目录路径（可使用 @书签）
         remote_path: String,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// 显示权限、属主、属组和符号链接的目标（每个链接多两次往返，属主名称通过一次 getent 解析）
+        #[arg(short, long)]
+        long: bool,
+        
+        /// 忽略控制主连接缓存的目录列表，重新读取
+        #[arg(long)]
+        refresh: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
     },
     
     /// 创建远程目录
@@ -142,16 +595,68 @@ pub enum SftpCommands {
         /// 连接名称或 user@host 格式
         target: String,
         
-        /// 远程目录路径
+        /// 远程目录路径（可使用 @书签）
         remote_path: String,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// 目录权限（八进制，如 750；默认使用配置 [defaults] 中的 dir_mode，否则为 755）
+        #[arg(long, value_parser = parse_mode)]
+        mode: Option<u32>,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
+    },
+    
+    /// 在远程主机上复制文件或目录（有 shell 时执行 cp，数据不经过本机）
+    Copy {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 源路径（可使用 @书签）
+        source: String,
+        
+        /// 目标路径（可使用 @书签），已存在的目录表示复制到其中
+        dest: String,
+        
+        /// 递归复制目录
+        #[arg(short, long)]
+        recursive: bool,
+        
+        /// 先复制到临时文件，完成后重命名，失败时不留下不完整的目标文件
+        #[arg(long, conflicts_with = "recursive")]
+        atomic: bool,
+        
+        /// 递归复制前不统计文件数和总大小（目录很大时遍历较慢），也不询问是否继续
+        #[arg(long)]
+        no_preflight: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
     },
     
     /// 删除远程文件
@@ -159,74 +664,981 @@ pub enum SftpCommands {
         /// 连接名称或 user@host 格式
         target: String,
         
-        /// 远程文件路径
+        /// 远程文件路径（可使用 @书签）
         remote_path: String,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
-    },
-}
-
-#[derive(Subcommand, Debug)]
-pub enum ConfigCommands {
-    /// 添加新的连接配置
-    Add {
-        /// 连接名称
-        name: String,
         
-        /// 主机地址
-        host: String,
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
         
-        /// 用户名
-        username: String,
+        /// 相对远程路径的基准目录（优先于连接保存的 default_remote_dir，都未设置时为登录目录）
+        #[arg(long, value_name = "PATH")]
+        remote_cwd: Option<String>,
         
-        /// SSH 端口
-        #[arg(short, long, default_value = "22")]
-        port: u16,
+        /// 移到远程回收站而不是直接删除（也可以是目录），可用 sftp trash restore 恢复
+        #[arg(long)]
+        trash: bool,
+    },
+    
+    /// 用本地编辑器编辑远程文件，保存退出后上传修改
+    Edit {
+        /// 连接名称或 user@host 格式
+        target: String,
         
-        /// 使用公钥认证
+        /// 远程文件路径（可使用 @书签）
+        remote_path: String,
+        
+        /// 编辑器命令（默认使用 $VISUAL 或 $EDITOR）
         #[arg(long)]
-        use_key: bool,
+        editor: Option<String>,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
         
         /// 私钥文件路径
         #[arg(short = 'i', long)]
         identity_file: Option<String>,
         
-        /// 公钥文件路径
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
         #[arg(long)]
-        public_key: Option<String>,
+        jump: Option<String>,
     },
     
-    /// 列出所有保存的连接
-    List,
-    
-    /// 删除连接配置
-    Remove {
-        /// 连接名称
-        name: String,
+    /// 比较本地文件和远程文件，输出统一格式的差异（二进制文件只比较哈希），不同时退出码为 1
+    Diff {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 本地文件路径
+        local_path: String,
+        
+        /// 远程文件路径（可使用 @书签）
+        remote_path: String,
+        
+        /// 只输出文件是否不同（类似 diff -q）
+        #[arg(short = 'q', long)]
+        brief: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
     },
     
-    /// 设置默认连接
-    SetDefault {
-        /// 连接名称
-        name: String,
+    /// 在远程文件中搜索（扩展正则），输出 path:line:text
+    Grep {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 正则表达式
+        pattern: String,
+        
+        /// 远程文件或目录路径（可使用 @书签）
+        remote_path: String,
+        
+        /// 递归搜索目录
+        #[arg(short, long)]
+        recursive: bool,
+        
+        /// 忽略大小写（-i 已用于私钥文件，只提供长选项）
+        #[arg(long)]
+        ignore_case: bool,
+        
+        /// 只列出包含匹配的文件名
+        #[arg(short = 'l', long)]
+        files_with_matches: bool,
+        
+        /// 同时搜索二进制文件（默认跳过）
+        #[arg(long)]
+        binary: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
     },
     
-    /// 显示连接详情
+    /// 计算远程文件的 SHA-256（按远程系统选择命令，无法执行命令时通过 SFTP 读取）
+    Checksum {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 远程文件路径（可使用 @书签）
+        remote_path: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 查看远程路径所在磁盘的空间
+    Df {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 远程路径（可使用 @书签，默认为登录目录）
+        #[arg(default_value = ".")]
+        remote_path: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 测试传输速度：用随机数据分别以 1 个和 N 个分块上传、下载，比较吞吐量（测试文件结束后删除）
+    Bench {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 存放远程测试文件的目录（可使用 @书签）
+        #[arg(default_value = "/tmp")]
+        remote_dir: String,
+        
+        /// 测试数据大小（MB）
+        #[arg(long, value_name = "MB", default_value_t = 64)]
+        size: u64,
+        
+        /// 与单个分块比较的并行分块数
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        parallel_chunks: usize,
+        
+        /// 以 JSON 格式输出结果
+        #[arg(long)]
+        json: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 输出远程文件的最后几行，--follow 持续输出新内容（按 Ctrl+C 停止）
+    Tail {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 远程文件路径（可使用 @书签）
+        remote_path: String,
+        
+        /// 输出的行数
+        #[arg(short = 'n', long, default_value = "10")]
+        lines: usize,
+        
+        /// 持续输出追加的内容，文件被截断或轮转时从头读取
+        #[arg(short, long)]
+        follow: bool,
+        
+        /// 文件不存在或暂时消失时继续等待
+        #[arg(long, requires = "follow")]
+        retry: bool,
+        
+        /// 在远程执行 tail（需要 shell），默认通过 SFTP 轮询，受限账号也可使用
+        #[arg(long)]
+        exec: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 监听本地目录，文件创建或修改后自动上传（按 Ctrl+C 停止）
+    Watch {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 本地目录
+        local_dir: String,
+        
+        /// 远程目录（可使用 @书签）
+        remote_dir: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 排除的通配符模式，可多次指定（如 --exclude '*.swp' --exclude .git）
+        #[arg(long)]
+        exclude: Vec<String>,
+        
+        /// 本地删除文件时同步删除远程文件
+        #[arg(long)]
+        delete: bool,
+        
+        /// 合并连续写入的等待时间（毫秒）
+        #[arg(long, default_value_t = 300)]
+        debounce: u64,
+        
+        /// 远程文件大小相同且修改时间相差不超过该秒数时视为未变化，不再上传
+        #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+        modify_window: u64,
+        
+        /// 只按大小判断远程文件是否未变化（适用于修改时间不可信的文件系统）
+        #[arg(long, conflicts_with = "modify_window")]
+        size_only: bool,
+        
+        /// 符号链接：preserve（在远程重建链接）、follow（上传链接目标的内容）、skip（跳过并提示）
+        #[arg(long, value_enum, default_value_t = Links::Skip)]
+        links: Links,
+    },
+    
+    /// 按文件逐行执行 SFTP 命令（put/get/ls/mkdir/rm/rmdir），所有行共用一个连接；put 会自动创建远程父目录
+    Batch {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 批处理文件路径（# 开头为注释）
+        file: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 管理远程目录书签（在远程路径中用 @名称 引用）
+    Bookmark {
+        #[command(subcommand)]
+        action: BookmarkCommands,
+    },
+    
+    /// 查看、恢复和清空 sftp remove --trash 使用的远程回收站
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommands {
+    /// 列出回收站中的项
+    List {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 把回收站中的项移回原路径（原路径已存在时不覆盖）
+    Restore {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 回收站编号（见 sftp trash list）
+        id: String,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+    
+    /// 永久删除回收站中的所有内容
+    Empty {
+        /// 连接名称或 user@host 格式
+        target: String,
+        
+        /// 不询问确认
+        #[arg(short, long)]
+        yes: bool,
+        
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 经过跳板机连接（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BookmarkCommands {
+    /// 添加书签
+    Add {
+        /// 连接名称
+        target: String,
+        
+        /// 书签名称（字母、数字、- 和 _）
+        name: String,
+        
+        /// 远程目录路径
+        remote_path: String,
+    },
+    
+    /// 列出连接的书签
+    List {
+        /// 连接名称
+        target: String,
+    },
+    
+    /// 删除书签
+    Remove {
+        /// 连接名称
+        target: String,
+        
+        /// 书签名称
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// 添加新的连接配置
+    Add {
+        /// 连接名称
+        name: String,
+        
+        /// 主机地址
+        host: String,
+        
+        /// 用户名
+        username: String,
+        
+        /// SSH 端口
+        #[arg(short, long, default_value = "22")]
+        port: u16,
+        
+        /// 使用公钥认证
+        #[arg(long)]
+        use_key: bool,
+        
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+        
+        /// 公钥文件路径
+        #[arg(long)]
+        public_key: Option<String>,
+        
+        /// 使用 SSH agent 认证（Windows 上为 OpenSSH agent 或 Pageant）
+        #[arg(long, conflicts_with = "use_key")]
+        use_agent: bool,
+        
+        /// 连接前执行的本地命令（可使用 {host} {port} {name} {username}）
+        #[arg(long)]
+        pre_connect: Option<String>,
+        
+        /// 会话结束后执行的本地命令
+        #[arg(long)]
+        post_disconnect: Option<String>,
+        
+        /// 获取密码或私钥密码的外部命令，输出的第一行作为密文（可使用 {host} {port} {name} {username}）
+        #[arg(long, value_name = "COMMAND")]
+        credential_helper: Option<String>,
+        
+        /// 跳板机（保存的连接名称或 user@host[:port]）
+        #[arg(long)]
+        jump: Option<String>,
+        
+        /// 交互式会话的转义字符（单个字符或 none）
+        #[arg(long, value_name = "CHAR")]
+        escape: Option<String>,
+        
+        /// 保存前检查主机名解析、端口可达性和私钥文件
+        #[arg(long)]
+        check: bool,
+        
+        /// 在 --check 的基础上实际登录一次（会询问密码）
+        #[arg(long)]
+        check_auth: bool,
+    },
+    
+    /// 列出所有保存的连接
+    List,
+    
+    /// 删除连接配置
+    Remove {
+        /// 连接名称
+        name: String,
+    },
+    
+    /// 设置默认连接
+    SetDefault {
+        /// 连接名称
+        name: String,
+    },
+    
+    /// 显示连接详情
     Show {
         /// 连接名称
         name: String,
     },
     
+    /// 修改连接的备注和标签颜色
+    Edit {
+        /// 连接名称
+        name: String,
+        
+        /// 备注（空字符串表示清除）
+        #[arg(long, required_unless_present_any = ["color", "remote_dir"])]
+        note: Option<String>,
+        
+        /// 标签颜色：red、orange、yellow、green、blue、purple、gray 或 #rrggbb（空字符串表示清除）
+        #[arg(long)]
+        color: Option<String>,
+        
+        /// sftp 命令中相对远程路径的默认基准目录（空字符串表示清除）
+        #[arg(long, value_name = "PATH")]
+        remote_dir: Option<String>,
+    },
+    
+    /// 检查连接（DNS、端口、私钥、登录），并记录服务器标识和协商的算法
+    Test {
+        /// 连接名称
+        name: String,
+    },
+    
+    /// 执行连接的凭据助手并报告结果（不连接服务器，不显示输出内容）
+    TestCredentialHelper {
+        /// 连接名称
+        name: String,
+    },
+    
+    /// 清除连接固定的主机密钥指纹（服务器合法更换密钥后使用）
+    ResetFingerprint {
+        /// 连接名称
+        name: String,
+    },
+    
     /// 显示已保存的密码（需要主密码）
     ShowPassword {
         /// 连接名称（可选，不提供则显示所有）
         name: Option<String>,
     },
+
+    /// 从 ~/.ssh/config 导入主机别名（先显示预览，确认后写入）
+    ImportSsh {
+        /// 只导入这些别名（不提供则导入所有不含通配符的别名）
+        hosts: Vec<String>,
+
+        /// 覆盖同名的已有连接
+        #[arg(long)]
+        overwrite: bool,
+
+        /// 不询问，直接写入（非交互运行时不加此参数只显示预览）
+        #[arg(long)]
+        apply: bool,
+    },
+
+    /// 从 PuTTY 保存的会话导入连接（仅 Windows，跳过串口、telnet 等非 SSH 会话）
+    #[cfg(windows)]
+    ImportPutty {
+        /// 只导入这些会话（不提供则导入所有 SSH 会话）
+        sessions: Vec<String>,
+
+        /// 覆盖同名的已有连接
+        #[arg(long)]
+        overwrite: bool,
+
+        /// 不询问，直接写入（非交互运行时不加此参数只显示预览）
+        #[arg(long)]
+        apply: bool,
+
+        /// 只列出将要导入的连接，不保存
+        #[arg(long, conflicts_with = "apply")]
+        dry_run: bool,
+    },
+
+    /// 导出所有连接到 TOML 文件
+    Export {
+        /// 输出文件
+        file: String,
+
+        /// 包含加密的密码和私钥密码（需要主密码）
+        #[arg(long)]
+        include_secrets: bool,
+    },
+
+    /// 从导出文件导入连接（先显示预览，确认后写入）
+    Import {
+        /// 导出文件
+        file: String,
+
+        /// 覆盖同名的已有连接
+        #[arg(long)]
+        overwrite: bool,
+
+        /// 不询问，直接写入（非交互运行时不加此参数只显示预览）
+        #[arg(long)]
+        apply: bool,
+    },
+
+    /// 管理命令片段
+    Snippet {
+        #[command(subcommand)]
+        action: SnippetCommands,
+    },
+    
+    /// 管理连接的附加密文（sudo 密码、API token 等，加密保存）
+    Secret {
+        #[command(subcommand)]
+        action: SecretCommands,
+    },
+    
+    /// 从自动备份中恢复配置文件
+    RestoreBackup {
+        /// 备份编号（1 为最新，不提供则交互选择）
+        index: Option<usize>,
+        
+        /// 只列出可用的备份
+        #[arg(long)]
+        list: bool,
+        
+        /// 不询问确认
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum SecretCommands {
+    /// 保存密文（值从终端隐藏输入，需要主密码）
+    Set {
+        /// 连接名称
+        connection: String,
+        
+        /// 密文名称（例如 sudo、token），片段中用 {{secret:名称}} 引用
+        key: String,
+        
+        /// 从标准输入读取第一行作为值（用于脚本）
+        #[arg(long)]
+        stdin: bool,
+    },
+    
+    /// 显示解密后的值（需要主密码）
+    Show {
+        /// 连接名称
+        connection: String,
+        
+        /// 密文名称
+        key: String,
+    },
+    
+    /// 列出连接保存的密文名称（不显示值）
+    List {
+        /// 连接名称
+        connection: String,
+    },
+    
+    /// 删除密文
+    Remove {
+        /// 连接名称
+        connection: String,
+        
+        /// 密文名称
+        key: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnippetCommands {
+    /// 添加命令片段（可使用 {{var}} 占位符）
+    Add {
+        /// 片段名称
+        name: String,
+        
+        /// 命令内容
+        command: String,
+        
+        /// 只对指定连接生效（不提供则为全局片段）
+        #[arg(long)]
+        connection: Option<String>,
+    },
+    
+    /// 列出命令片段
+    List {
+        /// 同时列出该连接专用的片段
+        #[arg(long)]
+        connection: Option<String>,
+    },
+    
+    /// 删除命令片段
+    Remove {
+        /// 片段名称
+        name: String,
+        
+        /// 删除指定连接的片段（不提供则删除全局片段）
+        #[arg(long)]
+        connection: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HostkeyCommands {
+    /// 列出已记录的主机及密钥指纹
+    List {
+        /// 同时列出 ~/.ssh/known_hosts 中的记录
+        #[arg(long)]
+        openssh: bool,
+    },
+
+    /// 删除主机的密钥记录（例如服务器重装后）
+    Remove {
+        /// 主机地址
+        host: String,
+
+        /// SSH 端口
+        #[arg(short, long, default_value = "22")]
+        port: u16,
+    },
+
+    /// 连接服务器并显示其提供的主机密钥指纹（不进行认证）
+    Scan {
+        /// 主机地址
+        host: String,
+
+        /// SSH 端口
+        #[arg(short, long, default_value = "22")]
+        port: u16,
+
+        /// 将扫描到的密钥保存到 known_hosts
+        #[arg(long)]
+        add: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MasterCommands {
+    /// 列出正在运行的控制主连接（同时清理失效的套接字）
+    List,
+
+    /// 停止控制主连接
+    Stop {
+        /// 连接名称
+        name: String,
+    },
+
+    /// 后台进程入口，由 connect --master 启动
+    #[command(hide = true)]
+    Serve {
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommands {
+    /// 以表格显示审计日志
+    Show {
+        /// 只显示这段时间内的记录，如 30m、12h、7d、2w
+        #[arg(long, value_parser = parse_age)]
+        since: Option<std::time::Duration>,
+
+        /// 只显示该连接的记录（保存的连接名称或 user@host）
+        #[arg(long)]
+        connection: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProcCommands {
+    /// 列出远程进程（解析 ps 输出）
+    List {
+        /// 连接名称或 user@host 格式
+        target: String,
+
+        /// 排序方式
+        #[arg(long, value_parser = ["cpu", "mem"], default_value = "cpu")]
+        sort: String,
+
+        /// 只显示命令中包含该字符串的进程（不区分大小写）
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// 最多显示的进程数
+        #[arg(short = 'n', long, default_value = "20")]
+        top: usize,
+
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+    },
+
+    /// 向远程进程发送信号并确认其已退出
+    Kill {
+        /// 连接名称或 user@host 格式
+        target: String,
+
+        /// 进程 ID
+        pid: u32,
+
+        /// 信号名称或编号，例如 TERM、KILL、9
+        #[arg(short, long, default_value = "TERM")]
+        signal: String,
+
+        /// SSH 端口（默认 22，或使用 ~/.ssh/config 中的 Port）
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// 私钥文件路径
+        #[arg(short = 'i', long)]
+        identity_file: Option<String>,
+    },
+}
+
+/// 解析八进制权限（可以带前导 0 或 0o）
+pub fn parse_mode(value: &str) -> Result<u32, String> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    let mode = u32::from_str_radix(digits, 8)
+        .map_err(|_| format!("无效的权限: {}（应为八进制，如 755 或 0644）", value))?;
+    if digits.is_empty() || mode > 0o7777 {
+        return Err(format!("无效的权限: {}（超出范围）", value));
+    }
+    Ok(mode)
+}
+
+/// 解析带单位的大小：`1024`、`512K`、`50M`、`2G`（1024 进制，大小写均可）
+fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let upper = trimmed.to_ascii_uppercase();
+    let digits = upper.trim_end_matches('B');
+    let (number, scale) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1u64 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        Some('T') => (&digits[..digits.len() - 1], 1 << 40),
+        _ => (digits, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("无效的大小: {}（例如 1024、512K、50M、2G）", value))
+}
+
+/// 解析时间长度：数字加单位 s、m、h、d、w
+fn parse_age(value: &str) -> Result<std::time::Duration, String> {
+    let trimmed = value.trim();
+    let (number, unit) = trimmed.split_at(trimmed.len() - trimmed.chars().last().map_or(0, char::len_utf8));
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => 0,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .filter(|&secs| secs > 0)
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(|| format!("无效的时间: {}（例如 30m、12h、7d、2w）", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("755"), Ok(0o755));
+        assert_eq!(parse_mode("0644"), Ok(0o644));
+        assert_eq!(parse_mode("0o4755"), Ok(0o4755));
+        assert!(parse_mode("789").is_err());
+        assert!(parse_mode("17777").is_err());
+        assert!(parse_mode("rwx").is_err());
+    }
+
+    #[test]
+    fn test_progress_mode() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from([&["rust-ssh-sftp", "sftp", "download"], args, &["srv", "/a", "a"]].concat())
+        };
+        let Commands::Sftp { action: SftpCommands::Download { progress, .. }, .. } = parse(&[]).unwrap().command else {
+            panic!("应解析为 sftp download");
+        };
+        assert_eq!(progress, ProgressMode::Bar);
+        let Commands::Sftp { action: SftpCommands::Download { progress, .. }, .. } =
+            parse(&["--progress", "json"]).unwrap().command
+        else {
+            panic!("应解析为 sftp download");
+        };
+        assert_eq!(progress, ProgressMode::Json);
+        assert!(parse(&["--progress", "json", "--no-progress"]).is_err());
+        assert!(parse(&["--progress", "dots"]).is_err());
+    }
+
+    #[test]
+    fn test_charset_flags() {
+        let parse = |args: &[&str]| Cli::try_parse_checked([&["rust-ssh-sftp"], args].concat());
+        assert!(parse(&["config", "list", "--ascii"]).unwrap().ascii);
+        assert!(parse(&["--unicode", "config", "list"]).unwrap().unicode);
+        assert_eq!(parse(&["--ascii", "--unicode", "config", "list"]).unwrap_err().kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_json_result_flags() {
+        let parse = |args: &[&str]| Cli::try_parse_checked([&["rust-ssh-sftp"], args].concat());
+
+        let cli = parse(&["--json-result", "sftp", "upload", "web", "a.txt", "/tmp/"]).unwrap();
+        assert!(cli.json_result);
+        assert_eq!(cli.command_path, "sftp upload");
+        assert_eq!(cli.command_target.as_deref(), Some("web"));
+
+        let cli = parse(&["config", "list", "--json-result", "--result-file", "fd:3"]).unwrap();
+        assert_eq!(cli.command_path, "config list");
+        assert_eq!(cli.command_target, None);
+        assert_eq!(cli.result_file.as_deref(), Some("fd:3"));
+
+        assert!(parse(&["--result-file", "out.json", "exec", "web", "uptime"]).is_err());
+    }
+
+    #[test]
+    fn test_password_flags() {
+        let parse = |args: &[&str]| Cli::try_parse_checked([&["rust-ssh-sftp"], args].concat());
+
+        let cli = parse(&["exec", "srv", "uptime", "--password-stdin"]).unwrap();
+        assert!(cli.password_stdin);
+        let cli = parse(&["--password-file", "/run/secrets/ssh", "exec", "srv", "uptime"]).unwrap();
+        assert_eq!(cli.password_file.as_deref(), Some("/run/secrets/ssh"));
+        assert_eq!(
+            parse(&["--password-stdin", "--password-file", "pw", "exec", "srv", "uptime"]).unwrap_err().kind(),
+            ErrorKind::ArgumentConflict
+        );
+
+        // 与同样读取标准输入的参数冲突
+        for args in [
+            &["--password-stdin", "sftp", "upload", "srv", "-", "/tmp/a"][..],
+            &["--password-stdin", "sftp", "upload", "srv", "/tmp", "--files-from", "-"],
+            &["--password-stdin", "sftp", "download", "srv", "/tmp", "--files-from", "-"],
+            &["--password-stdin", "config", "secret", "set", "web", "sudo", "--stdin"],
+        ] {
+            assert_eq!(parse(args).unwrap_err().kind(), ErrorKind::ArgumentConflict, "{:?}", args);
+        }
+        assert!(parse(&["--password-stdin", "sftp", "upload", "srv", "a.txt", "/tmp/a"]).is_ok());
+        assert!(parse(&["--password-stdin", "sftp", "upload", "srv", "/tmp", "--files-from", "list.txt"]).is_ok());
+        assert!(parse(&["sftp", "upload", "srv", "-", "/tmp/a"]).is_ok());
+
+        // 不接受命令行上的密码，提示中不回显密码
+        for args in [
+            &["connect", "srv", "--password", "hunter2"][..],
+            &["connect", "srv", "--password=hunter2"],
+            &["--master-password", "hunter2", "config", "list"],
+        ] {
+            let error = parse(args).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnknownArgument);
+            let message = error.to_string();
+            assert!(message.contains("--password-stdin"), "{}", message);
+            assert!(!message.contains("hunter2"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90s"), Ok(std::time::Duration::from_secs(90)));
+        assert_eq!(parse_age("12h"), Ok(std::time::Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_age("7d"), Ok(std::time::Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_age("2w"), Ok(std::time::Duration::from_secs(14 * 86400)));
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("0d").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("天").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("512k"), Ok(512 * 1024));
+        assert_eq!(parse_size("50M"), Ok(50 * 1024 * 1024));
+        assert_eq!(parse_size("2GB"), Ok(2 << 30));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("lots").is_err());
+    }
+}
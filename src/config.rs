@@ -1,11 +1,43 @@
 use anyhow::{Context, Result};
+use colored::Colorize;
+use fd_lock::{RwLock, RwLockWriteGuard};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use crate::cli::parse_mode;
+use crate::credentials;
 use crate::crypto::CryptoManager;
-use crate::ssh::{AuthMethod, SshConfig};
+use crate::escape::{parse_escape_char, DEFAULT_ESCAPE};
+use crate::glyphs;
+use crate::paths;
+use crate::prompt::Prompter;
+use crate::sftp::RetryPolicy;
+use crate::ssh::{AuthMethod, ServerInfo, SshConfig};
+
+/// 默认保留的配置备份数量
+const DEFAULT_CONFIG_BACKUPS: usize = 3;
+
+/// 配置备份数量上限
+const MAX_CONFIG_BACKUPS: usize = 20;
+
+/// 主密码连续输错后默认的冷却秒数
+const DEFAULT_UNLOCK_COOLDOWN: u64 = 30;
+
+/// 保存时按条目合并的表，其余顶层配置项整体比较
+const MERGED_TABLES: &[&str] = &["connections", "snippets"];
+
+thread_local! {
+    /// 本线程已持有配置锁；[`ConfigStore::update`] 内的保存或再次加锁（例如认证成功后记录指纹）不能再等待自己
+    static LOCK_HELD: Cell<bool> = const { Cell::new(false) };
+}
 
 /// 保存的连接配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,26 +58,164 @@ pub struct SavedConnection {
     /// 加密的私钥密码（仅用于公钥认证）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub encrypted_passphrase: Option<String>,
+    /// 该连接专用的命令片段
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub snippets: HashMap<String, String>,
+    /// 连接前执行的本地命令
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_connect_command: Option<String>,
+    /// 会话结束后执行的本地命令
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_disconnect_command: Option<String>,
+    /// 获取密码或私钥密码的外部命令，输出优先于保存的加密凭据和交互输入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+    /// 远程目录书签（名称 -> 路径），通过 `@名称` 引用
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub bookmarks: HashMap<String, String>,
+    /// 跳板机（保存的连接名称或 user@host[:port]）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jump_host: Option<String>,
+    /// 交互式会话的转义字符（单个字符或 none）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub escape_char: Option<String>,
+    /// 首次连接成功时记录的主机密钥指纹，之后必须一致（与 known_hosts 无关）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key_fingerprint: Option<String>,
+    /// 最近一次 `config test` 获取的服务器信息，离线时也可以查看
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<ServerInfo>,
+    /// 备注（GUI 中显示为提示）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// 标签颜色（预设颜色名称或 #rrggbb）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// 附加的加密数据（名称 -> 密文），例如 sudo 密码、API token
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub secrets: HashMap<String, String>,
+    /// 最近一次从 GUI 发起连接的时间（Unix 秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    /// sftp 命令中相对远程路径的基准目录（命令行的 --remote-cwd 优先），未设置时为登录目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_remote_dir: Option<String>,
 }
 
 /// 应用配置
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppConfig {
     #[serde(default)]
     pub connections: HashMap<String, SavedConnection>,
     #[serde(default)]
     pub default_connection: Option<String>,
+    /// 校验主机密钥时同时读取 ~/.ssh/known_hosts
+    #[serde(default)]
+    pub include_openssh_known_hosts: bool,
+    /// 目标不是保存的连接时，按 ~/.ssh/config 中的 Host 别名解析
+    #[serde(default)]
+    pub use_openssh_config: bool,
+    /// 所有连接共用的命令片段
+    #[serde(default)]
+    pub snippets: HashMap<String, String>,
+    /// 默认的连接前钩子（连接未单独设置时使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_connect_command: Option<String>,
+    /// 默认的断开后钩子（连接未单独设置时使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_disconnect_command: Option<String>,
+    /// 默认的凭据助手（连接未单独设置时使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+    /// 默认的转义字符（连接未单独设置时使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escape_char: Option<String>,
+    /// 保存前保留的配置备份数量（默认 3，0 表示不备份）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_backups: Option<usize>,
+    /// 主密码连续输错 3 次后的冷却秒数（每次翻倍，0 表示不冷却）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_cooldown: Option<u64>,
+    /// `[defaults]` 表：命令行未指定时使用的默认值
+    #[serde(default, skip_serializing_if = "Defaults::is_empty")]
+    pub defaults: Defaults,
+    /// `[gui]` 表：图形界面的外观设置
+    #[serde(default, skip_serializing_if = "GuiSettings::is_default")]
+    pub gui: GuiSettings,
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            connections: HashMap::new(),
-            default_connection: None,
-        }
+/// 配置文件中的 `[defaults]` 表
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Defaults {
+    /// 新建远程目录的权限（八进制字符串，如 "750"），未设置时为 755
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<String>,
+    /// 审计日志文件路径（每行一条 JSON，只追加），未设置时不记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<String>,
+    /// 审计日志中同时记录执行的命令文本（其中已知的密码替换为 ******）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub audit_log_commands: bool,
+    /// SFTP 幂等操作临时失败时的重试次数，未设置时为 2，0 表示不重试
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sftp_retries: Option<u32>,
+    /// 第一次重试前等待的毫秒数（之后每次翻倍），未设置时为 200
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sftp_retry_backoff_ms: Option<u64>,
+    /// 每个连接保留的命令历史条数，未设置时为 1000，0 表示不记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_size: Option<usize>,
+    /// 不记录到命令历史的命令（正则表达式），未设置时为以空格开头或包含 password 的命令
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_ignore: Option<String>,
+    /// `sftp remove --trash` 使用的远程回收站目录（`~` 为远程登录目录），未设置时为 ~/.rust-ssh-sftp-trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_dir: Option<String>,
+}
+
+impl Defaults {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
     }
 }
 
+/// 配置文件中的 `[gui]` 表
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GuiSettings {
+    #[serde(default)]
+    pub theme: GuiTheme,
+    /// 界面正文字号，其他文字按比例缩放；未设置时使用 egui 的默认大小
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    /// 等宽字体文件路径（供终端显示使用），未设置时使用中文字体
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monospace_font: Option<String>,
+}
+
+impl GuiSettings {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 图形界面主题
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuiTheme {
+    /// 跟随系统
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// 配置文件的一个备份
+#[derive(Debug, Clone)]
+pub struct ConfigBackup {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+}
+
 impl AppConfig {
     /// 获取配置文件路径
     pub fn config_path() -> Result<PathBuf> {
@@ -60,15 +230,17 @@ impl AppConfig {
         Ok(config_dir.join("config.toml"))
     }
     
-    /// 从文件加载配置
+    /// 从文件加载配置（只读；需要保存修改时使用 [`ConfigStore`]）
     pub fn load() -> Result<Self> {
-        let config_path = Self::config_path()?;
-        
+        Self::load_from(&Self::config_path()?)
+    }
+    
+    fn load_from(config_path: &Path) -> Result<Self> {
         if !config_path.exists() {
             return Ok(Self::default());
         }
         
-        let content = fs::read_to_string(&config_path)
+        let content = fs::read_to_string(config_path)
             .context("无法读取配置文件")?;
         
         let config: AppConfig = toml::from_str(&content)
@@ -77,22 +249,92 @@ impl AppConfig {
         Ok(config)
     }
     
-    /// 保存配置到文件
-    pub fn save(&self) -> Result<()> {
-        let config_path = Self::config_path()?;
-        
+    /// 保存配置到指定文件，覆盖前先备份旧文件
+    fn save_to(&self, path: &Path) -> Result<()> {
         let content = toml::to_string_pretty(self)
             .context("无法序列化配置")?;
         
-        fs::write(&config_path, content)
-            .context("无法写入配置文件")?;
-        
-        Ok(())
+        write_with_backup(path, &content, self.backup_count())
+    }
+    
+    /// 主密码连续输错后的初始冷却时间
+    pub fn unlock_cooldown(&self) -> Duration {
+        Duration::from_secs(self.unlock_cooldown.unwrap_or(DEFAULT_UNLOCK_COOLDOWN))
+    }
+    
+    /// `[defaults]` 中设置的 SFTP 重试策略
+    pub fn sftp_retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            attempts: self.defaults.sftp_retries.map_or(default.attempts, |retries| retries.saturating_add(1)),
+            backoff: self.defaults.sftp_retry_backoff_ms.map_or(default.backoff, Duration::from_millis),
+        }
+    }
+    
+    /// `[defaults]` 中设置的新建远程目录权限
+    pub fn default_dir_mode(&self) -> Result<Option<u32>> {
+        self.defaults
+            .dir_mode
+            .as_deref()
+            .map(|mode| parse_mode(mode).map_err(anyhow::Error::msg))
+            .transpose()
+            .context("配置 [defaults] 中的 dir_mode 无效")
     }
     
+    /// 实际保留的备份数量
+    pub fn backup_count(&self) -> usize {
+        self.config_backups
+            .unwrap_or(DEFAULT_CONFIG_BACKUPS)
+            .min(MAX_CONFIG_BACKUPS)
+    }
+    
+    /// 连接成功后保存登录密码：询问主密码（首次使用时设置并确认），加密后写入 `connection` 并添加
+    ///
+    /// 只修改内存中的配置，调用方负责保存。
+    pub fn store_password(
+        &mut self,
+        prompter: &mut dyn Prompter,
+        mut connection: SavedConnection,
+        password: &str,
+        is_first_time: bool,
+    ) -> Result<()> {
+        let purpose = format!("加密 {} 的密码", connection.name);
+        let master_password = CryptoManager::get_master_password(prompter, is_first_time, &purpose)?;
+        connection.encrypted_password = Some(CryptoManager::new(&master_password)?.encrypt(password)?);
+        connection.auth_type = "password".to_string();
+        self.add_connection(connection);
+        Ok(())
+    }
+
     /// 添加连接配置
-    pub fn add_connection(&mut self, connection: SavedConnection) {
+    pub fn add_connection(&mut self, mut connection: SavedConnection) {
         let name = connection.name.clone();
+        
+        // 覆盖已有连接时保留其命令片段、书签、备注、颜色、附加密文和默认远程目录
+        if let Some(existing) = self.connections.get(&name) {
+            if connection.snippets.is_empty() {
+                connection.snippets = existing.snippets.clone();
+            }
+            if connection.bookmarks.is_empty() {
+                connection.bookmarks = existing.bookmarks.clone();
+            }
+            if connection.note.is_none() {
+                connection.note = existing.note.clone();
+            }
+            if connection.color.is_none() {
+                connection.color = existing.color.clone();
+            }
+            if connection.last_used.is_none() {
+                connection.last_used = existing.last_used;
+            }
+            if connection.secrets.is_empty() {
+                connection.secrets = existing.secrets.clone();
+            }
+            if connection.default_remote_dir.is_none() {
+                connection.default_remote_dir = existing.default_remote_dir.clone();
+            }
+        }
+        
         self.connections.insert(name.clone(), connection);
         
         // 如果是第一个连接，设为默认
@@ -119,6 +361,28 @@ impl AppConfig {
         self.connections.get(name)
     }
     
+    /// 任一已保存的密文（登录密码、私钥密码或附加密文），用于校验主密码
+    pub fn any_ciphertext(&self) -> Option<&str> {
+        self.list_connections()
+            .into_iter()
+            .find_map(|c| c.encrypted_secret().or_else(|| c.secrets.values().next().map(String::as_str)))
+    }
+    
+    /// 最近使用过的连接，最新的在前
+    pub fn recent_connections(&self, limit: usize) -> Vec<&SavedConnection> {
+        let mut connections: Vec<_> = self.connections.values().filter(|c| c.last_used.is_some()).collect();
+        connections.sort_by(|a, b| b.last_used.cmp(&a.last_used).then_with(|| a.name.cmp(&b.name)));
+        connections.truncate(limit);
+        connections
+    }
+    
+    /// 记录连接的使用时间
+    pub fn touch(&mut self, name: &str, at: u64) {
+        if let Some(connection) = self.connections.get_mut(name) {
+            connection.last_used = Some(at);
+        }
+    }
+    
     /// 列出所有连接
     pub fn list_connections(&self) -> Vec<&SavedConnection> {
         let mut connections: Vec<_> = self.connections.values().collect();
@@ -135,6 +399,128 @@ impl AppConfig {
         Ok(())
     }
     
+    /// 指向 `username@host:port` 的保存的连接名称，有多个时取名称最小的
+    pub fn connection_name_for(&self, username: &str, host: &str, port: u16) -> Option<&str> {
+        self.connections
+            .values()
+            .filter(|c| c.targets(host, port) && c.username == username)
+            .map(|c| c.name.as_str())
+            .min()
+    }
+    
+    /// 指向 `host:port` 且固定了其他指纹的连接
+    pub fn pinned_mismatch(&self, host: &str, port: u16, fingerprint: &str) -> Option<&SavedConnection> {
+        self.list_connections().into_iter().find(|c| {
+            c.targets(host, port) && c.host_key_fingerprint.as_deref().is_some_and(|pinned| pinned != fingerprint)
+        })
+    }
+    
+    /// 是否有指向 `host:port` 且尚未固定指纹的连接
+    pub fn has_unpinned(&self, host: &str, port: u16) -> bool {
+        self.connections.values().any(|c| c.targets(host, port) && c.host_key_fingerprint.is_none())
+    }
+    
+    /// 为指向 `host:port` 且尚未固定指纹的连接记录指纹，返回新记录的连接名称
+    ///
+    /// 同一 `host:port` 的连接面对的是同一台服务器，一次成功的连接同时为它们固定指纹。
+    pub fn pin_fingerprint(&mut self, host: &str, port: u16, fingerprint: &str) -> Vec<String> {
+        let mut pinned: Vec<String> = self
+            .connections
+            .values_mut()
+            .filter(|c| c.targets(host, port) && c.host_key_fingerprint.is_none())
+            .map(|c| {
+                c.host_key_fingerprint = Some(fingerprint.to_string());
+                c.name.clone()
+            })
+            .collect();
+        pinned.sort();
+        pinned
+    }
+    
+    /// 清除连接固定的主机密钥指纹，下次连接成功时重新记录
+    pub fn reset_fingerprint(&mut self, name: &str) -> Result<Option<String>> {
+        let conn = self
+            .connections
+            .get_mut(name)
+            .context(format!("连接 '{}' 不存在", name))?;
+        Ok(conn.host_key_fingerprint.take())
+    }
+    
+    /// 查找命令片段，连接专用的片段优先于全局片段
+    pub fn find_snippet(&self, connection: Option<&str>, name: &str) -> Option<&String> {
+        connection
+            .and_then(|c| self.connections.get(c))
+            .and_then(|c| c.snippets.get(name))
+            .or_else(|| self.snippets.get(name))
+    }
+    
+    /// 连接实际使用的钩子命令 (连接前, 断开后)，连接自身的设置优先于全局默认值
+    pub fn hooks_for(&self, connection: Option<&str>) -> (Option<String>, Option<String>) {
+        let conn = connection.and_then(|c| self.connections.get(c));
+        let pre = conn
+            .and_then(|c| c.pre_connect_command.clone())
+            .or_else(|| self.pre_connect_command.clone());
+        let post = conn
+            .and_then(|c| c.post_disconnect_command.clone())
+            .or_else(|| self.post_disconnect_command.clone());
+        (pre, post)
+    }
+    
+    /// 连接实际使用的凭据助手，连接自身的设置优先于全局默认值
+    pub fn credential_helper_for(&self, connection: Option<&str>) -> Option<&str> {
+        connection
+            .and_then(|c| self.connections.get(c))
+            .and_then(|c| c.credential_helper.as_deref())
+            .or(self.credential_helper.as_deref())
+    }
+    
+    /// 连接实际使用的转义字符，`None` 表示禁用本地转义
+    ///
+    /// 优先级：命令行参数 > 连接设置 > 全局默认值 > `~`。
+    pub fn escape_char_for(&self, connection: Option<&str>, cli: Option<&str>) -> Result<Option<u8>> {
+        let setting = cli.map(str::to_string).or_else(|| {
+            connection
+                .and_then(|c| self.connections.get(c))
+                .and_then(|c| c.escape_char.clone())
+                .or_else(|| self.escape_char.clone())
+        });
+        match setting {
+            Some(value) => parse_escape_char(&value),
+            None => Ok(Some(DEFAULT_ESCAPE)),
+        }
+    }
+    
+    /// 解析远程路径中的 `@书签`，支持 `@名称` 和 `@名称/子路径`
+    ///
+    /// 不以 `@` 开头的路径原样返回；书签不存在时报错，而不是当作普通路径。
+    pub fn resolve_bookmark(&self, connection: &str, path: &str) -> Result<String> {
+        let Some(reference) = path.strip_prefix('@') else {
+            return Ok(path.to_string());
+        };
+        let (name, rest) = match reference.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (reference, None),
+        };
+        
+        let base = self.connections.get(connection)
+            .and_then(|c| c.bookmarks.get(name))
+            .context(format!("书签不存在: @{}（连接 '{}'）", name, connection))?;
+        
+        Ok(match rest {
+            Some(rest) if !rest.is_empty() => format!("{}/{}", base.trim_end_matches('/'), rest),
+            _ => base.clone(),
+        })
+    }
+    
+    /// 某个连接可用的全部命令片段（合并全局片段）
+    pub fn snippets_for(&self, connection: Option<&str>) -> HashMap<String, String> {
+        let mut snippets = self.snippets.clone();
+        if let Some(conn) = connection.and_then(|c| self.connections.get(c)) {
+            snippets.extend(conn.snippets.clone());
+        }
+        snippets
+    }
+    
     /// 获取默认连接
     #[allow(dead_code)]
     pub fn get_default_connection(&self) -> Option<&SavedConnection> {
@@ -143,7 +529,405 @@ impl AppConfig {
     }
 }
 
+/// 读取、修改并保存配置文件
+///
+/// 命令行的读取-修改-保存通过 [`ConfigStore::update`] 在锁文件（`config.toml.lock`）的排他锁内完成。
+/// 长期持有配置的 GUI 使用 [`ConfigStore::load`]：加载时记住磁盘上的内容作为基准，保存时加锁重新读取
+/// 磁盘上的配置，把本次相对基准的修改合并进去再写入，GUI 和命令行同时修改配置时不会互相覆盖：连接和
+/// 命令片段按条目合并，其余配置项整体合并；同一项被双方改成不同的值时以本次为准，并给出警告。
+#[derive(Debug, Default)]
+pub struct ConfigStore {
+    /// 配置文件路径，None 为默认位置
+    path: Option<PathBuf>,
+    /// 加载或上次保存时磁盘上的内容
+    base: AppConfig,
+    config: AppConfig,
+}
+
+impl ConfigStore {
+    /// 从默认位置加载（不加锁，保存时合并其他进程的修改）
+    pub fn load() -> Result<Self> {
+        let config = AppConfig::load()?;
+        Ok(Self { path: None, base: config.clone(), config })
+    }
+    
+    #[cfg(test)]
+    fn load_from(path: &Path) -> Result<Self> {
+        let config = AppConfig::load_from(path)?;
+        Ok(Self { path: Some(path.to_path_buf()), base: config.clone(), config })
+    }
+    
+    /// 锁定配置文件后从默认位置加载，交给 `f` 修改和保存，`f` 返回后才释放锁
+    ///
+    /// 其他进程的保存会等到本次结束，加载和保存之间不会有别的写入。
+    pub fn update<T>(f: impl FnOnce(&mut ConfigStore) -> Result<T>) -> Result<T> {
+        Self::update_at(None, f)
+    }
+    
+    fn update_at<T>(path: Option<PathBuf>, f: impl FnOnce(&mut ConfigStore) -> Result<T>) -> Result<T> {
+        let config_path = match path {
+            Some(ref path) => path.clone(),
+            None => AppConfig::config_path()?,
+        };
+        let mut lock = ConfigLock::open(&config_path)?;
+        let _guard = lock.write()?;
+        let config = AppConfig::load_from(&config_path)?;
+        f(&mut Self { path, base: config.clone(), config })
+    }
+    
+    fn path(&self) -> Result<PathBuf> {
+        match self.path {
+            Some(ref path) => Ok(path.clone()),
+            None => AppConfig::config_path(),
+        }
+    }
+    
+    /// 重新从磁盘加载，放弃未保存的修改
+    pub fn reload(&mut self) -> Result<()> {
+        let config = AppConfig::load_from(&self.path()?)?;
+        self.base = config.clone();
+        self.config = config;
+        Ok(())
+    }
+    
+    /// 合并其他进程在此期间的修改后保存，之后内容与磁盘上一致
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.path()?;
+        let mut lock = ConfigLock::open(&path)?;
+        let _guard = lock.write()?;
+        let on_disk = AppConfig::load_from(&path)
+            .context("磁盘上的配置文件无法解析，未保存（可使用 config restore-backup 恢复）")?;
+        let (merged, conflicts) = merge_configs(&self.base, &self.config, &on_disk)?;
+        for key in conflicts {
+            warn!("配置项 {} 同时被其他进程修改，已使用本次的修改", key);
+        }
+        merged.save_to(&path)?;
+        self.base = merged.clone();
+        self.config = merged;
+        Ok(())
+    }
+}
+
+impl Deref for ConfigStore {
+    type Target = AppConfig;
+    
+    fn deref(&self) -> &AppConfig {
+        &self.config
+    }
+}
+
+impl DerefMut for ConfigStore {
+    fn deref_mut(&mut self) -> &mut AppConfig {
+        &mut self.config
+    }
+}
+
+/// 配置文件旁的锁文件（劝告锁，只约束本程序的其他进程）
+///
+/// 配置文件本身通过重命名替换，因此锁加在旁边单独的锁文件上。
+struct ConfigLock {
+    path: PathBuf,
+    file: RwLock<fs::File>,
+}
+
+/// 持有中的配置锁，释放时解锁；本线程已持有锁时为空
+struct ConfigGuard<'a> {
+    guard: Option<RwLockWriteGuard<'a, fs::File>>,
+}
+
+impl ConfigLock {
+    fn open(config_path: &Path) -> Result<Self> {
+        let path = append_extension(config_path, "lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context(format!("无法打开锁文件: {}", path.display()))?;
+        Ok(Self { path, file: RwLock::new(file) })
+    }
+    
+    /// 取得排他锁，其他进程持有时等待
+    fn write(&mut self) -> Result<ConfigGuard<'_>> {
+        if LOCK_HELD.get() {
+            return Ok(ConfigGuard { guard: None });
+        }
+        if self.file.try_write().is_err() {
+            debug!("配置文件正被其他进程写入，等待: {}", self.path.display());
+        }
+        let guard = self.file.write().context(format!("无法锁定配置文件: {}", self.path.display()))?;
+        LOCK_HELD.set(true);
+        Ok(ConfigGuard { guard: Some(guard) })
+    }
+}
+
+impl Drop for ConfigGuard<'_> {
+    fn drop(&mut self) {
+        if self.guard.is_some() {
+            LOCK_HELD.set(false);
+        }
+    }
+}
+
+/// 三方合并：`ours` 和 `theirs` 分别是本进程和磁盘上相对 `base` 修改后的配置，返回合并结果和冲突项
+fn merge_configs(base: &AppConfig, ours: &AppConfig, theirs: &AppConfig) -> Result<(AppConfig, Vec<String>)> {
+    let to_value = |config: &AppConfig| serde_json::to_value(config).context("无法序列化配置");
+    let mut conflicts = Vec::new();
+    let merged = merge_objects(&to_value(base)?, &to_value(ours)?, &to_value(theirs)?, "", &mut conflicts);
+    let merged = serde_json::from_value(merged).context("无法合并配置")?;
+    Ok((merged, conflicts))
+}
+
+fn merge_objects(base: &Value, ours: &Value, theirs: &Value, prefix: &str, conflicts: &mut Vec<String>) -> Value {
+    let keys: BTreeSet<&String> = [base, ours, theirs]
+        .into_iter()
+        .filter_map(Value::as_object)
+        .flat_map(Map::keys)
+        .collect();
+    let mut merged = Map::new();
+    for key in keys {
+        let [b, o, t] = [base, ours, theirs].map(|value| value.get(key).unwrap_or(&Value::Null));
+        let label = format!("{}{}", prefix, key);
+        let value = if prefix.is_empty() && MERGED_TABLES.contains(&key.as_str()) {
+            merge_objects(b, o, t, &format!("{}.", label), conflicts)
+        } else if o == b || o == t {
+            t.clone()
+        } else if t == b {
+            o.clone()
+        } else {
+            conflicts.push(label);
+            o.clone()
+        };
+        if !value.is_null() {
+            merged.insert(key.clone(), value);
+        }
+    }
+    Value::Object(merged)
+}
+
+/// 写入配置文件：先轮转备份旧文件，再通过临时文件和重命名替换，避免写入中断损坏配置
+fn write_with_backup(path: &Path, content: &str, keep: usize) -> Result<()> {
+    // 内容未变化时不产生新的备份，以免挤掉有用的旧备份
+    if fs::read_to_string(path).is_ok_and(|existing| existing == content) {
+        return Ok(());
+    }
+    
+    if keep > 0 && path.exists() {
+        rotate_backups(path, keep)?;
+    }
+    
+    let tmp_path = append_extension(path, "tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp_path)
+        .context(format!("无法创建临时文件: {}", tmp_path.display()))?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .context("无法写入配置文件")?;
+    drop(file);
+    
+    fs::rename(&tmp_path, path)
+        .context(format!("无法替换配置文件: {}", path.display()))?;
+    
+    Ok(())
+}
+
+/// 第 `index` 个备份的路径：0 为 `config.toml.bak`，其余为 `config.toml.bak.N`
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        append_extension(path, "bak")
+    } else {
+        append_extension(path, &format!("bak.{}", index))
+    }
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// 轮转备份并把当前文件复制为最新的备份，最多保留 `keep` 个
+fn rotate_backups(path: &Path, keep: usize) -> Result<()> {
+    // 删除最旧的备份，以及调小数量后多出的备份
+    for index in keep - 1..MAX_CONFIG_BACKUPS {
+        let stale = backup_path(path, index);
+        if stale.exists() {
+            fs::remove_file(&stale)
+                .context(format!("无法删除旧备份: {}", stale.display()))?;
+        }
+    }
+    
+    for index in (0..keep - 1).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))
+                .context(format!("无法轮转备份: {}", from.display()))?;
+        }
+    }
+    
+    let backup = backup_path(path, 0);
+    fs::copy(path, &backup)
+        .context(format!("无法备份配置文件到: {}", backup.display()))?;
+    restrict_permissions(&backup)?;
+    
+    Ok(())
+}
+
+/// 备份包含加密的密码，只允许当前用户读写
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .context(format!("无法设置文件权限: {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// 列出配置文件的备份，从新到旧排列
+pub fn list_backups(path: &Path) -> Vec<ConfigBackup> {
+    (0..MAX_CONFIG_BACKUPS)
+        .map(|index| backup_path(path, index))
+        .filter_map(|backup| {
+            let modified = fs::metadata(&backup).and_then(|m| m.modified()).ok()?;
+            Some(ConfigBackup { path: backup, modified })
+        })
+        .collect()
+}
+
+/// 用备份替换配置文件（当前配置同样先被备份，恢复操作可以撤销）
+pub fn restore_backup(path: &Path, backup: &ConfigBackup, keep: usize) -> Result<()> {
+    let content = fs::read_to_string(&backup.path)
+        .context(format!("无法读取备份: {}", backup.path.display()))?;
+    toml::from_str::<AppConfig>(&content)
+        .context(format!("备份文件无法解析: {}", backup.path.display()))?;
+    
+    let mut lock = ConfigLock::open(path)?;
+    let _guard = lock.write()?;
+    write_with_backup(path, &content, keep)
+}
+
+/// 校验书签名称（字母、数字、`-`、`_`）
+pub fn validate_bookmark_name(name: &str) -> Result<()> {
+    if !is_simple_name(name) {
+        anyhow::bail!("无效的书签名称: '{}'（只能包含字母、数字、'-' 和 '_'）", name);
+    }
+    Ok(())
+}
+
+/// 校验附加密文的名称（规则与书签名称相同）
+pub fn validate_secret_key(key: &str) -> Result<()> {
+    if !is_simple_name(key) {
+        anyhow::bail!("无效的密文名称: '{}'（只能包含字母、数字、'-' 和 '_'）", key);
+    }
+    Ok(())
+}
+
+fn is_simple_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 预设的标签颜色
+pub const LABEL_COLORS: &[(&str, [u8; 3])] = &[
+    ("red", [0xe5, 0x48, 0x4d]),
+    ("orange", [0xf7, 0x8c, 0x2c]),
+    ("yellow", [0xe5, 0xc0, 0x2e]),
+    ("green", [0x46, 0xa7, 0x58]),
+    ("blue", [0x3e, 0x7c, 0xd6]),
+    ("purple", [0x8e, 0x4e, 0xc6]),
+    ("gray", [0x8b, 0x8d, 0x98]),
+];
+
+/// 解析标签颜色：预设颜色名称（不区分大小写）或 `#rrggbb`，返回规范化后的写法
+pub fn parse_label_color(color: &str) -> Result<String> {
+    let color = color.trim().to_lowercase();
+    if label_rgb(&color).is_none() {
+        let names: Vec<_> = LABEL_COLORS.iter().map(|(name, _)| *name).collect();
+        anyhow::bail!("无效的颜色: '{}'（可用 {} 或 #rrggbb）", color, names.join("、"));
+    }
+    Ok(color)
+}
+
+/// 标签颜色对应的 RGB 值
+pub fn label_rgb(color: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some([channel(0)?, channel(2)?, channel(4)?]);
+    }
+    LABEL_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+        .map(|(_, rgb)| *rgb)
+}
+
+/// 连接时采用的认证来源
+///
+/// 优先级：命令行 `-i` > 连接保存的认证配置 > 交互式输入密码。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthPlan {
+    /// 使用命令行指定的私钥
+    CliIdentity(String),
+    /// 解密连接保存的密码或私钥密码（需要主密码）
+    SavedSecret,
+    /// 使用连接保存的私钥，私钥密码未保存
+    SavedKey,
+    /// 使用 SSH agent
+    Agent,
+    /// 交互式输入密码
+    Prompt,
+}
+
+impl AuthPlan {
+    /// 认证来源的说明，用于调试日志
+    pub fn source(&self) -> &'static str {
+        match self {
+            AuthPlan::CliIdentity(_) => "命令行私钥",
+            AuthPlan::SavedSecret => "保存的加密凭据",
+            AuthPlan::SavedKey => "保存的私钥",
+            AuthPlan::Agent => "SSH agent",
+            AuthPlan::Prompt => "交互式输入",
+        }
+    }
+}
+
+/// 根据命令行参数和保存的连接决定认证来源
+pub fn resolve_auth(cli_identity: Option<&str>, saved: Option<&SavedConnection>) -> AuthPlan {
+    if let Some(path) = cli_identity {
+        return AuthPlan::CliIdentity(path.to_string());
+    }
+    let Some(saved) = saved else {
+        return AuthPlan::Prompt;
+    };
+    if saved.has_saved_password() {
+        return AuthPlan::SavedSecret;
+    }
+    match saved.auth_type.as_str() {
+        "publickey" => AuthPlan::SavedKey,
+        "agent" => AuthPlan::Agent,
+        _ => AuthPlan::Prompt,
+    }
+}
+
 impl SavedConnection {
+    /// 连接是否指向 `host:port`（主机名不区分大小写）
+    fn targets(&self, host: &str, port: u16) -> bool {
+        self.port == port && self.host.eq_ignore_ascii_case(host)
+    }
+    
     /// 转换为 SshConfig（需要密码或密钥密码）
     pub fn to_ssh_config(&self, password: Option<String>, passphrase: Option<String>) -> Result<SshConfig> {
         let auth = match self.auth_type.as_str() {
@@ -152,15 +936,17 @@ impl SavedConnection {
                 AuthMethod::Password(pwd)
             }
             "publickey" => {
-                let private_key = self.private_key_path.clone()
+                let private_key = self.private_key_path.as_deref()
                     .context("公钥认证需要提供私钥路径")?;
+                let private_key = paths::resolve_key_path(private_key)?;
 
                 AuthMethod::PublicKey {
-                    public_key: self.public_key_path.clone(),
+                    public_key: self.public_key_path.as_deref().map(paths::expand),
                     private_key,
                     passphrase,
                 }
             }
+            "agent" => AuthMethod::Agent,
             _ => anyhow::bail!("未知的认证类型: {}", self.auth_type),
         };
 
@@ -192,8 +978,9 @@ impl SavedConnection {
                 AuthMethod::Password(pwd)
             }
             "publickey" => {
-                let private_key = self.private_key_path.clone()
+                let private_key = self.private_key_path.as_deref()
                     .context("公钥认证需要提供私钥路径")?;
+                let private_key = paths::resolve_key_path(private_key)?;
 
                 let passphrase = if let Some(pp) = passphrase_override {
                     Some(pp)
@@ -205,11 +992,12 @@ impl SavedConnection {
                 };
 
                 AuthMethod::PublicKey {
-                    public_key: self.public_key_path.clone(),
+                    public_key: self.public_key_path.as_deref().map(paths::expand),
                     private_key,
                     passphrase,
                 }
             }
+            "agent" => AuthMethod::Agent,
             _ => anyhow::bail!("未知的认证类型: {}", self.auth_type),
         };
 
@@ -221,12 +1009,45 @@ impl SavedConnection {
         })
     }
 
+    /// 解密保存的密码或私钥密码；解密失败时提示并改为询问
+    pub fn to_ssh_config_or_prompt(&self, crypto: &CryptoManager, prompter: &mut dyn Prompter) -> Result<SshConfig> {
+        match self.to_ssh_config_with_decryption(crypto, None, None) {
+            Ok(config) => {
+                eprintln!("{} 使用已保存的密码", glyphs::ok().green());
+                Ok(config)
+            }
+            Err(e) => {
+                eprintln!("{} 解密失败: {}", glyphs::fail().red(), e);
+                eprintln!("{} 请手动输入密码", glyphs::arrow().yellow());
+
+                let password = if self.auth_type == "password" {
+                    Some(credentials::password(prompter, &self.username, &self.host)?)
+                } else {
+                    None
+                };
+
+                let passphrase = if self.auth_type == "publickey" {
+                    credentials::passphrase(prompter, self.private_key_path.as_deref().unwrap_or_default())?
+                } else {
+                    None
+                };
+
+                self.to_ssh_config(password, passphrase)
+            }
+        }
+    }
+
     /// 检查是否保存了密码
     pub fn has_saved_password(&self) -> bool {
+        self.encrypted_secret().is_some()
+    }
+    
+    /// 已保存的加密数据（密码或私钥密码），用于校验主密码
+    pub fn encrypted_secret(&self) -> Option<&str> {
         match self.auth_type.as_str() {
-            "password" => self.encrypted_password.is_some(),
-            "publickey" => self.encrypted_passphrase.is_some(),
-            _ => false,
+            "password" => self.encrypted_password.as_deref(),
+            "publickey" => self.encrypted_passphrase.as_deref(),
+            _ => None,
         }
     }
     
@@ -242,6 +1063,20 @@ impl SavedConnection {
             public_key_path: None,
             encrypted_password: None,
             encrypted_passphrase: None,
+            snippets: HashMap::new(),
+            pre_connect_command: None,
+            post_disconnect_command: None,
+            credential_helper: None,
+            bookmarks: HashMap::new(),
+            jump_host: None,
+            escape_char: None,
+            host_key_fingerprint: None,
+            server_info: None,
+            note: None,
+            color: None,
+            secrets: HashMap::new(),
+            last_used: None,
+            default_remote_dir: None,
         }
     }
 
@@ -263,6 +1098,20 @@ impl SavedConnection {
             public_key_path: None,
             encrypted_password: Some(encrypted_password),
             encrypted_passphrase: None,
+            snippets: HashMap::new(),
+            pre_connect_command: None,
+            post_disconnect_command: None,
+            credential_helper: None,
+            bookmarks: HashMap::new(),
+            jump_host: None,
+            escape_char: None,
+            host_key_fingerprint: None,
+            server_info: None,
+            note: None,
+            color: None,
+            secrets: HashMap::new(),
+            last_used: None,
+            default_remote_dir: None,
         }
     }
 
@@ -285,6 +1134,49 @@ impl SavedConnection {
             public_key_path,
             encrypted_password: None,
             encrypted_passphrase: None,
+            snippets: HashMap::new(),
+            pre_connect_command: None,
+            post_disconnect_command: None,
+            credential_helper: None,
+            bookmarks: HashMap::new(),
+            jump_host: None,
+            escape_char: None,
+            host_key_fingerprint: None,
+            server_info: None,
+            note: None,
+            color: None,
+            secrets: HashMap::new(),
+            last_used: None,
+            default_remote_dir: None,
+        }
+    }
+
+    /// 创建新的 SSH agent 认证连接
+    pub fn new_agent(name: String, host: String, port: u16, username: String) -> Self {
+        Self {
+            name,
+            host,
+            port,
+            username,
+            auth_type: "agent".to_string(),
+            private_key_path: None,
+            public_key_path: None,
+            encrypted_password: None,
+            encrypted_passphrase: None,
+            snippets: HashMap::new(),
+            pre_connect_command: None,
+            post_disconnect_command: None,
+            credential_helper: None,
+            bookmarks: HashMap::new(),
+            jump_host: None,
+            escape_char: None,
+            host_key_fingerprint: None,
+            server_info: None,
+            note: None,
+            color: None,
+            secrets: HashMap::new(),
+            last_used: None,
+            default_remote_dir: None,
         }
     }
 
@@ -309,6 +1201,20 @@ impl SavedConnection {
             public_key_path,
             encrypted_password: None,
             encrypted_passphrase: Some(encrypted_passphrase),
+            snippets: HashMap::new(),
+            pre_connect_command: None,
+            post_disconnect_command: None,
+            credential_helper: None,
+            bookmarks: HashMap::new(),
+            jump_host: None,
+            escape_char: None,
+            host_key_fingerprint: None,
+            server_info: None,
+            note: None,
+            color: None,
+            secrets: HashMap::new(),
+            last_used: None,
+            default_remote_dir: None,
         }
     }
 }
@@ -316,6 +1222,7 @@ impl SavedConnection {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prompt::Scripted;
     
     #[test]
     fn test_saved_connection_creation() {
@@ -345,5 +1252,456 @@ mod tests {
         assert_eq!(config.connections.len(), 1);
         assert!(config.get_connection("test").is_some());
     }
-}
+    
+    #[test]
+    fn test_note_and_color() {
+        assert_eq!(parse_label_color(" Red ").unwrap(), "red");
+        assert_eq!(parse_label_color("#FF8800").unwrap(), "#ff8800");
+        assert_eq!(label_rgb("#ff8800"), Some([0xff, 0x88, 0x00]));
+        assert!(parse_label_color("#ff88").is_err());
+        assert!(parse_label_color("pink").is_err());
+        
+        // 旧配置没有这两个字段
+        let config: AppConfig = toml::from_str(
+            "[connections.web]\nname = \"web\"\nhost = \"h\"\nport = 22\nusername = \"u\"\n",
+        ).unwrap();
+        assert_eq!(config.connections["web"].note, None);
+        
+        // 覆盖连接时保留备注和颜色
+        let mut config = AppConfig::default();
+        let mut conn = SavedConnection::new_password("web".into(), "h".into(), 22, "u".into());
+        conn.note = Some("生产环境".to_string());
+        conn.color = Some("red".to_string());
+        config.add_connection(conn);
+        config.add_connection(SavedConnection::new_password("web".into(), "h2".into(), 22, "u".into()));
+        let conn = config.get_connection("web").unwrap();
+        assert_eq!(conn.note.as_deref(), Some("生产环境"));
+        assert_eq!(conn.color.as_deref(), Some("red"));
+        assert!(!toml::to_string(&SavedConnection::new_agent("a".into(), "h".into(), 22, "u".into())).unwrap().contains("note"));
+    }
+    
+    #[test]
+    fn test_recent_connections() {
+        let mut config = AppConfig::default();
+        for name in ["a", "b", "c"] {
+            config.add_connection(SavedConnection::new_password(name.into(), "h".into(), 22, "u".into()));
+        }
+        config.touch("a", 100);
+        config.touch("c", 300);
+        config.touch("missing", 400);
+        let recent: Vec<_> = config.recent_connections(5).iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(recent, ["c", "a"]);
+        assert_eq!(config.recent_connections(1).len(), 1);
+        
+        // 覆盖连接时保留使用时间
+        config.add_connection(SavedConnection::new_password("a".into(), "h2".into(), 22, "u".into()));
+        assert_eq!(config.get_connection("a").unwrap().last_used, Some(100));
+    }
+    
+    #[test]
+    fn test_agent_connection_to_ssh_config() {
+        let conn = SavedConnection::new_agent(
+            "agent".to_string(),
+            "example.com".to_string(),
+            22,
+            "user".to_string(),
+        );
+        
+        assert!(!conn.has_saved_password());
+        let config = conn.to_ssh_config(None, None).unwrap();
+        assert!(matches!(config.auth, AuthMethod::Agent));
+    }
+    
+    #[test]
+    fn test_decrypt_failure_falls_back_to_prompt() {
+        let crypto = CryptoManager::new("right-master").unwrap();
+        let stale = CryptoManager::new("old-master").unwrap().encrypt("saved-pw").unwrap();
+        let conn = SavedConnection::new_password_with_encrypted(
+            "fallback".into(), "fallback.example".into(), 22, "deploy".into(), stale,
+        );
+
+        let mut prompter = Scripted::new(&["typed-pw"]);
+        let config = conn.to_ssh_config_or_prompt(&crypto, &mut prompter).unwrap();
+        assert!(matches!(config.auth, AuthMethod::Password(ref pw) if pw == "typed-pw"));
+        assert_eq!(prompter.asked, ["[deploy@fallback.example] 密码: "]);
+
+        // 能解密时不询问
+        let conn = SavedConnection::new_password_with_encrypted(
+            "ok".into(), "ok.example".into(), 22, "deploy".into(), crypto.encrypt("saved-pw").unwrap(),
+        );
+        let mut prompter = Scripted::new(&[]);
+        let config = conn.to_ssh_config_or_prompt(&crypto, &mut prompter).unwrap();
+        assert!(matches!(config.auth, AuthMethod::Password(ref pw) if pw == "saved-pw"));
+        assert!(prompter.asked.is_empty());
+    }
+
+    #[test]
+    fn test_store_password_after_connect() {
+        let connection = || SavedConnection::new_password("web".into(), "web.example".into(), 2222, "deploy".into());
+
+        // 首次设置主密码时两次输入不一致，不保存
+        let mut config = AppConfig::default();
+        let mut prompter = Scripted::new(&["master-a", "master-b"]);
+        assert!(config.store_password(&mut prompter, connection(), "login-pw", true).is_err());
+        assert!(config.get_connection("web").is_none());
 
+        let mut prompter = Scripted::new(&["master-a", "master-a"]);
+        config.store_password(&mut prompter, connection(), "login-pw", true).unwrap();
+        let saved = config.get_connection("web").unwrap();
+        assert_eq!((saved.host.as_str(), saved.port, saved.username.as_str()), ("web.example", 2222, "deploy"));
+        let encrypted = saved.encrypted_password.as_deref().unwrap();
+        assert_eq!(CryptoManager::new("master-a").unwrap().decrypt(encrypted).unwrap(), "login-pw");
+    }
+
+    #[test]
+    fn test_resolve_auth() {
+        let h = || "h".to_string();
+        let password = SavedConnection::new_password("p".into(), h(), 22, "u".into());
+        let saved_password = SavedConnection::new_password_with_encrypted("s".into(), h(), 22, "u".into(), "x".into());
+        let key = SavedConnection::new_publickey("k".into(), h(), 22, "u".into(), "~/.ssh/id".into(), None);
+        let saved_key = SavedConnection::new_publickey_with_encrypted("e".into(), h(), 22, "u".into(), "~/.ssh/id".into(), None, "x".into());
+        let agent = SavedConnection::new_agent("a".into(), h(), 22, "u".into());
+
+        // 命令行 -i 优先于任何保存的认证配置
+        for conn in [None, Some(&password), Some(&saved_password), Some(&key), Some(&saved_key), Some(&agent)] {
+            assert_eq!(resolve_auth(Some("~/.ssh/cli"), conn), AuthPlan::CliIdentity("~/.ssh/cli".to_string()));
+        }
+        assert_eq!(resolve_auth(None, Some(&saved_password)), AuthPlan::SavedSecret);
+        assert_eq!(resolve_auth(None, Some(&saved_key)), AuthPlan::SavedSecret);
+        assert_eq!(resolve_auth(None, Some(&key)), AuthPlan::SavedKey);
+        assert_eq!(resolve_auth(None, Some(&agent)), AuthPlan::Agent);
+        assert_eq!(resolve_auth(None, Some(&password)), AuthPlan::Prompt);
+        assert_eq!(resolve_auth(None, None), AuthPlan::Prompt);
+    }
+    
+    #[test]
+    fn test_snippet_lookup() {
+        let mut config = AppConfig::default();
+        config.snippets.insert("disk".to_string(), "df -h".to_string());
+        config.snippets.insert("logs".to_string(), "tail /var/log/syslog".to_string());
+        
+        let mut conn = SavedConnection::new_password(
+            "web".to_string(),
+            "example.com".to_string(),
+            22,
+            "user".to_string(),
+        );
+        conn.snippets.insert("logs".to_string(), "tail /var/log/nginx/error.log".to_string());
+        config.add_connection(conn);
+        
+        assert_eq!(config.find_snippet(Some("web"), "logs").unwrap(), "tail /var/log/nginx/error.log");
+        assert_eq!(config.find_snippet(Some("web"), "disk").unwrap(), "df -h");
+        assert_eq!(config.find_snippet(None, "logs").unwrap(), "tail /var/log/syslog");
+        assert!(config.find_snippet(Some("web"), "missing").is_none());
+        
+        // 覆盖连接时保留片段
+        config.add_connection(SavedConnection::new_password(
+            "web".to_string(),
+            "example.org".to_string(),
+            22,
+            "user".to_string(),
+        ));
+        assert_eq!(config.snippets_for(Some("web")).len(), 2);
+        assert_eq!(config.find_snippet(Some("web"), "logs").unwrap(), "tail /var/log/nginx/error.log");
+    }
+    
+    #[test]
+    fn test_hooks_fallback_and_round_trip() {
+        let mut config = AppConfig {
+            pre_connect_command: Some("vpn up".to_string()),
+            ..Default::default()
+        };
+        
+        let mut conn = SavedConnection::new_password(
+            "web".to_string(),
+            "example.com".to_string(),
+            22,
+            "user".to_string(),
+        );
+        conn.post_disconnect_command = Some("sync-notes {name}".to_string());
+        config.add_connection(conn);
+        
+        assert_eq!(
+            config.hooks_for(Some("web")),
+            (Some("vpn up".to_string()), Some("sync-notes {name}".to_string()))
+        );
+        assert_eq!(config.hooks_for(None), (Some("vpn up".to_string()), None));
+        
+        let parsed: AppConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(parsed.hooks_for(Some("web")), config.hooks_for(Some("web")));
+    }
+    
+    #[test]
+    fn test_credential_helper_fallback() {
+        let mut config = AppConfig {
+            credential_helper: Some("vault kv get -field=password secret/ssh/{name}".to_string()),
+            ..Default::default()
+        };
+        let mut conn = SavedConnection::new_password("db".to_string(), "db.internal".to_string(), 22, "app".to_string());
+        conn.credential_helper = Some("pass show db".to_string());
+        config.add_connection(conn);
+        config.add_connection(SavedConnection::new_password("web".to_string(), "web.internal".to_string(), 22, "app".to_string()));
+        
+        assert_eq!(config.credential_helper_for(Some("db")), Some("pass show db"));
+        assert_eq!(config.credential_helper_for(Some("web")), Some("vault kv get -field=password secret/ssh/{name}"));
+        assert_eq!(config.credential_helper_for(None), config.credential_helper.as_deref());
+        
+        let parsed: AppConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(parsed.credential_helper_for(Some("db")), Some("pass show db"));
+    }
+    
+    #[test]
+    fn test_resolve_bookmark() {
+        let mut config = AppConfig::default();
+        let mut conn = SavedConnection::new_password(
+            "web".to_string(),
+            "example.com".to_string(),
+            22,
+            "user".to_string(),
+        );
+        conn.bookmarks.insert("logs".to_string(), "/var/log/myapp/".to_string());
+        config.add_connection(conn);
+        
+        assert_eq!(config.resolve_bookmark("web", "@logs").unwrap(), "/var/log/myapp/");
+        assert_eq!(config.resolve_bookmark("web", "@logs/app.log").unwrap(), "/var/log/myapp/app.log");
+        assert_eq!(config.resolve_bookmark("web", "/tmp").unwrap(), "/tmp");
+        assert!(config.resolve_bookmark("web", "@missing").unwrap_err().to_string().contains("书签不存在"));
+        assert!(config.resolve_bookmark("user@other", "@logs").is_err());
+        
+        assert!(validate_bookmark_name("app-logs_2").is_ok());
+        assert!(validate_bookmark_name("a/b").is_err());
+        assert!(validate_bookmark_name("").is_err());
+    }
+    
+    #[test]
+    fn test_escape_char_priority() {
+        let mut config = AppConfig::default();
+        assert_eq!(config.escape_char_for(Some("web"), None).unwrap(), Some(b'~'));
+        
+        config.escape_char = Some("%".to_string());
+        let mut conn = SavedConnection::new_password(
+            "web".to_string(),
+            "example.com".to_string(),
+            22,
+            "user".to_string(),
+        );
+        conn.escape_char = Some("none".to_string());
+        config.add_connection(conn);
+        
+        assert_eq!(config.escape_char_for(Some("web"), None).unwrap(), None);
+        assert_eq!(config.escape_char_for(Some("other"), None).unwrap(), Some(b'%'));
+        assert_eq!(config.escape_char_for(Some("web"), Some("!")).unwrap(), Some(b'!'));
+        assert!(config.escape_char_for(None, Some("ab")).is_err());
+    }
+    
+    #[test]
+    fn test_defaults_table() {
+        let config = AppConfig::default();
+        assert_eq!(config.default_dir_mode().unwrap(), None);
+        assert!(!toml::to_string_pretty(&config).unwrap().contains("[defaults]"));
+        
+        let config: AppConfig = toml::from_str("unlock_cooldown = 10\n\n[defaults]\ndir_mode = \"750\"\n").unwrap();
+        assert_eq!(config.default_dir_mode().unwrap(), Some(0o750));
+        assert_eq!(config.sftp_retry_policy(), RetryPolicy::default());
+        let parsed: AppConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(parsed.defaults, config.defaults);
+        
+        let config: AppConfig = toml::from_str("[defaults]\ndir_mode = \"rwx\"\n").unwrap();
+        assert!(config.default_dir_mode().is_err());
+        
+        let config: AppConfig = toml::from_str("[defaults]\nsftp_retries = 0\nsftp_retry_backoff_ms = 50\n").unwrap();
+        assert_eq!(config.sftp_retry_policy(), RetryPolicy { attempts: 1, backoff: Duration::from_millis(50) });
+    }
+    
+    #[test]
+    fn test_gui_settings_round_trip() {
+        let mut config = AppConfig::default();
+        assert!(!toml::to_string_pretty(&config).unwrap().contains("[gui]"));
+        
+        config.gui = GuiSettings {
+            theme: GuiTheme::Dark,
+            font_size: Some(16.0),
+            monospace_font: Some("/usr/share/fonts/mono.ttf".to_string()),
+        };
+        let content = toml::to_string_pretty(&config).unwrap();
+        assert!(content.contains("theme = \"dark\""));
+        let parsed: AppConfig = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.gui, config.gui);
+        
+        let parsed: AppConfig = toml::from_str("[gui]\ntheme = \"light\"\n").unwrap();
+        assert_eq!(parsed.gui.theme, GuiTheme::Light);
+        assert_eq!(parsed.gui.font_size, None);
+    }
+    
+    #[test]
+    fn test_pin_fingerprint() {
+        let mut config = AppConfig::default();
+        for name in ["web", "web-admin", "db"] {
+            let host = if name == "db" { "db.example.com" } else { "Example.com" };
+            config.add_connection(SavedConnection::new_password(
+                name.to_string(),
+                host.to_string(),
+                22,
+                "user".to_string(),
+            ));
+        }
+        
+        assert!(config.has_unpinned("example.com", 22));
+        assert_eq!(config.pin_fingerprint("example.com", 22, "SHA256:a"), vec!["web", "web-admin"]);
+        assert!(!config.has_unpinned("example.com", 22));
+        assert!(config.pin_fingerprint("example.com", 22, "SHA256:b").is_empty());
+        assert!(config.pinned_mismatch("example.com", 22, "SHA256:a").is_none());
+        assert!(config.pinned_mismatch("example.com", 2222, "SHA256:b").is_none());
+        assert_eq!(config.pinned_mismatch("example.com", 22, "SHA256:b").unwrap().name, "web");
+        
+        assert_eq!(config.reset_fingerprint("web").unwrap().as_deref(), Some("SHA256:a"));
+        assert_eq!(config.pinned_mismatch("example.com", 22, "SHA256:b").unwrap().name, "web-admin");
+        assert!(config.reset_fingerprint("missing").is_err());
+    }
+    
+    #[test]
+    fn test_server_info_round_trip() {
+        let mut config = AppConfig::default();
+        config.add_connection(SavedConnection::new_password(
+            "db".to_string(),
+            "db.example.com".to_string(),
+            22,
+            "user".to_string(),
+        ));
+        config.connections.get_mut("db").unwrap().server_info = Some(ServerInfo {
+            banner: Some("SSH-2.0-OpenSSH_9.6".to_string()),
+            kex: "curve25519-sha256".to_string(),
+            host_key_type: "ssh-ed25519".to_string(),
+            fingerprint: "SHA256:c".to_string(),
+            cipher: "chacha20-poly1305@openssh.com".to_string(),
+            mac: "hmac-sha2-256".to_string(),
+            seen_at: 1_700_000_000,
+            os: Some("Debian GNU/Linux 12 (bookworm) (Linux x86_64)".to_string()),
+        });
+        let parsed: AppConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(parsed.connections["db"].server_info, config.connections["db"].server_info);
+    }
+    
+    #[test]
+    fn test_save_rotates_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = |name: &str| AppConfig {
+            default_connection: Some(name.to_string()),
+            config_backups: Some(2),
+            ..Default::default()
+        };
+        
+        config("v1").save_to(&path).unwrap();
+        assert!(list_backups(&path).is_empty());
+        // 内容未变化时不产生备份
+        config("v1").save_to(&path).unwrap();
+        assert!(list_backups(&path).is_empty());
+        
+        for name in ["v2", "v3", "v4"] {
+            config(name).save_to(&path).unwrap();
+        }
+        let backups = list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        assert!(fs::read_to_string(&backups[0].path).unwrap().contains("v3"));
+        assert!(fs::read_to_string(&backups[1].path).unwrap().contains("v2"));
+        assert!(!append_extension(&path, "tmp").exists());
+        
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [&path, &backups[0].path] {
+                assert_eq!(fs::metadata(file).unwrap().permissions().mode() & 0o777, 0o600);
+            }
+        }
+        
+        // 恢复后当前配置成为最新的备份
+        restore_backup(&path, &backups[1], 2).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("v2"));
+        assert!(fs::read_to_string(backup_path(&path, 0)).unwrap().contains("v4"));
+        
+        // 损坏的备份不会被恢复
+        fs::write(backup_path(&path, 1), "connections = [").unwrap();
+        assert!(restore_backup(&path, &list_backups(&path)[1], 2).is_err());
+        assert!(fs::read_to_string(&path).unwrap().contains("v2"));
+    }
+    
+    #[test]
+    fn test_store_merges_concurrent_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let conn = |name: &str, host: &str| SavedConnection::new_password(name.into(), host.into(), 22, "u".into());
+        
+        // 两个进程（例如 GUI 和命令行）先后加载同一份配置
+        let mut gui = ConfigStore::load_from(&path).unwrap();
+        let mut cli = ConfigStore::load_from(&path).unwrap();
+        
+        gui.add_connection(conn("web", "10.0.0.1"));
+        gui.save().unwrap();
+        cli.add_connection(conn("db", "10.0.0.2"));
+        cli.snippets.insert("uptime".into(), "uptime".into());
+        cli.save().unwrap();
+        
+        let on_disk = AppConfig::load_from(&path).unwrap();
+        assert!(on_disk.get_connection("web").is_some());
+        assert!(on_disk.get_connection("db").is_some());
+        assert_eq!(on_disk.snippets["uptime"], "uptime");
+        // 保存后与磁盘一致，GUI 之后的保存也不会丢掉命令行的修改
+        assert!(cli.get_connection("web").is_some());
+        gui.remove_connection("web").unwrap();
+        gui.save().unwrap();
+        let on_disk = AppConfig::load_from(&path).unwrap();
+        assert!(on_disk.get_connection("web").is_none());
+        assert!(on_disk.get_connection("db").is_some());
+        
+        // 同一连接被双方改成不同的值时以本次为准
+        let (base, mut ours, mut theirs) = (on_disk.clone(), on_disk.clone(), on_disk);
+        ours.connections.get_mut("db").unwrap().port = 2200;
+        theirs.connections.get_mut("db").unwrap().port = 2222;
+        theirs.use_openssh_config = true;
+        let (merged, conflicts) = merge_configs(&base, &ours, &theirs).unwrap();
+        assert_eq!(conflicts, vec!["connections.db".to_string()]);
+        assert_eq!(merged.connections["db"].port, 2200);
+        assert!(merged.use_openssh_config);
+        
+        // 磁盘上的配置损坏时不覆盖
+        fs::write(&path, "connections = [").unwrap();
+        assert!(cli.save().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "connections = [");
+    }
+    
+    #[test]
+    fn test_update_holds_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let conn = |name: &str| SavedConnection::new_password(name.into(), "10.0.0.1".into(), 22, "u".into());
+        
+        let other = ConfigStore::update_at(Some(path.clone()), |config| {
+            // 另一个线程的保存要等到本次修改完成
+            let other_path = path.clone();
+            let other = std::thread::spawn(move || {
+                let mut gui = ConfigStore::load_from(&other_path).unwrap();
+                gui.add_connection(conn("web"));
+                gui.save().unwrap();
+            });
+            std::thread::sleep(Duration::from_millis(200));
+            assert!(!other.is_finished());
+            
+            // 锁内再次加锁（例如认证成功后记录指纹）不会等待自己
+            ConfigStore::update_at(Some(path.clone()), |nested| {
+                nested.snippets.insert("uptime".into(), "uptime".into());
+                nested.save()
+            })?;
+            config.add_connection(conn("db"));
+            config.save()?;
+            Ok(other)
+        })
+        .unwrap();
+        other.join().unwrap();
+        
+        let on_disk = AppConfig::load_from(&path).unwrap();
+        assert!(on_disk.get_connection("web").is_some());
+        assert!(on_disk.get_connection("db").is_some());
+        assert_eq!(on_disk.snippets["uptime"], "uptime");
+    }
+}
@@ -0,0 +1,593 @@
+//! 建立连接的统一流程：解析目标 → 获取凭据 → 连接 → 连接成功后保存
+//!
+//! `connect` 命令的 russh 交互式终端和简单 shell 都经过这里，GUI 在新终端中启动的会话同样运行
+//! `connect` 命令。凭据通过 [`Prompter`] 询问；需要写入配置的内容只在连接成功后随连接一起返回，
+//! 连接失败时不会保存任何东西。
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use tokio_util::sync::CancellationToken;
+use zeroize::Zeroizing;
+
+use crate::config::{resolve_auth, AppConfig, AuthPlan, ConfigStore, SavedConnection};
+use crate::credential_helper;
+use crate::credentials::{self, Credential};
+use crate::crypto::CryptoManager;
+use crate::glyphs;
+use crate::interrupt::SigintScope;
+use crate::openssh_config::{HostSettings, OpenSshConfig};
+use crate::paths;
+use crate::prompt::{self, Prompter};
+use crate::ssh::{AuthMethod, PasswordRejected, SshClient, SshConfig};
+use crate::ssh_russh::{self, RusshClient};
+
+/// `--batch` 下保存的密码被服务器拒绝时的退出码
+pub const STALE_PASSWORD_EXIT_CODE: i32 = 77;
+
+/// 保存的密码能解密但被服务器拒绝（密码可能已更改），`--batch` 下不询问新密码
+#[derive(Debug, thiserror::Error)]
+#[error("连接 '{connection}' 保存的密码已被服务器拒绝（密码可能已更改），请交互运行一次 connect 输入新密码并更新")]
+pub struct StalePassword {
+    pub connection: String,
+}
+
+/// 连接选项（对应 `connect` 命令的参数）
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    /// 目标不是保存的连接时使用的端口
+    pub port: Option<u16>,
+    /// 命令行 `-i` 指定的私钥，优先于连接保存的认证配置
+    pub identity_file: Option<String>,
+    /// 连接成功后加密保存输入的登录密码
+    pub save_password: bool,
+    /// 目标不是保存的连接时，连接成功后以此名称保存
+    pub save_as: Option<String>,
+}
+
+/// 连接成功后需要写入配置的内容
+pub struct PendingSave {
+    /// 要添加或覆盖的连接
+    pub connection: SavedConnection,
+    /// 需要加密保存的登录密码
+    pub password: Option<Zeroizing<String>>,
+}
+
+impl PendingSave {
+    /// 写入内存中的配置，有密码时询问主密码并加密；调用方负责保存
+    pub fn apply(self, config: &mut AppConfig, prompter: &mut dyn Prompter, is_first_time: bool) -> Result<()> {
+        match self.password {
+            Some(password) => config.store_password(prompter, self.connection, &password, is_first_time),
+            None => {
+                config.add_connection(self.connection);
+                Ok(())
+            }
+        }
+    }
+
+    /// 写入配置并保存到磁盘
+    pub fn persist(self, config: &mut ConfigStore, prompter: &mut dyn Prompter) -> Result<()> {
+        let name = self.connection.name.clone();
+        let with_password = self.password.is_some();
+        if with_password {
+            println!("{} 正在保存密码...", glyphs::arrow().cyan());
+        }
+
+        let is_first_time = !CryptoManager::has_master_password();
+        self.apply(config, prompter, is_first_time)?;
+        config.save()?;
+
+        if with_password {
+            println!("{} 密码已加密保存到连接: {}", glyphs::ok().green(), name.bold());
+        } else {
+            println!("{} 已保存为连接: {}", glyphs::ok().green(), name.bold());
+        }
+        Ok(())
+    }
+}
+
+/// 已获取凭据、尚未连接的目标
+pub struct Prepared {
+    pub ssh_config: SshConfig,
+    pub save: Option<PendingSave>,
+    /// 使用了解密得到的已保存密码时为该连接，密码被拒绝时据此询问新密码
+    pub saved_password: Option<SavedConnection>,
+}
+
+/// 保存的密码被拒绝后，用新输入的密码重试
+struct StaleRetry {
+    ssh_config: SshConfig,
+    connection: SavedConnection,
+    password: Zeroizing<String>,
+}
+
+/// 已建立的连接，以及连接成功后需要保存的内容
+pub struct Connected<C> {
+    pub client: C,
+    pub save: Option<PendingSave>,
+}
+
+/// 连接流程，在 `config` 中查找保存的连接，通过 `prompter` 询问凭据
+pub struct ConnectPipeline<'a> {
+    config: &'a AppConfig,
+    prompter: &'a mut dyn Prompter,
+}
+
+impl<'a> ConnectPipeline<'a> {
+    pub fn new(config: &'a AppConfig, prompter: &'a mut dyn Prompter) -> Self {
+        Self { config, prompter }
+    }
+
+    /// 解析目标并获取凭据，不建立连接
+    ///
+    /// 保存的连接按 [`resolve_auth`] 的优先级认证；`--save-password` 只保存本次手动输入的密码。
+    /// 其他目标按 `user@host` 或 ~/.ssh/config 中的别名解析，指定了 `--save-as` 或
+    /// `--save-password` 时保存为连接（名称默认为 `user@host`）。
+    pub fn prepare(&mut self, target: &str, options: &ConnectOptions) -> Result<Prepared> {
+        match self.config.get_connection(target) {
+            Some(saved) => self.prepare_saved(saved, options),
+            None => self.prepare_target(target, options),
+        }
+    }
+
+    /// 使用 ssh2 连接（简单 shell、命令执行等）
+    pub fn connect(mut self, target: &str, options: &ConnectOptions) -> Result<Connected<SshClient>> {
+        let Prepared { ssh_config, save, saved_password } = self.prepare(target, options)?;
+        print_connecting(&ssh_config);
+        let (client, save) = match SshClient::connect(ssh_config.clone()) {
+            Ok(client) => (client, save),
+            Err(e) => {
+                let retry = self.ask_new_password(e, saved_password, ssh_config)?;
+                let client = SshClient::connect(retry.ssh_config)?;
+                (client, self.offer_update(retry.connection, retry.password)?)
+            }
+        };
+        println!("{} 连接成功!", glyphs::ok().green().bold());
+        Ok(Connected { client, save })
+    }
+
+    /// 使用 russh 连接（原始模式交互式终端）
+    pub async fn connect_russh(mut self, target: &str, options: &ConnectOptions) -> Result<Connected<RusshClient>> {
+        let Prepared { ssh_config, save, saved_password } = self.prepare(target, options)?;
+        print_connecting(&ssh_config);
+        let (client, save) = match connect_russh(ssh_config.clone()).await {
+            Ok(client) => (client, save),
+            Err(e) => {
+                let retry = self.ask_new_password(e, saved_password, ssh_config)?;
+                let client = connect_russh(retry.ssh_config).await?;
+                (client, self.offer_update(retry.connection, retry.password)?)
+            }
+        };
+        println!("{} 连接成功!", glyphs::ok().green().bold());
+        Ok(Connected { client, save })
+    }
+
+    /// 保存的密码被服务器拒绝时询问新密码（`--batch` 下返回 [`StalePassword`]），其他错误原样返回
+    fn ask_new_password(&mut self, error: anyhow::Error, saved: Option<SavedConnection>, ssh_config: SshConfig) -> Result<StaleRetry> {
+        let Some(connection) = saved.filter(|_| error.downcast_ref::<PasswordRejected>().is_some()) else {
+            return Err(error);
+        };
+        if prompt::is_batch() {
+            return Err(StalePassword { connection: connection.name }.into());
+        }
+        println!("{} 服务器拒绝了连接 '{}' 保存的密码（密码可能已更改），请输入新密码", glyphs::warn().yellow(), connection.name);
+        credentials::forget(&Credential::password(&connection.username, &connection.host));
+        let password = credentials::password(self.prompter, &connection.username, &connection.host)?;
+        Ok(StaleRetry {
+            ssh_config: SshConfig { auth: AuthMethod::Password(password.clone()), ..ssh_config },
+            connection,
+            password: Zeroizing::new(password),
+        })
+    }
+
+    /// 新密码连接成功后询问是否更新保存的密码，同意时随连接返回，由调用方加密保存
+    fn offer_update(&mut self, connection: SavedConnection, password: Zeroizing<String>) -> Result<Option<PendingSave>> {
+        if !self.prompter.confirm(&format!("用新密码更新连接 '{}' 保存的密码?", connection.name))? {
+            return Ok(None);
+        }
+        Ok(Some(PendingSave { connection, password: Some(password) }))
+    }
+
+    fn prepare_saved(&mut self, saved: &SavedConnection, options: &ConnectOptions) -> Result<Prepared> {
+        println!("{} 使用保存的连接: {}", glyphs::arrow().cyan(), saved.name.bold());
+        if let Some(ref name) = options.save_as {
+            println!("{} '{}' 已是保存的连接，忽略 --save-as {}", glyphs::warn().yellow(), saved.name, name);
+        }
+
+        let plan = resolve_auth(options.identity_file.as_deref(), Some(saved));
+        log::debug!("认证来源: {}", plan.source());
+        let mut typed_password = None;
+        let mut saved_password = None;
+        let ssh_config = match plan {
+            AuthPlan::CliIdentity(key_path) => SshConfig {
+                host: saved.host.clone(),
+                port: saved.port,
+                username: saved.username.clone(),
+                auth: key_auth(self.prompter, &key_path, None)?,
+            },
+            AuthPlan::SavedSecret => match credential_helper::for_connection(saved) {
+                // 凭据助手优先于保存的密码，不需要主密码
+                Some(secret) => helper_ssh_config(saved, &secret)?,
+                None => {
+                    println!("{} 检测到已保存的密码", glyphs::ok().green());
+
+                    // 获取主密码（输错时重试，多次失败后冷却）
+                    let encrypted = saved.encrypted_secret().context("未保存密码")?;
+                    let purpose = format!("解密 {} 的已保存密码", saved.name);
+                    let crypto = CryptoManager::unlock(self.prompter, encrypted, self.config.unlock_cooldown(), &purpose)?;
+                    // 解锁时已确认能解密，被服务器拒绝说明保存的密码已失效
+                    if saved.auth_type == "password" {
+                        saved_password = Some(saved.clone());
+                    }
+                    saved.to_ssh_config_or_prompt(&crypto, self.prompter)?
+                }
+            },
+            AuthPlan::SavedKey | AuthPlan::Agent | AuthPlan::Prompt => {
+                let password = if saved.auth_type == "password" {
+                    let password = credentials::password(self.prompter, &saved.username, &saved.host)?;
+                    typed_password = Some(Zeroizing::new(password.clone()));
+                    Some(password)
+                } else {
+                    None
+                };
+                let passphrase = match saved.private_key_path.as_deref() {
+                    Some(key_path) if saved.auth_type == "publickey" => {
+                        key_passphrase(self.prompter, &paths::resolve_key_path(key_path)?)?
+                    }
+                    _ => None,
+                };
+                saved.to_ssh_config(password, passphrase)?
+            }
+        };
+
+        let save = match typed_password {
+            Some(password) if options.save_password => Some(PendingSave {
+                connection: saved.clone(),
+                password: Some(password),
+            }),
+            _ => None,
+        };
+        Ok(Prepared { ssh_config, save, saved_password })
+    }
+
+    fn prepare_target(&mut self, target: &str, options: &ConnectOptions) -> Result<Prepared> {
+        let ResolvedTarget { username, host, port, identity_file } =
+            resolve_target(self.config, target, options.port, options.identity_file.clone())?;
+
+        let (auth, typed_password) = match identity_file {
+            Some(ref key_path) => (key_auth(self.prompter, key_path, None)?, None),
+            None => {
+                let password = credentials::password(self.prompter, &username, &host)?;
+                (AuthMethod::Password(password.clone()), Some(Zeroizing::new(password)))
+            }
+        };
+
+        let save = if options.save_password || options.save_as.is_some() {
+            let name = options.save_as.clone().unwrap_or_else(|| format!("{}@{}", username, host));
+            let connection = match identity_file {
+                Some(key_path) => SavedConnection::new_publickey(name, host.clone(), port, username.clone(), key_path, None),
+                None => SavedConnection::new_password(name, host.clone(), port, username.clone()),
+            };
+            Some(PendingSave {
+                connection,
+                password: typed_password.filter(|_| options.save_password),
+            })
+        } else {
+            None
+        };
+
+        Ok(Prepared {
+            ssh_config: SshConfig { host, port, username, auth },
+            save,
+            saved_password: None,
+        })
+    }
+}
+
+/// 使用 russh 建立连接，连接期间按 Ctrl+C 放弃
+async fn connect_russh(ssh_config: SshConfig) -> Result<RusshClient> {
+    let SshConfig { host, port, username, auth } = ssh_config;
+    let cancel = CancellationToken::new();
+    let mut client = RusshClient::new(ssh_russh::SshConfig::new(host, port, username, auth.into()))
+        .with_cancellation(cancel.clone());
+    // 只在连接期间接管 Ctrl+C，不留下仍在进行的连接尝试。tokio 安装的处理器会一直保留，
+    // 返回时恢复原来的处理方式，之后的保存密码等提示中 Ctrl+C 仍能结束进程
+    let _sigint = SigintScope::save();
+    let result = {
+        let connect = client.connect();
+        tokio::pin!(connect);
+        tokio::select! {
+            result = &mut connect => result,
+            Ok(()) = tokio::signal::ctrl_c() => {
+                cancel.cancel();
+                connect.await
+            }
+        }
+    };
+    result?;
+    Ok(client)
+}
+
+fn print_connecting(config: &SshConfig) {
+    println!("{} 正在连接到 {}@{}:{}...", glyphs::arrow().cyan(), config.username, config.host, config.port);
+}
+
+/// 使用指定的私钥认证，私钥需要密码时询问
+pub fn key_auth(prompter: &mut dyn Prompter, key_path: &str, public_key: Option<&str>) -> Result<AuthMethod> {
+    let private_key = paths::resolve_key_path(key_path)?;
+    let passphrase = key_passphrase(prompter, &private_key)?;
+    Ok(AuthMethod::PublicKey {
+        public_key: public_key.map(paths::expand),
+        private_key,
+        passphrase,
+    })
+}
+
+/// 私钥密码：私钥无需密码即可读取时不询问
+fn key_passphrase(prompter: &mut dyn Prompter, private_key: &str) -> Result<Option<String>> {
+    match russh_keys::load_secret_key(private_key, None) {
+        Ok(_) => Ok(None),
+        Err(_) => credentials::passphrase(prompter, private_key),
+    }
+}
+
+/// 用凭据助手输出的密码或私钥密码认证，并放入缓存供跳板机、重连等复用
+fn helper_ssh_config(saved: &SavedConnection, secret: &str) -> Result<SshConfig> {
+    if saved.auth_type == "publickey" {
+        println!("{} 使用凭据助手提供的私钥密码", glyphs::ok().green());
+        let key_path = saved.private_key_path.as_deref().context("公钥认证需要提供私钥路径")?;
+        credentials::remember(Credential::Passphrase(paths::resolve_key_path(key_path)?), secret);
+        saved.to_ssh_config(None, Some(secret.to_string()))
+    } else {
+        println!("{} 使用凭据助手提供的密码", glyphs::ok().green());
+        credentials::remember(Credential::password(&saved.username, &saved.host), secret);
+        saved.to_ssh_config(Some(secret.to_string()), None)
+    }
+}
+
+/// 解析后的目标主机
+pub struct ResolvedTarget {
+    pub username: String,
+    pub host: String,
+    pub port: u16,
+    pub identity_file: Option<String>,
+}
+
+/// 解析不在保存的连接中的目标
+///
+/// 开启 `use_openssh_config` 时按 ~/.ssh/config 中的 Host 配置补全主机名、用户、端口和私钥，
+/// 命令行参数优先于 ssh_config 中的值。
+pub fn resolve_target(config: &AppConfig, target: &str, port: Option<u16>, identity_file: Option<String>) -> Result<ResolvedTarget> {
+    let (username, alias) = match target.split_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host),
+        None => (None, target),
+    };
+
+    let use_openssh_config = config.use_openssh_config;
+    let settings = if use_openssh_config {
+        OpenSshConfig::load()?.resolve(alias)
+    } else {
+        HostSettings::default()
+    };
+
+    let username = match username.or(settings.user) {
+        Some(user) => user,
+        // 与 OpenSSH 一致，别名没有指定 User 时使用本地用户名
+        None if use_openssh_config => std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .context("无法确定用户名，请使用 'user@host' 格式")?,
+        None => anyhow::bail!("无效的目标格式。请使用 'user@host' 或保存的连接名称"),
+    };
+
+    Ok(ResolvedTarget {
+        username,
+        host: settings.hostname.unwrap_or_else(|| alias.to_string()),
+        port: port.or(settings.port).unwrap_or(22),
+        identity_file: identity_file
+            .or(settings.identity_file)
+            .map(|path| paths::resolve_key_path(&path))
+            .transpose()?,
+    })
+}
+
+/// 命令行操作（sftp、exec 等）使用的认证方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetAuth {
+    /// 私钥：命令行 `-i`、ssh_config 的 IdentityFile 或连接保存的私钥
+    Key { private_key: String, public_key: Option<String> },
+    /// 询问登录密码（命令行操作不解密保存的密码）
+    Password,
+    Agent,
+}
+
+/// 命令行操作的目标主机和认证方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandTarget {
+    pub username: String,
+    pub host: String,
+    pub port: u16,
+    pub auth: TargetAuth,
+    /// 认证来源的说明，用于调试日志
+    pub source: &'static str,
+}
+
+/// 解析命令行操作的目标，不询问凭据、不修改配置
+///
+/// 与 `connect` 的优先级相同：命令行 `-i` 优先于连接保存的认证方式，只对本次操作生效。
+pub fn resolve_command_target(
+    config: &AppConfig,
+    target: &str,
+    port: Option<u16>,
+    identity_file: Option<String>,
+) -> Result<CommandTarget> {
+    if let Some(saved) = config.get_connection(target) {
+        let plan = resolve_auth(identity_file.as_deref(), Some(saved));
+        let auth = match plan {
+            AuthPlan::CliIdentity(ref key_path) => TargetAuth::Key { private_key: key_path.clone(), public_key: None },
+            _ => match saved.auth_type.as_str() {
+                "publickey" => TargetAuth::Key {
+                    private_key: saved.private_key_path.clone().context("公钥认证需要提供私钥路径")?,
+                    public_key: saved.public_key_path.clone(),
+                },
+                "agent" => TargetAuth::Agent,
+                _ => TargetAuth::Password,
+            },
+        };
+        return Ok(CommandTarget {
+            username: saved.username.clone(),
+            host: saved.host.clone(),
+            port: saved.port,
+            auth,
+            source: plan.source(),
+        });
+    }
+
+    let from_cli = identity_file.is_some();
+    let resolved = resolve_target(config, target, port, identity_file)?;
+    let (auth, source) = match resolved.identity_file {
+        Some(private_key) if from_cli => (TargetAuth::Key { private_key, public_key: None }, "命令行私钥"),
+        Some(private_key) => (TargetAuth::Key { private_key, public_key: None }, "ssh_config 的 IdentityFile"),
+        None => (TargetAuth::Password, AuthPlan::Prompt.source()),
+    };
+    Ok(CommandTarget {
+        username: resolved.username,
+        host: resolved.host,
+        port: resolved.port,
+        auth,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::Scripted;
+    use crate::test_server::{TestServer, TEST_PASSWORD, TEST_USER};
+
+    #[test]
+    fn test_connect_new_target() {
+        let server = TestServer::start();
+        let config = AppConfig::default();
+        let target = format!("{}@127.0.0.1", TEST_USER);
+
+        // 不保存时没有需要写入的内容
+        let options = ConnectOptions { port: Some(server.port), ..Default::default() };
+        let mut prompter = Scripted::new(&[TEST_PASSWORD]);
+        let connected = ConnectPipeline::new(&config, &mut prompter).connect(&target, &options).unwrap();
+        assert!(connected.save.is_none());
+
+        // 只有 --save-as 时保存连接但不保存密码
+        let options = ConnectOptions { save_as: Some("lab".to_string()), ..options };
+        let mut prompter = Scripted::new(&[TEST_PASSWORD]);
+        let save = ConnectPipeline::new(&config, &mut prompter).connect(&target, &options).unwrap().save.unwrap();
+        assert_eq!(save.connection.name, "lab");
+        assert_eq!((save.connection.host.as_str(), save.connection.port), ("127.0.0.1", server.port));
+        assert!(save.password.is_none());
+
+        // --save-password 时一并保存输入的密码，名称默认为 user@host
+        let options = ConnectOptions { save_as: None, save_password: true, ..options };
+        let mut prompter = Scripted::new(&[TEST_PASSWORD]);
+        let save = ConnectPipeline::new(&config, &mut prompter).connect(&target, &options).unwrap().save.unwrap();
+        assert_eq!(save.connection.name, target);
+        assert_eq!(save.password.as_deref().map(String::as_str), Some(TEST_PASSWORD));
+
+        // 连接失败时不返回需要保存的内容
+        let options = ConnectOptions { port: Some(1), ..options };
+        let mut prompter = Scripted::new(&[TEST_PASSWORD]);
+        assert!(ConnectPipeline::new(&config, &mut prompter).connect(&target, &options).is_err());
+    }
+
+    #[test]
+    fn test_stale_saved_password() {
+        let server = TestServer::start();
+        let saved = SavedConnection::new_password("rotated".into(), "127.0.0.1".into(), server.port, TEST_USER.into());
+        let ssh_config = saved.to_ssh_config(Some("old-password".to_string()), None).unwrap();
+        let rejected = SshClient::connect(ssh_config.clone()).unwrap_err();
+        assert!(rejected.downcast_ref::<PasswordRejected>().is_some());
+
+        // 询问新密码重试，成功后同意更新保存的密码
+        let config = AppConfig::default();
+        let mut prompter = Scripted::new(&[TEST_PASSWORD, "y"]);
+        let mut pipeline = ConnectPipeline::new(&config, &mut prompter);
+        let retry = pipeline.ask_new_password(rejected, Some(saved.clone()), ssh_config.clone()).unwrap();
+        SshClient::connect(retry.ssh_config).unwrap();
+        let save = pipeline.offer_update(retry.connection, retry.password).unwrap().unwrap();
+        assert_eq!(save.connection.name, "rotated");
+        assert_eq!(save.password.as_deref().map(String::as_str), Some(TEST_PASSWORD));
+
+        // 不是密码被拒绝（例如端口不通），或没有使用保存的密码时原样返回错误，不询问
+        let refused = SshClient::connect(SshConfig { port: 1, ..ssh_config.clone() }).unwrap_err();
+        assert!(pipeline.ask_new_password(refused, Some(saved), ssh_config.clone()).is_err());
+        let rejected = SshClient::connect(ssh_config.clone()).unwrap_err();
+        assert!(pipeline.ask_new_password(rejected, None, ssh_config).is_err());
+    }
+
+    #[test]
+    fn test_save_password_for_saved_connection() {
+        let mut conn = SavedConnection::new_password("pipeline".into(), "pipeline.example".into(), 2222, "deploy".into());
+        conn.jump_host = Some("bastion".to_string());
+        let mut config = AppConfig::default();
+        config.add_connection(conn);
+
+        let options = ConnectOptions { save_password: true, save_as: Some("ignored".to_string()), ..Default::default() };
+        let mut prompter = Scripted::new(&["typed-pw"]);
+        let prepared = ConnectPipeline::new(&config, &mut prompter).prepare("pipeline", &options).unwrap();
+        assert!(matches!(prepared.ssh_config.auth, AuthMethod::Password(ref pw) if pw == "typed-pw"));
+
+        // 保存密码时保留连接的其他设置，--save-as 不会另存一份
+        let save = prepared.save.unwrap();
+        let mut prompter = Scripted::new(&["master", "master"]);
+        save.apply(&mut config, &mut prompter, true).unwrap();
+        assert!(config.get_connection("ignored").is_none());
+        let saved = config.get_connection("pipeline").unwrap();
+        assert_eq!(saved.jump_host.as_deref(), Some("bastion"));
+        let encrypted = saved.encrypted_password.as_deref().unwrap();
+        assert_eq!(CryptoManager::new("master").unwrap().decrypt(encrypted).unwrap(), "typed-pw");
+    }
+
+    #[test]
+    fn test_command_target_identity_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli_key = dir.path().join("newkey.pem").display().to_string();
+        std::fs::write(&cli_key, "").unwrap();
+        let mut config = AppConfig::default();
+        config.add_connection(SavedConnection::new_password_with_encrypted("prod".into(), "prod.example".into(), 2222, "deploy".into(), "x".into()));
+        config.add_connection(SavedConnection::new_publickey("web".into(), "web.example".into(), 22, "ops".into(), "~/.ssh/web".into(), Some("~/.ssh/web.pub".into())));
+        let key = |path: &str| TargetAuth::Key { private_key: path.to_string(), public_key: None };
+
+        // 保存了密码的连接 + -i：使用命令行私钥，主机、端口、用户名仍来自连接
+        let target = resolve_command_target(&config, "prod", None, Some(cli_key.clone())).unwrap();
+        assert_eq!((target.host.as_str(), target.port, target.username.as_str()), ("prod.example", 2222, "deploy"));
+        assert_eq!(target.auth, key(&cli_key));
+        assert_eq!(target.source, "命令行私钥");
+        assert_eq!(resolve_command_target(&config, "prod", None, None).unwrap().auth, TargetAuth::Password);
+
+        // 保存了另一个私钥的连接 + -i：命令行私钥优先
+        assert_eq!(resolve_command_target(&config, "web", None, Some(cli_key.clone())).unwrap().auth, key(&cli_key));
+        assert_eq!(
+            resolve_command_target(&config, "web", None, None).unwrap().auth,
+            TargetAuth::Key { private_key: "~/.ssh/web".to_string(), public_key: Some("~/.ssh/web.pub".to_string()) }
+        );
+
+        // 未保存的目标 + -i
+        let target = resolve_command_target(&config, "root@10.0.0.9", Some(2200), Some(cli_key.clone())).unwrap();
+        assert_eq!((target.host.as_str(), target.port, target.username.as_str()), ("10.0.0.9", 2200, "root"));
+        assert_eq!(target.auth, key(&cli_key));
+        assert_eq!(resolve_command_target(&config, "root@10.0.0.9", None, None).unwrap().auth, TargetAuth::Password);
+    }
+
+    /// 连接结束后不再接管 Ctrl+C，否则之后的提示中按 Ctrl+C 无法结束进程
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_restores_sigint() {
+        let sigint = || {
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigaction(libc::SIGINT, std::ptr::null(), &mut action) };
+            action.sa_sigaction
+        };
+        let before = sigint();
+        let server = TestServer::start();
+        let mut client = connect_russh(server.password_config()).await.unwrap();
+        assert_eq!(sigint(), before);
+        client.disconnect().await.unwrap();
+    }
+}
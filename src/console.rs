@@ -0,0 +1,252 @@
+//! 本地控制台的平台差异
+//!
+//! Unix 终端在原始模式下直接从 stdin 读到按键字节；Windows 控制台（conhost）的方向键、
+//! 退格等特殊键只以按键事件的形式出现，需要通过 crossterm 读取事件再转换成远程 PTY
+//! 期望的字节序列。
+//!
+//! 进入原始模式统一通过 [`RawModeGuard`]，提前返回、出错或 panic 时都会恢复终端。
+
+use anyhow::{Context, Result};
+use crossterm::cursor::Show;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 是否处于原始模式（由 [`RawModeGuard`] 设置），panic 钩子据此决定是否恢复终端
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 是否处于备用屏幕
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// 输入来自按键事件而不是原始字节流，不会混入终端的 CPR 应答，无需过滤
+pub const KEY_EVENTS: bool = cfg!(windows);
+
+/// 为控制台输出启用虚拟终端处理，必须在写入任何转义序列之前调用
+///
+/// Windows 10 的 conhost 默认会把转义序列原样显示；其他平台无需处理。返回控制台是否支持转义序列，
+/// 不支持时（旧版控制台）输出改用 ASCII 符号且不着色。
+pub fn enable_virtual_terminal() -> bool {
+    #[cfg(windows)]
+    {
+        if !crossterm::ansi_support::supports_ansi() {
+            log::warn!("无法为控制台启用虚拟终端处理，颜色和远程 shell 的控制序列可能显示为原始字符");
+            return false;
+        }
+    }
+    true
+}
+
+/// 原始模式守卫：创建时启用原始模式，释放时恢复终端（退出原始模式和备用屏幕、显示光标）
+pub struct RawModeGuard {
+    restore: fn(),
+}
+
+impl RawModeGuard {
+    pub fn enable() -> Result<Self> {
+        enable_raw_mode().context("无法启用原始模式")?;
+        RAW_MODE.store(true, Ordering::SeqCst);
+        Ok(Self { restore: restore_terminal })
+    }
+
+    /// 进入备用屏幕，释放守卫时退出
+    #[allow(dead_code)]
+    pub fn enter_alternate_screen(&self) -> Result<()> {
+        crossterm::execute!(std::io::stdout(), EnterAlternateScreen).context("无法进入备用屏幕")?;
+        ALTERNATE_SCREEN.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        (self.restore)();
+    }
+}
+
+/// 恢复终端；终端不处于原始模式或备用屏幕时不做任何事，可以重复调用
+pub fn restore_terminal() {
+    if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) {
+        let _ = crossterm::execute!(std::io::stdout(), LeaveAlternateScreen);
+    }
+    if RAW_MODE.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+        let _ = crossterm::execute!(std::io::stdout(), Show);
+    }
+}
+
+/// 安装 panic 钩子：打印 panic 信息之前先恢复终端，否则原始模式下的输出无法阅读
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+}
+
+/// 交互式会话的本地输入，Unix 上为 stdin，Windows 上为转换后的按键事件
+pub fn input() -> Box<dyn Read + Send> {
+    if KEY_EVENTS {
+        Box::new(KeyReader::default())
+    } else {
+        Box::new(std::io::stdin())
+    }
+}
+
+/// 把 crossterm 按键事件转换为字节流（需要已启用原始模式）
+#[derive(Default)]
+struct KeyReader {
+    pending: Vec<u8>,
+}
+
+impl Read for KeyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use crossterm::event::{self, Event};
+
+        while self.pending.is_empty() {
+            match event::read()? {
+                Event::Key(key) => self.pending = key_to_bytes(&key).unwrap_or_default(),
+                Event::Paste(text) => self.pending = text.into_bytes(),
+                _ => {}
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+/// 将按键事件转换为发送给远程 PTY 的字节（xterm 序列）
+///
+/// 松开按键的事件（Windows 上会与按下事件成对出现）返回 None。
+pub fn key_to_bytes(key_event: &KeyEvent) -> Option<Vec<u8>> {
+    if key_event.kind == KeyEventKind::Release {
+        return None;
+    }
+    match key_event.code {
+        // 回车统一发送 \r，由远程 PTY 转换（与 Unix 终端原始模式一致）
+        KeyCode::Enter | KeyCode::Char('\r') | KeyCode::Char('\n') => Some(vec![b'\r']),
+        KeyCode::Char(c) => {
+            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+                // Ctrl+字母组合
+                match c {
+                    'a'..='z' => Some(vec![(c as u8) - b'a' + 1]),
+                    'A'..='Z' => Some(vec![(c as u8) - b'A' + 1]),
+                    '@' | ' ' => Some(vec![0x00]), // Ctrl+@ / Ctrl+Space
+                    '[' => Some(vec![0x1b]), // Ctrl+[
+                    '\\' => Some(vec![0x1c]), // Ctrl+\
+                    ']' => Some(vec![0x1d]), // Ctrl+]
+                    '^' => Some(vec![0x1e]), // Ctrl+^
+                    '_' => Some(vec![0x1f]), // Ctrl+_
+                    '?' => Some(vec![0x7f]), // Ctrl+?
+                    _ => None,
+                }
+            } else if key_event.modifiers.contains(KeyModifiers::ALT) {
+                // Alt+字符组合
+                let mut bytes = vec![0x1b];
+                bytes.extend(c.to_string().into_bytes());
+                Some(bytes)
+            } else {
+                // 普通字符
+                Some(c.to_string().into_bytes())
+            }
+        }
+        KeyCode::Backspace => Some(vec![0x7f]),
+        KeyCode::Tab => Some(vec![b'\t']),
+        KeyCode::BackTab => Some(vec![0x1b, b'[', b'Z']),
+        KeyCode::Esc => Some(vec![0x1b]),
+        KeyCode::Up => Some(vec![0x1b, b'[', b'A']),
+        KeyCode::Down => Some(vec![0x1b, b'[', b'B']),
+        KeyCode::Right => Some(vec![0x1b, b'[', b'C']),
+        KeyCode::Left => Some(vec![0x1b, b'[', b'D']),
+        KeyCode::Home => Some(vec![0x1b, b'[', b'H']),
+        KeyCode::End => Some(vec![0x1b, b'[', b'F']),
+        KeyCode::PageUp => Some(vec![0x1b, b'[', b'5', b'~']),
+        KeyCode::PageDown => Some(vec![0x1b, b'[', b'6', b'~']),
+        KeyCode::Delete => Some(vec![0x1b, b'[', b'3', b'~']),
+        KeyCode::Insert => Some(vec![0x1b, b'[', b'2', b'~']),
+        KeyCode::F(n) => {
+            // F1-F12 功能键
+            match n {
+                1 => Some(vec![0x1b, b'O', b'P']),
+                2 => Some(vec![0x1b, b'O', b'Q']),
+                3 => Some(vec![0x1b, b'O', b'R']),
+                4 => Some(vec![0x1b, b'O', b'S']),
+                5 => Some(vec![0x1b, b'[', b'1', b'5', b'~']),
+                6 => Some(vec![0x1b, b'[', b'1', b'7', b'~']),
+                7 => Some(vec![0x1b, b'[', b'1', b'8', b'~']),
+                8 => Some(vec![0x1b, b'[', b'1', b'9', b'~']),
+                9 => Some(vec![0x1b, b'[', b'2', b'0', b'~']),
+                10 => Some(vec![0x1b, b'[', b'2', b'1', b'~']),
+                11 => Some(vec![0x1b, b'[', b'2', b'3', b'~']),
+                12 => Some(vec![0x1b, b'[', b'2', b'4', b'~']),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEventState;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_guard_restores_on_panic() {
+        use std::sync::atomic::AtomicUsize;
+
+        static RESTORED: AtomicUsize = AtomicUsize::new(0);
+        fn count() {
+            RESTORED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = RawModeGuard { restore: count };
+            panic!("受保护的代码中 panic");
+        });
+        assert!(result.is_err());
+        assert_eq!(RESTORED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_special_keys() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(key_to_bytes(&press(KeyCode::Enter, none)), Some(b"\r".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::Char('\r'), none)), Some(b"\r".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::Backspace, none)), Some(vec![0x7f]));
+        assert_eq!(key_to_bytes(&press(KeyCode::Up, none)), Some(b"\x1b[A".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::Left, none)), Some(b"\x1b[D".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::Delete, none)), Some(b"\x1b[3~".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::BackTab, KeyModifiers::SHIFT)), Some(b"\x1b[Z".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::F(5), none)), Some(b"\x1b[15~".to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::F(13), none)), None);
+    }
+
+    #[test]
+    fn test_characters_and_modifiers() {
+        assert_eq!(key_to_bytes(&press(KeyCode::Char('中'), KeyModifiers::NONE)), Some("中".as_bytes().to_vec()));
+        assert_eq!(key_to_bytes(&press(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(vec![0x03]));
+        assert_eq!(key_to_bytes(&press(KeyCode::Char('D'), KeyModifiers::CONTROL)), Some(vec![0x04]));
+        assert_eq!(key_to_bytes(&press(KeyCode::Char(' '), KeyModifiers::CONTROL)), Some(vec![0x00]));
+        assert_eq!(key_to_bytes(&press(KeyCode::Char('b'), KeyModifiers::ALT)), Some(b"\x1bb".to_vec()));
+    }
+
+    #[test]
+    fn test_release_events_ignored() {
+        let release = KeyEvent::new_with_kind_and_state(
+            KeyCode::Char('a'),
+            KeyModifiers::NONE,
+            KeyEventKind::Release,
+            KeyEventState::NONE,
+        );
+        assert_eq!(key_to_bytes(&release), None);
+        let repeat = KeyEvent { kind: KeyEventKind::Repeat, ..release };
+        assert_eq!(key_to_bytes(&repeat), Some(b"a".to_vec()));
+    }
+}
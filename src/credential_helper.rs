@@ -0,0 +1,267 @@
+//! 凭据助手：执行外部命令获取密码或私钥密码
+//!
+//! 配置 `credential_helper`（全局默认值或连接单独设置）后，需要密码或私钥密码时先执行该命令，以标准输出的
+//! 第一行作为密文，优先于保存的加密凭据和交互输入，适合从 vault、pass 等密码库读取。命令中可使用
+//! `{name}`、`{host}`、`{port}`、`{username}`，同样的值也通过 `RUST_SSH_SFTP_*` 环境变量传给命令。
+//! 超时、非零退出码或输出为空时给出警告并改用下一种方式。命令的输出不写入日志。
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use std::io::Read;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::config::{AppConfig, SavedConnection};
+use crate::hooks;
+use crate::paths;
+
+/// 等待助手命令结束的最长时间
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 检查助手命令是否结束的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 配置了凭据助手时保存的配置，未配置时为空
+static CONFIG: OnceLock<AppConfig> = OnceLock::new();
+
+/// 按配置开启凭据助手，在处理命令前调用一次
+pub fn init(config: &AppConfig) {
+    let configured = config.credential_helper.is_some()
+        || config.connections.values().any(|c| c.credential_helper.is_some());
+    if configured {
+        let _ = CONFIG.set(config.clone());
+    }
+}
+
+/// 请求的密文种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretKind {
+    Password,
+    Passphrase,
+}
+
+impl SecretKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SecretKind::Password => "password",
+            SecretKind::Passphrase => "passphrase",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SecretKind::Password => "密码",
+            SecretKind::Passphrase => "私钥密码",
+        }
+    }
+}
+
+/// 传给助手命令的信息，未知的值为空
+#[derive(Debug, Clone)]
+pub struct HelperRequest {
+    pub kind: SecretKind,
+    pub name: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub key_path: Option<String>,
+}
+
+impl HelperRequest {
+    /// 保存的连接需要的密文：公钥认证为私钥密码，其他为登录密码
+    pub fn for_connection(connection: &SavedConnection) -> Self {
+        let kind = if connection.auth_type == "publickey" { SecretKind::Passphrase } else { SecretKind::Password };
+        Self {
+            kind,
+            name: Some(connection.name.clone()),
+            host: Some(connection.host.clone()),
+            port: Some(connection.port),
+            username: Some(connection.username.clone()),
+            key_path: connection.private_key_path.as_deref().filter(|_| kind == SecretKind::Passphrase).map(paths::expand),
+        }
+    }
+
+    /// 替换 `{name}`、`{host}`、`{port}`、`{username}`
+    pub fn render(&self, command: &str) -> String {
+        command
+            .replace("{name}", self.name.as_deref().unwrap_or_default())
+            .replace("{host}", self.host.as_deref().unwrap_or_default())
+            .replace("{port}", &self.port.map(|p| p.to_string()).unwrap_or_default())
+            .replace("{username}", self.username.as_deref().unwrap_or_default())
+    }
+
+    fn envs(&self) -> Vec<(&'static str, String)> {
+        let mut envs = vec![("RUST_SSH_SFTP_SECRET", self.kind.as_str().to_string())];
+        let optional = [
+            ("RUST_SSH_SFTP_CONNECTION", self.name.clone()),
+            ("RUST_SSH_SFTP_HOST", self.host.clone()),
+            ("RUST_SSH_SFTP_PORT", self.port.map(|p| p.to_string())),
+            ("RUST_SSH_SFTP_USERNAME", self.username.clone()),
+            ("RUST_SSH_SFTP_KEY", self.key_path.clone()),
+        ];
+        envs.extend(optional.into_iter().filter_map(|(name, value)| value.map(|v| (name, v))));
+        envs
+    }
+}
+
+/// 执行助手命令，返回输出的第一行；超时、失败或输出为空时返回错误
+pub fn run(command: &str, request: &HelperRequest, timeout: Duration) -> Result<Zeroizing<String>> {
+    let rendered = request.render(command);
+    debug!("执行凭据助手: {}", rendered);
+    let mut child = hooks::shell_command(&rendered)
+        .envs(request.envs())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context(format!("无法执行凭据助手: {}", rendered))?;
+
+    // 在单独的线程中读取，输出较多时助手不会因管道写满而阻塞
+    let mut stdout = child.stdout.take().context("无法读取凭据助手的输出")?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Zeroizing::new(Vec::new());
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().context("等待凭据助手失败")? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("凭据助手超过 {} 秒未结束，已终止", timeout.as_secs());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    let output = reader
+        .join()
+        .map_err(|_| anyhow!("读取凭据助手的输出失败"))?
+        .context("读取凭据助手的输出失败")?;
+
+    if !status.success() {
+        let code = status.code().map_or_else(|| "被信号终止".to_string(), |code| code.to_string());
+        anyhow::bail!("凭据助手执行失败（退出码 {}）", code);
+    }
+    let text = std::str::from_utf8(&output).context("凭据助手的输出不是有效的 UTF-8")?;
+    let secret = Zeroizing::new(text.lines().next().unwrap_or_default().to_string());
+    if secret.is_empty() {
+        anyhow::bail!("凭据助手没有输出{}", request.kind.label());
+    }
+    Ok(secret)
+}
+
+/// 执行助手命令，失败时给出警告并返回 None，由调用方改用下一种方式
+fn fetch(command: &str, request: &HelperRequest) -> Option<Zeroizing<String>> {
+    match run(command, request, TIMEOUT) {
+        Ok(secret) => Some(secret),
+        Err(e) => {
+            warn!("{:#}，改用其他方式获取{}", e, request.kind.label());
+            None
+        }
+    }
+}
+
+/// 连接实际使用的助手命令
+fn command_for<'a>(config: &'a AppConfig, connection: Option<&'a SavedConnection>) -> Option<&'a str> {
+    connection
+        .and_then(|c| c.credential_helper.as_deref())
+        .or(config.credential_helper.as_deref())
+}
+
+/// 保存的连接需要的密码或私钥密码
+pub fn for_connection(connection: &SavedConnection) -> Option<Zeroizing<String>> {
+    let config = CONFIG.get()?;
+    let command = command_for(config, Some(connection))?;
+    fetch(command, &HelperRequest::for_connection(connection))
+}
+
+/// `username@host` 的登录密码；有用户名和主机相同的保存连接时使用该连接的设置
+pub fn for_login(username: &str, host: &str) -> Option<Zeroizing<String>> {
+    let config = CONFIG.get()?;
+    let connection = config
+        .connections
+        .values()
+        .find(|c| c.username == username && c.host.eq_ignore_ascii_case(host));
+    let command = command_for(config, connection)?;
+    let request = match connection {
+        Some(connection) => HelperRequest { kind: SecretKind::Password, key_path: None, ..HelperRequest::for_connection(connection) },
+        None => HelperRequest {
+            kind: SecretKind::Password,
+            name: None,
+            host: Some(host.to_string()),
+            port: None,
+            username: Some(username.to_string()),
+            key_path: None,
+        },
+    };
+    fetch(command, &request)
+}
+
+/// 私钥 `key_path` 的密码；有使用该私钥的保存连接时使用该连接的设置
+pub fn for_key(key_path: &str) -> Option<Zeroizing<String>> {
+    let config = CONFIG.get()?;
+    let connection = config
+        .connections
+        .values()
+        .find(|c| c.private_key_path.as_deref().is_some_and(|path| paths::expand(path) == key_path));
+    let command = command_for(config, connection)?;
+    let request = match connection {
+        Some(connection) => HelperRequest {
+            kind: SecretKind::Passphrase,
+            key_path: Some(key_path.to_string()),
+            ..HelperRequest::for_connection(connection)
+        },
+        None => HelperRequest {
+            kind: SecretKind::Passphrase,
+            name: None,
+            host: None,
+            port: None,
+            username: None,
+            key_path: Some(key_path.to_string()),
+        },
+    };
+    fetch(command, &request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> HelperRequest {
+        HelperRequest::for_connection(&SavedConnection::new_password(
+            "db".to_string(),
+            "db.internal".to_string(),
+            2222,
+            "app".to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            request().render("vault kv get -field=password secret/ssh/{name}/{username}@{host}:{port}"),
+            "vault kv get -field=password secret/ssh/db/app@db.internal:2222"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let secret = run("printf 'hunter2\\nignored'", &request(), TIMEOUT).unwrap();
+        assert_eq!(secret.as_str(), "hunter2");
+
+        let secret = run("printf '%s:%s' \"$RUST_SSH_SFTP_SECRET\" \"$RUST_SSH_SFTP_CONNECTION\"", &request(), TIMEOUT).unwrap();
+        assert_eq!(secret.as_str(), "password:db");
+
+        // 失败、输出为空和超时都返回错误
+        assert!(run("echo secret; exit 3", &request(), TIMEOUT).is_err());
+        assert!(run("true", &request(), TIMEOUT).is_err());
+        let started = Instant::now();
+        assert!(run("sleep 5", &request(), Duration::from_millis(200)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,265 @@
+//! 单次运行内的凭据缓存
+//!
+//! 同一次运行中可能多次需要同一个凭据：连接失败后重试、`--save-password` 时重新询问主密码、
+//! 跳板机与目标主机使用相同账号等。已输入过的密码、私钥密码和主密码缓存在内存中，
+//! 再次需要时直接使用，不再重复询问；进程退出前（或从缓存移除时）清零。
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+use crate::credential_helper;
+use crate::paths;
+use crate::prompt::{self, Prompter};
+
+/// 缓存的凭据种类
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Credential {
+    /// 某个账号的登录密码
+    Password { username: String, host: String },
+    /// 私钥密码，按私钥路径区分（未知路径时为空字符串）
+    Passphrase(String),
+    /// 解密已保存密码的主密码（只缓存校验通过的）
+    MasterPassword,
+    /// 连接的附加密文（sudo 密码等），解密或输入后缓存，同时用于日志脱敏
+    Secret { connection: String, key: String },
+}
+
+impl Credential {
+    pub fn password(username: &str, host: &str) -> Self {
+        Credential::Password { username: username.to_string(), host: host.to_string() }
+    }
+
+    pub fn secret(connection: &str, key: &str) -> Self {
+        Credential::Secret { connection: connection.to_string(), key: key.to_string() }
+    }
+}
+
+/// 凭据缓存，值在移除或缓存销毁时清零
+#[derive(Default)]
+pub struct CredentialCache {
+    entries: BTreeMap<Credential, Zeroizing<String>>,
+}
+
+impl CredentialCache {
+    pub const fn new() -> Self {
+        CredentialCache { entries: BTreeMap::new() }
+    }
+
+    pub fn get(&self, key: &Credential) -> Option<String> {
+        self.entries.get(key).map(|value| value.to_string())
+    }
+
+    pub fn insert(&mut self, key: Credential, value: String) {
+        self.entries.insert(key, Zeroizing::new(value));
+    }
+
+    /// 移除凭据（例如认证失败，说明缓存的值已经不对）
+    pub fn forget(&mut self, key: &Credential) {
+        self.entries.remove(key);
+    }
+
+    /// 优先使用缓存，没有时调用 `prompt` 询问并缓存结果；询问失败时不缓存
+    pub fn get_or_prompt(&mut self, key: Credential, prompt: impl FnOnce() -> Result<String>) -> Result<String> {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let value = prompt()?;
+        self.insert(key, value.clone());
+        Ok(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// 把文本中出现的缓存凭据替换为 `******`
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for value in self.entries.values().filter(|v| !v.is_empty()) {
+            if text.contains(value.as_str()) {
+                text = text.replace(value.as_str(), REDACTED);
+            }
+        }
+        text
+    }
+}
+
+/// 脱敏后的占位符
+pub const REDACTED: &str = "******";
+
+/// 本次运行的缓存
+static CACHE: Mutex<CredentialCache> = Mutex::new(CredentialCache::new());
+
+fn with_cache<T>(f: impl FnOnce(&mut CredentialCache) -> T) -> T {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut cache)
+}
+
+/// 提供登录密码的环境变量（未指定 `--password-stdin`、`--password-file` 时使用）
+pub const PASSWORD_ENV: &str = "RUST_SSH_SFTP_PASSWORD";
+
+/// 非交互提供的登录密码（`--password-stdin`、`--password-file` 或环境变量），需要登录密码时代替询问
+static PRESET: Mutex<Option<Zeroizing<String>>> = Mutex::new(None);
+
+pub fn set_preset_password(password: Zeroizing<String>) {
+    *PRESET.lock().unwrap_or_else(|e| e.into_inner()) = Some(password);
+}
+
+pub fn preset_password() -> Option<Zeroizing<String>> {
+    PRESET.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// 读取非交互提供的登录密码：`--password-stdin` 读标准输入的第一行，`--password-file` 读文件的第一行，
+/// 都未指定时读环境变量 [`PASSWORD_ENV`]；应在读取标准输入的其他操作之前调用
+pub fn load_preset_password(from_stdin: bool, file: Option<&str>) -> Result<()> {
+    let source = if from_stdin {
+        let mut line = Zeroizing::new(String::new());
+        std::io::stdin().read_line(&mut line).context("无法从标准输入读取密码")?;
+        line
+    } else if let Some(path) = file {
+        Zeroizing::new(std::fs::read_to_string(paths::expand(path)).context(format!("无法读取密码文件 {}", path))?)
+    } else {
+        match std::env::var(PASSWORD_ENV) {
+            Ok(value) => Zeroizing::new(value),
+            Err(_) => return Ok(()),
+        }
+    };
+    let password = Zeroizing::new(source.lines().next().unwrap_or_default().to_string());
+    if password.is_empty() {
+        anyhow::bail!("提供的登录密码为空");
+    }
+    set_preset_password(password);
+    Ok(())
+}
+
+/// 登录密码：已输入过则直接使用，其次使用非交互提供的密码、凭据助手的输出，否则询问
+pub fn password(prompter: &mut dyn Prompter, username: &str, host: &str) -> Result<String> {
+    let preset = preset_password();
+    with_cache(|cache| {
+        cache.get_or_prompt(Credential::password(username, host), || {
+            if let Some(password) = preset.or_else(|| credential_helper::for_login(username, host)) {
+                return Ok(password.to_string());
+            }
+            prompter.ask_password(&prompt::labeled(&format!("{}@{}", username, host), "密码"))
+        })
+    })
+}
+
+/// 私钥密码：已输入过则直接使用，其次使用凭据助手的输出，否则询问；直接回车（私钥没有密码）返回 None，
+/// 同样会缓存
+pub fn passphrase(prompter: &mut dyn Prompter, key_path: &str) -> Result<Option<String>> {
+    let passphrase = with_cache(|cache| {
+        cache.get_or_prompt(Credential::Passphrase(key_path.to_string()), || {
+            if let Some(passphrase) = credential_helper::for_key(key_path) {
+                return Ok(passphrase.to_string());
+            }
+            let context = if key_path.is_empty() { "私钥" } else { key_path };
+            prompter.ask_password(&prompt::labeled(context, "私钥密码（如果没有请直接回车）"))
+        })
+    })?;
+    Ok(if passphrase.is_empty() { None } else { Some(passphrase) })
+}
+
+pub fn get(key: &Credential) -> Option<String> {
+    with_cache(|cache| cache.get(key))
+}
+
+pub fn remember(key: Credential, value: &str) {
+    with_cache(|cache| cache.insert(key, value.to_string()));
+}
+
+pub fn forget(key: &Credential) {
+    with_cache(|cache| cache.forget(key));
+}
+
+/// 脱敏日志等文本；缓存正被占用（例如正在询问密码）时返回 None，调用方应丢弃该文本而不是原样保存
+pub fn redact(text: &str) -> Option<String> {
+    let cache = match CACHE.try_lock() {
+        Ok(cache) => cache,
+        Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(std::sync::TryLockError::WouldBlock) => return None,
+    };
+    Some(cache.redact(text))
+}
+
+/// 用于显示和日志的文本：脱敏，缓存正被占用时整体替换为 `******`
+pub fn redacted(text: &str) -> String {
+    redact(text).unwrap_or_else(|| REDACTED.to_string())
+}
+
+/// 清零并清空缓存，在进程退出前调用（静态变量不会被析构）
+pub fn clear() {
+    with_cache(CredentialCache::clear);
+    PRESET.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_before_prompt() {
+        let mut cache = CredentialCache::new();
+        let key = Credential::password("root", "example.com");
+
+        let mut prompts = 0;
+        let mut prompt = || {
+            prompts += 1;
+            Ok("secret".to_string())
+        };
+        assert_eq!(cache.get_or_prompt(key.clone(), &mut prompt).unwrap(), "secret");
+        assert_eq!(cache.get_or_prompt(key.clone(), &mut prompt).unwrap(), "secret");
+        assert_eq!(prompts, 1);
+
+        // 不同账号、主机和种类互不影响
+        assert_eq!(cache.get(&Credential::password("admin", "example.com")), None);
+        assert_eq!(cache.get(&Credential::password("root", "example.org")), None);
+        assert_eq!(cache.get(&Credential::MasterPassword), None);
+
+        // 移除后重新询问
+        cache.forget(&key);
+        assert_eq!(cache.get_or_prompt(key.clone(), || Ok("new".to_string())).unwrap(), "new");
+        assert_eq!(cache.get(&key).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_failed_prompt_not_cached() {
+        let mut cache = CredentialCache::new();
+        let key = Credential::Passphrase("~/.ssh/id_ed25519".to_string());
+        assert!(cache.get_or_prompt(key.clone(), || anyhow::bail!("no tty")).is_err());
+        assert_eq!(cache.get(&key), None);
+
+        // 空的私钥密码也是有效输入，会被缓存
+        assert_eq!(cache.get_or_prompt(key.clone(), || Ok(String::new())).unwrap(), "");
+        assert_eq!(cache.get_or_prompt(key, || anyhow::bail!("不应再询问")).unwrap(), "");
+
+        cache.clear();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_labeled_prompt_asked_once() {
+        use crate::prompt::Scripted;
+
+        let mut prompter = Scripted::new(&["bastion-pw", "key-pw"]);
+        assert_eq!(password(&mut prompter, "deploy", "bastion.labeled.example").unwrap(), "bastion-pw");
+        // 跳板机和目标主机使用同一账号时不再询问
+        assert_eq!(password(&mut prompter, "deploy", "bastion.labeled.example").unwrap(), "bastion-pw");
+        assert_eq!(passphrase(&mut prompter, "/tmp/labeled_id").unwrap().as_deref(), Some("key-pw"));
+        assert_eq!(
+            prompter.asked,
+            ["[deploy@bastion.labeled.example] 密码: ", "[/tmp/labeled_id] 私钥密码（如果没有请直接回车）: "]
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut cache = CredentialCache::new();
+        cache.insert(Credential::password("root", "example.com"), "hunter2".to_string());
+        cache.insert(Credential::Passphrase(String::new()), String::new());
+        assert_eq!(cache.redact("auth with hunter2 failed"), "auth with ****** failed");
+        assert_eq!(cache.redact("nothing here"), "nothing here");
+    }
+}
@@ -8,9 +8,22 @@ use argon2::{
     Argon2,
 };
 use base64::{engine::general_purpose, Engine as _};
+use colored::Colorize;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::credentials::{self, Credential};
+use crate::glyphs;
+use crate::prompt::{self, Prompter};
+
+/// 每轮允许的主密码尝试次数
+const MAX_UNLOCK_ATTEMPTS: u32 = 3;
+
+/// 冷却时间上限
+const MAX_COOLDOWN: Duration = Duration::from_secs(3600);
 
 /// 加密密钥管理器
 pub struct CryptoManager {
@@ -54,7 +67,7 @@ impl CryptoManager {
             // 读取现有盐值
             let salt_str = fs::read_to_string(&salt_path)
                 .context("无法读取盐值文件")?;
-            SaltString::from_b64(&salt_str.trim())
+            SaltString::from_b64(salt_str.trim())
                 .map_err(|e| anyhow::anyhow!("无效的盐值: {}", e))
         } else {
             // 创建新盐值
@@ -108,14 +121,7 @@ impl CryptoManager {
 
     /// 解密字符串
     pub fn decrypt(&self, encrypted: &str) -> Result<String> {
-        // Base64 解码
-        let data = general_purpose::STANDARD
-            .decode(encrypted)
-            .context("Base64 解码失败")?;
-        
-        if data.len() < 12 {
-            anyhow::bail!("加密数据太短");
-        }
+        let data = Self::decode(encrypted)?;
         
         // 分离 nonce 和 ciphertext
         let (nonce_bytes, ciphertext) = data.split_at(12);
@@ -135,32 +141,59 @@ impl CryptoManager {
             .context("解密后的数据不是有效的 UTF-8")
     }
 
+    /// 检查密文格式，不需要主密码；格式错误的密文无论主密码是否正确都无法解密
+    pub fn check_format(encrypted: &str) -> Result<()> {
+        Self::decode(encrypted).map(|_| ())
+    }
+
+    /// Base64 解码，并检查长度至少包含 nonce（12 字节）和认证标签（16 字节）
+    fn decode(encrypted: &str) -> Result<Vec<u8>> {
+        let data = general_purpose::STANDARD
+            .decode(encrypted)
+            .context("Base64 解码失败")?;
+        
+        if data.len() < 12 + 16 {
+            anyhow::bail!("加密数据太短");
+        }
+        Ok(data)
+    }
+
+    /// 能否解密任一密文
+    fn decrypts_any(&self, ciphertexts: &[&str]) -> bool {
+        ciphertexts.iter().any(|encrypted| self.decrypt(encrypted).is_ok())
+    }
+
     /// 获取或创建主密码
     /// 如果是首次使用，会提示用户设置主密码
     /// 如果已有主密码，会提示用户输入
-    pub fn get_master_password(is_first_time: bool) -> Result<String> {
+    ///
+    /// `purpose` 显示在提示中，说明这次需要主密码做什么，例如“解密 prod-db 的已保存密码”。
+    pub fn get_master_password(prompter: &mut dyn Prompter, is_first_time: bool, purpose: &str) -> Result<String> {
+        let context = format!("主密码 {} {}", glyphs::bullet(), purpose);
         if is_first_time {
-            println!("首次使用加密功能，请设置主密码（用于加密保存的密码）");
-            println!("⚠️  请牢记此密码，忘记后无法恢复已保存的密码！");
+            eprintln!("首次使用加密功能，请设置主密码（用于加密保存的密码）");
+            eprintln!("{}  请牢记此密码，忘记后无法恢复已保存的密码！", glyphs::warn());
             
-            let password = rpassword::prompt_password("请输入主密码: ")
-                .context("无法读取密码")?;
+            let password = prompter.ask_password(&prompt::labeled(&context, "请输入"))?;
             
             if password.is_empty() {
                 anyhow::bail!("主密码不能为空");
             }
             
-            let confirm = rpassword::prompt_password("请再次输入主密码: ")
-                .context("无法读取密码")?;
+            let confirm = prompter.ask_password(&prompt::labeled(&context, "请再次输入"))?;
             
             if password != confirm {
                 anyhow::bail!("两次输入的密码不一致");
             }
             
+            credentials::remember(Credential::MasterPassword, &password);
             Ok(password)
         } else {
-            let password = rpassword::prompt_password("请输入主密码: ")
-                .context("无法读取密码")?;
+            if let Some(password) = credentials::get(&Credential::MasterPassword) {
+                return Ok(password);
+            }
+
+            let password = prompter.ask_password(&prompt::labeled(&context, "请输入"))?;
             
             if password.is_empty() {
                 anyhow::bail!("主密码不能为空");
@@ -170,6 +203,75 @@ impl CryptoManager {
         }
     }
 
+    /// 询问主密码，直到能解密 `encrypted`（用已保存的密文校验主密码）
+    ///
+    /// 最多尝试 3 次；连续输错 3 次后开始冷却，冷却时间从 `cooldown` 起每次翻倍，记录在状态文件中，
+    /// 反复运行命令也无法绕过。`cooldown` 为 0 时不冷却。非交互模式下输错一次即失败，冷却中也不等待。
+    pub fn unlock(prompter: &mut dyn Prompter, encrypted: &str, cooldown: Duration, purpose: &str) -> Result<Self> {
+        Self::unlock_any(prompter, &[encrypted], cooldown, purpose)
+    }
+
+    /// 与 [`unlock`](Self::unlock) 相同，但主密码能解密 `ciphertexts` 中任一密文即视为正确，
+    /// 部分密文损坏时仍能解锁
+    pub fn unlock_any(prompter: &mut dyn Prompter, ciphertexts: &[&str], cooldown: Duration, purpose: &str) -> Result<Self> {
+        // 本次运行已解锁过时直接使用，不再询问
+        if let Some(password) = credentials::get(&Credential::MasterPassword) {
+            let manager = Self::new(&password)?;
+            if manager.decrypts_any(ciphertexts) {
+                return Ok(manager);
+            }
+            credentials::forget(&Credential::MasterPassword);
+        }
+
+        let state_path = Self::lockout_path()?;
+        let mut state = LockoutState::load(&state_path);
+        let batch = prompt::is_batch();
+
+        if let Some(remaining) = state.remaining(unix_now()) {
+            if batch {
+                anyhow::bail!("主密码输错次数过多，请在 {} 秒后重试", remaining.as_secs());
+            }
+            eprintln!(
+                "{}",
+                format!("{}  主密码输错次数过多，需等待 {} 秒后才能重试", glyphs::warn(), remaining.as_secs()).red().bold()
+            );
+            std::thread::sleep(remaining);
+        }
+
+        let attempts = if batch { 1 } else { MAX_UNLOCK_ATTEMPTS };
+        for attempt in 1..=attempts {
+            let password = Self::get_master_password(prompter, false, purpose)?;
+            let manager = Self::new(&password)?;
+            if manager.decrypts_any(ciphertexts) {
+                credentials::remember(Credential::MasterPassword, &password);
+                if state != LockoutState::default() {
+                    LockoutState::default().save(&state_path)?;
+                }
+                return Ok(manager);
+            }
+
+            let locked = state.record_failure(unix_now(), cooldown);
+            state.save(&state_path)?;
+            if let Some(locked) = locked {
+                eprintln!(
+                    "{}",
+                    format!("{} 主密码连续 {} 次错误，已锁定 {} 秒", glyphs::fail(), MAX_UNLOCK_ATTEMPTS, locked.as_secs()).red().bold()
+                );
+                anyhow::bail!("主密码错误次数过多，请在 {} 秒后重试", locked.as_secs());
+            }
+            if attempt < attempts {
+                eprintln!("{} 主密码错误，请重试（剩余 {} 次）", glyphs::fail().red(), attempts - attempt);
+            }
+        }
+
+        anyhow::bail!("主密码错误")
+    }
+
+    /// 主密码失败记录的路径
+    fn lockout_path() -> Result<PathBuf> {
+        Ok(Self::salt_path()?.with_file_name(".unlock_state"))
+    }
+
     /// 检查是否已设置主密码（通过检查盐值文件是否存在）
     pub fn has_master_password() -> bool {
         Self::salt_path()
@@ -178,9 +280,67 @@ impl CryptoManager {
     }
 }
 
+/// 主密码连续输错的记录
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct LockoutState {
+    /// 当前连续失败次数
+    failures: u32,
+    /// 已经历的冷却次数，决定下一次冷却的长度
+    lockouts: u32,
+    /// 冷却结束时间（Unix 秒）
+    locked_until: u64,
+}
+
+impl LockoutState {
+    /// 读取记录，文件不存在或损坏时视为没有失败记录
+    fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string(self).context("无法序列化主密码失败记录")?;
+        fs::write(path, content).context("无法保存主密码失败记录")
+    }
+
+    /// 冷却剩余时间
+    fn remaining(&self, now: u64) -> Option<Duration> {
+        (self.locked_until > now).then(|| Duration::from_secs(self.locked_until - now))
+    }
+
+    /// 记录一次失败，达到次数上限时开始冷却并返回冷却时长
+    fn record_failure(&mut self, now: u64, base: Duration) -> Option<Duration> {
+        self.failures += 1;
+        if self.failures < MAX_UNLOCK_ATTEMPTS {
+            return None;
+        }
+
+        self.failures = 0;
+        if base.is_zero() {
+            return None;
+        }
+        let cooldown = base
+            .saturating_mul(2u32.saturating_pow(self.lockouts))
+            .min(MAX_COOLDOWN);
+        self.lockouts += 1;
+        self.locked_until = now + cooldown.as_secs();
+        Some(cooldown)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prompt::Scripted;
 
     #[test]
     fn test_encrypt_decrypt() {
@@ -224,5 +384,59 @@ mod tests {
         assert_eq!(manager.decrypt(&encrypted1).unwrap(), plaintext);
         assert_eq!(manager.decrypt(&encrypted2).unwrap(), plaintext);
     }
-}
 
+    #[test]
+    fn test_check_format() {
+        let manager = CryptoManager::new("test_password").unwrap();
+        assert!(CryptoManager::check_format(&manager.encrypt("").unwrap()).is_ok());
+        assert!(CryptoManager::check_format("不是 base64").is_err());
+        // 只有 nonce，没有认证标签
+        assert!(CryptoManager::check_format(&general_purpose::STANDARD.encode([0u8; 12])).is_err());
+    }
+
+    #[test]
+    fn test_first_time_master_password() {
+        let mut prompter = Scripted::new(&["master-1", "master-2"]);
+        let err = CryptoManager::get_master_password(&mut prompter, true, "加密 web 的密码").unwrap_err();
+        assert!(err.to_string().contains("不一致"));
+        assert_eq!(prompter.asked, ["[主密码 • 加密 web 的密码] 请输入: ", "[主密码 • 加密 web 的密码] 请再次输入: "]);
+
+        // 空密码在确认之前就拒绝
+        let mut prompter = Scripted::new(&[""]);
+        assert!(CryptoManager::get_master_password(&mut prompter, true, "加密 web 的密码").is_err());
+        assert_eq!(prompter.asked.len(), 1);
+
+        let mut prompter = Scripted::new(&["master-1", "master-1"]);
+        assert_eq!(CryptoManager::get_master_password(&mut prompter, true, "加密 web 的密码").unwrap(), "master-1");
+    }
+
+    #[test]
+    fn test_lockout_cooldown_grows() {
+        let base = Duration::from_secs(30);
+        let mut state = LockoutState::default();
+
+        assert_eq!(state.record_failure(100, base), None);
+        assert_eq!(state.record_failure(100, base), None);
+        assert_eq!(state.record_failure(100, base), Some(Duration::from_secs(30)));
+        assert_eq!(state.remaining(110), Some(Duration::from_secs(20)));
+        assert_eq!(state.remaining(130), None);
+
+        // 下一轮冷却时间翻倍，且有上限
+        for _ in 0..3 {
+            state.record_failure(200, base);
+        }
+        assert_eq!(state.remaining(200), Some(Duration::from_secs(60)));
+        state.lockouts = 20;
+        for _ in 0..2 {
+            state.record_failure(300, base);
+        }
+        assert_eq!(state.record_failure(300, base), Some(MAX_COOLDOWN));
+
+        // 冷却为 0 时只计数，不锁定
+        let mut state = LockoutState::default();
+        for _ in 0..3 {
+            assert_eq!(state.record_failure(100, Duration::ZERO), None);
+        }
+        assert_eq!(state.remaining(100), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! 出错时的诊断报告（`--debug-report <path>`）
+//!
+//! 开启后，命令失败时把排查问题需要的信息写成一个 JSON 文件，提交问题时附上即可：版本和平台、开启的选项、
+//! 命令行参数、每次连接实际使用的配置、协商的算法和服务器接受的认证方式（能取得时）、错误链和 backtrace、
+//! 最近的日志。连接配置中的密码和私钥密码在 `Debug` 输出中已是 `***`，写出前整份报告再按本次运行缓存的
+//! 凭据和非交互提供的密码脱敏，无法脱敏时不写出。解析命令行后立即开启，读取密码等初始化步骤失败时也能生成。
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::backtrace::BacktraceStatus;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::credentials;
+use crate::glyphs;
+use crate::log_buffer::{self, LogEntry};
+use crate::outcome;
+
+/// 报告中保留的日志条数
+pub const LOG_RECORDS: usize = 200;
+
+/// 本次运行收集的信息，未开启 `--debug-report` 时为空
+static REPORT: Mutex<Option<Collector>> = Mutex::new(None);
+
+struct Collector {
+    path: String,
+    args: Vec<String>,
+    options: Vec<String>,
+    connections: Vec<Attempt>,
+}
+
+/// 一次连接尝试
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    /// `user@host:port`
+    pub target: String,
+    /// 实际使用的连接配置（`Debug` 格式，密文为 `***`）
+    pub config: String,
+    /// 服务器标识，握手成功后才有
+    pub banner: Option<String>,
+    /// 协商的算法，握手成功后才有
+    pub algorithms: Vec<Algorithm>,
+    /// 认证失败时服务器接受的认证方式
+    pub server_auth_methods: Option<String>,
+    /// 认证成功时实际使用的认证方式
+    pub authenticated: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Algorithm {
+    pub name: String,
+    pub value: String,
+}
+
+/// 写出的报告
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    version: &'static str,
+    platform: String,
+    options: &'a [String],
+    args: &'a [String],
+    error: ErrorReport,
+    connections: &'a [Attempt],
+    logs: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorReport {
+    /// 与 `--json-result` 相同的错误类别
+    category: &'static str,
+    /// 从外到内的错误信息
+    chain: Vec<String>,
+    /// 未捕获时为空
+    backtrace: Option<String>,
+}
+
+/// 开启诊断报告，在解析命令行后调用一次；`options` 为开启的全局选项
+pub fn enable(path: &str, args: Vec<String>, options: Vec<String>) {
+    // 让 anyhow 的错误捕获 backtrace；只影响错误，不影响 panic 的输出
+    if std::env::var_os("RUST_BACKTRACE").is_none() && std::env::var_os("RUST_LIB_BACKTRACE").is_none() {
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+    *lock() = Some(Collector { path: path.to_string(), args, options, connections: Vec::new() });
+}
+
+pub fn enabled() -> bool {
+    lock().is_some()
+}
+
+/// 开始连接 `username@host:port`，`config` 为实际使用的连接配置
+pub fn attempt(username: &str, host: &str, port: u16, config: &dyn fmt::Debug) {
+    if let Some(collector) = lock().as_mut() {
+        collector.connections.push(Attempt {
+            target: format!("{}@{}:{}", username, host, port),
+            config: format!("{:?}", config),
+            banner: None,
+            algorithms: Vec::new(),
+            server_auth_methods: None,
+            authenticated: None,
+        });
+    }
+}
+
+/// 握手完成：服务器标识和协商的算法
+pub fn handshake(banner: Option<&str>, algorithms: Vec<(&str, String)>) {
+    update(|attempt| {
+        attempt.banner = banner.map(str::to_string);
+        attempt.algorithms = algorithms
+            .into_iter()
+            .map(|(name, value)| Algorithm { name: name.to_string(), value })
+            .collect();
+    });
+}
+
+/// 认证被拒绝时服务器接受的认证方式
+pub fn server_auth_methods(methods: &str) {
+    update(|attempt| attempt.server_auth_methods = Some(methods.to_string()));
+}
+
+/// 认证成功
+pub fn authenticated(method: &str) {
+    update(|attempt| attempt.authenticated = Some(method.to_string()));
+}
+
+fn update(f: impl FnOnce(&mut Attempt)) {
+    if let Some(attempt) = lock().as_mut().and_then(|collector| collector.connections.last_mut()) {
+        f(attempt);
+    }
+}
+
+/// 命令失败：写出报告并说明位置和内容；未开启时什么也不做，多次调用只写一次
+pub fn write(error: &anyhow::Error) {
+    let Some(collector) = lock().take() else {
+        return;
+    };
+    let logs = log_buffer::snapshot();
+    let result = render(&collector, error, &logs).and_then(|content| {
+        std::fs::write(&collector.path, content).context(format!("无法写入诊断报告 {}", collector.path))
+    });
+    match result {
+        Ok(()) => {
+            eprintln!(
+                "{} 诊断报告已写入 {}：版本和平台、命令行参数、{} 次连接的配置和握手信息、错误链、最近 {} 条日志",
+                glyphs::arrow().cyan(),
+                collector.path,
+                collector.connections.len(),
+                logs.len().min(LOG_RECORDS)
+            );
+            eprintln!("  密码和私钥密码已脱敏；主机名、用户名和路径保留，提交前请检查");
+        }
+        Err(e) => eprintln!("{} 无法生成诊断报告: {:#}", glyphs::warn().yellow(), e),
+    }
+}
+
+fn render(collector: &Collector, error: &anyhow::Error, logs: &[LogEntry]) -> Result<String> {
+    let backtrace = error.backtrace();
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        platform: format!("{} {} ({})", std::env::consts::OS, std::env::consts::ARCH, std::env::consts::FAMILY),
+        options: &collector.options,
+        args: &collector.args,
+        error: ErrorReport {
+            category: outcome::category(error),
+            chain: error.chain().map(ToString::to_string).collect(),
+            backtrace: (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string()),
+        },
+        connections: &collector.connections,
+        logs: logs[logs.len().saturating_sub(LOG_RECORDS)..].iter().map(LogEntry::line).collect(),
+    };
+    let content = serde_json::to_string_pretty(&report).context("无法序列化诊断报告")?;
+    redact(&content)
+}
+
+/// 按缓存的凭据和非交互提供的密码脱敏
+fn redact(content: &str) -> Result<String> {
+    let mut content = credentials::redact(content).context("凭据缓存正被占用，无法脱敏")?;
+    if let Some(password) = credentials::preset_password().filter(|p| !p.is_empty()) {
+        content = content.replace(password.as_str(), credentials::REDACTED);
+    }
+    Ok(content)
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Collector>> {
+    REPORT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::Credential;
+    use crate::ssh::{AuthMethod, SshConfig};
+    use chrono::Local;
+    use log::Level;
+
+    #[test]
+    fn test_render_redacts_secrets() {
+        let config = SshConfig {
+            host: "db.example.invalid".to_string(),
+            port: 2222,
+            username: "report".to_string(),
+            auth: AuthMethod::Password("report-secret-1".to_string()),
+        };
+        let key = Credential::password("report", "db.example.invalid");
+        credentials::remember(key.clone(), "report-secret-1");
+
+        let mut collector = Collector {
+            path: String::new(),
+            args: vec!["exec".to_string(), "db".to_string(), "uptime".to_string()],
+            options: vec!["--ssh-debug".to_string()],
+            connections: Vec::new(),
+        };
+        collector.connections.push(Attempt {
+            target: "report@db.example.invalid:2222".to_string(),
+            config: format!("{:?}", config),
+            banner: Some("SSH-2.0-OpenSSH_9.6".to_string()),
+            algorithms: vec![Algorithm { name: "密钥交换".to_string(), value: "curve25519-sha256".to_string() }],
+            server_auth_methods: Some("publickey".to_string()),
+            authenticated: None,
+        });
+        let error = anyhow::anyhow!("服务器拒绝了密码 report-secret-1").context("连接 db 失败");
+        let logs: Vec<LogEntry> = (0..250)
+            .map(|i| LogEntry { time: Local::now(), level: Level::Debug, target: "test".to_string(), message: format!("第 {} 条", i) })
+            .collect();
+
+        let content = render(&collector, &error, &logs).unwrap();
+        credentials::forget(&key);
+        assert!(!content.contains("report-secret-1"), "{}", content);
+
+        let report: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(report["error"]["chain"][0], "连接 db 失败");
+        assert_eq!(report["error"]["chain"][1], format!("服务器拒绝了密码 {}", credentials::REDACTED));
+        assert!(report["connections"][0]["config"].as_str().unwrap().contains("Password(***)"));
+        assert_eq!(report["connections"][0]["server_auth_methods"], "publickey");
+        let logs = report["logs"].as_array().unwrap();
+        assert_eq!(logs.len(), LOG_RECORDS);
+        assert!(logs[0].as_str().unwrap().ends_with("第 50 条"));
+    }
+}
@@ -0,0 +1,295 @@
+//! 远程环境诊断（`diag` 命令）
+//!
+//! 连接后一次收集排查问题常用的信息：服务器标识和协商的算法、认证方式、往返延迟、远程 shell、
+//! 常用工具是否存在、登录目录及其磁盘空间、与本机的时钟偏差。单项失败不影响其他项，原因记录在
+//! `problems` 中。
+
+use anyhow::Result;
+use colored::{Color, Colorize};
+use log::debug;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::{check, format_size, glyphs};
+use crate::remote_os::{self, DiskFree};
+use crate::sftp::SftpClient;
+use crate::table::{self, Align, Cell, Table};
+use crate::ssh::{self, AuthMethod, ServerInfo, SshClient};
+
+/// 检查是否存在的远程命令
+const TOOLS: &[&str] = &["scp", "tar", "sha256sum"];
+
+/// 测量往返延迟的次数（取最小值）
+const LATENCY_SAMPLES: usize = 3;
+
+/// 时钟偏差超过该值时提示
+pub const SKEW_WARN_SECS: i64 = 5;
+
+/// 一次性收集 shell、登录目录、工具路径和远程时间的脚本，每行输出 `key=value`
+const PROBE_SCRIPT: &str = r#"printf 'shell=%s\nhome=%s\n' "$SHELL" "$HOME"; for t in scp tar sha256sum; do printf 'tool.%s=%s\n' "$t" "$(command -v "$t" 2>/dev/null)"; done; printf 'date=%s\n' "$(date +%s)""#;
+
+/// 诊断报告
+#[derive(Debug, Serialize)]
+pub struct DiagReport {
+    pub target: String,
+    pub server: ServerInfo,
+    /// 认证方式：password、publickey 或 agent
+    pub auth: String,
+    /// 最小往返延迟（毫秒）
+    pub latency_ms: Option<f64>,
+    pub shell: Option<String>,
+    pub home: Option<String>,
+    /// 登录目录所在磁盘的空间
+    pub disk: Option<DiskFree>,
+    /// SFTP 子系统可用
+    pub sftp: bool,
+    pub tools: Vec<ToolStatus>,
+    /// 远程时钟减本机时钟（秒），正数表示远程较快
+    pub clock_skew_secs: Option<i64>,
+    /// 未能完成的检查项及原因
+    pub problems: Vec<String>,
+}
+
+/// 远程命令是否存在
+#[derive(Debug, Serialize)]
+pub struct ToolStatus {
+    pub name: String,
+    /// `command -v` 给出的路径，不存在时为 None
+    pub path: Option<String>,
+}
+
+/// 解析后的探测脚本输出
+#[derive(Debug, Default, PartialEq)]
+struct Probe {
+    shell: Option<String>,
+    home: Option<String>,
+    tools: Vec<(String, Option<String>)>,
+    date: Option<i64>,
+}
+
+/// 收集诊断信息
+pub fn collect(target: &str, client: &SshClient) -> DiagReport {
+    let mut problems = Vec::new();
+    let mut server = client.server_info();
+    let remote = client.remote_info();
+    server.os = Some(remote.describe());
+
+    let sftp = match SftpClient::new(client) {
+        Ok(sftp) => Some(sftp),
+        Err(e) => {
+            problems.push(format!("SFTP 不可用: {:#}", e));
+            None
+        }
+    };
+
+    let latency_ms = match measure_latency(client, sftp.as_ref()) {
+        Ok(latency) => Some(latency.as_secs_f64() * 1000.0),
+        Err(e) => {
+            problems.push(format!("无法测量延迟: {:#}", e));
+            None
+        }
+    };
+
+    let mut probe = Probe::default();
+    let mut clock_skew_secs = None;
+    if let Err(e) = remote.require_unix("环境探测") {
+        problems.push(format!("{:#}", e));
+    } else {
+        match run_probe(client) {
+            Ok((parsed, local_time)) => {
+                clock_skew_secs = parsed.date.map(|remote_time| remote_time - local_time);
+                if parsed.date.is_none() {
+                    problems.push("无法解析远程时间（date +%s）".to_string());
+                }
+                probe = parsed;
+            }
+            Err(e) => problems.push(format!("环境探测失败: {:#}", e)),
+        }
+    }
+
+    let disk = match (&probe.home, &sftp) {
+        (Some(home), Some(sftp)) => match remote_os::disk_free(client, sftp, home) {
+            Ok(free) => Some(free),
+            Err(e) => {
+                problems.push(format!("无法查询磁盘空间: {:#}", e));
+                None
+            }
+        },
+        _ => None,
+    };
+
+    DiagReport {
+        target: target.to_string(),
+        server,
+        auth: auth_name(&client.config().auth).to_string(),
+        latency_ms,
+        shell: probe.shell,
+        home: probe.home,
+        disk,
+        sftp: sftp.is_some(),
+        tools: probe.tools.into_iter().map(|(name, path)| ToolStatus { name, path }).collect(),
+        clock_skew_secs,
+        problems,
+    }
+}
+
+/// 最小往返延迟：SFTP 可用时测量 stat 请求，否则测量执行空命令（包含打开通道的开销）
+fn measure_latency(client: &SshClient, sftp: Option<&SftpClient>) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..LATENCY_SAMPLES {
+        let started = Instant::now();
+        match sftp {
+            Some(sftp) => {
+                sftp.stat(".")?;
+            }
+            None => {
+                client.exec_command_timeout("true", Some(ssh::PROBE_TIMEOUT))?;
+            }
+        }
+        best = best.min(started.elapsed());
+    }
+    Ok(best)
+}
+
+/// 执行探测脚本，返回解析结果和执行期间的本机时间（取开始和结束的中点，秒）
+/// 远程时钟减本机时钟（秒），无法解析 `date +%s` 的输出时为 None
+pub fn clock_skew(client: &SshClient) -> Result<Option<i64>> {
+    client.remote_info().require_unix("读取远程时间")?;
+    let (probe, local_time) = run_probe(client)?;
+    Ok(probe.date.map(|remote_time| remote_time - local_time))
+}
+
+fn run_probe(client: &SshClient) -> Result<(Probe, i64)> {
+    let before = chrono::Utc::now().timestamp_millis();
+    let (output, stderr, status) = client.exec_command_timeout(PROBE_SCRIPT, Some(ssh::PROBE_TIMEOUT))?;
+    let after = chrono::Utc::now().timestamp_millis();
+    if status != 0 {
+        debug!("探测脚本退出码 {}: {}", status, stderr.trim());
+    }
+    let local_time = (before + after) / 2 / 1000;
+    Ok((parse_probe(&String::from_utf8_lossy(&output)), local_time))
+}
+
+fn parse_probe(output: &str) -> Probe {
+    let mut probe = Probe::default();
+    let non_empty = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        match key {
+            "shell" => probe.shell = non_empty(value),
+            "home" => probe.home = non_empty(value),
+            "date" => probe.date = value.trim().parse().ok(),
+            _ => {
+                if let Some(tool) = key.strip_prefix("tool.") {
+                    probe.tools.push((tool.to_string(), non_empty(value)));
+                }
+            }
+        }
+    }
+    // 输出被截断时缺少的工具记为不存在
+    for tool in TOOLS {
+        if !probe.tools.iter().any(|(name, _)| name == tool) {
+            probe.tools.push((tool.to_string(), None));
+        }
+    }
+    probe
+}
+
+fn auth_name(auth: &AuthMethod) -> &'static str {
+    match auth {
+        AuthMethod::Password(_) => "password",
+        AuthMethod::PublicKey { .. } => "publickey",
+        AuthMethod::Agent => "agent",
+    }
+}
+
+/// 以表格形式打印报告
+pub fn print(report: &DiagReport) {
+    if !table::is_plain() {
+        println!("{} {}", "诊断:".cyan().bold(), report.target);
+    }
+
+    let mut info = check::server_info_table(&report.server);
+    let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    info.row(vec!["认证:".into(), report.auth.as_str().into()]);
+    info.row(vec!["延迟:".into(), report.latency_ms.map(|ms| format!("{:.1} ms", ms)).unwrap_or_else(|| "-".to_string()).into()]);
+    info.row(vec!["Shell:".into(), or_dash(&report.shell).into()]);
+    info.row(vec!["登录目录:".into(), or_dash(&report.home).into()]);
+    if let Some(ref disk) = report.disk {
+        info.row(vec!["可用空间:".into(), format!("{} / {}", format_size(disk.available), format_size(disk.total)).into()]);
+    }
+    info.row(vec!["时钟偏差:".into(), match report.clock_skew_secs {
+        Some(skew) if skew.abs() > SKEW_WARN_SECS => Cell::new(format!("{:+} 秒", skew)).color(Color::Yellow),
+        Some(skew) => Cell::new(format!("{:+} 秒", skew)),
+        None => "-".into(),
+    }]);
+    info.print();
+
+    if !table::is_plain() {
+        println!("{}", "工具:".cyan().bold());
+    }
+    let mark = |found: bool| if found { Cell::new(glyphs::ok()).color(Color::Green) } else { Cell::new(glyphs::fail()).color(Color::Red) };
+    let mut tools = Table::without_header()
+        .indent(2)
+        .column("", Align::Left)
+        .column("工具", Align::Left)
+        .truncated_column("路径", Align::Left);
+    tools.row(vec![mark(report.sftp), "sftp".into(), if report.sftp { "子系统可用" } else { "不可用" }.into()]);
+    for tool in &report.tools {
+        tools.row(vec![mark(tool.path.is_some()), tool.name.as_str().into(), tool.path.as_deref().unwrap_or("未找到").into()]);
+    }
+    tools.print();
+
+    if !report.problems.is_empty() && !table::is_plain() {
+        println!("{}", "问题:".yellow().bold());
+        for problem in &report.problems {
+            println!("  {} {}", glyphs::fail().red(), problem);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_parse_probe() {
+        let probe = parse_probe("shell=/bin/bash\nhome=/home/ops\ntool.scp=/usr/bin/scp\ntool.tar=\ndate=1700000000\n");
+        assert_eq!(probe.shell.as_deref(), Some("/bin/bash"));
+        assert_eq!(probe.home.as_deref(), Some("/home/ops"));
+        assert_eq!(probe.date, Some(1_700_000_000));
+        assert_eq!(probe.tools, vec![
+            ("scp".to_string(), Some("/usr/bin/scp".to_string())),
+            ("tar".to_string(), None),
+            ("sha256sum".to_string(), None),
+        ]);
+
+        let empty = parse_probe("shell=\ndate=oops\n");
+        assert_eq!(empty.shell, None);
+        assert_eq!(empty.date, None);
+        assert_eq!(empty.tools.len(), TOOLS.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_against_server() {
+        let server = TestServer::start();
+        let client = server.connect();
+
+        let report = collect("test", &client);
+        assert_eq!(report.auth, "password");
+        assert!(report.sftp);
+        assert!(report.latency_ms.is_some());
+        assert!(report.server.banner.is_some());
+        assert!(report.clock_skew_secs.unwrap().abs() <= 2, "{:?}", report.clock_skew_secs);
+        assert_eq!(report.tools.len(), TOOLS.len());
+        if report.home.is_some() {
+            assert!(report.disk.is_some(), "{:?}", report.problems);
+        }
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["auth"], "password");
+        assert!(json["server"]["kex"].is_string());
+    }
+}
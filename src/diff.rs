@@ -0,0 +1,130 @@
+//! 比较本地文件与远程文件（`sftp diff`）
+//!
+//! 远程文件只读入内存，不写入本地磁盘。任一方开头含 NUL 时视为二进制，只比较 SHA-256；
+//! 否则用 similar 按行比较，输出统一格式（unified）的差异。
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::sftp::{self, SftpClient};
+
+/// 判断二进制文件时检查的开头字节数（与 grep 相同，含 NUL 即视为二进制）
+const BINARY_PROBE: usize = 8192;
+
+/// 差异前后保留的上下文行数
+const CONTEXT: usize = 3;
+
+/// 比较结果
+#[derive(Debug, PartialEq)]
+pub enum Comparison {
+    Identical,
+    /// 二进制文件内容不同（只比较了哈希）
+    BinaryDiffer,
+    /// 文本文件不同，`brief` 时没有差异内容
+    TextDiffer(Option<String>),
+}
+
+/// 比较本地文件和远程文件，`brief` 时只判断是否相同
+pub fn compare(sftp: &SftpClient, local_path: &str, remote_path: &str, brief: bool) -> Result<Comparison> {
+    let local = Path::new(local_path);
+    let metadata = std::fs::metadata(local).context(format!("无法读取本地文件: {}", local_path))?;
+    if metadata.is_dir() {
+        anyhow::bail!("本地路径是目录: {}（只能比较单个文件）", local_path);
+    }
+    let info = sftp.stat(remote_path)?;
+    if info.is_dir {
+        anyhow::bail!("远程路径是目录: {}（只能比较单个文件）", remote_path);
+    }
+
+    let local_data = std::fs::read(local).context(format!("无法读取本地文件: {}", local_path))?;
+    let mut reader = BufReader::with_capacity(64 * 1024, sftp.open_reader(Path::new(remote_path))?);
+    let head = reader.fill_buf().context(format!("读取远程文件失败: {}", remote_path))?;
+
+    if is_binary(&local_data) || is_binary(head) {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut reader, &mut hasher).context(format!("读取远程文件失败: {}", remote_path))?;
+        let same = hasher.finalize().as_slice() == Sha256::digest(&local_data).as_slice();
+        return Ok(if same { Comparison::Identical } else { Comparison::BinaryDiffer });
+    }
+
+    let mut remote_data = Vec::with_capacity(info.size as usize);
+    reader.read_to_end(&mut remote_data).context(format!("读取远程文件失败: {}", remote_path))?;
+    if local_data == remote_data {
+        return Ok(Comparison::Identical);
+    }
+    if brief {
+        return Ok(Comparison::TextDiffer(None));
+    }
+    let label = sftp::escape_name(remote_path);
+    Ok(Comparison::TextDiffer(Some(unified(&local_data, &remote_data, local_path, &label))))
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_PROBE)].contains(&0)
+}
+
+/// 生成统一格式的差异，`a` 到 `b`
+fn unified(a: &[u8], b: &[u8], a_label: &str, b_label: &str) -> String {
+    TextDiff::from_lines(a, b)
+        .unified_diff()
+        .context_radius(CONTEXT)
+        .header(a_label, b_label)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_unified() {
+        let a = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let b = b"1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13";
+        let diff = unified(a, b, "a", "b");
+        assert_eq!(
+            diff,
+            "--- a\n+++ b\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n\\ No newline at end of file\n"
+        );
+
+        // 两处改动相距不超过 6 行时合并为一段
+        let diff = unified(b"a\nb\nc\nd\ne\n", b"x\nb\nc\nd\ny\n", "a", "b");
+        assert_eq!(diff, "--- a\n+++ b\n@@ -1,5 +1,5 @@\n-a\n+x\n b\n c\n d\n-e\n+y\n");
+
+        let diff = unified(b"", b"new\n", "a", "b");
+        assert_eq!(diff, "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n");
+    }
+
+    #[test]
+    fn test_compare() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let local = dir.path().join("app.conf");
+        let local = local.to_str().unwrap();
+
+        std::fs::write(local, b"port = 80\nhost = a\n").unwrap();
+        std::fs::write(server.local_path("/app.conf"), b"port = 80\nhost = a\n").unwrap();
+        assert_eq!(compare(&sftp, local, "/app.conf", false).unwrap(), Comparison::Identical);
+
+        std::fs::write(server.local_path("/app.conf"), b"port = 8080\nhost = a\n").unwrap();
+        assert_eq!(compare(&sftp, local, "/app.conf", true).unwrap(), Comparison::TextDiffer(None));
+        let Comparison::TextDiffer(Some(diff)) = compare(&sftp, local, "/app.conf", false).unwrap() else {
+            panic!("应为文本差异");
+        };
+        assert!(diff.contains("-port = 80\n+port = 8080\n"));
+
+        std::fs::write(server.local_path("/app.conf"), b"\0\x01").unwrap();
+        assert_eq!(compare(&sftp, local, "/app.conf", false).unwrap(), Comparison::BinaryDiffer);
+        std::fs::write(local, b"\0\x01").unwrap();
+        assert_eq!(compare(&sftp, local, "/app.conf", false).unwrap(), Comparison::Identical);
+
+        assert!(compare(&sftp, local, "/", false).is_err());
+    }
+}
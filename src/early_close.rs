@@ -0,0 +1,210 @@
+//! shell 请求后服务器立即关闭通道
+//!
+//! 只允许 git 的账号、配置了强制命令（`authorized_keys` 的 `command=`、`ForceCommand`）或受限 shell 的账号，
+//! 认证和 shell 请求都会成功，随后服务器执行自己的命令并关闭通道。用户还没有输入任何内容时通道就结束，
+//! 视为服务器拒绝了交互式会话：恢复终端后说明退出状态，并重新显示服务器的输出（通常是拒绝的原因，
+//! 原始模式下可能显示错乱）。
+
+use russh::ChannelMsg;
+
+/// 保留的服务器输出上限
+const CAPTURE_LIMIT: usize = 4096;
+
+/// 记录用户输入前服务器的输出和退出状态
+#[derive(Debug, Default)]
+pub struct EarlyClose {
+    output: Vec<u8>,
+    typed: bool,
+    closed: bool,
+    refused: bool,
+    exit_status: Option<u32>,
+    exit_signal: Option<String>,
+}
+
+impl EarlyClose {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 服务器的输出（标准输出和标准错误）
+    pub fn output(&mut self, data: &[u8]) {
+        if !self.typed {
+            let room = CAPTURE_LIMIT.saturating_sub(self.output.len());
+            self.output.extend_from_slice(&data[..data.len().min(room)]);
+        }
+    }
+
+    /// 用户有了输入，之后通道关闭属于正常结束
+    pub fn input(&mut self) {
+        if !self.typed {
+            self.typed = true;
+            self.output = Vec::new();
+        }
+    }
+
+    /// 服务器发送了 EOF 或关闭了通道
+    pub fn closed(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn exit_status(&mut self, code: u32) {
+        self.exit_status = Some(code);
+    }
+
+    pub fn exit_signal(&mut self, signal: String) {
+        self.exit_signal = Some(signal);
+    }
+
+    /// 处理 russh 通道消息
+    pub fn message(&mut self, msg: &ChannelMsg) {
+        match msg {
+            ChannelMsg::Data { data } | ChannelMsg::ExtendedData { data, .. } => self.output(data),
+            ChannelMsg::ExitStatus { exit_status } => self.exit_status(*exit_status),
+            ChannelMsg::ExitSignal { signal_name, .. } => self.exit_signal(format!("{:?}", signal_name)),
+            // shell 请求被拒绝（PTY 请求不要求应答，只有 shell 请求会收到 Failure）
+            ChannelMsg::Failure => self.refused = true,
+            ChannelMsg::Eof | ChannelMsg::Close => self.closed(),
+            _ => {}
+        }
+    }
+
+    /// 用户输入前通道已经结束或 shell 请求被拒绝
+    pub fn is_early(&self) -> bool {
+        !self.typed && (self.closed || self.refused)
+    }
+
+    /// 会话在用户输入前结束时的说明，否则为 None
+    pub fn report(&self) -> Option<String> {
+        if !self.is_early() {
+            return None;
+        }
+        let status = if self.refused {
+            "服务器拒绝了 shell 请求".to_string()
+        } else {
+            match (self.exit_status, &self.exit_signal) {
+                (_, Some(signal)) => format!("服务器在 shell 启动后立即结束了会话（信号 {}）", signal),
+                (Some(code), None) => format!("服务器在 shell 启动后立即结束了会话（退出码 {}）", code),
+                (None, None) => "服务器在 shell 启动后立即关闭了通道".to_string(),
+            }
+        };
+        let mut lines = vec![format!("{}，该账号可能不允许交互式登录（例如只允许 git 或配置了强制命令）", status)];
+
+        let text = String::from_utf8_lossy(&self.output);
+        let output: Vec<String> = text
+            .lines()
+            .map(|line| plain_text(line).trim_end().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if output.is_empty() {
+            lines.push("服务器没有给出原因".to_string());
+        } else {
+            lines.push("服务器的输出:".to_string());
+            lines.extend(output.iter().map(|line| format!("  {}", line)));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// 去掉颜色等 CSI 控制序列和其他控制字符
+fn plain_text(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // ESC [ 参数 结束字符（0x40-0x7E）
+            if chars.clone().next() == Some('[') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else if !c.is_control() || c == '\t' {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_russh::{AuthMethod, RusshClient, SshConfig};
+    use crate::test_server::{TestServer, TEST_PASSWORD, TEST_USER};
+
+    #[test]
+    fn test_report() {
+        let mut early = EarlyClose::new();
+        early.output(b"\x1b[31mfatal: Interactive git shell is not enabled.\x1b[0m\r\n");
+        early.closed();
+        early.exit_status(128);
+        let report = early.report().unwrap();
+        assert!(report.starts_with("服务器在 shell 启动后立即结束了会话（退出码 128）"));
+        assert!(report.ends_with("服务器的输出:\n  fatal: Interactive git shell is not enabled."));
+
+        // 用户输入后的关闭是正常结束
+        let mut normal = EarlyClose::new();
+        normal.output(b"Welcome\r\n");
+        normal.input();
+        normal.closed();
+        assert!(normal.report().is_none());
+
+        // 本地输入结束但通道仍然打开
+        assert!(EarlyClose::new().report().is_none());
+    }
+
+    /// 配置了强制命令的服务器：shell 请求成功，命令输出原因后立即退出
+    #[tokio::test]
+    async fn test_forced_command() {
+        let server = TestServer::start_with_forced_command("echo 'fatal: Interactive git shell is not enabled.' >&2; exit 128");
+        let mut client = RusshClient::new(SshConfig::new(
+            "127.0.0.1".to_string(),
+            server.port,
+            TEST_USER.to_string(),
+            AuthMethod::Password(TEST_PASSWORD.to_string()),
+        ));
+        client.connect().await.unwrap();
+
+        let mut channel = client.session().unwrap().channel_open_session().await.unwrap();
+        channel.request_pty(false, "xterm", 80, 24, 0, 0, &[]).await.unwrap();
+        channel.request_shell(true).await.unwrap();
+        let mut early = EarlyClose::new();
+        while let Some(msg) = channel.wait().await {
+            early.message(&msg);
+            if matches!(msg, ChannelMsg::Close) {
+                break;
+            }
+        }
+
+        let report = early.report().unwrap();
+        assert!(report.contains("退出码 128"), "{}", report);
+        assert!(report.contains("  fatal: Interactive git shell is not enabled."), "{}", report);
+        client.disconnect().await.unwrap();
+    }
+
+    /// 没有强制命令时测试服务器拒绝 shell 请求
+    #[tokio::test]
+    async fn test_shell_refused() {
+        let server = TestServer::start();
+        let mut client = RusshClient::new(SshConfig::new(
+            "127.0.0.1".to_string(),
+            server.port,
+            TEST_USER.to_string(),
+            AuthMethod::Password(TEST_PASSWORD.to_string()),
+        ));
+        client.connect().await.unwrap();
+
+        let mut channel = client.session().unwrap().channel_open_session().await.unwrap();
+        channel.request_shell(true).await.unwrap();
+        let mut early = EarlyClose::new();
+        while let Some(msg) = channel.wait().await {
+            early.message(&msg);
+            if early.is_early() {
+                break;
+            }
+        }
+        assert!(early.report().unwrap().starts_with("服务器拒绝了 shell 请求"));
+        client.disconnect().await.unwrap();
+    }
+}
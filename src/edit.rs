@@ -0,0 +1,235 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sftp::{FileInfo, SftpClient};
+
+/// 编辑的结果
+#[derive(Debug, PartialEq)]
+pub enum EditOutcome {
+    /// 内容没有变化，未上传
+    Unchanged,
+    /// 已上传修改后的内容（字节数）
+    Uploaded(u64),
+    /// 远程文件在编辑期间被修改，用户选择放弃
+    Aborted,
+}
+
+/// 下载远程文件到私有临时目录，调用 `edit` 编辑后按需上传
+///
+/// 内容未变化时不上传。上传前重新检查远程文件，编辑期间被其他人修改时由 `overwrite`
+/// 决定是否覆盖。临时文件无论成功与否都会删除。
+pub fn edit_remote(
+    sftp: &SftpClient,
+    remote_path: &str,
+    edit: impl FnOnce(&Path) -> Result<()>,
+    overwrite: impl FnOnce() -> Result<bool>,
+) -> Result<EditOutcome> {
+    let before = sftp.stat(remote_path)?;
+    if before.is_dir {
+        anyhow::bail!("远程路径是目录: {}（只能编辑单个文件）", remote_path);
+    }
+
+    let temp = TempDir::create()?;
+    // 保留原文件名，编辑器可以按扩展名识别文件类型
+    let local = temp.path.join(&before.name);
+    create_private_file(&local)?;
+    let local_path = local.to_string_lossy().to_string();
+
+    sftp.download_file(remote_path, &local_path, None)?;
+    let original = hash_file(&local)?;
+
+    edit(&local)?;
+
+    if hash_file(&local)? == original {
+        debug!("内容未变化: {}", remote_path);
+        return Ok(EditOutcome::Unchanged);
+    }
+
+    let after = sftp.stat(remote_path)?;
+    if changed_remotely(&before, &after) {
+        warn!("远程文件在编辑期间被修改: {}", remote_path);
+        if !overwrite()? {
+            return Ok(EditOutcome::Aborted);
+        }
+    }
+
+    let stats = sftp.upload_file(&local_path, remote_path, None)?;
+    Ok(EditOutcome::Uploaded(stats.total_bytes))
+}
+
+/// 启动编辑器并等待其退出：`--editor` 优先，其次是 `$VISUAL`、`$EDITOR`
+///
+/// 编辑器命令可以带参数，例如 `code --wait`。
+pub fn run_editor(editor: Option<&str>, path: &Path) -> Result<()> {
+    let command = editor
+        .map(str::to_string)
+        .or_else(|| std::env::var("VISUAL").ok().filter(|v| !v.trim().is_empty()))
+        .or_else(|| std::env::var("EDITOR").ok().filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| default_editor().to_string());
+
+    let mut parts = command.split_whitespace();
+    let program = parts.next().context("编辑器命令为空")?;
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .context(format!("无法启动编辑器: {}", command))?;
+    if !status.success() {
+        anyhow::bail!("编辑器异常退出（{}），未上传修改", status);
+    }
+    Ok(())
+}
+
+fn default_editor() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
+    } else {
+        "vi"
+    }
+}
+
+/// 远程文件的大小或修改时间与下载时不同
+fn changed_remotely(before: &FileInfo, after: &FileInfo) -> bool {
+    before.size != after.size || before.modified != after.modified
+}
+
+fn hash_file(path: &Path) -> Result<Vec<u8>> {
+    let data = fs::read(path).context(format!("无法读取临时文件: {}", path.display()))?;
+    Ok(Sha256::digest(&data).to_vec())
+}
+
+/// 新建只有当前用户可读写的文件（Unix 上为 0600）
+fn create_private_file(path: &Path) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .context(format!("无法创建临时文件: {}", path.display()))?;
+    Ok(())
+}
+
+/// 私有临时目录（Unix 上为 0700），释放时连同其中的文件一起删除
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn create() -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let path = std::env::temp_dir().join(format!("rust-ssh-sftp-edit-{}-{}", std::process::id(), nanos));
+
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder
+            .create(&path)
+            .context(format!("无法创建临时目录: {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            warn!("无法删除临时目录 {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+
+    #[test]
+    fn test_edit_round_trip() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::write(server.local_path("/app.conf"), b"port = 80\n").unwrap();
+
+        let temp_path = RefCell::new(PathBuf::new());
+        let outcome = edit_remote(
+            &sftp,
+            "/app.conf",
+            |path| {
+                *temp_path.borrow_mut() = path.to_path_buf();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o600);
+                }
+                assert_eq!(path.file_name().unwrap(), "app.conf");
+                fs::write(path, b"port = 8080\n")?;
+                Ok(())
+            },
+            || panic!("没有冲突时不应询问"),
+        )
+        .unwrap();
+
+        assert_eq!(outcome, EditOutcome::Uploaded(12));
+        assert_eq!(std::fs::read(server.local_path("/app.conf")).unwrap(), b"port = 8080\n");
+        assert!(!temp_path.borrow().exists());
+
+        let outcome = edit_remote(&sftp, "/app.conf", |_| Ok(()), || Ok(true)).unwrap();
+        assert_eq!(outcome, EditOutcome::Unchanged);
+
+        // 编辑器出错时同样删除临时文件
+        let err = edit_remote(
+            &sftp,
+            "/app.conf",
+            |path| {
+                *temp_path.borrow_mut() = path.to_path_buf();
+                anyhow::bail!("editor crashed")
+            },
+            || Ok(true),
+        );
+        assert!(err.is_err());
+        assert!(!temp_path.borrow().exists());
+    }
+
+    #[test]
+    fn test_edit_detects_remote_change() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let remote = server.local_path("/app.conf");
+        std::fs::write(&remote, b"a\n").unwrap();
+
+        let edit = |path: &Path| -> Result<()> {
+            fs::write(path, b"mine\n")?;
+            // 模拟其他人在编辑期间修改了远程文件
+            fs::write(&remote, b"theirs\n")?;
+            let file = fs::File::options().write(true).open(&remote)?;
+            file.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+            Ok(())
+        };
+
+        let outcome = edit_remote(&sftp, "/app.conf", edit, || Ok(false)).unwrap();
+        assert_eq!(outcome, EditOutcome::Aborted);
+        assert_eq!(std::fs::read(&remote).unwrap(), b"theirs\n");
+
+        let outcome = edit_remote(&sftp, "/app.conf", edit, || Ok(true)).unwrap();
+        assert_eq!(outcome, EditOutcome::Uploaded(5));
+        assert_eq!(std::fs::read(&remote).unwrap(), b"mine\n");
+    }
+}
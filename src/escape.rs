@@ -0,0 +1,282 @@
+/// 默认的转义字符
+pub const DEFAULT_ESCAPE: u8 = b'~';
+
+/// Ctrl+Z
+const CTRL_Z: u8 = 0x1a;
+
+/// 解析转义字符设置：`none` 表示禁用本地转义，否则必须是单个可打印 ASCII 字符
+pub fn parse_escape_char(value: &str) -> anyhow::Result<Option<u8>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    match value.as_bytes() {
+        [byte] if byte.is_ascii_graphic() => Ok(Some(*byte)),
+        _ => anyhow::bail!("无效的转义字符: {}（应为单个可打印字符或 none）", value),
+    }
+}
+
+/// 输入字节经过转义处理后的动作
+#[derive(Debug, Clone, PartialEq)]
+pub enum EscapeAction {
+    /// 发送到远程（可能为空，例如转义字符本身被暂存）
+    Send(Vec<u8>),
+    /// `~.` 断开连接
+    Disconnect,
+    /// `~^Z` 挂起到本地 shell
+    Suspend,
+    /// `~?` 显示帮助
+    Help,
+    /// `~u`、`~g` 开始输入传输参数
+    Transfer(Direction),
+}
+
+/// `~u` 上传、`~g` 下载
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+impl Direction {
+    /// 输入参数时显示的提示
+    pub fn prompt(&self, escape: u8) -> String {
+        match self {
+            Direction::Upload => format!("{}u 本地路径 [远程路径]> ", escape as char),
+            Direction::Download => format!("{}g 远程路径 [本地路径]> ", escape as char),
+        }
+    }
+}
+
+/// 一次 `~u`/`~g` 传输：`source` 为上传的本地文件或下载的远程文件，`target` 未指定时使用同名文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferRequest {
+    pub direction: Direction,
+    pub source: String,
+    pub target: Option<String>,
+}
+
+impl TransferRequest {
+    /// 解析 `~u`/`~g` 后输入的参数，路径中有空格时用引号括起
+    pub fn parse(direction: Direction, line: &str) -> anyhow::Result<Self> {
+        let mut args = split_args(line)?.into_iter();
+        let Some(source) = args.next() else {
+            anyhow::bail!("缺少要传输的文件");
+        };
+        let target = args.next();
+        if args.next().is_some() {
+            anyhow::bail!("参数过多：只接受源路径和目标路径");
+        }
+        Ok(Self { direction, source, target })
+    }
+}
+
+/// 按空白分割参数，支持单引号和双引号
+fn split_args(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => args.extend(current.take()),
+            None => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        anyhow::bail!("引号不匹配: {}", line);
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// 本地输入一行时每个按键的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineEvent {
+    /// 继续输入，附带需要回显的字节
+    Echo(Vec<u8>),
+    /// 回车结束输入
+    Done(String),
+    /// Ctrl+C 取消
+    Cancel,
+}
+
+/// 在本地编辑一行输入（不发送到远程），只支持退格
+#[derive(Default)]
+pub struct LineInput {
+    buffer: Vec<u8>,
+}
+
+impl LineInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 处理一个输入字节
+    pub fn process(&mut self, byte: u8) -> LineEvent {
+        match byte {
+            b'\r' | b'\n' => LineEvent::Done(String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned()),
+            0x03 => {
+                self.buffer.clear();
+                LineEvent::Cancel
+            }
+            0x7f | 0x08 => {
+                // 删除整个 UTF-8 字符
+                while self.buffer.pop().is_some_and(|b| b & 0xc0 == 0x80) {}
+                LineEvent::Echo(b"\x08 \x08".to_vec())
+            }
+            b if b < 0x20 => LineEvent::Echo(Vec::new()),
+            b => {
+                self.buffer.push(b);
+                LineEvent::Echo(vec![b])
+            }
+        }
+    }
+}
+
+/// 行首转义序列解析（与 OpenSSH 的 `~` 转义相同）
+///
+/// 只有在行首输入的转义字符才会被识别，其他位置的按原样发送。
+pub struct EscapeParser {
+    escape: u8,
+    at_line_start: bool,
+    pending: bool,
+}
+
+impl EscapeParser {
+    pub fn new(escape: u8) -> Self {
+        Self {
+            escape,
+            at_line_start: true,
+            pending: false,
+        }
+    }
+
+    /// 处理一个输入字节
+    pub fn process(&mut self, byte: u8) -> EscapeAction {
+        if self.pending {
+            self.pending = false;
+            match byte {
+                b'.' => return EscapeAction::Disconnect,
+                CTRL_Z => return EscapeAction::Suspend,
+                b'?' => return EscapeAction::Help,
+                b'u' => return EscapeAction::Transfer(Direction::Upload),
+                b'g' => return EscapeAction::Transfer(Direction::Download),
+                // 连续两个转义字符发送一个
+                b if b == self.escape => {
+                    self.at_line_start = false;
+                    return EscapeAction::Send(vec![self.escape]);
+                }
+                // 不是转义序列，补发暂存的转义字符
+                _ => {
+                    self.at_line_start = is_line_end(byte);
+                    return EscapeAction::Send(vec![self.escape, byte]);
+                }
+            }
+        }
+
+        if self.at_line_start && byte == self.escape {
+            self.pending = true;
+            return EscapeAction::Send(Vec::new());
+        }
+
+        self.at_line_start = is_line_end(byte);
+        EscapeAction::Send(vec![byte])
+    }
+
+    /// 帮助文本（原始模式下使用 `\r\n` 换行）
+    pub fn help(&self) -> String {
+        let c = self.escape as char;
+        format!(
+            "\r\n支持的转义序列:\r\n  {c}.  断开连接\r\n  {c}^Z 挂起到本地 shell\r\n  {c}?  显示此帮助\r\n  {c}u  上传文件：{c}u 本地路径 [远程路径]\r\n  {c}g  下载文件：{c}g 远程路径 [本地路径]\r\n  {c}{c}  发送 {c}\r\n（只在行首输入时生效）\r\n"
+        )
+    }
+}
+
+fn is_line_end(byte: u8) -> bool {
+    byte == b'\r' || byte == b'\n'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(parser: &mut EscapeParser, input: &[u8]) -> Vec<EscapeAction> {
+        input.iter().map(|&b| parser.process(b)).collect()
+    }
+
+    #[test]
+    fn test_escape_at_line_start() {
+        let mut parser = EscapeParser::new(DEFAULT_ESCAPE);
+        let actions = feed(&mut parser, b"~\x1a");
+        assert_eq!(actions[1], EscapeAction::Suspend);
+
+        let actions = feed(&mut parser, b"ls\r~.");
+        assert_eq!(actions[3], EscapeAction::Send(Vec::new()));
+        assert_eq!(actions[4], EscapeAction::Disconnect);
+    }
+
+    #[test]
+    fn test_escape_passthrough() {
+        let mut parser = EscapeParser::new(DEFAULT_ESCAPE);
+        // 行中间的 ~ 直接发送
+        assert_eq!(feed(&mut parser, b"a~.")[1], EscapeAction::Send(vec![b'~']));
+
+        // ~~ 发送一个 ~，其他字符补发 ~
+        let mut parser = EscapeParser::new(DEFAULT_ESCAPE);
+        assert_eq!(feed(&mut parser, b"~~")[1], EscapeAction::Send(vec![b'~']));
+        let mut parser = EscapeParser::new(DEFAULT_ESCAPE);
+        assert_eq!(feed(&mut parser, b"~/")[1], EscapeAction::Send(vec![b'~', b'/']));
+    }
+
+    #[test]
+    fn test_custom_escape_char() {
+        assert_eq!(parse_escape_char("~").unwrap(), Some(b'~'));
+        assert_eq!(parse_escape_char("none").unwrap(), None);
+        assert!(parse_escape_char("~~").is_err());
+        assert!(parse_escape_char(" ").is_err());
+
+        let mut parser = EscapeParser::new(b'%');
+        assert_eq!(feed(&mut parser, b"~.")[1], EscapeAction::Send(vec![b'.']));
+        assert_eq!(feed(&mut parser, b"\r%.")[2], EscapeAction::Disconnect);
+        assert!(parser.help().contains("%."));
+    }
+
+    #[test]
+    fn test_transfer_escape() {
+        let mut parser = EscapeParser::new(DEFAULT_ESCAPE);
+        assert_eq!(feed(&mut parser, b"~u")[1], EscapeAction::Transfer(Direction::Upload));
+        assert_eq!(feed(&mut parser, b"\r~g")[2], EscapeAction::Transfer(Direction::Download));
+        // 行中间的 ~u 原样发送
+        assert_eq!(feed(&mut parser, b"a~u")[1], EscapeAction::Send(vec![b'~']));
+
+        let request = TransferRequest::parse(Direction::Upload, "  'my notes.txt'  /tmp/ ").unwrap();
+        assert_eq!(request.source, "my notes.txt");
+        assert_eq!(request.target.as_deref(), Some("/tmp/"));
+        let request = TransferRequest::parse(Direction::Download, "app.log").unwrap();
+        assert_eq!(request.target, None);
+        assert!(TransferRequest::parse(Direction::Download, "   ").is_err());
+        assert!(TransferRequest::parse(Direction::Download, "a b c").is_err());
+        assert!(TransferRequest::parse(Direction::Download, "\"a b").is_err());
+    }
+
+    #[test]
+    fn test_line_input() {
+        let mut line = LineInput::new();
+        for &b in "日志x".as_bytes() {
+            assert!(matches!(line.process(b), LineEvent::Echo(_)));
+        }
+        line.process(0x7f);
+        line.process(0x7f);
+        line.process(b'a');
+        assert_eq!(line.process(b'\r'), LineEvent::Done("日a".to_string()));
+
+        line.process(b'b');
+        assert_eq!(line.process(0x03), LineEvent::Cancel);
+        assert_eq!(line.process(b'\r'), LineEvent::Done(String::new()));
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use log::info;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::session::RemoteSession;
+use crate::sftp::{self, SftpClient, SkipExisting, TransferStats};
+use crate::ssh::SshConfig;
+
+/// 一个目标主机（连接参数已解析，密码已在开始前询问）
+pub struct FanoutTarget {
+    /// 命令行中的目标名称
+    pub name: String,
+    pub config: SshConfig,
+    pub jump: Option<SshConfig>,
+    /// 该主机上的远程路径（书签按各自的连接解析）
+    pub remote_path: String,
+    /// 该主机上相对路径的基准目录（连接后通过 realpath 解析）
+    pub remote_cwd: Option<String>,
+}
+
+/// 上传选项
+#[derive(Debug, Clone, Default)]
+pub struct FanoutOptions {
+    pub create_dirs: bool,
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub skip_existing: SkipExisting,
+    pub keepalive: Option<u32>,
+}
+
+/// 单个主机的结果
+#[derive(Debug, Serialize)]
+pub struct HostReport {
+    pub target: String,
+    pub remote_path: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<TransferStats>,
+}
+
+/// 全部主机的结果，`total` 的耗时为整体耗时，吞吐量按所有主机合计
+#[derive(Debug, Serialize)]
+pub struct FanoutReport {
+    pub hosts: Vec<HostReport>,
+    pub total: TransferStats,
+}
+
+impl FanoutReport {
+    pub fn failed(&self) -> usize {
+        self.hosts.iter().filter(|h| !h.ok).count()
+    }
+}
+
+/// 把同一个本地文件上传到所有目标，同时最多进行 `concurrency` 个连接
+///
+/// 单个主机失败不影响其他主机，结果按目标的原始顺序返回。
+pub fn upload(
+    targets: Vec<FanoutTarget>,
+    local_path: &str,
+    options: &FanoutOptions,
+    concurrency: usize,
+    cancel: Arc<AtomicBool>,
+) -> FanoutReport {
+    let started = Instant::now();
+    let count = targets.len();
+    let queue = Mutex::new(targets.into_iter().enumerate().collect::<VecDeque<_>>());
+    let results: Mutex<Vec<Option<HostReport>>> = Mutex::new((0..count).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, count.max(1)) {
+            scope.spawn(|| loop {
+                let Some((index, target)) = queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front() else {
+                    break;
+                };
+                info!("开始上传到 {}", target.name);
+                let (remote_path, result) = match upload_one(&target, local_path, options, Arc::clone(&cancel)) {
+                    Ok((remote_path, stats)) => (remote_path, Ok(stats)),
+                    Err(e) => (target.remote_path, Err(e)),
+                };
+                let report = HostReport {
+                    target: target.name,
+                    remote_path,
+                    ok: result.is_ok(),
+                    error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                    stats: result.ok(),
+                };
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(report);
+            });
+        }
+    });
+
+    let hosts: Vec<HostReport> = results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut total = TransferStats::default();
+    for stats in hosts.iter().filter_map(|h| h.stats.as_ref()) {
+        total.merge(stats);
+    }
+    total.elapsed = started.elapsed();
+    FanoutReport { hosts, total }
+}
+
+/// 上传到一个主机，返回实际的远程路径和传输统计
+fn upload_one(
+    target: &FanoutTarget,
+    local_path: &str,
+    options: &FanoutOptions,
+    cancel: Arc<AtomicBool>,
+) -> Result<(String, TransferStats)> {
+    let mut session = RemoteSession::connect(target.config.clone(), target.jump.clone())?;
+    session.set_keepalive(options.keepalive);
+    let sftp = SftpClient::new(session.client())?
+        .with_cancel_flag(cancel)
+        .with_file_mode(options.file_mode)
+        .with_dir_mode(options.dir_mode)
+        .with_skip_existing(options.skip_existing);
+    let cwd = match target.remote_cwd {
+        Some(ref cwd) => Some(sftp.realpath(&sftp.resolve_path(cwd, None)?)?),
+        None => None,
+    };
+    let remote_path = sftp.resolve_path(&target.remote_path, cwd.as_deref())?;
+    if options.create_dirs {
+        if let Some(parent) = sftp::remote_parent(&remote_path) {
+            sftp.mkdir_p(parent)?;
+        }
+    }
+    let stats = sftp.upload_file(local_path, &remote_path, None)?;
+    Ok((remote_path, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::AuthMethod;
+    use crate::test_server::{TestServer, TEST_PASSWORD, TEST_USER};
+
+    fn target(server: &TestServer, name: &str, password: &str) -> FanoutTarget {
+        FanoutTarget {
+            name: name.to_string(),
+            config: SshConfig {
+                host: "127.0.0.1".to_string(),
+                port: server.port,
+                username: TEST_USER.to_string(),
+                auth: AuthMethod::Password(password.to_string()),
+            },
+            jump: None,
+            remote_path: "/opt/app/app.tar.gz".to_string(),
+            remote_cwd: None,
+        }
+    }
+
+    #[test]
+    fn test_upload_to_all_targets() {
+        let servers = [TestServer::start(), TestServer::start()];
+        let local = tempfile::tempdir().unwrap();
+        let artifact = local.path().join("app.tar.gz");
+        std::fs::write(&artifact, vec![9u8; 4096]).unwrap();
+
+        let targets = vec![
+            target(&servers[0], "web1", TEST_PASSWORD),
+            target(&servers[1], "web2", "wrong"),
+            target(&servers[1], "web3", TEST_PASSWORD),
+        ];
+        let options = FanoutOptions { create_dirs: true, ..Default::default() };
+        let report = upload(targets, artifact.to_str().unwrap(), &options, 2, Arc::new(AtomicBool::new(false)));
+
+        let names: Vec<&str> = report.hosts.iter().map(|h| h.target.as_str()).collect();
+        assert_eq!(names, vec!["web1", "web2", "web3"]);
+        assert!(report.hosts[0].ok && report.hosts[2].ok);
+        assert!(!report.hosts[1].ok && report.hosts[1].error.is_some());
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.total.files_transferred, 2);
+        assert_eq!(report.total.total_bytes, 8192);
+        for server in &servers {
+            assert_eq!(std::fs::read(server.local_path("/opt/app/app.tar.gz")).unwrap().len(), 4096);
+        }
+
+        // 相对路径放到各自的工作目录下，报告中为规范化后的路径
+        let mut relative = target(&servers[0], "web1", TEST_PASSWORD);
+        relative.remote_path = "../releases/app.tar.gz".to_string();
+        relative.remote_cwd = Some("opt/app".to_string());
+        let report = upload(vec![relative], artifact.to_str().unwrap(), &options, 1, Arc::new(AtomicBool::new(false)));
+        assert_eq!(report.hosts[0].remote_path, "/opt/releases/app.tar.gz");
+        assert!(servers[0].local_path("/opt/releases/app.tar.gz").is_file());
+    }
+}
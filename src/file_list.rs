@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::Path;
+
+/// 读取文件列表，`-` 表示标准输入
+///
+/// 默认按行分隔（忽略空行和行尾的 `\r`），`null` 为 true 时按 NUL 分隔（配合 `find -print0`）。
+pub fn read(source: &str, null: bool) -> Result<Vec<String>> {
+    let mut content = Vec::new();
+    if source == "-" {
+        std::io::stdin()
+            .read_to_end(&mut content)
+            .context("无法从标准输入读取文件列表")?;
+    } else {
+        content = std::fs::read(source).context(format!("无法读取文件列表: {}", source))?;
+    }
+    Ok(parse(&content, null))
+}
+
+fn parse(content: &[u8], null: bool) -> Vec<String> {
+    let separator = if null { b'\0' } else { b'\n' };
+    content
+        .split(|b| *b == separator)
+        .map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            if null {
+                entry.into_owned()
+            } else {
+                entry.trim_end_matches('\r').to_string()
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// 列表中的文件在目标目录下的相对路径
+///
+/// `relative` 为 true 时保留列表中的子路径（去掉开头的 `/` 和 `./`），否则只保留文件名。
+pub fn destination(entry: &str, relative: bool) -> Result<String> {
+    if relative {
+        let mut path = entry.trim_start_matches('/');
+        while let Some(rest) = path.strip_prefix("./") {
+            path = rest.trim_start_matches('/');
+        }
+        if path.split('/').any(|c| c == "..") {
+            anyhow::bail!("路径中不能包含 ..: {}", entry);
+        }
+        if !path.is_empty() {
+            return Ok(path.to_string());
+        }
+    }
+
+    Path::new(entry.trim_end_matches('/'))
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .context(format!("无法确定文件名: {}", entry))
+}
+
+/// 拼接目标目录和相对路径
+pub fn join(dir: &str, relative: &str) -> String {
+    if dir.is_empty() {
+        return relative.to_string();
+    }
+    format!("{}/{}", dir.trim_end_matches('/'), relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_separators() {
+        assert_eq!(parse(b"a.so\r\nlib/b.so\n\n", false), vec!["a.so", "lib/b.so"]);
+        assert_eq!(parse(b"with space.so\0line\nbreak.so\0", true), vec!["with space.so", "line\nbreak.so"]);
+        assert!(parse(b"", false).is_empty());
+    }
+
+    #[test]
+    fn test_destination() {
+        assert_eq!(destination("build/lib/a.so", false).unwrap(), "a.so");
+        assert_eq!(destination("./build/lib/a.so", true).unwrap(), "build/lib/a.so");
+        assert_eq!(destination("/var/log/app.log", true).unwrap(), "var/log/app.log");
+        assert!(destination("../secret", true).is_err());
+        assert_eq!(join("/opt/libs/", "lib/a.so"), "/opt/libs/lib/a.so");
+    }
+}
@@ -0,0 +1,148 @@
+//! 输出中使用的符号
+//!
+//! 启动时根据终端能力选择 Unicode 符号（✓、🔒 等）或 ASCII 替代（`[ok]`、`[locked]` 等）：标准输出
+//! 不是终端（重定向到文件、管道）、`TERM=dumb` 或 Linux 控制台、区域设置不是 UTF-8、Windows 控制台
+//! 不支持虚拟终端处理时使用 ASCII。`--ascii`/`--unicode` 可以强制指定。颜色同样按终端能力和
+//! `NO_COLOR` 决定。所有带符号的输出都从这里取符号，不在各处单独判断。
+
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// 本次运行的输出风格，未初始化时（测试中）使用 Unicode 和颜色
+static STYLE: OnceLock<OutputStyle> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputStyle {
+    pub unicode: bool,
+    pub color: bool,
+}
+
+impl OutputStyle {
+    const FULL: OutputStyle = OutputStyle { unicode: true, color: true };
+
+    /// 按环境变量、标准输出是否为终端和虚拟终端支持（`vt`）判断
+    fn detect(env: impl Fn(&str) -> Option<String>, tty: bool, vt: bool) -> Self {
+        let term = env("TERM").unwrap_or_default();
+        let capable = tty && vt && term != "dumb";
+        let no_color = env("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self {
+            unicode: capable && term != "linux" && utf8_locale(&env),
+            color: capable && !no_color,
+        }
+    }
+}
+
+/// 区域设置是否为 UTF-8（Windows 控制台不看区域设置）
+fn utf8_locale(env: impl Fn(&str) -> Option<String>) -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| env(name).filter(|v| !v.is_empty()))
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+/// 检测终端能力并设置输出风格，`unicode` 为 `--ascii`/`--unicode` 指定的值；在任何输出之前调用一次
+pub fn init(unicode: Option<bool>, vt: bool) {
+    let mut style = OutputStyle::detect(|name| std::env::var(name).ok(), std::io::stdout().is_terminal(), vt);
+    if let Some(unicode) = unicode {
+        style.unicode = unicode;
+    }
+    if !style.color {
+        colored::control::set_override(false);
+    }
+    let _ = STYLE.set(style);
+}
+
+pub fn output_style() -> OutputStyle {
+    STYLE.get().copied().unwrap_or(OutputStyle::FULL)
+}
+
+fn pick(unicode: &'static str, ascii: &'static str) -> &'static str {
+    if output_style().unicode {
+        unicode
+    } else {
+        ascii
+    }
+}
+
+/// 成功
+pub fn ok() -> &'static str {
+    pick("✓", "[ok]")
+}
+
+/// 失败
+pub fn fail() -> &'static str {
+    pick("✗", "[x]")
+}
+
+/// 警告
+pub fn warn() -> &'static str {
+    pick("⚠", "[!]")
+}
+
+/// 进行中的步骤、提示
+pub fn arrow() -> &'static str {
+    pick("→", "->")
+}
+
+/// 标签中的分隔点，例如 `[主密码 • 用途]`
+pub fn bullet() -> &'static str {
+    pick("•", "-")
+}
+
+/// 截断文本的结尾
+pub fn ellipsis() -> &'static str {
+    pick("…", "...")
+}
+
+/// 连接已保存密码
+pub fn locked() -> &'static str {
+    pick("🔒", "[locked]")
+}
+
+/// 连接未保存密码
+pub fn unlocked() -> &'static str {
+    pick("🔓", "[open]")
+}
+
+/// 列表中标记已保存密码的连接（ASCII 下不用 `*`，它已表示默认连接）
+pub fn key() -> &'static str {
+    pick("🔑", "[key]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)], tty: bool, vt: bool) -> OutputStyle {
+        OutputStyle::detect(
+            |name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string()),
+            tty,
+            vt,
+        )
+    }
+
+    #[test]
+    fn test_detect() {
+        let utf8 = [("TERM", "xterm-256color"), ("LANG", "zh_CN.UTF-8")];
+        assert_eq!(detect(&utf8, true, true), OutputStyle { unicode: true, color: true });
+        // 重定向到文件、Windows 旧控制台
+        assert_eq!(detect(&utf8, false, true), OutputStyle { unicode: false, color: false });
+        assert_eq!(detect(&utf8, true, false), OutputStyle { unicode: false, color: false });
+        assert_eq!(detect(&[("TERM", "dumb"), ("LANG", "en_US.UTF-8")], true, true), OutputStyle { unicode: false, color: false });
+        assert!(!detect(&[("TERM", "linux"), ("LANG", "en_US.UTF-8")], true, true).unicode);
+        assert!(!detect(&[("TERM", "xterm"), ("LANG", "en_US.UTF-8"), ("NO_COLOR", "1")], true, true).color);
+        #[cfg(unix)]
+        {
+            assert!(!detect(&[("TERM", "xterm"), ("LANG", "C")], true, true).unicode);
+            // LC_ALL 优先于 LANG
+            assert!(!detect(&[("TERM", "xterm"), ("LC_ALL", "POSIX"), ("LANG", "en_US.UTF-8")], true, true).unicode);
+            assert!(detect(&[("TERM", "xterm"), ("LC_CTYPE", "en_US.utf8")], true, true).unicode);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use regex::bytes::{Regex, RegexBuilder};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::remote_os::shell_quote;
+use crate::sftp::SftpClient;
+use crate::ssh::{self, SshClient};
+
+/// 判断二进制文件时检查的开头字节数（与 GNU grep 一样，含 NUL 即视为二进制）
+const BINARY_PROBE: usize = 8192;
+
+/// 搜索选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrepOptions {
+    pub recursive: bool,
+    pub ignore_case: bool,
+    /// 只列出包含匹配的文件名
+    pub files_with_matches: bool,
+    /// 同时搜索二进制文件
+    pub binary: bool,
+}
+
+/// 一条搜索结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hit {
+    /// 匹配的行（行号从 1 开始）
+    Line { path: String, number: u64, text: String },
+    /// `--files-with-matches` 模式下包含匹配的文件
+    File(String),
+}
+
+/// 在远程路径中搜索，返回结果数
+///
+/// 服务器提供 shell 和 grep 时直接在远程执行 `grep -E`，否则（仅 SFTP 的服务器）逐个文件
+/// 流式读取并在本地匹配，文件内容不会写入磁盘。两种方式都使用扩展正则语法。
+pub fn search(
+    client: &SshClient,
+    sftp: &SftpClient,
+    pattern: &str,
+    remote_path: &str,
+    options: &GrepOptions,
+    on_hit: &mut dyn FnMut(Hit),
+) -> Result<u64> {
+    let regex = build_regex(pattern, options)?;
+    if client.remote_info().is_unix() && has_remote_grep(client) {
+        search_exec(client, pattern, remote_path, options, on_hit)
+    } else {
+        debug!("服务器没有可用的 grep，改为通过 SFTP 搜索");
+        search_sftp(sftp, &regex, remote_path, options, on_hit)
+    }
+}
+
+fn build_regex(pattern: &str, options: &GrepOptions) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .context(format!("无效的正则表达式: {}", pattern))
+}
+
+/// 服务器能否执行 grep（限制为 internal-sftp 的服务器会拒绝 exec 或输出其他内容）
+fn has_remote_grep(client: &SshClient) -> bool {
+    match client.exec_command_timeout("command -v grep >/dev/null 2>&1 && echo ok", Some(ssh::PROBE_TIMEOUT)) {
+        Ok((output, _, 0)) => output.trim_ascii() == b"ok",
+        _ => false,
+    }
+}
+
+fn grep_command(pattern: &str, remote_path: &str, options: &GrepOptions) -> String {
+    let mut command = String::from("grep -E -n -H");
+    if options.recursive {
+        command.push_str(" -r");
+    }
+    if options.ignore_case {
+        command.push_str(" -i");
+    }
+    if options.files_with_matches {
+        command.push_str(" -l");
+    }
+    command.push_str(if options.binary { " -a" } else { " -I" });
+    format!("{} -e {} -- {}", command, shell_quote(pattern), shell_quote(remote_path))
+}
+
+/// 通过 exec 在远程执行 grep
+pub fn search_exec(
+    client: &SshClient,
+    pattern: &str,
+    remote_path: &str,
+    options: &GrepOptions,
+    on_hit: &mut dyn FnMut(Hit),
+) -> Result<u64> {
+    let (output, stderr, status) = client.exec_command_output(&grep_command(pattern, remote_path, options))?;
+    let mut hits = 0;
+    for line in output.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+        let line = String::from_utf8_lossy(line);
+        let hit = if options.files_with_matches {
+            Some(Hit::File(line.into_owned()))
+        } else {
+            parse_line(&line)
+        };
+        if let Some(hit) = hit {
+            hits += 1;
+            on_hit(hit);
+        }
+    }
+
+    // 退出码 1 表示没有匹配；2 表示出错，有部分结果时（如个别文件无权限）只给出警告
+    if status > 1 {
+        let message = stderr.trim();
+        if hits == 0 {
+            anyhow::bail!("远程 grep 失败（退出码 {}）: {}", status, message);
+        }
+        warn!("远程 grep 部分失败: {}", message);
+    }
+    Ok(hits)
+}
+
+/// 解析 `path:line:text`，路径中含冒号时取第一个 `:数字:` 分隔
+fn parse_line(line: &str) -> Option<Hit> {
+    let mut start = 0;
+    while let Some(offset) = line[start..].find(':') {
+        let colon = start + offset;
+        let rest = &line[colon + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 && rest.as_bytes().get(digits) == Some(&b':') {
+            return Some(Hit::Line {
+                path: line[..colon].to_string(),
+                number: rest[..digits].parse().ok()?,
+                text: rest[digits + 1..].to_string(),
+            });
+        }
+        start = colon + 1;
+    }
+    None
+}
+
+/// 通过 SFTP 逐个读取文件并在本地匹配
+pub fn search_sftp(
+    sftp: &SftpClient,
+    regex: &Regex,
+    remote_path: &str,
+    options: &GrepOptions,
+    on_hit: &mut dyn FnMut(Hit),
+) -> Result<u64> {
+    let info = sftp.stat(remote_path)?;
+    if !info.is_dir {
+        return grep_file(sftp, regex, Path::new(remote_path), options, on_hit);
+    }
+    if !options.recursive {
+        anyhow::bail!("远程路径是目录: {}（使用 --recursive 搜索目录）", remote_path);
+    }
+
+    let mut hits = 0;
+    let mut pending = vec![remote_path.to_string()];
+    while let Some(dir) = pending.pop() {
+        let entries = match sftp.list_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("{:#}", e);
+                continue;
+            }
+        };
+        // list_dir 已按名称排序，逆序入栈使子目录按名称顺序处理
+        for entry in entries.iter().rev().filter(|e| e.is_dir && !e.is_symlink) {
+            pending.push(entry.path.clone());
+        }
+        // 与 grep -r 一样不跟随目录中的符号链接
+        for entry in entries.iter().filter(|e| !e.is_dir && !e.is_symlink) {
+            match grep_file(sftp, regex, &entry.raw_path, options, on_hit) {
+                Ok(n) => hits += n,
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// 流式读取单个文件并匹配，二进制文件在未指定 `binary` 时跳过
+fn grep_file(
+    sftp: &SftpClient,
+    regex: &Regex,
+    path: &Path,
+    options: &GrepOptions,
+    on_hit: &mut dyn FnMut(Hit),
+) -> Result<u64> {
+    let display = path.to_string_lossy();
+    let mut reader = BufReader::with_capacity(64 * 1024, sftp.open_reader(path)?);
+
+    if !options.binary {
+        let head = reader.fill_buf().context(format!("读取远程文件失败: {}", display))?;
+        if head[..head.len().min(BINARY_PROBE)].contains(&0) {
+            debug!("跳过二进制文件: {}", display);
+            return Ok(0);
+        }
+    }
+
+    let mut hits = 0;
+    let mut number = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let n = reader
+            .read_until(b'\n', &mut line)
+            .context(format!("读取远程文件失败: {}", display))?;
+        if n == 0 {
+            break;
+        }
+        number += 1;
+        let text = line.strip_suffix(b"\n").unwrap_or(&line);
+        if !regex.is_match(text) {
+            continue;
+        }
+        hits += 1;
+        if options.files_with_matches {
+            // 只需要知道是否匹配，不再读取剩余内容
+            on_hit(Hit::File(display.into_owned()));
+            break;
+        }
+        on_hit(Hit::Line {
+            path: display.to_string(),
+            number,
+            text: String::from_utf8_lossy(text).into_owned(),
+        });
+    }
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+
+    fn populate(server: &TestServer) {
+        std::fs::create_dir_all(server.local_path("/logs/old")).unwrap();
+        std::fs::write(server.local_path("/logs/app.log"), "start\nERROR: it's broken\nok\n").unwrap();
+        std::fs::write(server.local_path("/logs/old/a:1.log"), "error again").unwrap();
+        std::fs::write(server.local_path("/logs/core.bin"), b"\0\x01error\n").unwrap();
+    }
+
+    fn collect(run: impl FnOnce(&mut dyn FnMut(Hit)) -> Result<u64>) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let count = run(&mut |hit| hits.push(hit)).unwrap();
+        assert_eq!(count as usize, hits.len());
+        hits
+    }
+
+    fn line(path: &str, number: u64, text: &str) -> Hit {
+        Hit::Line { path: path.to_string(), number, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("a.log:12:x: 1:2"), Some(line("a.log", 12, "x: 1:2")));
+        assert_eq!(parse_line("old/a:1.log:3:"), Some(line("old/a:1.log", 3, "")));
+        assert_eq!(parse_line("no match"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_search_exec() {
+        let server = TestServer::start();
+        let client = server.connect();
+        populate(&server);
+        assert!(has_remote_grep(&client));
+
+        // 模式中的引号和 shell 元字符原样传给 grep
+        let options = GrepOptions { ignore_case: true, ..Default::default() };
+        let hits = collect(|f| search_exec(&client, "it's|; false", "logs/app.log", &options, f));
+        assert_eq!(hits, vec![line("logs/app.log", 2, "ERROR: it's broken")]);
+
+        let options = GrepOptions { recursive: true, ignore_case: true, files_with_matches: true, binary: false };
+        let mut hits = collect(|f| search_exec(&client, "error", "logs", &options, f));
+        hits.sort_by_key(|hit| format!("{:?}", hit));
+        assert_eq!(hits, vec![Hit::File("logs/app.log".into()), Hit::File("logs/old/a:1.log".into())]);
+
+        assert!(search_exec(&client, "x", "missing", &GrepOptions::default(), &mut |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_search_sftp() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        populate(&server);
+
+        let options = GrepOptions { recursive: true, ..Default::default() };
+        let regex = build_regex("error", &options).unwrap();
+        let hits = collect(|f| search_sftp(&sftp, &regex, "/logs", &options, f));
+        assert_eq!(hits, vec![line("/logs/old/a:1.log", 1, "error again")]);
+
+        let options = GrepOptions { recursive: true, ignore_case: true, binary: true, ..Default::default() };
+        let regex = build_regex("error", &options).unwrap();
+        let hits = collect(|f| search_sftp(&sftp, &regex, "/logs", &options, f));
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0], line("/logs/app.log", 2, "ERROR: it's broken"));
+
+        let options = GrepOptions { files_with_matches: true, ignore_case: true, ..Default::default() };
+        let regex = build_regex("error", &options).unwrap();
+        let hits = collect(|f| search_sftp(&sftp, &regex, "/logs/app.log", &options, f));
+        assert_eq!(hits, vec![Hit::File("/logs/app.log".into())]);
+
+        let err = search_sftp(&sftp, &regex, "/logs", &options, &mut |_| {}).unwrap_err();
+        assert!(err.to_string().contains("--recursive"));
+        assert!(build_regex("(", &options).is_err());
+    }
+}
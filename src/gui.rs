@@ -1,13 +1,93 @@
 use eframe::egui;
 use std::sync::{Arc, Mutex};
-use crate::config::{AppConfig, SavedConnection};
+use std::time::{Duration, Instant};
+use crate::config::{self, ConfigStore, GuiSettings, GuiTheme, SavedConnection};
 use crate::crypto::CryptoManager;
+use crate::import::{self, ImportCandidate, ImportSummary};
+use crate::log_buffer::{self, LogEntry};
+use crate::openssh_config::OpenSshConfig;
+
+/// 字号滑块的范围
+const FONT_SIZE_RANGE: std::ops::RangeInclusive<f32> = 10.0..=24.0;
+
+/// 提示消息的显示时间
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+/// “最近”中显示的会话数量
+const RECENT_LIMIT: usize = 5;
+
+/// 从 GUI 发起的会话
+#[derive(Debug, Clone)]
+struct RecentSession {
+    /// 显示名称（保存的连接名称或 user@host:port）
+    name: String,
+    /// 重新连接时使用的命令行参数
+    args: Vec<String>,
+    started: chrono::DateTime<chrono::Local>,
+    /// 启动终端失败时的错误
+    error: Option<String>,
+}
+
+/// 日志窗口打开时的刷新间隔
+const LOG_REFRESH: Duration = Duration::from_millis(500);
+
+/// 日志级别对应的颜色
+fn level_color(level: log::Level) -> egui::Color32 {
+    match level {
+        log::Level::Error => egui::Color32::RED,
+        log::Level::Warn => egui::Color32::YELLOW,
+        log::Level::Info => egui::Color32::GREEN,
+        log::Level::Debug | log::Level::Trace => egui::Color32::GRAY,
+    }
+}
+
+/// 导出/导入连接对话框
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransferMode {
+    Export,
+    Import,
+}
+
+/// 标签颜色对应的 egui 颜色，无法识别时返回 None
+fn label_color(color: Option<&str>) -> Option<egui::Color32> {
+    let [r, g, b] = config::label_rgb(color?)?;
+    Some(egui::Color32::from_rgb(r, g, b))
+}
+
+/// 绘制颜色色块，返回其响应（用于点击选择）
+fn color_chip(ui: &mut egui::Ui, color: Option<egui::Color32>, size: egui::Vec2, selected: bool) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+    let painter = ui.painter();
+    match color {
+        Some(color) => {
+            painter.rect_filled(rect, 2.0, color);
+        }
+        None => {
+            painter.rect_stroke(rect, 2.0, ui.visuals().widgets.noninteractive.bg_stroke);
+        }
+    }
+    if selected {
+        painter.rect_stroke(rect.expand(2.0), 3.0, ui.visuals().selection.stroke);
+    }
+    response
+}
+
+/// 导出/导入文件的默认路径
+fn default_transfer_path() -> String {
+    dirs::home_dir()
+        .unwrap_or_default()
+        .join("rust-ssh-sftp-connections.toml")
+        .display()
+        .to_string()
+}
 
 pub fn run_gui() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([800.0, 600.0])
             .with_title("Rust SSH/SFTP Client"),
+        // 让 frame.info().system_theme 随系统变化，“跟随系统”主题据此切换
+        follow_system_theme: true,
         ..Default::default()
     };
     
@@ -15,15 +95,54 @@ pub fn run_gui() -> Result<(), eframe::Error> {
         "Rust SSH/SFTP Client",
         options,
         Box::new(|cc| {
-            // 设置中文字体
-            setup_custom_fonts(&cc.egui_ctx);
-            Box::new(SshGuiApp::new())
+            let app = SshGuiApp::new();
+            // 设置中文字体和保存的字号，主题在每一帧中应用
+            if let Err(e) = setup_custom_fonts(&cc.egui_ctx, app.settings.monospace_font.as_deref()) {
+                eprintln!("警告: {}", e);
+            }
+            apply_font_size(&cc.egui_ctx, app.settings.font_size);
+            Box::new(app)
         }),
     )
 }
 
-/// 设置自定义字体以支持中文
-fn setup_custom_fonts(ctx: &egui::Context) {
+/// 正文的默认字号
+fn default_font_size() -> f32 {
+    egui::Style::default()
+        .text_styles
+        .get(&egui::TextStyle::Body)
+        .map_or(12.5, |font| font.size)
+}
+
+/// 按正文字号等比例缩放所有文字样式，None 时恢复默认大小
+fn apply_font_size(ctx: &egui::Context, font_size: Option<f32>) {
+    let defaults = egui::Style::default().text_styles;
+    let scale = font_size.map_or(1.0, |size| size / default_font_size());
+    let mut style = (*ctx.style()).clone();
+    for (text_style, font) in style.text_styles.iter_mut() {
+        if let Some(default) = defaults.get(text_style) {
+            font.size = default.size * scale;
+        }
+    }
+    ctx.set_style(style);
+}
+
+/// 应用主题；跟随系统但系统主题未知时保持当前外观
+fn apply_theme(ctx: &egui::Context, theme: GuiTheme, system: Option<eframe::Theme>) {
+    let dark = match (theme, system) {
+        (GuiTheme::Light, _) | (GuiTheme::System, Some(eframe::Theme::Light)) => false,
+        (GuiTheme::Dark, _) | (GuiTheme::System, Some(eframe::Theme::Dark)) => true,
+        (GuiTheme::System, None) => return,
+    };
+    if ctx.style().visuals.dark_mode != dark {
+        ctx.set_visuals(if dark { egui::Visuals::dark() } else { egui::Visuals::light() });
+    }
+}
+
+/// 设置自定义字体以支持中文，`monospace_font` 为等宽字体文件路径
+///
+/// 等宽字体无法读取时返回错误，此时等宽文字仍使用中文字体。
+fn setup_custom_fonts(ctx: &egui::Context, monospace_font: Option<&str>) -> Result<(), String> {
     let mut fonts = egui::FontDefinitions::default();
     
     // 尝试加载系统中文字体
@@ -64,11 +183,32 @@ fn setup_custom_fonts(ctx: &egui::Context) {
         eprintln!("警告: 无法加载中文字体，中文可能无法正确显示");
     }
     
+    // 自定义等宽字体排在最前，缺少的字形（如中文）回退到中文字体
+    let result = match monospace_font.filter(|path| !path.trim().is_empty()) {
+        Some(path) => match std::fs::read(path.trim()) {
+            Ok(font_data) => {
+                fonts.font_data.insert(
+                    "monospace_font".to_owned(),
+                    egui::FontData::from_owned(font_data),
+                );
+                fonts
+                    .families
+                    .entry(egui::FontFamily::Monospace)
+                    .or_default()
+                    .insert(0, "monospace_font".to_owned());
+                Ok(())
+            }
+            Err(e) => Err(format!("无法读取等宽字体 {}: {}", path, e)),
+        },
+        None => Ok(()),
+    };
+    
     ctx.set_fonts(fonts);
+    result
 }
 
 struct SshGuiApp {
-    config: Arc<Mutex<AppConfig>>,
+    config: Arc<Mutex<ConfigStore>>,
     selected_connection: Option<String>,
     
     // New connection form
@@ -80,6 +220,43 @@ struct SshGuiApp {
     new_conn_password: String,
     new_conn_save_password: bool,
     
+    // Quick connect
+    quick_target: String,
+    quick_save: bool,
+    quick_save_password: bool,
+    quick_error: String,
+    
+    // Appearance settings
+    settings: GuiSettings,
+    show_settings: bool,
+    /// 打开设置对话框时的设置，取消时恢复
+    settings_before: GuiSettings,
+    settings_error: String,
+    monospace_input: String,
+    
+    // Import / export
+    /// 从 ssh config 读取的候选连接及是否勾选
+    ssh_import: Option<Vec<(ImportCandidate, bool)>>,
+    transfer_mode: Option<TransferMode>,
+    transfer_path: String,
+    export_include_secrets: bool,
+    transfer_error: String,
+    toast: Option<(String, Instant)>,
+    
+    // Edit connection (note / color)
+    edit_connection: Option<String>,
+    edit_note: String,
+    edit_color: Option<String>,
+    
+    // Recent sessions
+    recent: Vec<RecentSession>,
+    
+    // Log viewer
+    show_logs: bool,
+    /// 只显示该级别及更严重的日志
+    log_level: log::Level,
+    log_search: String,
+    
     // Master password
     master_password: String,
     show_master_password_dialog: bool,
@@ -95,14 +272,49 @@ struct SshGuiApp {
 impl SshGuiApp {
     /// 创建新的 GUI 应用实例，自动加载配置
     fn new() -> Self {
-        let config = AppConfig::load().unwrap_or_default();
+        let config = ConfigStore::load().unwrap_or_default();
         let status_message = if config.list_connections().is_empty() {
             String::new()
         } else {
             format!("已加载 {} 个连接", config.list_connections().len())
         };
         
+        let settings = config.gui.clone();
+        
+        // 上次运行的会话只知道时间，结果未知
+        let recent = config
+            .recent_connections(RECENT_LIMIT)
+            .into_iter()
+            .filter_map(|conn| {
+                let started = chrono::DateTime::from_timestamp(conn.last_used? as i64, 0)?;
+                Some(RecentSession {
+                    name: conn.name.clone(),
+                    args: connect_args(&conn.name),
+                    started: started.with_timezone(&chrono::Local),
+                    error: None,
+                })
+            })
+            .collect();
+        
         Self {
+            recent,
+            settings_before: settings.clone(),
+            settings,
+            show_settings: false,
+            settings_error: String::new(),
+            monospace_input: String::new(),
+            ssh_import: None,
+            transfer_mode: None,
+            transfer_path: default_transfer_path(),
+            export_include_secrets: false,
+            transfer_error: String::new(),
+            toast: None,
+            edit_connection: None,
+            edit_note: String::new(),
+            edit_color: None,
+            show_logs: false,
+            log_level: log::Level::Info,
+            log_search: String::new(),
             config: Arc::new(Mutex::new(config)),
             selected_connection: None,
             show_new_connection: false,
@@ -112,6 +324,10 @@ impl SshGuiApp {
             new_conn_username: String::new(),
             new_conn_password: String::new(),
             new_conn_save_password: false,
+            quick_target: String::new(),
+            quick_save: false,
+            quick_save_password: false,
+            quick_error: String::new(),
             master_password: String::new(),
             show_master_password_dialog: false,
             status_message,
@@ -121,26 +337,32 @@ impl SshGuiApp {
     }
     
     fn load_config(&mut self) {
-        match AppConfig::load() {
-            Ok(config) => {
-                *self.config.lock().unwrap() = config;
+        let result = self.config.lock().unwrap().reload();
+        match result {
+            Ok(()) => {
                 self.status_message = "配置加载成功".to_string();
             }
             Err(e) => {
-                self.error_message = format!("加载配置失败: {}", e);
+                self.set_error(format!("加载配置失败: {}", e));
             }
         }
     }
     
     fn save_config(&mut self) {
-        let config = self.config.lock().unwrap();
-        if let Err(e) = config.save() {
-            self.error_message = format!("保存配置失败: {}", e);
+        let result = self.config.lock().unwrap().save();
+        if let Err(e) = result {
+            self.set_error(format!("保存配置失败: {}", e));
         } else {
             self.status_message = "配置保存成功".to_string();
         }
     }
     
+    /// 显示错误并写入日志（日志窗口中可以看到完整记录）
+    fn set_error(&mut self, message: String) {
+        log::error!("{}", message);
+        self.error_message = message;
+    }
+    
     fn add_new_connection(&mut self) {
         // Validate inputs
         if self.new_conn_name.is_empty() || self.new_conn_host.is_empty() 
@@ -172,13 +394,13 @@ impl SshGuiApp {
                             )
                         }
                         Err(e) => {
-                            self.error_message = format!("加密密码失败: {}", e);
+                            self.set_error(format!("加密密码失败: {}", e));
                             return;
                         }
                     }
                 }
                 Err(e) => {
-                    self.error_message = format!("创建加密管理器失败: {}", e);
+                    self.set_error(format!("创建加密管理器失败: {}", e));
                     return;
                 }
             }
@@ -209,12 +431,495 @@ impl SshGuiApp {
         self.status_message = "连接添加成功".to_string();
     }
     
-    fn delete_connection(&mut self, name: &str) {
+    /// 快速连接：解析 `user@host[:port]` 后打开终端连接
+    ///
+    /// 保存由终端中的 `connect --save-as`/`--save-password` 在连接成功后完成，与命令行行为一致。
+    fn quick_connect(&mut self) {
+        self.quick_error.clear();
+        let (username, host, port) = match parse_quick_target(&self.quick_target) {
+            Ok(target) => target,
+            Err(e) => {
+                self.quick_error = e;
+                return;
+            }
+        };
+        
+        let mut args = vec![
+            "connect".to_string(),
+            format!("{}@{}", username, host),
+            "-p".to_string(),
+            port.to_string(),
+            "-I".to_string(),
+        ];
+        let name = if self.quick_save {
+            let name = format!("{}@{}", username, host);
+            args.extend(["--save-as".to_string(), name.clone()]);
+            if self.quick_save_password {
+                args.push("--save-password".to_string());
+            }
+            name
+        } else {
+            format!("{}@{}:{}", username, host, port)
+        };
+        
+        if self.start_session(name, args) {
+            self.quick_target.clear();
+        }
+    }
+    
+    fn open_settings(&mut self) {
+        self.settings_before = self.settings.clone();
+        self.settings_error.clear();
+        self.monospace_input = self.settings.monospace_font.clone().unwrap_or_default();
+        self.show_settings = true;
+    }
+    
+    /// 重新加载字体（等宽字体路径变化后）
+    fn reload_fonts(&mut self, ctx: &egui::Context) {
+        self.settings_error = setup_custom_fonts(ctx, self.settings.monospace_font.as_deref())
+            .err()
+            .unwrap_or_default();
+    }
+    
+    fn save_settings(&mut self) {
+        self.config.lock().unwrap().gui = self.settings.clone();
+        self.save_config();
+        self.show_settings = false;
+    }
+    
+    /// 放弃修改，恢复打开对话框前的设置
+    fn cancel_settings(&mut self, ctx: &egui::Context) {
+        let monospace_changed = self.settings.monospace_font != self.settings_before.monospace_font;
+        self.settings = self.settings_before.clone();
+        apply_font_size(ctx, self.settings.font_size);
+        if monospace_changed {
+            self.reload_fonts(ctx);
+        }
+        self.show_settings = false;
+    }
+    
+    fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("设置")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("主题:");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.settings.theme, GuiTheme::System, "跟随系统");
+                    ui.radio_value(&mut self.settings.theme, GuiTheme::Light, "浅色");
+                    ui.radio_value(&mut self.settings.theme, GuiTheme::Dark, "深色");
+                });
+                
+                ui.label("字号:");
+                ui.horizontal(|ui| {
+                    let mut size = self.settings.font_size.unwrap_or_else(default_font_size);
+                    if ui.add(egui::Slider::new(&mut size, FONT_SIZE_RANGE).step_by(0.5)).changed() {
+                        self.settings.font_size = Some(size);
+                        apply_font_size(ctx, self.settings.font_size);
+                    }
+                    if ui.button("默认").clicked() {
+                        self.settings.font_size = None;
+                        apply_font_size(ctx, None);
+                    }
+                });
+                
+                ui.label("等宽字体文件（终端使用，留空则使用中文字体）:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.monospace_input).hint_text("/path/to/font.ttf"));
+                    if ui.button("应用").clicked() {
+                        let path = self.monospace_input.trim();
+                        self.settings.monospace_font = (!path.is_empty()).then(|| path.to_string());
+                        self.reload_fonts(ctx);
+                    }
+                });
+                ui.label(egui::RichText::new("示例: 0123456789 ABC abc 中文").monospace());
+                if !self.settings_error.is_empty() {
+                    ui.label(egui::RichText::new(&self.settings_error).color(egui::Color32::RED));
+                }
+                
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    if ui.button("保存").clicked() {
+                        self.save_settings();
+                    }
+                    if ui.button("取消").clicked() {
+                        self.cancel_settings(ctx);
+                    }
+                });
+            });
+        if !open {
+            self.cancel_settings(ctx);
+        }
+    }
+    
+    /// 读取 ~/.ssh/config，打开导入对话框（已存在的连接默认不勾选）
+    fn open_ssh_import(&mut self) {
+        let ssh_config = match OpenSshConfig::load() {
+            Ok(ssh_config) => ssh_config,
+            Err(e) => {
+                self.set_error(format!("读取 ssh config 失败: {:#}", e));
+                return;
+            }
+        };
+        let candidates = import::ssh_config_candidates(&ssh_config, &self.config.lock().unwrap());
+        if candidates.is_empty() {
+            self.status_message = "~/.ssh/config 中没有可导入的主机".to_string();
+            return;
+        }
+        self.ssh_import = Some(candidates.into_iter().map(|c| {
+            let checked = !c.conflict;
+            (c, checked)
+        }).collect());
+    }
+    
+    /// 导入勾选的主机，勾选了已存在的连接表示覆盖
+    fn import_ssh_selected(&mut self) {
+        let Some(candidates) = self.ssh_import.take() else {
+            return;
+        };
+        let selected = candidates.into_iter().filter(|(_, checked)| *checked).map(|(c, _)| c.connection);
+        let summary = import::apply(&mut self.config.lock().unwrap(), selected, true);
+        self.finish_import(summary);
+    }
+    
+    /// 保存配置并显示导入结果
+    fn finish_import(&mut self, summary: ImportSummary) {
+        if summary.added > 0 {
+            self.save_config();
+        }
+        if let Some((name, reason)) = summary.errors.first() {
+            self.set_error(format!("导入 '{}' 失败: {}", name, reason));
+        }
+        self.show_toast(format!("导入完成: {}", summary.describe()));
+    }
+    
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+    
+    fn open_transfer(&mut self, mode: TransferMode) {
+        self.transfer_mode = Some(mode);
+        self.transfer_error.clear();
+        self.export_include_secrets = false;
+    }
+    
+    /// 导出连接；包含密文时需要能解密已保存密码的主密码
+    fn export_connections(&mut self) -> Result<(), String> {
+        let config = self.config.lock().unwrap().clone();
+        if self.export_include_secrets {
+            if let Some(encrypted) = config.any_ciphertext() {
+                let unlocked = CryptoManager::new(&self.master_password)
+                    .map(|crypto| crypto.decrypt(encrypted).is_ok())
+                    .unwrap_or(false);
+                if !unlocked {
+                    return Err("主密码错误，无法导出密文".to_string());
+                }
+            }
+        }
+        let content = import::export(&config, self.export_include_secrets).map_err(|e| format!("{:#}", e))?;
+        let path = self.transfer_path.trim();
+        std::fs::write(path, content).map_err(|e| format!("无法写入文件 {}: {}", path, e))?;
+        self.show_toast(format!("已导出 {} 个连接到 {}", config.connections.len(), path));
+        Ok(())
+    }
+    
+    /// 从导出文件导入连接，已存在的同名连接跳过
+    fn import_connections(&mut self) -> Result<(), String> {
+        let path = self.transfer_path.trim();
+        let content = std::fs::read_to_string(path).map_err(|e| format!("无法读取文件 {}: {}", path, e))?;
+        let connections = import::read_export(&content).map_err(|e| format!("{:#}", e))?;
+        let summary = import::apply(&mut self.config.lock().unwrap(), connections, false);
+        self.finish_import(summary);
+        Ok(())
+    }
+    
+    fn ssh_import_window(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut import = false;
+        let Some(candidates) = self.ssh_import.as_mut() else {
+            return;
+        };
+        egui::Window::new("从 ssh config 导入")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("勾选要导入的主机（黄色表示已存在同名连接，勾选后覆盖）:");
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (candidate, checked) in candidates.iter_mut() {
+                        let conn = &candidate.connection;
+                        ui.horizontal(|ui| {
+                            let name = egui::RichText::new(&conn.name);
+                            let name = if candidate.conflict { name.color(egui::Color32::YELLOW) } else { name };
+                            ui.checkbox(checked, name);
+                            ui.label(format!("{}@{}:{}", conn.username, conn.host, conn.port));
+                            if let Some(ref key) = conn.private_key_path {
+                                ui.label(format!("🔑 {}", key));
+                            }
+                            if candidate.conflict {
+                                ui.label(egui::RichText::new("已存在").color(egui::Color32::YELLOW));
+                            }
+                        });
+                    }
+                });
+                
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    if ui.button("全选").clicked() {
+                        candidates.iter_mut().for_each(|(_, checked)| *checked = true);
+                    }
+                    if ui.button("全不选").clicked() {
+                        candidates.iter_mut().for_each(|(_, checked)| *checked = false);
+                    }
+                    let count = candidates.iter().filter(|(_, checked)| *checked).count();
+                    if ui.add_enabled(count > 0, egui::Button::new(format!("导入 {} 个", count))).clicked() {
+                        import = true;
+                    }
+                });
+            });
+        if import {
+            self.import_ssh_selected();
+        } else if !open {
+            self.ssh_import = None;
+        }
+    }
+    
+    fn transfer_window(&mut self, ctx: &egui::Context) {
+        let Some(mode) = self.transfer_mode else {
+            return;
+        };
+        let title = match mode {
+            TransferMode::Export => "导出连接",
+            TransferMode::Import => "导入连接",
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("文件路径:");
+                ui.add(egui::TextEdit::singleline(&mut self.transfer_path).desired_width(360.0));
+                
+                match mode {
+                    TransferMode::Export => {
+                        ui.checkbox(&mut self.export_include_secrets, "包含加密的密码（需要主密码）");
+                        if self.export_include_secrets {
+                            ui.label("主密码:");
+                            ui.add(egui::TextEdit::singleline(&mut self.master_password).password(true));
+                            ui.label(egui::RichText::new("密文只能在使用相同主密码和 salt 文件的环境中解密").weak());
+                        }
+                    }
+                    TransferMode::Import => {
+                        ui.label(egui::RichText::new("已存在的同名连接会被跳过").weak());
+                    }
+                }
+                if !self.transfer_error.is_empty() {
+                    ui.label(egui::RichText::new(&self.transfer_error).color(egui::Color32::RED));
+                }
+                
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    let label = match mode {
+                        TransferMode::Export => "导出",
+                        TransferMode::Import => "导入",
+                    };
+                    if ui.button(label).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+        
+        if confirmed {
+            let result = match mode {
+                TransferMode::Export => self.export_connections(),
+                TransferMode::Import => self.import_connections(),
+            };
+            match result {
+                Ok(()) => self.transfer_mode = None,
+                Err(e) => self.transfer_error = e,
+            }
+        } else if !open || cancelled {
+            self.transfer_mode = None;
+        }
+    }
+    
+    /// 在窗口右下角显示提示消息，超时后消失
+    fn toast_area(&mut self, ctx: &egui::Context) {
+        let Some((message, shown)) = &self.toast else {
+            return;
+        };
+        let elapsed = shown.elapsed();
+        if elapsed >= TOAST_DURATION {
+            self.toast = None;
+            return;
+        }
+        egui::Area::new(egui::Id::new("toast"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-16.0, -40.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(message.as_str());
+                });
+            });
+        ctx.request_repaint_after(TOAST_DURATION - elapsed);
+    }
+    
+    fn open_edit(&mut self, name: &str) {
+        let config = self.config.lock().unwrap();
+        let Some(conn) = config.get_connection(name) else {
+            return;
+        };
+        self.edit_note = conn.note.clone().unwrap_or_default();
+        self.edit_color = conn.color.clone();
+        drop(config);
+        self.edit_connection = Some(name.to_string());
+    }
+    
+    fn save_edit(&mut self) {
+        let Some(name) = self.edit_connection.take() else {
+            return;
+        };
         let mut config = self.config.lock().unwrap();
-        if let Err(e) = config.remove_connection(name) {
-            self.error_message = format!("删除连接失败: {}", e);
+        if let Some(conn) = config.connections.get_mut(&name) {
+            let note = self.edit_note.trim();
+            conn.note = (!note.is_empty()).then(|| note.to_string());
+            conn.color = self.edit_color.clone();
+        }
+        drop(config);
+        self.save_config();
+        self.status_message = format!("连接 '{}' 已更新", name);
+    }
+    
+    fn edit_window(&mut self, ctx: &egui::Context) {
+        let Some(name) = self.edit_connection.clone() else {
+            return;
+        };
+        let mut open = true;
+        let mut save = false;
+        let mut cancelled = false;
+        egui::Window::new(format!("编辑连接 - {}", name))
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("备注:");
+                ui.add(egui::TextEdit::multiline(&mut self.edit_note).desired_rows(3));
+                
+                ui.label("颜色:");
+                ui.horizontal(|ui| {
+                    let size = egui::vec2(18.0, 18.0);
+                    if color_chip(ui, None, size, self.edit_color.is_none()).on_hover_text("无").clicked() {
+                        self.edit_color = None;
+                    }
+                    for (color_name, _) in config::LABEL_COLORS {
+                        let selected = self.edit_color.as_deref() == Some(*color_name);
+                        if color_chip(ui, label_color(Some(color_name)), size, selected)
+                            .on_hover_text(*color_name)
+                            .clicked()
+                        {
+                            self.edit_color = Some(color_name.to_string());
+                        }
+                    }
+                });
+                
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    if ui.button("保存").clicked() {
+                        save = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+        if save {
+            self.save_edit();
+        } else if !open || cancelled {
+            self.edit_connection = None;
+        }
+    }
+    
+    /// 按级别和搜索词过滤日志（搜索不区分大小写）
+    fn filtered_logs(&self) -> Vec<LogEntry> {
+        let search = self.log_search.trim().to_lowercase();
+        log_buffer::snapshot()
+            .into_iter()
+            .filter(|entry| entry.level <= self.log_level)
+            .filter(|entry| {
+                search.is_empty()
+                    || entry.message.to_lowercase().contains(&search)
+                    || entry.target.to_lowercase().contains(&search)
+            })
+            .collect()
+    }
+    
+    fn log_window(&mut self, ctx: &egui::Context) {
+        let entries = self.filtered_logs();
+        let mut open = true;
+        egui::Window::new("日志")
+            .default_size([720.0, 400.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("级别:");
+                    egui::ComboBox::from_id_source("log_level")
+                        .selected_text(self.log_level.as_str())
+                        .show_ui(ui, |ui| {
+                            for level in [log::Level::Error, log::Level::Warn, log::Level::Info, log::Level::Debug] {
+                                ui.selectable_value(&mut self.log_level, level, level.as_str());
+                            }
+                        });
+                    ui.label("搜索:");
+                    ui.add(egui::TextEdit::singleline(&mut self.log_search).desired_width(200.0));
+                    if ui.button("复制全部").clicked() {
+                        let text: Vec<String> = entries.iter().map(LogEntry::line).collect();
+                        ui.output_mut(|o| o.copied_text = text.join("\n"));
+                        self.show_toast(format!("已复制 {} 条日志", entries.len()));
+                    }
+                    if ui.button("清空").clicked() {
+                        log_buffer::clear();
+                    }
+                });
+                ui.label(egui::RichText::new(format!("共 {} 条（最多保留 {} 条）", entries.len(), log_buffer::CAPACITY)).weak());
+                
+                ui.separator();
+                
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, entries.len(), |ui, range| {
+                        for entry in &entries[range] {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(entry.time.format("%H:%M:%S%.3f").to_string()).monospace().weak());
+                                ui.label(egui::RichText::new(format!("{:<5}", entry.level)).monospace().color(level_color(entry.level)));
+                                ui.label(egui::RichText::new(&entry.message).monospace())
+                                    .on_hover_text(&entry.target);
+                            });
+                        }
+                    });
+            });
+        if !open {
+            self.show_logs = false;
+        }
+        ctx.request_repaint_after(LOG_REFRESH);
+    }
+    
+    fn delete_connection(&mut self, name: &str) {
+        let result = self.config.lock().unwrap().remove_connection(name);
+        if let Err(e) = result {
+            self.set_error(format!("删除连接失败: {}", e));
         } else {
-            drop(config);
             self.save_config();
             self.status_message = format!("连接 '{}' 已删除", name);
             if self.selected_connection.as_deref() == Some(name) {
@@ -224,26 +929,82 @@ impl SshGuiApp {
     }
     
     fn connect_to_selected(&mut self) {
-        if let Some(conn_name) = &self.selected_connection {
+        if let Some(conn_name) = self.selected_connection.clone() {
             self.status_message = format!("正在打开终端连接到 '{}'...", conn_name);
             self.connecting = true;
             
             // 启动新的终端窗口进行连接
-            match self.launch_terminal_connection(conn_name) {
-                Ok(_) => {
-                    self.status_message = format!("已启动终端连接到 '{}'", conn_name);
-                }
-                Err(e) => {
-                    self.error_message = format!("启动终端失败: {}", e);
-                }
-            }
+            self.start_session(conn_name.clone(), connect_args(&conn_name));
             
             self.connecting = false;
         }
     }
     
-    /// 启动新的终端窗口并执行SSH连接
-    fn launch_terminal_connection(&self, conn_name: &str) -> Result<(), String> {
+    /// 在新终端中启动会话并记录到“最近”，保存的连接同时记录使用时间；返回是否启动成功
+    fn start_session(&mut self, name: String, args: Vec<String>) -> bool {
+        let result = self.launch_terminal_connection(&args);
+        let started = chrono::Local::now();
+        
+        self.recent.retain(|session| session.name != name);
+        self.recent.insert(0, RecentSession {
+            name: name.clone(),
+            args,
+            started,
+            error: result.as_ref().err().cloned(),
+        });
+        self.recent.truncate(RECENT_LIMIT);
+        
+        match result {
+            Ok(()) => {
+                let saved = {
+                    let mut config = self.config.lock().unwrap();
+                    let saved = config.get_connection(&name).is_some();
+                    config.touch(&name, started.timestamp() as u64);
+                    saved
+                };
+                if saved {
+                    self.save_config();
+                }
+                self.status_message = format!("已启动终端连接到 '{}'", name);
+                true
+            }
+            Err(e) => {
+                self.set_error(format!("启动终端失败: {}", e));
+                false
+            }
+        }
+    }
+    
+    /// “最近”列表：点击名称重新连接，失败的会话悬停显示错误并提供重试
+    fn recent_sessions(&mut self, ui: &mut egui::Ui) {
+        let mut reconnect: Option<RecentSession> = None;
+        for session in &self.recent {
+            ui.horizontal(|ui| {
+                let button = ui.button(&session.name).on_hover_text("重新连接");
+                if button.clicked() {
+                    reconnect = Some(session.clone());
+                }
+                ui.label(egui::RichText::new(format_started(session.started)).weak());
+                match session.error {
+                    Some(ref error) => {
+                        ui.label(egui::RichText::new("✗ 失败").color(egui::Color32::RED)).on_hover_text(error);
+                        if ui.button("重试").clicked() {
+                            reconnect = Some(session.clone());
+                        }
+                    }
+                    None => {
+                        ui.label(egui::RichText::new("✓").color(egui::Color32::GREEN));
+                    }
+                }
+            });
+        }
+        if let Some(session) = reconnect {
+            self.start_session(session.name, session.args);
+        }
+    }
+    
+    /// 启动新的终端窗口并以 `args` 为参数运行本程序
+    fn launch_terminal_connection(&self, args: &[String]) -> Result<(), String> {
         use std::process::Command;
         
         // 获取当前可执行文件的路径
@@ -251,9 +1012,8 @@ impl SshGuiApp {
             .map_err(|e| format!("无法获取可执行文件路径: {}", e))?;
         
         // 构建连接命令
-        let connect_cmd = format!("{} connect {} -I", 
-            exe_path.display(), 
-            conn_name);
+        let connect_cmd = format!("{} {}", exe_path.display(), args.join(" "));
+        log::info!("启动终端: {}", connect_cmd);
         
         // 在Windows上启动新的终端窗口
         #[cfg(target_os = "windows")]
@@ -278,17 +1038,19 @@ impl SshGuiApp {
         #[cfg(target_os = "linux")]
         {
             // 尝试使用常见的终端模拟器
+            let shell_cmd = format!("{}; exec bash", connect_cmd);
             let terminals = vec![
-                ("gnome-terminal", vec!["--", "bash", "-c", &format!("{}; exec bash", connect_cmd)]),
-                ("konsole", vec!["-e", "bash", "-c", &format!("{}; exec bash", connect_cmd)]),
-                ("xterm", vec!["-e", "bash", "-c", &format!("{}; exec bash", connect_cmd)]),
+                ("gnome-terminal", vec!["--", "bash", "-c", &shell_cmd]),
+                ("konsole", vec!["-e", "bash", "-c", &shell_cmd]),
+                ("xterm", vec!["-e", "bash", "-c", &shell_cmd]),
             ];
             
             let mut launched = false;
             for (terminal, args) in terminals {
-                if let Ok(_) = Command::new(terminal)
+                if Command::new(terminal)
                     .args(&args)
-                    .spawn() {
+                    .spawn()
+                    .is_ok() {
                     launched = true;
                     break;
                 }
@@ -303,13 +1065,8 @@ impl SshGuiApp {
         #[cfg(target_os = "macos")]
         {
             Command::new("open")
-                .args(&[
-                    "-a", "Terminal",
-                    &exe_path.to_string_lossy().to_string(),
-                    "connect",
-                    conn_name,
-                    "-I"
-                ])
+                .args(["-a", "Terminal", &exe_path.to_string_lossy()])
+                .args(args)
                 .spawn()
                 .map_err(|e| format!("启动终端失败: {}", e))?;
         }
@@ -319,7 +1076,9 @@ impl SshGuiApp {
 }
 
 impl eframe::App for SshGuiApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        apply_theme(ctx, self.settings.theme, frame.info().system_theme);
+        
         // Top panel
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
@@ -333,6 +1092,28 @@ impl eframe::App for SshGuiApp {
                         ui.close_menu();
                     }
                     ui.separator();
+                    if ui.button("从 ssh config 导入…").clicked() {
+                        self.open_ssh_import();
+                        ui.close_menu();
+                    }
+                    if ui.button("导出连接…").clicked() {
+                        self.open_transfer(TransferMode::Export);
+                        ui.close_menu();
+                    }
+                    if ui.button("导入连接…").clicked() {
+                        self.open_transfer(TransferMode::Import);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("设置").clicked() {
+                        self.open_settings();
+                        ui.close_menu();
+                    }
+                    if ui.button("查看日志").clicked() {
+                        self.show_logs = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("退出").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
@@ -345,6 +1126,25 @@ impl eframe::App for SshGuiApp {
                     }
                 });
             });
+            
+            // Quick connect
+            ui.horizontal(|ui| {
+                ui.label("快速连接:");
+                let target = ui.add(
+                    egui::TextEdit::singleline(&mut self.quick_target)
+                        .hint_text("user@host[:port]")
+                        .desired_width(200.0),
+                );
+                ui.checkbox(&mut self.quick_save, "保存为连接");
+                ui.add_enabled(self.quick_save, egui::Checkbox::new(&mut self.quick_save_password, "保存密码"));
+                let submitted = target.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("连接").clicked() || submitted {
+                    self.quick_connect();
+                }
+            });
+            if !self.quick_error.is_empty() {
+                ui.label(egui::RichText::new(&self.quick_error).color(egui::Color32::RED));
+            }
         });
         
         // Bottom panel for status
@@ -364,6 +1164,12 @@ impl eframe::App for SshGuiApp {
             ui.heading("SSH 连接管理");
             ui.separator();
             
+            if !self.recent.is_empty() {
+                ui.label(egui::RichText::new("最近").strong());
+                self.recent_sessions(ui);
+                ui.separator();
+            }
+            
             // Connection list
             ui.horizontal(|ui| {
                 ui.label("已保存的连接:");
@@ -375,41 +1181,50 @@ impl eframe::App for SshGuiApp {
             ui.separator();
 
             // Collect connection data first to avoid borrow issues
-            let connections_data: Vec<(String, String, String, u16, bool)> = {
+            let connections_data: Vec<SavedConnection> = {
                 let config = self.config.lock().unwrap();
-                config.list_connections()
-                    .iter()
-                    .map(|conn| (
-                        conn.name.clone(),
-                        conn.username.clone(),
-                        conn.host.clone(),
-                        conn.port,
-                        conn.has_saved_password(),
-                    ))
-                    .collect()
+                config.list_connections().into_iter().cloned().collect()
             };
 
             let mut connection_to_delete: Option<String> = None;
+            let mut connection_to_edit: Option<String> = None;
 
             if connections_data.is_empty() {
                 ui.label("没有保存的连接");
             } else {
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    for (name, username, host, port, has_password) in &connections_data {
+                egui::ScrollArea::vertical().max_height((ui.available_height() - 80.0).max(100.0)).show(ui, |ui| {
+                    for conn in &connections_data {
                         ui.horizontal(|ui| {
+                            let name = &conn.name;
                             let is_selected = self.selected_connection.as_deref() == Some(name.as_str());
 
-                            if ui.selectable_label(is_selected, name).clicked() {
+                            // 左侧颜色条
+                            let height = ui.spacing().interact_size.y;
+                            let color = label_color(conn.color.as_deref()).unwrap_or(egui::Color32::TRANSPARENT);
+                            color_chip(ui, Some(color), egui::vec2(4.0, height), false);
+
+                            let label = ui.selectable_label(is_selected, name);
+                            let label = match conn.note {
+                                Some(ref note) => label.on_hover_text(note),
+                                None => label,
+                            };
+                            if label.clicked() {
                                 self.selected_connection = Some(name.clone());
                                 self.error_message.clear();
                             }
 
-                            ui.label(format!("{}@{}:{}", username, host, port));
+                            ui.label(format!("{}@{}:{}", conn.username, conn.host, conn.port));
 
-                            if *has_password {
+                            if conn.has_saved_password() {
                                 ui.label("🔒");
                             }
+                            if conn.note.is_some() {
+                                ui.label("📝");
+                            }
 
+                            if ui.button("✏").on_hover_text("编辑备注和颜色").clicked() {
+                                connection_to_edit = Some(name.clone());
+                            }
                             if ui.button("🗑").clicked() {
                                 connection_to_delete = Some(name.clone());
                             }
@@ -418,6 +1233,18 @@ impl eframe::App for SshGuiApp {
                 });
             }
 
+            // 选中连接的备注
+            let selected = self.selected_connection.as_deref()
+                .and_then(|name| connections_data.iter().find(|c| c.name == name));
+            if let Some(note) = selected.and_then(|c| c.note.as_deref()) {
+                ui.separator();
+                ui.label(egui::RichText::new(format!("备注: {}", note)).italics());
+            }
+
+            if let Some(name) = connection_to_edit {
+                self.open_edit(&name);
+            }
+
             // Delete connection if requested
             if let Some(name) = connection_to_delete {
                 self.delete_connection(&name);
@@ -435,6 +1262,17 @@ impl eframe::App for SshGuiApp {
             });
         });
         
+        if self.show_settings {
+            self.settings_window(ctx);
+        }
+        self.edit_window(ctx);
+        if self.show_logs {
+            self.log_window(ctx);
+        }
+        self.ssh_import_window(ctx);
+        self.transfer_window(ctx);
+        self.toast_area(ctx);
+        
         // New connection dialog
         if self.show_new_connection {
             egui::Window::new("新建连接")
@@ -478,3 +1316,65 @@ impl eframe::App for SshGuiApp {
     }
 }
 
+
+/// 在终端中连接保存的连接的参数
+fn connect_args(name: &str) -> Vec<String> {
+    vec!["connect".to_string(), name.to_string(), "-I".to_string()]
+}
+
+/// 会话开始时间：今天只显示时间，否则显示日期
+fn format_started(started: chrono::DateTime<chrono::Local>) -> String {
+    if started.date_naive() == chrono::Local::now().date_naive() {
+        started.format("%H:%M").to_string()
+    } else {
+        started.format("%m-%d %H:%M").to_string()
+    }
+}
+
+/// 解析快速连接的 `user@host[:port]`，IPv6 地址带端口时写成 `user@[::1]:2222`
+fn parse_quick_target(input: &str) -> Result<(String, String, u16), String> {
+    let input = input.trim();
+    let (username, rest) = match input.rsplit_once('@') {
+        Some((username, rest)) if !username.is_empty() => (username, rest),
+        _ => return Err("缺少用户名（格式: user@host[:port]）".to_string()),
+    };
+    
+    let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+        let (host, after) = bracketed.split_once(']').ok_or_else(|| format!("无效的主机地址: {}", rest))?;
+        (host, after.strip_prefix(':'))
+    } else if rest.matches(':').count() == 1 {
+        let (host, port) = rest.split_once(':').unwrap_or((rest, ""));
+        (host, Some(port))
+    } else {
+        (rest, None)
+    };
+    if host.is_empty() {
+        return Err("缺少主机地址（格式: user@host[:port]）".to_string());
+    }
+    
+    let port = match port {
+        Some(port) => port.parse::<u16>().ok().filter(|&p| p != 0).ok_or_else(|| format!("无效的端口: {}", port))?,
+        None => 22,
+    };
+    Ok((username.to_string(), host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_quick_target() {
+        let target = |user: &str, host: &str, port| Ok((user.to_string(), host.to_string(), port));
+        assert_eq!(parse_quick_target(" root@example.com "), target("root", "example.com", 22));
+        assert_eq!(parse_quick_target("deploy@10.0.0.5:2222"), target("deploy", "10.0.0.5", 2222));
+        assert_eq!(parse_quick_target("admin@[::1]:2200"), target("admin", "::1", 2200));
+        assert_eq!(parse_quick_target("admin@fe80::1"), target("admin", "fe80::1", 22));
+        
+        assert!(parse_quick_target("example.com").unwrap_err().contains("用户名"));
+        assert!(parse_quick_target("@example.com").unwrap_err().contains("用户名"));
+        assert!(parse_quick_target("root@").unwrap_err().contains("主机"));
+        assert!(parse_quick_target("root@host:ssh").unwrap_err().contains("端口"));
+        assert!(parse_quick_target("root@host:0").unwrap_err().contains("端口"));
+    }
+}
@@ -0,0 +1,220 @@
+//! 远程命令历史
+//!
+//! 按连接分别保存在配置目录的 `history/` 下，同一连接的多个会话共用一个文件：每次记录前重新读取
+//! 文件，因此其他会话刚执行的命令也能通过 `history`、`!N` 调出。SSH 命令行和 SFTP 命令行使用不同的
+//! 文件（`<连接>.shell`、`<连接>.sftp`）。文件新建时权限为 0600，超过 `[defaults] history_size`
+//! 条时丢弃最早的记录；匹配 `history_ignore` 的命令（默认为以空格开头或包含 "password"）不记录。
+
+use anyhow::{Context, Result};
+use log::warn;
+use regex::Regex;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+
+/// 未配置 `history_size` 时保留的条数
+const DEFAULT_SIZE: usize = 1000;
+
+/// 未配置 `history_ignore` 时不记录的命令
+const DEFAULT_IGNORE: &str = r"^\s|(?i)password";
+
+/// 历史记录的保存设置
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    /// 最多保留的条数，0 表示不记录
+    pub max_entries: usize,
+    /// 匹配的命令不记录
+    pub ignore: Regex,
+}
+
+impl HistoryOptions {
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let pattern = config.defaults.history_ignore.as_deref().unwrap_or(DEFAULT_IGNORE);
+        Ok(Self {
+            max_entries: config.defaults.history_size.unwrap_or(DEFAULT_SIZE),
+            ignore: Regex::new(pattern).context(format!("history_ignore 不是有效的正则表达式: {}", pattern))?,
+        })
+    }
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_SIZE,
+            ignore: Regex::new(DEFAULT_IGNORE).expect("默认的忽略规则有效"),
+        }
+    }
+}
+
+/// 一个连接在某种命令行中的历史
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+    options: HistoryOptions,
+}
+
+impl History {
+    /// 打开连接 `connection` 在 `scope`（"shell" 或 "sftp"）下的历史
+    pub fn open(connection: &str, scope: &str) -> Result<Self> {
+        let config = AppConfig::load()?;
+        let dir = AppConfig::config_path()?
+            .parent()
+            .context("无法获取配置目录")?
+            .join("history");
+        Ok(Self::load(dir.join(format!("{}.{}", file_stem(connection), scope)), HistoryOptions::from_config(&config)?))
+    }
+
+    /// 从指定文件读取，文件不存在时为空
+    pub fn load(path: PathBuf, options: HistoryOptions) -> Self {
+        let mut history = Self { path, entries: Vec::new(), options };
+        history.reload();
+        history
+    }
+
+    /// 重新读取文件，取得其他会话记录的命令
+    fn reload(&mut self) {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => self.entries = content.lines().filter(|l| !l.is_empty()).map(str::to_string).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.entries.clear(),
+            Err(e) => warn!("无法读取命令历史 {}: {}", self.path.display(), e),
+        }
+    }
+
+    /// 按编号排列的记录（编号从 1 开始，与 `!N` 对应）
+    pub fn entries(&mut self) -> &[String] {
+        self.reload();
+        &self.entries
+    }
+
+    /// 展开 `!!`（上一条）和 `!N`（第 N 条）；不是历史引用时返回 `None`
+    pub fn expand(&mut self, input: &str) -> Result<Option<String>> {
+        let Some(reference) = input.strip_prefix('!') else {
+            return Ok(None);
+        };
+        self.reload();
+        let entry = if reference == "!" {
+            self.entries.last().context("命令历史为空")?
+        } else {
+            let index: usize = reference.parse().context(format!("无效的历史引用: {}", input))?;
+            index
+                .checked_sub(1)
+                .and_then(|i| self.entries.get(i))
+                .context(format!("命令历史中没有第 {} 条", index))?
+        };
+        Ok(Some(entry.clone()))
+    }
+
+    /// 记录一条命令，写入失败只给出警告
+    pub fn record(&mut self, command: &str) {
+        if self.options.max_entries == 0 || command.trim().is_empty() || command.contains('\n') || self.options.ignore.is_match(command) {
+            return;
+        }
+        self.reload();
+        if self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+        self.entries.push(command.to_string());
+        let excess = self.entries.len().saturating_sub(self.options.max_entries);
+        self.entries.drain(..excess);
+        if let Err(e) = write(&self.path, &self.entries) {
+            warn!("无法保存命令历史 {}: {:#}", self.path.display(), e);
+        }
+    }
+}
+
+/// 连接名称中不能用作文件名的字符替换为 `_`
+fn file_stem(connection: &str) -> String {
+    connection
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '@') { c } else { '_' })
+        .collect()
+}
+
+fn write(path: &Path, entries: &[String]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    let mut content = entries.join("\n");
+    content.push('\n');
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(max_entries: usize) -> HistoryOptions {
+        HistoryOptions { max_entries, ..Default::default() }
+    }
+
+    #[test]
+    fn test_shared_between_sessions_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history").join("web.shell");
+        let mut first = History::load(path.clone(), options(3));
+        let mut second = History::load(path.clone(), options(3));
+
+        first.record("uptime");
+        second.record("df -h");
+        first.record("df -h");
+        first.record("ls /var/log");
+        second.record("free -m");
+        assert_eq!(first.entries(), ["df -h", "ls /var/log", "free -m"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_ignored_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load(dir.path().join("db.shell"), options(10));
+        history.record(" export TOKEN=abc");
+        history.record("mysql --password=secret");
+        history.record("echo PASSWORD");
+        history.record("whoami");
+        assert_eq!(history.entries(), ["whoami"]);
+
+        let mut disabled = History::load(dir.path().join("off.shell"), options(0));
+        disabled.record("whoami");
+        assert!(disabled.entries().is_empty());
+        assert!(!dir.path().join("off.shell").exists());
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load(dir.path().join("web.shell"), options(10));
+        assert!(history.expand("!!").is_err());
+        history.record("uptime");
+        history.record("df -h");
+
+        assert_eq!(history.expand("!!").unwrap().as_deref(), Some("df -h"));
+        assert_eq!(history.expand("!1").unwrap().as_deref(), Some("uptime"));
+        assert_eq!(history.expand("ls").unwrap(), None);
+        assert!(history.expand("!3").is_err());
+        assert!(history.expand("!0").is_err());
+        assert!(history.expand("!abc").is_err());
+    }
+
+    #[test]
+    fn test_file_stem() {
+        assert_eq!(file_stem("web-01"), "web-01");
+        assert_eq!(file_stem("root@10.0.0.1:2222"), "root@10.0.0.1_2222");
+        assert_eq!(file_stem("../etc/passwd"), ".._etc_passwd");
+    }
+}
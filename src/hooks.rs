@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::{info, warn};
+use std::process::Command;
+
+use crate::glyphs;
+
+/// 连接前钩子以非零退出码结束
+#[derive(Debug, thiserror::Error)]
+#[error("连接前钩子执行失败（退出码 {code}）")]
+pub struct HookFailed {
+    pub code: i32,
+}
+
+/// 钩子命令中可使用的模板变量
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+}
+
+impl HookContext {
+    /// 替换 `{host}`、`{port}`、`{name}`、`{username}`
+    pub fn render(&self, command: &str) -> String {
+        command
+            .replace("{host}", &self.host)
+            .replace("{port}", &self.port.to_string())
+            .replace("{name}", &self.name)
+            .replace("{username}", &self.username)
+    }
+}
+
+/// 用本地 shell（Windows 上为 cmd）执行命令的 `Command`
+pub(crate) fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// 用本地 shell 执行命令，输出直接显示在控制台，返回退出码
+fn run_shell(command: &str) -> Result<i32> {
+    let status = shell_command(command)
+        .status()
+        .context(format!("无法执行钩子命令: {}", command))?;
+    // 被信号终止时没有退出码
+    Ok(status.code().unwrap_or(1))
+}
+
+/// 执行连接前钩子，非零退出码时返回 `HookFailed`
+pub fn run_pre_connect(command: &str, context: &HookContext) -> Result<()> {
+    let command = context.render(command);
+    info!("执行连接前钩子: {}", command);
+    println!("{} 执行连接前钩子: {}", glyphs::arrow().cyan(), command);
+
+    let code = run_shell(&command)?;
+    if code != 0 {
+        return Err(HookFailed { code }.into());
+    }
+    Ok(())
+}
+
+/// 执行断开后钩子，失败时只输出警告
+pub fn run_post_disconnect(command: &str, context: &HookContext) {
+    let command = context.render(command);
+    info!("执行断开后钩子: {}", command);
+    println!("{} 执行断开后钩子: {}", glyphs::arrow().cyan(), command);
+
+    match run_shell(&command) {
+        Ok(0) => {}
+        Ok(code) => {
+            warn!("断开后钩子退出码: {}", code);
+            println!("{} 断开后钩子执行失败（退出码 {}）", glyphs::fail().red(), code);
+        }
+        Err(e) => {
+            warn!("{}", e);
+            println!("{} {}", glyphs::fail().red(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> HookContext {
+        HookContext {
+            name: "web".to_string(),
+            host: "example.com".to_string(),
+            port: 2222,
+            username: "deploy".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            context().render("vpn-up {name} && ping -c1 {host}:{port} # {username}"),
+            "vpn-up web && ping -c1 example.com:2222 # deploy"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pre_connect_exit_code() {
+        assert!(run_pre_connect("true", &context()).is_ok());
+
+        let err = run_pre_connect("exit 7", &context()).unwrap_err();
+        assert_eq!(err.downcast_ref::<HookFailed>().unwrap().code, 7);
+    }
+}
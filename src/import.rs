@@ -0,0 +1,600 @@
+use anyhow::{Context, Result};
+use colored::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::config::{AppConfig, SavedConnection};
+use crate::glyphs;
+use crate::openssh_config::OpenSshConfig;
+use crate::paths;
+use crate::table::{Align, Cell, Style, Table};
+
+/// 导出文件格式版本
+const EXPORT_VERSION: u32 = 1;
+
+/// 导出文件（TOML）
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportFile {
+    version: u32,
+    #[serde(default)]
+    connections: Vec<SavedConnection>,
+}
+
+/// 待导入的连接
+#[derive(Debug, Clone)]
+pub struct ImportCandidate {
+    pub connection: SavedConnection,
+    /// 已存在同名连接
+    pub conflict: bool,
+}
+
+/// 导入结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub skipped: usize,
+    /// 与已有连接相同、没有改写的连接
+    pub unchanged: usize,
+    pub failed: usize,
+    /// 失败的连接及原因
+    pub errors: Vec<(String, String)>,
+}
+
+impl ImportSummary {
+    pub fn describe(&self) -> String {
+        format!("新增 {}，跳过 {}，未变化 {}，失败 {}", self.added, self.skipped, self.unchanged, self.failed)
+    }
+}
+
+/// 把 ~/.ssh/config 中的主机别名转换为待导入的连接
+///
+/// 别名没有 User 时与 OpenSSH 一致使用本地用户名；有 IdentityFile 时使用公钥认证，否则使用密码认证。
+pub fn ssh_config_candidates(ssh_config: &OpenSshConfig, config: &AppConfig) -> Vec<ImportCandidate> {
+    let local_user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+    let connections = ssh_config.hosts().into_iter().map(|alias| {
+        let settings = ssh_config.resolve(&alias);
+        let host = settings.hostname.unwrap_or_else(|| alias.clone());
+        let port = settings.port.unwrap_or(22);
+        let user = settings.user.unwrap_or_else(|| local_user.clone());
+        match settings.identity_file {
+            Some(key) => SavedConnection::new_publickey(alias, host, port, user, key, None),
+            None => SavedConnection::new_password(alias, host, port, user),
+        }
+    });
+    candidates(connections, config)
+}
+
+/// PuTTY 保存的会话（注册表 `HKCU\Software\SimonTatham\PuTTY\Sessions` 下的一项）
+#[cfg(any(windows, test))]
+#[derive(Debug, Clone, Default)]
+pub struct PuttySession {
+    /// 注册表中的键名（PuTTY 把特殊字符编码为 `%XX`）
+    pub key: String,
+    /// ssh、telnet、serial、raw 等
+    pub protocol: String,
+    pub host: String,
+    pub port: u32,
+    pub user: String,
+    pub key_file: Option<String>,
+}
+
+/// PuTTY 会话的转换结果
+#[cfg(any(windows, test))]
+#[derive(Debug, Default)]
+pub struct PuttyImport {
+    pub candidates: Vec<ImportCandidate>,
+    /// 跳过的非 SSH 会话（串口、telnet 等）数量
+    pub skipped_protocols: usize,
+    /// 需要用户处理的问题，例如 .ppk 私钥需要转换
+    pub warnings: Vec<String>,
+}
+
+/// 把 PuTTY 会话转换为待导入的连接，连接名称为会话名称
+///
+/// 跳过 PuTTY 的 "Default Settings" 模板和非 SSH 会话。HostName 可以是 `user@host`；没有用户名时
+/// 使用本地用户名。PuTTY 的 .ppk 私钥不能直接使用，仍然导入并给出转换提示。
+#[cfg(any(windows, test))]
+pub fn putty_candidates(sessions: Vec<PuttySession>, config: &AppConfig) -> PuttyImport {
+    let local_user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+    let mut result = PuttyImport::default();
+    let mut connections = Vec::new();
+    for session in sessions {
+        let name = decode_putty_name(&session.key);
+        if name == "Default Settings" {
+            continue;
+        }
+        if !session.protocol.eq_ignore_ascii_case("ssh") {
+            result.skipped_protocols += 1;
+            continue;
+        }
+        let (user, host) = match session.host.split_once('@') {
+            Some((user, host)) => (user.to_string(), host.to_string()),
+            None => (session.user.clone(), session.host.clone()),
+        };
+        let user = if user.is_empty() { local_user.clone() } else { user };
+        let port = u16::try_from(session.port).unwrap_or(0);
+        connections.push(match session.key_file.filter(|key| !key.is_empty()) {
+            Some(key) => {
+                if key.to_ascii_lowercase().ends_with(".ppk") {
+                    result.warnings.push(format!(
+                        "{}: 私钥 {} 是 PuTTY 格式，请用 puttygen 转换为 OpenSSH 格式（Conversions > Export OpenSSH key）后用 config edit 修改路径",
+                        name, key
+                    ));
+                }
+                SavedConnection::new_publickey(name, host, port, user, key, None)
+            }
+            None => SavedConnection::new_password(name, host, port, user),
+        });
+    }
+    result.candidates = candidates(connections, config);
+    result
+}
+
+/// 还原 PuTTY 会话名称中的 `%XX` 编码
+#[cfg(any(windows, test))]
+fn decode_putty_name(key: &str) -> String {
+    let bytes = key.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                out.push(byte);
+                i += 3;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 从注册表读取 PuTTY 保存的会话，没有保存过会话时为空
+#[cfg(windows)]
+pub fn read_putty_sessions() -> Result<Vec<PuttySession>> {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    const SESSIONS: &str = r"Software\SimonTatham\PuTTY\Sessions";
+    let root = match RegKey::predef(HKEY_CURRENT_USER).open_subkey(SESSIONS) {
+        Ok(root) => root,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("无法读取注册表 HKCU\\{}", SESSIONS)),
+    };
+    let mut sessions = Vec::new();
+    for key in root.enum_keys() {
+        let key = key.context("无法枚举 PuTTY 会话")?;
+        let entry = root.open_subkey(&key).context(format!("无法读取 PuTTY 会话: {}", key))?;
+        let text = |name: &str| entry.get_value::<String, _>(name).unwrap_or_default();
+        sessions.push(PuttySession {
+            protocol: entry.get_value::<String, _>("Protocol").unwrap_or_else(|_| "ssh".to_string()),
+            host: text("HostName"),
+            port: entry.get_value::<u32, _>("PortNumber").unwrap_or(22),
+            user: text("UserName"),
+            key_file: Some(text("PublicKeyFile")).filter(|path| !path.is_empty()),
+            key,
+        });
+    }
+    Ok(sessions)
+}
+
+/// 读取导出文件中的连接
+pub fn read_export(content: &str) -> Result<Vec<SavedConnection>> {
+    let file: ExportFile = toml::from_str(content).context("无法解析导出文件")?;
+    if file.version > EXPORT_VERSION {
+        anyhow::bail!("不支持的导出文件版本: {}（当前支持 {}）", file.version, EXPORT_VERSION);
+    }
+    Ok(file.connections)
+}
+
+/// 标记与现有配置冲突的连接
+pub fn candidates(
+    connections: impl IntoIterator<Item = SavedConnection>,
+    config: &AppConfig,
+) -> Vec<ImportCandidate> {
+    connections
+        .into_iter()
+        .map(|connection| ImportCandidate {
+            conflict: config.get_connection(&connection.name).is_some(),
+            connection,
+        })
+        .collect()
+}
+
+/// 导入前与现有配置比较的字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub existing: String,
+    pub incoming: String,
+}
+
+/// 待导入连接的分类
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanStatus {
+    /// 没有同名连接
+    New,
+    /// 同名连接的主机、端口、用户名、认证方式、私钥、跳板机都相同
+    Identical,
+    /// 同名连接有不同的字段
+    Conflict(Vec<FieldDiff>),
+    /// 缺少必要字段，或与前面的待导入连接重名
+    Invalid(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanEntry {
+    pub connection: SavedConnection,
+    pub status: PlanStatus,
+}
+
+/// 导入计划：ssh_config、PuTTY 和导出文件的导入都先分类预览，确认后再写入
+///
+/// 只比较决定连到哪里、怎么认证的字段。相同的连接不改写，保留本地的备注、主机指纹、书签等。
+#[derive(Debug, Clone, Default)]
+pub struct ImportPlan {
+    pub entries: Vec<PlanEntry>,
+}
+
+impl ImportPlan {
+    pub fn new(connections: impl IntoIterator<Item = SavedConnection>, config: &AppConfig) -> Self {
+        let mut seen = HashSet::new();
+        let entries = connections
+            .into_iter()
+            .map(|connection| {
+                let status = if let Err(reason) = validate(&connection) {
+                    PlanStatus::Invalid(reason)
+                } else if !seen.insert(connection.name.clone()) {
+                    PlanStatus::Invalid("导入内容中有重名的连接".to_string())
+                } else {
+                    match config.get_connection(&connection.name) {
+                        None => PlanStatus::New,
+                        Some(existing) => match diff(existing, &connection) {
+                            diffs if diffs.is_empty() => PlanStatus::Identical,
+                            diffs => PlanStatus::Conflict(diffs),
+                        },
+                    }
+                };
+                PlanEntry { connection, status }
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// 执行后是否会改动配置
+    pub fn has_changes(&self, overwrite: bool) -> bool {
+        self.entries.iter().any(|entry| match entry.status {
+            PlanStatus::New => true,
+            PlanStatus::Conflict(_) => overwrite,
+            PlanStatus::Identical | PlanStatus::Invalid(_) => false,
+        })
+    }
+
+    /// 分类表格，冲突的连接后面列出不同的字段
+    pub fn render(&self, style: Style, overwrite: bool) -> String {
+        let mut table = Table::new()
+            .column("状态", Align::Left)
+            .truncated_column("名称", Align::Left)
+            .truncated_column("地址", Align::Left)
+            .column("认证", Align::Left)
+            .separator();
+        for entry in &self.entries {
+            let conn = &entry.connection;
+            let (status, color) = match (&entry.status, overwrite) {
+                (PlanStatus::New, _) => ("新增", Color::Green),
+                (PlanStatus::Identical, _) => ("相同", Color::BrightBlack),
+                (PlanStatus::Conflict(_), true) => ("覆盖", Color::Yellow),
+                (PlanStatus::Conflict(_), false) => ("冲突", Color::Yellow),
+                (PlanStatus::Invalid(_), _) => ("无效", Color::Red),
+            };
+            table.row(vec![
+                Cell::new(status).color(color),
+                Cell::new(conn.name.as_str()),
+                Cell::new(format!("{}@{}:{}", conn.username, conn.host, conn.port)),
+                Cell::new(conn.auth_type.as_str()),
+            ]);
+        }
+        let mut out = table.render(style);
+        for entry in &self.entries {
+            match &entry.status {
+                PlanStatus::Conflict(diffs) => {
+                    out.push_str(&format!("\n{} 的变化（已有 {} 导入）:\n", entry.connection.name, glyphs::arrow()));
+                    for d in diffs {
+                        out.push_str(&format!("  {}: {} {} {}\n", d.field, d.existing, glyphs::arrow(), d.incoming));
+                    }
+                }
+                PlanStatus::Invalid(reason) => {
+                    out.push_str(&format!("\n{}: {}\n", entry.connection.name, reason));
+                }
+                PlanStatus::New | PlanStatus::Identical => {}
+            }
+        }
+        out
+    }
+
+    /// 写入新增的连接；冲突的连接除非 `overwrite` 否则跳过
+    pub fn apply(self, config: &mut AppConfig, overwrite: bool) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        for entry in self.entries {
+            match entry.status {
+                PlanStatus::New => {
+                    config.add_connection(entry.connection);
+                    summary.added += 1;
+                }
+                PlanStatus::Conflict(_) if overwrite => {
+                    config.add_connection(entry.connection);
+                    summary.added += 1;
+                }
+                PlanStatus::Conflict(_) => summary.skipped += 1,
+                PlanStatus::Identical => summary.unchanged += 1,
+                PlanStatus::Invalid(reason) => {
+                    summary.failed += 1;
+                    summary.errors.push((entry.connection.name, reason));
+                }
+            }
+        }
+        summary
+    }
+}
+
+/// 两个同名连接不同的字段；主机名不区分大小写，私钥路径展开 `~` 和环境变量后比较
+fn diff(existing: &SavedConnection, incoming: &SavedConnection) -> Vec<FieldDiff> {
+    let text = |value: Option<&str>| value.unwrap_or("-").to_string();
+    let key_path = |value: Option<&String>| value.map(|path| paths::expand(path));
+    let mut diffs = Vec::new();
+    let mut check = |field, same: bool, existing: String, incoming: String| {
+        if !same {
+            diffs.push(FieldDiff { field, existing, incoming });
+        }
+    };
+    check("主机", existing.host.eq_ignore_ascii_case(&incoming.host), existing.host.clone(), incoming.host.clone());
+    check("端口", existing.port == incoming.port, existing.port.to_string(), incoming.port.to_string());
+    check("用户名", existing.username == incoming.username, existing.username.clone(), incoming.username.clone());
+    check("认证", existing.auth_type == incoming.auth_type, existing.auth_type.clone(), incoming.auth_type.clone());
+    check(
+        "私钥",
+        key_path(existing.private_key_path.as_ref()) == key_path(incoming.private_key_path.as_ref()),
+        text(existing.private_key_path.as_deref()),
+        text(incoming.private_key_path.as_deref()),
+    );
+    check(
+        "跳板机",
+        existing.jump_host == incoming.jump_host,
+        text(existing.jump_host.as_deref()),
+        text(incoming.jump_host.as_deref()),
+    );
+    diffs
+}
+
+/// 导入连接；已存在同名连接时除非 `overwrite` 否则跳过，与已有连接相同的不改写，缺少必要字段的连接计为失败
+pub fn apply(
+    config: &mut AppConfig,
+    connections: impl IntoIterator<Item = SavedConnection>,
+    overwrite: bool,
+) -> ImportSummary {
+    ImportPlan::new(connections, config).apply(config, overwrite)
+}
+
+fn validate(connection: &SavedConnection) -> Result<(), String> {
+    if connection.name.trim().is_empty() {
+        return Err("连接名称为空".to_string());
+    }
+    if connection.host.trim().is_empty() {
+        return Err("主机为空".to_string());
+    }
+    if connection.username.trim().is_empty() {
+        return Err("用户名为空".to_string());
+    }
+    if connection.port == 0 {
+        return Err("端口无效".to_string());
+    }
+    match connection.auth_type.as_str() {
+        "password" | "agent" => Ok(()),
+        "publickey" if connection.private_key_path.is_some() => Ok(()),
+        "publickey" => Err("公钥认证缺少私钥路径".to_string()),
+        other => Err(format!("未知的认证方式: {}", other)),
+    }
+}
+
+/// 导出连接为 TOML；不包含密文时去掉加密的密码、私钥密码和附加密文
+pub fn export(config: &AppConfig, include_secrets: bool) -> Result<String> {
+    let connections = config
+        .list_connections()
+        .into_iter()
+        .cloned()
+        .map(|mut connection| {
+            if !include_secrets {
+                connection.encrypted_password = None;
+                connection.encrypted_passphrase = None;
+                connection.secrets.clear();
+            }
+            connection
+        })
+        .collect();
+    let file = ExportFile { version: EXPORT_VERSION, connections };
+    toml::to_string_pretty(&file).context("无法序列化连接")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(connections: Vec<SavedConnection>) -> AppConfig {
+        let mut config = AppConfig::default();
+        for connection in connections {
+            config.add_connection(connection);
+        }
+        config
+    }
+
+    #[test]
+    fn test_ssh_config_candidates() {
+        let ssh_config = OpenSshConfig::parse(
+            "Host web\n  HostName 10.0.0.1\n  User ops\n  Port 2222\n\
+             Host db\n  User admin\n  IdentityFile /keys/db\n\
+             Host *\n  User fallback\n",
+        );
+        let existing = SavedConnection::new_password("db".into(), "old".into(), 22, "root".into());
+        let candidates = ssh_config_candidates(&ssh_config, &config_with(vec![existing]));
+
+        assert_eq!(candidates.len(), 2);
+        let web = &candidates[0];
+        assert!(!web.conflict);
+        assert_eq!(web.connection.host, "10.0.0.1");
+        assert_eq!(web.connection.port, 2222);
+        assert_eq!(web.connection.username, "ops");
+        assert_eq!(web.connection.auth_type, "password");
+
+        let db = &candidates[1];
+        assert!(db.conflict);
+        assert_eq!(db.connection.host, "db");
+        assert_eq!(db.connection.auth_type, "publickey");
+        assert_eq!(db.connection.private_key_path.as_deref(), Some("/keys/db"));
+    }
+
+    #[test]
+    fn test_putty_candidates() {
+        let session = |key: &str, protocol: &str, host: &str| PuttySession {
+            key: key.to_string(),
+            protocol: protocol.to_string(),
+            host: host.to_string(),
+            port: 22,
+            user: "ops".to_string(),
+            key_file: None,
+        };
+        let sessions = vec![
+            session("Default%20Settings", "ssh", ""),
+            PuttySession { port: 2222, key_file: Some(r"C:\keys\prod.ppk".to_string()), ..session("prod%20web", "ssh", "10.0.0.1") },
+            session("db", "ssh", "admin@db.internal"),
+            session("router", "telnet", "192.168.1.1"),
+            session("console", "serial", ""),
+        ];
+        let existing = SavedConnection::new_password("db".into(), "old".into(), 22, "root".into());
+        let result = putty_candidates(sessions, &config_with(vec![existing]));
+
+        assert_eq!(result.skipped_protocols, 2);
+        assert_eq!(result.candidates.len(), 2);
+        let web = &result.candidates[0].connection;
+        assert_eq!((web.name.as_str(), web.host.as_str(), web.port, web.username.as_str()), ("prod web", "10.0.0.1", 2222, "ops"));
+        assert_eq!(web.auth_type, "publickey");
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("prod.ppk"));
+
+        let db = &result.candidates[1];
+        assert!(db.conflict);
+        assert_eq!((db.connection.host.as_str(), db.connection.username.as_str()), ("db.internal", "admin"));
+        assert_eq!(db.connection.auth_type, "password");
+    }
+
+    #[test]
+    fn test_decode_putty_name() {
+        assert_eq!(decode_putty_name("my%20server%2Fprod"), "my server/prod");
+        assert_eq!(decode_putty_name("100%"), "100%");
+        assert_eq!(decode_putty_name("%E7%94%9F%E4%BA%A7"), "生产");
+    }
+
+    #[test]
+    fn test_apply_counts() {
+        let mut config = config_with(vec![SavedConnection::new_password("a".into(), "h".into(), 22, "u".into())]);
+        let incoming = vec![
+            SavedConnection::new_password("a".into(), "new".into(), 22, "u".into()),
+            SavedConnection::new_password("b".into(), "h".into(), 22, "u".into()),
+            SavedConnection::new_password("c".into(), "".into(), 22, "u".into()),
+        ];
+
+        let summary = apply(&mut config, incoming.clone(), false);
+        assert_eq!((summary.added, summary.skipped, summary.failed), (1, 1, 1));
+        assert_eq!(summary.errors[0].0, "c");
+        assert_eq!(config.get_connection("a").unwrap().host, "h");
+
+        // 上一次已导入的 b 没有变化，不再改写
+        let summary = apply(&mut config, incoming, true);
+        assert_eq!((summary.added, summary.skipped, summary.unchanged, summary.failed), (1, 0, 1, 1));
+        assert_eq!(config.get_connection("a").unwrap().host, "new");
+    }
+
+    #[test]
+    fn test_import_plan() {
+        let mut existing = SavedConnection::new_publickey("db".into(), "DB.internal".into(), 22, "app".into(), "~/.ssh/db".into(), None);
+        existing.note = Some("生产库".into());
+        let config = config_with(vec![
+            existing,
+            SavedConnection::new_password("web".into(), "10.0.0.1".into(), 22, "ops".into()),
+        ]);
+
+        // 字段顺序不同、主机名大小写不同、私钥路径已展开，仍然视为相同
+        let expanded = paths::expand("~/.ssh/db");
+        let export = format!(
+            "version = 1\n[[connections]]\nprivate_key_path = '{}'\nauth_type = \"publickey\"\nusername = \"app\"\n\
+             port = 22\nhost = \"db.internal\"\nname = \"db\"\n",
+            expanded
+        );
+        let mut incoming = read_export(&export).unwrap();
+        incoming.extend([
+            // 同名不同主机
+            SavedConnection::new_password("web".into(), "10.0.0.2".into(), 2222, "ops".into()),
+            SavedConnection::new_password("cache".into(), "10.0.0.3".into(), 22, "ops".into()),
+            SavedConnection::new_password("cache".into(), "10.0.0.4".into(), 22, "ops".into()),
+            SavedConnection::new_password("".into(), "10.0.0.5".into(), 22, "ops".into()),
+        ]);
+        let plan = ImportPlan::new(incoming, &config);
+        let statuses: Vec<_> = plan.entries.iter().map(|e| &e.status).collect();
+        assert_eq!(statuses[0], &PlanStatus::Identical);
+        assert_eq!(
+            statuses[1],
+            &PlanStatus::Conflict(vec![
+                FieldDiff { field: "主机", existing: "10.0.0.1".into(), incoming: "10.0.0.2".into() },
+                FieldDiff { field: "端口", existing: "22".into(), incoming: "2222".into() },
+            ])
+        );
+        assert_eq!(statuses[2], &PlanStatus::New);
+        assert!(matches!(statuses[3], PlanStatus::Invalid(_)));
+        assert!(matches!(statuses[4], PlanStatus::Invalid(_)));
+        assert!(plan.has_changes(false));
+
+        let rendered = plan.render(Style::Plain, false);
+        assert!(rendered.starts_with("状态\t名称\t地址\t认证\n相同\tdb\tapp@db.internal:22\tpublickey\n冲突\tweb\t"));
+        assert!(rendered.contains("  端口: 22 → 2222\n"));
+
+        // 相同的连接不改写，保留本地的备注
+        let mut target = config.clone();
+        let summary = plan.apply(&mut target, false);
+        assert_eq!((summary.added, summary.skipped, summary.unchanged, summary.failed), (1, 1, 1, 2));
+        assert_eq!(target.get_connection("db").unwrap().note.as_deref(), Some("生产库"));
+        assert_eq!(target.get_connection("web").unwrap().host, "10.0.0.1");
+        assert_eq!(target.get_connection("cache").unwrap().host, "10.0.0.3");
+
+        let unchanged = ImportPlan::new(vec![config.get_connection("web").unwrap().clone()], &config);
+        assert!(!unchanged.has_changes(true));
+    }
+
+    #[test]
+    fn test_export_round_trip_strips_secrets() {
+        let mut connection = SavedConnection::new_password_with_encrypted(
+            "a".into(),
+            "h".into(),
+            22,
+            "u".into(),
+            "cipher".into(),
+        );
+        connection.secrets.insert("sudo".into(), "sudo-cipher".into());
+        let config = config_with(vec![connection]);
+        assert_eq!(config.any_ciphertext(), Some("cipher"));
+
+        let plain = export(&config, false).unwrap();
+        assert!(!plain.contains("cipher"));
+        assert!(read_export(&plain).unwrap()[0].secrets.is_empty());
+        let imported = read_export(&plain).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].host, "h");
+        assert_eq!(imported[0].encrypted_password, None);
+
+        let with_secrets = export(&config, true).unwrap();
+        assert_eq!(read_export(&with_secrets).unwrap()[0].encrypted_password.as_deref(), Some("cipher"));
+
+        assert!(read_export("version = 99\n").is_err());
+    }
+}
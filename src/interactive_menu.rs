@@ -1,10 +1,11 @@
 use anyhow::{Context, Result};
 use colored::Colorize;
-use std::io::{self, Write};
 use crate::config::{AppConfig, SavedConnection};
+use crate::glyphs;
+use crate::prompt::Prompter;
 
 /// 显示交互式连接选择菜单
-pub fn show_connection_menu() -> Result<Option<String>> {
+pub fn show_connection_menu(prompter: &mut dyn Prompter) -> Result<Option<String>> {
     let config = AppConfig::load()?;
     let connections = config.list_connections();
     
@@ -25,9 +26,9 @@ pub fn show_connection_menu() -> Result<Option<String>> {
         let name = conn.name.bold();
         let info = format!("{}@{}:{}", conn.username, conn.host, conn.port).dimmed();
         let pwd_indicator = if conn.has_saved_password() {
-            "🔒".green()
+            glyphs::locked().green()
         } else {
-            "🔓".yellow()
+            glyphs::unlocked().yellow()
         };
         
         println!("  {} {} {} {}", num, name, info, pwd_indicator);
@@ -40,12 +41,11 @@ pub fn show_connection_menu() -> Result<Option<String>> {
     
     // 获取用户选择
     loop {
-        print!("{} ", format!("请选择连接 [1-{}, 0=手动, q=退出]:", connections.len()).green().bold());
-        io::stdout().flush()?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
+        let input = prompter.ask_line(&format!(
+            "{} ",
+            format!("请选择连接 [1-{}, 0=手动, q=退出]:", connections.len()).green().bold()
+        ))?;
+        let input = input.as_str();
         
         if input.eq_ignore_ascii_case("q") {
             return Ok(None);
@@ -53,54 +53,44 @@ pub fn show_connection_menu() -> Result<Option<String>> {
         
         if input == "0" {
             // 手动输入
-            return get_manual_connection_info();
+            return get_manual_connection_info(prompter);
         }
         
         // 尝试解析为数字
         if let Ok(choice) = input.parse::<usize>() {
             if choice >= 1 && choice <= connections.len() {
                 let selected = connections[choice - 1];
-                println!("\n{} 已选择: {}", "✓".green(), selected.name.bold());
+                println!("\n{} 已选择: {}", glyphs::ok().green(), selected.name.bold());
                 return Ok(Some(selected.name.clone()));
             }
         }
         
-        println!("{} 无效的选择，请重试", "✗".red());
+        println!("{} 无效的选择，请重试", glyphs::fail().red());
     }
 }
 
 /// 手动输入连接信息
-fn get_manual_connection_info() -> Result<Option<String>> {
+fn get_manual_connection_info(prompter: &mut dyn Prompter) -> Result<Option<String>> {
     println!("\n{}", "=== 手动输入连接信息 ===".cyan().bold());
     
     // 获取主机
-    print!("{} ", "主机地址:".green());
-    io::stdout().flush()?;
-    let mut host = String::new();
-    io::stdin().read_line(&mut host)?;
-    let host = host.trim();
+    let host = prompter.ask_line(&format!("{} ", "主机地址:".green()))?;
+    let host = host.as_str();
     
     if host.is_empty() {
         return Ok(None);
     }
     
     // 获取用户名
-    print!("{} ", "用户名:".green());
-    io::stdout().flush()?;
-    let mut username = String::new();
-    io::stdin().read_line(&mut username)?;
-    let username = username.trim();
+    let username = prompter.ask_line(&format!("{} ", "用户名:".green()))?;
+    let username = username.as_str();
     
     if username.is_empty() {
         return Ok(None);
     }
     
     // 获取端口
-    print!("{} [默认: 22]: ", "端口".green());
-    io::stdout().flush()?;
-    let mut port_str = String::new();
-    io::stdin().read_line(&mut port_str)?;
-    let port_str = port_str.trim();
+    let port_str = prompter.ask_line(&format!("{} [默认: 22]: ", "端口".green()))?;
     let port: u16 = if port_str.is_empty() {
         22
     } else {
@@ -108,23 +98,16 @@ fn get_manual_connection_info() -> Result<Option<String>> {
     };
     
     // 询问是否保存
-    print!("{} [y/N]: ", "是否保存此连接?".green());
-    io::stdout().flush()?;
-    let mut save_choice = String::new();
-    io::stdin().read_line(&mut save_choice)?;
-    let should_save = save_choice.trim().eq_ignore_ascii_case("y");
+    let save_choice = prompter.ask_line(&format!("{} [y/N]: ", "是否保存此连接?".green()))?;
+    let should_save = save_choice.eq_ignore_ascii_case("y");
     
     let connection_name = if should_save {
-        print!("{} [默认: {}@{}]: ", "连接名称".green(), username, host);
-        io::stdout().flush()?;
-        let mut name = String::new();
-        io::stdin().read_line(&mut name)?;
-        let name = name.trim();
+        let name = prompter.ask_line(&format!("{} [默认: {}@{}]: ", "连接名称".green(), username, host))?;
         
         if name.is_empty() {
             format!("{}@{}", username, host)
         } else {
-            name.to_string()
+            name
         }
     } else {
         format!("{}@{}", username, host)
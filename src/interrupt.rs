@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::outcome;
+
+/// Ctrl+C 退出码（128 + SIGINT）
+pub const EXIT_CODE: i32 = 130;
+
+/// 传输被用户取消
+#[derive(Debug, thiserror::Error)]
+#[error("传输已取消")]
+pub struct Cancelled;
+
+/// 连接尚未建立时被取消（Ctrl+C 或界面上的取消按钮）
+#[derive(Debug, thiserror::Error)]
+#[error("连接已取消")]
+pub struct ConnectCancelled;
+
+/// 安装 Ctrl+C 处理器，返回取消标志
+///
+/// 第一次 Ctrl+C 只设置标志，由传输循环自行停止并清理；再次按下则立即退出。
+/// 交互式终端有自己的 Ctrl+C 语义，不应调用此函数。
+pub fn install_handler() -> Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&flag);
+
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            eprintln!("\n强制退出");
+            outcome::exit(EXIT_CODE, Some(&Cancelled.into()));
+        }
+        eprintln!("\n正在取消传输，再次按 Ctrl+C 强制退出...");
+    })
+    .context("无法安装 Ctrl+C 处理器")?;
+
+    Ok(flag)
+}
+
+/// 保存当前的 Ctrl+C（SIGINT）处理方式，drop 时恢复
+///
+/// tokio 的 `signal::ctrl_c()` 第一次调用时安装的处理器在进程结束前一直有效：之后的 Ctrl+C 只通知
+/// 已不存在的监听者，既不结束进程也不会被处理。只在一段时间内监听 Ctrl+C 时用它限定范围。tokio
+/// 不会再次安装处理器，之后再监听 `ctrl_c()` 收不到通知，Ctrl+C 按恢复后的方式处理（通常是结束进程）。
+/// Windows 上没有监听者时 tokio 交给系统默认处理，不需要恢复。
+pub struct SigintScope {
+    #[cfg(unix)]
+    previous: libc::sigaction,
+}
+
+impl SigintScope {
+    pub fn save() -> Self {
+        #[cfg(unix)]
+        {
+            // SAFETY: 只查询，不修改当前的处理方式
+            let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigaction(libc::SIGINT, std::ptr::null(), &mut previous) };
+            Self { previous }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+}
+
+impl Drop for SigintScope {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: previous 是之前由 sigaction 填写的有效设置
+        unsafe {
+            libc::sigaction(libc::SIGINT, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use crate::ssh::SshClient;
+
+/// 没有数据可转发时的等待时间
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// 通过跳板机建立到目标主机的隧道
+///
+/// libssh2 的第二个 Session 需要一个真正的套接字，因此在本地回环地址上监听，
+/// 由后台线程在该连接和跳板机的 direct-tcpip 通道之间转发数据。
+/// 返回的 TcpStream 可以直接交给目标主机的 Session 使用。
+pub fn open_tunnel(jump: SshClient, host: &str, port: u16) -> Result<TcpStream> {
+    info!("通过跳板机 {} 建立到 {}:{} 的隧道", jump.config().host, host, port);
+
+    let channel = jump
+        .session()
+        .channel_direct_tcpip(host, port, None)
+        .context(format!("跳板机无法连接到 {}:{}", host, port))?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").context("无法创建本地隧道端口")?;
+    let client = TcpStream::connect(listener.local_addr()?).context("无法连接本地隧道端口")?;
+    let (local, _) = listener.accept().context("无法接受本地隧道连接")?;
+
+    // 转发线程持有跳板机连接，隧道关闭后一并断开
+    thread::spawn(move || {
+        if let Err(e) = pump(&jump, channel, local) {
+            error!("跳板机隧道异常结束: {}", e);
+        }
+        debug!("跳板机隧道已关闭");
+    });
+
+    Ok(client)
+}
+
+/// 在本地套接字和 SSH 通道之间双向转发，直到任意一端关闭
+fn pump(jump: &SshClient, mut channel: ssh2::Channel, mut local: TcpStream) -> Result<()> {
+    // 单线程轮询两端，避免两个线程同时阻塞在同一个 libssh2 会话上
+    jump.session().set_blocking(false);
+    local.set_nonblocking(true)?;
+
+    let mut to_local: Vec<u8> = Vec::new();
+    let mut to_remote: Vec<u8> = Vec::new();
+    let mut buffer = vec![0u8; 32 * 1024];
+    let mut local_closed = false;
+
+    loop {
+        let mut progressed = false;
+
+        // 通道 -> 本地
+        if to_local.is_empty() {
+            match channel.read(&mut buffer) {
+                Ok(0) => {}
+                Ok(n) => {
+                    to_local.extend_from_slice(&buffer[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("从跳板机通道读取失败"),
+            }
+        }
+        if !to_local.is_empty() {
+            match local.write(&to_local) {
+                Ok(n) => {
+                    to_local.drain(..n);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("写入本地隧道失败"),
+            }
+        }
+
+        // 本地 -> 通道
+        if to_remote.is_empty() && !local_closed {
+            match local.read(&mut buffer) {
+                Ok(0) => local_closed = true,
+                Ok(n) => {
+                    to_remote.extend_from_slice(&buffer[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("从本地隧道读取失败"),
+            }
+        }
+        if !to_remote.is_empty() {
+            match channel.write(&to_remote) {
+                Ok(n) => {
+                    to_remote.drain(..n);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("写入跳板机通道失败"),
+            }
+        }
+
+        if local_closed && to_remote.is_empty() {
+            debug!("本地隧道连接已关闭");
+            break;
+        }
+        if channel.eof() && to_local.is_empty() {
+            debug!("跳板机通道已关闭");
+            let _ = local.shutdown(Shutdown::Both);
+            break;
+        }
+
+        if !progressed {
+            thread::sleep(IDLE_WAIT);
+        }
+    }
+
+    jump.session().set_blocking(true);
+    let _ = channel.close();
+    Ok(())
+}
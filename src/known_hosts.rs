@@ -0,0 +1,457 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use russh::client;
+use russh_keys::key;
+use russh_keys::PublicKeyBase64;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{AppConfig, ConfigStore};
+
+/// known_hosts 中的一条记录
+#[derive(Debug, Clone)]
+pub struct KnownHostEntry {
+    /// 主机模式（逗号分隔，可能是 `|1|salt|hash` 形式的哈希主机名）
+    pub hosts: String,
+    /// 密钥类型，例如 ssh-ed25519
+    pub key_type: String,
+    /// Base64 编码的公钥
+    pub key_base64: String,
+    /// 来源文件
+    pub source: PathBuf,
+}
+
+/// 主机密钥校验结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostKeyStatus {
+    /// 与已记录的密钥一致
+    Trusted,
+    /// 没有该主机的记录
+    Unknown,
+    /// 已记录的密钥与服务器提供的不一致
+    Mismatch,
+}
+
+impl KnownHostEntry {
+    /// 解析一行 known_hosts，注释和空行返回 None
+    fn parse(line: &str, source: PathBuf) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let mut parts = line.split_whitespace();
+        let mut hosts = parts.next()?;
+        // 跳过 @cert-authority / @revoked 等标记
+        if hosts.starts_with('@') {
+            hosts = parts.next()?;
+        }
+        let key_type = parts.next()?;
+        let key_base64 = parts.next()?;
+
+        Some(Self {
+            hosts: hosts.to_string(),
+            key_type: key_type.to_string(),
+            key_base64: key_base64.to_string(),
+            source,
+        })
+    }
+
+    /// 是否匹配指定主机和端口
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        let host_port = host_pattern(host, port);
+        self.hosts
+            .split(',')
+            .any(|pattern| match_host_pattern(&host_port, pattern))
+    }
+
+    /// 是否为哈希主机名记录
+    pub fn is_hashed(&self) -> bool {
+        self.hosts.starts_with("|1|")
+    }
+
+    /// SHA256 指纹（与 OpenSSH 的显示格式一致）
+    pub fn fingerprint(&self) -> String {
+        match general_purpose::STANDARD.decode(&self.key_base64) {
+            Ok(blob) => fingerprint_blob(&blob),
+            Err(_) => "<无效的密钥>".to_string(),
+        }
+    }
+}
+
+/// known_hosts 中的主机写法：默认端口直接写主机名，其他端口写成 [host]:port
+fn host_pattern(host: &str, port: u16) -> Cow<'_, str> {
+    if port == 22 {
+        Cow::Borrowed(host)
+    } else {
+        Cow::Owned(format!("[{}]:{}", host, port))
+    }
+}
+
+/// 匹配单个主机模式（支持哈希主机名）
+fn match_host_pattern(host_port: &str, pattern: &str) -> bool {
+    if let Some(hashed) = pattern.strip_prefix("|1|") {
+        let mut parts = hashed.split('|');
+        let (Some(salt), Some(hash)) = (parts.next(), parts.next()) else {
+            return false;
+        };
+        let (Ok(salt), Ok(hash)) = (
+            general_purpose::STANDARD.decode(salt),
+            general_purpose::STANDARD.decode(hash),
+        ) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+            return false;
+        };
+        mac.update(host_port.as_bytes());
+        mac.verify_slice(&hash).is_ok()
+    } else {
+        pattern == host_port
+    }
+}
+
+/// 计算公钥数据的 SHA256 指纹
+pub fn fingerprint_blob(blob: &[u8]) -> String {
+    let digest = Sha256::digest(blob);
+    format!("SHA256:{}", general_purpose::STANDARD_NO_PAD.encode(digest))
+}
+
+/// 从公钥数据中读取密钥类型（第一个 SSH string 字段）
+fn key_type_from_blob(blob: &[u8]) -> Option<String> {
+    let len = u32::from_be_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+    let name = blob.get(4..4 + len)?;
+    String::from_utf8(name.to_vec()).ok()
+}
+
+/// known_hosts 文件管理
+pub struct KnownHosts {
+    entries: Vec<KnownHostEntry>,
+}
+
+impl KnownHosts {
+    /// 本工具自己的 known_hosts 文件路径（位于配置目录）
+    pub fn path() -> Result<PathBuf> {
+        Ok(AppConfig::config_path()?.with_file_name("known_hosts"))
+    }
+
+    /// OpenSSH 的 known_hosts 文件路径
+    pub fn openssh_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts"))
+    }
+
+    /// 按配置加载（`include_openssh_known_hosts` 决定是否同时读取 ~/.ssh/known_hosts）
+    pub fn load() -> Result<Self> {
+        let include_openssh = AppConfig::load()
+            .map(|config| config.include_openssh_known_hosts)
+            .unwrap_or(false);
+        Self::load_with(include_openssh)
+    }
+
+    /// 加载 known_hosts 记录
+    pub fn load_with(include_openssh: bool) -> Result<Self> {
+        let mut paths = vec![Self::path()?];
+        if include_openssh {
+            if let Some(path) = Self::openssh_path() {
+                paths.push(path);
+            }
+        }
+
+        let mut entries = Vec::new();
+        for path in paths {
+            if !path.exists() {
+                continue;
+            }
+            let content = fs::read_to_string(&path)
+                .context(format!("无法读取 known_hosts 文件: {}", path.display()))?;
+            entries.extend(
+                content
+                    .lines()
+                    .filter_map(|line| KnownHostEntry::parse(line, path.clone())),
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// 所有记录
+    pub fn entries(&self) -> &[KnownHostEntry] {
+        &self.entries
+    }
+
+    /// 查找指定主机的记录
+    pub fn lookup(&self, host: &str, port: u16) -> Vec<&KnownHostEntry> {
+        self.entries.iter().filter(|e| e.matches(host, port)).collect()
+    }
+
+    /// 校验服务器提供的公钥数据
+    pub fn check_blob(&self, host: &str, port: u16, blob: &[u8]) -> HostKeyStatus {
+        let offered = general_purpose::STANDARD.encode(blob);
+        let offered_type = key_type_from_blob(blob);
+        let known = self.lookup(host, port);
+
+        if known.is_empty() {
+            return HostKeyStatus::Unknown;
+        }
+        if known.iter().any(|e| e.key_base64 == offered) {
+            return HostKeyStatus::Trusted;
+        }
+        // 只记录了其他类型的密钥时不视为冲突
+        if known.iter().any(|e| Some(&e.key_type) == offered_type.as_ref()) {
+            HostKeyStatus::Mismatch
+        } else {
+            HostKeyStatus::Unknown
+        }
+    }
+
+    /// 将主机密钥追加到本工具的 known_hosts 文件
+    pub fn add_blob(host: &str, port: u16, blob: &[u8]) -> Result<()> {
+        let key_type = key_type_from_blob(blob).context("无法识别的公钥格式")?;
+        let path = Self::path()?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("无法写入 known_hosts 文件: {}", path.display()))?;
+        writeln!(
+            file,
+            "{} {} {}",
+            host_pattern(host, port),
+            key_type,
+            general_purpose::STANDARD.encode(blob)
+        )
+        .context("无法写入 known_hosts 文件")?;
+        Ok(())
+    }
+
+    /// 从本工具的 known_hosts 文件中删除主机的所有记录，返回删除的条数
+    pub fn remove(host: &str, port: u16) -> Result<usize> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(0);
+        }
+
+        let content = fs::read_to_string(&path).context("无法读取 known_hosts 文件")?;
+        let mut removed = 0;
+        let kept: Vec<&str> = content
+            .lines()
+            .filter(|line| {
+                let matched = KnownHostEntry::parse(line, path.clone())
+                    .map(|e| e.matches(host, port))
+                    .unwrap_or(false);
+                if matched {
+                    removed += 1;
+                }
+                !matched
+            })
+            .collect();
+
+        if removed > 0 {
+            let mut output = kept.join("\n");
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            fs::write(&path, output).context("无法写入 known_hosts 文件")?;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// 连接时校验服务器公钥：已记录且不一致时拒绝，未记录时给出提示
+///
+/// 保存的连接固定的指纹优先检查，即使 known_hosts 被清空也会拒绝。
+pub fn verify_host_key(host: &str, port: u16, blob: &[u8]) -> Result<()> {
+    check_pinned(host, port, blob)?;
+    let known_hosts = KnownHosts::load()?;
+    match known_hosts.check_blob(host, port, blob) {
+        HostKeyStatus::Trusted => {
+            debug!("主机密钥已验证: {}", fingerprint_blob(blob));
+            Ok(())
+        }
+        HostKeyStatus::Unknown => {
+            warn!(
+                "主机 {} 的密钥未记录 ({})，可使用 `hostkey scan {} --add` 保存",
+                host_pattern(host, port),
+                fingerprint_blob(blob),
+                host
+            );
+            Ok(())
+        }
+        HostKeyStatus::Mismatch => anyhow::bail!(
+            "主机 {} 的密钥与已记录的不一致 ({})！可能存在中间人攻击；如服务器已重装，请使用 `hostkey remove {}` 删除旧记录",
+            host_pattern(host, port),
+            fingerprint_blob(blob),
+            host
+        ),
+    }
+}
+
+/// 与保存的连接固定的指纹比对，不一致时直接拒绝（不询问）
+fn check_pinned(host: &str, port: u16, blob: &[u8]) -> Result<()> {
+    let Ok(config) = AppConfig::load() else {
+        return Ok(());
+    };
+    let fingerprint = fingerprint_blob(blob);
+    if let Some(conn) = config.pinned_mismatch(host, port, &fingerprint) {
+        anyhow::bail!(
+            "主机 {} 的密钥指纹已变化！连接 '{}' 记录的是 {}，服务器提供的是 {}。可能存在中间人攻击；如服务器已重装，请使用 `config reset-fingerprint {}`",
+            host_pattern(host, port),
+            conn.name,
+            conn.host_key_fingerprint.as_deref().unwrap_or_default(),
+            fingerprint,
+            conn.name
+        );
+    }
+    Ok(())
+}
+
+/// 认证成功后为指向该主机、尚未固定指纹的保存连接记录指纹
+///
+/// 两种后端在认证成功后都调用这里。写入失败只记录警告，不影响已建立的连接。
+pub fn pin_host_key(host: &str, port: u16, blob: &[u8]) {
+    // 大多数连接没有需要记录指纹的保存连接，先不加锁检查，不必等待配置锁
+    let Ok(config) = AppConfig::load() else {
+        return;
+    };
+    if !config.has_unpinned(host, port) {
+        return;
+    }
+    let fingerprint = fingerprint_blob(blob);
+    let result = ConfigStore::update(|config| {
+        let pinned = config.pin_fingerprint(host, port, &fingerprint);
+        if !pinned.is_empty() {
+            config.save()?;
+        }
+        Ok(pinned)
+    });
+    match result {
+        Ok(pinned) if pinned.is_empty() => {}
+        Ok(pinned) => info!("已为连接 {} 记录主机密钥指纹 {}", pinned.join(", "), fingerprint),
+        Err(e) => warn!("无法保存主机密钥指纹: {:#}", e),
+    }
+}
+
+/// 扫描时只记录服务器公钥、不进行认证的处理器
+struct ScanHandler {
+    keys: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+#[async_trait::async_trait]
+impl client::Handler for ScanHandler {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &key::PublicKey,
+    ) -> Result<bool, Self::Error> {
+        self.keys.lock().unwrap().push(server_public_key.public_key_bytes());
+        // 拿到密钥后即中止握手
+        Ok(false)
+    }
+}
+
+/// 扫描服务器提供的所有主机密钥（每种算法单独握手一次）
+pub async fn scan_host_keys(host: &str, port: u16) -> Result<Vec<Vec<u8>>> {
+    const ALGORITHMS: &[key::Name] = &[
+        key::ED25519,
+        key::ECDSA_SHA2_NISTP256,
+        key::ECDSA_SHA2_NISTP384,
+        key::ECDSA_SHA2_NISTP521,
+        key::RSA_SHA2_512,
+    ];
+
+    let keys = Arc::new(Mutex::new(Vec::new()));
+    for algorithm in ALGORITHMS {
+        let config = client::Config {
+            preferred: russh::Preferred {
+                key: Cow::Owned(vec![*algorithm]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let handler = ScanHandler { keys: keys.clone() };
+        // 服务器不支持该算法或密钥被拒绝时都会返回错误，这里只关心收集到的密钥
+        if let Err(e) = client::connect(Arc::new(config), (host, port), handler).await {
+            debug!("扫描 {} 算法: {}", algorithm.as_ref(), e);
+            if matches!(e, russh::Error::IO(_)) {
+                return Err(e).context(format!("无法连接到 {}:{}", host, port));
+            }
+        }
+    }
+
+    let mut keys = keys.lock().unwrap().clone();
+    keys.dedup();
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_plain_and_port_entries() {
+        let entry = KnownHostEntry::parse(
+            "example.com,[example.com]:2222 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA==",
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        assert!(entry.matches("example.com", 22));
+        assert!(entry.matches("example.com", 2222));
+        assert!(!entry.matches("example.com", 2200));
+        assert!(!entry.matches("other.com", 22));
+    }
+
+    #[test]
+    fn test_match_hashed_entry() {
+        let salt = b"0123456789abcdef0123";
+        let mut mac = Hmac::<Sha1>::new_from_slice(salt).unwrap();
+        mac.update(b"[host.internal]:2022");
+        let hash = mac.finalize().into_bytes();
+        let line = format!(
+            "|1|{}|{} ssh-ed25519 AAAA",
+            general_purpose::STANDARD.encode(salt),
+            general_purpose::STANDARD.encode(hash)
+        );
+
+        let entry = KnownHostEntry::parse(&line, PathBuf::new()).unwrap();
+        assert!(entry.is_hashed());
+        assert!(entry.matches("host.internal", 2022));
+        assert!(!entry.matches("host.internal", 22));
+    }
+
+    #[test]
+    fn test_check_blob_status() {
+        let blob = b"\x00\x00\x00\x0bssh-ed25519\x00\x00\x00\x01A".to_vec();
+        let other = b"\x00\x00\x00\x0bssh-ed25519\x00\x00\x00\x01B".to_vec();
+        let known_hosts = KnownHosts {
+            entries: vec![KnownHostEntry {
+                hosts: "example.com".to_string(),
+                key_type: "ssh-ed25519".to_string(),
+                key_base64: general_purpose::STANDARD.encode(&blob),
+                source: PathBuf::new(),
+            }],
+        };
+
+        assert_eq!(known_hosts.check_blob("example.com", 22, &blob), HostKeyStatus::Trusted);
+        assert_eq!(known_hosts.check_blob("example.com", 22, &other), HostKeyStatus::Mismatch);
+        assert_eq!(known_hosts.check_blob("other.com", 22, &blob), HostKeyStatus::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_scan_host_keys() {
+        let server = crate::test_server::TestServer::start();
+        let keys = scan_host_keys("127.0.0.1", server.port).await.unwrap();
+
+        assert_eq!(keys.len(), 1);
+        assert_eq!(key_type_from_blob(&keys[0]).as_deref(), Some("ssh-ed25519"));
+    }
+}
@@ -0,0 +1,211 @@
+//! 内存中的日志环形缓冲区
+//!
+//! 日志在输出到 stderr 的同时保存最近的若干条，供 GUI 的日志窗口查看。
+//! 输出和保存前都把本次运行缓存的凭据替换为 `******`；无法确认时 stderr 上只输出一条说明，
+//! 缓冲区丢弃该条，两处都不会出现密码。
+
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::credentials;
+
+/// 缓冲区保留的日志条数
+pub const CAPACITY: usize = 5000;
+
+/// 缓冲区记录的最低级别（不受 RUST_LOG 影响，GUI 中再按级别过滤）
+const BUFFER_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// 一条日志
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// 单行文本（用于复制）
+    pub fn line(&self) -> String {
+        format!("{} {:<5} {}: {}", self.time.format("%H:%M:%S%.3f"), self.level, self.target, self.message)
+    }
+}
+
+/// 固定容量的日志缓冲区，满了以后丢弃最旧的
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub const fn new(capacity: usize) -> Self {
+        LogBuffer { entries: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+static BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new(CAPACITY));
+
+fn with_buffer<T>(f: impl FnOnce(&mut LogBuffer) -> T) -> T {
+    let mut buffer = BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut buffer)
+}
+
+/// 当前缓冲区中的日志
+pub fn snapshot() -> Vec<LogEntry> {
+    with_buffer(|buffer| buffer.entries().cloned().collect())
+}
+
+pub fn clear() {
+    with_buffer(LogBuffer::clear);
+}
+
+/// 缓存正被占用、无法脱敏时代替日志内容输出的文本
+const UNREDACTABLE: &str = "（无法确认日志中是否有密码，已省略）";
+
+/// 同时输出到 env_logger 和缓冲区，两处写出的是同一条脱敏后的文本
+struct TeeLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= BUFFER_LEVEL || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let to_stderr = self.inner.matches(record);
+        let to_buffer = record.level() <= BUFFER_LEVEL;
+        if !to_stderr && !to_buffer {
+            return;
+        }
+        let message = credentials::redact(&record.args().to_string());
+        if to_stderr {
+            let text = message.as_deref().unwrap_or(UNREDACTABLE);
+            self.inner.log(
+                &Record::builder()
+                    .args(format_args!("{}", text))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            );
+        }
+        if !to_buffer {
+            return;
+        }
+        let Some(message) = message else {
+            return;
+        };
+        let entry = LogEntry {
+            time: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message,
+        };
+        with_buffer(|buffer| buffer.push(entry));
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 安装 logger：按 `builder` 的配置输出，同时记录到缓冲区
+pub fn init(mut builder: env_logger::Builder) {
+    let inner = builder.build();
+    let max_level = inner.filter().max(BUFFER_LEVEL);
+    if log::set_boxed_logger(Box::new(TeeLogger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// 测试用：安装输出到内存的 logger（整个测试进程只安装一次），返回 env_logger 一侧写出的内容
+#[cfg(test)]
+pub fn capture() -> std::sync::Arc<Mutex<Vec<u8>>> {
+    use std::sync::{Arc, OnceLock};
+
+    static CAPTURED: OnceLock<Arc<Mutex<Vec<u8>>>> = OnceLock::new();
+    CAPTURED
+        .get_or_init(|| {
+            let captured = Arc::new(Mutex::new(Vec::new()));
+            let mut builder = env_logger::Builder::new();
+            builder
+                .filter_level(LevelFilter::Debug)
+                .target(env_logger::Target::Pipe(Box::new(Captured(captured.clone()))));
+            init(builder);
+            captured
+        })
+        .clone()
+}
+
+#[cfg(test)]
+struct Captured(std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::Credential;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry { time: Local::now(), level: Level::Info, target: "test".to_string(), message: message.to_string() }
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let mut buffer = LogBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(entry(&i.to_string()));
+        }
+        let messages: Vec<_> = buffer.entries().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["2", "3", "4"]);
+        assert!(entry("x").line().ends_with("INFO  test: x"));
+    }
+
+    #[test]
+    fn test_both_sinks_are_redacted() {
+        let captured = capture();
+        let key = Credential::password("logger", "tee.example.invalid");
+        credentials::remember(key.clone(), "tee-secret-42");
+        log::info!("使用密码 tee-secret-42 登录 tee.example.invalid");
+        log::logger().flush();
+        credentials::forget(&key);
+
+        let stderr = String::from_utf8_lossy(&captured.lock().unwrap()).into_owned();
+        assert!(stderr.contains("登录 tee.example.invalid"), "{}", stderr);
+        assert!(!stderr.contains("tee-secret-42"), "{}", stderr);
+        let buffered: Vec<_> = snapshot().into_iter().filter(|e| e.message.contains("tee.example.invalid")).collect();
+        assert_eq!(buffered.len(), 1);
+        assert_eq!(buffered[0].message, format!("使用密码 {} 登录 tee.example.invalid", credentials::REDACTED));
+    }
+}
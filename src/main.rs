@@ -1,37 +1,210 @@
+mod agent;
+mod audit;
+mod batch;
+mod capture;
+mod check;
+mod chunked;
 mod cli;
 mod config;
+mod connect;
+mod console;
+mod credential_helper;
+mod credentials;
 mod crypto;
+mod debug_report;
+mod diag;
+mod diff;
+mod early_close;
+mod edit;
+mod escape;
+mod fanout;
+mod file_list;
+mod glyphs;
+mod grep;
 mod gui;
+mod history;
+mod hooks;
+mod import;
+mod interrupt;
+mod jump;
 mod interactive_menu;
+mod known_hosts;
+mod log_buffer;
+mod manifest;
+mod master;
+mod offline;
+mod openssh_config;
+mod outcome;
+mod paths;
+mod preflight;
+mod process;
+mod progress;
+mod prompt;
+mod recorder;
+mod relay;
+mod remote_copy;
+mod remote_os;
+mod session;
+mod secrets;
 mod sftp;
+mod shell_transfer;
+mod snippet;
 mod ssh;
 mod ssh_russh;
+mod table;
+mod tail;
 mod terminal;
 mod terminal_russh;
+mod trash;
+mod watch;
+#[cfg(test)]
+mod test_server;
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use cli::{Cli, Commands, ConfigCommands, SftpCommands};
-use colored::Colorize;
-use config::{AppConfig, SavedConnection};
-use crypto::CryptoManager;
-use sftp::SftpClient;
+use cli::{
+    AuditCommands, BookmarkCommands, Cli, Commands, ConfigCommands, HostkeyCommands, MasterCommands, ProcCommands, SecretCommands, SftpCommands,
+    SnippetCommands, TrashCommands,
+};
+use colored::{Color, Colorize};
+use config::{AppConfig, ConfigStore, SavedConnection};
+use connect::{resolve_target, ConnectOptions, ConnectPipeline, Connected, TargetAuth};
+use import::ImportPlan;
+use manifest::Manifest;
+use offline::OfflineContext;
+use openssh_config::OpenSshConfig;
+use progress::{ProgressMode, ProgressReporter};
+use prompt::Prompter;
+use recorder::{CastRecorder, RecordOptions};
+use session::RemoteSession;
+use sftp::{Links, SftpClient, SkipExisting, TransferStats};
 use ssh::{AuthMethod, SshClient, SshConfig};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use table::{Align, Cell, Table};
 use terminal::{InteractiveTerminal, SimpleShell};
+use trash::Trash;
+use zeroize::Zeroizing;
 
 #[tokio::main]
 async fn main() {
+    // Windows 控制台需要在输出任何转义序列（包括帮助信息的颜色）之前启用
+    let vt = console::enable_virtual_terminal();
+    console::install_panic_hook();
+    let cli = Cli::parse_checked();
+    glyphs::init(cli.ascii.then_some(false).or(cli.unicode.then_some(true)), vt);
+    if let Some(ref path) = cli.debug_report {
+        debug_report::enable(path, std::env::args().skip(1).collect(), global_options(&cli));
+    }
+
     // 初始化日志
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format_timestamp(None)
-        .init();
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    logger.format_timestamp(None);
+    if cli.ssh_debug {
+        logger
+            .filter_module("rust_ssh_sftp", log::LevelFilter::Debug)
+            .filter_module("russh", log::LevelFilter::Trace)
+            .filter_module("russh_keys", log::LevelFilter::Debug);
+        ssh::enable_debug();
+    }
+    log_buffer::init(logger);
+    if cli.batch {
+        prompt::enable_batch();
+    }
+    if cli.no_table {
+        table::enable_plain();
+    }
+    if cli.auth == ssh::AuthMode::Key {
+        ssh::pin_key_auth();
+    }
+    if cli.json_result {
+        match outcome::Destination::parse(cli.result_file.as_deref()) {
+            Ok(destination) => outcome::enable(&cli.command_path, cli.command_target.as_deref(), destination),
+            Err(e) => {
+                eprintln!("{} {:#}", "错误:".red().bold(), e);
+                debug_report::write(&e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // 标准输入上的密码必须在其他任何读取之前取走
+    if let Err(e) = credentials::load_preset_password(cli.password_stdin, cli.password_file.as_deref()) {
+        eprintln!("{} {:#}", "错误:".red().bold(), e);
+        debug_report::write(&e);
+        outcome::exit(1, Some(&e));
+    }
+
+    if let Ok(config) = AppConfig::load() {
+        audit::init(&config);
+        credential_helper::init(&config);
+        sftp::set_retry_policy(config.sftp_retry_policy());
+    }
+
+    let result = run(cli).await;
+    // 报告按缓存的凭据脱敏，需在清空缓存之前写出
+    if let Err(ref e) = result {
+        debug_report::write(e);
+    }
+    // process::exit 不会执行析构，退出前主动清零缓存的密码
+    credentials::clear();
+    match result {
+        Ok(()) => outcome::finish(0, None),
+        Err(e) => {
+            let code = report_error(&e);
+            outcome::exit(code, Some(&e));
+        }
+    }
+}
 
-    let cli = Cli::parse();
+/// 开启的全局选项，写入诊断报告
+fn global_options(cli: &Cli) -> Vec<String> {
+    let flags = [
+        ("--ssh-debug", cli.ssh_debug),
+        ("--batch", cli.batch),
+        ("--no-table", cli.no_table),
+        ("--ascii", cli.ascii),
+        ("--unicode", cli.unicode),
+        ("--auth key", cli.auth == ssh::AuthMode::Key),
+        ("--password-file", cli.password_file.is_some()),
+        ("--password-stdin", cli.password_stdin),
+        ("--json-result", cli.json_result),
+        ("debug 构建", cfg!(debug_assertions)),
+    ];
+    flags.into_iter().filter(|(_, on)| *on).map(|(name, _)| name.to_string()).collect()
+}
 
-    if let Err(e) = run(cli).await {
+/// 输出错误并返回对应的退出码
+fn report_error(e: &anyhow::Error) -> i32 {
+    if let Some(hook) = e.downcast_ref::<hooks::HookFailed>() {
+        eprintln!("{} {}", "错误:".red().bold(), hook);
+        return hook.code;
+    }
+    if e.downcast_ref::<capture::OutputLimitExceeded>().is_some() {
+        eprintln!("{} {}", "错误:".red().bold(), e);
+        return capture::EXIT_CODE;
+    }
+    if e.downcast_ref::<connect::StalePassword>().is_some() {
+        eprintln!("{} {}", "错误:".red().bold(), e);
+        return connect::STALE_PASSWORD_EXIT_CODE;
+    }
+    if e.downcast_ref::<ssh::CommandTimedOut>().is_some() {
         eprintln!("{} {}", "错误:".red().bold(), e);
-        std::process::exit(1);
+        return ssh::TIMEOUT_EXIT_CODE;
+    }
+    if let Some(failed) = e.downcast_ref::<remote_copy::CopyFailed>() {
+        eprintln!("{} {}", "错误:".red().bold(), failed);
+        return failed.code;
     }
+    if e.downcast_ref::<interrupt::Cancelled>().is_some() {
+        eprintln!("{}", "传输已取消".yellow());
+        return interrupt::EXIT_CODE;
+    }
+    if e.downcast_ref::<interrupt::ConnectCancelled>().is_some() {
+        eprintln!("{}", "连接已取消".yellow());
+        return interrupt::EXIT_CODE;
+    }
+    eprintln!("{} {}", "错误:".red().bold(), e);
+    1
 }
 
 async fn run(cli: Cli) -> Result<()> {
@@ -43,12 +216,18 @@ async fn run(cli: Cli) -> Result<()> {
             identity_file,
             save_password,
             save_as,
+            record,
+            record_input,
+            no_hooks,
+            escape,
+            master,
         } => {
+            let mut prompter = prompt::Terminal;
             // 如果没有提供 target，显示交互式菜单
             let actual_target = if let Some(t) = target {
                 t
             } else {
-                match interactive_menu::show_connection_menu()? {
+                match interactive_menu::show_connection_menu(&mut prompter)? {
                     Some(t) => t,
                     None => {
                         println!("{}", "已取消连接".yellow());
@@ -59,7 +238,7 @@ async fn run(cli: Cli) -> Result<()> {
 
             // 检查是否是手动输入的连接（通过环境变量）
             let actual_port = if let Ok(manual_port) = std::env::var("MANUAL_CONNECTION_PORT") {
-                manual_port.parse().unwrap_or(port)
+                manual_port.parse().ok().or(port)
             } else {
                 port
             };
@@ -81,14 +260,51 @@ async fn run(cli: Cli) -> Result<()> {
             std::env::remove_var("MANUAL_CONNECTION_SAVE");
             std::env::remove_var("MANUAL_CONNECTION_NAME");
 
-            handle_connect_command(
-                &actual_target,
-                actual_port,
-                interactive,
+            if master {
+                master::ensure_supported()?;
+                let (config, jump) = target_configs(&actual_target, actual_port, identity_file, None)?;
+                let path = master::spawn(&actual_target, &config, jump.as_ref())?;
+                println!("{} 控制主连接已启动: {} ({})", glyphs::ok().green().bold(), actual_target, path.display());
+                println!("  之后的 exec/sftp 操作将复用该连接，使用 `master stop {}` 停止", actual_target);
+                return Ok(());
+            }
+
+            let record = record.map(|path| RecordOptions {
+                path: PathBuf::from(path),
+                include_input: record_input,
+            });
+
+            // 连接前/断开后钩子
+            let config = AppConfig::load()?;
+            let (pre_hook, post_hook) = if no_hooks {
+                (None, None)
+            } else {
+                config.hooks_for(Some(&actual_target))
+            };
+            let escape_char = config.escape_char_for(Some(&actual_target), escape.as_deref())?;
+            let hook_context = if pre_hook.is_some() || post_hook.is_some() {
+                Some(hook_context(&config, &actual_target, actual_port)?)
+            } else {
+                None
+            };
+
+            if let (Some(command), Some(context)) = (&pre_hook, &hook_context) {
+                hooks::run_pre_connect(command, context)?;
+            }
+
+            let options = ConnectOptions {
+                port: actual_port,
                 identity_file,
-                actual_save_password,
-                actual_save_as,
-            ).await?;
+                save_password: actual_save_password,
+                save_as: actual_save_as,
+            };
+            let result = handle_connect_command(&actual_target, &options, interactive, record, escape_char, &mut prompter).await;
+
+            if let (Some(command), Some(context)) = (&post_hook, &hook_context) {
+                hooks::run_post_disconnect(command, context);
+            }
+
+            result?;
         }
         
         Commands::Exec {
@@ -96,19 +312,148 @@ async fn run(cli: Cli) -> Result<()> {
             command,
             port,
             identity_file,
+            tty,
+            output_file,
+            max_output,
+            merge_output,
+            sudo,
+            timeout,
+        } => {
+            let timeout = timeout.filter(|secs| *secs > 0).map(std::time::Duration::from_secs);
+            if sudo {
+                // 连接前取得 sudo 密码，输错主密码时不会建立连接
+                let config = AppConfig::load()?;
+                let password = secrets::sudo_password(&config, &target)?;
+                let ssh_config = parse_target(&target, port, identity_file, &mut prompt::Terminal)?;
+                let mut client = SshClient::connect(ssh_config)?;
+                client.set_command_timeout(timeout);
+                println!("执行命令: sudo {}", command);
+                let input = Zeroizing::new(format!("{}\n", password.as_str()).into_bytes());
+                let result = client.exec_command_input(&secrets::sudo_command(&command), &input);
+                let exit_code = result.as_ref().ok().map(|(_, _, status)| *status);
+                audit_exec(client.config(), &format!("sudo {}", command), &result, exit_code);
+                let (output, stderr, status) = result?;
+                std::io::Write::write_all(&mut std::io::stdout(), &output)?;
+                eprint!("{}", stderr);
+                if status != 0 {
+                    drop(client);
+                    credentials::clear();
+                    outcome::exit(status, None);
+                }
+                return Ok(());
+            }
+            if !tty && output_file.is_none() && timeout.is_none() {
+                if let Some(master) = master::connect(&target)? {
+                    println!("执行命令: {}", command);
+                    master.exec(&command, &mut std::io::stdout(), &mut std::io::stderr())?;
+                    return Ok(());
+                }
+            }
+            let ssh_config = parse_target(&target, port, identity_file, &mut prompt::Terminal)?;
+            let mut client = SshClient::connect(ssh_config)?;
+            client.set_command_timeout(timeout);
+            if let Some(output) = output_file {
+                let output = PathBuf::from(output);
+                let result = capture::exec_to_file(&client, &command, &output, merge_output, max_output);
+                audit_exec(client.config(), &command, &result, result.as_ref().ok().copied());
+                let exit_status = result?;
+                eprintln!("{} 输出已写入 {}", glyphs::ok().green(), output.display());
+                if exit_status != 0 {
+                    drop(client);
+                    outcome::exit(exit_status, None);
+                }
+                return Ok(());
+            }
+            let terminal = InteractiveTerminal::new(&client);
+            if tty {
+                let result = terminal.exec_with_pty(&command);
+                audit_exec(client.config(), &command, &result, result.as_ref().ok().copied());
+                let exit_status = result?;
+                if exit_status != 0 {
+                    drop(client);
+                    outcome::exit(exit_status, None);
+                }
+            } else {
+                let result = terminal.exec_command(&command);
+                audit_exec(client.config(), &command, &result, None);
+                result?;
+            }
+        }
+        
+        Commands::Run {
+            target,
+            snippet,
+            vars,
+            port,
+            identity_file,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
+            // 在连接前展开占位符，缺少变量时不会向服务器发送任何内容
+            let config = AppConfig::load()?;
+            let template = config.find_snippet(Some(&target), &snippet)
+                .context(format!("命令片段 '{}' 不存在", snippet))?;
+            // {{secret:key}} 在运行时解密；显示和日志中只出现模板
+            let command = secrets::expand_snippet(&config, &target, template, &vars)?;
+
+            let ssh_config = parse_target(&target, port, identity_file, &mut prompt::Terminal)?;
             let client = SshClient::connect(ssh_config)?;
             let terminal = InteractiveTerminal::new(&client);
-            terminal.exec_command(&command)?;
+            let result = terminal.exec_command_shown(&command, template);
+            audit_exec(client.config(), &command, &result, None);
+            result?;
+        }
+        
+        Commands::Diag {
+            target,
+            port,
+            identity_file,
+            jump,
+            json,
+        } => {
+            let session = connect_target(&target, port, identity_file, jump, None)?;
+            let report = diag::collect(&target, session.client());
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).context("无法序列化诊断报告")?);
+            } else {
+                diag::print(&report);
+            }
         }
         
-        Commands::Sftp { action } => {
-            handle_sftp_command(action)?;
+        Commands::Sftp { keepalive, action } => {
+            handle_sftp_command(action, keepalive)?;
+        }
+
+        Commands::Config { action: ConfigCommands::RestoreBackup { index, list, yes } } => {
+            // 当前配置可能已损坏，不能先加载
+            handle_restore_backup(index, list, yes)?;
         }
 
         Commands::Config { action } => {
-            handle_config_command(action)?;
+            ConfigStore::update(|config| handle_config_command(config, action))?;
+        }
+
+        Commands::Hostkey { action } => {
+            handle_hostkey_command(action).await?;
+        }
+
+        Commands::Proc { action } => {
+            handle_proc_command(action)?;
+        }
+
+        Commands::Master { action } => {
+            handle_master_command(action)?;
+        }
+
+        Commands::Audit { action: AuditCommands::Show { since, connection } } => {
+            let config = AppConfig::load()?;
+            let path = config.defaults.audit_log.as_deref()
+                .context("未开启审计日志，请在配置文件的 [defaults] 中设置 audit_log")?;
+            let path = PathBuf::from(paths::expand(path));
+            let records = audit::filter(audit::read(&path)?, since, connection.as_deref(), chrono::Local::now());
+            if records.is_empty() {
+                println!("{}", "没有匹配的审计记录".yellow());
+            } else {
+                audit::print(&records);
+            }
         }
 
         Commands::Gui => {
@@ -120,21 +465,209 @@ async fn run(cli: Cli) -> Result<()> {
     Ok(())
 }
 
-fn handle_sftp_command(action: SftpCommands) -> Result<()> {
+fn handle_sftp_command(action: SftpCommands, keepalive: Option<u32>) -> Result<()> {
     match action {
         SftpCommands::Upload {
             target,
             local_path,
             remote_path,
+            targets,
+            parallel,
             port,
             identity_file,
+            jump,
+            remote_cwd,
+            create_dirs,
+            chmod,
+            chmod_dirs,
+            append,
+            skip_existing,
+            files_from,
+            null,
+            relative,
+            from_manifest,
+            parallel_chunks,
+            verify,
+            recursive,
+            links,
+            progress,
             no_progress,
+            json,
+            quiet,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
-            let client = SshClient::connect(ssh_config)?;
-            let sftp = SftpClient::new(&client)?;
-            sftp.upload_file(&local_path, &remote_path, !no_progress)?;
-            println!("{}", "上传成功!".green().bold());
+            let chmod_dirs = dir_mode_or_default(chmod_dirs)?;
+            let parallel_chunks = parallel_chunks.filter(|chunks| *chunks > 1);
+            let progress = progress_mode(progress, no_progress, quiet || json);
+            
+            if !targets.is_empty() {
+                // 位置参数依次为：本地文件、远程路径；所有密码在开始传输前依次询问
+                let (local_path, remote_path) = (target, local_path);
+                if !std::path::Path::new(&local_path).is_file() {
+                    anyhow::bail!("本地文件不存在或不是文件: {}", local_path);
+                }
+                let mut jobs = Vec::with_capacity(targets.len());
+                for target in targets {
+                    let (config, jump) = target_configs(&target, port, identity_file.clone(), jump.clone())?;
+                    jobs.push(fanout::FanoutTarget {
+                        remote_path: resolve_remote_path(&target, &remote_path)?,
+                        remote_cwd: remote_cwd_for(&target, remote_cwd.clone())?,
+                        name: target,
+                        config,
+                        jump,
+                    });
+                }
+                let options = fanout::FanoutOptions {
+                    create_dirs,
+                    file_mode: chmod,
+                    dir_mode: chmod_dirs,
+                    skip_existing,
+                    keepalive,
+                };
+                return upload_to_targets(jobs, &local_path, parallel, &options, json, quiet);
+            }
+            
+            if let Some(path) = from_manifest {
+                // 只有一个位置参数：远程目标目录
+                let remote_dir = resolve_remote_path(&target, &local_path)?;
+                let manifest = Manifest::read(Path::new(&path))?;
+                if manifest.entries.is_empty() {
+                    println!("{} 清单为空，没有需要上传的文件", glyphs::arrow().cyan());
+                    return Ok(());
+                }
+                let entries: HashMap<String, &manifest::ManifestEntry> = manifest.entries.iter()
+                    .map(|entry| (manifest.local_path(entry).to_string_lossy().into_owned(), entry))
+                    .collect();
+                let locals: Vec<String> = manifest.entries.iter()
+                    .map(|entry| manifest.local_path(entry).to_string_lossy().into_owned())
+                    .collect();
+                
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?
+                    .with_cancel_flag(cancel)
+                    .with_file_mode(chmod)
+                    .with_dir_mode(chmod_dirs)
+                    .with_skip_existing(skip_existing);
+                let remote_dir = resolve_in_session(&sftp, remote_cwd_for(&target, remote_cwd)?.as_deref(), &remote_dir)?;
+                sftp.mkdir_p(&remote_dir)?;
+                
+                let stats = transfer_file_list(&locals, |local| {
+                    let entry = entries[local];
+                    let remote = file_list::join(&remote_dir, &entry.path);
+                    if let Some(parent) = sftp::remote_parent(&remote) {
+                        sftp.mkdir_p(parent)?;
+                    }
+                    let stats = upload_with_progress(&sftp, local, &remote, progress)?;
+                    // 跳过的文件内容相同，同样恢复元数据
+                    if entry.mode.is_some() || entry.mtime.is_some() {
+                        sftp.set_metadata(&remote, entry.mode, entry.mtime)?;
+                    }
+                    Ok(stats)
+                })?;
+                if !quiet {
+                    print_transfer_summary("上传成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            
+            if let Some(source) = files_from {
+                // 只有一个位置参数：远程目标目录
+                let remote_dir = resolve_remote_path(&target, &local_path)?;
+                let entries = file_list::read(&source, null)?;
+                if entries.is_empty() {
+                    println!("{} 文件列表为空，没有需要上传的文件", glyphs::arrow().cyan());
+                    return Ok(());
+                }
+                
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?
+                    .with_cancel_flag(cancel)
+                    .with_file_mode(chmod)
+                    .with_dir_mode(chmod_dirs)
+                    .with_append(append)
+                    .with_skip_existing(skip_existing);
+                let remote_dir = resolve_in_session(&sftp, remote_cwd_for(&target, remote_cwd)?.as_deref(), &remote_dir)?;
+                sftp.mkdir_p(&remote_dir)?;
+                
+                let stats = transfer_file_list(&entries, |entry| {
+                    let remote = file_list::join(&remote_dir, &file_list::destination(entry, relative)?);
+                    if let Some(parent) = sftp::remote_parent(&remote) {
+                        sftp.mkdir_p(parent)?;
+                    }
+                    upload_with_progress(&sftp, entry, &remote, progress)
+                })?;
+                if !quiet {
+                    print_transfer_summary("上传成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            
+            if recursive {
+                let remote_dir = resolve_remote_path(&target, &remote_path.context("缺少远程目录路径")?)?;
+                if !Path::new(&local_path).is_dir() {
+                    anyhow::bail!("本地路径不是目录: {}", local_path);
+                }
+                
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?
+                    .with_cancel_flag(cancel)
+                    .with_file_mode(chmod)
+                    .with_dir_mode(chmod_dirs)
+                    .with_skip_existing(skip_existing);
+                let remote_dir = resolve_in_session(&sftp, remote_cwd_for(&target, remote_cwd)?.as_deref(), &remote_dir)?;
+                let stats = upload_dir_with_progress(&sftp, &local_path, &remote_dir, links, progress)?;
+                if !quiet {
+                    print_transfer_summary("上传成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            if Path::new(&local_path).is_dir() {
+                anyhow::bail!("本地路径是目录: {}（使用 --recursive 递归上传）", local_path);
+            }
+            
+            let remote_path = remote_path.context("缺少远程文件路径")?;
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            let from_stdin = local_path == "-";
+            if from_stdin && (parallel_chunks.is_some() || skip_existing != SkipExisting::Never) {
+                anyhow::bail!("从标准输入上传时不能使用 --parallel-chunks 或 --skip-existing");
+            }
+            if !from_stdin && !create_dirs && !append && skip_existing == SkipExisting::Never && parallel_chunks.is_none() && chmod.is_none() && chmod_dirs.is_none() && progress != ProgressMode::Json {
+                if let Some((master, remote_path)) = connect_master(&target, cwd.as_deref(), &remote_path)? {
+                    let stats = master.upload_file(&local_path, &remote_path)?;
+                    if !quiet {
+                        print_transfer_summary("上传成功!", &stats, json)?;
+                    }
+                    return Ok(());
+                }
+            }
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let cancel = interrupt::install_handler()?;
+            let sftp = SftpClient::new(session.client())?
+                .with_cancel_flag(cancel.clone())
+                .with_file_mode(chmod)
+                .with_dir_mode(chmod_dirs)
+                .with_append(append)
+                .with_skip_existing(skip_existing);
+            let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+            if create_dirs {
+                if let Some(parent) = sftp::remote_parent(&remote_path) {
+                    sftp.mkdir_p(parent)?;
+                }
+            }
+            let stats = match parallel_chunks {
+                Some(chunks) => {
+                    let options = chunked::ChunkOptions { chunks, verify, cancel };
+                    upload_chunked(&session, &sftp, &local_path, &remote_path, &options, progress)?
+                }
+                None if from_stdin => upload_stdin_with_progress(&sftp, &remote_path, progress)?,
+                None => upload_with_progress(&sftp, &local_path, &remote_path, progress)?,
+            };
+            if !quiet {
+                print_transfer_summary("上传成功!", &stats, json)?;
+            }
         }
         
         SftpCommands::Download {
@@ -143,430 +676,1486 @@ fn handle_sftp_command(action: SftpCommands) -> Result<()> {
             local_path,
             port,
             identity_file,
+            jump,
+            remote_cwd,
+            files_from,
+            null,
+            relative,
+            apply_remote_perms,
+            manifest,
+            no_manifest,
+            parallel_chunks,
+            verify,
+            recursive,
+            links,
+            progress,
             no_progress,
+            json,
+            quiet,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
-            let client = SshClient::connect(ssh_config)?;
-            let sftp = SftpClient::new(&client)?;
-            sftp.download_file(&remote_path, &local_path, !no_progress)?;
-            println!("{}", "下载成功!".green().bold());
+            let progress = progress_mode(progress, no_progress, quiet || json);
+            let parallel_chunks = parallel_chunks.filter(|chunks| *chunks > 1);
+            if (manifest.is_some() || no_manifest) && files_from.is_none() && !recursive {
+                anyhow::bail!("--manifest 和 --no-manifest 只能与 --files-from 或 --recursive 同用");
+            }
+            
+            if let Some(source) = files_from {
+                // 只有一个位置参数：本地目标目录
+                let local_dir = remote_path;
+                let entries = file_list::read(&source, null)?;
+                if entries.is_empty() {
+                    println!("{} 文件列表为空，没有需要下载的文件", glyphs::arrow().cyan());
+                    return Ok(());
+                }
+                
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?
+                    .with_cancel_flag(cancel)
+                    .with_remote_perms(apply_remote_perms);
+                let cwd = resolve_remote_cwd(&sftp, remote_cwd_for(&target, remote_cwd)?.as_deref())?;
+                // 默认在保留权限时记录元数据清单，说明哪些字段没有应用到本地
+                let manifest_path = manifest.map(PathBuf::from).or_else(|| {
+                    (apply_remote_perms && !no_manifest).then(|| PathBuf::from(&local_dir).join(manifest::DEFAULT_NAME))
+                });
+                let mut manifest = match manifest_path {
+                    Some(_) => Some(Manifest::new(&target, Path::new(&local_dir))?),
+                    None => None,
+                };
+                
+                let result = transfer_file_list(&entries, |entry| {
+                    // 列表中的路径相对于基准目录，本地也按列表中的路径保存
+                    let entry = resolve_remote_path(&target, entry)?;
+                    let remote = sftp.resolve_path(&entry, Some(&cwd))?;
+                    let destination = file_list::destination(entry.strip_prefix("~/").unwrap_or(&entry), relative)?;
+                    let local = PathBuf::from(&local_dir).join(&destination);
+                    if let Some(parent) = local.parent().filter(|parent| !parent.exists()) {
+                        std::fs::create_dir_all(parent)
+                            .context(format!("无法创建本地目录: {}", parent.display()))?;
+                        // 新建的本地目录使用对应远程目录的权限
+                        if let Some(remote_parent) = sftp::remote_parent(&remote).filter(|_| apply_remote_perms && relative) {
+                            sftp::set_local_mode(parent, sftp.stat(remote_parent)?.permissions)?;
+                        }
+                    }
+                    let stats = download_with_progress(&sftp, &remote, &local.to_string_lossy(), progress)?;
+                    if let Some(ref mut manifest) = manifest {
+                        manifest.record(&destination, &sftp.stat(&remote)?, apply_remote_perms && cfg!(unix));
+                    }
+                    Ok(stats)
+                });
+                // 部分文件失败时也写入已下载文件的清单
+                if let (Some(manifest), Some(path)) = (manifest, manifest_path) {
+                    manifest.write(&path)?;
+                    if !quiet && !json {
+                        println!("{} 元数据清单已写入 {}", glyphs::arrow().cyan(), path.display());
+                    }
+                }
+                let stats = result?;
+                if !quiet {
+                    print_transfer_summary("下载成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            
+            let local_path = local_path.context("缺少本地文件路径")?;
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            
+            if recursive {
+                if local_path == "-" {
+                    anyhow::bail!("递归下载时不能输出到标准输出");
+                }
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?
+                    .with_cancel_flag(cancel)
+                    .with_remote_perms(apply_remote_perms);
+                let remote_dir = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+                let manifest_path = manifest.map(PathBuf::from).or_else(|| {
+                    (apply_remote_perms && !no_manifest).then(|| PathBuf::from(&local_path).join(manifest::DEFAULT_NAME))
+                });
+                let mut manifest = match manifest_path {
+                    Some(_) => Some(Manifest::new(&target, Path::new(&local_path))?),
+                    None => None,
+                };
+                let result = download_dir_with_progress(&sftp, &remote_dir, &local_path, links, manifest.as_mut(), progress);
+                // 中途失败时也写入已下载文件的清单
+                if let (Some(manifest), Some(path)) = (manifest, manifest_path) {
+                    manifest.write(&path)?;
+                    if !quiet && !json {
+                        println!("{} 元数据清单已写入 {}", glyphs::arrow().cyan(), path.display());
+                    }
+                }
+                let stats = result?;
+                if !quiet {
+                    print_transfer_summary("下载成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            
+            let to_stdout = local_path == "-";
+            if to_stdout {
+                if parallel_chunks.is_some() || apply_remote_perms || json {
+                    anyhow::bail!("下载到标准输出时不能使用 --parallel-chunks、--apply-remote-perms 或 --json");
+                }
+                // 标准输出只写文件内容：不着色，进度条和提示都在标准错误上
+                colored::control::set_override(false);
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let cancel = interrupt::install_handler()?;
+                let sftp = SftpClient::new(session.client())?.with_cancel_flag(cancel);
+                let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+                download_stdout_with_progress(&sftp, &remote_path, progress)?;
+                return Ok(());
+            }
+            let use_master = progress != ProgressMode::Json && !apply_remote_perms && parallel_chunks.is_none();
+            let master = if use_master { connect_master(&target, cwd.as_deref(), &remote_path)? } else { None };
+            if let Some((master, remote_path)) = master {
+                let stats = master.download_file(&remote_path, &local_path)?;
+                if !quiet {
+                    print_transfer_summary("下载成功!", &stats, json)?;
+                }
+                return Ok(());
+            }
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let cancel = interrupt::install_handler()?;
+            let sftp = SftpClient::new(session.client())?
+                .with_cancel_flag(cancel.clone())
+                .with_remote_perms(apply_remote_perms);
+            let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+            let stats = match parallel_chunks {
+                Some(chunks) => {
+                    let options = chunked::ChunkOptions { chunks, verify, cancel };
+                    download_chunked(&session, &sftp, &remote_path, &local_path, &options, progress)?
+                }
+                None => download_with_progress(&sftp, &remote_path, &local_path, progress)?,
+            };
+            if !quiet {
+                print_transfer_summary("下载成功!", &stats, json)?;
+            }
+        }
+        
+        SftpCommands::Transfer { source, dest, atomic, chmod, progress, no_progress, json, quiet } => {
+            let progress = progress_mode(progress, no_progress, quiet || json);
+            let (source_target, source_path) = split_remote_spec(&source)?;
+            let (dest_target, dest_path) = split_remote_spec(&dest)?;
+            let source_path = resolve_remote_path(source_target, source_path)?;
+            let dest_path = resolve_remote_path(dest_target, dest_path)?;
+            
+            // 两台主机分别认证
+            println!("{} 源主机: {}", glyphs::arrow().cyan(), source_target);
+            let source_session = connect_target(source_target, None, None, None, keepalive)?;
+            println!("{} 目标主机: {}", glyphs::arrow().cyan(), dest_target);
+            let dest_session = connect_target(dest_target, None, None, None, keepalive)?;
+            
+            let cancel = interrupt::install_handler()?;
+            let source_sftp = SftpClient::new(source_session.client())?.with_cancel_flag(cancel.clone());
+            let dest_sftp = SftpClient::new(dest_session.client())?
+                .with_cancel_flag(cancel)
+                .with_file_mode(chmod);
+            let source_path = source_sftp.resolve_path(&source_path, None)?;
+            let dest_path = dest_sftp.resolve_path(&dest_path, None)?;
+            
+            let mut reporter = ProgressReporter::new(format!("传输: {}", source), &source, progress);
+            let result = relay::relay_file(
+                &source_sftp,
+                &source_path,
+                &dest_sftp,
+                &dest_path,
+                atomic,
+                Some(&mut |p| reporter.update(p)),
+            );
+            finish_progress(&reporter, &result, format!("传输完成: {}", dest), "传输已取消");
+            let stats = result?;
+            if !quiet {
+                print_transfer_summary("传输成功!", &stats, json)?;
+            }
         }
         
         SftpCommands::List {
             target,
             remote_path,
+            long,
+            refresh,
             port,
             identity_file,
+            jump,
+            remote_cwd,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
-            let client = SshClient::connect(ssh_config)?;
-            let sftp = SftpClient::new(&client)?;
-            let files = sftp.list_dir(&remote_path)?;
-            
-            println!("\n{} {}\n", "目录:".cyan().bold(), remote_path);
-            println!("{:<40} {:>12} {}", "名称", "大小", "类型");
-            println!("{}", "-".repeat(60));
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            if !long {
+                if let Some((master, remote_path)) = connect_master(&target, cwd.as_deref(), &remote_path)? {
+                    print_file_list(&remote_path, master.list_dir(&remote_path, refresh)?);
+                    return Ok(());
+                }
+            }
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+            let mut files = sftp.list_dir(&remote_path)?;
             
-            for file in files {
-                let file_type = if file.is_dir { "目录".blue() } else { "文件".normal() };
-                let size = if file.is_dir {
-                    "-".to_string()
-                } else {
-                    format_size(file.size)
-                };
-                println!("{:<40} {:>12} {}", file.name, size, file_type);
+            if long {
+                if !table::is_plain() {
+                    println!("\n{} {}\n", "目录:".cyan().bold(), remote_path);
+                }
+                sftp.resolve_links(&mut files);
+                let names = session.client().owner_names();
+                let mut list = Table::new()
+                    .column("权限", Align::Left)
+                    .column("属主", Align::Left)
+                    .column("属组", Align::Left)
+                    .column("大小", Align::Right)
+                    .truncated_column("名称", Align::Left)
+                    .separator();
+                for file in files {
+                    let size = if file.is_dir || file.is_symlink {
+                        "-".to_string()
+                    } else {
+                        format_size(file.size)
+                    };
+                    let display = file.display_name();
+                    let name = match file.link_target {
+                        Some(ref link) if file.link_broken => {
+                            Cell::new(format!("{} -> {}", display, sftp::escape_name(link))).color(Color::Red)
+                        }
+                        Some(ref link) => Cell::new(format!("{} -> {}", display, sftp::escape_name(link))).color(Color::Cyan),
+                        None if file.is_dir => Cell::new(display).color(Color::Blue),
+                        None => Cell::new(display),
+                    };
+                    list.row(vec![
+                        file.mode_string().into(),
+                        file.owner(names).into(),
+                        file.group(names).into(),
+                        size.into(),
+                        name,
+                    ]);
+                }
+                list.print();
+                return Ok(());
             }
+            
+            print_file_list(&remote_path, files);
         }
         
         SftpCommands::Mkdir {
             target,
             remote_path,
+            mode,
             port,
             identity_file,
+            jump,
+            remote_cwd,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
-            let client = SshClient::connect(ssh_config)?;
-            let sftp = SftpClient::new(&client)?;
-            sftp.mkdir(&remote_path)?;
-            println!("{} 目录创建成功: {}", "✓".green().bold(), remote_path);
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            let mode = dir_mode_or_default(mode)?;
+            // 控制主连接只能使用默认权限创建目录
+            let master = if mode.is_none() { connect_master(&target, cwd.as_deref(), &remote_path)? } else { None };
+            let remote_path = if let Some((master, remote_path)) = master {
+                master.mkdir(&remote_path)?;
+                remote_path
+            } else {
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let sftp = SftpClient::new(session.client())?.with_dir_mode(mode);
+                let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+                sftp.mkdir(&remote_path)?;
+                remote_path
+            };
+            println!("{} 目录创建成功: {}", glyphs::ok().green().bold(), remote_path);
         }
         
-        SftpCommands::Remove {
+        SftpCommands::Copy {
             target,
-            remote_path,
+            source,
+            dest,
+            recursive,
+            atomic,
+            no_preflight,
             port,
             identity_file,
+            jump,
+            remote_cwd,
         } => {
-            let ssh_config = parse_target(&target, port, identity_file)?;
-            let client = SshClient::connect(ssh_config)?;
-            let sftp = SftpClient::new(&client)?;
-            sftp.remove_file(&remote_path)?;
-            println!("{} 文件删除成功: {}", "✓".green().bold(), remote_path);
+            let source = resolve_remote_path(&target, &source)?;
+            let dest = resolve_remote_path(&target, &dest)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?.with_cancel_flag(interrupt::install_handler()?);
+            let resolved = resolve_remote_cwd(&sftp, cwd.as_deref())?;
+            let source = sftp.resolve_path(&source, Some(&resolved))?;
+            let dest = sftp.resolve_path(&dest, Some(&resolved))?;
+            
+            // 复制目录前统计总量，交互运行时确认后再开始
+            let mut total_bytes = None;
+            if recursive && !no_preflight && sftp.stat(&source)?.is_dir {
+                let totals = preflight::scan_remote(&sftp, &source, Links::Skip)?;
+                let summary = totals.summary("复制");
+                if std::io::stdin().is_terminal() && !prompt::is_batch() {
+                    if !prompt::confirm_default_yes(&format!("{}，继续?", summary))? {
+                        println!("{} 已取消", glyphs::arrow().cyan());
+                        return Ok(());
+                    }
+                } else {
+                    println!("{} {}", glyphs::arrow().cyan(), summary);
+                }
+                total_bytes = Some(totals.bytes);
+            }
+            
+            let options = remote_copy::CopyOptions { recursive, atomic, total_bytes };
+            let mut reporter = ProgressReporter::new(format!("复制: {}", source), &source, ProgressMode::Bar);
+            let result = remote_copy::copy(session.client(), &sftp, &source, &dest, options, Some(&mut |p| reporter.update(p)));
+            match &result {
+                // 只有通过 SFTP 复制时才有进度条
+                Ok(_) => reporter.abandon("复制完成".to_string()),
+                Err(e) if e.downcast_ref::<interrupt::Cancelled>().is_some() => reporter.abandon("复制已取消".to_string()),
+                Err(e) => reporter.fail(e),
+            }
+            let method = result?;
+            let via = match method {
+                remote_copy::CopyMethod::Shell => "远程 cp",
+                remote_copy::CopyMethod::Sftp => "SFTP",
+            };
+            println!("{} 复制完成（{}）: {} -> {}", glyphs::ok().green().bold(), via, source, dest);
         }
-    }
-    
-    Ok(())
-}
-
-fn handle_config_command(action: ConfigCommands) -> Result<()> {
-    let mut config = AppConfig::load()?;
-    
-    match action {
-        ConfigCommands::Add {
-            name,
-            host,
-            username,
+        
+        SftpCommands::Remove {
+            target,
+            remote_path,
             port,
-            use_key,
             identity_file,
-            public_key,
+            jump,
+            remote_cwd,
+            trash,
         } => {
-            let connection = if use_key {
-                let private_key = identity_file
-                    .context("使用公钥认证时必须提供 --identity-file")?;
-                SavedConnection::new_publickey(name.clone(), host, port, username, private_key, public_key)
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let cwd = remote_cwd_for(&target, remote_cwd)?;
+            if trash {
+                // 跨文件系统时需要在远程执行 cp，不经过控制连接
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let sftp = SftpClient::new(session.client())?;
+                let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+                let entry = Trash::open(session.client(), &sftp, &AppConfig::load()?)?.move_to_trash(&remote_path)?;
+                println!("{} 已移到回收站: {}（编号 {}）", glyphs::ok().green().bold(), remote_path, entry.id);
+                println!("{} 恢复: sftp trash restore {} {}", glyphs::arrow().cyan(), target, entry.id);
+                return Ok(());
+            }
+            let remote_path = if let Some((master, remote_path)) = connect_master(&target, cwd.as_deref(), &remote_path)? {
+                master.remove_file(&remote_path)?;
+                remote_path
             } else {
-                SavedConnection::new_password(name.clone(), host, port, username)
+                let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+                let sftp = SftpClient::new(session.client())?;
+                let remote_path = resolve_in_session(&sftp, cwd.as_deref(), &remote_path)?;
+                sftp.remove_file(&remote_path)?;
+                remote_path
             };
-            
-            config.add_connection(connection);
-            config.save()?;
-            println!("{} 连接 '{}' 已添加", "✓".green().bold(), name);
+            println!("{} 文件删除成功: {}", glyphs::ok().green().bold(), remote_path);
         }
         
-        ConfigCommands::List => {
-            let connections = config.list_connections();
-
-            if connections.is_empty() {
-                println!("没有保存的连接");
-                return Ok(());
-            }
-
-            println!("\n{}\n", "保存的连接:".cyan().bold());
-
-            let first_name = connections.first().map(|c| c.name.clone());
-
-            for conn in connections {
-                let is_default = config.default_connection.as_deref() == Some(&conn.name);
-                let marker = if is_default { "*" } else { " " };
-                let password_marker = if conn.has_saved_password() { "🔑" } else { "" };
-
-                println!("{} [{}] {}@{}:{} ({}) {}",
-                    marker.green().bold(),
-                    conn.name.yellow().bold(),
-                    conn.username.cyan(),
-                    conn.host,
-                    conn.port,
-                    conn.auth_type,
-                    password_marker);
+        SftpCommands::Edit {
+            target,
+            remote_path,
+            editor,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = sftp.resolve_path(&remote_path, None)?;
+            let outcome = edit::edit_remote(
+                &sftp,
+                &remote_path,
+                |path| edit::run_editor(editor.as_deref(), path),
+                || {
+                    println!("{} 远程文件在编辑期间已被修改: {}", glyphs::fail().red(), remote_path);
+                    if prompt::is_batch() {
+                        return Ok(false);
+                    }
+                    prompt::confirm("仍然用本地修改覆盖?")
+                },
+            )?;
+            match outcome {
+                edit::EditOutcome::Unchanged => println!("{} 内容未变化，未上传", glyphs::arrow().cyan()),
+                edit::EditOutcome::Uploaded(bytes) => {
+                    println!("{} 已保存到 {} ({})", glyphs::ok().green().bold(), remote_path, format_size(bytes));
+                }
+                edit::EditOutcome::Aborted => println!("{} 已放弃修改", glyphs::fail().red()),
             }
-
-            println!("\n{}", "提示:".yellow().bold());
-            println!("  {} 表示默认连接", "*".green().bold());
-            println!("  {} 表示已保存密码", "🔑");
-            println!("  使用 {} 连接，例如: connect {}",
-                "[连接名称]".yellow(),
-                first_name.as_deref().unwrap_or("myserver"));
         }
         
-        ConfigCommands::Remove { name } => {
-            config.remove_connection(&name)?;
-            config.save()?;
-            println!("{} 连接 '{}' 已删除", "✓".green().bold(), name);
+        SftpCommands::Diff {
+            target,
+            local_path,
+            remote_path,
+            brief,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = sftp.resolve_path(&remote_path, None)?;
+            match diff::compare(&sftp, &local_path, &remote_path, brief)? {
+                diff::Comparison::Identical => return Ok(()),
+                diff::Comparison::BinaryDiffer => println!("二进制文件 {} 和 {} 不同", local_path, remote_path),
+                diff::Comparison::TextDiffer(None) => println!("文件 {} 和 {} 不同", local_path, remote_path),
+                diff::Comparison::TextDiffer(Some(text)) => {
+                    for (index, line) in text.lines().enumerate() {
+                        match line.chars().next() {
+                            // 前两行是文件名
+                            _ if index < 2 => println!("{}", line.bold()),
+                            Some('@') => println!("{}", line.cyan()),
+                            Some('+') => println!("{}", line.green()),
+                            Some('-') => println!("{}", line.red()),
+                            _ => println!("{}", line),
+                        }
+                    }
+                }
+            }
+            // 与 diff 一致：文件不同时退出码为 1
+            drop(session);
+            outcome::exit(1, None);
         }
         
-        ConfigCommands::SetDefault { name } => {
-            config.set_default(&name)?;
-            config.save()?;
-            println!("{} '{}' 已设为默认连接", "✓".green().bold(), name);
+        SftpCommands::Grep {
+            target,
+            pattern,
+            remote_path,
+            recursive,
+            ignore_case,
+            files_with_matches,
+            binary,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = sftp.resolve_path(&remote_path, None)?;
+            let options = grep::GrepOptions { recursive, ignore_case, files_with_matches, binary };
+            let hits = grep::search(session.client(), &sftp, &pattern, &remote_path, &options, &mut |hit| match hit {
+                grep::Hit::Line { path, number, text } => {
+                    println!("{}:{}:{}", sftp::escape_name(&path).magenta(), number.to_string().green(), text);
+                }
+                grep::Hit::File(path) => println!("{}", sftp::escape_name(&path).magenta()),
+            })?;
+            // 与 grep 一致：没有匹配时退出码为 1
+            if hits == 0 {
+                outcome::exit(1, None);
+            }
         }
         
-        ConfigCommands::Show { name } => {
-            let conn = config.get_connection(&name)
-                .context(format!("连接 '{}' 不存在", name))?;
-            
-            println!("\n{} {}\n", "连接详情:".cyan().bold(), name);
-            println!("  主机:     {}", conn.host);
-            println!("  端口:     {}", conn.port);
-            println!("  用户名:   {}", conn.username);
-            println!("  认证方式: {}", conn.auth_type);
-            
-            if let Some(ref key) = conn.private_key_path {
-                println!("  私钥:     {}", key);
+        SftpCommands::Checksum {
+            target,
+            remote_path,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = sftp.resolve_path(&remote_path, None)?;
+            let digest = remote_os::sha256(session.client(), &sftp, &remote_path)?;
+            println!("{}  {}", digest, remote_path);
+        }
+        
+        SftpCommands::Df {
+            target,
+            remote_path,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_path = sftp.resolve_path(&remote_path, None)?;
+            let free = remote_os::disk_free(session.client(), &sftp, &remote_path)?;
+            let used = free.total.saturating_sub(free.available);
+            let percent = if free.total == 0 { 0.0 } else { used as f64 * 100.0 / free.total as f64 };
+            println!("{} {}", "路径:".cyan().bold(), remote_path);
+            println!("  总计: {}", format_size(free.total));
+            println!("  已用: {} ({:.0}%)", format_size(used), percent);
+            println!("  可用: {}", format_size(free.available));
+        }
+        
+        SftpCommands::Bench {
+            target,
+            remote_dir,
+            size,
+            parallel_chunks,
+            json,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_dir = resolve_remote_path(&target, &remote_dir)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let cancel = interrupt::install_handler()?;
+            let sftp = SftpClient::new(session.client())?;
+            let remote_dir = sftp.resolve_path(&remote_dir, None)?;
+            if !json {
+                println!("{} 正在测试 {} 的传输速度（{} MB）...", glyphs::arrow().cyan(), remote_dir, size);
             }
-            if let Some(ref key) = conn.public_key_path {
-                println!("  公钥:     {}", key);
+            let results = chunked::bench(&session, &sftp, &remote_dir, size * 1024 * 1024, parallel_chunks, cancel)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&results).context("无法序列化测试结果")?);
+                return Ok(());
+            }
+            let mut table = Table::new()
+                .column("分块数", Align::Right)
+                .column("上传", Align::Right)
+                .column("下载", Align::Right)
+                .separator();
+            for result in &results {
+                table.row(vec![
+                    result.chunks.to_string().into(),
+                    progress::format_speed(result.upload.throughput()).into(),
+                    progress::format_speed(result.download.throughput()).into(),
+                ]);
             }
+            table.print();
         }
         
-        ConfigCommands::ShowPassword { name } => {
-            // 检查是否有保存的密码
-            let connections_with_password: Vec<_> = if let Some(ref name) = name {
-                // 显示指定连接的密码
-                let conn = config.get_connection(name)
-                    .context(format!("连接 '{}' 不存在", name))?;
-                if !conn.has_saved_password() {
-                    anyhow::bail!("连接 '{}' 没有保存密码", name);
-                }
-                vec![conn.clone()]
+        SftpCommands::Tail {
+            target,
+            remote_path,
+            lines,
+            follow,
+            retry,
+            exec,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let remote_path = resolve_remote_path(&target, &remote_path)?;
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let options = tail::TailOptions {
+                lines,
+                follow,
+                retry,
+                interval: std::time::Duration::from_secs(1),
+            };
+            // Ctrl+C 只是结束跟踪，正常退出
+            let stop = interrupt::install_handler()?;
+            let mut stdout = std::io::stdout().lock();
+            if exec {
+                tail::tail_exec(session.client(), &remote_path, &options, &stop, &mut stdout)?;
             } else {
-                // 显示所有有保存密码的连接
-                config.list_connections()
-                    .into_iter()
-                    .filter(|c| c.has_saved_password())
-                    .cloned()
-                    .collect()
+                let sftp = SftpClient::new(session.client())?;
+                let remote_path = sftp.resolve_path(&remote_path, None)?;
+                tail::tail_sftp(&sftp, &remote_path, &options, &stop, &mut stdout)?;
+            }
+        }
+        
+        SftpCommands::Watch {
+            target,
+            local_dir,
+            remote_dir,
+            port,
+            identity_file,
+            jump,
+            exclude,
+            delete,
+            debounce,
+            modify_window,
+            size_only,
+            links,
+        } => {
+            let mut options = watch::WatchOptions {
+                local_dir: PathBuf::from(local_dir),
+                remote_dir: resolve_remote_path(&target, &remote_dir)?,
+                excludes: exclude,
+                delete,
+                debounce: std::time::Duration::from_millis(debounce),
+                compare: if size_only {
+                    watch::Compare::SizeOnly
+                } else {
+                    watch::Compare::ModifyWindow(modify_window)
+                },
+                links,
             };
+            let mut session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            options.remote_dir = SftpClient::new(session.client())?.resolve_path(&options.remote_dir, None)?;
+            let stop = interrupt::install_handler()?;
+            watch::run(&mut session, &options, stop)?;
+        }
+        
+        SftpCommands::Batch {
+            target,
+            file,
+            port,
+            identity_file,
+            jump,
+        } => {
+            let content = std::fs::read_to_string(&file)
+                .context(format!("无法读取批处理文件: {}", file))?;
+            let lines = batch::parse(&content)?
+                .into_iter()
+                .map(|mut line| {
+                    line.command = line.command.map_remote(|path| resolve_remote_path(&target, path))?;
+                    Ok(line)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let results = batch::run(&mut session, &lines)?;
+            batch::print_summary(&results, session.reconnects());
+        }
+        
+        SftpCommands::Bookmark { action } => {
+            ConfigStore::update(|config| handle_bookmark_command(config, action))?;
+        }
+        
+        SftpCommands::Trash { action } => {
+            handle_trash_command(action, keepalive)?;
+        }
+    }
+    
+    Ok(())
+}
 
-            if connections_with_password.is_empty() {
-                println!("{}", "没有保存密码的连接".yellow());
+/// 打印目录列表（名称、大小、类型）
+fn print_file_list(remote_path: &str, files: Vec<sftp::FileInfo>) {
+    if !table::is_plain() {
+        println!("\n{} {}\n", "目录:".cyan().bold(), remote_path);
+    }
+    let mut list = Table::new()
+        .truncated_column("名称", Align::Left)
+        .column("大小", Align::Right)
+        .column("类型", Align::Left)
+        .separator();
+    
+    for file in files {
+        let file_type = if file.is_symlink {
+            Cell::new("链接").color(Color::Cyan)
+        } else if file.is_dir {
+            Cell::new("目录").color(Color::Blue)
+        } else {
+            Cell::new("文件")
+        };
+        let size = if file.is_dir {
+            "-".to_string()
+        } else {
+            format_size(file.size)
+        };
+        list.row(vec![file.display_name().into(), size.into(), file_type]);
+    }
+    list.print();
+}
+
+fn handle_trash_command(action: TrashCommands, keepalive: Option<u32>) -> Result<()> {
+    let config = AppConfig::load()?;
+    match action {
+        TrashCommands::List { target, port, identity_file, jump } => {
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let trash = Trash::open(session.client(), &sftp, &config)?;
+            let entries = trash.list()?;
+            if entries.is_empty() {
+                println!("回收站 {} 是空的", trash.root());
+                return Ok(());
+            }
+            if !table::is_plain() {
+                println!("\n{} {}\n", "回收站:".cyan().bold(), trash.root());
+            }
+            let mut list = Table::new()
+                .column("编号", Align::Left)
+                .truncated_column("原路径", Align::Left)
+                .column("大小", Align::Right)
+                .separator();
+            for entry in entries {
+                let size = if entry.is_dir { "目录".to_string() } else { format_size(entry.size) };
+                list.row(vec![Cell::new(entry.id).color(Color::Yellow), entry.original.into(), size.into()]);
+            }
+            list.print();
+        }
+        
+        TrashCommands::Restore { target, id, port, identity_file, jump } => {
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let entry = Trash::open(session.client(), &sftp, &config)?.restore(&id)?;
+            println!("{} 已恢复: {}", glyphs::ok().green().bold(), entry.original);
+        }
+        
+        TrashCommands::Empty { target, yes, port, identity_file, jump } => {
+            let session = connect_target(&target, port, identity_file, jump, keepalive)?;
+            let sftp = SftpClient::new(session.client())?;
+            let trash = Trash::open(session.client(), &sftp, &config)?;
+            let count = trash.list()?.len();
+            if count == 0 {
+                println!("回收站 {} 是空的", trash.root());
+                return Ok(());
+            }
+            if !yes && !prompt::confirm(&format!("永久删除回收站 {} 中的 {} 项?", trash.root(), count))? {
+                println!("{}", "已取消".yellow());
                 return Ok(());
             }
+            let removed = trash.empty()?;
+            println!("{} 已清空回收站，删除了 {} 项", glyphs::ok().green().bold(), removed);
+        }
+    }
+    
+    Ok(())
+}
 
-            // 检查主密码是否存在
-            if !CryptoManager::has_master_password() {
-                anyhow::bail!("未设置主密码，无法解密");
+fn handle_bookmark_command(config: &mut ConfigStore, action: BookmarkCommands) -> Result<()> {
+    match action {
+        BookmarkCommands::Add { target, name, remote_path } => {
+            config::validate_bookmark_name(&name)?;
+            let conn = config.connections.get_mut(&target)
+                .context(format!("连接 '{}' 不存在（书签只能添加到保存的连接）", target))?;
+            conn.bookmarks.insert(name.clone(), remote_path.clone());
+            config.save()?;
+            println!("{} 书签 @{} -> {} 已添加", glyphs::ok().green().bold(), name, remote_path);
+        }
+        
+        BookmarkCommands::List { target } => {
+            let conn = config.get_connection(&target)
+                .context(format!("连接 '{}' 不存在", target))?;
+            
+            if conn.bookmarks.is_empty() {
+                println!("连接 '{}' 没有书签", target);
+                return Ok(());
+            }
+            
+            let mut bookmarks: Vec<_> = conn.bookmarks.iter().collect();
+            bookmarks.sort();
+            if !table::is_plain() {
+                println!("\n{} {}\n", "书签:".cyan().bold(), target);
             }
+            let mut list = Table::without_header().indent(2).column("书签", Align::Left).truncated_column("路径", Align::Left);
+            for (name, path) in bookmarks {
+                list.row(vec![Cell::new(format!("@{}", name)).color(Color::Yellow).bold(), path.as_str().into()]);
+            }
+            list.print();
+        }
+        
+        BookmarkCommands::Remove { target, name } => {
+            let conn = config.connections.get_mut(&target)
+                .context(format!("连接 '{}' 不存在", target))?;
+            conn.bookmarks.remove(&name)
+                .context(format!("书签不存在: @{}", name))?;
+            config.save()?;
+            println!("{} 书签 @{} 已删除", glyphs::ok().green().bold(), name);
+        }
+    }
+    
+    Ok(())
+}
 
-            // 获取主密码
-            println!("{}", "需要主密码来解密保存的密码".yellow().bold());
-            let master_password = rpassword::prompt_password("请输入主密码: ")
-                .context("无法读取主密码")?;
+/// 解析远程路径中的 `@书签`
+fn resolve_remote_path(target: &str, remote_path: &str) -> Result<String> {
+    if !remote_path.starts_with('@') {
+        return Ok(remote_path.to_string());
+    }
+    AppConfig::load()?.resolve_bookmark(target, remote_path)
+}
 
-            if master_password.is_empty() {
-                anyhow::bail!("主密码不能为空");
-            }
+/// 相对远程路径的基准目录（未解析）：`--remote-cwd` 优先，其次为保存的连接的 default_remote_dir
+fn remote_cwd_for(target: &str, remote_cwd: Option<String>) -> Result<Option<String>> {
+    match remote_cwd {
+        Some(dir) => resolve_remote_path(target, &dir).map(Some),
+        None => Ok(AppConfig::load()?.get_connection(target).and_then(|c| c.default_remote_dir.clone())),
+    }
+}
 
-            // 创建加密管理器
-            let crypto_manager = CryptoManager::new(&master_password)
-                .context("创建加密管理器失败")?;
+/// 基准目录：展开 `~` 后通过 realpath 解析（同时确认目录存在），未设置时为登录目录
+fn resolve_remote_cwd(sftp: &SftpClient, cwd: Option<&str>) -> Result<String> {
+    match cwd {
+        Some(dir) => sftp.realpath(&sftp.resolve_path(dir, None)?).context(format!("远程工作目录无效: {}", dir)),
+        None => sftp.home_dir(),
+    }
+}
 
-            // 解密并显示密码
-            println!("\n{}\n", "已保存的密码:".cyan().bold());
+/// 把远程路径解析为绝对路径：展开 `~`，相对路径以基准目录（未设置时为登录目录）为准
+fn resolve_in_session(sftp: &SftpClient, cwd: Option<&str>, remote_path: &str) -> Result<String> {
+    let cwd = resolve_remote_cwd(sftp, cwd)?;
+    sftp.resolve_path(remote_path, Some(&cwd))
+}
 
-            for conn in connections_with_password {
-                let password_info = if conn.auth_type == "password" {
-                    // 密码认证
-                    if let Some(ref encrypted) = conn.encrypted_password {
-                        match crypto_manager.decrypt(encrypted) {
-                            Ok(password) => format!("{}", password.green()),
-                            Err(e) => format!("{}", format!("解密失败: {}", e).red()),
-                        }
-                    } else {
-                        "无密码".red().to_string()
-                    }
-                } else if conn.auth_type == "publickey" {
-                    // 公钥认证 - 显示私钥密码
-                    if let Some(ref encrypted) = conn.encrypted_passphrase {
-                        match crypto_manager.decrypt(encrypted) {
-                            Ok(passphrase) => format!("{}", passphrase.green()),
-                            Err(e) => format!("{}", format!("解密失败: {}", e).red()),
-                        }
-                    } else {
-                        "无私钥密码".red().to_string()
+/// 记录一次命令执行，`exit_code` 为空表示退出码未知
+fn audit_exec<T>(config: &SshConfig, command: &str, result: &Result<T>, exit_code: Option<i32>) {
+    let mut event = audit::Event::for_config(audit::Operation::Exec, config).command(command);
+    if let Some(code) = exit_code {
+        event = event.exit_code(code);
+    }
+    audit::record(event, result);
+}
+
+/// 连接控制主连接，并通过它把 `remote_path` 解析为绝对路径（规则与 [`resolve_in_session`] 相同）
+///
+/// 主连接逐个处理请求，查询登录目录、解析基准目录和之后的操作各用一个连接。
+fn connect_master(target: &str, cwd: Option<&str>, remote_path: &str) -> Result<Option<(master::MasterClient, String)>> {
+    let Some(probe) = master::connect(target)? else {
+        return Ok(None);
+    };
+    let home = probe.realpath(".")?;
+    let cwd = match cwd {
+        Some(dir) => match master::connect(target)? {
+            Some(master) => master
+                .realpath(&sftp::resolve_remote_path(dir, &home, &home)?)
+                .context(format!("远程工作目录无效: {}", dir))?,
+            None => return Ok(None),
+        },
+        None => home.clone(),
+    };
+    let remote_path = sftp::resolve_remote_path(remote_path, &cwd, &home)?;
+    Ok(master::connect(target)?.map(|master| (master, remote_path)))
+}
+
+fn handle_config_command(config: &mut ConfigStore, action: ConfigCommands) -> Result<()> {
+    match action {
+        ConfigCommands::Add {
+            name,
+            host,
+            username,
+            port,
+            use_key,
+            identity_file,
+            public_key,
+            use_agent,
+            pre_connect,
+            post_disconnect,
+            credential_helper,
+            jump,
+            escape,
+            check,
+            check_auth,
+        } => {
+            if let Some(ref value) = escape {
+                escape::parse_escape_char(value)?;
+            }
+            let mut connection = if use_agent {
+                SavedConnection::new_agent(name.clone(), host, port, username)
+            } else if use_key {
+                let private_key = identity_file
+                    .context("使用公钥认证时必须提供 --identity-file")?;
+                SavedConnection::new_publickey(name.clone(), host, port, username, private_key, public_key)
+            } else {
+                SavedConnection::new_password(name.clone(), host, port, username)
+            };
+            connection.pre_connect_command = pre_connect;
+            connection.post_disconnect_command = post_disconnect;
+            connection.credential_helper = credential_helper;
+            connection.jump_host = jump;
+            connection.escape_char = escape;
+            
+            if check || check_auth {
+                let auth = check_auth.then(|| auth_check(&connection));
+                println!("{} 正在检查连接 '{}'...", glyphs::arrow().cyan(), name);
+                let result = check::run(&connection, auth);
+                if !result.passed && !prompt::confirm("检查未通过，仍然保存?")? {
+                    println!("{}", "已取消".yellow());
+                    return Ok(());
+                }
+                connection.server_info = result.server;
+            }
+            
+            config.add_connection(connection);
+            config.save()?;
+            println!("{} 连接 '{}' 已添加", glyphs::ok().green().bold(), name);
+        }
+        
+        ConfigCommands::List => {
+            OfflineContext::enter(config).list()?;
+        }
+        
+        ConfigCommands::Remove { name } => {
+            config.remove_connection(&name)?;
+            config.save()?;
+            println!("{} 连接 '{}' 已删除", glyphs::ok().green().bold(), name);
+        }
+        
+        ConfigCommands::SetDefault { name } => {
+            config.set_default(&name)?;
+            config.save()?;
+            println!("{} '{}' 已设为默认连接", glyphs::ok().green().bold(), name);
+        }
+        
+        ConfigCommands::Show { name } => {
+            OfflineContext::enter(config).show(&name)?;
+        }
+        
+        ConfigCommands::Edit { name, note, color, remote_dir } => {
+            let color = match color.as_deref().map(str::trim) {
+                Some("") => Some(None),
+                Some(color) => Some(Some(config::parse_label_color(color)?)),
+                None => None,
+            };
+            let conn = config.connections.get_mut(&name)
+                .context(format!("连接 '{}' 不存在", name))?;
+            if let Some(note) = note {
+                let note = note.trim();
+                conn.note = (!note.is_empty()).then(|| note.to_string());
+            }
+            if let Some(color) = color {
+                conn.color = color;
+            }
+            if let Some(dir) = remote_dir {
+                let dir = dir.trim();
+                conn.default_remote_dir = (!dir.is_empty()).then(|| dir.to_string());
+            }
+            config.save()?;
+            println!("{} 连接 '{}' 已更新", glyphs::ok().green().bold(), name);
+        }
+        
+        ConfigCommands::Test { name } => {
+            let connection = config.get_connection(&name)
+                .context(format!("连接 '{}' 不存在", name))?
+                .clone();
+            println!("{} 正在检查连接 '{}'...", glyphs::arrow().cyan(), name);
+            let result = check::run(&connection, Some(auth_check(&connection)));
+            
+            if let Some(server) = result.server {
+                // 登录时可能刚记录了主机指纹，保存时会合并进来
+                if let Some(conn) = config.connections.get_mut(&name) {
+                    conn.server_info = Some(server);
+                    config.save()?;
+                }
+            }
+            if !result.passed {
+                anyhow::bail!("连接 '{}' 检查未通过", name);
+            }
+            println!("{} 连接 '{}' 检查通过", glyphs::ok().green().bold(), name);
+        }
+        
+        ConfigCommands::TestCredentialHelper { name } => {
+            let connection = config.get_connection(&name)
+                .context(format!("连接 '{}' 不存在", name))?;
+            let command = config.credential_helper_for(Some(&name))
+                .context(format!("连接 '{}' 没有配置凭据助手（credential_helper）", name))?;
+            let request = credential_helper::HelperRequest::for_connection(connection);
+            println!("{} 正在执行凭据助手: {}", glyphs::arrow().cyan(), request.render(command));
+            let started = std::time::Instant::now();
+            let secret = credential_helper::run(command, &request, credential_helper::TIMEOUT)?;
+            println!(
+                "{} 凭据助手返回了{}（{} 个字符，用时 {:.1} 秒），未连接服务器",
+                glyphs::ok().green().bold(),
+                request.kind.label(),
+                secret.chars().count(),
+                started.elapsed().as_secs_f64()
+            );
+        }
+        
+        ConfigCommands::ResetFingerprint { name } => {
+            match config.reset_fingerprint(&name)? {
+                Some(old) => {
+                    config.save()?;
+                    println!("{} 已清除连接 '{}' 的主机密钥指纹 {}", glyphs::ok().green().bold(), name, old);
+                    println!("{} 下次连接成功时将记录新的指纹", glyphs::arrow().cyan());
+                }
+                None => println!("{} 连接 '{}' 尚未记录主机密钥指纹", glyphs::arrow().cyan(), name),
+            }
+        }
+        
+        ConfigCommands::ShowPassword { name } => {
+            OfflineContext::enter(config).show_password(&mut prompt::Terminal, name.as_deref())?;
+        }
+
+        ConfigCommands::ImportSsh { hosts, overwrite, apply } => {
+            let ssh_config = OpenSshConfig::load()?;
+            let mut candidates = import::ssh_config_candidates(&ssh_config, config);
+            if !hosts.is_empty() {
+                for host in &hosts {
+                    if !candidates.iter().any(|c| &c.connection.name == host) {
+                        anyhow::bail!("~/.ssh/config 中没有主机别名 '{}'", host);
                     }
-                } else {
-                    "未知认证类型".red().to_string()
-                };
+                }
+                candidates.retain(|c| hosts.contains(&c.connection.name));
+            }
+            if candidates.is_empty() {
+                println!("{}", "~/.ssh/config 中没有可导入的主机".yellow());
+                return Ok(());
+            }
 
-                println!("  [{}]", conn.name.yellow().bold());
-                println!("    主机:     {}@{}:{}", conn.username, conn.host, conn.port);
-                println!("    认证方式: {}", conn.auth_type);
-                
-                if conn.auth_type == "password" {
-                    println!("    密码:     {}", password_info);
-                } else if conn.auth_type == "publickey" {
-                    if let Some(ref key) = conn.private_key_path {
-                        println!("    私钥:     {}", key);
+            let plan = ImportPlan::new(candidates.into_iter().map(|c| c.connection), config);
+            run_import(config, plan, overwrite, apply)?;
+        }
+
+        #[cfg(windows)]
+        ConfigCommands::ImportPutty { sessions, overwrite, apply, dry_run } => {
+            let mut result = import::putty_candidates(import::read_putty_sessions()?, config);
+            if !sessions.is_empty() {
+                for session in &sessions {
+                    if !result.candidates.iter().any(|c| &c.connection.name == session) {
+                        anyhow::bail!("PuTTY 中没有 SSH 会话 '{}'", session);
                     }
-                    println!("    私钥密码: {}", password_info);
                 }
-                println!();
+                result.candidates.retain(|c| sessions.contains(&c.connection.name));
+            }
+            if result.skipped_protocols > 0 {
+                println!("{} 跳过 {} 个非 SSH 会话（串口、telnet 等）", glyphs::arrow().cyan(), result.skipped_protocols);
+            }
+            if result.candidates.is_empty() {
+                println!("{}", "PuTTY 中没有可导入的 SSH 会话".yellow());
+                return Ok(());
+            }
+
+            for warning in &result.warnings {
+                println!("{} {}", glyphs::warn().yellow(), warning);
             }
 
-            println!("{}", "⚠️  请注意保护好这些密码信息！".yellow().bold());
+            let plan = ImportPlan::new(result.candidates.into_iter().map(|c| c.connection), config);
+            if dry_run {
+                print!("{}", plan.render(table::Style::detect(), overwrite));
+                println!("{}", "--dry-run: 未保存任何连接".yellow());
+                return Ok(());
+            }
+            run_import(config, plan, overwrite, apply)?;
+        }
+
+        ConfigCommands::Export { file, include_secrets } => {
+            OfflineContext::enter(config).export(&mut prompt::Terminal, &file, include_secrets)?;
+        }
+
+        ConfigCommands::Import { file, overwrite, apply } => {
+            let content = std::fs::read_to_string(&file).context(format!("无法读取文件: {}", file))?;
+            let plan = ImportPlan::new(import::read_export(&content)?, config);
+            run_import(config, plan, overwrite, apply)?;
+        }
+
+        ConfigCommands::Snippet { action } => {
+            handle_snippet_command(config, action)?;
         }
+        
+        ConfigCommands::Secret { action } => {
+            handle_secret_command(config, action)?;
+        }
+        
+        ConfigCommands::RestoreBackup { .. } => unreachable!("在 main 中处理"),
     }
     
     Ok(())
 }
 
-/// 处理连接命令
-async fn handle_connect_command(
-    target: &str,
-    port: u16,
-    interactive: bool,
-    identity_file: Option<String>,
-    save_password: bool,
-    save_as: Option<String>,
-) -> Result<()> {
-    // 使用 russh 进行交互式连接
-    if interactive {
-        return handle_interactive_connect_russh(target, port, identity_file, save_password, save_as).await;
+/// 显示导入预览，确认后写入；`--apply` 时不询问，非交互运行时只显示预览
+fn run_import(config: &mut ConfigStore, plan: ImportPlan, overwrite: bool, apply: bool) -> Result<()> {
+    print!("{}", plan.render(table::Style::detect(), overwrite));
+    if !plan.has_changes(overwrite) {
+        println!("{} 没有需要写入的变化", glyphs::arrow().cyan());
+        return Ok(());
+    }
+    if !apply {
+        if prompt::is_batch() || !std::io::stdin().is_terminal() {
+            println!("{}", "预览: 未保存任何连接，使用 --apply 写入".yellow());
+            return Ok(());
+        }
+        if !prompt::confirm("写入以上连接?")? {
+            println!("{}", "已取消".yellow());
+            return Ok(());
+        }
     }
 
-    // 非交互式模式继续使用旧代码
-    handle_connect_command_legacy(target, port, interactive, identity_file, save_password, save_as)
+    let summary = plan.apply(config, overwrite);
+    print_import_summary(&summary);
+    if summary.added > 0 {
+        config.save()?;
+    }
+    Ok(())
 }
 
-/// 使用 russh 处理交互式连接
-async fn handle_interactive_connect_russh(
-    target: &str,
-    port: u16,
-    identity_file: Option<String>,
-    save_password: bool,
-    save_as: Option<String>,
-) -> Result<()> {
-    use ssh_russh::{AuthMethod as RusshAuthMethod, RusshClient, SshConfig as RusshSshConfig};
-    use terminal_russh::InteractiveTerminal as RusshInteractiveTerminal;
+/// 输出导入结果
+fn print_import_summary(summary: &import::ImportSummary) {
+    for (name, reason) in &summary.errors {
+        println!("{} {}: {}", glyphs::fail().red(), name, reason);
+    }
+    println!("{} 导入完成: {}", glyphs::ok().green().bold(), summary.describe());
+    if summary.skipped > 0 {
+        println!("{}", "已存在的同名连接被跳过，使用 --overwrite 覆盖".yellow());
+    }
+}
 
-    // 加载配置以检查是否有保存的连接
-    let mut config = AppConfig::load()?;
-    let mut actual_port = port;
-    let mut password_to_save: Option<String> = None;
-    let mut connection_info: Option<(String, String, u16, String)> = None; // (name, host, port, username)
-
-    // 检查是否从保存的连接加载
-    let saved_conn = config.get_connection(target);
-
-    // 获取认证信息
-    let (actual_host, actual_username, auth) = if let Some(saved_conn) = saved_conn {
-        println!("{} 使用保存的连接: {}", "→".cyan(), saved_conn.name.bold());
-        let host = saved_conn.host.clone();
-        actual_port = saved_conn.port;
-        let username = saved_conn.username.clone();
-
-        // 尝试使用已保存的密码
-        let auth = if saved_conn.has_saved_password() && identity_file.is_none() {
-            println!("{} 检测到已保存的密码", "✓".green());
-
-            // 获取主密码
-            let is_first_time = !CryptoManager::has_master_password();
-            let master_password = CryptoManager::get_master_password(is_first_time)?;
-            let crypto_manager = CryptoManager::new(&master_password)?;
-
-            // 尝试解密密码
-            match saved_conn.to_ssh_config_with_decryption(&crypto_manager, None, None) {
-                Ok(ssh_config) => {
-                    println!("{} 使用已保存的密码", "✓".green());
-                    // 从 ssh_config 提取密码
-                    if let AuthMethod::Password(pwd) = ssh_config.auth {
-                        RusshAuthMethod::Password(pwd)
-                    } else {
-                        // 不应该发生，但以防万一
-                        let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
-                        RusshAuthMethod::Password(password)
-                    }
-                }
-                Err(e) => {
-                    println!("{} 解密失败: {}", "✗".red(), e);
-                    println!("{} 请手动输入密码", "→".yellow());
-                    let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
-
-                    if save_password {
-                        password_to_save = Some(password.clone());
-                        connection_info = Some((
-                            saved_conn.name.clone(),
-                            saved_conn.host.clone(),
-                            saved_conn.port,
-                            saved_conn.username.clone(),
-                        ));
-                    }
+/// 为 `config add --check-auth` 询问认证信息
+fn auth_check(connection: &SavedConnection) -> Result<check::AuthCheck> {
+    let prompter = &mut prompt::Terminal;
+    let password = if connection.auth_type == "password" {
+        Some(credentials::password(prompter, &connection.username, &connection.host)?)
+    } else {
+        None
+    };
+    let passphrase = if connection.auth_type == "publickey" {
+        credentials::passphrase(prompter, connection.private_key_path.as_deref().unwrap_or_default())?
+    } else {
+        None
+    };
+    let config = connection.to_ssh_config(password, passphrase)?;
 
-                    RusshAuthMethod::Password(password)
-                }
+    let jump = match connection.jump_host {
+        Some(ref jump) => {
+            println!("{} 跳板机认证: {}", glyphs::arrow().cyan(), jump);
+            let (jump_target, jump_port) = split_host_port(jump);
+            Some(parse_target(jump_target, jump_port, None, &mut prompt::Terminal)?)
+        }
+        None => None,
+    };
+
+    Ok(check::AuthCheck { config, jump })
+}
+
+fn handle_restore_backup(index: Option<usize>, list: bool, yes: bool) -> Result<()> {
+    use std::io::Write;
+
+    let config_path = AppConfig::config_path()?;
+    let backups = config::list_backups(&config_path);
+    if backups.is_empty() {
+        println!("没有可用的配置备份");
+        return Ok(());
+    }
+
+    if list || index.is_none() {
+        println!("\n{}\n", "配置备份:".cyan().bold());
+        for (i, backup) in backups.iter().enumerate() {
+            let modified = chrono::DateTime::<chrono::Local>::from(backup.modified);
+            println!("  [{}] {}  {}",
+                (i + 1).to_string().yellow().bold(),
+                modified.format("%Y-%m-%d %H:%M:%S"),
+                backup.path.display().to_string().dimmed());
+        }
+        println!();
+        if list {
+            return Ok(());
+        }
+    }
+
+    let index = match index {
+        Some(index) => index,
+        None => {
+            print!("{} [默认: 1]: ", "要恢复的备份编号".green());
+            std::io::stdout().flush()?;
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            let input = input.trim();
+            if input.is_empty() { 1 } else { input.parse().context("无效的备份编号")? }
+        }
+    };
+    let backup = index
+        .checked_sub(1)
+        .and_then(|i| backups.get(i))
+        .context(format!("备份编号 {} 不存在（共 {} 个备份）", index, backups.len()))?;
+
+    if !yes && !prompt::confirm(&format!("用 {} 覆盖当前配置?", backup.path.display()))? {
+        println!("{}", "已取消".yellow());
+        return Ok(());
+    }
+
+    // 当前配置无法解析时按默认数量保留备份
+    let keep = AppConfig::load().map(|c| c.backup_count()).unwrap_or_else(|_| AppConfig::default().backup_count());
+    config::restore_backup(&config_path, backup, keep)?;
+    println!("{} 已从 {} 恢复配置", glyphs::ok().green().bold(), backup.path.display());
+    if keep > 0 {
+        println!("{} 恢复前的配置已保存为最新的备份", glyphs::arrow().cyan());
+    }
+
+    Ok(())
+}
+
+fn handle_secret_command(config: &mut ConfigStore, action: SecretCommands) -> Result<()> {
+    match action {
+        SecretCommands::Set { connection, key, stdin } => {
+            let value = Zeroizing::new(if stdin {
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line).context("无法读取标准输入")?;
+                line.trim_end_matches(['\r', '\n']).to_string()
+            } else {
+                prompt::Terminal.ask_password(&prompt::labeled(&connection, &format!("'{}' 的值", key)))?
+            });
+            if value.is_empty() {
+                anyhow::bail!("值不能为空");
             }
-        } else if let Some(key_path) = identity_file {
-            RusshAuthMethod::PublicKey(key_path)
-        } else {
-            // 没有保存的密码，手动输入
-            let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
+            secrets::set(config, &connection, &key, &value)?;
+            config.save()?;
+            println!("{} 已加密保存 '{}' 到连接 '{}'", glyphs::ok().green().bold(), key, connection);
+        }
+        
+        SecretCommands::Show { connection, key } => {
+            let value = secrets::reveal(config, &connection, &key)?;
+            println!("{}", value.as_str());
+        }
+        
+        SecretCommands::List { connection } => {
+            let conn = config.get_connection(&connection)
+                .context(format!("连接 '{}' 不存在", connection))?;
+            if conn.secrets.is_empty() {
+                println!("{}", "没有保存的密文".yellow());
+                return Ok(());
+            }
+            let mut keys: Vec<_> = conn.secrets.keys().collect();
+            keys.sort();
+            for key in keys {
+                println!("  {}", key);
+            }
+        }
+        
+        SecretCommands::Remove { connection, key } => {
+            let conn = config.connections.get_mut(&connection)
+                .context(format!("连接 '{}' 不存在", connection))?;
+            if conn.secrets.remove(&key).is_none() {
+                anyhow::bail!("连接 '{}' 没有保存密文 '{}'", connection, key);
+            }
+            config.save()?;
+            println!("{} 已删除连接 '{}' 的密文 '{}'", glyphs::ok().green().bold(), connection, key);
+        }
+    }
+    Ok(())
+}
 
-            if save_password {
-                password_to_save = Some(password.clone());
-                connection_info = Some((
-                    saved_conn.name.clone(),
-                    saved_conn.host.clone(),
-                    saved_conn.port,
-                    saved_conn.username.clone(),
-                ));
+fn handle_snippet_command(config: &mut ConfigStore, action: SnippetCommands) -> Result<()> {
+    match action {
+        SnippetCommands::Add { name, command, connection } => {
+            let scope = if let Some(ref conn_name) = connection {
+                let conn = config.connections.get_mut(conn_name)
+                    .context(format!("连接 '{}' 不存在", conn_name))?;
+                conn.snippets.insert(name.clone(), command);
+                format!("连接 '{}'", conn_name)
+            } else {
+                config.snippets.insert(name.clone(), command);
+                "全局".to_string()
+            };
+            config.save()?;
+            println!("{} 命令片段 '{}' 已添加（{}）", glyphs::ok().green().bold(), name, scope);
+        }
+        
+        SnippetCommands::List { connection } => {
+            let mut snippets: Vec<_> = config.snippets.iter()
+                .map(|(name, command)| (name, command, "全局".to_string()))
+                .collect();
+            if let Some(ref conn_name) = connection {
+                let conn = config.get_connection(conn_name)
+                    .context(format!("连接 '{}' 不存在", conn_name))?;
+                // 连接专用的片段覆盖同名的全局片段
+                snippets.retain(|(name, _, _)| !conn.snippets.contains_key(*name));
+                snippets.extend(conn.snippets.iter().map(|(name, command)| (name, command, conn_name.clone())));
+            }
+            
+            if snippets.is_empty() {
+                println!("没有保存的命令片段");
+                return Ok(());
             }
+            
+            snippets.sort_by(|a, b| a.0.cmp(b.0));
+            println!("\n{}\n", "命令片段:".cyan().bold());
+            for (name, command, scope) in snippets {
+                println!("  [{}] {} ({})", name.yellow().bold(), command, scope.dimmed());
+            }
+        }
+        
+        SnippetCommands::Remove { name, connection } => {
+            if let Some(ref conn_name) = connection {
+                let conn = config.connections.get_mut(conn_name)
+                    .context(format!("连接 '{}' 不存在", conn_name))?;
+                conn.snippets.remove(&name)
+                    .context(format!("连接 '{}' 没有命令片段 '{}'", conn_name, name))?;
+            } else {
+                config.snippets.remove(&name)
+                    .context(format!("全局命令片段 '{}' 不存在", name))?;
+            }
+            config.save()?;
+            println!("{} 命令片段 '{}' 已删除", glyphs::ok().green().bold(), name);
+        }
+    }
+    
+    Ok(())
+}
 
-            RusshAuthMethod::Password(password)
-        };
+fn handle_master_command(action: MasterCommands) -> Result<()> {
+    master::ensure_supported()?;
+    match action {
+        MasterCommands::List => {
+            let names = master::list()?;
+            if names.is_empty() {
+                println!("{}", "没有正在运行的控制主连接".yellow());
+                return Ok(());
+            }
+            println!("\n{}\n", "控制主连接:".cyan().bold());
+            for name in names {
+                println!("  {}", name.green());
+            }
+        }
+        MasterCommands::Stop { name } => {
+            if master::stop(&name)? {
+                println!("{} 控制主连接已停止: {}", glyphs::ok().green().bold(), name);
+            } else {
+                println!("{} 没有正在运行的控制主连接: {}", glyphs::fail().red(), name);
+            }
+        }
+        MasterCommands::Serve { name } => master::run_server(&name)?,
+    }
+    Ok(())
+}
 
-        (host, username, auth)
-    } else {
-        // 没有保存的连接，解析目标
-        let (username, host) = if target.contains('@') {
-            let parts: Vec<&str> = target.split('@').collect();
-            if parts.len() != 2 {
-                return Err(anyhow::anyhow!("无效的目标格式，应为 user@host"));
+fn handle_proc_command(action: ProcCommands) -> Result<()> {
+    match action {
+        ProcCommands::List {
+            target,
+            sort,
+            filter,
+            top,
+            port,
+            identity_file,
+        } => {
+            let client = SshClient::connect(parse_target(&target, port, identity_file, &mut prompt::Terminal)?)?;
+            let sort = if sort == "mem" { process::SortKey::Mem } else { process::SortKey::Cpu };
+            let processes = process::select(process::list(&client)?, filter.as_deref(), sort);
+
+            // 列只在远程 ps 提供时显示
+            let show_cpu = processes.iter().any(|p| p.cpu.is_some());
+            let show_mem = processes.iter().any(|p| p.mem.is_some());
+            let percent = |value: Option<f32>| value.map(|v| format!("{:.1}", v)).unwrap_or_else(|| "-".to_string());
+
+            let mut list = Table::new().column("PID", Align::Right).truncated_column("用户", Align::Left);
+            if show_cpu {
+                list = list.column("%CPU", Align::Right);
             }
-            (parts[0].to_string(), parts[1].to_string())
-        } else {
-            return Err(anyhow::anyhow!("目标必须包含用户名，格式: user@host"));
-        };
+            if show_mem {
+                list = list.column("%MEM", Align::Right);
+            }
+            list = list.column("内存", Align::Right).truncated_column("命令", Align::Left);
 
-        let auth = if let Some(key_path) = identity_file {
-            RusshAuthMethod::PublicKey(key_path)
-        } else {
-            let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
+            for p in processes.iter().take(top) {
+                let mut row = vec![p.pid.to_string().into(), p.user.as_str().into()];
+                if show_cpu {
+                    row.push(percent(p.cpu).into());
+                }
+                if show_mem {
+                    row.push(percent(p.mem).into());
+                }
+                let rss = p.rss.map(|kb| format_size(kb * 1024)).unwrap_or_else(|| "-".to_string());
+                row.push(rss.into());
+                row.push(p.command.as_str().into());
+                list.row(row);
+            }
+            list.print();
+            if processes.len() > top && !table::is_plain() {
+                println!("{} 共 {} 个进程，只显示前 {} 个", glyphs::arrow().cyan(), processes.len(), top);
+            }
+        }
 
-            if save_password {
-                let conn_name = save_as.unwrap_or_else(|| format!("{}@{}", username, host));
-                password_to_save = Some(password.clone());
-                connection_info = Some((
-                    conn_name,
-                    host.clone(),
-                    actual_port,
-                    username.clone(),
-                ));
+        ProcCommands::Kill {
+            target,
+            pid,
+            signal,
+            port,
+            identity_file,
+        } => {
+            let client = SshClient::connect(parse_target(&target, port, identity_file, &mut prompt::Terminal)?)?;
+            process::kill(&client, pid, &signal)?;
+            println!("{} 进程 {} 已退出", glyphs::ok().green(), pid);
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_hostkey_command(action: HostkeyCommands) -> Result<()> {
+    use known_hosts::KnownHosts;
+
+    match action {
+        HostkeyCommands::List { openssh } => {
+            let config = AppConfig::load()?;
+            let known_hosts = KnownHosts::load_with(openssh || config.include_openssh_known_hosts)?;
+
+            if known_hosts.entries().is_empty() {
+                println!("没有已记录的主机密钥");
+                return Ok(());
             }
 
-            RusshAuthMethod::Password(password)
-        };
+            println!("\n{}\n", "已知主机:".cyan().bold());
+            for entry in known_hosts.entries() {
+                let hosts = if entry.is_hashed() {
+                    "(哈希主机名)".dimmed().to_string()
+                } else {
+                    entry.hosts.yellow().to_string()
+                };
+                println!("  {} {} {}", hosts, entry.key_type, entry.fingerprint().green());
+                println!("    {}", entry.source.display().to_string().dimmed());
+            }
+        }
 
-        (host, username, auth)
-    };
+        HostkeyCommands::Remove { host, port } => {
+            let removed = KnownHosts::remove(&host, port)?;
+            if removed == 0 {
+                anyhow::bail!("known_hosts 中没有主机 '{}' 的记录", host);
+            }
+            println!("{} 已删除主机 '{}' 的 {} 条密钥记录", glyphs::ok().green().bold(), host, removed);
+        }
 
-    // 创建配置
-    let ssh_config = RusshSshConfig::new(actual_host.clone(), actual_port, actual_username.clone(), auth);
+        HostkeyCommands::Scan { host, port, add } => {
+            println!("{} 正在扫描 {}:{} 的主机密钥...", glyphs::arrow().cyan(), host, port);
+            let keys = known_hosts::scan_host_keys(&host, port).await?;
 
-    // 连接
-    println!("{} 正在连接到 {}@{}:{}...", "→".cyan(), actual_username, actual_host, actual_port);
-    let mut client = RusshClient::new(ssh_config);
-    client.connect().await?;
-    println!("{} 连接成功!", "✓".green());
+            if keys.is_empty() {
+                anyhow::bail!("未获取到主机密钥");
+            }
 
-    // 如果需要保存密码，在连接成功后保存
-    if let (Some(password), Some((name, host, port, username))) = (password_to_save, connection_info) {
-        println!("{} 正在保存密码...", "→".cyan());
+            let known_hosts = KnownHosts::load()?;
+            for key in &keys {
+                let status = match known_hosts.check_blob(&host, port, key) {
+                    known_hosts::HostKeyStatus::Trusted => "已记录".green(),
+                    known_hosts::HostKeyStatus::Unknown => "未记录".yellow(),
+                    known_hosts::HostKeyStatus::Mismatch => "与记录不一致".red().bold(),
+                };
+                println!("  {} [{}]", known_hosts::fingerprint_blob(key), status);
 
-        let is_first_time = !CryptoManager::has_master_password();
-        let master_password = CryptoManager::get_master_password(is_first_time)?;
-        let crypto_manager = CryptoManager::new(&master_password)?;
+                if add && known_hosts.check_blob(&host, port, key) == known_hosts::HostKeyStatus::Unknown {
+                    KnownHosts::add_blob(&host, port, key)?;
+                    println!("    {} 已保存", glyphs::ok().green());
+                }
+            }
+        }
+    }
 
-        // 加密密码
-        let encrypted_password = crypto_manager.encrypt(&password)?;
+    Ok(())
+}
 
-        // 创建保存的连接
-        let saved_conn = SavedConnection::new_password_with_encrypted(
-            name.clone(),
-            host,
-            port,
-            username,
-            encrypted_password,
-        );
+/// 处理连接命令
+async fn handle_connect_command(
+    target: &str,
+    options: &ConnectOptions,
+    interactive: bool,
+    record: Option<RecordOptions>,
+    escape_char: Option<u8>,
+    prompter: &mut dyn Prompter,
+) -> Result<()> {
+    // 使用 russh 进行交互式连接（录制会话需要原始模式终端）
+    if interactive || record.is_some() {
+        return handle_interactive_connect_russh(target, options, record, escape_char, prompter).await;
+    }
 
-        config.add_connection(saved_conn);
-        config.save()?;
+    // 非交互式模式继续使用旧代码
+    handle_connect_command_legacy(target, options, interactive, prompter)
+}
 
-        println!("{} 密码已保存到连接: {}", "✓".green(), name.bold());
+/// 使用 russh 处理交互式连接
+async fn handle_interactive_connect_russh(
+    target: &str,
+    options: &ConnectOptions,
+    record: Option<RecordOptions>,
+    escape_char: Option<u8>,
+    prompter: &mut dyn Prompter,
+) -> Result<()> {
+    use terminal_russh::InteractiveTerminal as RusshInteractiveTerminal;
+
+    let config = AppConfig::load()?;
+    let Connected { mut client, save } = ConnectPipeline::new(&config, prompter).connect_russh(target, options).await?;
+
+    // 连接成功后保存
+    if let Some(save) = save {
+        ConfigStore::update(|config| save.persist(config, prompter))?;
     }
 
     // 启动交互式终端
-    let mut terminal = RusshInteractiveTerminal::new(&mut client);
+    let mut terminal = RusshInteractiveTerminal::new(&mut client).with_escape(escape_char);
+    if let Some(ref options) = record {
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        terminal = terminal.with_recorder(CastRecorder::from_options(options, cols, rows)?);
+        println!("{} 正在录制会话到: {}", glyphs::arrow().cyan(), options.path.display());
+    }
     terminal.start_shell().await?;
 
     // 断开连接
@@ -578,216 +2167,347 @@ async fn handle_interactive_connect_russh(
 /// 旧的连接处理函数（保留用于非交互式模式）
 fn handle_connect_command_legacy(
     target: &str,
-    port: u16,
+    options: &ConnectOptions,
     interactive: bool,
-    identity_file: Option<String>,
-    save_password: bool,
-    save_as: Option<String>,
+    prompter: &mut dyn Prompter,
 ) -> Result<()> {
-    let mut config = AppConfig::load()?;
-    let crypto: Option<CryptoManager> = None;
-    let mut password_to_save: Option<String> = None;
-    let mut connection_info: Option<(String, String, u16, String)> = None; // (name, host, port, username)
-
-    // 检查是否从保存的连接加载
-    let ssh_config = if let Some(saved_conn) = config.get_connection(target) {
-        // 从保存的连接加载
-        println!("{} 使用保存的连接: {}", "→".cyan(), saved_conn.name.bold());
-
-        let ssh_config = if saved_conn.has_saved_password() {
-            // 有保存的密码，尝试自动填充
-            println!("{} 检测到已保存的密码", "✓".green());
-
-            // 获取主密码
-            let is_first_time = !CryptoManager::has_master_password();
-            let master_password = CryptoManager::get_master_password(is_first_time)?;
-            let crypto_manager = CryptoManager::new(&master_password)?;
-
-            // 尝试解密并连接
-            match saved_conn.to_ssh_config_with_decryption(&crypto_manager, None, None) {
-                Ok(config) => {
-                    println!("{} 使用已保存的密码", "✓".green());
-                    config
-                }
-                Err(e) => {
-                    println!("{} 解密失败: {}", "✗".red(), e);
-                    println!("{} 请手动输入密码", "→".yellow());
-
-                    // 手动输入密码
-                    let password = if saved_conn.auth_type == "password" {
-                        Some(rpassword::prompt_password(format!("{}@{} 的密码: ", saved_conn.username, saved_conn.host))?)
-                    } else {
-                        None
-                    };
+    let config = AppConfig::load()?;
+    let Connected { client, save } = ConnectPipeline::new(&config, prompter).connect(target, options)?;
 
-                    let passphrase = if saved_conn.auth_type == "publickey" {
-                        let pp = rpassword::prompt_password("私钥密码（如果没有请直接回车）: ")?;
-                        if pp.is_empty() { None } else { Some(pp) }
-                    } else {
-                        None
-                    };
+    // 连接成功后保存
+    if let Some(save) = save {
+        ConfigStore::update(|config| save.persist(config, prompter))?;
+    }
 
-                    saved_conn.to_ssh_config(password, passphrase)?
-                }
-            }
-        } else {
-            // 没有保存的密码，手动输入
-            let password = if saved_conn.auth_type == "password" {
-                let pwd = rpassword::prompt_password(format!("{}@{} 的密码: ", saved_conn.username, saved_conn.host))?;
-                if save_password {
-                    password_to_save = Some(pwd.clone());
-                    connection_info = Some((
-                        saved_conn.name.clone(),
-                        saved_conn.host.clone(),
-                        saved_conn.port,
-                        saved_conn.username.clone(),
-                    ));
-                }
-                Some(pwd)
-            } else {
-                None
-            };
+    // 启动 shell
+    if interactive {
+        let terminal = InteractiveTerminal::new(&client);
+        terminal.start_shell()?;
+    } else {
+        let mut shell = SimpleShell::new(&client)
+            .with_snippets(config.snippets_for(Some(target)))
+            .with_history(history::History::open(target, "shell")?);
+        shell.start()?;
+    }
 
-            let passphrase = if saved_conn.auth_type == "publickey" {
-                let pp = rpassword::prompt_password("私钥密码（如果没有请直接回车）: ")?;
-                if pp.is_empty() { None } else { Some(pp) }
-            } else {
-                None
-            };
+    Ok(())
+}
 
-            saved_conn.to_ssh_config(password, passphrase)?
-        };
+/// 解析目标字符串（连接名称或 user@host 格式）
+fn parse_target(target: &str, port: Option<u16>, identity_file: Option<String>, prompter: &mut dyn Prompter) -> Result<SshConfig> {
+    let config = AppConfig::load()?;
+    let target = connect::resolve_command_target(&config, target, port, identity_file)?;
+    log::debug!("认证来源: {}", target.source);
+
+    let auth = match target.auth {
+        TargetAuth::Key { ref private_key, ref public_key } => connect::key_auth(prompter, private_key, public_key.as_deref())?,
+        TargetAuth::Agent => AuthMethod::Agent,
+        // 命令行操作不解密保存的密码，与未保存时一样询问
+        TargetAuth::Password => AuthMethod::Password(credentials::password(prompter, &target.username, &target.host)?),
+    };
 
-        ssh_config
-    } else {
-        // 解析 user@host 格式
-        if let Some((username, host)) = target.split_once('@') {
-            let auth = if let Some(key_path) = identity_file {
-                let passphrase = rpassword::prompt_password("私钥密码（如果没有请直接回车）: ")?;
-                let passphrase = if passphrase.is_empty() { None } else { Some(passphrase) };
+    Ok(SshConfig {
+        host: target.host,
+        port: target.port,
+        username: target.username,
+        auth,
+    })
+}
 
-                AuthMethod::PublicKey {
-                    public_key: None,
-                    private_key: key_path,
-                    passphrase,
-                }
-            } else {
-                let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
-                if save_password || save_as.is_some() {
-                    password_to_save = Some(password.clone());
-                    let conn_name = save_as.clone().unwrap_or_else(|| format!("{}@{}", username, host));
-                    connection_info = Some((conn_name, host.to_string(), port, username.to_string()));
-                }
-                AuthMethod::Password(password)
-            };
+/// 连接目标主机，需要时经过跳板机（`--jump` 优先于连接配置中的 `jump_host`）
+fn connect_target(
+    target: &str,
+    port: Option<u16>,
+    identity_file: Option<String>,
+    jump: Option<String>,
+    keepalive: Option<u32>,
+) -> Result<RemoteSession> {
+    let (target_config, jump_config) = target_configs(target, port, identity_file, jump)?;
+    let mut session = RemoteSession::connect(target_config, jump_config)?;
+    session.set_keepalive(keepalive);
+    Ok(session)
+}
 
-            SshConfig {
-                host: host.to_string(),
-                port,
-                username: username.to_string(),
-                auth,
-            }
-        } else {
-            anyhow::bail!("无效的目标格式。请使用 'user@host' 或保存的连接名称");
-        }
+/// 解析目标主机和跳板机的连接参数（此时询问密码），不建立连接
+fn target_configs(
+    target: &str,
+    port: Option<u16>,
+    identity_file: Option<String>,
+    jump: Option<String>,
+) -> Result<(SshConfig, Option<SshConfig>)> {
+    let jump = jump.or_else(|| {
+        AppConfig::load()
+            .ok()
+            .and_then(|config| config.get_connection(target).and_then(|c| c.jump_host.clone()))
+    });
+
+    let Some(jump) = jump else {
+        return Ok((parse_target(target, port, identity_file, &mut prompt::Terminal)?, None));
     };
 
-    // 连接到服务器
-    println!("{} 正在连接到 {}@{}:{}...", "→".cyan(), ssh_config.username, ssh_config.host, ssh_config.port);
-    let client = SshClient::connect(ssh_config)?;
-    println!("{} 连接成功!", "✓".green().bold());
+    // 分别标明每一跳的认证提示（写到 stderr，不混入 `download ... -` 输出到 stdout 的文件内容）
+    eprintln!("{} 跳板机认证: {}", glyphs::arrow().cyan(), jump);
+    let (jump_target, jump_port) = split_host_port(&jump);
+    let jump_config = parse_target(jump_target, jump_port, None, &mut prompt::Terminal)?;
 
-    // 如果需要保存密码
-    if let (Some(password), Some((name, host, port, username))) = (password_to_save, connection_info) {
-        println!("\n{} 正在保存密码...", "→".cyan());
+    eprintln!("{} 目标主机认证: {}", glyphs::arrow().cyan(), target);
+    let target_config = parse_target(target, port, identity_file, &mut prompt::Terminal)?;
 
-        // 获取或创建加密管理器
-        let crypto_manager = if let Some(c) = crypto {
-            c
-        } else {
-            let is_first_time = !CryptoManager::has_master_password();
-            let master_password = CryptoManager::get_master_password(is_first_time)?;
-            CryptoManager::new(&master_password)?
-        };
+    Ok((target_config, Some(jump_config)))
+}
 
-        // 加密密码
-        let encrypted_password = crypto_manager.encrypt(&password)?;
+/// 拆分 `target:path` 形式的远程路径（在第一个 `:` 处拆分，与 scp 一致）
+fn split_remote_spec(spec: &str) -> Result<(&str, &str)> {
+    match spec.split_once(':') {
+        Some((target, path)) if !target.is_empty() && !path.is_empty() => Ok((target, path)),
+        _ => anyhow::bail!("无效的远程路径: '{}'，格式应为 <连接名称或 user@host>:<路径>", spec),
+    }
+}
 
-        // 创建或更新连接配置
-        let saved_conn = SavedConnection::new_password_with_encrypted(
-            name.clone(),
-            host,
-            port,
-            username,
-            encrypted_password,
+/// 拆分 `host:port`（端口不是数字时视为主机名的一部分）
+fn split_host_port(spec: &str) -> (&str, Option<u16>) {
+    match spec.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (spec, None),
+        },
+        None => (spec, None),
+    }
+}
+
+/// 钩子模板变量：保存的连接使用其配置，其他目标按 user@host 或 ssh_config 解析
+fn hook_context(config: &AppConfig, target: &str, port: Option<u16>) -> Result<hooks::HookContext> {
+    if let Some(conn) = config.get_connection(target) {
+        return Ok(hooks::HookContext {
+            name: conn.name.clone(),
+            host: conn.host.clone(),
+            port: conn.port,
+            username: conn.username.clone(),
+        });
+    }
+
+    let resolved = resolve_target(config, target, port, None)?;
+    Ok(hooks::HookContext {
+        name: target.to_string(),
+        host: resolved.host,
+        port: resolved.port,
+        username: resolved.username,
+    })
+}
+
+/// 把同一个文件上传到多个目标，打印每个主机的结果，有失败时返回错误
+fn upload_to_targets(
+    jobs: Vec<fanout::FanoutTarget>,
+    local_path: &str,
+    parallel: usize,
+    options: &fanout::FanoutOptions,
+    json: bool,
+    quiet: bool,
+) -> Result<()> {
+    if !quiet && !json {
+        println!("{} 正在上传到 {} 个目标（并发 {}）...", glyphs::arrow().cyan(), jobs.len(), parallel);
+    }
+    let cancel = interrupt::install_handler()?;
+    let report = fanout::upload(jobs, local_path, options, parallel, cancel);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).context("无法序列化传输结果")?);
+    } else if !quiet {
+        let width = report.hosts.iter().map(|h| h.target.len()).max().unwrap_or(0).max(4);
+        for host in &report.hosts {
+            match (&host.stats, &host.error) {
+                (Some(stats), _) => println!(
+                    "{} {:<width$}  {:>10}  {:>6.2} 秒  {}/s",
+                    glyphs::ok().green(),
+                    host.target,
+                    format_size(stats.total_bytes),
+                    stats.elapsed.as_secs_f64(),
+                    format_size(stats.throughput() as u64),
+                ),
+                (None, error) => println!(
+                    "{} {:<width$}  {}",
+                    glyphs::fail().red(),
+                    host.target,
+                    error.as_deref().unwrap_or("").red()
+                ),
+            }
+        }
+        println!(
+            "  成功 {}/{}，共 {}，耗时 {:.2} 秒，合计 {}/s",
+            report.hosts.len() - report.failed(),
+            report.hosts.len(),
+            format_size(report.total.total_bytes),
+            report.total.elapsed.as_secs_f64(),
+            format_size(report.total.throughput() as u64)
         );
+    }
 
-        config.add_connection(saved_conn);
-        config.save()?;
+    if report.failed() > 0 {
+        anyhow::bail!("{} 个目标上传失败", report.failed());
+    }
+    Ok(())
+}
 
-        println!("{} 密码已加密保存到连接 '{}'", "✓".green().bold(), name);
+/// 命令行未指定新建目录的权限时，使用配置 `[defaults]` 中的 dir_mode
+fn dir_mode_or_default(mode: Option<u32>) -> Result<Option<u32>> {
+    match mode {
+        Some(mode) => Ok(Some(mode)),
+        None => AppConfig::load()?.default_dir_mode(),
     }
+}
 
-    // 启动 shell
-    if interactive {
-        let terminal = InteractiveTerminal::new(&client);
-        terminal.start_shell()?;
-    } else {
-        let shell = SimpleShell::new(&client);
-        shell.start()?;
+/// 实际使用的进度显示方式：`--no-progress` 等同于 `--progress none`，
+/// 静默或输出 JSON 统计时不显示进度条（JSON 事件输出到 stderr，不受影响）
+fn progress_mode(mode: ProgressMode, no_progress: bool, hide_bar: bool) -> ProgressMode {
+    match mode {
+        _ if no_progress => ProgressMode::None,
+        ProgressMode::Bar if hide_bar => ProgressMode::None,
+        mode => mode,
     }
+}
 
-    Ok(())
+/// 上传文件并显示进度
+fn upload_with_progress(sftp: &SftpClient, local: &str, remote: &str, mode: ProgressMode) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("上传: {}", local), local, mode);
+    let result = sftp.upload_file(local, remote, Some(&mut |p| reporter.update(p)));
+    let done = match result {
+        Ok(ref stats) if stats.files_skipped > 0 => format!("已跳过（远程文件相同）: {}", local),
+        _ => format!("上传完成: {}", local),
+    };
+    finish_progress(&reporter, &result, done, "上传已取消");
+    result
 }
 
-/// 解析目标字符串（连接名称或 user@host 格式）
-fn parse_target(target: &str, port: u16, identity_file: Option<String>) -> Result<SshConfig> {
-    // 首先尝试从配置中加载
-    let config = AppConfig::load()?;
-    
-    if let Some(saved_conn) = config.get_connection(target) {
-        // 从保存的连接加载
-        let password = if saved_conn.auth_type == "password" {
-            Some(rpassword::prompt_password(format!("{}@{} 的密码: ", saved_conn.username, saved_conn.host))?)
-        } else {
-            None
-        };
-        
-        let passphrase = if saved_conn.auth_type == "publickey" {
-            let pp = rpassword::prompt_password("私钥密码（如果没有请直接回车）: ")?;
-            if pp.is_empty() { None } else { Some(pp) }
-        } else {
-            None
-        };
-        
-        return saved_conn.to_ssh_config(password, passphrase);
+/// 递归上传目录并显示所有文件的累计进度
+fn upload_dir_with_progress(sftp: &SftpClient, local: &str, remote: &str, links: Links, mode: ProgressMode) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("上传目录: {}", local), local, mode);
+    let result = sftp.upload_dir(local, remote, links, Some(&mut |p| reporter.update(p)));
+    let files = result.as_ref().map_or(0, |stats| stats.files_transferred + stats.files_skipped);
+    finish_progress(&reporter, &result, format!("上传完成: {}（{} 个文件）", local, files), "上传已取消");
+    result
+}
+
+/// 递归下载目录并显示所有文件的累计进度
+fn download_dir_with_progress(
+    sftp: &SftpClient,
+    remote: &str,
+    local: &str,
+    links: Links,
+    manifest: Option<&mut Manifest>,
+    mode: ProgressMode,
+) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("下载目录: {}", remote), remote, mode);
+    let result = sftp.download_dir(remote, local, links, manifest, Some(&mut |p| reporter.update(p)));
+    let files = result.as_ref().map_or(0, |stats| stats.files_transferred);
+    finish_progress(&reporter, &result, format!("下载完成: {}（{} 个文件）", local, files), "下载已取消");
+    result
+}
+
+/// 下载文件并显示进度
+fn download_with_progress(sftp: &SftpClient, remote: &str, local: &str, mode: ProgressMode) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("下载: {}", remote), remote, mode);
+    let result = sftp.download_file(remote, local, Some(&mut |p| reporter.update(p)));
+    finish_progress(&reporter, &result, format!("下载完成: {}", local), "下载已取消");
+    result
+}
+
+/// 从标准输入上传并显示进度（大小未知，进度条只显示已传输的字节数）
+fn upload_stdin_with_progress(sftp: &SftpClient, remote: &str, mode: ProgressMode) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("上传: 标准输入 -> {}", remote), "-", mode);
+    let result = sftp.upload_stream(&mut std::io::stdin().lock(), remote, Some(&mut |p| reporter.update(p)));
+    finish_progress(&reporter, &result, format!("上传完成: {}", remote), "上传已取消");
+    result
+}
+
+/// 下载到标准输出，进度显示在标准错误上
+fn download_stdout_with_progress(sftp: &SftpClient, remote: &str, mode: ProgressMode) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("下载: {}", remote), remote, mode);
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let result = sftp.download_stream(remote, &mut stdout, Some(&mut |p| reporter.update(p)));
+    finish_progress(&reporter, &result, format!("下载完成: {}", remote), "下载已取消");
+    result
+}
+
+/// 分块并行上传并显示进度
+fn upload_chunked(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    local: &str,
+    remote: &str,
+    options: &chunked::ChunkOptions,
+    mode: ProgressMode,
+) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("上传: {}", local), local, mode);
+    let result = chunked::upload(session, sftp, local, remote, options, Some(&mut |p| reporter.update(p)));
+    finish_progress(&reporter, &result, format!("上传完成: {}", local), "上传已取消");
+    result
+}
+
+/// 分块并行下载并显示进度
+fn download_chunked(
+    session: &RemoteSession,
+    sftp: &SftpClient,
+    remote: &str,
+    local: &str,
+    options: &chunked::ChunkOptions,
+    mode: ProgressMode,
+) -> Result<TransferStats> {
+    let mut reporter = ProgressReporter::new(format!("下载: {}", remote), remote, mode);
+    let result = chunked::download(session, sftp, remote, local, options, Some(&mut |p| reporter.update(p)));
+    finish_progress(&reporter, &result, format!("下载完成: {}", local), "下载已取消");
+    result
+}
+
+fn finish_progress(reporter: &ProgressReporter, result: &Result<TransferStats>, done: String, cancelled: &str) {
+    match result {
+        Ok(stats) => reporter.finish(done, stats),
+        Err(e) if e.downcast_ref::<interrupt::Cancelled>().is_some() => reporter.abandon(cancelled.to_string()),
+        Err(e) => reporter.fail(e),
     }
+}
+
+/// 依次传输列表中的文件，单个文件失败时继续，最后汇总失败数
+fn transfer_file_list<F>(entries: &[String], mut transfer: F) -> Result<TransferStats>
+where
+    F: FnMut(&str) -> Result<TransferStats>,
+{
+    let mut stats = TransferStats::default();
+    let mut failed = 0;
     
-    // 解析 user@host 格式
-    if let Some((username, host)) = target.split_once('@') {
-        let auth = if let Some(key_path) = identity_file {
-            let passphrase = rpassword::prompt_password("私钥密码（如果没有请直接回车）: ")?;
-            let passphrase = if passphrase.is_empty() { None } else { Some(passphrase) };
-            
-            AuthMethod::PublicKey {
-                public_key: None,
-                private_key: key_path,
-                passphrase,
+    for entry in entries {
+        match transfer(entry) {
+            Ok(file_stats) => stats.merge(&file_stats),
+            // 取消时立即停止
+            Err(e) if e.downcast_ref::<interrupt::Cancelled>().is_some() => return Err(e),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{} {}: {:#}", glyphs::fail().red(), entry, e);
             }
-        } else {
-            let password = rpassword::prompt_password(format!("{}@{} 的密码: ", username, host))?;
-            AuthMethod::Password(password)
-        };
-        
-        return Ok(SshConfig {
-            host: host.to_string(),
-            port,
-            username: username.to_string(),
-            auth,
-        });
+        }
     }
     
-    anyhow::bail!("无效的目标格式。请使用 'user@host' 或保存的连接名称")
+    if failed > 0 {
+        anyhow::bail!("{} 个文件传输失败（共 {} 个）", failed, entries.len());
+    }
+    Ok(stats)
+}
+
+/// 打印传输统计摘要
+fn print_transfer_summary(title: &str, stats: &TransferStats, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(stats).context("无法序列化传输统计")?);
+        return Ok(());
+    }
+
+    println!("{}", title.green().bold());
+    println!("  传输文件: {}", stats.files_transferred);
+    if stats.files_skipped > 0 {
+        println!("  跳过文件: {}", stats.files_skipped);
+    }
+    println!("  总大小:   {}", format_size(stats.total_bytes));
+    println!("  耗时:     {:.2} 秒", stats.elapsed.as_secs_f64());
+    println!("  平均速度: {}/s", format_size(stats.throughput() as u64));
+    Ok(())
 }
 
 /// 格式化文件大小
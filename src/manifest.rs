@@ -0,0 +1,143 @@
+//! 下载时记录远程文件元数据的清单
+//!
+//! 批量下载（`download --files-from`）和递归下载（`download -r`）时把每个文件在远程的属主、权限位和
+//! 修改时间写入 JSON 清单，并标出未能应用到本地文件的字段；`upload --from-manifest` 读取清单，上传后
+//! 在远程恢复权限位和修改时间。
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::sftp::FileInfo;
+
+/// 下载目录中默认的清单文件名
+pub const DEFAULT_NAME: &str = ".sftp-manifest.json";
+
+/// 元数据清单
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    /// 下载来源（连接名称或 user@host）
+    pub source: String,
+    /// 本地下载目录（绝对路径），条目的 `path` 相对于该目录
+    pub local_dir: PathBuf,
+    /// 生成时间（RFC 3339）
+    pub created: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// 单个文件的远程元数据
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ManifestEntry {
+    /// 相对下载目录的本地路径
+    pub path: String,
+    /// 远程路径
+    pub remote: String,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// 权限位（不含文件类型位）
+    pub mode: Option<u32>,
+    /// 修改时间（Unix 时间戳，秒）
+    pub mtime: Option<u64>,
+    /// 未能应用到本地文件的字段：owner、mode、mtime
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_applied: Vec<String>,
+}
+
+impl Manifest {
+    pub fn new(source: &str, local_dir: &Path) -> Result<Self> {
+        let local_dir = std::path::absolute(local_dir)
+            .context(format!("无法解析本地目录: {}", local_dir.display()))?;
+        Ok(Self {
+            source: source.to_string(),
+            local_dir,
+            created: chrono::Local::now().to_rfc3339(),
+            entries: Vec::new(),
+        })
+    }
+
+    /// 记录已下载的文件，`mode_applied` 表示权限位已应用到本地文件
+    ///
+    /// 下载不修改本地文件的属主和修改时间，这两项总是记为未应用。
+    pub fn record(&mut self, path: &str, info: &FileInfo, mode_applied: bool) {
+        let mut not_applied = vec!["owner".to_string()];
+        if !mode_applied {
+            not_applied.push("mode".to_string());
+        }
+        not_applied.push("mtime".to_string());
+        self.entries.push(ManifestEntry {
+            path: path.to_string(),
+            remote: info.path.clone(),
+            uid: info.uid,
+            gid: info.gid,
+            mode: Some(info.permissions & 0o7777).filter(|_| info.permissions != 0),
+            mtime: info.modified,
+            not_applied,
+        });
+    }
+
+    /// 按本地文件路径记录（递归下载时使用），`local` 须位于清单的下载目录下
+    pub fn record_local(&mut self, local: &Path, info: &FileInfo, mode_applied: bool) -> Result<()> {
+        let absolute = std::path::absolute(local)
+            .context(format!("无法解析本地路径: {}", local.display()))?;
+        let path = absolute.strip_prefix(&self.local_dir)
+            .context(format!("{} 不在下载目录 {} 下", local.display(), self.local_dir.display()))?;
+        self.record(&path.to_string_lossy(), info, mode_applied);
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("无法读取清单文件: {}", path.display()))?;
+        serde_json::from_str(&content).context(format!("清单文件格式错误: {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content + "\n").context(format!("无法写入清单文件: {}", path.display()))
+    }
+
+    /// 条目对应的本地文件
+    pub fn local_path(&self, entry: &ManifestEntry) -> PathBuf {
+        self.local_dir.join(&entry.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(path: &str) -> FileInfo {
+        FileInfo {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.to_string(),
+            raw_path: PathBuf::from(path),
+            size: 4,
+            is_dir: false,
+            permissions: 0o100750,
+            is_symlink: false,
+            link_target: None,
+            link_broken: false,
+            modified: Some(1_700_000_000),
+            uid: Some(1001),
+            gid: Some(100),
+        }
+    }
+
+    #[test]
+    fn test_record_and_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::new("prod", dir.path()).unwrap();
+        manifest.record("bin/run.sh", &info("/srv/app/bin/run.sh"), true);
+        manifest.record("conf/app.toml", &info("/srv/app/conf/app.toml"), false);
+
+        let entry = &manifest.entries[0];
+        assert_eq!((entry.uid, entry.gid, entry.mode, entry.mtime), (Some(1001), Some(100), Some(0o750), Some(1_700_000_000)));
+        assert_eq!(entry.not_applied, vec!["owner", "mtime"]);
+        assert_eq!(manifest.entries[1].not_applied, vec!["owner", "mode", "mtime"]);
+        assert_eq!(manifest.local_path(entry), dir.path().join("bin/run.sh"));
+
+        let path = dir.path().join(DEFAULT_NAME);
+        manifest.write(&path).unwrap();
+        assert_eq!(Manifest::read(&path).unwrap(), manifest);
+    }
+}
@@ -0,0 +1,1017 @@
+//! 控制主连接（与 OpenSSH 的 ControlMaster 类似）
+//!
+//! `connect --master` 在后台启动一个持有已认证会话的进程，Unix 上监听运行时目录下的 Unix 域套接字，
+//! Windows 上监听只允许当前用户访问、名称随机的命名管道（套接字目录下同名的 `.sock` 文件记录管道名称）。
+//! 之后对同一连接名称的 `exec` 和 `sftp` 操作把请求发给它执行，不再重新认证。主连接进程与
+//! 调用方在同一台机器上，上传下载直接读写本地路径。协议为每行一个 JSON 消息，每个请求一个连接。
+//! 主连接缓存目录列表 [`LISTING_TTL`]，经它执行的修改会使对应的缓存失效，`list --refresh` 忽略缓存。
+//!
+//! 主连接持有的是 `exec` 和 `sftp` 命令使用的会话（[`RemoteSession`]），转发的操作与直接连接时走同一套实现。
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::audit;
+use crate::session::{self, RemoteSession};
+use crate::sftp::{FileInfo, ListingCache, SftpClient, TransferStats};
+use crate::ssh::{AuthMethod, SshConfig};
+
+/// 主连接的保活间隔（秒）
+const KEEPALIVE: u32 = 60;
+
+/// 目录列表的缓存时间；不经主连接的修改最多这么久后可见
+const LISTING_TTL: Duration = Duration::from_secs(5);
+
+/// 套接字文件的扩展名
+const SOCKET_EXT: &str = "sock";
+
+/// 当前平台是否支持控制主连接（需要 Unix 域套接字或 Windows 命名管道）
+pub const SUPPORTED: bool = cfg!(any(unix, windows));
+
+/// 不支持控制主连接的平台上返回错误，在询问密码之前调用
+pub fn ensure_supported() -> Result<()> {
+    if !SUPPORTED {
+        anyhow::bail!("当前平台不支持控制主连接，请直接连接");
+    }
+    Ok(())
+}
+
+/// 客户端发给主连接的请求
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Stop,
+    Exec { command: String },
+    List {
+        path: String,
+        /// 忽略缓存重新读取
+        #[serde(default)]
+        refresh: bool,
+    },
+    Mkdir { path: String },
+    Remove { path: String },
+    Upload { local: String, remote: String },
+    Download { remote: String, local: String },
+    Realpath { path: String },
+}
+
+/// 主连接的应答，一个请求可能对应多条（命令输出）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    /// 标准输出（base64）
+    Stdout { data: String },
+    /// 标准错误（base64）
+    Stderr { data: String },
+    Exit { code: i32 },
+    Files { files: Vec<Entry> },
+    Transferred { bytes: u64, elapsed_ms: u64 },
+    Path { path: String },
+    Done,
+    Error { message: String },
+}
+
+/// 目录列表中的一项
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    name: String,
+    path: String,
+    size: u64,
+    is_dir: bool,
+    is_symlink: bool,
+    permissions: u32,
+    modified: Option<u64>,
+}
+
+/// 传给后台进程的连接参数（通过标准输入传递，不出现在命令行或环境变量中）
+#[derive(Serialize, Deserialize)]
+struct Spec {
+    target: Endpoint,
+    jump: Option<Endpoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Endpoint {
+    host: String,
+    port: u16,
+    username: String,
+    auth: Auth,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Auth {
+    Password { password: String },
+    PublicKey { public_key: Option<String>, private_key: String, passphrase: Option<String> },
+    Agent,
+}
+
+impl From<&SshConfig> for Endpoint {
+    fn from(config: &SshConfig) -> Self {
+        let auth = match &config.auth {
+            AuthMethod::Password(password) => Auth::Password { password: password.clone() },
+            AuthMethod::PublicKey { public_key, private_key, passphrase } => Auth::PublicKey {
+                public_key: public_key.clone(),
+                private_key: private_key.clone(),
+                passphrase: passphrase.clone(),
+            },
+            AuthMethod::Agent => Auth::Agent,
+        };
+        Self { host: config.host.clone(), port: config.port, username: config.username.clone(), auth }
+    }
+}
+
+impl From<Endpoint> for SshConfig {
+    fn from(endpoint: Endpoint) -> Self {
+        let auth = match endpoint.auth {
+            Auth::Password { password } => AuthMethod::Password(password),
+            Auth::PublicKey { public_key, private_key, passphrase } => {
+                AuthMethod::PublicKey { public_key, private_key, passphrase }
+            }
+            Auth::Agent => AuthMethod::Agent,
+        };
+        Self { host: endpoint.host, port: endpoint.port, username: endpoint.username, auth }
+    }
+}
+
+/// 存放套接字的目录：`$XDG_RUNTIME_DIR/rust-ssh-sftp`，没有时为临时目录下按用户区分的目录
+///
+/// 目录权限为 0700，已存在但属于其他用户或对其他用户开放时拒绝使用。Windows 的临时目录本身按用户区分。
+pub fn socket_dir() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(runtime) => runtime.join("rust-ssh-sftp"),
+        None if cfg!(windows) => std::env::temp_dir().join("rust-ssh-sftp"),
+        None => std::env::temp_dir().join(format!("rust-ssh-sftp-{}", user_id())),
+    };
+    ensure_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// 连接名称对应的套接字路径（Windows 上为记录管道名称的文件），名称中的特殊字符按 `%XX` 编码
+pub fn socket_path(dir: &Path, name: &str) -> PathBuf {
+    let mut file = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'_' | b'-' | b'@') {
+            file.push(byte as char);
+        } else {
+            file.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    dir.join(format!("{}.{}", file, SOCKET_EXT))
+}
+
+/// 从套接字文件名还原连接名称
+fn socket_name(path: &Path) -> Option<String> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(&format!(".{}", SOCKET_EXT))?;
+    let mut bytes = Vec::with_capacity(stem.len());
+    let mut rest = stem.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// 在后台启动主连接进程，等待其完成认证并开始监听
+///
+/// 认证信息（包括已输入的密码）经管道传给子进程，不会出现在进程列表中。
+pub fn spawn(name: &str, config: &SshConfig, jump: Option<&SshConfig>) -> Result<PathBuf> {
+    use std::process::{Command, Stdio};
+
+    let path = socket_path(&socket_dir()?, name);
+    if connect_path(&path)?.is_some() {
+        anyhow::bail!("'{}' 的控制主连接已在运行（使用 `master stop {}` 停止）", name, name);
+    }
+
+    let exe = std::env::current_exe().context("无法获取当前程序路径")?;
+    let mut command = Command::new(exe);
+    command
+        .args(["master", "serve", name])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        // 独立的进程组，终端的 Ctrl+C 不会传给主连接
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        // 不附加到当前控制台，关闭终端或 Ctrl+C 不会结束主连接
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW};
+        command.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+    }
+    let mut child = command.spawn().context("无法启动控制主连接进程")?;
+
+    let spec = Spec { target: config.into(), jump: jump.map(Endpoint::from) };
+    let mut stdin = child.stdin.take().context("无法写入控制主连接进程")?;
+    serde_json::to_writer(&mut stdin, &spec)?;
+    drop(stdin);
+
+    let mut status = String::new();
+    let stdout = child.stdout.take().context("无法读取控制主连接进程")?;
+    BufReader::new(stdout).read_line(&mut status).context("无法读取控制主连接进程")?;
+    match status.trim_end().strip_prefix("error: ") {
+        None if status.trim_end() == "ok" => Ok(path),
+        Some(message) => anyhow::bail!("控制主连接启动失败: {}", message),
+        None => {
+            let _ = child.kill();
+            anyhow::bail!("控制主连接进程意外退出");
+        }
+    }
+}
+
+/// 后台进程入口（`master serve`）：从标准输入读取连接参数，认证后监听套接字直到收到停止请求
+pub fn run_server(name: &str) -> Result<()> {
+    let mut out = std::io::stdout();
+    let result = (|| {
+        let spec: Spec = serde_json::from_reader(std::io::stdin()).context("无法读取连接参数")?;
+        let mut session = RemoteSession::connect(spec.target.into(), spec.jump.map(SshConfig::from))?;
+        session.set_keepalive(Some(KEEPALIVE));
+        let path = socket_path(&socket_dir()?, name);
+        let listener = bind(&path)?;
+        Ok((session, path, listener))
+    })();
+
+    // 只有这一行写到标准输出，之后父进程会关闭管道
+    let (session, path, listener) = match result {
+        Ok(started) => {
+            writeln!(out, "ok")?;
+            out.flush()?;
+            started
+        }
+        Err(e) => {
+            writeln!(out, "error: {:#}", e)?;
+            return Err(e);
+        }
+    };
+
+    info!("控制主连接已启动: {} ({})", name, path.display());
+    let _guard = SocketGuard(path);
+    serve(session, listener)
+}
+
+/// 已连接到主连接的客户端，每个实例只发送一个请求
+pub struct MasterClient {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// 连接名称有可用的主连接时返回客户端，没有时返回 None（调用方直接连接）
+///
+/// 主连接进程已退出而留下的套接字文件会被删除。
+pub fn connect(name: &str) -> Result<Option<MasterClient>> {
+    if !SUPPORTED {
+        return Ok(None);
+    }
+    let path = socket_path(&socket_dir()?, name);
+    let client = connect_path(&path)?;
+    if client.is_some() {
+        debug!("通过控制主连接执行: {}", path.display());
+    }
+    Ok(client)
+}
+
+/// 所有正在运行的主连接名称，同时清理失效的套接字
+pub fn list() -> Result<Vec<String>> {
+    let dir = socket_dir()?;
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&dir).context(format!("无法读取目录: {}", dir.display()))? {
+        let path = entry?.path();
+        let Some(name) = socket_name(&path) else {
+            continue;
+        };
+        if connect_path(&path)?.is_some() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// 停止主连接，没有在运行时返回 false
+pub fn stop(name: &str) -> Result<bool> {
+    match connect(name)? {
+        Some(client) => {
+            client.request(&Request::Stop)?.expect_done()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+impl MasterClient {
+    /// 执行命令，输出写入 `stdout` / `stderr`，返回退出码
+    pub fn exec(self, command: &str, stdout: &mut dyn Write, stderr: &mut dyn Write) -> Result<i32> {
+        let mut replies = self.request(&Request::Exec { command: command.to_string() })?;
+        loop {
+            match replies.next()? {
+                Reply::Stdout { data } => stdout.write_all(&decode(&data)?)?,
+                Reply::Stderr { data } => stderr.write_all(&decode(&data)?)?,
+                Reply::Exit { code } => return Ok(code),
+                other => anyhow::bail!("控制主连接返回了意外的应答: {:?}", other),
+            }
+        }
+    }
+
+    /// 列出目录，`refresh` 为 true 时忽略主连接的列表缓存
+    pub fn list_dir(self, path: &str, refresh: bool) -> Result<Vec<FileInfo>> {
+        match self.request(&Request::List { path: path.to_string(), refresh })?.next()? {
+            Reply::Files { files } => Ok(files.into_iter().map(Entry::into_file_info).collect()),
+            other => anyhow::bail!("控制主连接返回了意外的应答: {:?}", other),
+        }
+    }
+
+    pub fn mkdir(self, path: &str) -> Result<()> {
+        self.request(&Request::Mkdir { path: path.to_string() })?.expect_done()
+    }
+
+    pub fn remove_file(self, path: &str) -> Result<()> {
+        self.request(&Request::Remove { path: path.to_string() })?.expect_done()
+    }
+
+    pub fn realpath(self, path: &str) -> Result<String> {
+        match self.request(&Request::Realpath { path: path.to_string() })?.next()? {
+            Reply::Path { path } => Ok(path),
+            other => anyhow::bail!("控制主连接返回了意外的应答: {:?}", other),
+        }
+    }
+
+    /// 上传本地文件，相对路径按当前目录解析后再发给主连接
+    pub fn upload_file(self, local: &str, remote: &str) -> Result<TransferStats> {
+        let local = absolute(local)?;
+        self.request(&Request::Upload { local, remote: remote.to_string() })?.expect_transferred()
+    }
+
+    pub fn download_file(self, remote: &str, local: &str) -> Result<TransferStats> {
+        let local = absolute(local)?;
+        self.request(&Request::Download { remote: remote.to_string(), local })?.expect_transferred()
+    }
+
+    fn request(mut self, request: &Request) -> Result<Replies> {
+        let stream = self.stream.get_mut();
+        serde_json::to_writer(&mut *stream, request)?;
+        stream.write_all(b"\n")?;
+        stream.flush().context("无法发送请求到控制主连接")?;
+        Ok(Replies { stream: self.stream })
+    }
+}
+
+/// 一个请求的应答序列
+struct Replies {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Replies {
+    /// 下一条应答，主连接报告的错误转换为 Err
+    fn next(&mut self) -> Result<Reply> {
+        match read_message(&mut self.stream)? {
+            Some(Reply::Error { message }) => Err(anyhow::anyhow!(message)),
+            Some(reply) => Ok(reply),
+            None => anyhow::bail!("控制主连接意外断开"),
+        }
+    }
+
+    fn expect_done(mut self) -> Result<()> {
+        match self.next()? {
+            Reply::Done => Ok(()),
+            other => anyhow::bail!("控制主连接返回了意外的应答: {:?}", other),
+        }
+    }
+
+    fn expect_transferred(mut self) -> Result<TransferStats> {
+        match self.next()? {
+            Reply::Transferred { bytes, elapsed_ms } => {
+                Ok(TransferStats::single_file(bytes, Duration::from_millis(elapsed_ms)))
+            }
+            other => anyhow::bail!("控制主连接返回了意外的应答: {:?}", other),
+        }
+    }
+}
+
+impl Entry {
+    fn from_file_info(file: FileInfo) -> Self {
+        Self {
+            name: file.name,
+            path: file.path,
+            size: file.size,
+            is_dir: file.is_dir,
+            is_symlink: file.is_symlink,
+            permissions: file.permissions,
+            modified: file.modified,
+        }
+    }
+
+    fn into_file_info(self) -> FileInfo {
+        FileInfo {
+            raw_path: PathBuf::from(&self.path),
+            name: self.name,
+            path: self.path,
+            size: self.size,
+            is_dir: self.is_dir,
+            permissions: self.permissions,
+            is_symlink: self.is_symlink,
+            link_target: None,
+            link_broken: false,
+            modified: self.modified,
+            uid: None,
+            gid: None,
+        }
+    }
+}
+
+fn read_message<T: for<'de> Deserialize<'de>>(reader: &mut impl BufRead) -> Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line).context("无法解析控制主连接的消息")?))
+}
+
+fn write_message(writer: &mut impl Write, reply: &Reply) -> Result<()> {
+    serde_json::to_writer(&mut *writer, reply)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn decode(data: &str) -> Result<Vec<u8>> {
+    general_purpose::STANDARD.decode(data).context("无法解析控制主连接的输出")
+}
+
+fn absolute(path: &str) -> Result<String> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return Ok(path.to_string_lossy().into_owned());
+    }
+    let cwd = std::env::current_dir().context("无法获取当前目录")?;
+    Ok(cwd.join(path).to_string_lossy().into_owned())
+}
+
+/// 依次处理请求，直到收到停止请求或连接断开且无法重连
+fn serve(mut session: RemoteSession, mut listener: impl Listen) -> Result<()> {
+    let cache = Arc::new(ListingCache::new(LISTING_TTL));
+    loop {
+        let stream = match listener.accept() {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("接受连接失败: {}", e);
+                continue;
+            }
+        };
+        let mut reader = BufReader::new(stream);
+        let request: Request = match read_message(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("{:#}", e);
+                continue;
+            }
+        };
+        debug!("控制主连接收到请求: {:?}", request);
+        if matches!(request, Request::Stop) {
+            write_message(reader.get_mut(), &Reply::Done).ok();
+            info!("控制主连接已停止");
+            return Ok(());
+        }
+
+        let writer = reader.get_mut();
+        if let Err(e) = handle(&session, &cache, request, writer) {
+            write_message(writer, &Reply::Error { message: format!("{:#}", e) }).ok();
+            if session::is_connection_error(&e) {
+                // 重连失败时退出，套接字随之删除，之后的调用会直接连接
+                session.reconnect().context("控制主连接已断开且无法重连")?;
+                cache.clear();
+            }
+        }
+    }
+}
+
+fn handle(session: &RemoteSession, cache: &Arc<ListingCache>, request: Request, writer: &mut impl Write) -> Result<()> {
+    let client = session.client();
+    let sftp = || SftpClient::new(client).map(|sftp| sftp.with_listing_cache(cache.clone()));
+    let reply = match request {
+        Request::Exec { command } => {
+            let result = exec(session, &command, writer);
+            let event = audit::Event::for_config(audit::Operation::Exec, client.config()).command(&command);
+            let event = match result {
+                Ok(code) => event.exit_code(code),
+                Err(_) => event,
+            };
+            audit::record(event, &result);
+            return write_message(writer, &Reply::Exit { code: result? });
+        }
+        Request::List { path, refresh } => {
+            let sftp = sftp()?;
+            let files = if refresh { sftp.refresh_dir(&path)? } else { sftp.list_dir(&path)? };
+            Reply::Files { files: files.into_iter().map(Entry::from_file_info).collect() }
+        }
+        Request::Mkdir { path } => {
+            sftp()?.mkdir(&path)?;
+            Reply::Done
+        }
+        Request::Remove { path } => {
+            sftp()?.remove_file(&path)?;
+            Reply::Done
+        }
+        Request::Upload { local, remote } => {
+            transferred(sftp()?.upload_file(&local, &remote, None)?)
+        }
+        Request::Download { remote, local } => {
+            transferred(sftp()?.download_file(&remote, &local, None)?)
+        }
+        Request::Realpath { path } => Reply::Path { path: sftp()?.realpath(&path)? },
+        Request::Stop => Reply::Done,
+    };
+    write_message(writer, &reply)
+}
+
+fn transferred(stats: TransferStats) -> Reply {
+    Reply::Transferred { bytes: stats.total_bytes, elapsed_ms: stats.elapsed.as_millis() as u64 }
+}
+
+/// 执行命令，标准输出边读边转发，返回退出码
+fn exec(session: &RemoteSession, command: &str, writer: &mut impl Write) -> Result<i32> {
+    let client = session.client();
+    let _io = client.lock_io();
+    let mut channel = client.session().channel_session().context("无法创建通道")?;
+    channel.exec(command).context("命令执行失败")?;
+
+    let mut buffer = [0u8; 32 * 1024];
+    loop {
+        let n = channel.read(&mut buffer).context("读取输出失败")?;
+        if n == 0 {
+            break;
+        }
+        write_message(writer, &Reply::Stdout { data: general_purpose::STANDARD.encode(&buffer[..n]) })?;
+    }
+    let mut stderr = Vec::new();
+    channel.stderr().read_to_end(&mut stderr).ok();
+    if !stderr.is_empty() {
+        write_message(writer, &Reply::Stderr { data: general_purpose::STANDARD.encode(&stderr) })?;
+    }
+    channel.wait_close().context("等待通道关闭失败")?;
+    channel.exit_status().context("获取退出状态失败")
+}
+
+/// 接受客户端连接的监听端，每个连接处理一个请求
+trait Listen {
+    type Stream: Read + Write;
+
+    fn accept(&mut self) -> std::io::Result<Self::Stream>;
+}
+
+#[cfg(unix)]
+impl Listen for std::os::unix::net::UnixListener {
+    type Stream = std::os::unix::net::UnixStream;
+
+    fn accept(&mut self) -> std::io::Result<Self::Stream> {
+        std::os::unix::net::UnixListener::accept(self).map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(windows)]
+impl Listen for pipe::Listener {
+    type Stream = pipe::Stream;
+
+    fn accept(&mut self) -> std::io::Result<Self::Stream> {
+        pipe::Listener::accept(self)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+impl Listen for std::convert::Infallible {
+    type Stream = std::io::Empty;
+
+    fn accept(&mut self) -> std::io::Result<Self::Stream> {
+        match *self {}
+    }
+}
+
+/// 连接套接字；连接被拒绝或文件不存在时视为没有主连接，并删除失效的套接字文件
+#[cfg(unix)]
+fn connect_path(path: &Path) -> Result<Option<MasterClient>> {
+    use std::io::ErrorKind;
+    use std::os::unix::net::UnixStream;
+
+    match UnixStream::connect(path) {
+        Ok(stream) => Ok(Some(MasterClient { stream: BufReader::new(Box::new(stream)) })),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            debug!("删除失效的控制主连接套接字: {}", path.display());
+            std::fs::remove_file(path).ok();
+            Ok(None)
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("无法连接控制主连接: {}", path.display()))),
+    }
+}
+
+/// 连接记录文件中的命名管道；文件不存在时视为没有主连接，管道已随主连接进程消失时删除记录文件
+#[cfg(windows)]
+fn connect_path(path: &Path) -> Result<Option<MasterClient>> {
+    use std::io::ErrorKind;
+
+    let name = match std::fs::read_to_string(path) {
+        Ok(name) => name,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow::Error::new(e).context(format!("无法读取控制主连接: {}", path.display()))),
+    };
+    match pipe::connect(name.trim()) {
+        Ok(stream) => Ok(Some(MasterClient { stream: BufReader::new(Box::new(stream)) })),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            debug!("删除失效的控制主连接记录: {}", path.display());
+            std::fs::remove_file(path).ok();
+            Ok(None)
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("无法连接控制主连接: {}", name.trim()))),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn connect_path(_path: &Path) -> Result<Option<MasterClient>> {
+    Ok(None)
+}
+
+/// 创建只有当前用户可以访问的套接字
+#[cfg(unix)]
+fn bind(path: &Path) -> Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if connect_path(path)?.is_some() {
+        anyhow::bail!("控制主连接已在运行: {}", path.display());
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .context(format!("无法监听套接字: {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .context(format!("无法设置套接字权限: {}", path.display()))?;
+    Ok(listener)
+}
+
+/// 创建只有当前用户可以访问的命名管道，并把管道名称写入 `path`
+///
+/// 管道名称随机生成，其他用户无法预先占用；记录文件先写入临时文件再重命名，客户端不会读到一半的名称。
+#[cfg(windows)]
+fn bind(path: &Path) -> Result<pipe::Listener> {
+    if connect_path(path)?.is_some() {
+        anyhow::bail!("控制主连接已在运行: {}", path.display());
+    }
+    let name = format!(r"\\.\pipe\rust-ssh-sftp-{:032x}", rand::random::<u128>());
+    let listener = pipe::Listener::bind(&name).context(format!("无法创建命名管道: {}", name))?;
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, &name).context(format!("无法写入文件: {}", temp.display()))?;
+    std::fs::rename(&temp, path).context(format!("无法写入文件: {}", path.display()))?;
+    Ok(listener)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn bind(_path: &Path) -> Result<std::convert::Infallible> {
+    anyhow::bail!("当前平台不支持控制主连接")
+}
+
+/// Windows 命名管道的服务端和客户端
+#[cfg(windows)]
+mod pipe {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, LocalFree, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER};
+    use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX, SECURITY_IDENTIFICATION};
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, WaitNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    /// 管道的输入输出缓冲区大小
+    const BUFFER_SIZE: u32 = 64 * 1024;
+
+    /// 所有实例都在使用时，客户端最多等待的毫秒数
+    const BUSY_TIMEOUT_MS: u32 = 5000;
+
+    /// 监听中的管道，始终保留一个等待客户端的实例
+    pub struct Listener {
+        name: Vec<u16>,
+        security: SecurityDescriptor,
+        waiting: File,
+    }
+
+    /// 服务端的一个连接，关闭前等待客户端读完应答
+    pub struct Stream(File);
+
+    impl Listener {
+        /// 创建第一个实例，同名管道已存在时失败
+        pub fn bind(name: &str) -> io::Result<Self> {
+            let security = SecurityDescriptor::current_user()?;
+            let name = wide(name);
+            let waiting = create(&name, &security, true)?;
+            Ok(Self { name, security, waiting })
+        }
+
+        /// 等待客户端连接；先创建下一个实例，处理这个连接期间新的客户端也能连上等待
+        pub fn accept(&mut self) -> io::Result<Stream> {
+            let next = create(&self.name, &self.security, false)?;
+            let connected = unsafe { ConnectNamedPipe(self.waiting.as_raw_handle(), ptr::null_mut()) } != 0;
+            let previous = std::mem::replace(&mut self.waiting, next);
+            if !connected {
+                // 客户端在创建实例和等待之间已连上时也算成功，其他错误丢弃这个实例
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                    return Err(error);
+                }
+            }
+            Ok(Stream(previous))
+        }
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Drop for Stream {
+        fn drop(&mut self) {
+            // FlushFileBuffers：直接关闭会丢弃客户端还没读取的应答
+            self.0.sync_all().ok();
+        }
+    }
+
+    /// 连接管道，所有实例都在使用时等待；管道不存在时返回 NotFound
+    pub fn connect(name: &str) -> io::Result<File> {
+        loop {
+            // 只允许服务端识别、不允许模拟客户端身份
+            match OpenOptions::new().read(true).write(true).security_qos_flags(SECURITY_IDENTIFICATION).open(name) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                    if unsafe { WaitNamedPipeW(wide(name).as_ptr(), BUSY_TIMEOUT_MS) } == 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn create(name: &[u16], security: &SecurityDescriptor, first: bool) -> io::Result<File> {
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: security.0,
+            bInheritHandle: 0,
+        };
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                &attributes,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_handle(handle) })
+    }
+
+    /// 只授予当前用户访问权限的安全描述符
+    struct SecurityDescriptor(PSECURITY_DESCRIPTOR);
+
+    impl SecurityDescriptor {
+        fn current_user() -> io::Result<Self> {
+            // 受保护的 DACL，不继承任何权限，只有当前用户一条完全控制的 ACE
+            let sddl = wide(&format!("D:P(A;;GA;;;{})", current_user_sid()?));
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            let ok = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1, &mut descriptor, ptr::null_mut())
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(descriptor))
+        }
+    }
+
+    impl Drop for SecurityDescriptor {
+        fn drop(&mut self) {
+            unsafe { LocalFree(self.0) };
+        }
+    }
+
+    /// 当前进程用户的 SID（`S-1-5-21-...` 形式）
+    fn current_user_sid() -> io::Result<String> {
+        unsafe {
+            let mut token: HANDLE = ptr::null_mut();
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut len = 0u32;
+            GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut len);
+            // 按 8 字节对齐，TOKEN_USER 中含指针
+            let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+            let ok = GetTokenInformation(token, TokenUser, buffer.as_mut_ptr().cast(), len, &mut len);
+            let error = io::Error::last_os_error();
+            CloseHandle(token);
+            if ok == 0 {
+                return Err(error);
+            }
+
+            let user = &*buffer.as_ptr().cast::<TOKEN_USER>();
+            let mut sid: *mut u16 = ptr::null_mut();
+            if ConvertSidToStringSidW(user.User.Sid, &mut sid) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let len = (0..).take_while(|&i| *sid.add(i) != 0).count();
+            let string = String::from_utf16_lossy(std::slice::from_raw_parts(sid, len));
+            LocalFree(sid.cast());
+            Ok(string)
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+}
+
+/// 退出时删除套接字文件
+struct SocketGuard(PathBuf);
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn ensure_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if !dir.exists() {
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(dir)
+            .context(format!("无法创建目录: {}", dir.display()))?;
+    }
+    let metadata = std::fs::symlink_metadata(dir).context(format!("无法访问目录: {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != user_id() || metadata.mode() & 0o077 != 0 {
+        anyhow::bail!("目录 {} 不属于当前用户或权限过宽（应为 0700），拒绝使用", dir.display());
+    }
+    Ok(())
+}
+
+/// 位于当前用户的临时目录下，沿用其只有本人可以访问的权限
+#[cfg(windows)]
+fn ensure_private_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).context(format!("无法创建目录: {}", dir.display()))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn ensure_private_dir(_dir: &Path) -> Result<()> {
+    ensure_supported()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_name_round_trip() {
+        let dir = Path::new("/run/user/1000/rust-ssh-sftp");
+        let path = socket_path(dir, "deploy@web:2222/a b");
+        assert_eq!(path.file_name().unwrap(), "deploy@web%3A2222%2Fa%20b.sock");
+        assert_eq!(socket_name(&path).unwrap(), "deploy@web:2222/a b");
+        assert_eq!(socket_name(Path::new("other.txt")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_socket_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gone.sock");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        assert!(connect_path(&path).unwrap().is_none());
+        assert!(!path.exists());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_stale_pipe_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gone.sock");
+        std::fs::write(&path, r"\\.\pipe\rust-ssh-sftp-test-gone").unwrap();
+        assert!(connect_path(&path).unwrap().is_none());
+        assert!(!path.exists());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_pipe_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.sock");
+        let mut listener = bind(&path).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with(r"\\.\pipe\rust-ssh-sftp-"));
+        let handle = std::thread::spawn(move || {
+            let mut reader = BufReader::new(listener.accept().unwrap());
+            let request: Request = read_message(&mut reader).unwrap().unwrap();
+            assert!(matches!(request, Request::Stop));
+            write_message(reader.get_mut(), &Reply::Done).unwrap();
+        });
+        connect_path(&path).unwrap().unwrap().request(&Request::Stop).unwrap().expect_done().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_master_operations() {
+        use crate::test_server::TestServer;
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = TestServer::start();
+        let session = RemoteSession::connect(server.password_config(), None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.sock");
+        let listener = bind(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let handle = std::thread::spawn(move || serve(session, listener));
+        let client = || connect_path(&path).unwrap().unwrap();
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = client().exec("echo hi; echo oops >&2; exit 3", &mut out, &mut err).unwrap();
+        assert_eq!((code, out.as_slice(), err.as_slice()), (3, &b"hi\n"[..], &b"oops\n"[..]));
+
+        let local = dir.path().join("a.txt");
+        std::fs::write(&local, b"hello").unwrap();
+        client().mkdir("/up").unwrap();
+        let stats = client().upload_file(local.to_str().unwrap(), "/up/a.txt").unwrap();
+        assert_eq!(stats.total_bytes, 5);
+        let files = client().list_dir("/up", false).unwrap();
+        assert_eq!((files[0].name.as_str(), files[0].size), ("a.txt", 5));
+        // 列表被缓存，不经主连接的修改需要 refresh 才能看到
+        std::fs::write(server.local_path("/up/c.txt"), b"c").unwrap();
+        assert_eq!(client().list_dir("/up", false).unwrap().len(), 1);
+        assert_eq!(client().list_dir("/up", true).unwrap().len(), 2);
+        let back = dir.path().join("b.txt");
+        client().download_file("/up/a.txt", back.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read(&back).unwrap(), b"hello");
+        assert_eq!(client().realpath("up/../up/.").unwrap(), "/up");
+        client().remove_file("/up/a.txt").unwrap();
+        assert!(!server.local_path("/up/a.txt").exists());
+        assert_eq!(client().list_dir("/up", false).unwrap().len(), 1);
+
+        let err = client().remove_file("/up/missing").unwrap_err();
+        assert!(!err.to_string().is_empty());
+
+        client().request(&Request::Stop).unwrap().expect_done().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+//! 离线命令：只读写本地配置的命令
+//!
+//! `config list`、`config show`、`config export`、`config show-password` 只需要配置文件和主密码，
+//! 不应解析主机名或连接任何服务器。这些命令通过 [`OfflineContext`] 执行：它只持有只读的配置，
+//! 没有任何建立连接的方法；同时在存续期间把当前线程标记为离线，连接、DNS 解析和
+//! SSH agent 的入口调用 [`ensure_network`]，若共用的辅助函数以后意外走到这些入口会直接报错，
+//! 而不是悄悄访问网络。
+
+use anyhow::{Context, Result};
+use colored::{Color, Colorize};
+use std::cell::Cell;
+
+use crate::check;
+use crate::config::{AppConfig, SavedConnection};
+use crate::crypto::CryptoManager;
+use crate::glyphs;
+use crate::import;
+use crate::prompt::Prompter;
+use crate::table::{self, Align, Cell as TableCell, Table};
+
+thread_local! {
+    /// 当前线程是否处于离线命令中
+    static OFFLINE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 访问网络前调用，离线命令中返回错误；`action` 说明要做什么，例如“连接 example.com:22”
+pub fn ensure_network(action: &str) -> Result<()> {
+    if OFFLINE.with(Cell::get) {
+        anyhow::bail!("离线命令不能访问网络（{}）", action);
+    }
+    Ok(())
+}
+
+/// 只读配置命令的执行环境，存续期间当前线程不能访问网络
+pub struct OfflineContext<'a> {
+    config: &'a AppConfig,
+    previous: bool,
+}
+
+impl<'a> OfflineContext<'a> {
+    pub fn enter(config: &'a AppConfig) -> Self {
+        let previous = OFFLINE.with(|offline| offline.replace(true));
+        Self { config, previous }
+    }
+
+    /// `config list`
+    pub fn list(&self) -> Result<()> {
+        let connections = self.config.list_connections();
+
+        if connections.is_empty() {
+            println!("没有保存的连接");
+            return Ok(());
+        }
+
+        if !table::is_plain() {
+            println!("\n{}\n", "保存的连接:".cyan().bold());
+        }
+
+        let first_name = connections.first().map(|c| c.name.clone());
+
+        let mut list = Table::new()
+            .column("", Align::Left)
+            .truncated_column("名称", Align::Left)
+            .truncated_column("地址", Align::Left)
+            .column("认证", Align::Left)
+            .column("密码", Align::Left);
+        for conn in connections {
+            let is_default = self.config.default_connection.as_deref() == Some(&conn.name);
+            let marker = if is_default { "*" } else { "" };
+            let password_marker = if conn.has_saved_password() { glyphs::key() } else { "" };
+
+            list.row(vec![
+                TableCell::new(marker).color(Color::Green).bold(),
+                TableCell::new(conn.name.as_str()).color(Color::Yellow).bold(),
+                format!("{}@{}:{}", conn.username, conn.host, conn.port).into(),
+                conn.auth_type.as_str().into(),
+                password_marker.into(),
+            ]);
+        }
+        list.print();
+        if table::is_plain() {
+            return Ok(());
+        }
+
+        println!("\n{}", "提示:".yellow().bold());
+        println!("  {} 表示默认连接", "*".green().bold());
+        println!("  {} 表示已保存密码", glyphs::key());
+        println!("  使用 {} 连接，例如: connect {}",
+            "[连接名称]".yellow(),
+            first_name.as_deref().unwrap_or("myserver"));
+        Ok(())
+    }
+
+    /// `config show`
+    pub fn show(&self, name: &str) -> Result<()> {
+        let conn = self.config.get_connection(name)
+            .context(format!("连接 '{}' 不存在", name))?;
+
+        println!("\n{} {}\n", "连接详情:".cyan().bold(), name);
+        println!("  主机:     {}", conn.host);
+        println!("  端口:     {}", conn.port);
+        println!("  用户名:   {}", conn.username);
+        println!("  认证方式: {}", conn.auth_type);
+        if let Some(ref color) = conn.color {
+            println!("  颜色:     {}", color);
+        }
+        if let Some(ref note) = conn.note {
+            println!("  备注:     {}", note);
+        }
+        if !conn.secrets.is_empty() {
+            let mut keys: Vec<_> = conn.secrets.keys().map(String::as_str).collect();
+            keys.sort();
+            println!("  附加密文: {}", keys.join(", "));
+        }
+
+        if let Some(ref key) = conn.private_key_path {
+            println!("  私钥:     {}", key);
+        }
+        if let Some(ref key) = conn.public_key_path {
+            println!("  公钥:     {}", key);
+        }
+        if let Some(ref jump) = conn.jump_host {
+            println!("  跳板机:   {}", jump);
+        }
+        if let Some(ref dir) = conn.default_remote_dir {
+            println!("  远程目录: {}", dir);
+        }
+        if let Some(ref escape) = conn.escape_char {
+            println!("  转义字符: {}", escape);
+        }
+        if let Some(ref fingerprint) = conn.host_key_fingerprint {
+            println!("  主机指纹: {}", fingerprint);
+        }
+        if let Some(ref info) = conn.server_info {
+            let seen_at = chrono::DateTime::from_timestamp(info.seen_at as i64, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            println!("\n{} (截至 {})", "服务器信息:".cyan().bold(), seen_at);
+            check::print_server_info(info);
+        }
+        if let Some(ref command) = conn.pre_connect_command {
+            println!("  连接前:   {}", command);
+        }
+        if let Some(ref command) = conn.post_disconnect_command {
+            println!("  断开后:   {}", command);
+        }
+        if let Some(ref command) = conn.credential_helper {
+            println!("  凭据助手: {}", command);
+        }
+        Ok(())
+    }
+
+    /// `config export`
+    pub fn export(&self, prompter: &mut dyn Prompter, file: &str, include_secrets: bool) -> Result<()> {
+        if include_secrets {
+            // 与 show-password 相同，先确认主密码正确再导出密文
+            if let Some(encrypted) = self.config.any_ciphertext() {
+                println!("{}", "导出密文需要主密码".yellow().bold());
+                CryptoManager::unlock(prompter, encrypted, self.config.unlock_cooldown(), "导出密文")?;
+            }
+        }
+        let content = import::export(self.config, include_secrets)?;
+        std::fs::write(file, content).context(format!("无法写入文件: {}", file))?;
+        println!(
+            "{} 已导出 {} 个连接到 {}",
+            glyphs::ok().green().bold(),
+            self.config.connections.len(),
+            file
+        );
+        if include_secrets {
+            println!("{}", format!("{}  导出文件包含加密的密码，只能在使用相同主密码和 salt 文件的环境中解密", glyphs::warn()).yellow());
+        }
+        Ok(())
+    }
+
+    /// `config show-password`：密文损坏的连接单独报告，不影响其他连接
+    pub fn show_password(&self, prompter: &mut dyn Prompter, name: Option<&str>) -> Result<()> {
+        let connections: Vec<&SavedConnection> = if let Some(name) = name {
+            let conn = self.config.get_connection(name)
+                .context(format!("连接 '{}' 不存在", name))?;
+            if !conn.has_saved_password() {
+                anyhow::bail!("连接 '{}' 没有保存密码", name);
+            }
+            vec![conn]
+        } else {
+            self.config.list_connections()
+                .into_iter()
+                .filter(|c| c.has_saved_password())
+                .collect()
+        };
+
+        if connections.is_empty() {
+            println!("{}", "没有保存密码的连接".yellow());
+            return Ok(());
+        }
+
+        if !CryptoManager::has_master_password() {
+            anyhow::bail!("未设置主密码，无法解密");
+        }
+
+        // 用格式完好的密文校验主密码（能解密任一即可）；全部损坏时不询问主密码，直接逐条报告
+        let ciphertexts: Vec<&str> = connections
+            .iter()
+            .filter_map(|c| c.encrypted_secret())
+            .filter(|encrypted| CryptoManager::check_format(encrypted).is_ok())
+            .collect();
+        let crypto_manager = if ciphertexts.is_empty() {
+            None
+        } else {
+            println!("{}", "需要主密码来解密保存的密码".yellow().bold());
+            Some(CryptoManager::unlock_any(prompter, &ciphertexts, self.config.unlock_cooldown(), "查看已保存的密码")?)
+        };
+
+        println!("\n{}\n", "已保存的密码:".cyan().bold());
+
+        let mut failed = 0;
+        for conn in connections {
+            let secret = match reveal(crypto_manager.as_ref(), conn) {
+                Ok(secret) => secret.green().to_string(),
+                Err(e) => {
+                    failed += 1;
+                    format!("{:#}", e).red().to_string()
+                }
+            };
+
+            println!("  [{}]", conn.name.yellow().bold());
+            println!("    主机:     {}@{}:{}", conn.username, conn.host, conn.port);
+            println!("    认证方式: {}", conn.auth_type);
+            if conn.auth_type == "publickey" {
+                if let Some(ref key) = conn.private_key_path {
+                    println!("    私钥:     {}", key);
+                }
+                println!("    私钥密码: {}", secret);
+            } else {
+                println!("    密码:     {}", secret);
+            }
+            println!();
+        }
+
+        if failed > 0 {
+            println!("{} {} 个连接的密文无法解密，需要重新保存密码", glyphs::fail().red(), failed);
+        }
+        println!("{}", format!("{}  请注意保护好这些密码信息！", glyphs::warn()).yellow().bold());
+        Ok(())
+    }
+}
+
+impl Drop for OfflineContext<'_> {
+    fn drop(&mut self) {
+        OFFLINE.with(|offline| offline.set(self.previous));
+    }
+}
+
+/// 解密连接保存的密码或私钥密码
+fn reveal(crypto_manager: Option<&CryptoManager>, conn: &SavedConnection) -> Result<String> {
+    let encrypted = conn.encrypted_secret().context("未保存密码")?;
+    CryptoManager::check_format(encrypted).context("密文已损坏")?;
+    crypto_manager.context("未解锁主密码")?.decrypt(encrypted).context("解密失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::Scripted;
+    use crate::ssh::{AuthMethod, SshClient, SshConfig};
+
+    fn config() -> AppConfig {
+        let mut config = AppConfig::default();
+        let mut web = SavedConnection::new_password("web".to_string(), "web.example.invalid".to_string(), 22, "deploy".to_string());
+        web.encrypted_password = Some("不是密文".to_string());
+        web.jump_host = Some("bastion".to_string());
+        config.add_connection(web);
+        config.add_connection(SavedConnection::new_agent("db".to_string(), "db.example.invalid".to_string(), 2222, "app".to_string()));
+        config
+    }
+
+    #[test]
+    fn test_network_denied() {
+        let config = config();
+        {
+            let _offline = OfflineContext::enter(&config);
+            assert!(ensure_network("连接 web.example.invalid:22").is_err());
+            // 连接入口在建立 TCP 连接之前就失败
+            let ssh = SshConfig {
+                host: "127.0.0.1".to_string(),
+                port: 1,
+                username: "app".to_string(),
+                auth: AuthMethod::Password("x".to_string()),
+            };
+            let err = SshClient::connect(ssh).err().unwrap();
+            assert!(format!("{:#}", err).contains("离线命令不能访问网络"), "{:#}", err);
+        }
+        assert!(ensure_network("连接 web.example.invalid:22").is_ok());
+    }
+
+    /// 配置命令在网络不可用时完成，不会走到连接入口
+    #[test]
+    fn test_commands_offline() {
+        let config = config();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("export.toml");
+        let offline = OfflineContext::enter(&config);
+
+        offline.list().unwrap();
+        offline.show("web").unwrap();
+        offline.show("db").unwrap();
+        assert!(offline.show("missing").is_err());
+        offline.export(&mut Scripted::new(&[]), file.to_str().unwrap(), false).unwrap();
+        assert!(std::fs::read_to_string(&file).unwrap().contains("web.example.invalid"));
+        // 唯一的密文已损坏：逐条报告，不询问主密码
+        CryptoManager::new("test_password").unwrap();
+        offline.show_password(&mut Scripted::new(&[]), None).unwrap();
+        assert!(ensure_network("解析 web.example.invalid").is_err());
+    }
+
+    #[test]
+    fn test_reveal() {
+        let manager = CryptoManager::new("test_password").unwrap();
+        let mut good = SavedConnection::new_password("good".to_string(), "a".to_string(), 22, "u".to_string());
+        good.encrypted_password = Some(manager.encrypt("hunter2").unwrap());
+        let mut truncated = good.clone();
+        truncated.encrypted_password = Some("AAAA".to_string());
+        let mut foreign = good.clone();
+        foreign.encrypted_password = Some(CryptoManager::new("other_password").unwrap().encrypt("hunter2").unwrap());
+
+        assert_eq!(reveal(Some(&manager), &good).unwrap(), "hunter2");
+        assert!(format!("{:#}", reveal(Some(&manager), &truncated).unwrap_err()).starts_with("密文已损坏"));
+        assert!(format!("{:#}", reveal(None, &truncated).unwrap_err()).starts_with("密文已损坏"));
+        assert!(format!("{:#}", reveal(Some(&manager), &foreign).unwrap_err()).starts_with("解密失败"));
+    }
+}
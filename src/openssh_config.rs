@@ -0,0 +1,272 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::paths;
+
+/// ~/.ssh/config 中解析出的主机设置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostSettings {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<String>,
+}
+
+/// 一个 Host 块
+#[derive(Debug, Clone)]
+struct HostBlock {
+    /// Host 后面的模式列表（`!` 开头表示排除）
+    patterns: Vec<String>,
+    /// 块内的配置项（关键字已转为小写）
+    options: Vec<(String, String)>,
+}
+
+impl HostBlock {
+    /// 按 OpenSSH 规则匹配：任一否定模式命中则不匹配，否则需要至少一个普通模式命中
+    fn matches(&self, alias: &str) -> bool {
+        let alias = alias.to_lowercase();
+        let mut matched = false;
+        for pattern in &self.patterns {
+            let pattern = pattern.to_lowercase();
+            if let Some(negated) = pattern.strip_prefix('!') {
+                if wildcard_match(negated, &alias) {
+                    return false;
+                }
+            } else if wildcard_match(&pattern, &alias) {
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+/// OpenSSH 客户端配置（~/.ssh/config）
+#[derive(Debug, Clone, Default)]
+pub struct OpenSshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+impl OpenSshConfig {
+    /// ~/.ssh/config 路径
+    pub fn path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".ssh").join("config"))
+    }
+
+    /// 加载 ~/.ssh/config，文件不存在时返回空配置
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .context(format!("无法读取 SSH 配置文件: {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// 解析配置文本
+    pub fn parse(content: &str) -> Self {
+        // 第一个 Host 之前的配置项对所有主机生效
+        let mut blocks = vec![HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        }];
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((keyword, value)) = split_keyword(line) else {
+                continue;
+            };
+            let keyword = keyword.to_lowercase();
+
+            match keyword.as_str() {
+                "host" => blocks.push(HostBlock {
+                    patterns: value.split_whitespace().map(|p| p.to_string()).collect(),
+                    options: Vec::new(),
+                }),
+                // 不支持 Match 条件，其后的配置项全部忽略
+                "match" => blocks.push(HostBlock {
+                    patterns: Vec::new(),
+                    options: Vec::new(),
+                }),
+                "include" => debug!("忽略 SSH 配置中的 Include: {}", value),
+                _ => {
+                    if let Some(block) = blocks.last_mut() {
+                        block.options.push((keyword, unquote(value).to_string()));
+                    }
+                }
+            }
+        }
+
+        Self { blocks }
+    }
+
+    /// 解析主机别名，每个配置项以第一次出现的值为准（与 OpenSSH 一致）
+    pub fn resolve(&self, alias: &str) -> HostSettings {
+        let mut settings = HostSettings::default();
+
+        for block in self.blocks.iter().filter(|b| b.matches(alias)) {
+            for (keyword, value) in &block.options {
+                match keyword.as_str() {
+                    "hostname" if settings.hostname.is_none() => {
+                        settings.hostname = Some(value.replace("%h", alias));
+                    }
+                    "user" if settings.user.is_none() => {
+                        settings.user = Some(value.clone());
+                    }
+                    "port" if settings.port.is_none() => {
+                        settings.port = value.parse().ok();
+                    }
+                    "identityfile" if settings.identity_file.is_none() => {
+                        settings.identity_file = Some(paths::expand(value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        settings
+    }
+
+    /// 配置中出现的具体主机别名（不含通配符和否定模式），按出现顺序去重
+    pub fn hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = Vec::new();
+        for pattern in self.blocks.iter().flat_map(|b| &b.patterns) {
+            if pattern.starts_with('!') || pattern.contains(['*', '?']) {
+                continue;
+            }
+            if !hosts.contains(pattern) {
+                hosts.push(pattern.clone());
+            }
+        }
+        hosts
+    }
+}
+
+/// 拆分 `Keyword value` 或 `Keyword=value`
+fn split_keyword(line: &str) -> Option<(&str, &str)> {
+    let idx = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let keyword = &line[..idx];
+    let rest = line[idx..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+    if keyword.is_empty() || rest.is_empty() {
+        return None;
+    }
+    Some((keyword, rest))
+}
+
+/// 去掉值两侧的引号
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// 通配符匹配（`*` 匹配任意字符串，`?` 匹配单个字符）
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+# 团队共享配置
+Host jumpbox
+    HostName 10.0.0.5
+    User ops
+    Port 2222
+
+Host *.internal !db.internal
+    User deploy
+    IdentityFile "/keys/deploy"
+
+Host web?
+    HostName %h.example.com
+
+Match host foo
+    User ignored
+
+Host *
+    User fallback
+    Port=2200
+"#;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*.internal", "app.internal"));
+        assert!(!wildcard_match("*.internal", "app.external"));
+        assert!(wildcard_match("web?", "web1"));
+        assert!(!wildcard_match("web?", "web12"));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+    }
+
+    #[test]
+    fn test_resolve_first_match_wins() {
+        let config = OpenSshConfig::parse(SAMPLE);
+
+        let jumpbox = config.resolve("jumpbox");
+        assert_eq!(jumpbox.hostname.as_deref(), Some("10.0.0.5"));
+        assert_eq!(jumpbox.user.as_deref(), Some("ops"));
+        assert_eq!(jumpbox.port, Some(2222));
+
+        let app = config.resolve("app.internal");
+        assert_eq!(app.hostname, None);
+        assert_eq!(app.user.as_deref(), Some("deploy"));
+        assert_eq!(app.port, Some(2200));
+        assert_eq!(app.identity_file.as_deref(), Some("/keys/deploy"));
+    }
+
+    #[test]
+    fn test_resolve_negation_and_tokens() {
+        let config = OpenSshConfig::parse(SAMPLE);
+
+        let db = config.resolve("db.internal");
+        assert_eq!(db.user.as_deref(), Some("fallback"));
+        assert_eq!(db.identity_file, None);
+
+        let web = config.resolve("web1");
+        assert_eq!(web.hostname.as_deref(), Some("web1.example.com"));
+    }
+
+    #[test]
+    fn test_hosts_skips_patterns() {
+        let config = OpenSshConfig::parse(SAMPLE);
+        assert_eq!(config.hosts(), vec!["jumpbox"]);
+
+        let config = OpenSshConfig::parse("Host a b\nHost b c !d\n");
+        assert_eq!(config.hosts(), vec!["a", "b", "c"]);
+    }
+}
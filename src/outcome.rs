@@ -0,0 +1,251 @@
+//! 运行结果摘要（`--json-result`）
+//!
+//! 开启后，进程退出时把本次运行的结果写成一个 JSON 对象：子命令、目标、实际连接的主机和端口、认证方式、
+//! 服务器指纹、传输的字节数、退出码和错误类别，供编排工具判断结果，不必解析面向人的输出。人看的输出
+//! 不变。默认写到标准错误的最后一行，`--result-file` 可指定文件，Unix 上也可以写 `fd:3` 指定文件描述符。
+//!
+//! 字段是对外的约定：只增加字段，不改名、不删除；改动时同时更新 `SCHEMA_VERSION` 和测试。
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::{capture, connect, hooks, interrupt, remote_copy, ssh};
+
+/// 结果格式的版本，字段有不兼容的变化时递增
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// 本次运行收集的结果，未开启 `--json-result` 时为空
+static OUTCOME: Mutex<Option<Collector>> = Mutex::new(None);
+
+struct Collector {
+    started: Instant,
+    destination: Destination,
+    result: RunResult,
+}
+
+/// 结果写到哪里
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    Stderr,
+    File(String),
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Fd(i32),
+}
+
+impl Destination {
+    /// 解析 `--result-file`：`fd:N` 表示文件描述符，其他为文件路径
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        let Some(value) = value else {
+            return Ok(Destination::Stderr);
+        };
+        match value.strip_prefix("fd:") {
+            Some(fd) => {
+                let fd: i32 = fd.parse().context(format!("无效的文件描述符: {}", value))?;
+                if !cfg!(unix) {
+                    anyhow::bail!("当前平台不支持写入文件描述符，请指定文件路径");
+                }
+                if fd < 3 {
+                    anyhow::bail!("结果不能写入标准输入、输出或错误（fd:{}），请使用 3 及以上的描述符", fd);
+                }
+                Ok(Destination::Fd(fd))
+            }
+            None => Ok(Destination::File(value.to_string())),
+        }
+    }
+}
+
+/// 写出的 JSON 对象
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunResult {
+    pub schema: u32,
+    /// 子命令路径，例如 `connect`、`sftp upload`
+    pub command: String,
+    /// 命令行上的目标（连接名称或 user@host）
+    pub target: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    /// 实际成功的认证方式：password、keyboard-interactive、publickey、agent
+    pub auth_method: Option<String>,
+    /// 服务器主机密钥指纹（`SHA256:...`）
+    pub fingerprint: Option<String>,
+    /// 成功上传、下载的字节数，没有传输时为空
+    pub bytes_transferred: Option<u64>,
+    pub exit_code: i32,
+    pub success: bool,
+    pub error: Option<ErrorInfo>,
+    /// 从开始到认证完成的毫秒数，没有建立连接（例如复用控制主连接）时为空
+    pub connect_ms: Option<u64>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorInfo {
+    pub category: &'static str,
+    pub message: String,
+}
+
+/// 开启结果摘要，在解析命令行后调用一次
+pub fn enable(command: &str, target: Option<&str>, destination: Destination) {
+    *lock() = Some(Collector {
+        started: Instant::now(),
+        destination,
+        result: RunResult {
+            schema: SCHEMA_VERSION,
+            command: command.to_string(),
+            target: target.map(str::to_string),
+            ..Default::default()
+        },
+    });
+}
+
+/// 认证成功后记录实际连接的服务器；经过跳板机时最后一次（目标主机）为准
+pub fn connected(username: &str, host: &str, port: u16, auth_method: &str, fingerprint: Option<String>) {
+    if let Some(collector) = lock().as_mut() {
+        let result = &mut collector.result;
+        result.username = Some(username.to_string());
+        result.host = Some(host.to_string());
+        result.port = Some(port);
+        result.auth_method = Some(auth_method.to_string());
+        result.fingerprint = fingerprint;
+        result.connect_ms = Some(collector.started.elapsed().as_millis() as u64);
+    }
+}
+
+/// 累计成功传输的字节数
+pub fn transferred(bytes: u64) {
+    if let Some(collector) = lock().as_mut() {
+        *collector.result.bytes_transferred.get_or_insert(0) += bytes;
+    }
+}
+
+/// 写出结果后退出（替代运行中途的 `std::process::exit`）
+pub fn exit(code: i32, error: Option<&anyhow::Error>) -> ! {
+    finish(code, error);
+    std::process::exit(code)
+}
+
+/// 写出结果；未开启时什么也不做，多次调用只写一次
+pub fn finish(code: i32, error: Option<&anyhow::Error>) {
+    let Some(collector) = lock().take() else {
+        return;
+    };
+    let mut result = collector.result;
+    result.exit_code = code;
+    result.success = code == 0;
+    result.error = error.map(|e| ErrorInfo { category: category(e), message: format!("{:#}", e) });
+    result.duration_ms = collector.started.elapsed().as_millis() as u64;
+    if let Err(e) = write(&collector.destination, &result) {
+        eprintln!("无法写入运行结果: {:#}", e);
+    }
+}
+
+/// 错误类别，与退出码使用的错误类型对应
+pub fn category(error: &anyhow::Error) -> &'static str {
+    if error.downcast_ref::<hooks::HookFailed>().is_some() {
+        "hook_failed"
+    } else if error.downcast_ref::<capture::OutputLimitExceeded>().is_some() {
+        "output_limit"
+    } else if error.downcast_ref::<connect::StalePassword>().is_some() {
+        "stale_password"
+    } else if error.downcast_ref::<ssh::PasswordRejected>().is_some() {
+        "auth_rejected"
+    } else if error.downcast_ref::<ssh::CommandTimedOut>().is_some() {
+        "timeout"
+    } else if error.downcast_ref::<remote_copy::CopyFailed>().is_some() {
+        "copy_failed"
+    } else if error.downcast_ref::<interrupt::Cancelled>().is_some()
+        || error.downcast_ref::<interrupt::ConnectCancelled>().is_some()
+    {
+        "cancelled"
+    } else {
+        "other"
+    }
+}
+
+fn write(destination: &Destination, result: &RunResult) -> Result<()> {
+    let mut line = serde_json::to_string(result)?;
+    line.push('\n');
+    match destination {
+        Destination::Stderr => std::io::stderr().write_all(line.as_bytes())?,
+        Destination::File(path) => std::fs::write(path, line).context(format!("无法写入 {}", path))?,
+        #[cfg(unix)]
+        Destination::Fd(fd) => {
+            use std::os::unix::io::FromRawFd;
+            // 描述符由调用方打开，写完后不关闭
+            let mut file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(*fd) });
+            file.write_all(line.as_bytes()).context(format!("无法写入 fd:{}", fd))?;
+        }
+        #[cfg(not(unix))]
+        Destination::Fd(_) => unreachable!("Destination::parse 只在 Unix 上接受 fd"),
+    }
+    Ok(())
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Collector>> {
+    OUTCOME.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 字段名和顺序是对外的约定，改动必须是有意的
+    #[test]
+    fn test_schema_is_stable() {
+        let result = RunResult {
+            schema: SCHEMA_VERSION,
+            command: "sftp upload".to_string(),
+            target: Some("web".to_string()),
+            host: Some("10.0.0.5".to_string()),
+            port: Some(22),
+            username: Some("deploy".to_string()),
+            auth_method: Some("publickey".to_string()),
+            fingerprint: Some("SHA256:abc".to_string()),
+            bytes_transferred: Some(2048),
+            exit_code: 1,
+            success: false,
+            error: Some(ErrorInfo { category: "timeout", message: "超时".to_string() }),
+            connect_ms: Some(120),
+            duration_ms: 3400,
+        };
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            concat!(
+                r#"{"schema":1,"command":"sftp upload","target":"web","host":"10.0.0.5","port":22,"#,
+                r#""username":"deploy","auth_method":"publickey","fingerprint":"SHA256:abc","#,
+                r#""bytes_transferred":2048,"exit_code":1,"success":false,"#,
+                r#""error":{"category":"timeout","message":"超时"},"connect_ms":120,"duration_ms":3400}"#
+            )
+        );
+
+        // 未知的值输出为 null，字段不会省略
+        let empty = serde_json::to_value(RunResult::default()).unwrap();
+        let keys: Vec<&str> = empty.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys.len(), 14);
+        assert!(empty["host"].is_null() && empty["error"].is_null());
+    }
+
+    #[test]
+    fn test_category() {
+        assert_eq!(category(&anyhow::Error::new(interrupt::ConnectCancelled)), "cancelled");
+        let rejected = anyhow::anyhow!("认证失败").context(ssh::PasswordRejected("密码错误".to_string()));
+        assert_eq!(category(&rejected), "auth_rejected");
+        let stale = anyhow::Error::new(connect::StalePassword { connection: "web".to_string() }).context("连接失败");
+        assert_eq!(category(&stale), "stale_password");
+        assert_eq!(category(&anyhow::anyhow!("无法建立 TCP 连接")), "other");
+    }
+
+    #[test]
+    fn test_destination() {
+        assert_eq!(Destination::parse(None).unwrap(), Destination::Stderr);
+        assert_eq!(Destination::parse(Some("result.json")).unwrap(), Destination::File("result.json".to_string()));
+        assert!(Destination::parse(Some("fd:x")).is_err());
+        assert!(Destination::parse(Some("fd:1")).is_err());
+        #[cfg(unix)]
+        assert_eq!(Destination::parse(Some("fd:3")).unwrap(), Destination::Fd(3));
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// 展开路径中的 `~`、`~user`（仅 Unix）和环境变量（`$VAR`、`${VAR}`、`%VAR%`）
+///
+/// 无法展开的部分（未定义的变量、不存在的用户）保持原样。
+pub fn expand(path: &str) -> String {
+    expand_env(&expand_home(path), |name| std::env::var(name).ok())
+}
+
+/// 展开私钥路径并确认文件存在
+///
+/// 配置中保存的是原始写法，只在连接时展开，便于在不同机器间共用配置。
+pub fn resolve_key_path(raw: &str) -> Result<String> {
+    let expanded = expand(raw);
+    if !Path::new(&expanded).is_file() {
+        if expanded == raw {
+            anyhow::bail!("私钥不存在: {}", expanded);
+        }
+        anyhow::bail!("私钥不存在: {}（原始路径: {}）", expanded, raw);
+    }
+    Ok(expanded)
+}
+
+/// 展开开头的 `~` 或 `~user`
+fn expand_home(path: &str) -> String {
+    let Some(rest) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+    let (user, tail) = match rest.find(['/', '\\']) {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, ""),
+    };
+
+    let home = if user.is_empty() {
+        dirs::home_dir()
+    } else {
+        user_home(user)
+    };
+    match home {
+        Some(home) => format!("{}{}", home.to_string_lossy(), tail),
+        None => path.to_string(),
+    }
+}
+
+/// 指定用户的主目录
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<std::path::PathBuf> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = CString::new(user).ok()?;
+    // getpwnam 返回静态缓冲区，在下一次调用前读取完毕
+    unsafe {
+        let entry = libc::getpwnam(name.as_ptr());
+        if entry.is_null() || (*entry).pw_dir.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*entry).pw_dir);
+        Some(std::ffi::OsStr::from_bytes(dir.to_bytes()).into())
+    }
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &str) -> Option<std::path::PathBuf> {
+    None
+}
+
+/// 展开 `$VAR`、`${VAR}` 和 `%VAR%`
+fn expand_env(path: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+
+    while let Some(idx) = rest.find(['$', '%']) {
+        result.push_str(&rest[..idx]);
+        let after = &rest[idx + 1..];
+
+        let (name, consumed) = if rest[idx..].starts_with('%') {
+            match after.find('%') {
+                Some(end) => (&after[..end], end + 1),
+                None => ("", 0),
+            }
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        if !is_var_name(name) {
+            result.push_str(&rest[idx..idx + 1]);
+            rest = after;
+            continue;
+        }
+        match lookup(name) {
+            Some(value) => result.push_str(&value),
+            // 未定义的变量保持原样
+            None => result.push_str(&rest[idx..idx + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/alice".to_string()),
+            "USERPROFILE" => Some(r"C:\Users\alice".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_env() {
+        assert_eq!(expand_env("$HOME/.ssh/id_ed25519", lookup), "/home/alice/.ssh/id_ed25519");
+        assert_eq!(expand_env("${HOME}/keys", lookup), "/home/alice/keys");
+        assert_eq!(expand_env(r"%USERPROFILE%\.ssh\id_rsa", lookup), r"C:\Users\alice\.ssh\id_rsa");
+        // 未定义的变量和单独的符号保持原样
+        assert_eq!(expand_env("$MISSING/a", lookup), "$MISSING/a");
+        assert_eq!(expand_env("key%1$", lookup), "key%1$");
+        assert_eq!(expand_env("50%", lookup), "50%");
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home("~/.ssh/id_rsa"), format!("{}/.ssh/id_rsa", home.display()));
+        assert_eq!(expand_home("~"), home.to_string_lossy());
+        assert_eq!(expand_home("/etc/~x"), "/etc/~x");
+        assert_eq!(expand_home("~no-such-user-xyz/key"), "~no-such-user-xyz/key");
+        #[cfg(unix)]
+        assert_eq!(expand_home("~root/key"), format!("{}/key", user_home("root").unwrap().display()));
+    }
+
+    #[test]
+    fn test_resolve_key_path_reports_both_forms() {
+        let err = resolve_key_path("~/no-such-key-xyz").unwrap_err().to_string();
+        assert!(err.contains("no-such-key-xyz"));
+        assert!(err.contains("原始路径: ~/no-such-key-xyz"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("id_ed25519");
+        std::fs::write(&key, "key").unwrap();
+        assert_eq!(resolve_key_path(&key.to_string_lossy()).unwrap(), key.to_string_lossy());
+    }
+}
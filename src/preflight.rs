@@ -0,0 +1,132 @@
+//! 递归操作开始前的预检
+//!
+//! 先遍历远程（或上传时的本地）目录树统计文件数和总大小，用于开始前的确认提示和整体进度条。遍历时对符号链接的
+//! 处理（跳过或按 `--links follow` 跟随）与实际传输时相同，统计结果就是真正要传输的量。目录很大时可用
+//! `--no-preflight` 跳过。
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::format_size;
+use crate::sftp::{self, FileInfo, Links, SftpClient};
+
+/// 预检统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preflight {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+}
+
+impl Preflight {
+    /// 例如 `即将复制 1,234 个文件，共 2.3 GB`
+    pub fn summary(&self, verb: &str) -> String {
+        format!("即将{} {} 个文件，共 {}", verb, format_count(self.files), format_size(self.bytes))
+    }
+}
+
+/// 递归操作不处理的条目
+pub fn skipped(entry: &FileInfo) -> bool {
+    entry.is_symlink
+}
+
+/// 统计远程目录 `root` 下会被递归处理的文件，`links` 为 [`Links::Follow`] 时计入链接目标
+pub fn scan_remote(sftp: &SftpClient, root: &str, links: Links) -> Result<Preflight> {
+    let mut totals = Preflight::default();
+    let mut pending = vec![root.to_string()];
+    while let Some(dir) = pending.pop() {
+        totals.dirs += 1;
+        for mut entry in sftp.list_dir(&dir)? {
+            if skipped(&entry) {
+                // 跟随时按链接目标统计；目标不存在或指向上级目录的链接在传输时报告
+                let target = sftp.stat(&entry.path).ok().filter(|_| links == Links::Follow);
+                match target {
+                    Some(target) if !(target.is_dir && sftp.is_ancestor_link(&entry.raw_path, Path::new(&dir))) => {
+                        (entry.is_dir, entry.size) = (target.is_dir, target.size);
+                    }
+                    _ => continue,
+                }
+            }
+            if entry.is_dir {
+                pending.push(entry.path);
+            } else {
+                totals.files += 1;
+                totals.bytes += entry.size;
+            }
+        }
+    }
+    Ok(totals)
+}
+
+/// 统计本地目录 `root` 下会被递归上传的文件，符号链接的处理与 [`scan_remote`] 相同
+pub fn scan_local(root: &Path, links: Links) -> Result<Preflight> {
+    let mut totals = Preflight::default();
+    let mut pending: Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        totals.dirs += 1;
+        for entry in std::fs::read_dir(&dir).context(format!("无法读取目录: {}", dir.display()))? {
+            let entry = entry.context(format!("无法读取目录: {}", dir.display()))?;
+            let path = entry.path();
+            let file_type = entry.file_type().context(format!("无法读取文件信息: {}", path.display()))?;
+            let followed = file_type.is_symlink() && links == Links::Follow && path.exists();
+            if file_type.is_symlink() && !followed {
+                continue;
+            }
+            if path.is_dir() {
+                if !(followed && sftp::links_to_ancestor(&path, &dir)) {
+                    pending.push(path);
+                }
+            } else {
+                totals.files += 1;
+                totals.bytes += std::fs::metadata(&path).map_or(0, |m| m.len());
+            }
+        }
+    }
+    Ok(totals)
+}
+
+/// 带千位分隔符的数字
+pub fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1234), "1,234");
+        assert_eq!(format_count(1234567), "1,234,567");
+        let totals = Preflight { files: 1234, dirs: 3, bytes: 2048 };
+        assert_eq!(totals.summary("复制"), "即将复制 1,234 个文件，共 2.00 KB");
+    }
+
+    #[test]
+    fn test_scan_remote() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::create_dir_all(server.local_path("/site/css")).unwrap();
+        std::fs::write(server.local_path("/site/index.html"), vec![0u8; 100]).unwrap();
+        std::fs::write(server.local_path("/site/css/app.css"), vec![0u8; 20]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("index.html", server.local_path("/site/link.html")).unwrap();
+
+        let totals = scan_remote(&sftp, "/site", Links::Skip).unwrap();
+        assert_eq!(totals, Preflight { files: 2, dirs: 2, bytes: 120 });
+        #[cfg(unix)]
+        assert_eq!(scan_remote(&sftp, "/site", Links::Follow).unwrap(), Preflight { files: 3, dirs: 2, bytes: 220 });
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ssh::SshClient;
+
+/// 优先使用 `ps aux`，BusyBox 等不支持参数的实现退回到 `ps`
+const PS_COMMAND: &str = "ps aux 2>/dev/null || ps";
+
+/// 发送信号后等待进程退出的时间
+const KILL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 远程进程信息，`ps` 未提供的列为 None
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub user: String,
+    pub cpu: Option<f32>,
+    pub mem: Option<f32>,
+    /// 常驻内存（KB），没有 RSS 列时使用 VSZ
+    pub rss: Option<u64>,
+    pub command: String,
+}
+
+/// 排序方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Cpu,
+    Mem,
+}
+
+/// 在远程执行 `ps` 并解析结果
+pub fn list(client: &SshClient) -> Result<Vec<ProcessInfo>> {
+    client.remote_info().require_unix("进程列表")?;
+    let (output, status) = client.exec_command_with_status(PS_COMMAND)?;
+    let processes = parse_ps(&output);
+    if processes.is_empty() {
+        anyhow::bail!("无法解析 ps 输出（退出码 {}）", status);
+    }
+    Ok(processes)
+}
+
+/// 解析 `ps` 输出
+///
+/// 按表头定位各列，兼容 procps（`ps aux`）、BusyBox 和 BSD 的格式。命令列总是最后一列，
+/// 可能包含空格，取剩余的全部内容。
+pub fn parse_ps(output: &str) -> Vec<ProcessInfo> {
+    let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let columns: Vec<String> = header.split_whitespace().map(|c| c.to_ascii_uppercase()).collect();
+    let find = |names: &[&str]| columns.iter().position(|c| names.contains(&c.as_str()));
+
+    let Some(pid_col) = find(&["PID"]) else {
+        return Vec::new();
+    };
+    let user_col = find(&["USER", "UID"]);
+    let cpu_col = find(&["%CPU"]);
+    let mem_col = find(&["%MEM"]);
+    let rss_col = find(&["RSS"]).or_else(|| find(&["VSZ"]));
+    let command_col = columns.len() - 1;
+
+    lines
+        .filter_map(|line| {
+            let fields = split_fields(line, command_col);
+            let field = |col: Option<usize>| col.and_then(|i| fields.get(i).copied());
+            Some(ProcessInfo {
+                pid: fields.get(pid_col)?.parse().ok()?,
+                user: field(user_col).unwrap_or("").to_string(),
+                cpu: field(cpu_col).and_then(|v| v.parse().ok()),
+                mem: field(mem_col).and_then(|v| v.parse().ok()),
+                rss: field(rss_col).and_then(parse_kb),
+                command: fields.get(command_col)?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// 按空白拆分前 `last` 列，其余部分作为最后一列
+fn split_fields(line: &str, last: usize) -> Vec<&str> {
+    let mut fields = Vec::with_capacity(last + 1);
+    let mut rest = line.trim_start();
+    while fields.len() < last {
+        let Some(end) = rest.find(char::is_whitespace) else {
+            break;
+        };
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        fields.push(rest.trim_end());
+    }
+    fields
+}
+
+/// 解析内存大小，BusyBox 会输出 `12m`、`1.2g` 这样的单位
+fn parse_kb(value: &str) -> Option<u64> {
+    let (number, scale) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], 1.0),
+        'm' | 'M' => (&value[..value.len() - 1], 1024.0),
+        'g' | 'G' => (&value[..value.len() - 1], 1024.0 * 1024.0),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * scale) as u64)
+}
+
+/// 按名称过滤（不区分大小写）并排序，缺少该列时保持原顺序
+pub fn select(mut processes: Vec<ProcessInfo>, filter: Option<&str>, sort: SortKey) -> Vec<ProcessInfo> {
+    if let Some(filter) = filter {
+        let filter = filter.to_lowercase();
+        processes.retain(|p| p.command.to_lowercase().contains(&filter));
+    }
+    let key = |p: &ProcessInfo| match sort {
+        SortKey::Cpu => p.cpu.unwrap_or(0.0),
+        SortKey::Mem => p.mem.unwrap_or_else(|| p.rss.unwrap_or(0) as f32),
+    };
+    processes.sort_by(|a, b| key(b).total_cmp(&key(a)));
+    processes
+}
+
+/// 规范化信号名：接受 `TERM`、`SIGTERM`、`term` 或数字
+pub fn parse_signal(signal: &str) -> Result<String> {
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        anyhow::bail!("无效的信号: {}", signal);
+    }
+    Ok(name.to_string())
+}
+
+/// 向远程进程发送信号，并等待其退出
+///
+/// 进程在超时后仍然存在时返回错误（例如忽略了 TERM，可改用 KILL）。
+pub fn kill(client: &SshClient, pid: u32, signal: &str) -> Result<()> {
+    client.remote_info().require_unix("发送信号")?;
+    let signal = parse_signal(signal)?;
+    let (output, status) = client
+        .exec_command_with_status(&format!("kill -{} {} 2>&1", signal, pid))
+        .context(format!("无法向进程 {} 发送信号", pid))?;
+    if status != 0 {
+        anyhow::bail!("发送信号 {} 到进程 {} 失败: {}", signal, pid, output.trim());
+    }
+
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    loop {
+        if !is_alive(client, pid)? {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("进程 {} 在 {} 秒后仍在运行", pid, KILL_TIMEOUT.as_secs());
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// 进程是否仍在运行（尚未被回收的僵尸进程视为已退出）
+fn is_alive(client: &SshClient, pid: u32) -> Result<bool> {
+    let command = format!(
+        "kill -0 {pid} 2>/dev/null && ! grep -qs '^State:[[:space:]]*Z' /proc/{pid}/status",
+        pid = pid
+    );
+    let (_, status) = client.exec_command_with_status(&command)?;
+    Ok(status == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    const PROCPS: &str = "\
+USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND
+root           1  0.0  0.1 167744 11520 ?        Ss   Jun01   0:09 /sbin/init splash
+www-data    1234 12.5  3.2 512000 65536 ?        S    10:00   1:02 nginx: worker process
+";
+
+    const BUSYBOX: &str = "\
+PID   USER     VSZ STAT COMMAND
+    1 root      1.2m S    init
+  321 admin    12m  S    /usr/sbin/dropbear -R -p 22
+";
+
+    #[test]
+    fn test_parse_procps() {
+        let processes = parse_ps(PROCPS);
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[1].pid, 1234);
+        assert_eq!(processes[1].user, "www-data");
+        assert_eq!(processes[1].cpu, Some(12.5));
+        assert_eq!(processes[1].rss, Some(65536));
+        assert_eq!(processes[1].command, "nginx: worker process");
+        assert_eq!(processes[0].command, "/sbin/init splash");
+    }
+
+    #[test]
+    fn test_parse_busybox() {
+        let processes = parse_ps(BUSYBOX);
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[1].pid, 321);
+        assert_eq!(processes[1].cpu, None);
+        assert_eq!(processes[1].rss, Some(12 * 1024));
+        assert_eq!(processes[1].command, "/usr/sbin/dropbear -R -p 22");
+        assert!(parse_ps("garbage").is_empty());
+    }
+
+    #[test]
+    fn test_select_and_signal() {
+        let selected = select(parse_ps(PROCPS), None, SortKey::Cpu);
+        assert_eq!(selected[0].pid, 1234);
+        let selected = select(parse_ps(BUSYBOX), Some("DROP"), SortKey::Mem);
+        assert_eq!(selected.len(), 1);
+
+        assert_eq!(parse_signal("sigkill").unwrap(), "KILL");
+        assert_eq!(parse_signal("9").unwrap(), "9");
+        assert!(parse_signal("TERM; rm -rf /").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_and_kill() {
+        let server = TestServer::start();
+        let client = server.connect();
+
+        let pid: u32 = client
+            .exec_command("sleep 60 >/dev/null 2>&1 & echo $!")
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(list(&client).unwrap().iter().any(|p| p.pid == pid));
+
+        kill(&client, pid, "TERM").unwrap();
+        assert!(!is_alive(&client, pid).unwrap());
+    }
+}
@@ -0,0 +1,375 @@
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::sftp::{TransferProgress, TransferStats};
+
+/// 计算平滑速度使用的时间窗口
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// 两次采样之间的最小间隔，避免每个数据块都记录
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// JSON 进度事件的最小间隔
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 进度条每秒最多重绘的次数
+const BAR_HZ: u8 = 30;
+
+/// 两次更新进度条位置之间的最小间隔，与重绘频率一致
+const BAR_INTERVAL: Duration = Duration::from_millis(1000 / BAR_HZ as u64);
+
+/// 格式化传输速度
+pub fn format_speed(bytes_per_sec: f64) -> String {
+    format!("{}/s", HumanBytes(bytes_per_sec as u64))
+}
+
+/// 滑动窗口速度估计
+///
+/// 只使用最近一段时间内的采样计算速度，ETA 不会因为早期的快慢而剧烈跳动。
+#[derive(Debug)]
+pub struct RateWindow {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// 记录一次进度采样
+    pub fn record(&mut self, now: Instant, position: u64) {
+        if let Some(&(last, _)) = self.samples.back() {
+            if now.duration_since(last) < SAMPLE_INTERVAL {
+                return;
+            }
+        }
+        self.samples.push_back((now, position));
+
+        // 保留窗口开始前的最后一个采样作为基准点
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// 窗口内的平均速度（字节/秒）
+    pub fn rate(&self) -> f64 {
+        let (Some(&(start, start_pos)), Some(&(end, end_pos))) =
+            (self.samples.front(), self.samples.back())
+        else {
+            return 0.0;
+        };
+
+        let secs = end.duration_since(start).as_secs_f64();
+        if secs > 0.0 {
+            end_pos.saturating_sub(start_pos) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 按最小间隔限制更新频率
+///
+/// 时间由调用方传入，测试中可以用构造的 `Instant` 代替真实时钟。
+#[derive(Debug, Clone, Copy)]
+pub struct Throttle {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    pub const fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+
+    /// 第一次调用或距上次放行已达到间隔时返回 true，并记下 `now`
+    pub fn ready(&mut self, now: Instant) -> bool {
+        if self.last.is_some_and(|last| now.duration_since(last) < self.interval) {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+}
+
+/// 传输进度的显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// 终端进度条
+    #[default]
+    Bar,
+    /// 在 stderr 上逐行输出 JSON 事件，供 CI 等程序读取
+    Json,
+    /// 不显示进度
+    None,
+}
+
+/// `--progress json` 输出的事件，每个事件一行
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event<'a> {
+    Start { file: &'a str, total: u64 },
+    Progress { file: &'a str, bytes: u64, total: u64 },
+    Finish { file: &'a str, bytes: u64, elapsed_secs: f64, bytes_per_sec: f64 },
+    Error { file: &'a str, message: &'a str },
+}
+
+/// 传输进度的显示，作为 `SftpClient` 传输的进度回调使用
+///
+/// 进度条模式下显示 indicatif 进度条，位置最多每 [`BAR_INTERVAL`] 更新一次（局域网内传输时回调
+/// 很密集，每次都更新会让重绘占满一个核）；JSON 模式下在 stderr 输出开始、进度（按
+/// [`JSON_INTERVAL`] 限速）、完成和出错事件。
+pub struct ProgressReporter {
+    message: String,
+    file: String,
+    mode: ProgressMode,
+    bar: Option<ProgressBar>,
+    bar_throttle: Throttle,
+    /// 被限速跳过、尚未显示的最新位置，结束时补上
+    pending: Option<u64>,
+    rate: Arc<Mutex<RateWindow>>,
+    /// 上一次输出 JSON 事件的时间，None 表示还没有输出开始事件
+    last_event: Option<Instant>,
+}
+
+impl ProgressReporter {
+    /// 创建进度显示，进度条在第一次回调时按文件大小创建
+    pub fn new(message: String, file: &str, mode: ProgressMode) -> Self {
+        Self {
+            message,
+            file: file.to_string(),
+            mode,
+            bar: None,
+            bar_throttle: Throttle::new(BAR_INTERVAL),
+            pending: None,
+            rate: Arc::new(Mutex::new(RateWindow::new(RATE_WINDOW))),
+            last_event: None,
+        }
+    }
+
+    /// 进度回调：更新已传输的字节数，从不中止传输
+    pub fn update(&mut self, progress: TransferProgress) -> ControlFlow<()> {
+        self.update_at(progress, Instant::now())
+    }
+
+    fn update_at(&mut self, progress: TransferProgress, now: Instant) -> ControlFlow<()> {
+        match self.mode {
+            ProgressMode::Bar => {
+                let pb = self
+                    .bar
+                    .get_or_insert_with(|| create_bar(progress.total, self.message.clone(), Arc::clone(&self.rate)));
+                if let Ok(mut rate) = self.rate.lock() {
+                    rate.record(now, progress.transferred);
+                }
+                // 传输完成的位置总是立即显示
+                if self.bar_throttle.ready(now) || progress.transferred == progress.total {
+                    pb.set_position(progress.transferred);
+                    self.pending = None;
+                } else {
+                    self.pending = Some(progress.transferred);
+                }
+            }
+            ProgressMode::Json => {
+                match self.last_event {
+                    None => {
+                        emit(&Event::Start { file: &self.file, total: progress.total });
+                        self.last_event = Some(now);
+                    }
+                    Some(last) if now.duration_since(last) >= JSON_INTERVAL => {
+                        emit(&Event::Progress { file: &self.file, bytes: progress.transferred, total: progress.total });
+                        self.last_event = Some(now);
+                    }
+                    Some(_) => {}
+                }
+            }
+            ProgressMode::None => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// 中止进度显示（保留当前进度并恢复终端光标），JSON 模式下输出出错事件
+    pub fn abandon(&self, message: String) {
+        if let Some(ref pb) = self.bar {
+            self.flush(pb);
+            pb.abandon_with_message(message);
+        } else if self.mode == ProgressMode::Json {
+            emit(&Event::Error { file: &self.file, message: &message });
+        }
+    }
+
+    /// 传输出错：进度条保持原样（错误由调用方输出），JSON 模式下输出出错事件
+    pub fn fail(&self, error: &anyhow::Error) {
+        if self.mode == ProgressMode::Json {
+            emit(&Event::Error { file: &self.file, message: &format!("{:#}", error) });
+        }
+    }
+
+    /// 结束进度显示，并输出平均速度
+    pub fn finish(&self, message: String, stats: &TransferStats) {
+        if let Some(ref pb) = self.bar {
+            self.flush(pb);
+            pb.finish_with_message(format!("{} (平均速度 {})", message, format_speed(stats.throughput())));
+        } else if self.mode == ProgressMode::Json {
+            emit(&Event::Finish {
+                file: &self.file,
+                bytes: stats.total_bytes,
+                elapsed_secs: stats.elapsed.as_secs_f64(),
+                bytes_per_sec: stats.throughput(),
+            });
+        }
+    }
+
+    /// 显示被限速跳过的最新位置
+    fn flush(&self, pb: &ProgressBar) {
+        if let Some(position) = self.pending {
+            pb.set_position(position);
+        }
+    }
+}
+
+/// 在 stderr 输出一行 JSON 事件
+fn emit(event: &Event) {
+    if let Ok(line) = serde_json::to_string(event) {
+        eprintln!("{}", line);
+    }
+}
+
+/// 创建进度条，`total` 为 0 时表示大小未知，使用不带进度条的样式
+fn create_bar(total: u64, message: String, rate: Arc<Mutex<RateWindow>>) -> ProgressBar {
+    let smooth_eta = move |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+        let rate = rate.lock().map(|r| r.rate()).unwrap_or(0.0);
+        let remaining = state.len().unwrap_or(0).saturating_sub(state.pos());
+        if rate > 0.0 {
+            let eta = Duration::from_secs_f64(remaining as f64 / rate);
+            let _ = write!(w, "{}", HumanDuration(eta));
+        } else {
+            let _ = write!(w, "--");
+        }
+    };
+
+    let (pb, style) = if total > 0 {
+        (
+            ProgressBar::new(total),
+            ProgressStyle::default_bar()
+                .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} (剩余 {smooth_eta})")
+                .unwrap()
+                .progress_chars("#>-"),
+        )
+    } else {
+        (
+            ProgressBar::new_spinner(),
+            ProgressStyle::default_spinner()
+                .template("{msg}\n{spinner:.green} [{elapsed_precise}] {bytes} {bytes_per_sec}")
+                .unwrap(),
+        )
+    };
+    pb.set_draw_target(ProgressDrawTarget::stderr_with_hz(BAR_HZ));
+    pb.set_style(style.with_key("smooth_eta", smooth_eta));
+    pb.set_message(message);
+    pb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_window_uses_recent_samples() {
+        let start = Instant::now();
+        let mut window = RateWindow::new(Duration::from_secs(5));
+
+        // 前 10 秒 1MB/s，之后 2 秒 4MB/s
+        for sec in 0..=10u64 {
+            window.record(start + Duration::from_secs(sec), sec * 1_000_000);
+        }
+        for sec in 1..=2u64 {
+            window.record(start + Duration::from_secs(10 + sec), 10_000_000 + sec * 4_000_000);
+        }
+
+        // 窗口内：第 7 秒 7MB 到第 12 秒 18MB
+        let rate = window.rate();
+        assert!((rate - 11_000_000.0 / 5.0).abs() < 1.0, "rate = {}", rate);
+    }
+
+    #[test]
+    fn test_json_events() {
+        let line = |event: &Event| serde_json::to_string(event).unwrap();
+        assert_eq!(
+            line(&Event::Progress { file: "a.tar.gz", bytes: 512, total: 1024 }),
+            r#"{"event":"progress","file":"a.tar.gz","bytes":512,"total":1024}"#
+        );
+        assert_eq!(
+            line(&Event::Error { file: "a.tar.gz", message: "传输已取消" }),
+            r#"{"event":"error","file":"a.tar.gz","message":"传输已取消"}"#
+        );
+
+        // 第一次回调输出开始事件，之后按间隔限速
+        let mut reporter = ProgressReporter::new(String::new(), "a.tar.gz", ProgressMode::Json);
+        let progress = TransferProgress { transferred: 0, total: 1024, elapsed: Duration::ZERO };
+        assert!(reporter.update(progress).is_continue());
+        let started = reporter.last_event.unwrap();
+        assert!(reporter.update(TransferProgress { transferred: 512, ..progress }).is_continue());
+        assert_eq!(reporter.last_event, Some(started));
+        assert!(reporter.bar.is_none());
+    }
+
+    /// 假时钟：每 100 微秒一次回调，持续 10 秒
+    #[test]
+    fn test_throttle_bounds_update_rate() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(BAR_INTERVAL);
+        let passed = (0..100_000u64)
+            .filter(|i| throttle.ready(start + Duration::from_micros(i * 100)))
+            .count();
+        assert!(passed <= 10 * BAR_HZ as usize + 1, "passed = {}", passed);
+        assert!(passed >= 10 * BAR_HZ as usize / 2, "passed = {}", passed);
+    }
+
+    #[test]
+    fn test_bar_updates_are_throttled() {
+        let start = Instant::now();
+        let mut reporter = ProgressReporter::new(String::new(), "a.tar.gz", ProgressMode::Bar);
+        let at = |transferred| TransferProgress { transferred, total: 1 << 20, elapsed: Duration::ZERO };
+
+        // 同一间隔内的更新只记下位置，结束时补上
+        for i in 0..10u64 {
+            assert!(reporter.update_at(at(i * 8192), start + Duration::from_millis(i)).is_continue());
+        }
+        let pb = reporter.bar.clone().unwrap();
+        assert_eq!(pb.position(), 0);
+        assert_eq!(reporter.pending, Some(9 * 8192));
+
+        reporter.update_at(at(20 * 8192), start + BAR_INTERVAL);
+        assert_eq!(pb.position(), 20 * 8192);
+        reporter.update_at(at(21 * 8192), start + BAR_INTERVAL + Duration::from_millis(1));
+        reporter.abandon(String::new());
+        assert_eq!(pb.position(), 21 * 8192);
+
+        // 传输完成的位置不等待间隔
+        let mut reporter = ProgressReporter::new(String::new(), "a.tar.gz", ProgressMode::Bar);
+        reporter.update_at(at(0), start);
+        reporter.update_at(at(1 << 20), start + Duration::from_millis(1));
+        assert_eq!(reporter.bar.as_ref().unwrap().position(), 1 << 20);
+    }
+
+    #[test]
+    fn test_rate_window_ignores_dense_samples() {
+        let start = Instant::now();
+        let mut window = RateWindow::new(Duration::from_secs(5));
+
+        window.record(start, 0);
+        window.record(start + Duration::from_millis(10), 1_000);
+        assert_eq!(window.rate(), 0.0);
+
+        window.record(start + Duration::from_secs(1), 2_000);
+        assert!((window.rate() - 2_000.0).abs() < f64::EPSILON);
+    }
+}
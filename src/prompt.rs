@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// 是否处于非交互模式（--batch）
+static BATCH: AtomicBool = AtomicBool::new(false);
+
+/// 同一时间只有一个提示在等待输入，避免不同线程的提示和输入交错
+static PROMPTING: Mutex<()> = Mutex::new(());
+
+/// 开启非交互模式：不重试、不等待，需要人工判断的地方直接失败
+pub fn enable_batch() {
+    BATCH.store(true, Ordering::Relaxed);
+}
+
+pub fn is_batch() -> bool {
+    BATCH.load(Ordering::Relaxed)
+}
+
+/// 向用户询问输入；认证等流程通过它读取输入，测试时用 [`Scripted`] 提供预设的回答
+pub trait Prompter {
+    /// 询问密码（不回显）
+    fn ask_password(&mut self, prompt: &str) -> Result<String>;
+
+    /// 询问一行输入，去掉首尾空白
+    fn ask_line(&mut self, prompt: &str) -> Result<String>;
+
+    /// 询问是否继续，只有输入 y 时返回 true
+    fn confirm(&mut self, prompt: &str) -> Result<bool> {
+        let answer = self.ask_line(&format!("{} [y/N]: ", prompt.yellow()))?;
+        Ok(answer.eq_ignore_ascii_case("y"))
+    }
+}
+
+/// 带上下文的提示，例如 `[deploy@bastion.example.com] 密码: `
+///
+/// 一次运行中可能接连询问跳板机、目标主机、主密码和 sudo 密码，方括号中说明是哪一个。
+pub fn labeled(context: &str, what: &str) -> String {
+    format!("[{}] {}: ", context, what)
+}
+
+/// 从终端读取输入
+pub struct Terminal;
+
+impl Prompter for Terminal {
+    fn ask_password(&mut self, prompt: &str) -> Result<String> {
+        let _prompting = PROMPTING.lock().unwrap_or_else(|e| e.into_inner());
+        rpassword::prompt_password(prompt).context("无法读取密码")
+    }
+
+    fn ask_line(&mut self, prompt: &str) -> Result<String> {
+        let _prompting = PROMPTING.lock().unwrap_or_else(|e| e.into_inner());
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).context("无法读取输入")?;
+        Ok(answer.trim().to_string())
+    }
+}
+
+/// 询问是否继续，只有输入 y 时返回 true
+pub fn confirm(prompt: &str) -> Result<bool> {
+    Terminal.confirm(prompt)
+}
+
+/// 询问是否继续，直接回车视为同意，输入 n 时返回 false
+pub fn confirm_default_yes(prompt: &str) -> Result<bool> {
+    let answer = Terminal.ask_line(&format!("{} (Y/n) ", prompt.yellow()))?;
+    Ok(!answer.eq_ignore_ascii_case("n"))
+}
+
+/// 按顺序返回预设回答的输入源，并记录问过的问题
+#[cfg(test)]
+pub struct Scripted {
+    answers: std::collections::VecDeque<String>,
+    pub asked: Vec<String>,
+}
+
+#[cfg(test)]
+impl Scripted {
+    pub fn new(answers: &[&str]) -> Self {
+        Self {
+            answers: answers.iter().map(|answer| answer.to_string()).collect(),
+            asked: Vec::new(),
+        }
+    }
+
+    fn next(&mut self, prompt: &str) -> Result<String> {
+        self.asked.push(prompt.to_string());
+        self.answers.pop_front().context(format!("没有预设的回答: {}", prompt))
+    }
+}
+
+#[cfg(test)]
+impl Prompter for Scripted {
+    fn ask_password(&mut self, prompt: &str) -> Result<String> {
+        self.next(prompt)
+    }
+
+    fn ask_line(&mut self, prompt: &str) -> Result<String> {
+        self.next(prompt).map(|answer| answer.trim().to_string())
+    }
+}
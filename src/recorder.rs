@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// 录制选项（来自 `connect --record`）
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    pub path: PathBuf,
+    pub include_input: bool,
+}
+
+/// asciicast v2 格式的会话录制
+///
+/// 第一行为头部，之后每行一个 `[时间, 类型, 数据]` 事件。每个事件写入后立即刷新，
+/// 进程异常退出时已写入的部分仍然可以播放。
+pub struct CastRecorder {
+    file: File,
+    started: Instant,
+    record_input: bool,
+    /// 上一次输出末尾不完整的 UTF-8 字节
+    pending: Vec<u8>,
+}
+
+impl CastRecorder {
+    /// 创建录制文件并写入头部
+    pub fn create(path: &Path, width: u16, height: u16) -> Result<Self> {
+        let mut file = File::create(path)
+            .context(format!("无法创建录制文件: {}", path.display()))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": timestamp,
+            "env": { "TERM": "xterm" },
+        });
+        writeln!(file, "{}", header).context("写入录制文件失败")?;
+        file.flush().context("写入录制文件失败")?;
+
+        Ok(Self {
+            file,
+            started: Instant::now(),
+            record_input: false,
+            pending: Vec::new(),
+        })
+    }
+
+    /// 按录制选项创建
+    pub fn from_options(options: &RecordOptions, width: u16, height: u16) -> Result<Self> {
+        Ok(Self::create(&options.path, width, height)?.with_input(options.include_input))
+    }
+
+    /// 是否同时录制键盘输入（默认不录制）
+    pub fn with_input(mut self, record_input: bool) -> Self {
+        self.record_input = record_input;
+        self
+    }
+
+    /// 录制远程输出
+    pub fn output(&mut self, data: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(data);
+        let text = take_utf8(&mut self.pending);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.write_event("o", &text)
+    }
+
+    /// 录制键盘输入（未开启输入录制时忽略）
+    pub fn input(&mut self, data: &[u8]) -> Result<()> {
+        if !self.record_input {
+            return Ok(());
+        }
+        self.write_event("i", &String::from_utf8_lossy(data))
+    }
+
+    /// 录制终端大小变化
+    pub fn resize(&mut self, width: u16, height: u16) -> Result<()> {
+        self.write_event("r", &format!("{}x{}", width, height))
+    }
+
+    fn write_event(&mut self, kind: &str, data: &str) -> Result<()> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let event = json!([elapsed, kind, data]);
+        writeln!(self.file, "{}", event).context("写入录制文件失败")?;
+        self.file.flush().context("写入录制文件失败")?;
+        Ok(())
+    }
+}
+
+/// 取出缓冲区中完整的 UTF-8 文本，末尾不完整的多字节字符留在缓冲区中
+fn take_utf8(buffer: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(buffer) {
+        Ok(_) => buffer.len(),
+        // 末尾是不完整的字符，等待后续数据
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        // 中间有无效字节，直接替换
+        Err(_) => buffer.len(),
+    };
+    let rest = buffer.split_off(complete);
+    let text = String::from_utf8_lossy(buffer).into_owned();
+    *buffer = rest;
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// 按 asciicast v2 规范校验文件内容
+    fn validate_cast(content: &str) -> Vec<Value> {
+        let mut lines = content.lines();
+        let header: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 2);
+        assert!(header["width"].is_u64());
+        assert!(header["height"].is_u64());
+        assert!(header["timestamp"].is_u64());
+
+        let mut last_time = 0.0;
+        lines
+            .map(|line| {
+                let event: Value = serde_json::from_str(line).unwrap();
+                let event = event.as_array().unwrap().clone();
+                assert_eq!(event.len(), 3);
+                let time = event[0].as_f64().unwrap();
+                assert!(time >= last_time);
+                last_time = time;
+                assert!(matches!(event[1].as_str(), Some("o" | "i" | "r")));
+                assert!(event[2].is_string());
+                Value::Array(event)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cast_file_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.cast");
+
+        let mut recorder = CastRecorder::create(&path, 120, 40).unwrap();
+        recorder.output(b"$ ls\r\n").unwrap();
+        recorder.input(b"ls\r").unwrap();
+        // 多字节字符被拆分到两次读取中
+        let text = "中文\r\n".as_bytes();
+        recorder.output(&text[..4]).unwrap();
+        recorder.output(&text[4..]).unwrap();
+        recorder.resize(100, 30).unwrap();
+
+        // 未关闭文件时也应能读到已写入的事件
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events = validate_cast(&content);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0][2], "$ ls\r\n");
+        assert_eq!(events[1][2], "中");
+        assert_eq!(events[2][2], "文\r\n");
+        assert_eq!(events[3][1], "r");
+        assert_eq!(events[3][2], "100x30");
+    }
+
+    #[test]
+    fn test_cast_input_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.cast");
+
+        let mut recorder = CastRecorder::create(&path, 80, 24).unwrap().with_input(true);
+        recorder.input(b"exit\r").unwrap();
+
+        let events = validate_cast(&std::fs::read_to_string(&path).unwrap());
+        assert_eq!(events[0][1], "i");
+    }
+}
@@ -0,0 +1,256 @@
+//! 在两台远程主机之间直接传输文件
+//!
+//! 数据从源主机的 SFTP 会话读出，经本机内存写入目标主机，不落地到本地磁盘。读取在单独的线程中进行，
+//! 通过有界队列交给写入端，读写可以同时进行，内存占用不超过队列容量。
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Instant;
+
+use crate::audit::{self, Operation};
+use crate::interrupt::Cancelled;
+use crate::sftp::{self, ProgressCallback, SftpClient, TransferStats};
+
+/// 每次从源文件读取的块大小
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// 队列中最多缓存的块数（约 1MB）
+const QUEUE_DEPTH: usize = 32;
+
+/// 把 `source` 上的文件传输到 `dest`，`progress` 以源文件大小为总量
+///
+/// 目标路径是已存在的目录时传输到该目录下的同名文件。`atomic` 时先写入同目录下的临时文件，
+/// 完成后重命名为目标文件，中断或失败时删除临时文件，目标文件要么是旧内容要么是完整的新内容；
+/// 否则直接写入目标文件，取消时删除不完整的文件。
+pub fn relay_file(
+    source: &SftpClient,
+    source_path: &str,
+    dest: &SftpClient,
+    dest_path: &str,
+    atomic: bool,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    let result = relay(source, source_path, dest, dest_path, atomic, progress);
+    // 记为对目标主机的上传，源路径写成 user@host:path
+    let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+    let event = dest.audit_event(Operation::Upload).path(&source.audit_location(source_path)).path(dest_path).bytes(bytes);
+    audit::record(event, &result);
+    result
+}
+
+fn relay(
+    source: &SftpClient,
+    source_path: &str,
+    dest: &SftpClient,
+    dest_path: &str,
+    atomic: bool,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<TransferStats> {
+    info!("主机间传输: {} -> {}", source_path, dest_path);
+    let started = Instant::now();
+
+    let info = source.stat(source_path)?;
+    if info.is_dir {
+        anyhow::bail!("源路径是目录: {}（只能传输单个文件）", source_path);
+    }
+
+    let dest_path = match dest.stat(dest_path) {
+        Ok(existing) if existing.is_dir => {
+            let target = format!("{}/{}", dest_path.trim_end_matches('/'), info.name);
+            info!("目标路径是目录，传输到: {}", target);
+            target
+        }
+        _ => dest_path.to_string(),
+    };
+    let write_path = if atomic { temp_path(&dest_path) } else { dest_path.clone() };
+
+    let mut reader = source.open_reader(std::path::Path::new(source_path))?;
+    let mut writer = dest.create_writer(&write_path)?;
+
+    let copied = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_DEPTH);
+        scope.spawn(move || read_chunks(&mut reader, &sender));
+
+        let mut queue = QueueReader { receiver, current: Vec::new(), offset: 0 };
+        let result = sftp::copy_with_progress(
+            &mut queue,
+            &mut writer,
+            ("读取源文件失败", "写入目标文件失败"),
+            info.size,
+            progress,
+            || source.is_cancelled() || dest.is_cancelled(),
+        );
+        // 提前结束时关闭队列，读取线程随之退出
+        drop(queue);
+        result
+    });
+    drop(writer);
+
+    let transferred = match copied {
+        Ok(transferred) => transferred,
+        Err(e) => {
+            if atomic || e.downcast_ref::<Cancelled>().is_some() {
+                if let Err(e) = dest.remove_file(&write_path) {
+                    warn!("无法删除不完整的目标文件 {}: {:#}", write_path, e);
+                }
+            }
+            return Err(e);
+        }
+    };
+
+    if atomic {
+        replace(dest, &write_path, &dest_path)?;
+    }
+
+    info!("主机间传输完成: {} ({} 字节)", dest_path, transferred);
+    Ok(TransferStats::single_file(transferred, started.elapsed()))
+}
+
+/// 读取线程：按块读取并放入队列，队列已关闭（写入端结束）时停止
+fn read_chunks(reader: &mut dyn Read, sender: &SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        let item = match reader.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => {
+                chunk.truncate(n);
+                Ok(chunk)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+        let failed = item.is_err();
+        if sender.send(item).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// 从队列中读取数据块的 Read 适配器，发送端结束时返回 EOF
+struct QueueReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for QueueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.current.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.current = chunk?;
+                    self.offset = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.offset);
+        buf[..n].copy_from_slice(&self.current[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// 同目录下的临时文件名
+pub(crate) fn temp_path(dest_path: &str) -> String {
+    let (dir, name) = match dest_path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), dest_path),
+    };
+    format!("{}.{}.{}.part", dir, name, std::process::id())
+}
+
+/// 把临时文件重命名为目标文件
+///
+/// SFTP v3 的 rename 在目标已存在时会失败（OpenSSH 即是如此），此时先删除旧文件再重命名。
+pub(crate) fn replace(dest: &SftpClient, temp: &str, dest_path: &str) -> Result<()> {
+    if dest.rename(temp, dest_path).is_ok() {
+        return Ok(());
+    }
+    debug!("重命名失败，删除已存在的目标文件后重试: {}", dest_path);
+    let result = dest
+        .remove_file(dest_path)
+        .and_then(|_| dest.rename(temp, dest_path))
+        .context(format!("无法用临时文件替换目标文件: {}", dest_path));
+    if result.is_err() {
+        if let Err(e) = dest.remove_file(temp) {
+            warn!("无法删除临时文件 {}: {:#}", temp, e);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use std::ops::ControlFlow;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+
+    #[test]
+    fn test_temp_path() {
+        let pid = std::process::id();
+        assert_eq!(temp_path("/srv/app.tar"), format!("/srv/.app.tar.{}.part", pid));
+        assert_eq!(temp_path("app.tar"), format!(".app.tar.{}.part", pid));
+    }
+
+    #[test]
+    fn test_relay_between_servers() {
+        let source_server = TestServer::start();
+        let dest_server = TestServer::start();
+        let source_client = source_server.connect();
+        let dest_client = dest_server.connect();
+        let source = SftpClient::new(&source_client).unwrap();
+        let dest = SftpClient::new(&dest_client).unwrap();
+
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(source_server.local_path("/data.bin"), &content).unwrap();
+        std::fs::write(dest_server.local_path("/data.bin"), b"old").unwrap();
+
+        let mut last = None;
+        let stats = relay_file(&source, "/data.bin", &dest, "/data.bin", true, Some(&mut |p| {
+            last = Some(p);
+            ControlFlow::Continue(())
+        }))
+        .unwrap();
+        assert_eq!(stats.total_bytes, content.len() as u64);
+        assert_eq!(std::fs::read(dest_server.local_path("/data.bin")).unwrap(), content);
+        let last = last.unwrap();
+        assert_eq!((last.transferred, last.total), (content.len() as u64, content.len() as u64));
+
+        // 目标为目录时使用源文件名
+        std::fs::create_dir(dest_server.local_path("/incoming")).unwrap();
+        relay_file(&source, "/data.bin", &dest, "/incoming", false, None).unwrap();
+        assert_eq!(std::fs::read(dest_server.local_path("/incoming/data.bin")).unwrap(), content);
+    }
+
+    #[test]
+    fn test_relay_cancel_keeps_destination() {
+        let source_server = TestServer::start();
+        let dest_server = TestServer::start();
+        let source_client = source_server.connect();
+        let dest_client = dest_server.connect();
+        let source = SftpClient::new(&source_client).unwrap();
+        let dest = SftpClient::new(&dest_client).unwrap().with_cancel_flag(Arc::new(AtomicBool::new(false)));
+
+        std::fs::write(source_server.local_path("/data.bin"), vec![7u8; 300_000]).unwrap();
+        std::fs::write(dest_server.local_path("/data.bin"), b"old").unwrap();
+
+        let err = relay_file(&source, "/data.bin", &dest, "/data.bin", true, Some(&mut |p| {
+            if p.transferred > 0 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }))
+        .unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert_eq!(std::fs::read(dest_server.local_path("/data.bin")).unwrap(), b"old");
+        let leftovers: Vec<_> = std::fs::read_dir(dest_server.local_path("/")).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".part"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+}
@@ -0,0 +1,208 @@
+//! 在同一台远程主机上复制文件（`sftp copy`）
+//!
+//! 远程有 shell 时执行 `cp -p`（`--recursive` 时为 `cp -Rp`），数据不经过本机；无法执行命令时
+//! 通过 SFTP 读出再写回，数据要在本机和服务器之间往返一次。
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::preflight;
+use crate::relay;
+use crate::remote_os::shell_quote;
+use crate::sftp::{ProgressCallback, SftpClient, TransferProgress};
+use crate::ssh::SshClient;
+
+/// 远程 `cp` 以非零退出码结束
+#[derive(Debug, thiserror::Error)]
+#[error("远程复制失败（退出码 {code}）: {stderr}")]
+pub struct CopyFailed {
+    pub code: i32,
+    pub stderr: String,
+}
+
+/// 复制选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions {
+    /// 递归复制目录
+    pub recursive: bool,
+    /// 先复制到同目录下的临时文件，完成后重命名（只用于单个文件）
+    pub atomic: bool,
+    /// 预检得到的目录总大小，通过 SFTP 复制目录时作为整体进度的总量
+    pub total_bytes: Option<u64>,
+}
+
+/// 实际使用的复制方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyMethod {
+    /// 远程 `cp`
+    Shell,
+    /// 经本机的 SFTP 读写
+    Sftp,
+}
+
+/// 把远程的 `source` 复制到 `dest`，目标是已存在的目录时复制到其中的同名文件
+///
+/// `progress` 只在通过 SFTP 复制时调用；复制目录时报告所有文件累计的进度，总量为 `total_bytes`。
+pub fn copy(
+    client: &SshClient,
+    sftp: &SftpClient,
+    source: &str,
+    dest: &str,
+    options: CopyOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<CopyMethod> {
+    let info = sftp.stat(source)?;
+    if info.is_dir && !options.recursive {
+        anyhow::bail!("源路径是目录: {}（复制目录请使用 --recursive）", source);
+    }
+    if info.is_dir && options.atomic {
+        anyhow::bail!("--atomic 只能用于单个文件");
+    }
+
+    if client.remote_info().is_unix() {
+        let command = cp_command(sftp, source, dest, options)?;
+        info!("远程复制: {}", command);
+        let (_, stderr, code) = client.exec_command_timeout(&command, client.command_timeout())?;
+        if code != 0 {
+            return Err(CopyFailed { code, stderr: stderr.trim().to_string() }.into());
+        }
+        return Ok(CopyMethod::Shell);
+    }
+
+    warn!("远程无法执行 cp，改为通过 SFTP 读出再写回，数据会经过本机往返传输");
+    if info.is_dir {
+        copy_dir_via_sftp(sftp, source, dest, options.total_bytes.unwrap_or(0), progress)?;
+    } else {
+        relay::relay_file(sftp, source, sftp, dest, options.atomic, progress)?;
+    }
+    Ok(CopyMethod::Sftp)
+}
+
+/// 远程执行的复制命令；`atomic` 时复制到临时文件再用 `mv -f` 替换，失败时删除临时文件并保留退出码
+fn cp_command(sftp: &SftpClient, source: &str, dest: &str, options: CopyOptions) -> Result<String> {
+    let flags = if options.recursive { "-Rp" } else { "-p" };
+    if !options.atomic {
+        return Ok(format!("cp {} -- {} {}", flags, shell_quote(source), shell_quote(dest)));
+    }
+
+    let dest = final_dest(sftp, source, dest);
+    let temp = shell_quote(&relay::temp_path(&dest));
+    Ok(format!(
+        "cp {flags} -- {source} {temp} && mv -f -- {temp} {dest} || {{ code=$?; rm -f -- {temp}; exit $code; }}",
+        source = shell_quote(source),
+        dest = shell_quote(&dest),
+    ))
+}
+
+/// 目标是已存在的目录时复制到其中的同名路径
+fn final_dest(sftp: &SftpClient, source: &str, dest: &str) -> String {
+    match sftp.stat(dest) {
+        Ok(existing) if existing.is_dir => {
+            let name = source.trim_end_matches('/').rsplit('/').next().unwrap_or(source);
+            format!("{}/{}", dest.trim_end_matches('/'), name)
+        }
+        _ => dest.to_string(),
+    }
+}
+
+/// 通过 SFTP 递归复制目录，语义与 `cp -R` 相同：目标已存在时复制到其中的同名目录
+fn copy_dir_via_sftp(sftp: &SftpClient, source: &str, dest: &str, total: u64, progress: Option<ProgressCallback<'_>>) -> Result<()> {
+    let dest = final_dest(sftp, source, dest);
+    let mut tree = TreeProgress { total, done: 0, progress };
+    copy_tree(sftp, source, &dest, &mut tree)
+}
+
+/// 把单个文件的进度换算为整个目录的累计进度
+struct TreeProgress<'p> {
+    total: u64,
+    /// 已复制完的文件的总字节数
+    done: u64,
+    progress: Option<ProgressCallback<'p>>,
+}
+
+fn copy_tree(sftp: &SftpClient, source: &str, dest: &str, tree: &mut TreeProgress) -> Result<()> {
+    sftp.mkdir_p(dest)?;
+    for entry in sftp.list_dir(source)? {
+        let target = format!("{}/{}", dest.trim_end_matches('/'), entry.name);
+        if preflight::skipped(&entry) {
+            warn!("跳过符号链接: {}", entry.path);
+        } else if entry.is_dir {
+            copy_tree(sftp, &entry.path, &target, tree)?;
+        } else {
+            let (done, total) = (tree.done, tree.total);
+            let stats = match tree.progress.as_deref_mut() {
+                Some(progress) => {
+                    let mut file_progress = |p: TransferProgress| {
+                        progress(TransferProgress { transferred: done + p.transferred, total: total.max(done + p.total), ..p })
+                    };
+                    relay::relay_file(sftp, &entry.path, sftp, &target, false, Some(&mut file_progress))?
+                }
+                None => relay::relay_file(sftp, &entry.path, sftp, &target, false, None)?,
+            };
+            tree.done += stats.total_bytes;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_copy_with_cp() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::write(server.local_path("/app.conf"), b"port = 80").unwrap();
+        std::fs::create_dir_all(server.local_path("/site/css")).unwrap();
+        std::fs::write(server.local_path("/site/css/app.css"), b"body {}").unwrap();
+        std::fs::write(server.local_path("/backup.conf"), b"old").unwrap();
+
+        // 测试服务器的 exec 在根目录中执行，使用相对路径
+        let atomic = CopyOptions { atomic: true, ..Default::default() };
+        assert_eq!(copy(&client, &sftp, "app.conf", "backup.conf", atomic, None).unwrap(), CopyMethod::Shell);
+        assert_eq!(std::fs::read(server.local_path("/backup.conf")).unwrap(), b"port = 80");
+
+        let recursive = CopyOptions { recursive: true, ..Default::default() };
+        copy(&client, &sftp, "site", "site-copy", recursive, None).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/site-copy/css/app.css")).unwrap(), b"body {}");
+        assert!(copy(&client, &sftp, "site", "other", CopyOptions::default(), None).is_err());
+
+        // cp 失败时返回退出码和错误输出，不留下临时文件
+        std::fs::create_dir(server.local_path("/locked")).unwrap();
+        std::fs::write(server.local_path("/locked/app.conf"), b"keep").unwrap();
+        let err = copy(&client, &sftp, "app.conf", "locked/app.conf/x", atomic, None).unwrap_err();
+        let failed = err.downcast_ref::<CopyFailed>().unwrap();
+        assert_ne!(failed.code, 0);
+        assert!(!failed.stderr.is_empty());
+        assert_eq!(std::fs::read_dir(server.local_path("/locked")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_copy_via_sftp() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::create_dir_all(server.local_path("/site/css")).unwrap();
+        std::fs::write(server.local_path("/site/index.html"), b"<html>").unwrap();
+        std::fs::write(server.local_path("/site/css/app.css"), b"body {}").unwrap();
+        std::fs::create_dir(server.local_path("/backup")).unwrap();
+
+        // 进度按整个目录累计，最后一次报告的是两个文件的总大小
+        let mut last = None;
+        copy_dir_via_sftp(&sftp, "/site", "/backup", 13, Some(&mut |p| {
+            last = Some((p.transferred, p.total));
+            std::ops::ControlFlow::Continue(())
+        }))
+        .unwrap();
+        assert_eq!(last, Some((13, 13)));
+        assert_eq!(std::fs::read(server.local_path("/backup/site/index.html")).unwrap(), b"<html>");
+        assert_eq!(std::fs::read(server.local_path("/backup/site/css/app.css")).unwrap(), b"body {}");
+
+        // 单个文件的读写使用同一个 SFTP 会话
+        relay::relay_file(&sftp, "/site/index.html", &sftp, "/index.bak", true, None).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/index.bak")).unwrap(), b"<html>");
+    }
+}
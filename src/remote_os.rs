@@ -0,0 +1,398 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::sftp::SftpClient;
+use crate::ssh::{self, SshClient};
+
+/// 远程系统类型，决定辅助功能使用哪种命令
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OsFamily {
+    Linux,
+    MacOs,
+    /// FreeBSD、OpenBSD、NetBSD 等
+    Bsd,
+    /// 其他类 Unix 系统（Solaris、Cygwin 等），按 POSIX 命令处理
+    Unix,
+    /// Windows 上的 OpenSSH 服务器（默认 shell 为 cmd.exe）
+    Windows,
+    /// 无法执行命令（例如只允许 SFTP），只能使用 SFTP 实现的功能
+    Unknown,
+}
+
+/// 远程系统信息，见 [`SshClient::remote_info`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteInfo {
+    pub family: OsFamily,
+    /// `uname -s` 的结果，Windows 上为 `ver` 的输出
+    pub kernel: Option<String>,
+    pub arch: Option<String>,
+    /// 发行版名称（`/etc/os-release` 中的 PRETTY_NAME）
+    pub distro: Option<String>,
+}
+
+/// 远程磁盘空间（字节）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DiskFree {
+    pub total: u64,
+    pub available: u64,
+}
+
+/// 远程 uid/gid 对应的用户名和组名，见 [`SshClient::owner_names`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+/// `getent passwd` 和 `getent group` 输出之间的分隔行
+const GETENT_SEPARATOR: &str = "#";
+
+impl OwnerNames {
+    /// 用一条命令读取 `getent passwd` 和 `getent group`；无法执行命令（只允许 SFTP、Windows、
+    /// 没有 getent）时为空，显示数字 id
+    pub fn probe(client: &SshClient) -> Self {
+        let command = format!("getent passwd; echo '{}'; getent group", GETENT_SEPARATOR);
+        match client.exec_command_timeout(&command, Some(ssh::PROBE_TIMEOUT)) {
+            Ok((output, _, _)) => Self::parse(&String::from_utf8_lossy(&output)),
+            Err(e) => {
+                debug!("读取用户和组失败: {:#}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub(crate) fn parse(output: &str) -> Self {
+        let mut names = Self::default();
+        let mut table = &mut names.users;
+        for line in output.lines() {
+            if line.trim() == GETENT_SEPARATOR {
+                table = &mut names.groups;
+                continue;
+            }
+            // name:password:id:...
+            let mut fields = line.split(':');
+            if let (Some(name), Some(id)) = (fields.next(), fields.nth(1)) {
+                if let Ok(id) = id.parse() {
+                    table.entry(id).or_insert_with(|| name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// 用户名，未知时为数字 uid
+    pub fn user(&self, uid: u32) -> String {
+        self.users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+    }
+
+    /// 组名，未知时为数字 gid
+    pub fn group(&self, gid: u32) -> String {
+        self.groups.get(&gid).cloned().unwrap_or_else(|| gid.to_string())
+    }
+}
+
+impl RemoteInfo {
+    /// 无法识别的系统
+    pub fn unknown() -> Self {
+        Self { family: OsFamily::Unknown, kernel: None, arch: None, distro: None }
+    }
+
+    /// 探测远程系统：先试 `uname -s -m`，失败时试 Windows 的 `ver`，都失败时为 Unknown
+    pub fn probe(client: &SshClient) -> Self {
+        let run = |command: &str| match client.exec_command_timeout(command, Some(ssh::PROBE_TIMEOUT)) {
+            Ok((output, _, 0)) => Some(String::from_utf8_lossy(&output).trim().to_string()),
+            Ok((_, stderr, status)) => {
+                debug!("探测命令失败（退出码 {}）: {}: {}", status, command, stderr.trim());
+                None
+            }
+            Err(e) => {
+                debug!("探测命令失败: {}: {:#}", command, e);
+                None
+            }
+        };
+
+        let info = if let Some(mut info) = run("uname -s -m").and_then(|output| parse_uname(&output)) {
+            if info.family == OsFamily::Linux {
+                info.distro = run("cat /etc/os-release").and_then(|output| parse_os_release(&output));
+            }
+            info
+        } else if let Some(version) = run("ver").filter(|output| output.contains("Windows")) {
+            RemoteInfo {
+                family: OsFamily::Windows,
+                kernel: Some(version),
+                arch: run("echo %PROCESSOR_ARCHITECTURE%").filter(|arch| !arch.contains('%')),
+                distro: None,
+            }
+        } else {
+            RemoteInfo::unknown()
+        };
+        debug!("远程系统: {:?}", info);
+        info
+    }
+
+    /// 可以执行 POSIX shell 命令
+    pub fn is_unix(&self) -> bool {
+        matches!(self.family, OsFamily::Linux | OsFamily::MacOs | OsFamily::Bsd | OsFamily::Unix)
+    }
+
+    /// 不支持 POSIX shell 时返回说明哪个操作无法执行的错误
+    pub fn require_unix(&self, operation: &str) -> Result<()> {
+        match self.family {
+            OsFamily::Windows => anyhow::bail!("Windows 服务器不支持{}", operation),
+            OsFamily::Unknown => anyhow::bail!("无法识别远程系统（可能只允许 SFTP），不支持{}", operation),
+            _ => Ok(()),
+        }
+    }
+
+    /// 用于显示的描述，例如 `Ubuntu 22.04.4 LTS (Linux x86_64)`
+    pub fn describe(&self) -> String {
+        let platform = [self.kernel.as_deref(), self.arch.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match (&self.distro, self.family) {
+            (_, OsFamily::Unknown) => "未知（不支持执行命令）".to_string(),
+            (Some(distro), _) => format!("{} ({})", distro, platform),
+            (None, _) => platform,
+        }
+    }
+
+    /// 计算 SHA-256 的命令，Unknown 时为 None
+    fn sha256_command(&self, remote_path: &str) -> Option<String> {
+        let quoted = shell_quote(remote_path);
+        match self.family {
+            OsFamily::Linux | OsFamily::Unix => Some(format!("sha256sum -- {}", quoted)),
+            OsFamily::MacOs => Some(format!("shasum -a 256 -- {}", quoted)),
+            OsFamily::Bsd => Some(format!("sha256 -q -- {}", quoted)),
+            OsFamily::Windows => Some(format!("certutil -hashfile \"{}\" SHA256", windows_path(remote_path))),
+            OsFamily::Unknown => None,
+        }
+    }
+
+    /// 查询磁盘空间的命令，Unknown 时为 None
+    fn disk_free_command(&self, remote_path: &str) -> Option<String> {
+        match self.family {
+            OsFamily::Windows => Some(format!(
+                "powershell -NoProfile -Command \"$d = (Get-Item -LiteralPath '{}').PSDrive; Write-Output $d.Used $d.Free\"",
+                windows_path(remote_path).replace('\'', "''")
+            )),
+            OsFamily::Unknown => None,
+            _ => Some(format!("df -kP -- {}", shell_quote(remote_path))),
+        }
+    }
+}
+
+/// 计算远程文件的 SHA-256（小写十六进制）
+///
+/// 按远程系统选择 sha256sum、shasum、sha256 或 certutil；无法执行命令时通过 SFTP 读取文件
+/// 在本地计算。
+pub fn sha256(client: &SshClient, sftp: &SftpClient, remote_path: &str) -> Result<String> {
+    sha256_with(client.remote_info(), client, sftp, remote_path)
+}
+
+fn sha256_with(info: &RemoteInfo, client: &SshClient, sftp: &SftpClient, remote_path: &str) -> Result<String> {
+    let Some(command) = info.sha256_command(remote_path) else {
+        warn!("无法识别远程系统，改为通过 SFTP 读取文件计算校验和");
+        return sha256_via_sftp(sftp, remote_path);
+    };
+
+    let output = run_checked(client, &command, "计算校验和", client.command_timeout())?;
+    parse_checksum(&output).context(format!("无法解析校验和输出: {}", output.trim()))
+}
+
+/// 与 [`sha256`] 相同，但远程命令失败（没有 shell 或命令不存在）时改为通过 SFTP 读取文件计算
+pub fn sha256_or_read(client: &SshClient, sftp: &SftpClient, remote_path: &str) -> Result<String> {
+    sha256(client, sftp, remote_path).or_else(|e| {
+        debug!("远程计算校验和失败，改为通过 SFTP 读取: {:#}", e);
+        sha256_via_sftp(sftp, remote_path)
+    })
+}
+
+/// 通过 SFTP 读取远程文件，在本地计算 SHA-256
+fn sha256_via_sftp(sftp: &SftpClient, remote_path: &str) -> Result<String> {
+    let mut reader = sftp.open_reader(Path::new(remote_path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher).context(format!("读取远程文件失败: {}", remote_path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 查询远程路径所在文件系统的空间
+///
+/// Unix 上使用 `df -kP`，Windows 上使用 PowerShell；无法执行命令时使用 SFTP 的 statvfs 扩展。
+pub fn disk_free(client: &SshClient, sftp: &SftpClient, remote_path: &str) -> Result<DiskFree> {
+    let info = client.remote_info();
+    let Some(command) = info.disk_free_command(remote_path) else {
+        warn!("无法识别远程系统，改为通过 SFTP 查询磁盘空间");
+        return sftp.statvfs(remote_path).context("服务器不支持 SFTP statvfs 扩展，无法查询磁盘空间");
+    };
+
+    let output = run_checked(client, &command, "查询磁盘空间", Some(ssh::PROBE_TIMEOUT))?;
+    let parsed = if info.family == OsFamily::Windows {
+        parse_powershell_drive(&output)
+    } else {
+        parse_df(&output)
+    };
+    parsed.context(format!("无法解析磁盘空间输出: {}", output.trim()))
+}
+
+fn run_checked(client: &SshClient, command: &str, operation: &str, timeout: Option<Duration>) -> Result<String> {
+    let (output, stderr, status) = client.exec_command_timeout(command, timeout)?;
+    if status != 0 {
+        anyhow::bail!("{}失败（退出码 {}）: {}", operation, status, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// 单引号转义，使参数原样传给远程 POSIX shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// SFTP 路径转换为 Windows 路径：`/C:/Users/a` -> `C:\Users\a`
+fn windows_path(remote_path: &str) -> String {
+    let bytes = remote_path.as_bytes();
+    let path = if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        &remote_path[1..]
+    } else {
+        remote_path
+    };
+    path.replace('/', "\\")
+}
+
+fn parse_uname(output: &str) -> Option<RemoteInfo> {
+    let mut parts = output.split_whitespace();
+    let kernel = parts.next()?.to_string();
+    let family = match kernel.as_str() {
+        "Linux" => OsFamily::Linux,
+        "Darwin" => OsFamily::MacOs,
+        k if k.ends_with("BSD") || k == "DragonFly" => OsFamily::Bsd,
+        _ => OsFamily::Unix,
+    };
+    Some(RemoteInfo { family, kernel: Some(kernel), arch: parts.next().map(str::to_string), distro: None })
+}
+
+fn parse_os_release(output: &str) -> Option<String> {
+    let value = |key: &str| {
+        output.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string()).filter(|v| !v.is_empty())
+        })
+    };
+    value("PRETTY_NAME").or_else(|| value("NAME"))
+}
+
+/// 从各种校验和工具的输出中找出 64 位十六进制摘要（旧版 certutil 以空格分隔字节）
+fn parse_checksum(output: &str) -> Option<String> {
+    let is_digest = |s: &str| s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    output.lines().find_map(|line| {
+        let first = line.split_whitespace().next()?;
+        if is_digest(first) {
+            return Some(first.to_ascii_lowercase());
+        }
+        let joined: String = line.split_whitespace().collect();
+        is_digest(&joined).then(|| joined.to_ascii_lowercase())
+    })
+}
+
+/// 解析 `df -kP` 的数据行：文件系统、总块数、已用、可用（KB）……
+fn parse_df(output: &str) -> Option<DiskFree> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    Some(DiskFree {
+        total: fields.get(1)?.parse::<u64>().ok()? * 1024,
+        available: fields.get(3)?.parse::<u64>().ok()? * 1024,
+    })
+}
+
+/// 解析 PowerShell 输出的已用和可用字节数（各占一行）
+fn parse_powershell_drive(output: &str) -> Option<DiskFree> {
+    let mut values = output.split_whitespace().map(|v| v.parse::<u64>().ok());
+    let used = values.next()??;
+    let free = values.next()??;
+    Some(DiskFree { total: used + free, available: free })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_parse_system() {
+        let info = parse_uname("Darwin arm64\n").unwrap();
+        assert_eq!(info.family, OsFamily::MacOs);
+        assert_eq!(info.describe(), "Darwin arm64");
+        assert_eq!(parse_uname("FreeBSD amd64").unwrap().family, OsFamily::Bsd);
+        assert_eq!(parse_uname("CYGWIN_NT-10.0 x86_64").unwrap().family, OsFamily::Unix);
+        assert!(parse_uname("").is_none());
+
+        let release = "NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nPRETTY_NAME=\"Ubuntu 22.04.4 LTS\"\n";
+        assert_eq!(parse_os_release(release).unwrap(), "Ubuntu 22.04.4 LTS");
+        assert_eq!(parse_os_release("NAME=Alpine Linux\n").unwrap(), "Alpine Linux");
+
+        let mut info = parse_uname("Linux x86_64").unwrap();
+        info.distro = Some("Ubuntu 22.04.4 LTS".to_string());
+        assert_eq!(info.describe(), "Ubuntu 22.04.4 LTS (Linux x86_64)");
+        assert!(RemoteInfo::unknown().require_unix("进程列表").unwrap_err().to_string().contains("进程列表"));
+    }
+
+    #[test]
+    fn test_commands_per_os() {
+        let windows = RemoteInfo { family: OsFamily::Windows, kernel: None, arch: None, distro: None };
+        assert_eq!(
+            windows.sha256_command("/C:/Users/a/app.zip").unwrap(),
+            "certutil -hashfile \"C:\\Users\\a\\app.zip\" SHA256"
+        );
+        assert!(windows.disk_free_command("/D:/it's").unwrap().contains("'D:\\it''s'"));
+        let mac = parse_uname("Darwin arm64").unwrap();
+        assert_eq!(mac.sha256_command("/tmp/a b").unwrap(), "shasum -a 256 -- '/tmp/a b'");
+        assert_eq!(RemoteInfo::unknown().sha256_command("/a"), None);
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_parse_outputs() {
+        let digest = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(parse_checksum(&format!("{}  /tmp/empty\n", digest)).unwrap(), digest);
+        let certutil = "SHA256 hash of C:\\empty:\r\ne3 b0 c4 42 98 fc 1c 14 9a fb f4 c8 99 6f b9 24 27 ae 41 e4 64 9b 93 4c a4 95 99 1b 78 52 b8 55\r\nCertUtil: -hashfile command completed successfully.\r\n";
+        assert_eq!(parse_checksum(certutil).unwrap(), digest);
+        assert_eq!(parse_checksum("sha256sum: /x: No such file"), None);
+
+        let df = "Filesystem     1024-blocks    Used Available Capacity Mounted on\n/dev/sda1         1000000  400000    600000      40% /\n";
+        assert_eq!(parse_df(df), Some(DiskFree { total: 1_024_000_000, available: 614_400_000 }));
+        assert_eq!(parse_powershell_drive("300\r\n700\r\n"), Some(DiskFree { total: 1000, available: 700 }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_and_helpers() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::write(server.local_path("/data.bin"), b"hello").unwrap();
+        let expected = format!("{:x}", Sha256::digest(b"hello"));
+
+        let info = client.remote_info();
+        assert!(info.is_unix());
+        assert!(info.arch.is_some());
+        assert!(std::ptr::eq(info, client.remote_info()));
+
+        // 测试服务器的 exec 在根目录中执行，使用相对路径；SFTP 使用绝对路径
+        assert_eq!(sha256(&client, &sftp, "data.bin").unwrap(), expected);
+        assert_eq!(sha256_with(&RemoteInfo::unknown(), &client, &sftp, "/data.bin").unwrap(), expected);
+        assert!(sha256(&client, &sftp, "missing").unwrap_err().to_string().contains("计算校验和失败"));
+
+        let free = disk_free(&client, &sftp, ".").unwrap();
+        assert!(free.total >= free.available && free.total > 0);
+
+        let names = client.owner_names();
+        assert_eq!((names.user(0), names.group(0)), ("root".to_string(), "root".to_string()));
+        assert_eq!(names.user(4_000_000), "4000000");
+        assert!(std::ptr::eq(names, client.owner_names()));
+    }
+}
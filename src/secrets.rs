@@ -0,0 +1,149 @@
+//! 连接的附加密文（sudo 密码、API token 等）
+//!
+//! 与登录密码一样用主密码加密后保存在连接的 `secrets` 中，配置文件中只有密文。解密后的值登记到
+//! 本次运行的凭据缓存：日志中出现时会被替换为 `******`，进程退出前清零。
+
+use anyhow::{Context, Result};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::config::{validate_secret_key, AppConfig};
+use crate::credentials::{self, Credential};
+use crate::crypto::CryptoManager;
+use crate::glyphs;
+use crate::prompt::{self, Prompter};
+use crate::remote_os::shell_quote;
+use crate::snippet;
+
+/// `exec --sudo` 使用的密文名称
+pub const SUDO_KEY: &str = "sudo";
+
+/// 加密新数据用的加密管理器：已有密文时用它校验主密码，否则首次设置或直接询问主密码
+pub fn crypto_for_encrypt(config: &AppConfig, purpose: &str) -> Result<CryptoManager> {
+    if let Some(encrypted) = config.any_ciphertext() {
+        return CryptoManager::unlock(&mut prompt::Terminal, encrypted, config.unlock_cooldown(), purpose);
+    }
+    let is_first_time = !CryptoManager::has_master_password();
+    let master_password = CryptoManager::get_master_password(&mut prompt::Terminal, is_first_time, purpose)?;
+    CryptoManager::new(&master_password)
+}
+
+/// 加密 `value` 并保存到连接（只保存密文，调用方负责保存配置）
+pub fn set(config: &mut AppConfig, connection: &str, key: &str, value: &str) -> Result<()> {
+    validate_secret_key(key)?;
+    if config.get_connection(connection).is_none() {
+        anyhow::bail!("连接 '{}' 不存在", connection);
+    }
+    let purpose = format!("加密 {} 的密文 '{}'", connection, key);
+    let encrypted = crypto_for_encrypt(config, &purpose)?.encrypt(value)?;
+    let conn = config.connections.get_mut(connection).context(format!("连接 '{}' 不存在", connection))?;
+    conn.secrets.insert(key.to_string(), encrypted);
+    credentials::remember(Credential::secret(connection, key), value);
+    Ok(())
+}
+
+/// 解密连接的附加密文（需要主密码）
+pub fn reveal(config: &AppConfig, connection: &str, key: &str) -> Result<Zeroizing<String>> {
+    let cache_key = Credential::secret(connection, key);
+    if let Some(value) = credentials::get(&cache_key) {
+        return Ok(Zeroizing::new(value));
+    }
+    let encrypted = config
+        .get_connection(connection)
+        .context(format!("连接 '{}' 不存在", connection))?
+        .secrets
+        .get(key)
+        .context(format!("连接 '{}' 没有保存密文 '{}'", connection, key))?;
+    let purpose = format!("解密 {} 的密文 '{}'", connection, key);
+    let crypto = CryptoManager::unlock(&mut prompt::Terminal, encrypted, config.unlock_cooldown(), &purpose)?;
+    let value = Zeroizing::new(crypto.decrypt(encrypted).context(format!("无法解密 '{}'", key))?);
+    credentials::remember(cache_key, &value);
+    Ok(value)
+}
+
+/// sudo 密码：保存的连接有 `sudo` 密文时解密使用，否则询问（同一次运行只询问一次）
+pub fn sudo_password(config: &AppConfig, target: &str) -> Result<Zeroizing<String>> {
+    let has_saved = config.get_connection(target).is_some_and(|c| c.secrets.contains_key(SUDO_KEY));
+    if has_saved {
+        return reveal(config, target, SUDO_KEY);
+    }
+    let cache_key = Credential::secret(target, SUDO_KEY);
+    if let Some(value) = credentials::get(&cache_key) {
+        return Ok(Zeroizing::new(value));
+    }
+    let value = Zeroizing::new(prompt::Terminal.ask_password(&prompt::labeled(&format!("sudo {} {}", glyphs::bullet(), target), "密码"))?);
+    credentials::remember(cache_key, &value);
+    Ok(value)
+}
+
+/// 展开命令片段：`vars` 为 `key=value` 形式的变量，`{{secret:key}}` 解密连接的附加密文后代入
+///
+/// 结果只用于发送给服务器；显示和记录日志时使用模板本身。
+pub fn expand_snippet<S: AsRef<str>>(config: &AppConfig, connection: &str, template: &str, vars: &[S]) -> Result<Zeroizing<String>> {
+    let mut vars = snippet::parse_vars(vars)?;
+    // 解密的值用完后清零
+    for key in snippet::secret_refs(template) {
+        let value = reveal(config, connection, &key)?;
+        vars.insert(format!("{}{}", snippet::SECRET_PREFIX, key), value.to_string());
+    }
+    let expanded = snippet::expand(template, &vars);
+    vars.values_mut().for_each(Zeroize::zeroize);
+    Ok(Zeroizing::new(expanded?))
+}
+
+/// 用 sudo 执行命令：密码从标准输入读取，不显示提示
+pub fn sudo_command(command: &str) -> String {
+    format!("sudo -S -p '' -- sh -c {}", shell_quote(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SavedConnection;
+    use crate::log_buffer;
+    use crate::terminal::InteractiveTerminal;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_sudo_command() {
+        assert_eq!(sudo_command("systemctl restart nginx"), "sudo -S -p '' -- sh -c 'systemctl restart nginx'");
+        assert_eq!(sudo_command("echo 'a'"), "sudo -S -p '' -- sh -c 'echo '\\''a'\\'''");
+    }
+
+    #[test]
+    fn test_reveal_uses_cache_and_reports_missing() {
+        let mut config = AppConfig::default();
+        config.add_connection(SavedConnection::new_password("web".into(), "h".into(), 22, "u".into()));
+
+        let err = reveal(&config, "web", "token").unwrap_err();
+        assert!(err.to_string().contains("token"));
+        assert!(reveal(&config, "missing", "token").is_err());
+
+        // 本次运行已解密过的值直接使用，不需要主密码
+        credentials::remember(Credential::secret("web", "cached"), "value");
+        assert_eq!(*reveal(&config, "web", "cached").unwrap(), "value");
+        credentials::forget(&Credential::secret("web", "cached"));
+    }
+
+    #[test]
+    fn test_snippet_secret_not_logged() {
+        let captured = log_buffer::capture();
+        let server = TestServer::start();
+        let client = server.connect();
+        let config = AppConfig::default();
+        let key = Credential::secret("snippets", "token");
+        credentials::remember(key.clone(), "snippet-secret-7");
+
+        let template = "test -n {{secret:token}} && echo {{who}}";
+        let command = expand_snippet(&config, "snippets", template, &["who=ops"]).unwrap();
+        assert_eq!(*command, "test -n snippet-secret-7 && echo ops");
+        // 缓存中没有该值时日志无法脱敏，只能靠不记录展开后的命令
+        credentials::forget(&key);
+        InteractiveTerminal::new(&client).exec_command_shown(&command, template).unwrap();
+        log::logger().flush();
+
+        let logged = String::from_utf8_lossy(&captured.lock().unwrap()).into_owned();
+        assert!(logged.contains("执行命令: test -n {{secret:token}}"), "{}", logged);
+        assert!(!logged.contains("snippet-secret-7"), "{}", logged);
+        assert!(log_buffer::snapshot().iter().all(|e| !e.message.contains("snippet-secret-7")));
+    }
+}
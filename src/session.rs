@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::{info, warn};
+use std::io;
+
+use crate::glyphs;
+use crate::ssh::{SshClient, SshConfig};
+
+/// libssh2 中表示连接已断开的错误码
+const CONNECTION_ERROR_CODES: &[i32] = &[
+    -7,  // LIBSSH2_ERROR_SOCKET_SEND
+    -13, // LIBSSH2_ERROR_SOCKET_DISCONNECT
+    -26, // LIBSSH2_ERROR_CHANNEL_CLOSED
+    -30, // LIBSSH2_ERROR_SOCKET_TIMEOUT
+    -43, // LIBSSH2_ERROR_SOCKET_RECV
+];
+
+/// 已认证的远程会话
+///
+/// 保存认证所用的配置（包括已解密的密码），连接断开后可以重新建立而不需要再次输入。
+pub struct RemoteSession {
+    config: SshConfig,
+    jump: Option<SshConfig>,
+    client: SshClient,
+    reconnects: u32,
+    /// 保活间隔（秒），重连后重新启用
+    keepalive: Option<u32>,
+}
+
+impl RemoteSession {
+    /// 建立会话，`jump` 为跳板机配置
+    pub fn connect(config: SshConfig, jump: Option<SshConfig>) -> Result<Self> {
+        let client = Self::open(&config, jump.as_ref())?;
+        Ok(Self {
+            config,
+            jump,
+            client,
+            reconnects: 0,
+            keepalive: None,
+        })
+    }
+    
+    /// 启用后台保活
+    pub fn set_keepalive(&mut self, interval: Option<u32>) {
+        self.keepalive = interval;
+        if let Some(interval) = interval {
+            self.client.start_keepalive(interval);
+        }
+    }
+
+    fn open(config: &SshConfig, jump: Option<&SshConfig>) -> Result<SshClient> {
+        match jump {
+            Some(jump) => SshClient::connect_via_jump(jump.clone(), config.clone()),
+            None => SshClient::connect(config.clone()),
+        }
+    }
+
+    /// 当前的 SSH 客户端
+    pub fn client(&self) -> &SshClient {
+        &self.client
+    }
+
+    /// 用相同的配置再建立一个独立的连接（不再询问密码），供并行传输使用
+    pub fn connect_another(&self) -> Result<SshClient> {
+        Self::open(&self.config, self.jump.as_ref())
+    }
+
+    /// 使用内存中的认证信息重新连接
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.reconnects += 1;
+        warn!(
+            "连接已断开，正在重新连接 {}@{}:{}（第 {} 次）",
+            self.config.username, self.config.host, self.config.port, self.reconnects
+        );
+        eprintln!(
+            "{} 连接已断开，正在重新连接 {}@{}...",
+            glyphs::arrow().yellow(),
+            self.config.username,
+            self.config.host
+        );
+
+        self.client = Self::open(&self.config, self.jump.as_ref()).context("重新连接失败")?;
+        if let Some(interval) = self.keepalive {
+            self.client.start_keepalive(interval);
+        }
+        info!("重新连接成功");
+        Ok(())
+    }
+
+    /// 重新连接的次数
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+}
+
+/// 判断错误是否由连接断开引起（此时值得重连重试）
+pub fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<ssh2::Error>() {
+            return matches!(e.code(), ssh2::ErrorCode::Session(code) if CONNECTION_ERROR_CODES.contains(&code));
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_is_connection_error() {
+        let err = anyhow::Error::new(ssh2::Error::new(ssh2::ErrorCode::Session(-43), "recv"))
+            .context("读取远程文件失败");
+        assert!(is_connection_error(&err));
+
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(is_connection_error(&err));
+
+        // SFTP 层的错误（例如文件不存在）不需要重连
+        let err = anyhow::Error::new(ssh2::Error::new(ssh2::ErrorCode::SFTP(2), "no such file"));
+        assert!(!is_connection_error(&err));
+        assert!(!is_connection_error(&anyhow::anyhow!("其他错误")));
+    }
+
+    #[test]
+    fn test_reconnect_reuses_credentials() {
+        let server = TestServer::start();
+        let config = server.password_config();
+
+        let mut session = RemoteSession::connect(config, None).unwrap();
+        session.set_keepalive(Some(1));
+        session.reconnect().unwrap();
+        assert_eq!(session.reconnects(), 1);
+        assert_eq!(session.client().exec_command("echo ok").unwrap(), "ok\n");
+    }
+}
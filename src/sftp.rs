@@ -1,30 +1,340 @@
 use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use log::{debug, info};
-use ssh2::Sftp;
+use log::{debug, info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use ssh2::{FileStat, OpenFlags, OpenType, Sftp};
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
+use crate::audit::{self, Operation};
+use crate::interrupt::Cancelled;
+use crate::manifest::Manifest;
+use crate::preflight;
+use crate::progress::Throttle;
+use crate::remote_os::{self, DiskFree, OwnerNames};
 use crate::ssh::SshClient;
 
+/// 两次进度回调之间的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 传输进度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferProgress {
+    /// 已传输的字节数
+    pub transferred: u64,
+    /// 文件总大小，未知时为 0
+    pub total: u64,
+    /// 开始传输以来的时间
+    pub elapsed: Duration,
+}
+
+/// 进度回调，返回 `ControlFlow::Break` 时中止传输（与取消标志效果相同）
+pub type ProgressCallback<'c> = &'c mut dyn FnMut(TransferProgress) -> ControlFlow<()>;
+
+/// 上传前远程已有同名文件时如何判断可以跳过（`--skip-existing`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SkipExisting {
+    /// 总是上传
+    #[default]
+    Never,
+    /// 大小相同时跳过
+    Size,
+    /// 大小和 SHA-256 都相同时跳过
+    Checksum,
+}
+
+/// 递归操作遇到符号链接时的处理方式（`--links`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Links {
+    /// 在另一端重建链接，指向的路径原样保留（目标不存在也照样创建）
+    Preserve,
+    /// 复制链接目标的内容，目标不存在时报错
+    Follow,
+    /// 跳过并给出提示
+    #[default]
+    Skip,
+}
+
 /// SFTP 客户端
 pub struct SftpClient<'a> {
     sftp: Sftp,
-    #[allow(dead_code)]
     ssh_client: &'a SshClient,
+    /// 取消标志，传输循环中检查
+    cancel: Option<Arc<AtomicBool>>,
+    /// 上传后设置的文件权限，None 时使用服务器默认值
+    file_mode: Option<u32>,
+    /// 创建目录时使用的权限
+    dir_mode: u32,
+    /// 上传时追加到远程文件末尾，而不是覆盖
+    append: bool,
+    /// 远程文件与本地相同时跳过上传
+    skip_existing: SkipExisting,
+    /// 下载后把远程文件的权限应用到本地文件
+    apply_remote_perms: bool,
+    /// 幂等操作的重试设置
+    retry: RetryPolicy,
+    /// 登录目录，第一次需要时查询
+    home: OnceLock<String>,
+    /// 目录列表缓存，None 时每次都读取
+    listing_cache: Option<Arc<ListingCache>>,
 }
 
 /// 文件信息
 #[derive(Debug, Clone)]
 pub struct FileInfo {
+    /// 文件名（非 UTF-8 的字节被替换），显示时使用 [`display_name`](Self::display_name)
     pub name: String,
-    #[allow(dead_code)]
     pub path: String,
+    /// 服务器返回的原始路径，对列表中的条目继续操作时使用，避免有损转换后指向别的文件
+    pub raw_path: PathBuf,
     pub size: u64,
     pub is_dir: bool,
-    #[allow(dead_code)]
     pub permissions: u32,
+    /// 是否为符号链接（链接本身的属性，不跟随）
+    pub is_symlink: bool,
+    /// 符号链接指向的路径，只在 [`SftpClient::resolve_links`] 之后填充
+    pub link_target: Option<String>,
+    /// 符号链接的目标不存在
+    pub link_broken: bool,
+    /// 修改时间（Unix 时间戳，秒）
+    pub modified: Option<u64>,
+    /// 属主 uid 和属组 gid（服务器未返回时为 None）
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl FileInfo {
+    /// 转义控制字符后的文件名，可以安全地打印到终端
+    pub fn display_name(&self) -> String {
+        escape_name(&self.name)
+    }
+    
+    /// `ls -l` 风格的类型和权限，例如 `drwxr-xr-x`，包括 setuid/setgid（`s`/`S`）和粘滞位（`t`/`T`）
+    pub fn mode_string(&self) -> String {
+        let kind = if self.is_symlink {
+            'l'
+        } else if self.is_dir {
+            'd'
+        } else {
+            match self.permissions & 0o170000 {
+                0o010000 => 'p',
+                0o020000 => 'c',
+                0o060000 => 'b',
+                0o140000 => 's',
+                _ => '-',
+            }
+        };
+        let mut mode = String::with_capacity(10);
+        mode.push(kind);
+        // 每组权限对应的特殊位及其显示字符
+        for (shift, special, mark) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+            let bits = (self.permissions >> shift) & 0o7;
+            mode.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            mode.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            mode.push(match (bits & 0o1 != 0, self.permissions & special != 0) {
+                (true, true) => mark,
+                (false, true) => mark.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            });
+        }
+        mode
+    }
+
+    /// 属主名称，无法解析时为数字 uid，服务器未返回时为 `-`
+    pub fn owner(&self, names: &OwnerNames) -> String {
+        self.uid.map_or_else(|| "-".to_string(), |uid| names.user(uid))
+    }
+
+    /// 属组名称，无法解析时为数字 gid，服务器未返回时为 `-`
+    pub fn group(&self, names: &OwnerNames) -> String {
+        self.gid.map_or_else(|| "-".to_string(), |gid| names.group(gid))
+    }
+}
+
+/// 新建目录的默认权限
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
+/// SFTP 状态码 LIBSSH2_FX_NO_SUCH_FILE
+const FX_NO_SUCH_FILE: i32 = 2;
+
+/// SFTP 状态码 LIBSSH2_FX_FAILURE，负载高的服务器偶尔返回，重试通常可以成功
+const FX_FAILURE: i32 = 4;
+
+/// 会话错误码 LIBSSH2_ERROR_FILE，readdir 读到目录末尾时返回
+const ERROR_FILE: i32 = -16;
+
+/// 本次运行的重试设置，未设置时使用 [`RetryPolicy::default`]
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// 幂等 SFTP 操作（stat、读取目录、只读打开、mkdir、setstat 等）遇到临时失败时的重试设置
+///
+/// 写入不会整体重试，部分写入后重来可能产生重复或错乱的内容。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 总尝试次数（1 表示不重试）
+    pub attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { attempts: 3, backoff: Duration::from_millis(200) }
+    }
+}
+
+/// 设置本次运行的重试策略，在创建任何 [`SftpClient`] 之前调用一次
+pub fn set_retry_policy(policy: RetryPolicy) {
+    let _ = RETRY_POLICY.set(policy);
+}
+
+/// 按 `policy` 执行 `op`，只在临时失败（SSH_FX_FAILURE）时重试；`op` 的参数是第几次尝试（从 1 开始）
+fn with_retry<T>(policy: RetryPolicy, what: &str, mut op: impl FnMut(u32) -> Result<T, ssh2::Error>) -> Result<T, ssh2::Error> {
+    let mut delay = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match op(attempt) {
+            Err(e) if e.code() == ssh2::ErrorCode::SFTP(FX_FAILURE) && attempt < policy.attempts => {
+                debug!("{} 失败，{:?} 后重试（第 {}/{} 次尝试）: {}", what, delay, attempt + 1, policy.attempts, e);
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// 目录列表缓存，供长时间运行、反复列出同一目录的会话使用（控制主连接）；一次性的命令不使用
+///
+/// 按规范化后的路径缓存 [`SftpClient::list_dir`] 的结果，超过 `ttl` 后重新读取。通过挂载了同一缓存的
+/// [`SftpClient`] 创建、删除、改名、上传或修改属性时，使路径本身及其父目录的列表失效；其他客户端的
+/// 修改只能等缓存过期，或用 [`SftpClient::refresh_dir`] 重新读取。
+pub struct ListingCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Vec<FileInfo>)>>,
+}
+
+impl ListingCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, Vec<FileInfo>)>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, path: &str) -> Option<Vec<FileInfo>> {
+        let key = cache_key(path);
+        let mut entries = self.entries();
+        match entries.get(&key) {
+            Some((cached_at, files)) if cached_at.elapsed() < self.ttl => Some(files.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, path: &str, files: &[FileInfo]) {
+        self.entries().insert(cache_key(path), (Instant::now(), files.to_vec()));
+    }
+
+    /// 使 `path` 本身（是目录时）及其父目录的列表失效
+    pub fn invalidate(&self, path: &str) {
+        let key = cache_key(path);
+        let parent = match key.rsplit_once('/') {
+            Some(("", _)) => "/".to_string(),
+            Some((parent, _)) => parent.to_string(),
+            None => ".".to_string(),
+        };
+        let mut entries = self.entries();
+        entries.remove(&key);
+        entries.remove(&parent);
+    }
+
+    /// 清空缓存（例如重新连接后）
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+}
+
+/// 缓存键：绝对路径规范化 `.`、`..` 和重复的 `/`，相对路径只去掉结尾的 `/`
+fn cache_key(path: &str) -> String {
+    if path.starts_with('/') {
+        join_remote("/", path.trim_matches('/'))
+    } else {
+        let trimmed = path.trim_end_matches('/');
+        if trimmed.is_empty() { ".".to_string() } else { trimmed.to_string() }
+    }
+}
+
+/// [`SftpClient::list_dir_iter`] 默认每批返回的条目数
+pub const LIST_BATCH_SIZE: usize = 256;
+
+/// 将 SFTP 错误转换为带路径的错误，"文件不存在" 单独给出简洁的提示
+fn sftp_error(err: ssh2::Error, message: &str, remote_path: &str) -> anyhow::Error {
+    if err.code() == ssh2::ErrorCode::SFTP(FX_NO_SUCH_FILE) {
+        return anyhow::anyhow!("远程路径不存在: {}", remote_path);
+    }
+    anyhow::Error::new(err).context(format!("{}: {}", message, remote_path))
+}
+
+/// 传输统计信息
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransferStats {
+    /// 成功传输的文件数
+    pub files_transferred: u64,
+    /// 跳过的文件数
+    pub files_skipped: u64,
+    /// 传输的总字节数
+    pub total_bytes: u64,
+    /// 总耗时
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+impl TransferStats {
+    /// 平均吞吐量（字节/秒）
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.total_bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// 合并另一次传输的统计
+    pub fn merge(&mut self, other: &TransferStats) {
+        self.files_transferred += other.files_transferred;
+        self.files_skipped += other.files_skipped;
+        self.total_bytes += other.total_bytes;
+        self.elapsed += other.elapsed;
+    }
+
+    /// 单个文件传输完成时的统计
+    pub fn single_file(bytes: u64, elapsed: Duration) -> Self {
+        Self {
+            files_transferred: 1,
+            files_skipped: 0,
+            total_bytes: bytes,
+            elapsed,
+        }
+    }
+}
+
+fn serialize_secs<S: serde::Serializer>(d: &Duration, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
 }
 
 impl<'a> SftpClient<'a> {
@@ -34,225 +344,2026 @@ impl<'a> SftpClient<'a> {
         let sftp = ssh_client.session().sftp()
             .context("无法创建 SFTP 会话")?;
         
-        Ok(Self { sftp, ssh_client })
+        Ok(Self {
+            sftp,
+            ssh_client,
+            cancel: None,
+            file_mode: None,
+            dir_mode: DEFAULT_DIR_MODE,
+            append: false,
+            skip_existing: SkipExisting::Never,
+            apply_remote_perms: false,
+            retry: RETRY_POLICY.get().copied().unwrap_or_default(),
+            home: OnceLock::new(),
+            listing_cache: None,
+        })
+    }
+    
+    /// 使用目录列表缓存，同一会话中的多个客户端可以共用一个缓存
+    pub fn with_listing_cache(mut self, cache: Arc<ListingCache>) -> Self {
+        self.listing_cache = Some(cache);
+        self
+    }
+    
+    /// 修改了 `path` 后使相关的缓存列表失效
+    fn touched(&self, path: &str) {
+        if let Some(cache) = &self.listing_cache {
+            cache.invalidate(path);
+        }
+    }
+    
+    /// 设置取消标志（例如由 Ctrl+C 处理器设置）
+    pub fn with_cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+    
+    /// 上传完成后把文件权限设置为 `mode`
+    pub fn with_file_mode(mut self, mode: Option<u32>) -> Self {
+        self.file_mode = mode;
+        self
+    }
+    
+    /// 创建目录时使用的权限（默认 0755）
+    pub fn with_dir_mode(mut self, mode: Option<u32>) -> Self {
+        self.dir_mode = mode.unwrap_or(DEFAULT_DIR_MODE);
+        self
+    }
+    
+    /// 上传时追加到远程文件末尾（文件不存在时创建），重复上传会累积内容
+    pub fn with_append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+    
+    /// 远程已有相同的文件时跳过上传（追加模式下不生效）
+    pub fn with_skip_existing(mut self, skip: SkipExisting) -> Self {
+        self.skip_existing = skip;
+        self
+    }
+    
+    /// 下载后把远程文件的权限位应用到本地文件，而不是由本地 umask 决定
+    pub fn with_remote_perms(mut self, apply: bool) -> Self {
+        self.apply_remote_perms = apply;
+        self
+    }
+    
+    /// 覆盖 [`set_retry_policy`] 设置的重试策略
+    #[cfg(test)]
+    fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+    
+    /// 重试幂等操作，见 [`with_retry`]
+    fn retry<T>(&self, what: &str, op: impl FnMut(u32) -> Result<T, ssh2::Error>) -> Result<T, ssh2::Error> {
+        with_retry(self.retry, what, op)
+    }
+    
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|flag| flag.load(Ordering::SeqCst))
     }
     
-    /// 列出目录内容
+    /// 列出目录内容，按名称排序，目录在前；使用了列表缓存时优先返回未过期的缓存
     pub fn list_dir(&self, remote_path: &str) -> Result<Vec<FileInfo>> {
-        debug!("列出目录: {}", remote_path);
-        
-        let path = Path::new(remote_path);
-        let entries = self.sftp.readdir(path)
-            .context(format!("无法读取目录: {}", remote_path))?;
-        
+        if let Some(files) = self.listing_cache.as_ref().and_then(|cache| cache.get(remote_path)) {
+            debug!("使用缓存的目录列表: {}", remote_path);
+            return Ok(files);
+        }
+        self.refresh_dir(remote_path)
+    }
+    
+    /// 忽略列表缓存重新读取目录，并更新缓存
+    pub fn refresh_dir(&self, remote_path: &str) -> Result<Vec<FileInfo>> {
         let mut files = Vec::new();
-        
-        for (path, stat) in entries {
-            let name = path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-            
-            files.push(FileInfo {
-                name: name.clone(),
-                path: path.to_string_lossy().to_string(),
-                size: stat.size.unwrap_or(0),
-                is_dir: stat.is_dir(),
-                permissions: stat.perm.unwrap_or(0),
-            });
+        for batch in self.list_dir_iter(remote_path, LIST_BATCH_SIZE)? {
+            files.extend(batch?);
+        }
+        sort_entries(&mut files);
+        if let Some(cache) = &self.listing_cache {
+            cache.insert(remote_path, &files);
         }
-        
-        // 按名称排序，目录在前
-        files.sort_by(|a, b| {
-            match (a.is_dir, b.is_dir) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.name.cmp(&b.name),
-            }
-        });
-        
         Ok(files)
     }
     
-    /// 上传文件
-    pub fn upload_file(&self, local_path: &str, remote_path: &str, show_progress: bool) -> Result<()> {
-        info!("上传文件: {} -> {}", local_path, remote_path);
+    /// 分批读取目录内容，每批最多 `batch_size` 个条目（服务器返回的原始顺序）
+    ///
+    /// 大目录可以边读取边显示，而不必等待整个列表。设置了取消标志时在批次之间停止。
+    pub fn list_dir_iter(&self, remote_path: &str, batch_size: usize) -> Result<DirBatches<'_>> {
+        self.dir_batches(Path::new(remote_path), batch_size)
+    }
+    
+    fn dir_batches(&self, remote: &Path, batch_size: usize) -> Result<DirBatches<'_>> {
+        debug!("列出目录: {}", remote.display());
+        let dir = self.retry("opendir", |_| self.sftp.opendir(remote))
+            .map_err(|e| sftp_error(e, "无法读取目录", &remote.to_string_lossy()))?;
+        Ok(DirBatches {
+            client: self,
+            dir,
+            path: remote.to_path_buf(),
+            batch_size: batch_size.max(1),
+            done: false,
+        })
+    }
+    
+    /// 读取列表中符号链接的目标路径，并检查目标是否存在
+    ///
+    /// 每个链接需要额外两次往返，只在需要显示链接目标时调用（例如 `list --long`）。
+    pub fn resolve_links(&self, files: &mut [FileInfo]) {
+        for file in files.iter_mut().filter(|f| f.is_symlink) {
+            let path = file.raw_path.as_path();
+            match self.sftp.readlink(path) {
+                Ok(target) => file.link_target = Some(target.to_string_lossy().into_owned()),
+                Err(e) => debug!("无法读取链接目标 {}: {}", file.path, e),
+            }
+            file.link_broken = self.sftp.stat(path).is_err();
+        }
+    }
+    
+    /// 上传文件，`progress` 在开始、结束时以及传输过程中每隔约 100ms 调用一次
+    pub fn upload_file(&self, local_path: &str, remote_path: &str, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        self.upload_path(Path::new(local_path), Path::new(remote_path), progress)
+    }
+    
+    /// 与 [`upload_file`](Self::upload_file) 相同，路径按原始字节传递
+    fn upload_path(&self, local: &Path, remote: &Path, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let result = self.upload(local, remote, progress);
+        let remote_path = remote.to_string_lossy();
+        self.touched(&remote_path);
+        let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+        audit::record(self.audit_event(Operation::Upload).path(&local.to_string_lossy()).path(&remote_path).bytes(bytes), &result);
+        result
+    }
+    
+    /// 递归上传本地目录：`local_dir` 中的内容上传到 `remote_dir` 下并保留相对结构，缺少的远程目录
+    /// （包括空目录）逐级创建
+    ///
+    /// 符号链接按 `links` 处理；[`Links::Follow`] 时目标不存在或指向上级目录（跟随会无限循环）的链接记为错误，
+    /// 其余文件照常上传，结束后一并报告。开始前统计总大小，`progress` 报告所有文件的累计进度。
+    /// 任一文件失败时立即中止，错误信息中包含该文件的本地路径。
+    pub fn upload_dir(&self, local_dir: &str, remote_dir: &str, links: Links, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let local = Path::new(local_dir);
+        if !local.is_dir() {
+            anyhow::bail!("本地路径不是目录: {}", local_dir);
+        }
+        info!("上传目录: {} -> {}", local_dir, remote_dir);
+        let started = Instant::now();
+        let total = preflight::scan_local(local, links)?.bytes;
+        let mut tree = TransferTree::new(total, links, progress);
+        self.mkdir_p(remote_dir)?;
+        self.upload_tree(local, Path::new(remote_dir), &mut tree)?;
+        tree.finish(started)
+    }
+
+    /// 文件名按原始字节拼接到远程路径中，非 UTF-8 的文件名不会被替换成别的名字
+    fn upload_tree(&self, local: &Path, remote: &Path, tree: &mut TransferTree) -> Result<()> {
+        let mut entries = std::fs::read_dir(local)
+            .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+            .context(format!("无法读取目录: {}", local.display()))?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let target = remote_child(remote, &entry.file_name());
+            let file_type = entry.file_type().context(format!("无法读取文件信息: {}", path.display()))?;
+            if file_type.is_symlink() {
+                match tree.links {
+                    Links::Skip => {
+                        warn!("跳过符号链接: {}（可使用 --links preserve 或 follow）", path.display());
+                        continue;
+                    }
+                    Links::Preserve => {
+                        self.upload_link(&path, &target)?;
+                        continue;
+                    }
+                    Links::Follow if !path.exists() => {
+                        tree.failed_link(&path.display().to_string(), "目标不存在");
+                        continue;
+                    }
+                    Links::Follow if path.is_dir() && links_to_ancestor(&path, local) => {
+                        tree.failed_link(&path.display().to_string(), "指向上级目录，跟随会无限循环");
+                        continue;
+                    }
+                    Links::Follow => {}
+                }
+            }
+
+            if path.is_dir() {
+                self.tree_dir(&target)?;
+                self.upload_tree(&path, &target, tree)?;
+            } else {
+                let size = std::fs::metadata(&path).map_or(0, |m| m.len());
+                tree.transfer(size, |progress| self.upload_path(&path, &target, progress))
+                    .context(format!("上传 {} 失败", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 创建目录树中的一级远程目录，已存在时跳过
+    fn tree_dir(&self, remote: &Path) -> Result<()> {
+        let display = remote.to_string_lossy();
+        match self.retry("stat", |_| self.sftp.stat(remote)) {
+            Ok(stat) if stat.is_dir() => return Ok(()),
+            Ok(_) => anyhow::bail!("无法创建目录 {}: 已存在且不是目录", display),
+            Err(_) => {}
+        }
+        debug!("创建目录: {}", display);
+        let created = self.create_dir(remote);
+        self.touched(&display);
+        created.map_err(|e| sftp_error(e, "无法创建目录", &display))
+    }
+
+    /// 在远程重建本地符号链接，指向的路径原样保留（目标不存在也照样创建），替换远程已有的同名文件或链接
+    fn upload_link(&self, local: &Path, remote: &Path) -> Result<()> {
+        let display = remote.to_string_lossy();
+        let target = std::fs::read_link(local).context(format!("无法读取符号链接: {}", local.display()))?;
+        // Windows 的链接目标使用反斜杠分隔
+        #[cfg(not(unix))]
+        let target = PathBuf::from(target.to_string_lossy().replace('\\', "/"));
+        if let Ok(existing) = self.retry("lstat", |_| self.sftp.lstat(remote)) {
+            if existing.is_dir() {
+                anyhow::bail!("远程已存在同名目录: {}", display);
+            }
+            self.sftp.unlink(remote).map_err(|e| sftp_error(e, "无法删除文件", &display))?;
+        }
+        info!("创建符号链接: {} -> {}", display, target.display());
+        let result = self.sftp.symlink(&target, remote).map_err(|e| sftp_error(e, "无法创建符号链接", &display));
+        self.touched(&display);
+        result
+    }
+    
+    fn upload(&self, local: &Path, remote: &Path, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        info!("上传文件: {} -> {}", local.display(), remote.display());
+        let started = Instant::now();
         
-        let local = Path::new(local_path);
-        let remote = Path::new(remote_path);
+        if local.is_dir() {
+            anyhow::bail!("本地路径是目录: {}（只能上传单个文件）", local.display());
+        }
+        
+        // 远程路径是已存在的目录时，上传到该目录下的同名文件
+        let remote_target = match self.retry("stat", |_| self.sftp.stat(remote)) {
+            Ok(stat) if stat.is_dir() => {
+                let file_name = local.file_name()
+                    .context(format!("无法确定本地文件名: {}", local.display()))?;
+                let target = remote_child(remote, file_name);
+                info!("远程路径是目录，上传到: {}", target.display());
+                target
+            }
+            _ => remote.to_path_buf(),
+        };
+        let remote = remote_target.as_path();
+        let remote_path = &*remote.to_string_lossy();
         
         // 打开本地文件
         let mut local_file = File::open(local)
-            .context(format!("无法打开本地文件: {}", local_path))?;
+            .context(format!("无法打开本地文件: {}", local.display()))?;
         
         // 获取文件大小
         let file_size = local_file.metadata()?.len();
         
-        // 创建远程文件
-        let mut remote_file = self.sftp.create(remote)
-            .context(format!("无法创建远程文件: {}", remote_path))?;
-        
-        // 创建进度条
-        let pb = if show_progress {
-            let pb = ProgressBar::new(file_size);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-            pb.set_message(format!("上传: {}", local_path));
-            Some(pb)
+        if !self.append && self.remote_matches(local, remote, file_size)? {
+            info!("远程文件相同，跳过上传: {}", remote_path);
+            // 跳过的文件按已完成计入进度
+            if let Some(progress) = progress {
+                let _ = progress(TransferProgress { transferred: file_size, total: file_size, elapsed: started.elapsed() });
+            }
+            return Ok(TransferStats { files_skipped: 1, elapsed: started.elapsed(), ..Default::default() });
+        }
+        
+        // 创建远程文件（追加模式下打开已有文件）
+        let remote_file = if self.append {
+            self.open_append(remote)?
         } else {
-            None
+            self.sftp.create(remote)
+                .context(format!("无法创建远程文件: {}", remote_path))?
         };
+        let mut remote_file = Locked { inner: remote_file, ssh_client: self.ssh_client };
         
         // 传输文件
-        let mut buffer = vec![0u8; 8192];
-        let mut transferred = 0u64;
+        let copied = copy_with_progress(
+            &mut local_file,
+            &mut remote_file,
+            ("读取本地文件失败", "写入远程文件失败"),
+            file_size,
+            progress,
+            || self.is_cancelled(),
+        );
+        let transferred = match copied {
+            Ok(transferred) => transferred,
+            Err(e) => {
+                if e.downcast_ref::<Cancelled>().is_some() && !self.append {
+                    // 删除不完整的远程文件（追加模式下文件中还有之前的内容，保留）
+                    drop(remote_file);
+                    if let Err(e) = self.sftp.unlink(remote) {
+                        warn!("无法删除不完整的远程文件 {}: {}", remote_path, e);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        let mut remote_file = remote_file.inner;
         
-        loop {
-            let n = local_file.read(&mut buffer)
-                .context("读取本地文件失败")?;
-            
-            if n == 0 {
-                break;
+        if let Some(mode) = self.file_mode {
+            debug!("设置文件权限: {} {:o}", remote_path, mode);
+            remote_file.setstat(FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(mode),
+                atime: None,
+                mtime: None,
+            })
+            .map_err(|e| sftp_error(e, &format!("无法设置文件权限 {:o}", mode), remote_path))?;
+        }
+        
+        let stats = TransferStats::single_file(transferred, started.elapsed());
+        
+        if self.append {
+            let total = self.sftp.stat(remote)
+                .map_err(|e| sftp_error(e, "无法获取远程文件信息", remote_path))?
+                .size
+                .unwrap_or(0);
+            info!("已追加到远程文件: {} (追加 {} 字节，文件现为 {} 字节)", remote_path, transferred, total);
+        } else {
+            info!("文件上传成功: {} ({} 字节)", remote_path, transferred);
+        }
+        Ok(stats)
+    }
+    
+    /// 把 `reader` 的全部内容（如标准输入）写入远程文件，按原始字节传输
+    ///
+    /// 大小事先未知，进度回调中的 `total` 为 0。遵守 `with_append` 和 `with_file_mode`，
+    /// 不支持 `with_skip_existing`；远程路径不能是目录。
+    pub fn upload_stream(&self, reader: &mut dyn Read, remote_path: &str, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let result = self.upload_from(reader, remote_path, progress);
+        self.touched(remote_path);
+        let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+        audit::record(self.audit_event(Operation::Upload).path("-").path(remote_path).bytes(bytes), &result);
+        result
+    }
+    
+    fn upload_from(&self, reader: &mut dyn Read, remote_path: &str, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        info!("上传标准输入: {}", remote_path);
+        let started = Instant::now();
+        let remote = Path::new(remote_path);
+        
+        if let Ok(stat) = self.retry("stat", |_| self.sftp.stat(remote)) {
+            if stat.is_dir() {
+                anyhow::bail!("远程路径是目录: {}（从标准输入上传时需要指定文件名）", remote_path);
             }
-            
-            remote_file.write_all(&buffer[..n])
-                .context("写入远程文件失败")?;
-            
-            transferred += n as u64;
-            
-            if let Some(ref pb) = pb {
-                pb.set_position(transferred);
+        }
+        
+        let remote_file = if self.append {
+            self.open_append(remote)?
+        } else {
+            self.sftp.create(remote)
+                .map_err(|e| sftp_error(e, "无法创建远程文件", remote_path))?
+        };
+        let mut remote_file = Locked { inner: remote_file, ssh_client: self.ssh_client };
+        
+        let copied = copy_with_progress(
+            reader,
+            &mut remote_file,
+            ("读取标准输入失败", "写入远程文件失败"),
+            0,
+            progress,
+            || self.is_cancelled(),
+        );
+        let transferred = match copied {
+            Ok(transferred) => transferred,
+            Err(e) => {
+                // 中断时删除不完整的远程文件（追加模式下保留之前的内容）
+                if e.downcast_ref::<Cancelled>().is_some() && !self.append {
+                    drop(remote_file);
+                    if let Err(e) = self.sftp.unlink(remote) {
+                        warn!("无法删除不完整的远程文件 {}: {}", remote_path, e);
+                    }
+                }
+                return Err(e);
             }
+        };
+        
+        if let Some(mode) = self.file_mode {
+            debug!("设置文件权限: {} {:o}", remote_path, mode);
+            remote_file.inner.setstat(FileStat { size: None, uid: None, gid: None, perm: Some(mode), atime: None, mtime: None })
+                .map_err(|e| sftp_error(e, &format!("无法设置文件权限 {:o}", mode), remote_path))?;
         }
         
-        if let Some(pb) = pb {
-            pb.finish_with_message(format!("上传完成: {}", local_path));
+        info!("标准输入上传成功: {} ({} 字节)", remote_path, transferred);
+        Ok(TransferStats::single_file(transferred, started.elapsed()))
+    }
+    
+    /// 按 `skip_existing` 判断远程文件是否与本地文件相同，远程文件不存在时为 false
+    ///
+    /// 比较校验和时优先在远程执行命令计算，失败（没有 shell 或命令不存在）时通过 SFTP 读取文件计算。
+    fn remote_matches(&self, local: &Path, remote: &Path, size: u64) -> Result<bool> {
+        if self.skip_existing == SkipExisting::Never {
+            return Ok(false);
+        }
+        let Ok(stat) = self.retry("stat", |_| self.sftp.stat(remote)) else {
+            return Ok(false);
+        };
+        if stat.is_dir() || stat.size != Some(size) {
+            return Ok(false);
+        }
+        if self.skip_existing == SkipExisting::Size {
+            return Ok(true);
         }
         
-        info!("文件上传成功: {} ({} 字节)", remote_path, transferred);
-        Ok(())
+        let local_digest = sha256_local(local)?;
+        let remote_digest = remote_os::sha256_or_read(self.ssh_client, self, &remote.to_string_lossy())?;
+        debug!("校验和: 本地 {} 远程 {}", local_digest, remote_digest);
+        Ok(local_digest == remote_digest)
+    }
+    
+    /// 以追加方式打开远程文件，写入位置在文件末尾
+    ///
+    /// 部分服务器不遵守 SFTP 的 APPEND 标志（按写请求中的偏移量写入），因此同时把偏移量定位到文件末尾。
+    fn open_append(&self, remote: &Path) -> Result<ssh2::File> {
+        let display = remote.to_string_lossy();
+        let flags = OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE;
+        let mut file = self.sftp.open_mode(remote, flags, 0o644, OpenType::File)
+            .map_err(|e| sftp_error(e, "无法打开远程文件", &display))?;
+        file.seek(SeekFrom::End(0))
+            .context(format!("无法定位到远程文件末尾: {}", display))?;
+        Ok(file)
+    }
+    
+    /// 下载文件，`progress` 的调用方式与 [`upload_file`](Self::upload_file) 相同
+    pub fn download_file(&self, remote_path: &str, local_path: &str, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        self.download_path(Path::new(remote_path), Path::new(local_path), progress)
+    }
+    
+    /// 与 [`download_file`](Self::download_file) 相同，路径按原始字节传递（用于目录列表中的条目）
+    fn download_path(&self, remote: &Path, local: &Path, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let result = self.download(remote, local, progress);
+        let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+        audit::record(
+            self.audit_event(Operation::Download).path(&remote.to_string_lossy()).path(&local.to_string_lossy()).bytes(bytes),
+            &result,
+        );
+        result
     }
     
-    /// 下载文件
-    pub fn download_file(&self, remote_path: &str, local_path: &str, show_progress: bool) -> Result<()> {
+    fn download(&self, remote: &Path, local: &Path, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let remote_path = remote.to_string_lossy();
+        let local_path = local.to_string_lossy();
         info!("下载文件: {} -> {}", remote_path, local_path);
+        let started = Instant::now();
         
-        let remote = Path::new(remote_path);
-        let local = Path::new(local_path);
+        // 先检查远程路径，避免对目录读取时出现难以理解的底层错误
+        let stat = self.retry("stat", |_| self.sftp.stat(remote))
+            .map_err(|e| sftp_error(e, "无法获取远程文件信息", &remote_path))?;
+        if stat.is_dir() {
+            anyhow::bail!("远程路径是目录: {}（只能下载单个文件）", remote_path);
+        }
         
         // 打开远程文件
-        let mut remote_file = self.sftp.open(remote)
-            .context(format!("无法打开远程文件: {}", remote_path))?;
+        let mut remote_file = self.retry("open", |_| self.sftp.open(remote))
+            .map_err(|e| sftp_error(e, "无法打开远程文件", &remote_path))?;
         
         // 获取文件大小
         let file_size = remote_file.stat()?.size.unwrap_or(0);
+        let mut remote_file = Locked { inner: remote_file, ssh_client: self.ssh_client };
         
         // 创建本地文件
         let mut local_file = File::create(local)
             .context(format!("无法创建本地文件: {}", local_path))?;
         
-        // 创建进度条
-        let pb = if show_progress {
-            let pb = ProgressBar::new(file_size);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-            pb.set_message(format!("下载: {}", remote_path));
-            Some(pb)
-        } else {
-            None
+        // 传输文件
+        let copied = copy_with_progress(
+            &mut remote_file,
+            &mut local_file,
+            ("读取远程文件失败", "写入本地文件失败"),
+            file_size,
+            progress,
+            || self.is_cancelled(),
+        );
+        let transferred = match copied {
+            Ok(transferred) => transferred,
+            Err(e) => {
+                if e.downcast_ref::<Cancelled>().is_some() {
+                    // 删除不完整的本地文件
+                    drop(local_file);
+                    if let Err(e) = std::fs::remove_file(local) {
+                        warn!("无法删除不完整的本地文件 {}: {}", local_path, e);
+                    }
+                }
+                return Err(e);
+            }
         };
         
-        // 传输文件
-        let mut buffer = vec![0u8; 8192];
-        let mut transferred = 0u64;
+        if let Some(perm) = stat.perm.filter(|_| self.apply_remote_perms) {
+            drop(local_file);
+            set_local_mode(local, perm)?;
+        }
         
-        loop {
-            let n = remote_file.read(&mut buffer)
-                .context("读取远程文件失败")?;
-            
-            if n == 0 {
-                break;
+        let stats = TransferStats::single_file(transferred, started.elapsed());
+        
+        info!("文件下载成功: {} ({} 字节)", local_path, transferred);
+        Ok(stats)
+    }
+    
+    /// 递归下载远程目录：`remote_dir` 中的内容下载到 `local_dir` 下并保留相对结构，缺少的本地目录
+    /// （包括空目录）逐级创建
+    ///
+    /// 符号链接的处理与 [`upload_dir`](Self::upload_dir) 相同；[`Links::Preserve`] 用
+    /// `std::os::unix::fs::symlink` 在本地重建链接，Windows 上改为跳过并给出提示。文件名按服务器返回的
+    /// 原始字节使用。任一文件失败时立即中止，错误信息中包含该文件的远程路径。
+    ///
+    /// 提供 `manifest` 时把每个已下载文件的远程元数据记入清单（中止时保留已下载部分的条目）。
+    pub fn download_dir(
+        &self,
+        remote_dir: &str,
+        local_dir: &str,
+        links: Links,
+        manifest: Option<&mut Manifest>,
+        progress: Option<ProgressCallback<'_>>,
+    ) -> Result<TransferStats> {
+        if !self.stat(remote_dir)?.is_dir {
+            anyhow::bail!("远程路径不是目录: {}", remote_dir);
+        }
+        info!("下载目录: {} -> {}", remote_dir, local_dir);
+        let started = Instant::now();
+        let total = preflight::scan_remote(self, remote_dir, links)?.bytes;
+        let mut tree = TransferTree::new(total, links, progress);
+        std::fs::create_dir_all(local_dir).context(format!("无法创建本地目录: {}", local_dir))?;
+        self.download_tree(Path::new(remote_dir), Path::new(local_dir), &mut tree, manifest)?;
+        tree.finish(started)
+    }
+    
+    fn download_tree(&self, remote: &Path, local: &Path, tree: &mut TransferTree, mut manifest: Option<&mut Manifest>) -> Result<()> {
+        let mut entries = Vec::new();
+        for batch in self.dir_batches(remote, LIST_BATCH_SIZE)? {
+            entries.extend(batch?);
+        }
+        entries.sort_by(|a, b| a.raw_path.cmp(&b.raw_path));
+        
+        for entry in entries {
+            let Some(name) = entry.raw_path.file_name() else {
+                continue;
+            };
+            let source = entry.raw_path.as_path();
+            let target = local.join(name);
+            let (mut is_dir, mut size) = (entry.is_dir, entry.size);
+            let mut followed = None;
+            if entry.is_symlink {
+                match tree.links {
+                    Links::Skip => {
+                        warn!("跳过符号链接: {}（可使用 --links preserve 或 follow）", entry.path);
+                        continue;
+                    }
+                    Links::Preserve => {
+                        self.download_link(source, &target)?;
+                        continue;
+                    }
+                    Links::Follow => match self.retry("stat", |_| self.sftp.stat(source)) {
+                        Err(_) => {
+                            tree.failed_link(&entry.path, "目标不存在");
+                            continue;
+                        }
+                        Ok(stat) if stat.is_dir() && self.is_ancestor_link(source, remote) => {
+                            tree.failed_link(&entry.path, "指向上级目录，跟随会无限循环");
+                            continue;
+                        }
+                        Ok(stat) => {
+                            (is_dir, size) = (stat.is_dir(), stat.size.unwrap_or(0));
+                            followed = Some(file_info(entry.raw_path.clone(), &stat));
+                        }
+                    },
+                }
             }
             
-            local_file.write_all(&buffer[..n])
-                .context("写入本地文件失败")?;
-            
-            transferred += n as u64;
-            
-            if let Some(ref pb) = pb {
-                pb.set_position(transferred);
+            if is_dir {
+                std::fs::create_dir_all(&target).context(format!("无法创建本地目录: {}", target.display()))?;
+                self.download_tree(source, &target, tree, manifest.as_deref_mut())?;
+            } else {
+                tree.transfer(size, |progress| self.download_path(source, &target, progress))
+                    .context(format!("下载 {} 失败", entry.path))?;
+                if let Some(manifest) = manifest.as_deref_mut() {
+                    // 跟随的链接记录链接目标的元数据
+                    let info = followed.as_ref().unwrap_or(&entry);
+                    manifest.record_local(&target, info, self.apply_remote_perms && cfg!(unix))?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// 指向目录的远程链接 `link` 是否指向 `dir` 本身或它的上级目录（跟随会无限循环）
+    pub(crate) fn is_ancestor_link(&self, link: &Path, dir: &Path) -> bool {
+        let resolve = |path| self.retry("realpath", |_| self.sftp.realpath(path));
+        matches!((resolve(link), resolve(dir)), (Ok(link), Ok(dir)) if dir.starts_with(link))
+    }
+    
+    /// 在本地重建远程符号链接，指向的路径原样保留，替换本地已有的同名文件或链接
+    #[cfg(unix)]
+    fn download_link(&self, remote: &Path, local: &Path) -> Result<()> {
+        let target = self.retry("readlink", |_| self.sftp.readlink(remote))
+            .map_err(|e| sftp_error(e, "无法读取符号链接", &remote.to_string_lossy()))?;
+        if let Ok(existing) = std::fs::symlink_metadata(local) {
+            if existing.is_dir() {
+                anyhow::bail!("本地已存在同名目录: {}", local.display());
             }
+            std::fs::remove_file(local).context(format!("无法替换本地文件: {}", local.display()))?;
         }
+        std::os::unix::fs::symlink(&target, local).context(format!("无法创建符号链接: {}", local.display()))
+    }
+    
+    /// Windows 上创建符号链接需要额外权限，不重建
+    #[cfg(not(unix))]
+    fn download_link(&self, remote: &Path, _local: &Path) -> Result<()> {
+        warn!("跳过符号链接: {}（Windows 上不支持在本地重建链接）", remote.display());
+        Ok(())
+    }
+    
+    /// 把远程文件按原始字节写入 `writer`（如标准输出），结束时刷新 `writer`
+    pub fn download_stream(&self, remote_path: &str, writer: &mut dyn Write, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        let result = self.download_to(remote_path, writer, progress);
+        let bytes = result.as_ref().ok().map(|stats| stats.total_bytes);
+        audit::record(self.audit_event(Operation::Download).path(remote_path).path("-").bytes(bytes), &result);
+        result
+    }
+    
+    fn download_to(&self, remote_path: &str, writer: &mut dyn Write, progress: Option<ProgressCallback<'_>>) -> Result<TransferStats> {
+        info!("下载到标准输出: {}", remote_path);
+        let started = Instant::now();
+        let remote = Path::new(remote_path);
         
-        if let Some(pb) = pb {
-            pb.finish_with_message(format!("下载完成: {}", local_path));
+        let stat = self.retry("stat", |_| self.sftp.stat(remote))
+            .map_err(|e| sftp_error(e, "无法获取远程文件信息", remote_path))?;
+        if stat.is_dir() {
+            anyhow::bail!("远程路径是目录: {}（只能下载单个文件）", remote_path);
         }
+        let remote_file = self.retry("open", |_| self.sftp.open(remote))
+            .map_err(|e| sftp_error(e, "无法打开远程文件", remote_path))?;
+        let mut remote_file = Locked { inner: remote_file, ssh_client: self.ssh_client };
         
-        info!("文件下载成功: {} ({} 字节)", local_path, transferred);
-        Ok(())
+        let transferred = copy_with_progress(
+            &mut remote_file,
+            writer,
+            ("读取远程文件失败", "写入标准输出失败"),
+            stat.size.unwrap_or(0),
+            progress,
+            || self.is_cancelled(),
+        )?;
+        writer.flush().context("写入标准输出失败")?;
+        
+        info!("文件已输出到标准输出: {} ({} 字节)", remote_path, transferred);
+        Ok(TransferStats::single_file(transferred, started.elapsed()))
+    }
+    
+    /// 打开远程文件用于流式读取，数据不落地
+    pub fn open_reader(&self, remote_path: &Path) -> Result<impl Read + Seek + '_> {
+        let file = self.retry("open", |_| self.sftp.open(remote_path))
+            .map_err(|e| sftp_error(e, "无法打开远程文件", &remote_path.to_string_lossy()))?;
+        Ok(Locked { inner: file, ssh_client: self.ssh_client })
+    }
+    
+    /// 创建（或截断）远程文件用于流式写入，创建后设置 `with_file_mode` 指定的权限
+    pub fn create_writer(&self, remote_path: &str) -> Result<impl Write + '_> {
+        let remote = Path::new(remote_path);
+        let mut file = self.sftp.create(remote)
+            .map_err(|e| sftp_error(e, "无法创建远程文件", remote_path))?;
+        self.touched(remote_path);
+        if let Some(mode) = self.file_mode {
+            debug!("设置文件权限: {} {:o}", remote_path, mode);
+            file.setstat(FileStat { size: None, uid: None, gid: None, perm: Some(mode), atime: None, mtime: None })
+                .map_err(|e| sftp_error(e, &format!("无法设置文件权限 {:o}", mode), remote_path))?;
+        }
+        Ok(Locked { inner: file, ssh_client: self.ssh_client })
+    }
+    
+    /// 打开已存在的远程文件用于在任意位置写入（不截断），供分块并行上传
+    pub fn open_writer(&self, remote_path: &str) -> Result<impl Write + Seek + '_> {
+        let file = self.sftp.open_mode(Path::new(remote_path), OpenFlags::WRITE, 0o644, OpenType::File)
+            .map_err(|e| sftp_error(e, "无法打开远程文件", remote_path))?;
+        Ok(Locked { inner: file, ssh_client: self.ssh_client })
+    }
+    
+    /// 把远程文件截断或扩展到 `size` 字节
+    pub fn set_size(&self, remote_path: &str, size: u64) -> Result<()> {
+        let _guard = self.ssh_client.lock_io();
+        let stat = || FileStat { size: Some(size), uid: None, gid: None, perm: None, atime: None, mtime: None };
+        let result = self.retry("setstat", |_| self.sftp.setstat(Path::new(remote_path), stat()))
+            .map_err(|e| sftp_error(e, "无法设置文件大小", remote_path));
+        self.touched(remote_path);
+        result
+    }
+    
+    /// 路径所在文件系统的总空间和可用空间（需要服务器支持 statvfs@openssh.com 扩展）
+    pub fn statvfs(&self, remote_path: &str) -> Result<DiskFree> {
+        let _io = self.ssh_client.lock_io();
+        let mut dir = self.sftp.opendir(Path::new(remote_path))
+            .map_err(|e| sftp_error(e, "无法打开远程目录", remote_path))?;
+        let stat = dir.statvfs()
+            .map_err(|e| sftp_error(e, "无法获取文件系统信息", remote_path))?;
+        Ok(DiskFree {
+            total: stat.f_blocks * stat.f_frsize,
+            available: stat.f_bavail * stat.f_frsize,
+        })
     }
     
     /// 创建目录
     pub fn mkdir(&self, remote_path: &str) -> Result<()> {
         info!("创建目录: {}", remote_path);
-        self.sftp.mkdir(Path::new(remote_path), 0o755)
+        self.create_dir(Path::new(remote_path))
             .context(format!("无法创建目录: {}", remote_path))?;
+        self.touched(remote_path);
         Ok(())
     }
     
-    /// 删除文件
-    pub fn remove_file(&self, remote_path: &str) -> Result<()> {
-        info!("删除文件: {}", remote_path);
-        self.sftp.unlink(Path::new(remote_path))
-            .context(format!("无法删除文件: {}", remote_path))?;
-        Ok(())
+    /// 创建目录并在临时失败时重试；重试时目录已存在说明之前的请求其实已经成功
+    fn create_dir(&self, path: &Path) -> Result<(), ssh2::Error> {
+        self.retry("mkdir", |attempt| match self.sftp.mkdir(path, self.dir_mode as i32) {
+            Err(_) if attempt > 1 && self.sftp.stat(path).is_ok_and(|s| s.is_dir()) => Ok(()),
+            result => result,
+        })
     }
     
-    /// 删除目录
-    #[allow(dead_code)]
-    pub fn remove_dir(&self, remote_path: &str) -> Result<()> {
-        info!("删除目录: {}", remote_path);
-        self.sftp.rmdir(Path::new(remote_path))
-            .context(format!("无法删除目录: {}", remote_path))?;
-        Ok(())
+    /// 逐级创建目录（类似 `mkdir -p`），已存在的目录跳过
+    pub fn mkdir_p(&self, remote_path: &str) -> Result<()> {
+        let mut current = if remote_path.starts_with('/') { "/".to_string() } else { String::new() };
+        
+        for component in remote_path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if !current.is_empty() && !current.ends_with('/') {
+                current.push('/');
+            }
+            current.push_str(component);
+            
+            match self.retry("stat", |_| self.sftp.stat(Path::new(&current))) {
+                Ok(stat) if stat.is_dir() => continue,
+                Ok(_) => anyhow::bail!("无法创建目录 {}: {} 已存在且不是目录", remote_path, current),
+                Err(_) => {}
+            }
+            
+            debug!("创建目录: {}", current);
+            let created = self.create_dir(Path::new(&current));
+            self.touched(&current);
+            if let Err(e) = created {
+                // 可能已被其他进程同时创建
+                let created = self.sftp.stat(Path::new(&current)).map(|s| s.is_dir()).unwrap_or(false);
+                if !created {
+                    return Err(e).context(format!("无法创建目录 {}: 创建 {} 失败", remote_path, current));
+                }
+            }
+        }
+        
+        Ok(())
     }
     
-    /// 重命名文件或目录
+    /// 删除文件
+    pub fn remove_file(&self, remote_path: &str) -> Result<()> {
+        info!("删除文件: {}", remote_path);
+        let result = self.sftp.unlink(Path::new(remote_path))
+            .map_err(|e| sftp_error(e, "无法删除文件", remote_path));
+        self.touched(remote_path);
+        audit::record(self.audit_event(Operation::Remove).path(remote_path), &result);
+        result
+    }
+    
+    /// 删除目录
     #[allow(dead_code)]
+    pub fn remove_dir(&self, remote_path: &str) -> Result<()> {
+        info!("删除目录: {}", remote_path);
+        let result = self.sftp.rmdir(Path::new(remote_path))
+            .map_err(|e| sftp_error(e, "无法删除目录", remote_path));
+        self.touched(remote_path);
+        audit::record(self.audit_event(Operation::Remove).path(remote_path), &result);
+        result
+    }
+    
+    /// 重命名文件或目录
     pub fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
         info!("重命名: {} -> {}", old_path, new_path);
-        self.sftp.rename(Path::new(old_path), Path::new(new_path), None)
-            .context(format!("无法重命名: {} -> {}", old_path, new_path))?;
-        Ok(())
+        let result = self.sftp.rename(Path::new(old_path), Path::new(new_path), None)
+            .context(format!("无法重命名: {} -> {}", old_path, new_path));
+        self.touched(old_path);
+        self.touched(new_path);
+        audit::record(self.audit_event(Operation::Rename).path(old_path).path(new_path), &result);
+        result
+    }
+    
+    /// 本连接上一次操作的审计记录
+    pub(crate) fn audit_event(&self, operation: Operation) -> audit::Event {
+        audit::Event::for_config(operation, self.ssh_client.config())
+    }
+    
+    /// 审计记录中表示本连接上路径的 `user@host:path`
+    pub(crate) fn audit_location(&self, path: &str) -> String {
+        let config = self.ssh_client.config();
+        format!("{}@{}:{}", config.username, config.host, path)
+    }
+    
+    /// 服务器解析后的绝对路径（相对路径按登录目录解析，`..` 和符号链接已展开）
+    pub fn realpath(&self, remote_path: &str) -> Result<String> {
+        let resolved = self.retry("realpath", |_| self.sftp.realpath(Path::new(remote_path)))
+            .map_err(|e| sftp_error(e, "无法解析路径", remote_path))?;
+        Ok(resolved.to_string_lossy().into_owned())
+    }
+    
+    /// 登录目录（`.` 的 realpath），每个会话只查询一次
+    pub fn home_dir(&self) -> Result<String> {
+        if let Some(home) = self.home.get() {
+            return Ok(home.clone());
+        }
+        let home = self.realpath(".")?;
+        Ok(self.home.get_or_init(|| home).clone())
+    }
+    
+    /// 把远程路径解析为绝对路径，相对路径以 `cwd`（未指定时为登录目录）为基准，见 [`resolve_remote_path`]
+    pub fn resolve_path(&self, input: &str, cwd: Option<&str>) -> Result<String> {
+        let home = self.home_dir()?;
+        resolve_remote_path(input, cwd.unwrap_or(&home), &home)
+    }
+    
+    /// 设置远程文件的权限位和修改时间（访问时间同时设为修改时间），None 的字段保持不变
+    pub fn set_metadata(&self, remote_path: &str, mode: Option<u32>, mtime: Option<u64>) -> Result<()> {
+        debug!("设置文件属性: {} mode={:?} mtime={:?}", remote_path, mode.map(|m| format!("{:o}", m)), mtime);
+        let _guard = self.ssh_client.lock_io();
+        let stat = || FileStat { size: None, uid: None, gid: None, perm: mode, atime: mtime, mtime };
+        let result = self.retry("setstat", |_| self.sftp.setstat(Path::new(remote_path), stat()))
+            .map_err(|e| sftp_error(e, "无法设置文件属性", remote_path));
+        self.touched(remote_path);
+        result
     }
     
     /// 获取文件信息
-    #[allow(dead_code)]
     pub fn stat(&self, remote_path: &str) -> Result<FileInfo> {
         let path = Path::new(remote_path);
-        let stat = self.sftp.stat(path)
-            .context(format!("无法获取文件信息: {}", remote_path))?;
-        
-        let name = path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
-        
-        Ok(FileInfo {
-            name,
-            path: remote_path.to_string(),
-            size: stat.size.unwrap_or(0),
-            is_dir: stat.is_dir(),
-            permissions: stat.perm.unwrap_or(0),
-        })
+        let stat = self.retry("stat", |_| self.sftp.stat(path))
+            .map_err(|e| sftp_error(e, "无法获取文件信息", remote_path))?;
+        
+        Ok(file_info(PathBuf::from(remote_path), &stat))
+    }
+    
+    /// 获取文件信息，不跟随符号链接
+    pub fn lstat(&self, remote_path: &str) -> Result<FileInfo> {
+        let path = Path::new(remote_path);
+        let stat = self.retry("lstat", |_| self.sftp.lstat(path))
+            .map_err(|e| sftp_error(e, "无法获取文件信息", remote_path))?;
+        
+        Ok(file_info(PathBuf::from(remote_path), &stat))
+    }
+    
+    /// 读取符号链接指向的路径（不解析）
+    pub fn read_link(&self, remote_path: &str) -> Result<String> {
+        let target = self.retry("readlink", |_| self.sftp.readlink(Path::new(remote_path)))
+            .map_err(|e| sftp_error(e, "无法读取符号链接", remote_path))?;
+        Ok(target.to_string_lossy().into_owned())
+    }
+    
+    /// 在 `link_path` 创建指向 `target` 的符号链接，`target` 原样保存
+    pub fn symlink(&self, target: &str, link_path: &str) -> Result<()> {
+        info!("创建符号链接: {} -> {}", link_path, target);
+        let result = self.sftp.symlink(Path::new(target), Path::new(link_path))
+            .map_err(|e| sftp_error(e, "无法创建符号链接", link_path));
+        self.touched(link_path);
+        result
+    }
+}
+
+/// 远程目录 `dir` 下名为 `name` 的路径
+///
+/// Unix 上按原始字节拼接，非 UTF-8 的文件名原样发给服务器；远程路径总是用 `/` 分隔，不能用 [`Path::join`]
+/// （Windows 上会插入 `\`）。
+#[cfg(unix)]
+fn remote_child(dir: &Path, name: &OsStr) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let mut bytes = dir.as_os_str().as_bytes().to_vec();
+    while bytes.len() > 1 && bytes.ends_with(b"/") {
+        bytes.pop();
+    }
+    if !bytes.is_empty() && !bytes.ends_with(b"/") {
+        bytes.push(b'/');
+    }
+    bytes.extend_from_slice(name.as_bytes());
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn remote_child(dir: &Path, name: &OsStr) -> PathBuf {
+    let dir = dir.to_string_lossy();
+    PathBuf::from(format!("{}/{}", dir.trim_end_matches('/'), name.to_string_lossy()))
+}
+
+/// 指向目录的本地链接 `link` 是否指向 `dir` 本身或它的上级目录（跟随会无限循环）
+pub(crate) fn links_to_ancestor(link: &Path, dir: &Path) -> bool {
+    matches!((link.canonicalize(), dir.canonicalize()), (Ok(link), Ok(dir)) if dir.starts_with(link))
+}
+
+/// 流式计算本地文件的 SHA-256
+pub fn sha256_local(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("无法打开本地文件: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).context(format!("读取本地文件失败: {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 设置本地文件或目录的权限位（`mode` 中的文件类型位被忽略），Windows 上只记录日志
+pub fn set_local_mode(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        debug!("设置本地权限: {} {:o}", path.display(), mode & 0o7777);
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+            .context(format!("无法设置本地权限: {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    debug!("本地系统不支持 Unix 权限，忽略 {:o}: {}", mode & 0o7777, path.display());
+    Ok(())
+}
+
+/// 每次读写时持有会话锁的远程文件，避免与保活包交错
+struct Locked<'a, T> {
+    inner: T,
+    ssh_client: &'a SshClient,
+}
+
+impl<T: Read> Read for Locked<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let _guard = self.ssh_client.lock_io();
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Seek> Seek for Locked<'_, T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let _guard = self.ssh_client.lock_io();
+        self.inner.seek(pos)
+    }
+}
+
+impl<T: Write> Write for Locked<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let _guard = self.ssh_client.lock_io();
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let _guard = self.ssh_client.lock_io();
+        self.inner.flush()
     }
 }
 
+/// 递归传输的状态：把单个文件的进度换算为整个目录的累计进度，并收集无法跟随的符号链接
+struct TransferTree<'p> {
+    total: u64,
+    /// 已处理完（传输或跳过）的文件的总字节数
+    done: u64,
+    stats: TransferStats,
+    progress: Option<ProgressCallback<'p>>,
+    links: Links,
+    /// 未能跟随的符号链接及原因
+    failed_links: Vec<String>,
+}
+
+impl<'p> TransferTree<'p> {
+    fn new(total: u64, links: Links, progress: Option<ProgressCallback<'p>>) -> Self {
+        TransferTree { total, done: 0, stats: TransferStats::default(), progress, links, failed_links: Vec::new() }
+    }
+    
+    /// 传输一个大小为 `size` 的文件，进度换算为整棵树的累计值
+    fn transfer(&mut self, size: u64, transfer: impl FnOnce(Option<ProgressCallback<'_>>) -> Result<TransferStats>) -> Result<()> {
+        let (done, total) = (self.done, self.total);
+        let stats = match self.progress.as_deref_mut() {
+            Some(progress) => {
+                let mut file_progress = |p: TransferProgress| {
+                    progress(TransferProgress { transferred: done + p.transferred, total: total.max(done + p.total), ..p })
+                };
+                transfer(Some(&mut file_progress))
+            }
+            None => transfer(None),
+        }?;
+        self.done += size;
+        self.stats.merge(&stats);
+        Ok(())
+    }
+    
+    /// 记录无法跟随的符号链接，不中止其余文件的传输
+    fn failed_link(&mut self, path: &str, reason: &str) {
+        warn!("无法跟随符号链接 {}: {}", path, reason);
+        self.failed_links.push(format!("{}（{}）", path, reason));
+    }
+    
+    /// 结束统计；有无法跟随的符号链接时返回错误并列出它们
+    fn finish(mut self, started: Instant) -> Result<TransferStats> {
+        self.stats.elapsed = started.elapsed();
+        if !self.failed_links.is_empty() {
+            anyhow::bail!("{} 个符号链接无法跟随，已跳过: {}", self.failed_links.len(), self.failed_links.join(", "));
+        }
+        Ok(self.stats)
+    }
+}
+
+/// 复制数据并报告进度，返回复制的字节数
+///
+/// 每次读取前检查 `cancelled`；它返回 true 或进度回调返回 `Break` 时停止并返回 [`Cancelled`]。
+/// `errors` 为读、写失败时的错误信息。
+pub(crate) fn copy_with_progress(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    errors: (&str, &str),
+    total: u64,
+    mut progress: Option<ProgressCallback<'_>>,
+    cancelled: impl Fn() -> bool,
+) -> Result<u64> {
+    let started = Instant::now();
+    let mut throttle = Throttle::new(PROGRESS_INTERVAL);
+    let mut report = |transferred: u64, force: bool| -> ControlFlow<()> {
+        let Some(callback) = progress.as_mut() else {
+            return ControlFlow::Continue(());
+        };
+        if !throttle.ready(Instant::now()) && !force {
+            return ControlFlow::Continue(());
+        }
+        callback(TransferProgress { transferred, total, elapsed: started.elapsed() })
+    };
+
+    let mut buffer = vec![0u8; 8192];
+    let mut transferred = 0u64;
+    if report(0, true).is_break() {
+        return Err(Cancelled.into());
+    }
+
+    loop {
+        if cancelled() {
+            return Err(Cancelled.into());
+        }
+
+        let n = reader.read(&mut buffer).context(errors.0.to_string())?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n]).context(errors.1.to_string())?;
+        transferred += n as u64;
+
+        if report(transferred, false).is_break() {
+            return Err(Cancelled.into());
+        }
+    }
+
+    // 最后一次回调总是报告准确的字节数
+    if report(transferred, true).is_break() {
+        return Err(Cancelled.into());
+    }
+    Ok(transferred)
+}
+
+/// 目录的分批读取器，见 [`SftpClient::list_dir_iter`]
+pub struct DirBatches<'c> {
+    client: &'c SftpClient<'c>,
+    dir: ssh2::File,
+    path: PathBuf,
+    batch_size: usize,
+    done: bool,
+}
+
+impl Iterator for DirBatches<'_> {
+    type Item = Result<Vec<FileInfo>>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.client.is_cancelled() {
+            self.done = true;
+            return Some(Err(Cancelled.into()));
+        }
+        
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            match self.client.retry("readdir", |_| self.dir.readdir()) {
+                Ok((name, stat)) => {
+                    if name == Path::new(".") || name == Path::new("..") {
+                        continue;
+                    }
+                    batch.push(file_info(remote_child(&self.path, name.as_os_str()), &stat));
+                }
+                Err(e) if e.code() == ssh2::ErrorCode::Session(ERROR_FILE) => {
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(sftp_error(e, "无法读取目录", &self.path.to_string_lossy())));
+                }
+            }
+        }
+        
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
+fn file_info(path: PathBuf, stat: &FileStat) -> FileInfo {
+    FileInfo {
+        name: path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned()),
+        path: path.to_string_lossy().to_string(),
+        size: stat.size.unwrap_or(0),
+        is_dir: stat.is_dir(),
+        permissions: stat.perm.unwrap_or(0),
+        is_symlink: stat.file_type().is_symlink(),
+        link_target: None,
+        link_broken: false,
+        modified: stat.mtime,
+        uid: stat.uid,
+        gid: stat.gid,
+        raw_path: path,
+    }
+}
+
+/// 转义文件名中的控制字符（类似 `ls -b`），防止终端执行其中的转义序列
+pub fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 按名称排序，目录在前
+fn sort_entries(files: &mut [FileInfo]) {
+    files.sort_by(|a, b| {
+        match (a.is_dir, b.is_dir) {
+            (true, false) => std::cmp::Ordering::Less,
+            (false, true) => std::cmp::Ordering::Greater,
+            _ => a.name.cmp(&b.name),
+        }
+    });
+}
+
+/// 远程文件所在的目录，没有目录部分（上传到当前目录）或为根目录时返回 None
+pub fn remote_parent(remote_path: &str) -> Option<&str> {
+    let trimmed = remote_path.trim_end_matches('/');
+    let (parent, _) = trimmed.rsplit_once('/')?;
+    let parent = parent.trim_end_matches('/');
+    if parent.is_empty() || parent == "." {
+        return None;
+    }
+    Some(parent)
+}
+
+/// 把用户输入的远程路径解析为绝对路径：开头的 `~` 展开为登录目录 `home`，相对路径放到 `cwd` 下，
+/// 再规范化 `.` 和 `..`
+///
+/// 只做字符串处理，不访问服务器，也不解析符号链接。不支持 `~user`；远程路径中的反斜杠按普通字符处理
+/// （不当作目录分隔符），同时给出警告。
+pub fn resolve_remote_path(input: &str, cwd: &str, home: &str) -> Result<String> {
+    if input.contains('\\') {
+        warn!("远程路径中的反斜杠按普通字符处理，不是目录分隔符（应使用 /）: {}", input);
+    }
+    let (base, rest) = if input == "~" {
+        (home, "")
+    } else if let Some(rest) = input.strip_prefix("~/") {
+        (home, rest)
+    } else if input.starts_with('~') {
+        anyhow::bail!("不支持 ~用户名 形式的远程路径: {}（请使用绝对路径）", input);
+    } else if input.starts_with('/') {
+        ("/", input.trim_start_matches('/'))
+    } else {
+        (cwd, input)
+    };
+    Ok(join_remote(base, rest))
+}
+
+/// 把相对路径放到工作目录 `cwd` 下并规范化 `.` 和 `..`（超出根目录的 `..` 被忽略），绝对路径原样返回
+pub fn join_remote(cwd: &str, remote_path: &str) -> String {
+    if remote_path.starts_with('/') {
+        return remote_path.to_string();
+    }
+    let mut parts: Vec<&str> = Vec::new();
+    for part in cwd.split('/').chain(remote_path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            other => parts.push(other),
+        }
+    }
+    let joined = format!("/{}", parts.join("/"));
+    // 保留表示目录的结尾 `/`（上传到目录时有意义）
+    if remote_path.ends_with('/') && joined != "/" {
+        format!("{}/", joined)
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_transfer_stats_merge_and_throughput() {
+        let mut stats = TransferStats::single_file(1024, Duration::from_secs(1));
+        stats.merge(&TransferStats::single_file(3072, Duration::from_secs(1)));
+
+        assert_eq!(stats.files_transferred, 2);
+        assert_eq!(stats.total_bytes, 4096);
+        assert_eq!(stats.throughput(), 2048.0);
+        assert_eq!(TransferStats::default().throughput(), 0.0);
+    }
+
+    #[test]
+    fn test_upload_download_round_trip() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+
+        // 多兆字节文件，覆盖多次读写循环
+        let data: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+        let src = local.path().join("src.bin");
+        std::fs::write(&src, &data).unwrap();
+
+        let stats = sftp.upload_file(src.to_str().unwrap(), "/upload.bin", None).unwrap();
+        assert_eq!(stats.total_bytes, data.len() as u64);
+        assert_eq!(std::fs::read(server.local_path("/upload.bin")).unwrap(), data);
+
+        let dst = local.path().join("dst.bin");
+        let stats = sftp.download_file("/upload.bin", dst.to_str().unwrap(), None).unwrap();
+        assert_eq!(stats.files_transferred, 1);
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+    }
+
+    #[test]
+    fn test_stream_round_trip_is_binary_safe() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+
+        // 包含无效 UTF-8、NUL 和 CRLF，且跨越多个读写块
+        let mut data: Vec<u8> = vec![0xff, 0xfe, 0x00, b'\r', b'\n', 0xc3, 0x28];
+        data.extend((0..100_000u32).map(|i| (i % 256) as u8));
+
+        let mut totals = Vec::new();
+        let stats = sftp
+            .upload_stream(&mut data.as_slice(), "/stdin.bin", Some(&mut |p| {
+                totals.push(p.total);
+                ControlFlow::Continue(())
+            }))
+            .unwrap();
+        assert_eq!(stats.total_bytes, data.len() as u64);
+        assert!(totals.iter().all(|&total| total == 0));
+        assert_eq!(std::fs::read(server.local_path("/stdin.bin")).unwrap(), data);
+
+        let mut out = Vec::new();
+        let stats = sftp.download_stream("/stdin.bin", &mut out, None).unwrap();
+        assert_eq!(stats.total_bytes, data.len() as u64);
+        assert_eq!(out, data);
+
+        sftp.mkdir("/dir").unwrap();
+        assert!(sftp.upload_stream(&mut data.as_slice(), "/dir", None).is_err());
+    }
+
+    #[test]
+    fn test_cancelled_transfer_removes_partial_file() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let cancel = Arc::new(AtomicBool::new(true));
+        let sftp = SftpClient::new(&client).unwrap().with_cancel_flag(cancel);
+        let local = tempfile::tempdir().unwrap();
+
+        let src = local.path().join("src.bin");
+        std::fs::write(&src, vec![7u8; 64 * 1024]).unwrap();
+        let err = sftp.upload_file(src.to_str().unwrap(), "/partial.bin", None).unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert!(!server.local_path("/partial.bin").exists());
+
+        std::fs::write(server.local_path("/remote.bin"), vec![7u8; 64 * 1024]).unwrap();
+        let dst = local.path().join("dst.bin");
+        let err = sftp.download_file("/remote.bin", dst.to_str().unwrap(), None).unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert!(!dst.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_links() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::create_dir(server.local_path("/app")).unwrap();
+        std::fs::write(server.local_path("/app/data.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink("data.txt", server.local_path("/app/current")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", server.local_path("/app/stale")).unwrap();
+
+        let mut files = sftp.list_dir("/app").unwrap();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        assert!(files.iter().all(|f| f.link_target.is_none()));
+        sftp.resolve_links(&mut files);
+
+        let current = &files[0];
+        assert_eq!(current.name, "current");
+        assert!(current.is_symlink && !current.link_broken);
+        assert_eq!(current.link_target.as_deref(), Some("data.txt"));
+        assert!(current.mode_string().starts_with('l'));
+        assert!(!files[1].is_symlink && files[1].link_target.is_none());
+        let stale = &files[2];
+        assert!(stale.is_symlink && stale.link_broken);
+        assert_eq!(stale.link_target.as_deref(), Some("missing.txt"));
+    }
+
+    #[test]
+    fn test_escape_name() {
+        assert_eq!(escape_name("\x1b]0;pwned\x07"), "\\x1b]0;pwned\\x07");
+        assert_eq!(escape_name("a\nb\tc\\d"), "a\\nb\\tc\\\\d");
+        assert_eq!(escape_name("报告 2024.txt"), "报告 2024.txt");
+    }
+    
+    #[test]
+    fn test_list_control_characters() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::create_dir(server.local_path("/odd")).unwrap();
+        std::fs::write(server.local_path("/odd/\x1b]0;pwned\x07"), b"x").unwrap();
+        
+        let files = sftp.list_dir("/odd").unwrap();
+        assert_eq!(files[0].display_name(), "\\x1b]0;pwned\\x07");
+        assert_eq!(sftp.stat(&files[0].raw_path.to_string_lossy()).unwrap().size, 1);
+    }
+    
+    /// 测试服务器的协议库只能发送 UTF-8 文件名，直接构造服务器返回的原始路径
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_utf8_name_keeps_raw_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        
+        let raw = Path::new("/srv").join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let stat = FileStat { size: Some(6), uid: None, gid: None, perm: Some(0o100644), atime: None, mtime: None };
+        let info = file_info(raw.clone(), &stat);
+        assert_eq!(info.name, "caf\u{fffd}.txt");
+        assert_eq!(info.raw_path, raw);
+        assert_ne!(PathBuf::from(&info.path), raw);
+    }
+    
+    /// 测试服务器同样只能接收 UTF-8 文件名，直接检查递归上传时拼接出的远程路径
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_child_keeps_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        assert_eq!(remote_child(Path::new("/www/"), name).as_os_str().as_bytes(), b"/www/caf\xe9.txt");
+        assert_eq!(remote_child(Path::new("/"), name).as_os_str().as_bytes(), b"/caf\xe9.txt");
+        assert_eq!(remote_child(Path::new("site"), OsStr::new("a.txt")), Path::new("site/a.txt"));
+    }
+    
+    #[test]
+    fn test_mode_string() {
+        let file = FileInfo {
+            name: "bin".to_string(),
+            path: "/bin".to_string(),
+            size: 0,
+            is_dir: true,
+            permissions: 0o40755,
+            is_symlink: false,
+            link_target: None,
+            link_broken: false,
+            modified: None,
+            uid: None,
+            gid: None,
+            raw_path: PathBuf::from("/bin"),
+        };
+        assert_eq!(file.mode_string(), "drwxr-xr-x");
+        let with = |is_dir: bool, is_symlink: bool, permissions: u32| {
+            FileInfo { is_dir, is_symlink, permissions, ..file.clone() }.mode_string()
+        };
+        assert_eq!(with(false, false, 0o100640), "-rw-r-----");
+        assert_eq!(with(false, true, 0o120777), "lrwxrwxrwx");
+        // setuid/setgid：有执行位时为小写，没有时为大写
+        assert_eq!(with(false, false, 0o104755), "-rwsr-xr-x");
+        assert_eq!(with(false, false, 0o106644), "-rwSr-Sr--");
+        assert_eq!(with(false, false, 0o102711), "-rwx--s--x");
+        // 粘滞位
+        assert_eq!(with(true, false, 0o41777), "drwxrwxrwt");
+        assert_eq!(with(true, false, 0o41770), "drwxrwx--T");
+        assert_eq!(with(true, false, 0o43775), "drwxrwsr-t");
+        assert_eq!(with(false, false, 0o010644), "prw-r--r--");
+        assert_eq!(with(false, false, 0o140755), "srwxr-xr-x");
+
+        let names = OwnerNames::parse("root:x:0:0:root:/root:/bin/sh\n#\nwheel:x:10:root\n");
+        let file = FileInfo { uid: Some(0), gid: Some(1000), ..file };
+        assert_eq!((file.owner(&names), file.group(&names)), ("root".to_string(), "1000".to_string()));
+        let file = FileInfo { uid: None, gid: Some(10), ..file };
+        assert_eq!((file.owner(&names), file.group(&names)), ("-".to_string(), "wheel".to_string()));
+    }
+
+    /// 每次读取前等待的读取器，模拟慢速传输
+    struct SlowReader {
+        data: std::io::Cursor<Vec<u8>>,
+        delay: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(self.delay);
+            let n = buf.len().min(1024);
+            self.data.read(&mut buf[..n])
+        }
+    }
+
+    fn slow_reader(len: usize) -> SlowReader {
+        SlowReader {
+            data: std::io::Cursor::new(vec![5u8; len]),
+            delay: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn test_progress_callback_is_throttled() {
+        let len = 40 * 1024;
+        let mut output = Vec::new();
+        let mut calls = Vec::new();
+        let mut callback = |p: TransferProgress| {
+            calls.push(p);
+            ControlFlow::Continue(())
+        };
+        let copied = copy_with_progress(&mut slow_reader(len), &mut output, ("r", "w"), len as u64, Some(&mut callback), || false)
+            .unwrap();
+
+        assert_eq!(copied, len as u64);
+        assert_eq!(output.len(), len);
+        assert_eq!(calls.first().unwrap().transferred, 0);
+        assert_eq!(calls.last().unwrap().transferred, len as u64);
+        assert!(calls.iter().all(|p| p.total == len as u64));
+        // 约 400ms 的传输：开始、结束，加上中间每 100ms 至多一次
+        assert!(calls.len() >= 3 && calls.len() <= 8, "calls = {}", calls.len());
+        for pair in calls[..calls.len() - 1].windows(2) {
+            assert!(pair[1].elapsed - pair[0].elapsed >= PROGRESS_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn test_progress_callback_break_cancels() {
+        let len = 40 * 1024;
+        let mut output = Vec::new();
+        let mut callback = |p: TransferProgress| {
+            if p.transferred > 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        };
+        let err = copy_with_progress(&mut slow_reader(len), &mut output, ("r", "w"), len as u64, Some(&mut callback), || false)
+            .unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert!(output.len() < len);
+
+        // 经 SftpClient 中止时同样删除不完整的文件
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("src.bin");
+        std::fs::write(&src, vec![7u8; 64 * 1024]).unwrap();
+        let err = sftp
+            .upload_file(src.to_str().unwrap(), "/aborted.bin", Some(&mut |_| ControlFlow::Break(())))
+            .unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert!(!server.local_path("/aborted.bin").exists());
+    }
+
+    #[test]
+    fn test_upload_through_jump_host() {
+        let bastion = TestServer::start();
+        let target = TestServer::start();
+        let client = SshClient::connect_via_jump(bastion.password_config(), target.password_config()).unwrap();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+
+        let data: Vec<u8> = (0..512 * 1024).map(|i| (i % 253) as u8).collect();
+        let src = local.path().join("src.bin");
+        std::fs::write(&src, &data).unwrap();
+
+        sftp.upload_file(src.to_str().unwrap(), "/tunneled.bin", None).unwrap();
+        assert_eq!(std::fs::read(target.local_path("/tunneled.bin")).unwrap(), data);
+        assert!(!bastion.local_path("/tunneled.bin").exists());
+
+        let dst = local.path().join("dst.bin");
+        sftp.download_file("/tunneled.bin", dst.to_str().unwrap(), None).unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+    }
+
+    #[test]
+    fn test_mkdir_list_and_remove() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+
+        sftp.mkdir("/docs").unwrap();
+        std::fs::write(server.local_path("/docs/a.txt"), b"hello").unwrap();
+
+        let files = sftp.list_dir("/").unwrap();
+        assert!(files.iter().any(|f| f.name == "docs" && f.is_dir));
+
+        let files = sftp.list_dir("/docs").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, 5);
+        
+        for name in ["b.txt", "c.txt", "d.txt", "e.txt"] {
+            std::fs::write(server.local_path(&format!("/docs/{}", name)), b"x").unwrap();
+        }
+        let batches: Vec<Vec<FileInfo>> = sftp
+            .list_dir_iter("/docs", 2)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+        assert!(batches.concat().iter().all(|f| f.path.starts_with("/docs/")));
+        for name in ["b.txt", "c.txt", "d.txt", "e.txt"] {
+            sftp.remove_file(&format!("/docs/{}", name)).unwrap();
+        }
+
+        sftp.remove_file("/docs/a.txt").unwrap();
+        assert!(!server.local_path("/docs/a.txt").exists());
+        sftp.remove_dir("/docs").unwrap();
+        assert!(!server.local_path("/docs").exists());
+        assert!(sftp.remove_file("/missing.txt").is_err());
+    }
+
+    #[test]
+    fn test_directory_and_missing_path_errors() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+
+        sftp.mkdir("/logs").unwrap();
+        let dst = local.path().join("out");
+        let err = sftp.download_file("/logs", dst.to_str().unwrap(), None).unwrap_err();
+        assert!(err.to_string().contains("远程路径是目录: /logs"));
+        assert!(!dst.exists());
+
+        let err = sftp.download_file("/missing.txt", dst.to_str().unwrap(), None).unwrap_err();
+        assert_eq!(err.to_string(), "远程路径不存在: /missing.txt");
+
+        let err = sftp.upload_file(local.path().to_str().unwrap(), "/x", None).unwrap_err();
+        assert!(err.to_string().contains("本地路径是目录"));
+
+        // 上传到已存在的远程目录时使用本地文件名
+        let src = local.path().join("app.log");
+        std::fs::write(&src, b"log").unwrap();
+        sftp.upload_file(src.to_str().unwrap(), "/logs/", None).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/logs/app.log")).unwrap(), b"log");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_upload_with_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client)
+            .unwrap()
+            .with_file_mode(Some(0o750))
+            .with_dir_mode(Some(0o700));
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("run.sh");
+        std::fs::write(&src, b"#!/bin/sh\n").unwrap();
+
+        sftp.mkdir_p("/bin/tools").unwrap();
+        sftp.upload_file(src.to_str().unwrap(), "/bin/tools/run.sh", None).unwrap();
+
+        let mode = |path: &str| std::fs::metadata(server.local_path(path)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("/bin/tools/run.sh"), 0o750);
+        assert_eq!(mode("/bin/tools"), 0o700);
+    }
+
+    #[test]
+    fn test_upload_append() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap().with_append(true);
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("chunk.log");
+
+        // 文件不存在时创建，之后每次追加到末尾
+        std::fs::write(&src, b"first\n").unwrap();
+        let stats = sftp.upload_file(src.to_str().unwrap(), "/app.log", None).unwrap();
+        assert_eq!(stats.total_bytes, 6);
+        std::fs::write(&src, b"second\n").unwrap();
+        sftp.upload_file(src.to_str().unwrap(), "/app.log", None).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/app.log")).unwrap(), b"first\nsecond\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_metadata() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let remote = server.local_path("/run.sh");
+        std::fs::write(&remote, b"#!/bin/sh\n").unwrap();
+        
+        sftp.set_metadata("/run.sh", Some(0o750), Some(1_700_000_000)).unwrap();
+        let info = sftp.stat("/run.sh").unwrap();
+        assert_eq!(info.permissions & 0o7777, 0o750);
+        assert_eq!(info.modified, Some(1_700_000_000));
+        assert_eq!(std::fs::metadata(&remote).unwrap().permissions().mode() & 0o777, 0o750);
+        assert_eq!(info.uid, Some(std::fs::metadata(&remote).unwrap().uid()));
+    }
+    
+    #[test]
+    fn test_upload_dir() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let root = local.path().join("site");
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("index.html"), b"<html></html>").unwrap();
+        std::fs::write(root.join("css/app.css"), b"body {}").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("index.html"), root.join("link.html")).unwrap();
+
+        let mut reports = Vec::new();
+        let mut callback = |p: TransferProgress| {
+            reports.push(p);
+            ControlFlow::Continue(())
+        };
+        let stats = sftp.upload_dir(root.to_str().unwrap(), "/www/site", Links::Skip, Some(&mut callback)).unwrap();
+        assert_eq!(stats.files_transferred, 2);
+        assert_eq!(stats.total_bytes, 20);
+        assert_eq!(std::fs::read(server.local_path("/www/site/css/app.css")).unwrap(), b"body {}");
+        assert_eq!(std::fs::read(server.local_path("/www/site/index.html")).unwrap(), b"<html></html>");
+        assert!(server.local_path("/www/site/empty").is_dir());
+        assert!(!server.local_path("/www/site/link.html").exists());
+
+        // 进度是所有文件的累计值
+        assert!(reports.iter().all(|p| p.total == 20));
+        assert_eq!(reports.last().unwrap().transferred, 20);
+
+        // 远程同名路径是目录且其中的同名文件也是目录，无法写入：中止并指出是哪个文件
+        std::fs::remove_file(server.local_path("/www/site/index.html")).unwrap();
+        std::fs::create_dir_all(server.local_path("/www/site/index.html/index.html")).unwrap();
+        let err = sftp.upload_dir(root.to_str().unwrap(), "/www/site", Links::Skip, None).unwrap_err();
+        assert_eq!(err.to_string(), format!("上传 {} 失败", root.join("index.html").display()));
+    }
+
+    /// 符号链接夹具：指向文件、指向目录、目标不存在、指向上级目录各一个
+    #[cfg(unix)]
+    fn link_fixture(root: &Path) {
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/readme.txt"), b"readme").unwrap();
+        std::os::unix::fs::symlink("docs/readme.txt", root.join("file.lnk")).unwrap();
+        std::os::unix::fs::symlink("docs", root.join("dir.lnk")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", root.join("dangling.lnk")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("docs/parent.lnk")).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_upload_dir_links() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        link_fixture(local.path());
+        let upload = |links| {
+            let dest = format!("/{:?}", links);
+            (sftp.upload_dir(local.path().to_str().unwrap(), &dest, links, None), server.local_path(&dest))
+        };
+
+        // skip：只上传普通文件
+        let (stats, dest) = upload(Links::Skip);
+        assert_eq!(stats.unwrap().files_transferred, 1);
+        assert_eq!(std::fs::read(dest.join("docs/readme.txt")).unwrap(), b"readme");
+        assert!(dest.join("file.lnk").symlink_metadata().is_err());
+        assert!(dest.join("dir.lnk").symlink_metadata().is_err());
+
+        // preserve：原样重建，目标不存在的链接也照样创建
+        let (stats, dest) = upload(Links::Preserve);
+        assert_eq!(stats.unwrap().files_transferred, 1);
+        assert_eq!(std::fs::read_link(dest.join("file.lnk")).unwrap(), Path::new("docs/readme.txt"));
+        assert_eq!(std::fs::read_link(dest.join("dangling.lnk")).unwrap(), Path::new("missing.txt"));
+        assert_eq!(std::fs::read_link(dest.join("docs/parent.lnk")).unwrap(), Path::new(".."));
+        assert_eq!(std::fs::read(dest.join("dir.lnk/readme.txt")).unwrap(), b"readme");
+
+        // follow：上传目标内容；目标不存在和循环的链接报告错误，但不影响其余文件
+        let (stats, dest) = upload(Links::Follow);
+        let message = stats.unwrap_err().to_string();
+        assert!(message.starts_with("3 个符号链接无法跟随"), "{}", message);
+        assert!(message.contains("dangling.lnk（目标不存在）"), "{}", message);
+        assert!(message.contains("parent.lnk（指向上级目录"), "{}", message);
+        assert!(!dest.join("file.lnk").is_symlink());
+        assert_eq!(std::fs::read(dest.join("file.lnk")).unwrap(), b"readme");
+        assert!(!dest.join("dir.lnk").is_symlink());
+        assert_eq!(std::fs::read(dest.join("dir.lnk/readme.txt")).unwrap(), b"readme");
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_download_dir_links() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        link_fixture(&server.local_path("/src"));
+        let local = tempfile::tempdir().unwrap();
+        let download = |links| {
+            let dest = local.path().join(format!("{:?}", links));
+            (sftp.download_dir("/src", dest.to_str().unwrap(), links, None, None), dest)
+        };
+
+        let (stats, dest) = download(Links::Skip);
+        assert_eq!(stats.unwrap().files_transferred, 1);
+        assert_eq!(std::fs::read(dest.join("docs/readme.txt")).unwrap(), b"readme");
+        assert!(dest.join("file.lnk").symlink_metadata().is_err());
+        assert!(dest.join("dir.lnk").symlink_metadata().is_err());
+
+        let (stats, dest) = download(Links::Preserve);
+        assert_eq!(stats.unwrap().files_transferred, 1);
+        assert_eq!(std::fs::read_link(dest.join("file.lnk")).unwrap(), Path::new("docs/readme.txt"));
+        assert_eq!(std::fs::read_link(dest.join("dangling.lnk")).unwrap(), Path::new("missing.txt"));
+        assert_eq!(std::fs::read(dest.join("dir.lnk/readme.txt")).unwrap(), b"readme");
+
+        // 通过 dir.lnk 跟随到的 parent.lnk 同样会循环
+        let (stats, dest) = download(Links::Follow);
+        let message = stats.unwrap_err().to_string();
+        assert!(message.starts_with("3 个符号链接无法跟随"), "{}", message);
+        assert!(message.contains("/src/dangling.lnk（目标不存在）"), "{}", message);
+        assert!(message.contains("/src/docs/parent.lnk（指向上级目录"), "{}", message);
+        assert!(message.contains("/src/dir.lnk/parent.lnk（指向上级目录"), "{}", message);
+        assert!(!dest.join("file.lnk").is_symlink());
+        assert_eq!(std::fs::read(dest.join("file.lnk")).unwrap(), b"readme");
+        assert_eq!(std::fs::read(dest.join("dir.lnk/readme.txt")).unwrap(), b"readme");
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_download_dir_manifest() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap().with_remote_perms(true);
+        let root = server.local_path("/src");
+        link_fixture(&root);
+        std::fs::set_permissions(root.join("docs/readme.txt"), std::fs::Permissions::from_mode(0o640)).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let dest = local.path().join("backup");
+
+        let mut manifest = Manifest::new("test", &dest).unwrap();
+        let stats = sftp.download_dir("/src", dest.to_str().unwrap(), Links::Follow, Some(&mut manifest), None);
+        assert!(stats.is_err());
+        // 跟随的链接记录目标的元数据，无法跟随的链接不记录
+        let paths: Vec<_> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["dir.lnk/readme.txt", "docs/readme.txt", "file.lnk"]);
+        for entry in &manifest.entries {
+            assert_eq!(entry.mode, Some(0o640));
+            assert_eq!(entry.not_applied, vec!["owner", "mtime"]);
+            assert_eq!(std::fs::read(manifest.local_path(entry)).unwrap(), b"readme");
+        }
+        assert_eq!(manifest.entries[1].remote, "/src/docs/readme.txt");
+    }
+    
+    #[test]
+    fn test_upload_skip_existing() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("data.bin");
+        std::fs::write(&src, b"local!").unwrap();
+        let remote = server.local_path("/data.bin");
+        let upload = |skip| {
+            let sftp = SftpClient::new(&client).unwrap().with_skip_existing(skip);
+            sftp.upload_file(src.to_str().unwrap(), "/data.bin", None).unwrap()
+        };
+
+        // 远程不存在时正常上传
+        assert_eq!(upload(SkipExisting::Checksum).files_transferred, 1);
+
+        // 大小相同但内容不同：size 跳过，checksum 上传
+        std::fs::write(&remote, b"remote").unwrap();
+        let stats = upload(SkipExisting::Size);
+        assert_eq!((stats.files_transferred, stats.files_skipped, stats.total_bytes), (0, 1, 0));
+        assert_eq!(std::fs::read(&remote).unwrap(), b"remote");
+        assert_eq!(upload(SkipExisting::Checksum).files_transferred, 1);
+        assert_eq!(std::fs::read(&remote).unwrap(), b"local!");
+
+        // 内容相同时 checksum 跳过，never 总是上传
+        assert_eq!(upload(SkipExisting::Checksum).files_skipped, 1);
+        assert_eq!(upload(SkipExisting::Never).files_transferred, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_download_with_remote_perms() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = TestServer::start();
+        let client = server.connect();
+        let remote = server.local_path("/run.sh");
+        std::fs::write(&remote, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&remote, std::fs::Permissions::from_mode(0o750)).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let dest = local.path().join("run.sh");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let sftp = SftpClient::new(&client).unwrap();
+        sftp.download_file("/run.sh", dest.to_str().unwrap(), None).unwrap();
+        assert_ne!(mode(&dest), 0o750);
+
+        let sftp = sftp.with_remote_perms(true);
+        sftp.download_file("/run.sh", dest.to_str().unwrap(), None).unwrap();
+        assert_eq!(mode(&dest), 0o750);
+    }
+
+    #[test]
+    fn test_remote_parent() {
+        assert_eq!(remote_parent("/srv/releases/2024-06-01/a.tar.gz"), Some("/srv/releases/2024-06-01"));
+        assert_eq!(remote_parent("/srv/releases/"), Some("/srv"));
+        assert_eq!(remote_parent("releases//a.tar.gz"), Some("releases"));
+        assert_eq!(remote_parent("a.tar.gz"), None);
+        assert_eq!(remote_parent("./a.tar.gz"), None);
+        assert_eq!(remote_parent("/a.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_join_remote() {
+        assert_eq!(join_remote("/srv/app", "logs/a.log"), "/srv/app/logs/a.log");
+        assert_eq!(join_remote("/srv/app", "../../etc/hosts"), "/etc/hosts");
+        assert_eq!(join_remote("/srv/app", "../../../../x"), "/x");
+        assert_eq!(join_remote("/srv/app", "./releases/"), "/srv/app/releases/");
+        assert_eq!(join_remote("/srv/app", "."), "/srv/app");
+        assert_eq!(join_remote("/srv/app", "/tmp/a"), "/tmp/a");
+
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        assert_eq!(sftp.realpath("srv/../srv/./app").unwrap(), "/srv/app");
+    }
+
+    #[test]
+    fn test_resolve_remote_path() {
+        let home = "/home/deploy";
+        let cwd = "/srv/app";
+        for (input, expected) in [
+            ("~", "/home/deploy"),
+            ("~/", "/home/deploy"),
+            ("~/logs/../.ssh/config", "/home/deploy/.ssh/config"),
+            ("releases/current", "/srv/app/releases/current"),
+            ("./releases/", "/srv/app/releases/"),
+            ("..", "/srv"),
+            ("../../../..", "/"),
+            ("", "/srv/app"),
+            ("/var//log/./nginx/", "/var/log/nginx/"),
+            ("/tmp/../etc/hosts", "/etc/hosts"),
+            ("/", "/"),
+            ("logs\\app.log", "/srv/app/logs\\app.log"),
+        ] {
+            assert_eq!(resolve_remote_path(input, cwd, home).unwrap(), expected, "{}", input);
+        }
+        assert!(resolve_remote_path("~root/.bashrc", cwd, home).is_err());
+        assert!(resolve_remote_path("~deploy", cwd, home).is_err());
+
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let home = sftp.home_dir().unwrap();
+        assert!(home.starts_with('/'));
+        assert_eq!(sftp.resolve_path("~/a.txt", Some("/tmp")).unwrap(), join_remote(&home, "a.txt"));
+        assert_eq!(sftp.resolve_path("a.txt", None).unwrap(), join_remote(&home, "a.txt"));
+    }
+
+    #[test]
+    fn test_listing_cache_invalidation() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let cache = Arc::new(ListingCache::new(Duration::from_secs(60)));
+        let sftp = SftpClient::new(&client).unwrap().with_listing_cache(cache.clone());
+        let names = |files: Vec<FileInfo>| files.into_iter().map(|f| f.name).collect::<Vec<_>>();
+        std::fs::create_dir(server.local_path("/site")).unwrap();
+        std::fs::write(server.local_path("/site/a.txt"), b"a").unwrap();
+        assert_eq!(names(sftp.list_dir("/site").unwrap()), ["a.txt"]);
+
+        // 绕过本客户端的修改在缓存过期前不可见，refresh_dir 重新读取
+        std::fs::write(server.local_path("/site/b.txt"), b"b").unwrap();
+        assert_eq!(names(sftp.list_dir("/site/").unwrap()), ["a.txt"]);
+        assert_eq!(names(sftp.refresh_dir("/site").unwrap()), ["a.txt", "b.txt"]);
+
+        // 每种修改都使父目录的列表失效
+        let local = tempfile::tempdir().unwrap();
+        let src = local.path().join("c.txt");
+        std::fs::write(&src, b"c").unwrap();
+        sftp.upload_file(src.to_str().unwrap(), "/site/c.txt", None).unwrap();
+        assert_eq!(names(sftp.list_dir("/site").unwrap()), ["a.txt", "b.txt", "c.txt"]);
+        sftp.upload_stream(&mut &b"d"[..], "/site/d.txt", None).unwrap();
+        assert_eq!(sftp.list_dir("/site").unwrap().len(), 4);
+        sftp.mkdir("/site/css").unwrap();
+        assert_eq!(names(sftp.list_dir("/site").unwrap())[0], "css");
+        sftp.mkdir_p("/site/js/lib").unwrap();
+        assert_eq!(sftp.list_dir("/site").unwrap().len(), 6);
+        sftp.rename("/site/a.txt", "/site/css/a.txt").unwrap();
+        assert!(!names(sftp.list_dir("/site").unwrap()).contains(&"a.txt".to_string()));
+        assert_eq!(names(sftp.list_dir("/site/css").unwrap()), ["a.txt"]);
+        sftp.remove_file("/site/b.txt").unwrap();
+        assert_eq!(sftp.list_dir("/site").unwrap().len(), 4);
+        sftp.set_metadata("/site/c.txt", Some(0o600), None).unwrap();
+        let c = sftp.list_dir("/site").unwrap().into_iter().find(|f| f.name == "c.txt").unwrap();
+        assert_eq!(c.permissions & 0o777, 0o600);
+        sftp.remove_dir("/site/js/lib").unwrap();
+        assert!(sftp.list_dir("/site/js").unwrap().is_empty());
+
+        // 未挂载缓存的客户端不受影响，清空后重新读取
+        std::fs::write(server.local_path("/site/e.txt"), b"e").unwrap();
+        assert_eq!(SftpClient::new(&client).unwrap().list_dir("/site").unwrap().len(), 5);
+        assert_eq!(sftp.list_dir("/site").unwrap().len(), 4);
+        cache.clear();
+        assert_eq!(sftp.list_dir("/site").unwrap().len(), 5);
+
+        // 过期的缓存不再使用
+        let expired = SftpClient::new(&client).unwrap().with_listing_cache(Arc::new(ListingCache::new(Duration::ZERO)));
+        expired.list_dir("/site").unwrap();
+        std::fs::write(server.local_path("/site/f.txt"), b"f").unwrap();
+        assert_eq!(expired.list_dir("/site").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_retry_transient_failures() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let retry = RetryPolicy { attempts: 3, backoff: Duration::from_millis(1) };
+        let sftp = SftpClient::new(&client).unwrap().with_retry(retry);
+        std::fs::create_dir(server.local_path("/srv")).unwrap();
+        std::fs::write(server.local_path("/srv/a.txt"), b"hello").unwrap();
+
+        server.fail_sftp(2);
+        assert_eq!(sftp.stat("/srv/a.txt").unwrap().size, 5);
+        server.fail_sftp(2);
+        assert_eq!(sftp.list_dir("/srv").unwrap().len(), 1);
+        server.fail_sftp(1);
+        let mut data = Vec::new();
+        sftp.open_reader(Path::new("/srv/a.txt")).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"hello");
+        server.fail_sftp(1);
+        sftp.set_metadata("/srv/a.txt", Some(0o600), None).unwrap();
+
+        // 第一次 mkdir 实际已创建目录，重试时“已存在”视为成功
+        server.fail_sftp(1);
+        sftp.mkdir("/srv/new").unwrap();
+        assert!(server.local_path("/srv/new").is_dir());
+
+        // 超过尝试次数时返回错误
+        server.fail_sftp(3);
+        assert!(sftp.stat("/srv/a.txt").is_err());
+
+        // 不重试时第一次失败即返回
+        let sftp = SftpClient::new(&client).unwrap().with_retry(RetryPolicy { attempts: 1, ..retry });
+        server.fail_sftp(1);
+        assert!(sftp.stat("/srv/a.txt").is_err());
+        assert!(sftp.stat("/srv/a.txt").is_ok());
+    }
+
+    #[test]
+    fn test_retry_skips_permanent_errors() {
+        let calls = std::cell::Cell::new(0);
+        let policy = RetryPolicy { attempts: 3, backoff: Duration::from_millis(1) };
+        let result: Result<(), ssh2::Error> = with_retry(policy, "stat", |_| {
+            calls.set(calls.get() + 1);
+            Err(ssh2::Error::new(ssh2::ErrorCode::SFTP(FX_NO_SUCH_FILE), "no such file"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_mkdir_p() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+
+        sftp.mkdir("/srv").unwrap();
+        sftp.mkdir_p("/srv/releases/2024-06-01").unwrap();
+        assert!(server.local_path("/srv/releases/2024-06-01").is_dir());
+        // 再次执行不报错
+        sftp.mkdir_p("/srv/releases/2024-06-01/").unwrap();
+
+        std::fs::write(server.local_path("/srv/file"), b"x").unwrap();
+        let err = sftp.mkdir_p("/srv/file/sub").unwrap_err();
+        assert!(err.to_string().contains("/srv/file"));
+    }
+}
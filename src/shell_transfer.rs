@@ -0,0 +1,207 @@
+//! 交互式会话中的 `~u`、`~g` 转义：在同一连接上打开 SFTP 子系统通道传输文件
+//!
+//! 传输在 shell 循环中进行，期间远程输出留在 shell 通道中，结束后才继续显示，进度输出不会与 shell 的
+//! 输出交错。进度显示在终端第一行，写入前保存光标位置、写入后恢复，shell 的光标不受影响。
+//!
+//! 相对的远程路径以 shell 的当前目录为基准：通过一个 exec 通道读取 `$PWD`。exec 通道是新的会话，
+//! 得到的是登录目录，交互式 shell 中 `cd` 到的目录无法从另一个通道取得。
+
+use anyhow::{Context, Result};
+use russh::client::Handle;
+use russh::ChannelMsg;
+use russh_sftp::client::SftpSession;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::audit::{self, Event, Operation};
+use crate::escape::{Direction, TransferRequest};
+use crate::format_size;
+use crate::paths;
+use crate::sftp::{join_remote, resolve_remote_path};
+use crate::ssh_russh::{ClientHandler, SshConfig};
+
+/// 每次读写的块大小
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// 两次刷新进度之间的最小间隔
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 执行一次传输，返回完成后显示的摘要
+pub async fn run(
+    session: &Handle<ClientHandler>,
+    config: &SshConfig,
+    request: &TransferRequest,
+    stdout: &mut tokio::io::Stdout,
+) -> Result<String> {
+    let cwd = remote_cwd(session).await?;
+
+    let channel = session.channel_open_session().await.context("无法创建 SFTP 通道")?;
+    channel.request_subsystem(true, "sftp").await.context("无法启动 SFTP 子系统")?;
+    let sftp = SftpSession::new(channel.into_stream()).await.context("无法初始化 SFTP 会话")?;
+
+    let (operation, from) = match request.direction {
+        Direction::Upload => (Operation::Upload, paths::expand(&request.source)),
+        Direction::Download => (Operation::Download, resolve_remote_path(&request.source, &cwd, &cwd)?),
+    };
+
+    let result = match request.direction {
+        Direction::Upload => upload(&sftp, &from, request.target.as_deref(), &cwd, stdout).await,
+        Direction::Download => download(&sftp, &from, request.target.as_deref(), stdout).await,
+    };
+    // 无论成败都清除进度行
+    stdout.write_all(draw_status("").as_bytes()).await.context("写入标准输出失败")?;
+    stdout.flush().await.context("刷新标准输出失败")?;
+    let _ = sftp.close().await;
+
+    let bytes = result.as_ref().ok().map(|(_, bytes)| *bytes);
+    let target = result.as_ref().map(|(target, _)| target.clone()).unwrap_or_default();
+    let event = Event::new(operation, &config.username, &config.host, config.port).path(&from).path(&target).bytes(bytes);
+    audit::record(event, &result);
+
+    let (target, bytes) = result?;
+    let verb = match request.direction {
+        Direction::Upload => "已上传",
+        Direction::Download => "已下载",
+    };
+    Ok(format!("{} {} -> {}（{}）", verb, from, target, format_size(bytes)))
+}
+
+/// 通过 exec 通道读取远程 shell 的 `$PWD`
+async fn remote_cwd(session: &Handle<ClientHandler>) -> Result<String> {
+    let mut channel = session.channel_open_session().await.context("无法创建 SSH 通道")?;
+    channel.exec(true, "printf '%s' \"$PWD\"").await.context("无法查询远程当前目录")?;
+    let mut output = Vec::new();
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { data } => output.extend_from_slice(&data),
+            ChannelMsg::Eof | ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+    let cwd = String::from_utf8_lossy(&output).trim().to_string();
+    if !cwd.starts_with('/') {
+        anyhow::bail!("无法确定远程当前目录（$PWD 为 {:?}）", cwd);
+    }
+    Ok(cwd)
+}
+
+async fn upload(
+    sftp: &SftpSession,
+    local: &str,
+    target: Option<&str>,
+    cwd: &str,
+    stdout: &mut tokio::io::Stdout,
+) -> Result<(String, u64)> {
+    let name = file_name(local)?;
+    let mut remote = match target {
+        Some(target) => resolve_remote_path(target, cwd, cwd)?,
+        None => join_remote(cwd, &name),
+    };
+    // 目标是已有目录时放到目录下
+    let is_dir = remote.ends_with('/') || sftp.metadata(&remote).await.map(|m| m.is_dir()).unwrap_or(false);
+    if is_dir {
+        remote = join_remote(&remote, &name);
+    }
+
+    let file = tokio::fs::File::open(local).await.context(format!("无法打开本地文件: {}", local))?;
+    let total = file.metadata().await.ok().map(|m| m.len());
+    let writer = sftp.create(&remote).await.context(format!("无法创建远程文件: {}", remote))?;
+    let bytes = copy(file, writer, total, &name, stdout).await?;
+    Ok((remote, bytes))
+}
+
+async fn download(
+    sftp: &SftpSession,
+    remote: &str,
+    target: Option<&str>,
+    stdout: &mut tokio::io::Stdout,
+) -> Result<(String, u64)> {
+    let name = file_name(remote)?;
+    let mut local = match target {
+        Some(target) => PathBuf::from(paths::expand(target)),
+        None => PathBuf::from(&name),
+    };
+    if local.is_dir() {
+        local.push(&name);
+    }
+
+    let metadata = sftp.metadata(remote).await.context(format!("无法读取远程文件: {}", remote))?;
+    if metadata.is_dir() {
+        anyhow::bail!("{} 是目录，转义传输只支持单个文件", remote);
+    }
+    let reader = sftp.open(remote).await.context(format!("无法打开远程文件: {}", remote))?;
+    let file = tokio::fs::File::create(&local).await.context(format!("无法创建本地文件: {}", local.display()))?;
+    let bytes = copy(reader, file, metadata.size, &name, stdout).await?;
+    Ok((local.display().to_string(), bytes))
+}
+
+/// 复制数据并在终端第一行显示进度
+async fn copy<R, W>(mut reader: R, mut writer: W, total: Option<u64>, name: &str, stdout: &mut tokio::io::Stdout) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut done = 0u64;
+    let mut last_draw: Option<Instant> = None;
+    loop {
+        let n = reader.read(&mut buffer).await.context("读取数据失败")?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n]).await.context("写入数据失败")?;
+        done += n as u64;
+
+        if last_draw.map_or(true, |at| at.elapsed() >= REDRAW_INTERVAL) {
+            last_draw = Some(Instant::now());
+            stdout.write_all(draw_status(&progress_text(name, done, total)).as_bytes()).await
+                .context("写入标准输出失败")?;
+            stdout.flush().await.context("刷新标准输出失败")?;
+        }
+    }
+    // 关闭远程文件句柄，写入错误在这里才会返回
+    writer.shutdown().await.context("写入数据失败")?;
+    Ok(done)
+}
+
+/// 进度文本，例如 `[传输] app.log  1.00 MB / 4.00 MB (25%)`
+fn progress_text(name: &str, done: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) if total > 0 => format!(
+            "[传输] {}  {} / {} ({}%)",
+            name,
+            format_size(done),
+            format_size(total),
+            done.saturating_mul(100) / total
+        ),
+        _ => format!("[传输] {}  {}", name, format_size(done)),
+    }
+}
+
+/// 在终端第一行显示 `text`（为空时清除该行），前后保存、恢复光标位置
+fn draw_status(text: &str) -> String {
+    format!("\x1b7\x1b[1;1H\x1b[2K{}\x1b8", text)
+}
+
+/// 路径中的文件名部分，用作默认的目标文件名
+fn file_name(path: &str) -> Result<String> {
+    Path::new(path.trim_end_matches('/'))
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .context(format!("无法从路径中取得文件名: {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_text() {
+        assert_eq!(progress_text("app.log", 1024, Some(4096)), "[传输] app.log  1.00 KB / 4.00 KB (25%)");
+        assert_eq!(progress_text("app.log", 1024, None), "[传输] app.log  1.00 KB");
+        assert_eq!(draw_status(""), "\x1b7\x1b[1;1H\x1b[2K\x1b8");
+        assert_eq!(file_name("/var/log/app.log").unwrap(), "app.log");
+        assert!(file_name("/").is_err());
+    }
+}
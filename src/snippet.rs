@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+/// 解析 `key=value` 形式的变量
+pub fn parse_var(arg: &str) -> Result<(String, String)> {
+    let (key, value) = arg
+        .split_once('=')
+        .context(format!("无效的变量格式: {}（应为 key=value）", arg))?;
+    let key = key.trim();
+    if key.is_empty() {
+        anyhow::bail!("变量名不能为空: {}", arg);
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// 解析多个 `key=value` 变量
+pub fn parse_vars<S: AsRef<str>>(args: &[S]) -> Result<HashMap<String, String>> {
+    args.iter().map(|arg| parse_var(arg.as_ref())).collect()
+}
+
+/// 引用连接附加密文的占位符前缀，例如 `{{secret:token}}`
+pub const SECRET_PREFIX: &str = "secret:";
+
+/// 命令片段中引用的附加密文名称（`{{secret:key}}`），按出现顺序去重
+pub fn secret_refs(template: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = normalize(rest[start + 2..start + 2 + len].trim());
+        if let Some(key) = name.strip_prefix(SECRET_PREFIX) {
+            if !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    keys
+}
+
+/// `{{ secret: key }}` 与 `{{secret:key}}` 视为同一个占位符
+fn normalize(name: &str) -> String {
+    match name.strip_prefix(SECRET_PREFIX) {
+        Some(key) => format!("{}{}", SECRET_PREFIX, key.trim()),
+        None => name.to_string(),
+    }
+}
+
+/// 替换命令片段中的 `{{var}}` 占位符
+///
+/// 有任何占位符没有提供值时返回错误，并列出全部缺失的变量。
+pub fn expand(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = normalize(rest[start + 2..start + 2 + len].trim());
+
+        result.push_str(&rest[..start]);
+        match vars.get(&name) {
+            Some(value) => result.push_str(value),
+            None => {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    result.push_str(rest);
+
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "命令片段缺少变量: {}（使用 --var key=value 提供）",
+            missing.join(", ")
+        );
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_placeholders() {
+        let vars = parse_vars(&["service=nginx", "lines=50"]).unwrap();
+        let command = expand("journalctl -u {{service}} -n {{ lines }}", &vars).unwrap();
+        assert_eq!(command, "journalctl -u nginx -n 50");
+
+        assert_eq!(expand("uptime", &HashMap::new()).unwrap(), "uptime");
+    }
+
+    #[test]
+    fn test_secret_refs() {
+        let template = "curl -H 'Authorization: {{secret:token}}' {{url}} {{ secret: token }} {{secret:other}}";
+        assert_eq!(secret_refs(template), vec!["token", "other"]);
+
+        let mut vars = parse_vars(&["url=https://x"]).unwrap();
+        vars.insert("secret:token".to_string(), "T".to_string());
+        vars.insert("secret:other".to_string(), "O".to_string());
+        assert_eq!(expand(template, &vars).unwrap(), "curl -H 'Authorization: T' https://x T O");
+    }
+
+    #[test]
+    fn test_expand_missing_vars() {
+        let vars = parse_vars(&["a=1"]).unwrap();
+        let err = expand("echo {{a}} {{b}} {{c}} {{b}}", &vars).unwrap_err();
+        assert!(err.to_string().contains("b, c"));
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(
+            parse_var("path=/var/log=old").unwrap(),
+            ("path".to_string(), "/var/log=old".to_string())
+        );
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=x").is_err());
+    }
+}
@@ -1,12 +1,138 @@
 use anyhow::{Context, Result};
-use ssh2::Session;
-use std::io::prelude::*;
+use colored::Colorize;
+use indicatif::HumanBytes;
+use ssh2::{Channel, MethodType, Session, TraceFlags};
+use std::fmt;
+use std::io::{self, prelude::*, IsTerminal};
 use std::net::TcpStream;
 use std::path::Path;
-use log::{info, debug, error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::{info, debug, error, warn};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::prompt::{self, Prompter};
+use crate::remote_os::{OwnerNames, RemoteInfo};
+use crate::{agent, audit, credentials, debug_report, glyphs, jump, known_hosts, offline, outcome};
+
+/// 远程命令超过 `--timeout` 时的退出码（与 coreutils timeout 相同）
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// 内部探测命令（系统识别、磁盘空间、diag 等）的超时
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 超时后通知远程进程并关闭通道的最长等待时间
+const ABORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 远程命令在超时前没有结束，已关闭通道
+#[derive(Debug, thiserror::Error)]
+#[error("命令执行超过 {} 秒，已关闭通道（已收到 {} 输出）", .timeout.as_secs(), HumanBytes(*.received))]
+pub struct CommandTimedOut {
+    pub timeout: Duration,
+    /// 超时前已收到的标准输出和标准错误字节数
+    pub received: u64,
+}
+
+/// 服务器拒绝了密码（而不是网络、握手等其他错误），错误信息与原来相同
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct PasswordRejected(pub String);
+
+/// libssh2 的 LIBSSH2_ERROR_AUTHENTICATION_FAILED
+const AUTHENTICATION_FAILED: i32 = -18;
+
+/// 是否输出 libssh2 调试信息（--ssh-debug）
+static SSH_DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// 开启 libssh2 调试输出，影响之后建立的所有连接
+pub fn enable_debug() {
+    SSH_DEBUG.store(true, Ordering::Relaxed);
+}
+
+/// 只使用密钥或 agent 认证，被拒绝时不改用密码（--auth key）
+static KEY_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// `--auth` 的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum AuthMode {
+    /// 密钥或 agent 被拒绝、服务器允许密码登录时询问密码重试
+    #[default]
+    Auto,
+    /// 只使用指定的认证方式
+    Key,
+}
+
+/// 关闭密钥被拒绝后的密码回退，影响之后建立的所有连接
+pub fn pin_key_auth() {
+    KEY_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// 密钥被拒绝后能否改用密码：未指定 `--auth key`，并且已通过 `--password-stdin` 等提供了密码，
+/// 或者不是 `--batch` 且标准输入是终端
+pub(crate) fn can_fall_back() -> bool {
+    !KEY_ONLY.load(Ordering::Relaxed)
+        && (credentials::preset_password().is_some() || (!prompt::is_batch() && io::stdin().is_terminal()))
+}
+
+/// 说明密钥被拒绝并询问密码（已非交互提供密码时直接使用）
+pub(crate) fn ask_fallback_password(prompter: &mut dyn Prompter, username: &str, host: &str) -> Result<Zeroizing<String>> {
+    eprintln!("{} 服务器拒绝了密钥，改用密码认证（--auth key 可关闭）", glyphs::warn().yellow());
+    if let Some(password) = credentials::preset_password() {
+        return Ok(password);
+    }
+    let password = prompter.ask_password(&prompt::labeled(&format!("{}@{}", username, host), "密码"))?;
+    Ok(Zeroizing::new(password))
+}
+
+/// 握手后得到的服务器标识和协商算法
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// 服务器版本标识，例如 `SSH-2.0-OpenSSH_9.6`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    pub kex: String,
+    pub host_key_type: String,
+    /// 主机密钥的 SHA256 指纹
+    pub fingerprint: String,
+    /// 加密算法（客户端->服务器）
+    pub cipher: String,
+    /// MAC 算法（客户端->服务器）
+    pub mac: String,
+    /// 获取时间（Unix 时间戳，秒）
+    pub seen_at: u64,
+    /// 检测到的远程系统，例如 `Ubuntu 22.04.4 LTS (Linux x86_64)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+}
+
+impl ServerInfo {
+    /// 已不推荐使用的协商结果
+    pub fn weak_algorithms(&self) -> Vec<&str> {
+        let weak_kex = ["diffie-hellman-group1-sha1", "diffie-hellman-group14-sha1", "diffie-hellman-group-exchange-sha1"];
+        let mut weak = Vec::new();
+        if weak_kex.contains(&self.kex.as_str()) {
+            weak.push(self.kex.as_str());
+        }
+        if matches!(self.host_key_type.as_str(), "ssh-dss" | "ssh-rsa") {
+            weak.push(self.host_key_type.as_str());
+        }
+        if ["cbc", "3des", "arcfour", "blowfish"].iter().any(|w| self.cipher.contains(w)) {
+            weak.push(self.cipher.as_str());
+        }
+        if self.mac.contains("md5") || self.mac == "hmac-sha1" || self.mac.contains("-96") {
+            weak.push(self.mac.as_str());
+        }
+        weak
+    }
+}
 
 /// SSH 认证方式
-#[derive(Debug, Clone)]
+///
+/// `Debug` 输出中隐藏密码和私钥密码，避免在调试日志中泄露。
+#[derive(Clone)]
 pub enum AuthMethod {
     /// 密码认证
     Password(String),
@@ -16,6 +142,32 @@ pub enum AuthMethod {
         private_key: String,
         passphrase: Option<String>,
     },
+    /// 使用 SSH agent 中的密钥（Windows 上为 OpenSSH agent 或 Pageant）
+    Agent,
+}
+
+impl fmt::Debug for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMethod::Password(_) => f.debug_tuple("Password").field(&Redacted).finish(),
+            AuthMethod::PublicKey { public_key, private_key, passphrase } => f
+                .debug_struct("PublicKey")
+                .field("public_key", public_key)
+                .field("private_key", private_key)
+                .field("passphrase", &passphrase.as_ref().map(|_| Redacted))
+                .finish(),
+            AuthMethod::Agent => f.write_str("Agent"),
+        }
+    }
+}
+
+/// 在 `Debug` 输出中代替敏感内容
+pub(crate) struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
 }
 
 /// SSH 连接配置
@@ -31,29 +183,91 @@ pub struct SshConfig {
 pub struct SshClient {
     session: Session,
     config: SshConfig,
+    /// 串行化对会话的访问，避免保活包与传输中的读写交错
+    io_lock: Arc<Mutex<()>>,
+    keepalive: Option<Keepalive>,
+    /// 首次使用时探测的远程系统信息
+    remote_info: OnceLock<RemoteInfo>,
+    /// 首次使用时读取的远程用户名和组名
+    owner_names: OnceLock<OwnerNames>,
+    /// 执行命令的默认超时，None 表示不限制
+    command_timeout: Option<Duration>,
+}
+
+/// 后台保活线程
+struct Keepalive {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
 }
 
 impl SshClient {
     /// 创建新的 SSH 连接
     pub fn connect(config: SshConfig) -> Result<Self> {
         info!("正在连接到 {}@{}:{}", config.username, config.host, config.port);
+        offline::ensure_network(&format!("连接 {}:{}", config.host, config.port))?;
+        debug_report::attempt(&config.username, &config.host, config.port, &config);
         
+        let event = audit::Event::for_config(audit::Operation::Connect, &config);
         // 建立 TCP 连接
-        let tcp = TcpStream::connect(format!("{}:{}", config.host, config.port))
-            .context("无法建立 TCP 连接")?;
+        let result = TcpStream::connect(format!("{}:{}", config.host, config.port))
+            .context("无法建立 TCP 连接")
+            .and_then(|tcp| Self::connect_with_stream(tcp, config));
+        audit::record(event, &result);
+        result
+    }
+    
+    /// 经过跳板机连接目标主机
+    pub fn connect_via_jump(jump_config: SshConfig, config: SshConfig) -> Result<Self> {
+        let jump_client = Self::connect(jump_config).context("无法连接跳板机")?;
+        let tcp = jump::open_tunnel(jump_client, &config.host, config.port)?;
         
+        info!("正在通过跳板机连接到 {}@{}:{}", config.username, config.host, config.port);
+        debug_report::attempt(&config.username, &config.host, config.port, &config);
+        let event = audit::Event::for_config(audit::Operation::Connect, &config);
+        let result = Self::connect_with_stream(tcp, config);
+        audit::record(event, &result);
+        result
+    }
+    
+    /// 在已建立的 TCP 连接上完成握手和认证
+    fn connect_with_stream(tcp: TcpStream, config: SshConfig) -> Result<Self> {
+        let mut terminal = prompt::Terminal;
+        let fallback = can_fall_back().then_some(&mut terminal as &mut dyn Prompter);
+        Self::establish(tcp, config, fallback)
+    }
+    
+    /// 握手并认证；密钥被拒绝时通过 `fallback` 询问密码（为 None 时直接失败）
+    fn establish(tcp: TcpStream, config: SshConfig, fallback: Option<&mut dyn Prompter>) -> Result<Self> {
         // 创建 SSH 会话
         let mut session = Session::new().context("无法创建 SSH 会话")?;
+        let ssh_debug = SSH_DEBUG.load(Ordering::Relaxed);
+        if ssh_debug {
+            // 只有 libssh2 编译时启用了调试支持才会输出（直接写到 stderr）
+            session.trace(TraceFlags::all());
+        }
         session.set_tcp_stream(tcp);
         session.handshake().context("SSH 握手失败")?;
+        if ssh_debug {
+            log_negotiated_methods(&session);
+        }
+        if debug_report::enabled() {
+            debug_report::handshake(session.banner(), negotiated_methods(&session));
+        }
+        
+        // 校验主机密钥
+        let host_key = session.host_key().map(|(key, _)| key.to_vec());
+        if let Some(ref host_key) = host_key {
+            known_hosts::verify_host_key(&config.host, config.port, host_key)?;
+        }
         
         // 认证
-        match &config.auth {
+        let auth_method = match &config.auth {
             AuthMethod::Password(password) => {
                 debug!("使用密码认证");
                 session
                     .userauth_password(&config.username, password)
-                    .context("密码认证失败")?;
+                    .map_err(|e| auth_error(&session, &config.username, e, "密码认证失败", "password"))?;
+                "password"
             }
             AuthMethod::PublicKey {
                 public_key,
@@ -61,29 +275,123 @@ impl SshClient {
                 passphrase,
             } => {
                 debug!("使用公钥认证");
-                session
-                    .userauth_pubkey_file(
-                        &config.username,
-                        public_key.as_deref().map(Path::new),
-                        Path::new(private_key),
-                        passphrase.as_deref(),
-                    )
-                    .context("公钥认证失败")?;
+                let result = session.userauth_pubkey_file(
+                    &config.username,
+                    public_key.as_deref().map(Path::new),
+                    Path::new(private_key),
+                    passphrase.as_deref(),
+                );
+                match result {
+                    Ok(()) => "publickey",
+                    Err(e) => fall_back_to_password(&session, &config, e, "公钥认证失败", fallback)?,
+                }
             }
-        }
+            AuthMethod::Agent => {
+                debug!("使用 SSH agent 认证");
+                // libssh2 在 Windows 上会依次尝试 OpenSSH agent 管道和 Pageant
+                match session.userauth_agent(&config.username) {
+                    Ok(()) => "agent",
+                    Err(e) => {
+                        let message = format!("SSH agent 认证失败（期望: {}）", agent::expected_location());
+                        fall_back_to_password(&session, &config, e, &message, fallback)?
+                    }
+                }
+            }
+        };
         
         if !session.authenticated() {
             anyhow::bail!("认证失败");
         }
         
         info!("SSH 连接成功");
+        if let Some(ref host_key) = host_key {
+            known_hosts::pin_host_key(&config.host, config.port, host_key);
+        }
+        let fingerprint = host_key.as_deref().map(known_hosts::fingerprint_blob);
+        outcome::connected(&config.username, &config.host, config.port, auth_method, fingerprint);
+        debug_report::authenticated(auth_method);
+        
+        Ok(Self {
+            session,
+            config,
+            io_lock: Arc::new(Mutex::new(())),
+            keepalive: None,
+            remote_info: OnceLock::new(),
+            owner_names: OnceLock::new(),
+            command_timeout: None,
+        })
+    }
+    
+    /// 启动后台线程，每隔 `interval` 秒发送一次保活包，直到客户端被释放
+    pub fn start_keepalive(&mut self, interval: u32) {
+        if self.keepalive.is_some() || interval == 0 {
+            return;
+        }
+        info!("启用保活，间隔 {} 秒", interval);
+        self.session.set_keepalive(false, interval);
+        
+        let session = self.session.clone();
+        let io_lock = Arc::clone(&self.io_lock);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        
+        let handle = thread::spawn(move || {
+            let mut wait = Duration::from_secs(interval as u64);
+            loop {
+                // Drop 时会唤醒线程
+                thread::park_timeout(wait);
+                if thread_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                
+                let result = {
+                    let _guard = io_lock.lock().unwrap_or_else(|e| e.into_inner());
+                    session.keepalive_send()
+                };
+                match result {
+                    // 返回距离下一次需要发送的秒数
+                    Ok(next) => wait = Duration::from_secs(next.max(1) as u64),
+                    Err(e) => {
+                        warn!("发送保活包失败: {}", e);
+                        break;
+                    }
+                }
+            }
+            debug!("保活线程已退出");
+        });
         
-        Ok(Self { session, config })
+        self.keepalive = Some(Keepalive { stop, handle });
+    }
+    
+    /// 设置执行命令的默认超时（`--timeout`），None 表示不限制
+    pub fn set_command_timeout(&mut self, timeout: Option<Duration>) {
+        self.command_timeout = timeout;
+    }
+    
+    pub fn command_timeout(&self) -> Option<Duration> {
+        self.command_timeout
+    }
+    
+    /// 在一次读写期间独占会话（与保活线程互斥）
+    pub fn lock_io(&self) -> MutexGuard<'_, ()> {
+        self.io_lock.lock().unwrap_or_else(|e| e.into_inner())
     }
     
     /// 执行单个命令
     pub fn exec_command(&self, command: &str) -> Result<String> {
-        debug!("执行命令: {}", command);
+        let (output, _) = self.exec_command_with_status(command)?;
+        Ok(output)
+    }
+    
+    /// 执行单个命令，同时返回退出码
+    pub fn exec_command_with_status(&self, command: &str) -> Result<(String, i32)> {
+        self.exec_command_shown(command, &credentials::redacted(command))
+    }
+    
+    /// 与 [`exec_command_with_status`](Self::exec_command_with_status) 相同，但日志中记录 `shown`
+    /// （例如展开密文前的命令片段），实际发送的 `command` 只写入通道
+    pub fn exec_command_shown(&self, command: &str, shown: &str) -> Result<(String, i32)> {
+        debug!("执行命令: {}", shown);
         
         let mut channel = self.session.channel_session()
             .context("无法创建通道")?;
@@ -91,23 +399,76 @@ impl SshClient {
         channel.exec(command)
             .context("命令执行失败")?;
         
-        let mut output = String::new();
-        channel.read_to_string(&mut output)
-            .context("读取输出失败")?;
-        
-        channel.wait_close()
-            .context("等待通道关闭失败")?;
-        
-        let exit_status = channel.exit_status()
-            .context("获取退出状态失败")?;
+        let (output, stderr, exit_status) = self.finish_channel(channel, self.command_timeout)?;
+        let output = String::from_utf8(output).context("读取输出失败")?;
         
         if exit_status != 0 {
-            let mut stderr = String::new();
-            channel.stderr().read_to_string(&mut stderr).ok();
             error!("命令执行失败，退出码: {}, 错误: {}", exit_status, stderr);
         }
         
-        Ok(output)
+        Ok((output, exit_status))
+    }
+    
+    /// 执行命令，返回标准输出的原始字节、标准错误和退出码
+    ///
+    /// 退出码非零不视为错误，也不记录错误日志，适合探测命令和输出不一定是 UTF-8 的命令。
+    pub fn exec_command_output(&self, command: &str) -> Result<(Vec<u8>, String, i32)> {
+        self.exec_command_timeout(command, self.command_timeout)
+    }
+    
+    /// 与 [`exec_command_output`](Self::exec_command_output) 相同，但使用指定的超时而不是默认超时
+    pub fn exec_command_timeout(&self, command: &str, timeout: Option<Duration>) -> Result<(Vec<u8>, String, i32)> {
+        debug!("执行命令: {}", credentials::redacted(command));
+        let _io = self.lock_io();
+        let mut channel = self.session.channel_session().context("无法创建通道")?;
+        channel.exec(command).context("命令执行失败")?;
+        self.finish_channel(channel, timeout)
+    }
+    
+    /// 执行命令并把 `input` 写入其标准输入（随后关闭），返回值与 [`exec_command_output`](Self::exec_command_output) 相同
+    pub fn exec_command_input(&self, command: &str, input: &[u8]) -> Result<(Vec<u8>, String, i32)> {
+        debug!("执行命令: {}", credentials::redacted(command));
+        let _io = self.lock_io();
+        let mut channel = self.session.channel_session().context("无法创建通道")?;
+        channel.exec(command).context("命令执行失败")?;
+        channel.write_all(input).context("写入标准输入失败")?;
+        channel.send_eof().context("关闭标准输入失败")?;
+        self.finish_channel(channel, self.command_timeout)
+    }
+    
+    /// 读完通道的标准输出和标准错误，等待关闭并返回退出码
+    ///
+    /// 超过 `timeout` 时通知远程进程结束、关闭通道并返回 [`CommandTimedOut`]。
+    fn finish_channel(&self, mut channel: Channel, timeout: Option<Duration>) -> Result<(Vec<u8>, String, i32)> {
+        let mut output = Vec::new();
+        let mut stderr = Vec::new();
+        let Some(timeout) = timeout else {
+            channel.read_to_end(&mut output).context("读取输出失败")?;
+            channel.stderr().read_to_end(&mut stderr).ok();
+            channel.wait_close().context("等待通道关闭失败")?;
+            let status = channel.exit_status().context("获取退出状态失败")?;
+            return Ok((output, String::from_utf8_lossy(&stderr).into_owned(), status));
+        };
+        
+        let deadline = Instant::now() + timeout;
+        let result = read_until(&self.session, &mut channel, deadline, &mut output)
+            .and_then(|_| read_until(&self.session, &mut channel.stderr(), deadline, &mut stderr))
+            .and_then(|_| {
+                set_remaining_timeout(&self.session, deadline)?;
+                channel.wait_close().map_err(io::Error::from)
+            });
+        self.session.set_timeout(0);
+        match result {
+            Ok(()) => {
+                let status = channel.exit_status().context("获取退出状态失败")?;
+                Ok((output, String::from_utf8_lossy(&stderr).into_owned(), status))
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                abort_channel(&self.session, channel);
+                Err(CommandTimedOut { timeout, received: (output.len() + stderr.len()) as u64 }.into())
+            }
+            Err(e) => Err(anyhow::Error::new(e).context("读取输出失败")),
+        }
     }
     
     /// 获取 SSH 会话引用（用于 SFTP）
@@ -115,6 +476,35 @@ impl SshClient {
         &self.session
     }
     
+    /// 服务器标识、协商的算法和主机密钥指纹
+    pub fn server_info(&self) -> ServerInfo {
+        let method = |kind| self.session.methods(kind).unwrap_or("-").to_string();
+        ServerInfo {
+            banner: self.session.banner().map(str::to_string),
+            kex: method(MethodType::Kex),
+            host_key_type: method(MethodType::HostKey),
+            fingerprint: self
+                .session
+                .host_key()
+                .map(|(key, _)| known_hosts::fingerprint_blob(key))
+                .unwrap_or_else(|| "-".to_string()),
+            cipher: method(MethodType::CryptCs),
+            mac: method(MethodType::MacCs),
+            seen_at: chrono::Utc::now().timestamp().max(0) as u64,
+            os: None,
+        }
+    }
+    
+    /// 远程系统信息，每个连接只探测一次
+    pub fn remote_info(&self) -> &RemoteInfo {
+        self.remote_info.get_or_init(|| RemoteInfo::probe(self))
+    }
+    
+    /// 远程 uid/gid 对应的名称，每个连接只读取一次
+    pub fn owner_names(&self) -> &OwnerNames {
+        self.owner_names.get_or_init(|| OwnerNames::probe(self))
+    }
+    
     /// 获取配置信息
     pub fn config(&self) -> &SshConfig {
         &self.config
@@ -127,8 +517,147 @@ impl SshClient {
     }
 }
 
+/// 输出握手协商出的算法
+/// 读取到 EOF，每次阻塞读取最多等到 `deadline`
+fn read_until(session: &Session, reader: &mut impl Read, deadline: Instant, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut chunk = [0u8; 32 * 1024];
+    loop {
+        set_remaining_timeout(session, deadline)?;
+        match reader.read(&mut chunk)? {
+            0 => return Ok(()),
+            n => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// 把会话的阻塞超时设为距离 `deadline` 的剩余时间，已经超时时返回 TimedOut
+fn set_remaining_timeout(session: &Session, deadline: Instant) -> io::Result<()> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(io::ErrorKind::TimedOut.into());
+    }
+    // 0 表示不限制，剩余不足 1 毫秒时按 1 毫秒计
+    session.set_timeout(remaining.as_millis().clamp(1, u32::MAX as u128) as u32);
+    Ok(())
+}
+
+/// 放弃仍在运行的命令：请求远程发送 SIGTERM（服务器不一定支持），然后关闭通道
+///
+/// 会话须处于阻塞模式。整个过程最多等待 [`ABORT_TIMEOUT`]，失败只记录日志。
+pub fn abort_channel(session: &Session, mut channel: Channel) {
+    session.set_timeout(ABORT_TIMEOUT.as_millis() as u32);
+    if let Err(e) = channel.process_startup("signal", Some("TERM")) {
+        debug!("发送信号失败: {}", e);
+    }
+    if let Err(e) = channel.close() {
+        debug!("关闭通道失败: {}", e);
+    }
+    // 释放通道时也可能与服务器交互，须在恢复无限等待之前完成
+    drop(channel);
+    session.set_timeout(0);
+}
+
+/// 握手协商的算法
+fn negotiated_methods(session: &Session) -> Vec<(&'static str, String)> {
+    let methods = [
+        ("密钥交换", MethodType::Kex),
+        ("主机密钥", MethodType::HostKey),
+        ("加密 (客户端->服务器)", MethodType::CryptCs),
+        ("加密 (服务器->客户端)", MethodType::CryptSc),
+        ("MAC (客户端->服务器)", MethodType::MacCs),
+        ("MAC (服务器->客户端)", MethodType::MacSc),
+        ("压缩 (客户端->服务器)", MethodType::CompCs),
+        ("压缩 (服务器->客户端)", MethodType::CompSc),
+    ];
+    methods
+        .into_iter()
+        .map(|(name, method)| (name, session.methods(method).unwrap_or("-").to_string()))
+        .collect()
+}
+
+fn log_negotiated_methods(session: &Session) {
+    if let Some(banner) = session.banner() {
+        debug!("服务器标识: {}", banner);
+    }
+    for (name, value) in negotiated_methods(session) {
+        debug!("协商算法 {}: {}", name, value);
+    }
+}
+
+/// 密钥被拒绝后，服务器允许 password 或 keyboard-interactive 时询问密码重试，否则返回原来的认证错误
+///
+/// 成功时返回实际使用的认证方式。
+fn fall_back_to_password(
+    session: &Session,
+    config: &SshConfig,
+    err: ssh2::Error,
+    message: &str,
+    fallback: Option<&mut dyn Prompter>,
+) -> Result<&'static str> {
+    let methods = session.auth_methods(&config.username).unwrap_or_default().to_string();
+    debug_report::server_auth_methods(&methods);
+    let offered = |method: &str| methods.split(',').any(|m| m == method);
+    let method = if offered("password") {
+        "password"
+    } else if offered("keyboard-interactive") {
+        "keyboard-interactive"
+    } else {
+        return Err(auth_error(session, &config.username, err, message, "publickey"));
+    };
+    let Some(prompter) = fallback else {
+        return Err(auth_error(session, &config.username, err, message, "publickey"));
+    };
+
+    debug!("{}: {}，改用 {}", message, err, method);
+    let password = ask_fallback_password(prompter, &config.username, &config.host)?;
+    let result = match method {
+        "password" => session.userauth_password(&config.username, &password),
+        _ => session.userauth_keyboard_interactive(&config.username, &mut PasswordResponder(&password)),
+    };
+    result.map_err(|e| auth_error(session, &config.username, e, "密码认证失败", method))?;
+    warn!("密钥未被接受，已改用 {} 认证 {}@{}；请确认公钥已加入服务器的 authorized_keys", method, config.username, config.host);
+    Ok(method)
+}
+
+/// keyboard-interactive 认证中对每个提示都回答同一个密码
+struct PasswordResponder<'p>(&'p str);
+
+impl ssh2::KeyboardInteractivePrompt for PasswordResponder<'_> {
+    fn prompt<'a>(&mut self, _username: &str, _instructions: &str, prompts: &[ssh2::Prompt<'a>]) -> Vec<String> {
+        prompts.iter().map(|_| self.0.to_string()).collect()
+    }
+}
+
+/// 认证失败时附上服务器接受的认证方式，所用方式不被接受时给出提示
+fn auth_error(session: &Session, username: &str, err: ssh2::Error, message: &str, method: &str) -> anyhow::Error {
+    let message = match session.auth_methods(username) {
+        Ok(methods) => {
+            debug_report::server_auth_methods(methods);
+            let mut message = format!("{}（服务器接受的认证方式: {}）", message, methods);
+            if !methods.split(',').any(|m| m == method) {
+                let hint = match method {
+                    "password" => "服务器不允许密码登录，请使用 -i 指定私钥，或使用 SSH agent（config add --use-agent）",
+                    _ => "服务器不允许公钥登录",
+                };
+                message.push_str(&format!("\n  提示: {}", hint));
+            }
+            message
+        }
+        Err(_) => message.to_string(),
+    };
+    if method != "publickey" && err.code() == ssh2::ErrorCode::Session(AUTHENTICATION_FAILED) {
+        return anyhow::Error::new(err).context(PasswordRejected(message));
+    }
+    anyhow::Error::new(err).context(message)
+}
+
 impl Drop for SshClient {
     fn drop(&mut self) {
+        if let Some(keepalive) = self.keepalive.take() {
+            keepalive.stop.store(true, Ordering::SeqCst);
+            keepalive.handle.thread().unpark();
+            let _ = keepalive.handle.join();
+        }
         info!("断开 SSH 连接");
         let _ = self.session.disconnect(None, "客户端断开连接", None);
     }
@@ -137,6 +666,16 @@ impl Drop for SshClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server::{TestServer, TEST_PASSWORD, TEST_USER};
+    
+    fn password_config(server: &TestServer, password: &str) -> SshConfig {
+        SshConfig {
+            host: "127.0.0.1".to_string(),
+            port: server.port,
+            username: TEST_USER.to_string(),
+            auth: AuthMethod::Password(password.to_string()),
+        }
+    }
     
     #[test]
     fn test_ssh_config_creation() {
@@ -150,5 +689,209 @@ mod tests {
         assert_eq!(config.host, "example.com");
         assert_eq!(config.port, 22);
     }
-}
+    
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let secret = "hunter2-Sup3r-secret";
+        let configs = [
+            SshConfig {
+                host: "example.com".to_string(),
+                port: 22,
+                username: "user".to_string(),
+                auth: AuthMethod::Password(secret.to_string()),
+            },
+            SshConfig {
+                host: "example.com".to_string(),
+                port: 22,
+                username: "user".to_string(),
+                auth: AuthMethod::PublicKey {
+                    public_key: None,
+                    private_key: "/home/user/.ssh/id_ed25519".to_string(),
+                    passphrase: Some(secret.to_string()),
+                },
+            },
+        ];
+        
+        for config in &configs {
+            for output in [format!("{:?}", config), format!("{:#?}", config)] {
+                assert!(output.contains("***"));
+                // 密码的任何片段都不应出现
+                for fragment in secret.as_bytes().windows(4) {
+                    assert!(!output.contains(std::str::from_utf8(fragment).unwrap()), "{}", output);
+                }
+            }
+        }
+        assert!(format!("{:?}", configs[0]).contains("Password(***)"));
+    }
+    
+    #[test]
+    fn test_connect_with_password() {
+        let server = TestServer::start();
+        let client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        assert!(client.is_connected());
+    }
+    
+    #[test]
+    fn test_connect_with_wrong_password() {
+        let server = TestServer::start();
+        assert!(SshClient::connect(password_config(&server, "wrong")).is_err());
+    }
+    
+    #[test]
+    fn test_connect_with_publickey() {
+        let server = TestServer::start();
+        let config = SshConfig {
+            host: "127.0.0.1".to_string(),
+            port: server.port,
+            username: TEST_USER.to_string(),
+            auth: AuthMethod::PublicKey {
+                public_key: None,
+                private_key: server.client_key_path(),
+                passphrase: None,
+            },
+        };
+        let client = SshClient::connect(config).unwrap();
+        assert!(client.is_connected());
+    }
+    
+    #[test]
+    fn test_server_info() {
+        let server = TestServer::start();
+        let client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        
+        let mut info = client.server_info();
+        assert!(info.banner.as_deref().is_some_and(|b| b.starts_with("SSH-2.0-")), "{:?}", info.banner);
+        assert!(info.fingerprint.starts_with("SHA256:"));
+        assert_ne!(info.kex, "-");
+        assert!(info.weak_algorithms().is_empty(), "{:?}", info);
+        
+        info.cipher = "aes128-cbc".to_string();
+        info.mac = "hmac-sha1".to_string();
+        assert_eq!(info.weak_algorithms(), vec!["aes128-cbc", "hmac-sha1"]);
+    }
+    
+    #[test]
+    fn test_exec_command_exit_status() {
+        let server = TestServer::start();
+        let client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        
+        let (output, status) = client.exec_command_with_status("echo hello").unwrap();
+        assert_eq!(output, "hello\n");
+        assert_eq!(status, 0);
+        
+        let (_, status) = client.exec_command_with_status("exit 3").unwrap();
+        assert_eq!(status, 3);
+    }
+    
+    #[test]
+    fn test_exec_command_timeout() {
+        let server = TestServer::start();
+        let mut client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        client.set_command_timeout(Some(Duration::from_millis(500)));
+        
+        let started = Instant::now();
+        let err = client.exec_command_output("echo partial; sleep 5").unwrap_err();
+        let timed_out = err.downcast_ref::<CommandTimedOut>().unwrap();
+        assert_eq!(timed_out.received, 8);
+        assert!(started.elapsed() < Duration::from_secs(4), "{:?}", started.elapsed());
+        
+        // 结束较快的命令不受影响，超时后会话仍可继续使用
+        let (output, _, status) = client.exec_command_output("echo ok").unwrap();
+        assert_eq!((output.as_slice(), status), (&b"ok\n"[..], 0));
+        let (output, _, _) = client.exec_command_timeout("sleep 1; echo slow", None).unwrap();
+        assert_eq!(output, b"slow\n");
+    }
+    
+    #[test]
+    fn test_auth_failure_lists_server_methods() {
+        let server = TestServer::start();
+        let err = SshClient::connect(password_config(&server, "wrong")).err().unwrap();
+        let message = err.to_string();
+        assert!(message.contains("服务器接受的认证方式"), "{}", message);
+        assert!(message.contains("password"));
+        assert!(!message.contains("提示"));
+        
+        let server = TestServer::start_key_only();
+        let err = SshClient::connect(password_config(&server, TEST_PASSWORD)).err().unwrap();
+        let message = err.to_string();
+        assert!(message.contains("服务器接受的认证方式: publickey"), "{}", message);
+        assert!(message.contains("-i"));
+    }
+    
+    #[test]
+    fn test_rejected_key_falls_back_to_password() {
+        // 服务器不认识的密钥
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("id_ed25519");
+        let keypair = ssh_key::private::Ed25519Keypair::from_seed(&[7u8; 32]);
+        let key = ssh_key::PrivateKey::from(keypair).to_openssh(ssh_key::LineEnding::LF).unwrap();
+        std::fs::write(&key_path, key.as_bytes()).unwrap();
+        let key_config = |server: &TestServer| SshConfig {
+            auth: AuthMethod::PublicKey { public_key: None, private_key: key_path.to_string_lossy().into_owned(), passphrase: None },
+            ..password_config(server, "")
+        };
+        let tcp = |server: &TestServer| TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+
+        let server = TestServer::start();
+        let mut prompter = prompt::Scripted::new(&[TEST_PASSWORD]);
+        let client = SshClient::establish(tcp(&server), key_config(&server), Some(&mut prompter)).unwrap();
+        assert!(client.is_connected());
+        assert_eq!(prompter.asked, vec![format!("[{}@127.0.0.1] 密码: ", TEST_USER)]);
 
+        // 不回退（--auth key、--batch 或没有终端）时保留原来的错误
+        let err = SshClient::establish(tcp(&server), key_config(&server), None).err().unwrap();
+        assert!(err.to_string().contains("公钥认证失败"), "{}", err);
+
+        // 服务器不允许密码登录时不询问
+        let server = TestServer::start_key_only();
+        let mut prompter = prompt::Scripted::new(&[]);
+        assert!(SshClient::establish(tcp(&server), key_config(&server), Some(&mut prompter)).is_err());
+        assert!(prompter.asked.is_empty());
+    }
+    
+    #[test]
+    fn test_negotiated_methods_available() {
+        let server = TestServer::start();
+        let client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        
+        // --ssh-debug 输出的算法在握手后都能取到
+        assert!(client.session().methods(MethodType::Kex).is_some());
+        assert!(client.session().methods(MethodType::CryptCs).is_some());
+        log_negotiated_methods(client.session());
+    }
+    
+    #[test]
+    fn test_keepalive_thread() {
+        let server = TestServer::start();
+        let mut client = SshClient::connect(password_config(&server, TEST_PASSWORD)).unwrap();
+        client.start_keepalive(1);
+        
+        // 保活包与命令执行交替进行
+        std::thread::sleep(Duration::from_millis(2500));
+        assert_eq!(client.exec_command("echo ok").unwrap(), "ok\n");
+        
+        // 释放时立即停止线程，不等待下一个间隔
+        let started = std::time::Instant::now();
+        drop(client);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+    
+    #[test]
+    fn test_connect_via_jump_host() {
+        let bastion = TestServer::start();
+        let target = TestServer::start();
+        
+        let client = SshClient::connect_via_jump(
+            password_config(&bastion, TEST_PASSWORD),
+            password_config(&target, TEST_PASSWORD),
+        )
+        .unwrap();
+        
+        let (output, status) = client.exec_command_with_status("pwd").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(
+            std::path::Path::new(output.trim()).canonicalize().unwrap(),
+            target.root.path().canonicalize().unwrap()
+        );
+    }
+}
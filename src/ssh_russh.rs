@@ -1,14 +1,50 @@
 use anyhow::{Context, Result, anyhow};
-use log::{debug, info};
+use log::{debug, error, info, warn};
 use russh::*;
 use russh_keys::*;
-use std::sync::Arc;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
 
-/// SSH 认证方法
-#[derive(Debug, Clone)]
+use crate::interrupt::ConnectCancelled;
+use crate::ssh::{self, Redacted};
+use crate::{agent, audit, debug_report, known_hosts, offline, outcome, prompt};
+
+/// SSH 认证方法（`Debug` 输出中隐藏密码）
+#[derive(Clone)]
 pub enum AuthMethod {
     Password(String),
-    PublicKey(String),
+    PublicKey {
+        private_key: String,
+        passphrase: Option<String>,
+    },
+    Agent,
+}
+
+impl fmt::Debug for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMethod::Password(_) => f.debug_tuple("Password").field(&Redacted).finish(),
+            AuthMethod::PublicKey { private_key, passphrase } => f
+                .debug_struct("PublicKey")
+                .field("private_key", private_key)
+                .field("passphrase", &passphrase.as_ref().map(|_| Redacted))
+                .finish(),
+            AuthMethod::Agent => f.write_str("Agent"),
+        }
+    }
+}
+
+impl From<crate::ssh::AuthMethod> for AuthMethod {
+    fn from(auth: crate::ssh::AuthMethod) -> Self {
+        match auth {
+            crate::ssh::AuthMethod::Password(password) => AuthMethod::Password(password),
+            crate::ssh::AuthMethod::PublicKey { private_key, passphrase, .. } => {
+                AuthMethod::PublicKey { private_key, passphrase }
+            }
+            crate::ssh::AuthMethod::Agent => AuthMethod::Agent,
+        }
+    }
 }
 
 /// SSH 连接配置
@@ -32,7 +68,12 @@ impl SshConfig {
 }
 
 /// Russh 客户端处理器
-pub struct ClientHandler;
+pub struct ClientHandler {
+    host: String,
+    port: u16,
+    /// 握手时服务器提供的公钥，认证成功后用于固定指纹
+    host_key: Arc<Mutex<Option<Vec<u8>>>>,
+}
 
 #[async_trait::async_trait]
 impl client::Handler for ClientHandler {
@@ -40,11 +81,20 @@ impl client::Handler for ClientHandler {
 
     async fn check_server_key(
         &mut self,
-        _server_public_key: &key::PublicKey,
+        server_public_key: &key::PublicKey,
     ) -> Result<bool, Self::Error> {
-        // 在生产环境中应该验证服务器密钥
-        // 这里为了简单起见，接受所有密钥
-        Ok(true)
+        // 与 known_hosts 中的记录比对，不一致时拒绝连接
+        let blob = server_public_key.public_key_bytes();
+        match known_hosts::verify_host_key(&self.host, self.port, &blob) {
+            Ok(()) => {
+                *self.host_key.lock().unwrap_or_else(|e| e.into_inner()) = Some(blob);
+                Ok(true)
+            }
+            Err(e) => {
+                error!("{}", e);
+                Ok(false)
+            }
+        }
     }
 }
 
@@ -52,6 +102,7 @@ impl client::Handler for ClientHandler {
 pub struct RusshClient {
     config: SshConfig,
     session: Option<client::Handle<ClientHandler>>,
+    cancel: CancellationToken,
 }
 
 impl RusshClient {
@@ -60,16 +111,50 @@ impl RusshClient {
         Self {
             config,
             session: None,
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// 使用指定的取消令牌：连接或认证进行中时在其他任务（图形界面的取消按钮、Ctrl+C 处理）中
+    /// 调用 `cancel()`，[`connect`](Self::connect) 会立即返回 [`ConnectCancelled`]
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 本客户端的取消令牌（克隆后交给其他任务）
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
     /// 连接到 SSH 服务器
+    ///
+    /// 取消令牌被触发时放弃连接（DNS 解析、TCP 连接和认证都会被中断），返回 [`ConnectCancelled`]；
+    /// 已建立的部分连接随之关闭，不会留在后台。
     pub async fn connect(&mut self) -> Result<()> {
+        let cancel = self.cancel.clone();
+        let result = tokio::select! {
+            result = self.establish() => result,
+            _ = cancel.cancelled() => Err(ConnectCancelled.into()),
+        };
+        let event = audit::Event::new(audit::Operation::Connect, &self.config.username, &self.config.host, self.config.port);
+        audit::record(event, &result);
+        result
+    }
+
+    async fn establish(&mut self) -> Result<()> {
         info!("正在连接到 {}:{}",  self.config.host, self.config.port);
+        offline::ensure_network(&format!("连接 {}:{}", self.config.host, self.config.port))?;
+        debug_report::attempt(&self.config.username, &self.config.host, self.config.port, &self.config);
 
         // 创建 SSH 客户端配置
         let client_config = client::Config::default();
-        let sh = ClientHandler;
+        let host_key = Arc::new(Mutex::new(None));
+        let sh = ClientHandler {
+            host: self.config.host.clone(),
+            port: self.config.port,
+            host_key: Arc::clone(&host_key),
+        };
 
         // 连接到服务器
         let mut session = client::connect(
@@ -81,32 +166,94 @@ impl RusshClient {
         .context("无法连接到 SSH 服务器")?;
 
         // 认证
-        let auth_result = match &self.config.auth {
+        let mut authenticated = match &self.config.auth {
             AuthMethod::Password(password) => {
                 debug!("使用密码认证");
                 session
                     .authenticate_password(self.config.username.clone(), password.clone())
                     .await
+                    .context("认证失败")?
             }
-            AuthMethod::PublicKey(key_path) => {
-                debug!("使用公钥认证: {}", key_path);
-                let key_pair = load_secret_key(key_path, None)
+            AuthMethod::PublicKey { private_key, passphrase } => {
+                debug!("使用公钥认证: {}", private_key);
+                let key_pair = load_secret_key(private_key, passphrase.as_deref())
                     .context("无法加载私钥")?;
                 session
                     .authenticate_publickey(self.config.username.clone(), Arc::new(key_pair))
                     .await
+                    .context("认证失败")?
             }
+            AuthMethod::Agent => {
+                debug!("使用 SSH agent 认证");
+                Self::authenticate_agent(&mut session, &self.config.username).await?
+            }
+        };
+
+        // 密钥或 agent 被拒绝时与 OpenSSH 一样询问密码重试（--auth key 时不回退）
+        let key_auth = !matches!(self.config.auth, AuthMethod::Password(_));
+        let mut auth_method = match self.config.auth {
+            AuthMethod::Password(_) => "password",
+            AuthMethod::PublicKey { .. } => "publickey",
+            AuthMethod::Agent => "agent",
         };
+        if !authenticated && key_auth && ssh::can_fall_back() {
+            let password = ssh::ask_fallback_password(&mut prompt::Terminal, &self.config.username, &self.config.host)?;
+            authenticated = session
+                .authenticate_password(self.config.username.clone(), password.as_str())
+                .await
+                .context("认证失败")?;
+            if authenticated {
+                auth_method = "password";
+                warn!("密钥未被接受，已改用 password 认证 {}@{}；请确认公钥已加入服务器的 authorized_keys", self.config.username, self.config.host);
+            }
+        }
 
-        if !auth_result.context("认证失败")? {
+        if !authenticated {
+            if !key_auth {
+                return Err(ssh::PasswordRejected("认证被拒绝".to_string()).into());
+            }
             return Err(anyhow!("认证被拒绝"));
         }
 
         info!("SSH 连接成功");
+        let host_key = host_key.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(ref blob) = host_key {
+            known_hosts::pin_host_key(&self.config.host, self.config.port, blob);
+        }
+        let fingerprint = host_key.as_deref().map(known_hosts::fingerprint_blob);
+        outcome::connected(&self.config.username, &self.config.host, self.config.port, auth_method, fingerprint);
+        debug_report::authenticated(auth_method);
         self.session = Some(session);
         Ok(())
     }
 
+    /// 依次尝试 agent 中的每个密钥
+    async fn authenticate_agent(
+        session: &mut client::Handle<ClientHandler>,
+        username: &str,
+    ) -> Result<bool> {
+        let mut agent = agent::connect().await?;
+        let identities = agent
+            .request_identities()
+            .await
+            .context("无法从 SSH agent 获取密钥列表")?;
+        if identities.is_empty() {
+            anyhow::bail!("SSH agent 中没有可用的密钥");
+        }
+
+        for key in identities {
+            debug!("尝试 agent 密钥: {}", key.fingerprint());
+            let (returned, result) = session
+                .authenticate_future(username.to_string(), key, agent)
+                .await;
+            agent = returned;
+            if result.context("agent 认证失败")? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     /// 获取会话引用
     pub fn session(&mut self) -> Result<&mut client::Handle<ClientHandler>> {
         self.session.as_mut().ok_or_else(|| anyhow!("未连接"))
@@ -117,7 +264,7 @@ impl RusshClient {
         &self.config
     }
 
-    /// 断开连接
+    /// 断开连接；尚未连接（包括连接被取消）时什么也不做
     pub async fn disconnect(&mut self) -> Result<()> {
         if let Some(session) = self.session.take() {
             session
@@ -136,3 +283,75 @@ impl Drop for RusshClient {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{TestServer, TEST_PASSWORD, TEST_USER};
+
+    fn config(server: &TestServer, auth: AuthMethod) -> SshConfig {
+        SshConfig::new("127.0.0.1".to_string(), server.port, TEST_USER.to_string(), auth)
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_password() {
+        let server = TestServer::start();
+        let mut client = RusshClient::new(config(&server, AuthMethod::Password(TEST_PASSWORD.to_string())));
+        client.connect().await.unwrap();
+        assert!(client.session().is_ok());
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_publickey() {
+        let server = TestServer::start();
+        let mut client = RusshClient::new(config(&server, AuthMethod::PublicKey {
+            private_key: server.client_key_path(),
+            passphrase: None,
+        }));
+        client.connect().await.unwrap();
+        client.disconnect().await.unwrap();
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        let config = SshConfig::new("example.com".to_string(), 22, "user".to_string(), AuthMethod::Password("hunter2".to_string()));
+        let output = format!("{:?}", config);
+        assert!(output.contains("Password(***)"));
+        assert!(!output.contains("hunter"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_cancelled() {
+        // 只接受连接、从不发送 SSH 标识的服务器，连接会一直挂起
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let _accept = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let cancel = CancellationToken::new();
+        let config = SshConfig::new("127.0.0.1".to_string(), port, TEST_USER.to_string(), AuthMethod::Password(TEST_PASSWORD.to_string()));
+        let mut client = RusshClient::new(config).with_cancellation(cancel.clone());
+        let trigger = client.cancellation_token();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+
+        let err = tokio::time::timeout(std::time::Duration::from_secs(5), client.connect())
+            .await
+            .expect("取消后应立即返回")
+            .unwrap_err();
+        assert!(err.downcast_ref::<ConnectCancelled>().is_some());
+        assert!(client.session().is_err());
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejected() {
+        let server = TestServer::start();
+        let mut client = RusshClient::new(config(&server, AuthMethod::Password("wrong".to_string())));
+        assert!(client.connect().await.is_err());
+    }
+}
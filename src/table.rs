@@ -0,0 +1,360 @@
+//! 终端表格输出
+//!
+//! 按显示宽度对齐各列（中日韩字符和 emoji 占两列），单元格可以单独着色。输出到终端时，
+//! 超过终端宽度的表格从最宽的可截断列开始截断，末尾加省略号。`--no-table` 时改为制表符分隔的纯文本
+//! （不着色、不截断、不对齐），便于脚本处理；JSON 输出不经过这里。
+
+use colored::{Color, Colorize};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::glyphs;
+
+/// 是否输出制表符分隔的纯文本（--no-table）
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// 列之间的空格数
+const GAP: usize = 2;
+
+/// 截断后的列至少保留的宽度（含省略号）
+const MIN_WIDTH: usize = 6;
+
+pub fn enable_plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+}
+
+/// 纯文本模式下调用方应省略标题、提示等表格以外的输出
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+struct Column {
+    header: String,
+    align: Align,
+    /// 终端宽度不够时允许截断
+    truncate: bool,
+}
+
+/// 单元格：文本和可选的颜色
+#[derive(Debug, Clone, Default)]
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+    bold: bool,
+}
+
+impl Cell {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), ..Default::default() }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    fn paint(&self, text: &str) -> String {
+        let mut styled = text.normal();
+        if let Some(color) = self.color {
+            styled = styled.color(color);
+        }
+        if self.bold {
+            styled = styled.bold();
+        }
+        styled.to_string()
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::new(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::new(text)
+    }
+}
+
+/// 渲染方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// 对齐的表格；`width` 为终端宽度（None 时不截断）
+    Table { width: Option<usize>, color: bool },
+    /// 制表符分隔
+    Plain,
+}
+
+impl Style {
+    /// 按 `--no-table` 和标准输出是否为终端决定
+    pub fn detect() -> Self {
+        if is_plain() {
+            return Style::Plain;
+        }
+        let width = std::io::stdout()
+            .is_terminal()
+            .then(|| crossterm::terminal::size().ok())
+            .flatten()
+            .map(|(cols, _)| cols as usize);
+        Style::Table { width, color: glyphs::output_style().color }
+    }
+}
+
+/// 表格，先用 [`column`](Table::column) 定义各列，再逐行添加
+#[derive(Default)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+    header: bool,
+    separator: bool,
+    indent: usize,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self { header: true, ..Default::default() }
+    }
+
+    /// 键值对等不需要表头的表格
+    pub fn without_header() -> Self {
+        Self::default()
+    }
+
+    pub fn column(mut self, header: &str, align: Align) -> Self {
+        self.columns.push(Column { header: header.to_string(), align, truncate: false });
+        self
+    }
+
+    /// 终端宽度不够时可以截断的列（名称、命令等）
+    pub fn truncated_column(mut self, header: &str, align: Align) -> Self {
+        self.columns.push(Column { header: header.to_string(), align, truncate: true });
+        self
+    }
+
+    /// 表头下面画一条分隔线
+    pub fn separator(mut self) -> Self {
+        self.separator = true;
+        self
+    }
+
+    /// 每行前的缩进空格数
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// 添加一行，单元格少于列数时补空
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        self.rows.push(cells);
+    }
+
+    pub fn print(&self) {
+        print!("{}", self.render(Style::detect()));
+    }
+
+    pub fn render(&self, style: Style) -> String {
+        match style {
+            Style::Plain => self.render_plain(),
+            Style::Table { width, color } => self.render_table(width, color),
+        }
+    }
+
+    fn render_plain(&self) -> String {
+        let clean = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+        let mut out = String::new();
+        let mut push_line = |fields: Vec<String>| {
+            out.push_str(&fields.join("\t"));
+            out.push('\n');
+        };
+        if self.header {
+            push_line(self.columns.iter().map(|c| clean(&c.header)).collect());
+        }
+        for row in &self.rows {
+            push_line((0..self.columns.len()).map(|i| row.get(i).map_or_else(String::new, |c| clean(&c.text))).collect());
+        }
+        out
+    }
+
+    fn render_table(&self, limit: Option<usize>, color: bool) -> String {
+        let count = self.columns.len();
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .map(|c| if self.header { c.header.width() } else { 0 })
+            .collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().take(count).enumerate() {
+                widths[i] = widths[i].max(cell.text.width());
+            }
+        }
+        if let Some(limit) = limit {
+            self.shrink(&mut widths, limit);
+        }
+
+        let mut out = String::new();
+        if self.header {
+            let cells: Vec<Cell> = self.columns.iter().map(|c| Cell::new(c.header.as_str()).bold()).collect();
+            out.push_str(&self.line(&cells, &widths, color));
+            if self.separator {
+                let total = widths.iter().sum::<usize>() + GAP * count.saturating_sub(1);
+                out.push_str(&format!("{}{}\n", " ".repeat(self.indent), "-".repeat(total)));
+            }
+        }
+        for row in &self.rows {
+            out.push_str(&self.line(row, &widths, color));
+        }
+        out
+    }
+
+    /// 总宽度超过 `limit` 时，每次把最宽的可截断列缩小一列，直到放得下或都已缩到最小
+    fn shrink(&self, widths: &mut [usize], limit: usize) {
+        let total = |widths: &[usize]| self.indent + widths.iter().sum::<usize>() + GAP * widths.len().saturating_sub(1);
+        while total(widths) > limit {
+            let widest = (0..widths.len())
+                .filter(|&i| self.columns[i].truncate && widths[i] > MIN_WIDTH)
+                .max_by_key(|&i| (widths[i], std::cmp::Reverse(i)));
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    fn line(&self, cells: &[Cell], widths: &[usize], color: bool) -> String {
+        let mut line = " ".repeat(self.indent);
+        let last = widths.len().saturating_sub(1);
+        for (i, &width) in widths.iter().enumerate() {
+            let empty = Cell::default();
+            let cell = cells.get(i).unwrap_or(&empty);
+            let text = truncate(&cell.text, width);
+            let padding = " ".repeat(width - text.width());
+            let painted = if color { cell.paint(&text) } else { text };
+            match self.columns[i].align {
+                Align::Left if i == last => line.push_str(&painted),
+                Align::Left => {
+                    line.push_str(&painted);
+                    line.push_str(&padding);
+                }
+                Align::Right => {
+                    line.push_str(&padding);
+                    line.push_str(&painted);
+                }
+            }
+            if i != last {
+                line.push_str(&" ".repeat(GAP));
+            }
+        }
+        line.truncate(line.trim_end_matches(' ').len());
+        line.push('\n');
+        line
+    }
+}
+
+/// 按显示宽度截断，超出时以省略号（`…`，ASCII 下为 `...`）结尾
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let ellipsis = glyphs::ellipsis();
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + ellipsis.width() > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push_str(ellipsis);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Table {
+        let mut table = Table::new()
+            .truncated_column("名称", Align::Left)
+            .column("大小", Align::Right)
+            .column("类型", Align::Left)
+            .separator();
+        table.row(vec!["readme.md".into(), "1.2 KB".into(), "文件".into()]);
+        table.row(vec!["日志目录".into(), "-".into(), Cell::new("目录").color(Color::Blue)]);
+        table.row(vec!["a-very-long-file-name-from-the-build-server.tar.gz".into(), "512 MB".into(), "文件".into()]);
+        table
+    }
+
+    #[test]
+    fn test_cjk_alignment() {
+        let out = files().render(Style::Table { width: None, color: false });
+        assert_eq!(
+            out,
+            "\
+名称                                                  大小  类型
+----------------------------------------------------------------
+readme.md                                           1.2 KB  文件
+日志目录                                                 -  目录
+a-very-long-file-name-from-the-build-server.tar.gz  512 MB  文件
+"
+        );
+    }
+
+    #[test]
+    fn test_narrow_terminal_truncates() {
+        let out = files().render(Style::Table { width: Some(30), color: false });
+        assert_eq!(
+            out,
+            "\
+名称                大小  类型
+------------------------------
+readme.md         1.2 KB  文件
+日志目录               -  目录
+a-very-long-fil…  512 MB  文件
+"
+        );
+        // 宽度很小时截断列保留最小宽度，不截断的列保持完整
+        let out = files().render(Style::Table { width: Some(10), color: false });
+        assert!(out.lines().nth(4).unwrap().starts_with("a-ver…  512 MB"));
+    }
+
+    #[test]
+    fn test_long_hostname_and_cjk_truncation() {
+        let mut table = Table::new().indent(2).column("名称", Align::Left).truncated_column("地址", Align::Left);
+        table.row(vec!["生产数据库".into(), "deploy@db-primary.eu-west-1.internal.example.com:2222".into()]);
+        table.row(vec!["web".into(), "root@web:22".into()]);
+        let out = table.render(Style::Table { width: Some(32), color: false });
+        assert_eq!(
+            out,
+            "  名称        地址\n  生产数据库  deploy@db-primary…\n  web         root@web:22\n"
+        );
+        assert_eq!(truncate("测试服务器", 7), "测试服…");
+        assert_eq!(truncate("测试服务器", 6), "测试…");
+    }
+
+    #[test]
+    fn test_plain_and_color() {
+        let out = files().render(Style::Plain);
+        assert_eq!(out.lines().next().unwrap(), "名称\t大小\t类型");
+        assert_eq!(out.lines().nth(2).unwrap(), "日志目录\t-\t目录");
+        assert_eq!(out.lines().count(), 4);
+
+        let out = files().render(Style::Table { width: None, color: true });
+        let line = out.lines().nth(3).unwrap();
+        assert!(line.contains(&"目录".blue().to_string()));
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::debug;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::glyphs;
+use crate::remote_os::shell_quote;
+use crate::sftp::SftpClient;
+use crate::ssh::SshClient;
+
+/// 从文件末尾向前读取的块大小
+const CHUNK_SIZE: u64 = 8192;
+
+/// 检查停止标志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// tail 选项
+#[derive(Debug, Clone, Copy)]
+pub struct TailOptions {
+    /// 先输出的末尾行数
+    pub lines: usize,
+    /// 持续输出新追加的内容
+    pub follow: bool,
+    /// 文件不存在（或轮转期间暂时消失）时继续等待
+    pub retry: bool,
+    /// `follow` 时两次 stat 之间的间隔
+    pub interval: Duration,
+}
+
+/// 通过 SFTP 输出远程文件的最后几行，`follow` 时轮询并输出新追加的内容
+///
+/// 每隔 `interval` 对路径执行一次 stat：变大时从已打开的句柄继续读取；变小时视为被截断或轮转，
+/// 重新打开并从头读取。设置 `stop` 后正常返回。
+pub fn tail_sftp(
+    sftp: &SftpClient,
+    remote_path: &str,
+    options: &TailOptions,
+    stop: &AtomicBool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let path = Path::new(remote_path);
+    let mut current = match sftp.stat(remote_path) {
+        Ok(info) => {
+            if info.is_dir {
+                anyhow::bail!("远程路径是目录: {}", remote_path);
+            }
+            let mut file = sftp.open_reader(path)?;
+            let offset = write_last_lines(&mut file, info.size, options.lines, out)
+                .context(format!("读取远程文件失败: {}", remote_path))?;
+            Some((file, offset))
+        }
+        Err(e) if options.follow && options.retry => {
+            eprintln!("{} {:#}，等待文件出现...", glyphs::arrow().cyan(), e);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    out.flush()?;
+    if !options.follow {
+        return Ok(());
+    }
+
+    while wait(options.interval, stop) {
+        let size = match sftp.stat(remote_path) {
+            Ok(info) => info.size,
+            Err(e) if options.retry => {
+                if current.take().is_some() {
+                    eprintln!("{} {:#}，等待文件重新出现...", glyphs::arrow().cyan(), e);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (file, offset) = match current {
+            Some((_, offset)) if size < offset => {
+                eprintln!("{} 文件被截断或已轮转，从头读取: {}", glyphs::arrow().cyan(), remote_path);
+                current.insert((sftp.open_reader(path)?, 0))
+            }
+            Some(ref mut current) => current,
+            None => {
+                debug!("文件已出现: {}", remote_path);
+                current.insert((sftp.open_reader(path)?, 0))
+            }
+        };
+        if size > *offset {
+            *offset += io::copy(file, out).context(format!("读取远程文件失败: {}", remote_path))?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// 在远程执行 `tail` 并转发输出（需要 shell），`follow` 时使用 `tail -F`，设置 `stop` 后关闭通道
+pub fn tail_exec(
+    client: &SshClient,
+    remote_path: &str,
+    options: &TailOptions,
+    stop: &AtomicBool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    client.remote_info().require_unix("远程 tail")?;
+    let command = tail_command(remote_path, options);
+    debug!("执行命令: {}", command);
+
+    let _io = client.lock_io();
+    let session = client.session();
+    let mut channel = session.channel_session().context("无法创建通道")?;
+    channel.exec(&command).context("命令执行失败")?;
+
+    // 非阻塞读取，才能在没有新内容时检查停止标志
+    session.set_blocking(false);
+    let result = stream_channel(&mut channel, out, stop);
+    session.set_blocking(true);
+    result?;
+
+    if stop.load(Ordering::SeqCst) {
+        let _ = channel.close();
+        return Ok(());
+    }
+    channel.wait_close().context("等待通道关闭失败")?;
+    let status = channel.exit_status().context("获取退出状态失败")?;
+    if status != 0 {
+        let mut stderr = String::new();
+        channel.stderr().read_to_string(&mut stderr).ok();
+        anyhow::bail!("远程 tail 失败（退出码 {}）: {}", status, stderr.trim());
+    }
+    Ok(())
+}
+
+fn tail_command(remote_path: &str, options: &TailOptions) -> String {
+    let follow = if options.follow { " -F" } else { "" };
+    format!("tail -n {}{} -- {}", options.lines, follow, shell_quote(remote_path))
+}
+
+/// 转发通道的标准输出直到命令结束或设置了停止标志
+fn stream_channel(channel: &mut ssh2::Channel, out: &mut dyn Write, stop: &AtomicBool) -> Result<()> {
+    let mut buffer = [0u8; 32 * 1024];
+    while !stop.load(Ordering::SeqCst) {
+        match channel.read(&mut buffer) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => thread::sleep(POLL_INTERVAL),
+            Ok(n) => {
+                out.write_all(&buffer[..n])?;
+                out.flush()?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(anyhow::Error::new(e).context("读取命令输出失败")),
+        }
+    }
+    Ok(())
+}
+
+/// 等待 `interval`，期间设置了停止标志时返回 false
+fn wait(interval: Duration, stop: &AtomicBool) -> bool {
+    let deadline = Instant::now() + interval;
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// 输出大小为 `size` 的文件的最后 `lines` 行，返回读取结束时的偏移量（即 `size`）
+///
+/// 从末尾按块向前读取，直到找到足够的换行符，大文件也只读取需要的部分。
+fn write_last_lines<R: Read + Seek>(file: &mut R, size: u64, lines: usize, out: &mut dyn Write) -> io::Result<u64> {
+    let mut data = Vec::new();
+    let mut start = size;
+    let skip = loop {
+        if let Some(skip) = last_lines_start(&data, lines) {
+            break skip;
+        }
+        if start == 0 {
+            break 0;
+        }
+        let chunk = CHUNK_SIZE.min(start);
+        start -= chunk;
+        file.seek(SeekFrom::Start(start))?;
+        let mut block = vec![0u8; chunk as usize];
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&data);
+        data = block;
+    };
+    out.write_all(&data[skip..])?;
+    Ok(size)
+}
+
+/// 在文件末尾的一段数据中找到最后 `lines` 行的起始位置，数据中的行数不够时返回 None
+///
+/// 末尾的换行符不算作新的一行（与 tail 一致）。
+fn last_lines_start(data: &[u8], lines: usize) -> Option<usize> {
+    if lines == 0 {
+        return Some(data.len());
+    }
+    let body = data.strip_suffix(b"\n").unwrap_or(data);
+    body.iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(lines - 1)
+        .map(|(newline, _)| newline + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+
+    fn options(lines: usize, follow: bool) -> TailOptions {
+        TailOptions { lines, follow, retry: false, interval: Duration::from_millis(50) }
+    }
+
+    #[test]
+    fn test_last_lines_start() {
+        assert_eq!(last_lines_start(b"a\nb\nc\n", 2), Some(2));
+        assert_eq!(last_lines_start(b"a\nb\nc", 1), Some(4));
+        assert_eq!(last_lines_start(b"a\nb\nc", 0), Some(5));
+        // 行数不够时需要继续向前读取
+        assert_eq!(last_lines_start(b"b\nc\n", 2), None);
+        assert_eq!(last_lines_start(b"", 1), None);
+    }
+
+    #[test]
+    fn test_write_last_lines_across_chunks() {
+        let content: Vec<u8> = (0..3000).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+        let mut out = Vec::new();
+        let size = content.len() as u64;
+        assert_eq!(write_last_lines(&mut Cursor::new(&content), size, 2000, &mut out).unwrap(), size);
+        assert!(out.starts_with(b"line 1000\n"));
+        assert!(out.ends_with(b"line 2999\n"));
+
+        // 行数比文件多时输出整个文件
+        let mut out = Vec::new();
+        write_last_lines(&mut Cursor::new(b"x\ny"), 3, 10, &mut out).unwrap();
+        assert_eq!(out, b"x\ny");
+    }
+
+    #[test]
+    fn test_tail_sftp_follow() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = server.local_path("/app.log");
+        std::fs::write(&local, "one\ntwo\nthree\n").unwrap();
+
+        let mut out = Vec::new();
+        let stop = AtomicBool::new(false);
+        tail_sftp(&sftp, "/app.log", &options(2, false), &stop, &mut out).unwrap();
+        assert_eq!(out, b"two\nthree\n");
+
+        // 追加后输出新内容，截断后从头读取
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                let mut file = std::fs::OpenOptions::new().append(true).open(&local).unwrap();
+                file.write_all(b"four\n").unwrap();
+                thread::sleep(Duration::from_millis(300));
+                std::fs::write(&local, "new\n").unwrap();
+                thread::sleep(Duration::from_millis(300));
+                stop.store(true, Ordering::SeqCst);
+            })
+        };
+        let mut out = Vec::new();
+        tail_sftp(&sftp, "/app.log", &options(1, true), &stop, &mut out).unwrap();
+        writer.join().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "three\nfour\nnew\n");
+
+        let err = tail_sftp(&sftp, "/missing.log", &options(1, true), &stop, &mut Vec::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("missing.log"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tail_exec() {
+        let server = TestServer::start();
+        let client = server.connect();
+        std::fs::write(server.local_path("/app.log"), "one\ntwo\nthree\n").unwrap();
+
+        let mut out = Vec::new();
+        tail_exec(&client, "app.log", &options(2, false), &AtomicBool::new(false), &mut out).unwrap();
+        assert_eq!(out, b"two\nthree\n");
+        assert_eq!(tail_command("a b.log", &options(5, true)), "tail -n 5 -F -- 'a b.log'");
+    }
+}
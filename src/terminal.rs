@@ -1,13 +1,18 @@
 use anyhow::{Context, Result};
-use crossterm::{
-    event::{KeyCode, KeyEvent, KeyModifiers},
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
+use colored::Colorize;
 use log::{debug, error, info};
+use std::collections::HashMap;
 use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+use crate::console::{self, RawModeGuard};
+use crate::credentials;
+use crate::early_close::EarlyClose;
+use crate::glyphs;
+use crate::history::History;
+use crate::snippet;
 use crate::ssh::SshClient;
 
 /// 交互式 SSH 终端
@@ -53,257 +58,278 @@ impl<'a> InteractiveTerminal<'a> {
 
         debug!("准备启用原始模式");
         // 启用原始模式
-        enable_raw_mode().context("无法启用原始模式")?;
+        let raw_mode = RawModeGuard::enable()?;
         debug!("原始模式已启用");
 
         debug!("准备进入 shell 循环");
-        let result = self.run_shell_loop(&mut channel);
+        let result = relay_channel(&mut channel, true);
         debug!("shell 循环已退出");
 
         // 恢复终端
-        disable_raw_mode().context("无法禁用原始模式")?;
+        drop(raw_mode);
 
-        result
+        let early = result?;
+        if let Some(report) = early.report() {
+            eprintln!("\n{} {}", glyphs::warn().yellow(), report);
+        }
+        println!("\n\n=== Shell 会话已结束 ===");
+
+        Ok(())
     }
     
-    /// 运行 shell 循环
-    fn run_shell_loop(&self, channel: &mut ssh2::Channel) -> Result<()> {
-        debug!("进入 run_shell_loop");
-
-        // 克隆通道用于读取线程
-        debug!("准备克隆通道");
-        let mut channel_clone = channel.stream(0);
-        debug!("通道已克隆");
-
-        // 启动读取线程（从 SSH 读取并输出到终端）
-        debug!("准备启动读取线程");
-        let read_handle = thread::spawn(move || {
-            debug!("读取线程已启动");
-            let mut buffer = [0u8; 8192];
-
-            loop {
-                match channel_clone.read(&mut buffer) {
-                    Ok(0) => {
-                        debug!("读取线程: 收到 EOF");
-                        break;
-                    }
-                    Ok(n) => {
-                        debug!("读取线程: 读取到 {} 字节", n);
-
-                        // 过滤掉 CPR (Cursor Position Report) 等控制序列
-                        let filtered = filter_control_sequences(&buffer[..n]);
-
-                        // 输出到标准输出
-                        if !filtered.is_empty() {
-                            if let Err(e) = io::stdout().write_all(&filtered) {
-                                error!("写入标准输出失败: {}", e);
-                                break;
-                            }
-                            if let Err(e) = io::stdout().flush() {
-                                error!("刷新标准输出失败: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("从 SSH 读取失败: {}", e);
-                        break;
-                    }
+    /// 在 PTY 中执行单个命令，返回远程命令的退出码
+    pub fn exec_with_pty(&self, command: &str) -> Result<i32> {
+        info!("在 PTY 中执行命令: {}", credentials::redacted(command));
+
+        let mut channel = self.ssh_client.session().channel_session()
+            .context("无法创建 SSH 通道")?;
+
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        channel.request_pty("xterm", None, Some((cols as u32, rows as u32, 0, 0)))
+            .context("无法请求 PTY")?;
+
+        channel.exec(command)
+            .context("命令执行失败")?;
+
+        let raw_mode = RawModeGuard::enable()?;
+        // Ctrl+C 等按键交给远程 PTY 处理
+        let result = relay_channel(&mut channel, false);
+        drop(raw_mode);
+        result?;
+
+        channel.exit_status().context("获取退出状态失败")
+    }
+    
+    /// 执行单个命令（非交互式）
+    pub fn exec_command(&self, command: &str) -> Result<()> {
+        // 命令中可能有本次输入过的密码
+        self.exec_command_shown(command, &credentials::redacted(command))
+    }
+    
+    /// 执行单个命令，显示和记录日志时使用 `shown`（命令片段展开密文前的模板）
+    pub fn exec_command_shown(&self, command: &str, shown: &str) -> Result<()> {
+        println!("执行命令: {}", shown);
+        let (output, _) = self.ssh_client.exec_command_shown(command, shown)?;
+        print!("{}", output);
+        Ok(())
+    }
+}
+
+/// 在本地终端与已打开的 SSH 通道之间双向转发数据，直到通道关闭
+///
+/// `exit_on_ctrl` 为 true 时，Ctrl+C / Ctrl+D 会结束会话而不是发送给远程。返回用户输入前服务器的输出和
+/// 退出状态，交互式 shell 用来判断会话是否被服务器立即关闭。
+pub fn relay_channel(channel: &mut ssh2::Channel, exit_on_ctrl: bool) -> Result<EarlyClose> {
+    debug!("进入 relay_channel");
+
+    // 克隆通道用于读取线程
+    debug!("准备克隆通道");
+    let mut channel_clone = channel.stream(0);
+    debug!("通道已克隆");
+    let early = Arc::new(Mutex::new(EarlyClose::new()));
+    let reader_early = early.clone();
+
+    // 启动读取线程（从 SSH 读取并输出到终端）
+    debug!("准备启动读取线程");
+    let read_handle = thread::spawn(move || {
+        debug!("读取线程已启动");
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            match channel_clone.read(&mut buffer) {
+                Ok(0) => {
+                    debug!("读取线程: 收到 EOF");
+                    reader_early.lock().unwrap_or_else(|e| e.into_inner()).closed();
+                    break;
                 }
-            }
-        });
-        debug!("读取线程已启动完成");
-
-        // 主循环（使用两个线程：一个读取 stdin，一个写入 SSH）
-        debug!("准备进入主循环");
-
-        use std::sync::mpsc;
-
-        // 创建通道用于线程间通信
-        let (tx, rx) = mpsc::channel::<u8>();
-
-        // 启动 stdin 读取线程
-        let _stdin_handle = thread::spawn(move || {
-            use std::io::stdin;
-            let mut stdin = stdin();
-            let mut input_buffer = [0u8; 1];
-
-            loop {
-                match stdin.read(&mut input_buffer) {
-                    Ok(1) => {
-                        let byte = input_buffer[0];
-                        debug!("stdin 线程: 读取到字节 {} (0x{:02x})", byte, byte);
-                        if tx.send(byte).is_err() {
-                            debug!("stdin 线程: 发送失败，退出");
+                Ok(n) => {
+                    debug!("读取线程: 读取到 {} 字节", n);
+                    reader_early.lock().unwrap_or_else(|e| e.into_inner()).output(&buffer[..n]);
+
+                    // 过滤掉 CPR (Cursor Position Report) 等控制序列
+                    let filtered = filter_control_sequences(&buffer[..n]);
+
+                    // 输出到标准输出
+                    if !filtered.is_empty() {
+                        if let Err(e) = io::stdout().write_all(&filtered) {
+                            error!("写入标准输出失败: {}", e);
+                            break;
+                        }
+                        if let Err(e) = io::stdout().flush() {
+                            error!("刷新标准输出失败: {}", e);
                             break;
                         }
                     }
-                    Ok(0) => {
-                        debug!("stdin 线程: EOF");
-                        break;
-                    }
-                    Ok(_) => {}
-                    Err(e) => {
-                        error!("stdin 线程: 读取失败: {}", e);
-                        break;
-                    }
+                }
+                Err(e) => {
+                    error!("从 SSH 读取失败: {}", e);
+                    break;
                 }
             }
-        });
+        }
+    });
+    debug!("读取线程已启动完成");
 
-        // 主线程：接收字节并发送到 SSH
-        let mut byte_count = 0;
-        loop {
-            // 使用超时接收，这样可以定期检查通道状态
-            match rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(byte) => {
-                    byte_count += 1;
-                    debug!("主循环: 收到字节 #{}: {} (0x{:02x})", byte_count, byte, byte);
-
-                    // 检查 Ctrl+D (0x04) 或 Ctrl+C (0x03)
-                    if byte == 0x04 || byte == 0x03 {
-                        debug!("检测到 Ctrl+D/C，退出");
-                        break;
-                    }
+    // 主循环（使用两个线程：一个读取 stdin，一个写入 SSH）
+    debug!("准备进入主循环");
 
-                    // 过滤掉 CPR 序列的开始（ESC）
-                    if byte == 0x1b {
-                        debug!("主循环: 跳过 ESC 字节（可能是 CPR）");
-                        continue;
-                    }
+    use std::sync::mpsc;
 
-                    // 发送字节到 SSH
-                    debug!("主循环: 准备发送字节到 SSH");
-                    match channel.write(&[byte]) {
-                        Ok(n) => {
-                            debug!("主循环: write 成功，写入了 {} 字节", n);
-                        }
-                        Err(e) => {
-                            error!("主循环: write 失败: {}", e);
-                            break;
-                        }
+    // 创建通道用于线程间通信
+    let (tx, rx) = mpsc::channel::<u8>();
+
+    // 启动 stdin 读取线程（Windows 上读取按键事件）
+    let _stdin_handle = thread::spawn(move || {
+        let mut stdin = console::input();
+        let mut input_buffer = [0u8; 1];
+
+        loop {
+            match stdin.read(&mut input_buffer) {
+                Ok(1) => {
+                    // 不记录输入内容：其中可能有 sudo 等命令的密码
+                    if tx.send(input_buffer[0]).is_err() {
+                        debug!("stdin 线程: 发送失败，退出");
+                        break;
                     }
-                    debug!("主循环: 字节已发送，继续循环");
                 }
-                Err(mpsc::RecvTimeoutError::Timeout) => {
-                    // 超时，继续循环
+                Ok(0) => {
+                    debug!("stdin 线程: EOF");
+                    break;
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => {
-                    debug!("主循环: stdin 线程已断开");
+                Ok(_) => {}
+                Err(e) => {
+                    error!("stdin 线程: 读取失败: {}", e);
                     break;
                 }
             }
+        }
+    });
+
+    // 主线程：接收字节并发送到 SSH
+    let mut byte_count = 0;
+    loop {
+        // 使用超时接收，这样可以定期检查通道状态
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(byte) => {
+                early.lock().unwrap_or_else(|e| e.into_inner()).input();
+                byte_count += 1;
+                debug!("主循环: 收到第 {} 个输入字节", byte_count);
+
+                // 检查 Ctrl+D (0x04) 或 Ctrl+C (0x03)
+                if exit_on_ctrl && (byte == 0x04 || byte == 0x03) {
+                    debug!("检测到 Ctrl+D/C，退出");
+                    break;
+                }
+
+                // 过滤掉 CPR 序列的开始（ESC），按键事件转换的输入中不会有 CPR
+                if byte == 0x1b && !console::KEY_EVENTS {
+                    debug!("主循环: 跳过 ESC 字节（可能是 CPR）");
+                    continue;
+                }
 
-            // 检查通道是否已关闭
-            if channel.eof() {
-                debug!("SSH 通道已关闭");
+                // 发送字节到 SSH
+                debug!("主循环: 准备发送字节到 SSH");
+                match channel.write(&[byte]) {
+                    Ok(n) => {
+                        debug!("主循环: write 成功，写入了 {} 字节", n);
+                    }
+                    Err(e) => {
+                        error!("主循环: write 失败: {}", e);
+                        break;
+                    }
+                }
+                debug!("主循环: 字节已发送，继续循环");
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // 超时，继续循环
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                debug!("主循环: stdin 线程已断开");
                 break;
             }
         }
 
-        // 等待读取线程结束
-        let _ = read_handle.join();
+        // 检查通道是否已关闭
+        if channel.eof() {
+            debug!("SSH 通道已关闭");
+            break;
+        }
+    }
 
-        // 关闭通道
-        channel.close().ok();
-        channel.wait_close().ok();
+    // 等待读取线程结束
+    let _ = read_handle.join();
+
+    let mut early = Arc::try_unwrap(early)
+        .map(|early| early.into_inner().unwrap_or_else(|e| e.into_inner()))
+        .unwrap_or_default();
+    if early.is_early() {
+        // 没有 PTY 时拒绝原因可能在标准错误中
+        let mut stderr = Vec::new();
+        let _ = channel.stderr().read_to_end(&mut stderr);
+        early.output(&stderr);
+    }
 
-        println!("\n\n=== Shell 会话已结束 ===");
+    // 关闭通道
+    channel.close().ok();
+    channel.wait_close().ok();
 
-        Ok(())
+    if let Ok(code) = channel.exit_status() {
+        early.exit_status(code as u32);
     }
-    
-    /// 执行单个命令（非交互式）
-    pub fn exec_command(&self, command: &str) -> Result<()> {
-        println!("执行命令: {}", command);
-        let output = self.ssh_client.exec_command(command)?;
-        print!("{}", output);
-        Ok(())
-    }
-}
-
-/// 将按键事件转换为字节
-#[allow(dead_code)]
-fn key_to_bytes(key_event: &KeyEvent) -> Option<Vec<u8>> {
-    match key_event.code {
-        KeyCode::Char(c) => {
-            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                // Ctrl+字母组合
-                match c {
-                    'a'..='z' => Some(vec![(c as u8) - b'a' + 1]),
-                    'A'..='Z' => Some(vec![(c as u8) - b'A' + 1]),
-                    '@' => Some(vec![0x00]), // Ctrl+@
-                    '[' => Some(vec![0x1b]), // Ctrl+[
-                    '\\' => Some(vec![0x1c]), // Ctrl+\
-                    ']' => Some(vec![0x1d]), // Ctrl+]
-                    '^' => Some(vec![0x1e]), // Ctrl+^
-                    '_' => Some(vec![0x1f]), // Ctrl+_
-                    '?' => Some(vec![0x7f]), // Ctrl+?
-                    _ => None,
-                }
-            } else if key_event.modifiers.contains(KeyModifiers::ALT) {
-                // Alt+字符组合
-                let mut bytes = vec![0x1b];
-                bytes.extend(c.to_string().into_bytes());
-                Some(bytes)
-            } else {
-                // 普通字符
-                Some(c.to_string().into_bytes())
-            }
-        }
-        KeyCode::Enter => Some(vec![b'\r']),
-        KeyCode::Backspace => Some(vec![0x7f]),
-        KeyCode::Tab => Some(vec![b'\t']),
-        KeyCode::Esc => Some(vec![0x1b]),
-        KeyCode::Up => Some(vec![0x1b, b'[', b'A']),
-        KeyCode::Down => Some(vec![0x1b, b'[', b'B']),
-        KeyCode::Right => Some(vec![0x1b, b'[', b'C']),
-        KeyCode::Left => Some(vec![0x1b, b'[', b'D']),
-        KeyCode::Home => Some(vec![0x1b, b'[', b'H']),
-        KeyCode::End => Some(vec![0x1b, b'[', b'F']),
-        KeyCode::PageUp => Some(vec![0x1b, b'[', b'5', b'~']),
-        KeyCode::PageDown => Some(vec![0x1b, b'[', b'6', b'~']),
-        KeyCode::Delete => Some(vec![0x1b, b'[', b'3', b'~']),
-        KeyCode::Insert => Some(vec![0x1b, b'[', b'2', b'~']),
-        KeyCode::F(n) => {
-            // F1-F12 功能键
-            match n {
-                1 => Some(vec![0x1b, b'O', b'P']),
-                2 => Some(vec![0x1b, b'O', b'Q']),
-                3 => Some(vec![0x1b, b'O', b'R']),
-                4 => Some(vec![0x1b, b'O', b'S']),
-                5 => Some(vec![0x1b, b'[', b'1', b'5', b'~']),
-                6 => Some(vec![0x1b, b'[', b'1', b'7', b'~']),
-                7 => Some(vec![0x1b, b'[', b'1', b'8', b'~']),
-                8 => Some(vec![0x1b, b'[', b'1', b'9', b'~']),
-                9 => Some(vec![0x1b, b'[', b'2', b'0', b'~']),
-                10 => Some(vec![0x1b, b'[', b'2', b'1', b'~']),
-                11 => Some(vec![0x1b, b'[', b'2', b'3', b'~']),
-                12 => Some(vec![0x1b, b'[', b'2', b'4', b'~']),
-                _ => None,
-            }
-        }
-        _ => None,
+    if let Some(signal) = channel.exit_signal().ok().and_then(|signal| signal.exit_signal) {
+        early.exit_signal(signal);
     }
+    Ok(early)
 }
 
 /// 简单的命令行 shell（非原始模式）
 pub struct SimpleShell<'a> {
     ssh_client: &'a SshClient,
+    snippets: HashMap<String, String>,
+    history: Option<History>,
 }
 
 impl<'a> SimpleShell<'a> {
     pub fn new(ssh_client: &'a SshClient) -> Self {
-        Self { ssh_client }
+        Self {
+            ssh_client,
+            snippets: HashMap::new(),
+            history: None,
+        }
+    }
+    
+    /// 记录执行的命令，并启用 `history`、`!N`、`!!`
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+    
+    /// 设置可通过 `:snippet <name>` 执行的命令片段
+    pub fn with_snippets(mut self, snippets: HashMap<String, String>) -> Self {
+        self.snippets = snippets;
+        self
+    }
+    
+    /// 展开 `:snippet <name> [key=value...]`
+    fn expand_snippet(&self, args: &str) -> Result<String> {
+        let mut parts = args.split_whitespace();
+        let name = parts.next().context("用法: :snippet <名称> [key=value...]")?;
+        let template = self.snippets.get(name)
+            .context(format!("命令片段 '{}' 不存在", name))?;
+        let vars = snippet::parse_vars(&parts.collect::<Vec<_>>())?;
+        snippet::expand(template, &vars)
     }
     
     /// 启动简单的命令行界面
-    pub fn start(&self) -> Result<()> {
+    pub fn start(&mut self) -> Result<()> {
         println!("=== SSH 命令行模式 ===");
         println!("连接到: {}@{}", 
             self.ssh_client.config().username, 
             self.ssh_client.config().host);
         println!("输入命令并按回车执行，输入 'exit' 或 'quit' 退出");
+        if self.history.is_some() {
+            println!("输入 'history' 查看命令历史，!N 或 !! 重新执行");
+        }
         println!("=====================\n");
         
         let stdin = io::stdin();
@@ -318,7 +344,9 @@ impl<'a> SimpleShell<'a> {
             let mut input = String::new();
             stdin.read_line(&mut input)?;
             
-            let command = input.trim();
+            // 记录时保留开头的空格，以空格开头的命令按默认规则不记录
+            let line = input.trim_end();
+            let command = line.trim();
             
             if command.is_empty() {
                 continue;
@@ -328,7 +356,50 @@ impl<'a> SimpleShell<'a> {
                 break;
             }
             
-            match self.ssh_client.exec_command(command) {
+            if command == "history" {
+                match self.history {
+                    Some(ref mut history) => {
+                        for (index, entry) in history.entries().iter().enumerate() {
+                            println!("{:>5}  {}", index + 1, entry);
+                        }
+                    }
+                    None => eprintln!("错误: 未启用命令历史"),
+                }
+                continue;
+            }
+            
+            // 展开 !! 和 !N，展开后的命令按新输入记录
+            let (line, command) = match self.history.as_mut().map(|history| history.expand(command)) {
+                Some(Ok(Some(expanded))) => {
+                    println!("{} {}", glyphs::arrow(), expanded);
+                    (expanded.clone(), expanded)
+                }
+                Some(Err(e)) => {
+                    eprintln!("错误: {}", e);
+                    continue;
+                }
+                _ => (line.to_string(), command.to_string()),
+            };
+            if let Some(ref mut history) = self.history {
+                history.record(&line);
+            }
+            
+            let command = if let Some(args) = command.strip_prefix(":snippet") {
+                match self.expand_snippet(args) {
+                    Ok(expanded) => {
+                        println!("{} {}", glyphs::arrow(), expanded);
+                        expanded
+                    }
+                    Err(e) => {
+                        eprintln!("错误: {}", e);
+                        continue;
+                    }
+                }
+            } else {
+                command.to_string()
+            };
+            
+            match self.ssh_client.exec_command(&command) {
                 Ok(output) => print!("{}", output),
                 Err(e) => eprintln!("错误: {}", e),
             }
@@ -356,7 +427,7 @@ fn filter_control_sequences(data: &[u8]) -> Vec<u8> {
                 let c = data[j];
 
                 // CSI 序列的结束字符 (0x40-0x7E)
-                if c >= 0x40 && c <= 0x7E {
+                if (0x40..=0x7E).contains(&c) {
                     // 检查是否是 CPR (Cursor Position Report): ESC[n;mR
                     if c == b'R' {
                         let params = &data[i+2..j];
@@ -1,21 +1,56 @@
 use anyhow::{Context, Result};
+use colored::Colorize;
 use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 use log::{debug, error, info};
-use russh::Channel;
-
+use russh::{Channel, ChannelMsg};
+use std::io::Read;
+use std::time::Duration;
+
+use crate::console::{self, RawModeGuard};
+use crate::early_close::EarlyClose;
+use crate::escape::{EscapeAction, EscapeParser, LineEvent, LineInput, TransferRequest, DEFAULT_ESCAPE};
+use crate::glyphs;
+use crate::recorder::CastRecorder;
+use crate::shell_transfer;
 use crate::ssh_russh::RusshClient;
 
+/// 通道在用户输入前结束时，等待 EOF 之后的退出状态的最长时间
+const EXIT_STATUS_WAIT: Duration = Duration::from_secs(1);
+
+/// 没有 SIGWINCH 的平台上检查本地终端大小的间隔
+#[cfg(not(unix))]
+const RESIZE_POLL: Duration = Duration::from_millis(250);
+
 /// 交互式 SSH 终端（使用 russh）
 pub struct InteractiveTerminal<'a> {
     ssh_client: &'a mut RusshClient,
+    recorder: Option<CastRecorder>,
+    /// 转义字符，`None` 时所有输入都原样发送到远程
+    escape: Option<u8>,
 }
 
 impl<'a> InteractiveTerminal<'a> {
     /// 创建交互式终端
     pub fn new(ssh_client: &'a mut RusshClient) -> Self {
-        Self { ssh_client }
+        Self {
+            ssh_client,
+            recorder: None,
+            escape: Some(DEFAULT_ESCAPE),
+        }
+    }
+
+    /// 设置转义字符，`None` 禁用本地转义和 Ctrl+D/C 退出
+    pub fn with_escape(mut self, escape: Option<u8>) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// 录制会话到 asciicast 文件
+    pub fn with_recorder(mut self, recorder: CastRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
     }
 
     /// 启动交互式 shell 会话
@@ -47,9 +82,9 @@ impl<'a> InteractiveTerminal<'a> {
             .await
             .context("无法请求 PTY")?;
 
-        // 启动 shell
+        // 启动 shell（要求应答，被拒绝时能收到 Failure 而不是一直等待）
         channel
-            .request_shell(false)
+            .request_shell(true)
             .await
             .context("无法启动 shell")?;
 
@@ -59,78 +94,116 @@ impl<'a> InteractiveTerminal<'a> {
             self.ssh_client.config().username,
             self.ssh_client.config().host
         );
-        println!("按 Ctrl+D 或输入 'exit' 退出");
+        match self.escape {
+            Some(escape) => println!(
+                "按 Ctrl+D 或输入 'exit' 退出，行首输入 {}? 查看转义序列",
+                escape as char
+            ),
+            None => println!("本地转义已禁用，输入 'exit' 退出"),
+        }
         println!("========================\n");
 
         debug!("准备启用原始模式");
-        enable_raw_mode().context("无法启用原始模式")?;
+        let raw_mode = RawModeGuard::enable()?;
         debug!("原始模式已启用");
 
         let result = self.run_shell_loop(channel).await;
 
         // 恢复终端
-        disable_raw_mode().context("无法禁用原始模式")?;
+        drop(raw_mode);
 
-        result
+        if let Some(report) = result.as_ref().ok().and_then(EarlyClose::report) {
+            eprintln!("\n{} {}", glyphs::warn().yellow(), report);
+        }
+        result.map(|_| ())
     }
 
-    /// 运行 shell 循环
-    async fn run_shell_loop(&mut self, channel: Channel<russh::client::Msg>) -> Result<()> {
+    /// 运行 shell 循环，返回用户输入前服务器的输出和退出状态
+    async fn run_shell_loop(&mut self, mut channel: Channel<russh::client::Msg>) -> Result<EarlyClose> {
         debug!("进入 run_shell_loop");
 
         use tokio::select;
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::io::AsyncWriteExt;
 
-        // 将 channel 转换为流
-        let mut stream = channel.into_stream();
-
-        // 创建缓冲区
-        let mut ssh_buffer = vec![0u8; 8192];
-        let mut stdin_buffer = [0u8; 1];
-
-        // 使用 tokio 的 stdin（异步）
-        let mut stdin = tokio::io::stdin();
+        // 本地输入在独立线程中读取（Windows 上为按键事件）
+        let mut stdin = spawn_input();
         let mut stdout = tokio::io::stdout();
 
         // CPR 过滤器状态
         let mut cpr_filter = CprFilter::new();
+        let mut escape = self.escape.map(EscapeParser::new);
+        let mut resume = ResumeSignal::new()?;
+        let mut resize = ResizeSignal::new()?;
+        let mut early = EarlyClose::new();
 
         loop {
             select! {
                 // 从 SSH 读取数据
-                result = stream.read(&mut ssh_buffer) => {
-                    match result {
-                        Ok(0) => {
+                msg = channel.wait() => {
+                    if let Some(ref msg) = msg {
+                        early.message(msg);
+                    }
+                    let data = match msg {
+                        Some(ChannelMsg::Data { data }) => data,
+                        Some(ChannelMsg::ExtendedData { data, .. }) => data,
+                        Some(ChannelMsg::Eof) | Some(ChannelMsg::Close) | None => {
                             debug!("SSH 连接已关闭");
+                            early.closed();
                             break;
                         }
-                        Ok(n) => {
-                            debug!("从 SSH 读取到 {} 字节", n);
-
-                            // 过滤控制序列
-                            let filtered = filter_control_sequences(&ssh_buffer[..n]);
-
-                            // 输出到终端
-                            if !filtered.is_empty() {
-                                stdout.write_all(&filtered).await
-                                    .context("写入标准输出失败")?;
-                                stdout.flush().await
-                                    .context("刷新标准输出失败")?;
-                            }
-                        }
-                        Err(e) => {
-                            error!("从 SSH 读取失败: {}", e);
+                        Some(ChannelMsg::Failure) if early.is_early() => {
+                            debug!("shell 请求被拒绝");
                             break;
                         }
+                        Some(_) => continue,
+                    };
+                    debug!("从 SSH 读取到 {} 字节", data.len());
+
+                    // 过滤控制序列
+                    let filtered = filter_control_sequences(&data);
+
+                    // 输出到终端
+                    if !filtered.is_empty() {
+                        if let Some(ref mut recorder) = self.recorder {
+                            recorder.output(&filtered)?;
+                        }
+                        stdout.write_all(&filtered).await
+                            .context("写入标准输出失败")?;
+                        stdout.flush().await
+                            .context("刷新标准输出失败")?;
                     }
                 }
 
+                // 从本地挂起中恢复（SIGCONT）
+                _ = resume.recv() => {
+                    debug!("从挂起中恢复");
+                    enable_raw_mode().context("无法启用原始模式")?;
+                    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+                    self.window_change(&channel, cols, rows).await?;
+                }
+
+                // 本地终端大小变化
+                (cols, rows) = resize.recv() => {
+                    debug!("终端大小变为 {}x{}", cols, rows);
+                    self.window_change(&channel, cols, rows).await?;
+                }
+
                 // 从 stdin 读取数据
-                result = stdin.read(&mut stdin_buffer) => {
-                    match result {
-                        Ok(1) => {
-                            let byte = stdin_buffer[0];
-                            debug!("从 stdin 读取字节: {} (0x{:02x})", byte, byte);
+                input = stdin.recv() => {
+                    match input {
+                        Some(Ok(byte)) => {
+                            early.input();
+                            // 不记录输入内容：其中可能有 sudo 等命令的密码
+
+                            // 禁用转义时所有字节都发送到远程
+                            let Some(ref mut escape) = escape else {
+                                if let Some(ref mut recorder) = self.recorder {
+                                    recorder.input(&[byte])?;
+                                }
+                                channel.data(&[byte][..]).await
+                                    .context("发送数据到 SSH 失败")?;
+                                continue;
+                            };
 
                             // 检查退出条件
                             if byte == 0x04 || byte == 0x03 {
@@ -138,23 +211,61 @@ impl<'a> InteractiveTerminal<'a> {
                                 break;
                             }
 
-                            // 使用 CPR 过滤器处理字节
-                            if let Some(filtered_byte) = cpr_filter.process(byte) {
-                                // 发送到 SSH
-                                stream.write_all(&[filtered_byte]).await
-                                    .context("发送数据到 SSH 失败")?;
-                                stream.flush().await
-                                    .context("刷新 SSH 流失败")?;
-                            } else {
-                                debug!("字节被 CPR 过滤器过滤: {} (0x{:02x})", byte, byte);
+                            // 使用 CPR 过滤器处理字节（按键事件转换的输入中不会有 CPR）
+                            let filtered = if console::KEY_EVENTS { Some(byte) } else { cpr_filter.process(byte) };
+                            let Some(filtered_byte) = filtered else {
+                                continue;
+                            };
+
+                            let bytes = match escape.process(filtered_byte) {
+                                EscapeAction::Send(bytes) => bytes,
+                                EscapeAction::Disconnect => {
+                                    debug!("检测到转义序列，断开连接");
+                                    break;
+                                }
+                                EscapeAction::Suspend => {
+                                    suspend_local(&mut stdout).await?;
+                                    continue;
+                                }
+                                EscapeAction::Help => {
+                                    stdout.write_all(escape.help().as_bytes()).await
+                                        .context("写入标准输出失败")?;
+                                    stdout.flush().await
+                                        .context("刷新标准输出失败")?;
+                                    continue;
+                                }
+                                EscapeAction::Transfer(direction) => {
+                                    let prompt = direction.prompt(self.escape.unwrap_or(DEFAULT_ESCAPE));
+                                    let message = match read_line(&mut stdin, &mut stdout, &prompt).await? {
+                                        None => "已取消".to_string(),
+                                        Some(line) => match self.transfer(TransferRequest::parse(direction, &line), &mut stdout).await {
+                                            Ok(summary) => summary,
+                                            Err(e) => format!("传输失败: {:#}", e),
+                                        },
+                                    };
+                                    stdout.write_all(format!("\r\n[{}]\r\n", message).as_bytes()).await
+                                        .context("写入标准输出失败")?;
+                                    stdout.flush().await
+                                        .context("刷新标准输出失败")?;
+                                    continue;
+                                }
+                            };
+                            if bytes.is_empty() {
+                                continue;
                             }
+
+                            if let Some(ref mut recorder) = self.recorder {
+                                recorder.input(&bytes)?;
+                            }
+                            // 发送到 SSH
+                            channel.data(&bytes[..]).await
+                                .context("发送数据到 SSH 失败")?;
                         }
-                        Ok(0) => {
+                        None => {
                             debug!("stdin EOF");
                             break;
                         }
-                        Ok(_) => {}
-                        Err(e) => {
+                        Some(Err(e)) => {
                             error!("从 stdin 读取失败: {}", e);
                             break;
                         }
@@ -164,8 +275,193 @@ impl<'a> InteractiveTerminal<'a> {
         }
 
         debug!("shell 循环已退出");
+        if early.is_early() {
+            // 退出状态通常在 EOF 之后才到，短暂等待通道关闭
+            let _ = tokio::time::timeout(EXIT_STATUS_WAIT, async {
+                while let Some(msg) = channel.wait().await {
+                    early.message(&msg);
+                    if matches!(msg, ChannelMsg::Close) {
+                        break;
+                    }
+                }
+            })
+            .await;
+        }
+        Ok(early)
+    }
+
+    /// 把本地终端大小同步到远程，并记入录制文件
+    async fn window_change(&mut self, channel: &Channel<russh::client::Msg>, cols: u16, rows: u16) -> Result<()> {
+        channel.window_change(cols as u32, rows as u32, 0, 0).await
+            .context("无法更新远程终端大小")?;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.resize(cols, rows)?;
+        }
         Ok(())
     }
+
+    /// 在同一连接上执行 `~u`/`~g` 传输，期间不读取 shell 通道
+    async fn transfer(&mut self, request: Result<TransferRequest>, stdout: &mut tokio::io::Stdout) -> Result<String> {
+        let request = request?;
+        info!("转义传输: {:?}", request);
+        let config = self.ssh_client.config().clone();
+        let session = self.ssh_client.session()?;
+        shell_transfer::run(session, &config, &request, stdout).await
+    }
+}
+
+/// 在本地读取一行转义命令的参数（不发送到远程），Ctrl+C 或空行返回 `None`
+async fn read_line(
+    stdin: &mut tokio::sync::mpsc::UnboundedReceiver<std::io::Result<u8>>,
+    stdout: &mut tokio::io::Stdout,
+    prompt: &str,
+) -> Result<Option<String>> {
+    use tokio::io::AsyncWriteExt;
+
+    stdout.write_all(format!("\r\n{}", prompt).as_bytes()).await
+        .context("写入标准输出失败")?;
+    stdout.flush().await.context("刷新标准输出失败")?;
+
+    let mut line = LineInput::new();
+    while let Some(input) = stdin.recv().await {
+        let byte = input.context("从 stdin 读取失败")?;
+        match line.process(byte) {
+            LineEvent::Echo(bytes) => {
+                stdout.write_all(&bytes).await.context("写入标准输出失败")?;
+                stdout.flush().await.context("刷新标准输出失败")?;
+            }
+            LineEvent::Done(text) if text.trim().is_empty() => return Ok(None),
+            LineEvent::Done(text) => return Ok(Some(text)),
+            LineEvent::Cancel => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// 在后台线程中逐字节读取本地输入，通道关闭表示 EOF
+fn spawn_input() -> tokio::sync::mpsc::UnboundedReceiver<std::io::Result<u8>> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut input = console::input();
+        let mut buffer = [0u8; 1];
+        loop {
+            let result = match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => Ok(buffer[0]),
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if tx.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// 挂起本进程回到本地 shell（`fg` 恢复后由 `ResumeSignal` 重新进入原始模式）
+#[cfg(unix)]
+async fn suspend_local(stdout: &mut tokio::io::Stdout) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    info!("挂起到本地 shell");
+    stdout.write_all("\r\n[已挂起，使用 fg 恢复]\r\n".as_bytes()).await
+        .context("写入标准输出失败")?;
+    stdout.flush().await.context("刷新标准输出失败")?;
+    disable_raw_mode().context("无法禁用原始模式")?;
+
+    // SIGTSTP 的默认动作是停止进程，收到 SIGCONT 后从这里继续
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn suspend_local(stdout: &mut tokio::io::Stdout) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    stdout.write_all("\r\n[当前平台不支持挂起]\r\n".as_bytes()).await
+        .context("写入标准输出失败")?;
+    stdout.flush().await.context("刷新标准输出失败")?;
+    Ok(())
+}
+
+/// 进程从挂起中恢复的通知（Unix 上为 SIGCONT，其他平台永不触发）
+struct ResumeSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl ResumeSignal {
+    #[cfg(unix)]
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let signal = signal(SignalKind::from_raw(libc::SIGCONT)).context("无法监听 SIGCONT")?;
+        Ok(Self { signal })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Result<Self> {
+        Ok(Self {})
+    }
+
+    #[cfg(unix)]
+    async fn recv(&mut self) {
+        if self.signal.recv().await.is_none() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) {
+        std::future::pending::<()>().await
+    }
+}
+
+/// 本地终端大小变化的通知（Unix 上为 SIGWINCH，其他平台定时检查终端大小），返回新的列数和行数
+struct ResizeSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+    #[cfg(not(unix))]
+    size: (u16, u16),
+}
+
+impl ResizeSignal {
+    #[cfg(unix)]
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let signal = signal(SignalKind::window_change()).context("无法监听 SIGWINCH")?;
+        Ok(Self { signal })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Result<Self> {
+        Ok(Self { size: crossterm::terminal::size().unwrap_or((80, 24)) })
+    }
+
+    #[cfg(unix)]
+    async fn recv(&mut self) -> (u16, u16) {
+        if self.signal.recv().await.is_none() {
+            std::future::pending::<()>().await;
+        }
+        crossterm::terminal::size().unwrap_or((80, 24))
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) -> (u16, u16) {
+        loop {
+            tokio::time::sleep(RESIZE_POLL).await;
+            if let Ok(size) = crossterm::terminal::size() {
+                if size != self.size {
+                    self.size = size;
+                    return size;
+                }
+            }
+        }
+    }
 }
 
 /// CPR (Cursor Position Report) 过滤器
@@ -273,7 +569,7 @@ fn filter_control_sequences(data: &[u8]) -> Vec<u8> {
                 let c = data[j];
 
                 // CSI 序列的结束字符 (0x40-0x7E)
-                if c >= 0x40 && c <= 0x7E {
+                if (0x40..=0x7E).contains(&c) {
                     // 检查是否是 CPR (Cursor Position Report): ESC[n;mR
                     if c == b'R' {
                         let params = &data[i + 2..j];
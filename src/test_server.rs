@@ -0,0 +1,647 @@
+//! 测试用的内嵌 SSH 服务器
+//!
+//! 基于 russh 的服务端实现，在后台线程中监听 127.0.0.1 的随机端口，
+//! 支持密码认证、公钥认证、exec（通过本地 `sh -c` 执行）以及 SFTP 子系统。shell 请求默认被拒绝，
+//! 配置强制命令时改为执行该命令（模拟 `authorized_keys` 的 `command=`）。
+//! 所有文件操作都限定在临时目录内，服务器和临时目录会在 `TestServer` 被 drop 时清理。
+
+use async_trait::async_trait;
+use rand::RngCore;
+use russh::server::{self, Auth, Msg, Session};
+use russh::MethodSet;
+use russh::{Channel, ChannelId, CryptoVec};
+use russh_keys::key;
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+use crate::ssh::{AuthMethod, SshClient, SshConfig};
+
+pub const TEST_USER: &str = "tester";
+pub const TEST_PASSWORD: &str = "secret";
+
+/// 内嵌 SSH 服务器
+pub struct TestServer {
+    pub port: u16,
+    /// SFTP 根目录（远程路径 `/foo` 对应 `root/foo`）
+    pub root: TempDir,
+    /// 客户端私钥文件（OpenSSH 格式，无密码）
+    pub client_key: PathBuf,
+    _key_dir: TempDir,
+    /// 接下来多少个 SFTP 请求返回 SSH_FX_FAILURE，见 [`TestServer::fail_sftp`]
+    sftp_failures: Arc<AtomicU32>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// 启动服务器
+    pub fn start() -> Self {
+        Self::start_with(MethodSet::PASSWORD | MethodSet::PUBLICKEY, None)
+    }
+
+    /// 启动只允许公钥认证的服务器
+    pub fn start_key_only() -> Self {
+        Self::start_with(MethodSet::PUBLICKEY, None)
+    }
+
+    /// 启动服务器，shell 请求不启动 shell 而是执行 `command`（例如只允许 git 的账号）
+    pub fn start_with_forced_command(command: &str) -> Self {
+        Self::start_with(MethodSet::PASSWORD | MethodSet::PUBLICKEY, Some(command.to_string()))
+    }
+
+    /// 启动服务器，只接受 `methods` 中的认证方式
+    fn start_with(methods: MethodSet, forced_command: Option<String>) -> Self {
+        let root = TempDir::new().expect("无法创建临时目录");
+        let key_dir = TempDir::new().expect("无法创建临时目录");
+
+        // 生成客户端密钥
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        let keypair = ssh_key::private::Ed25519Keypair::from_seed(&seed);
+        let private_key = ssh_key::PrivateKey::from(keypair);
+        let client_key = key_dir.path().join("id_ed25519");
+        fs::write(
+            &client_key,
+            private_key.to_openssh(ssh_key::LineEnding::LF).unwrap().as_bytes(),
+        )
+        .unwrap();
+        let authorized = russh_keys::load_secret_key(&client_key, None)
+            .unwrap()
+            .clone_public_key()
+            .unwrap();
+
+        let config = Arc::new(server::Config {
+            auth_rejection_time: std::time::Duration::from_millis(10),
+            auth_rejection_time_initial: Some(std::time::Duration::from_millis(0)),
+            keys: vec![key::KeyPair::generate_ed25519().unwrap()],
+            methods,
+            ..Default::default()
+        });
+
+        let std_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        std_listener.set_nonblocking(true).unwrap();
+        let port = std_listener.local_addr().unwrap().port();
+
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+        let root_path = root.path().to_path_buf();
+        let authorized = Arc::new(authorized);
+        let sftp_failures = Arc::new(AtomicU32::new(0));
+        let failures = sftp_failures.clone();
+        let forced_command = Arc::new(forced_command);
+
+        let thread = std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                let listener = TcpListener::from_std(std_listener).unwrap();
+                loop {
+                    tokio::select! {
+                        accepted = listener.accept() => {
+                            let Ok((socket, _)) = accepted else { break };
+                            let handler = ServerHandler {
+                                root: root_path.clone(),
+                                authorized: authorized.clone(),
+                                methods,
+                                channels: HashMap::new(),
+                                sftp_failures: failures.clone(),
+                                forced_command: forced_command.clone(),
+                            };
+                            let config = config.clone();
+                            tokio::spawn(async move {
+                                if let Ok(session) = server::run_stream(config, socket, handler).await {
+                                    let _ = session.await;
+                                }
+                            });
+                        }
+                        _ = &mut shutdown_rx => break,
+                    }
+                }
+            });
+        });
+
+        Self {
+            port,
+            root,
+            client_key,
+            _key_dir: key_dir,
+            sftp_failures,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        }
+    }
+
+    /// 用测试账号的密码连接本服务器的配置
+    pub fn password_config(&self) -> SshConfig {
+        SshConfig {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            username: TEST_USER.to_string(),
+            auth: AuthMethod::Password(TEST_PASSWORD.to_string()),
+        }
+    }
+
+    /// 用测试账号的密码连接本服务器
+    pub fn connect(&self) -> SshClient {
+        SshClient::connect(self.password_config()).unwrap()
+    }
+
+    /// 客户端私钥路径字符串
+    pub fn client_key_path(&self) -> String {
+        self.client_key.to_string_lossy().to_string()
+    }
+
+    /// 将远程路径映射为本地路径
+    pub fn local_path(&self, remote: &str) -> PathBuf {
+        resolve(self.root.path(), remote)
+    }
+
+    /// 让接下来的 `count` 个 SFTP 请求（stat、open、opendir、readdir、mkdir、setstat）返回临时失败
+    ///
+    /// mkdir 会先创建目录再返回失败，模拟请求已执行但应答报错的情况。
+    pub fn fail_sftp(&self, count: u32) {
+        self.sftp_failures.store(count, Ordering::SeqCst);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// 将远程路径解析到根目录下
+fn resolve(root: &Path, remote: &str) -> PathBuf {
+    let mut path = root.to_path_buf();
+    for part in remote.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if path != root {
+                    path.pop();
+                }
+            }
+            other => path.push(other),
+        }
+    }
+    path
+}
+
+struct ServerHandler {
+    root: PathBuf,
+    authorized: Arc<key::PublicKey>,
+    methods: MethodSet,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+    sftp_failures: Arc<AtomicU32>,
+    /// shell 请求执行的命令，为空时拒绝 shell 请求
+    forced_command: Arc<Option<String>>,
+}
+
+impl ServerHandler {
+    /// 拒绝后仍然提供全部认证方式（与 OpenSSH 一致，russh 默认会去掉失败的方式）
+    fn reject(&self) -> Auth {
+        Auth::Reject { proceed_with_methods: Some(self.methods) }
+    }
+}
+
+#[async_trait]
+impl server::Handler for ServerHandler {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        if self.methods.contains(MethodSet::PASSWORD) && user == TEST_USER && password == TEST_PASSWORD {
+            Ok(Auth::Accept)
+        } else {
+            Ok(self.reject())
+        }
+    }
+
+    async fn auth_publickey(
+        &mut self,
+        user: &str,
+        public_key: &key::PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        if user == TEST_USER && public_key.fingerprint() == self.authorized.fingerprint() {
+            Ok(Auth::Accept)
+        } else {
+            Ok(self.reject())
+        }
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn channel_open_direct_tcpip(
+        &mut self,
+        channel: Channel<Msg>,
+        host_to_connect: &str,
+        port_to_connect: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        let Ok(mut stream) = tokio::net::TcpStream::connect((host_to_connect, port_to_connect as u16)).await
+        else {
+            return Ok(false);
+        };
+        tokio::spawn(async move {
+            let mut channel = channel.into_stream();
+            let _ = tokio::io::copy_bidirectional(&mut channel, &mut stream).await;
+        });
+        Ok(true)
+    }
+
+    async fn channel_eof(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // 客户端不再发送数据时关闭通道（libssh2 关闭 SFTP 时会等待服务端的 CLOSE）
+        session.close(channel);
+        Ok(())
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let command = String::from_utf8_lossy(data).to_string();
+        session.channel_success(channel);
+        spawn_command(session.handle(), channel, command, self.root.clone());
+        Ok(())
+    }
+
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel);
+        Ok(())
+    }
+
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match self.forced_command.as_ref() {
+            Some(command) => {
+                session.channel_success(channel);
+                spawn_command(session.handle(), channel, command.clone(), self.root.clone());
+            }
+            None => session.channel_failure(channel),
+        }
+        Ok(())
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match (name, self.channels.remove(&channel)) {
+            ("sftp", Some(chan)) => {
+                session.channel_success(channel);
+                let sftp = SftpHandler {
+                    root: self.root.clone(),
+                    handles: HashMap::new(),
+                    next_handle: 0,
+                    failures: self.sftp_failures.clone(),
+                };
+                russh_sftp::server::run(chan.into_stream(), sftp).await;
+            }
+            _ => session.channel_failure(channel),
+        }
+        Ok(())
+    }
+}
+
+/// 在根目录下用 `sh -c` 执行命令，把输出和退出码发送到通道后关闭通道
+fn spawn_command(handle: server::Handle, channel: ChannelId, command: String, root: PathBuf) {
+    tokio::spawn(async move {
+        use tokio::io::AsyncReadExt;
+
+        let child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .current_dir(&root)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn();
+        let (stderr, code) = match child {
+            Ok(mut child) => {
+                let mut stderr_pipe = child.stderr.take().unwrap();
+                let stderr_task = tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let _ = stderr_pipe.read_to_end(&mut buf).await;
+                    buf
+                });
+                // 标准输出边读边发送，客户端可以在命令结束前收到部分输出
+                let mut stdout = child.stdout.take().unwrap();
+                let mut buf = vec![0u8; 32 * 1024];
+                while let Ok(n @ 1..) = stdout.read(&mut buf).await {
+                    if handle.data(channel, CryptoVec::from_slice(&buf[..n])).await.is_err() {
+                        // 通道已关闭，不再等待命令结束
+                        return;
+                    }
+                }
+                let code = child.wait().await.ok().and_then(|status| status.code()).unwrap_or(255);
+                (stderr_task.await.unwrap_or_default(), code as u32)
+            }
+            Err(e) => (e.to_string().into_bytes(), 127),
+        };
+        if !stderr.is_empty() {
+            let _ = handle.extended_data(channel, 1, CryptoVec::from(stderr)).await;
+        }
+        let _ = handle.exit_status_request(channel, code).await;
+        let _ = handle.eof(channel).await;
+        let _ = handle.close(channel).await;
+    });
+}
+
+enum OpenHandle {
+    File(fs::File),
+    Dir(Option<Vec<File>>),
+}
+
+/// 基于本地临时目录的 SFTP 处理器
+struct SftpHandler {
+    root: PathBuf,
+    handles: HashMap<String, OpenHandle>,
+    next_handle: u64,
+    failures: Arc<AtomicU32>,
+}
+
+fn io_status(e: std::io::Error) -> StatusCode {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NoSuchFile,
+        std::io::ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+/// 按 SFTP 属性设置权限（客户端未指定时保持不变，非 Unix 平台忽略）
+fn set_attributes(path: &Path, attrs: &FileAttributes) -> Result<(), StatusCode> {
+    #[cfg(unix)]
+    if let Some(mode) = attrs.permissions {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777)).map_err(io_status)?;
+    }
+    if let Some(size) = attrs.size {
+        fs::File::options().write(true).open(path).and_then(|f| f.set_len(size)).map_err(io_status)?;
+    }
+    if let Some(mtime) = attrs.mtime {
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime as u64);
+        fs::File::options().write(true).open(path).and_then(|f| f.set_modified(modified)).map_err(io_status)?;
+    }
+    Ok(())
+}
+
+fn ok_status(id: u32) -> Status {
+    Status {
+        id,
+        status_code: StatusCode::Ok,
+        error_message: "Ok".to_string(),
+        language_tag: "en-US".to_string(),
+    }
+}
+
+impl SftpHandler {
+    fn insert(&mut self, handle: OpenHandle) -> String {
+        self.next_handle += 1;
+        let name = self.next_handle.to_string();
+        self.handles.insert(name.clone(), handle);
+        name
+    }
+
+    fn path(&self, remote: &str) -> PathBuf {
+        resolve(&self.root, remote)
+    }
+
+    /// 还有待注入的失败时返回 Failure
+    fn injected_failure(&self) -> Result<(), StatusCode> {
+        match self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+            Ok(_) => Err(StatusCode::Failure),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn stat_path(&self, remote: &str, follow: bool) -> Result<FileAttributes, StatusCode> {
+        let path = self.path(remote);
+        let meta = if follow { fs::metadata(&path) } else { fs::symlink_metadata(&path) };
+        meta.map(|m| FileAttributes::from(&m)).map_err(io_status)
+    }
+}
+
+impl russh_sftp::server::Handler for SftpHandler {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        self.injected_failure()?;
+        let file = fs::OpenOptions::from(pflags)
+            .open(self.path(&filename))
+            .map_err(io_status)?;
+        Ok(Handle { id, handle: self.insert(OpenHandle::File(file)) })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        self.handles.remove(&handle);
+        Ok(ok_status(id))
+    }
+
+    async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+        let Some(OpenHandle::File(file)) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        file.seek(SeekFrom::Start(offset)).map_err(io_status)?;
+        let mut data = vec![0u8; len as usize];
+        let n = file.read(&mut data).map_err(io_status)?;
+        if n == 0 {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(n);
+        Ok(Data { id, data })
+    }
+
+    async fn write(&mut self, id: u32, handle: String, offset: u64, data: Vec<u8>) -> Result<Status, Self::Error> {
+        let Some(OpenHandle::File(file)) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        file.seek(SeekFrom::Start(offset)).map_err(io_status)?;
+        file.write_all(&data).map_err(io_status)?;
+        Ok(ok_status(id))
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        let Some(OpenHandle::File(file)) = self.handles.get(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        let meta = file.metadata().map_err(io_status)?;
+        Ok(Attrs { id, attrs: FileAttributes::from(&meta) })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.injected_failure()?;
+        Ok(Attrs { id, attrs: self.stat_path(&path, true)? })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs { id, attrs: self.stat_path(&path, false)? })
+    }
+
+    async fn setstat(&mut self, id: u32, path: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+        self.injected_failure()?;
+        set_attributes(&self.path(&path), &attrs)?;
+        Ok(ok_status(id))
+    }
+
+    async fn fsetstat(&mut self, id: u32, handle: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+        let Some(OpenHandle::File(file)) = self.handles.get(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        #[cfg(unix)]
+        if let Some(mode) = attrs.permissions {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode & 0o7777)).map_err(io_status)?;
+        }
+        #[cfg(not(unix))]
+        let _ = (file, attrs);
+        Ok(ok_status(id))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        self.injected_failure()?;
+        let mut files = vec![
+            File::new(".", FileAttributes::default()),
+            File::new("..", FileAttributes::default()),
+        ];
+        for entry in fs::read_dir(self.path(&path)).map_err(io_status)? {
+            let entry = entry.map_err(io_status)?;
+            let meta = entry.metadata().map_err(io_status)?;
+            files.push(File::new(
+                entry.file_name().to_string_lossy().to_string(),
+                FileAttributes::from(&meta),
+            ));
+        }
+        Ok(Handle { id, handle: self.insert(OpenHandle::Dir(Some(files))) })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        self.injected_failure()?;
+        match self.handles.get_mut(&handle) {
+            Some(OpenHandle::Dir(files)) => match files.take() {
+                Some(files) => Ok(Name { id, files }),
+                None => Err(StatusCode::Eof),
+            },
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn mkdir(&mut self, id: u32, path: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+        let path = self.path(&path);
+        fs::create_dir(&path).map_err(io_status)?;
+        set_attributes(&path, &attrs)?;
+        self.injected_failure()?;
+        Ok(ok_status(id))
+    }
+
+    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+        fs::remove_dir(self.path(&path)).map_err(io_status)?;
+        Ok(ok_status(id))
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        fs::remove_file(self.path(&filename)).map_err(io_status)?;
+        Ok(ok_status(id))
+    }
+
+    async fn rename(&mut self, id: u32, oldpath: String, newpath: String) -> Result<Status, Self::Error> {
+        fs::rename(self.path(&oldpath), self.path(&newpath)).map_err(io_status)?;
+        Ok(ok_status(id))
+    }
+
+    async fn readlink(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let target = fs::read_link(self.path(&path)).map_err(io_status)?;
+        Ok(Name { id, files: vec![File::dummy(target.to_string_lossy().to_string())] })
+    }
+
+    /// 与 OpenSSH 的 sftp-server 一样，第一个字段是链接指向的路径，第二个字段是要创建的链接
+    #[cfg(unix)]
+    async fn symlink(&mut self, id: u32, linkpath: String, targetpath: String) -> Result<Status, Self::Error> {
+        std::os::unix::fs::symlink(&linkpath, self.path(&targetpath)).map_err(io_status)?;
+        Ok(ok_status(id))
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let mut parts: Vec<&str> = Vec::new();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                other => parts.push(other),
+            }
+        }
+        let normalized = format!("/{}", parts.join("/"));
+        // 路径存在时与 OpenSSH 一样展开其中的符号链接
+        let resolved = fs::canonicalize(self.path(&normalized))
+            .ok()
+            .zip(fs::canonicalize(&self.root).ok())
+            .and_then(|(path, root)| path.strip_prefix(root).ok().map(|rest| format!("/{}", rest.to_string_lossy())));
+        Ok(Name { id, files: vec![File::dummy(resolved.unwrap_or(normalized))] })
+    }
+}
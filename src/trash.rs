@@ -0,0 +1,349 @@
+//! 远程回收站（`sftp remove --trash`、`sftp trash`）
+//!
+//! 删除时把目标重命名到远程的回收站目录，保留原来的路径结构：`/srv/app/app.conf` 移到
+//! `<回收站>/<编号>/srv/app/app.conf`，编号为删除时的本地时间（同一秒内重复时加 `-2`、`-3` 后缀），
+//! 原路径另外记录在 `<回收站>/<编号>.path` 中。回收站默认为远程登录目录下的 `.rust-ssh-sftp-trash`，
+//! 可在配置 `[defaults]` 中用 `trash_dir` 修改；回收站目录创建后权限设为 0700。
+//!
+//! 回收站与目标不在同一文件系统时重命名会失败，此时给出警告并改为复制后删除原路径。
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use log::{info, warn};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::config::AppConfig;
+use crate::remote_copy::{self, CopyOptions};
+use crate::sftp::{join_remote, remote_parent, FileInfo, SftpClient};
+use crate::ssh::SshClient;
+
+/// 未配置 `trash_dir` 时的回收站目录
+pub const DEFAULT_DIR: &str = "~/.rust-ssh-sftp-trash";
+
+/// 回收站目录的权限
+pub const DIR_MODE: u32 = 0o700;
+
+/// 记录原路径的文件的后缀
+const ORIGIN_SUFFIX: &str = ".path";
+
+/// 同一秒内最多生成的编号数
+const MAX_ID_SUFFIX: u32 = 100;
+
+/// 回收站中的一项
+#[derive(Debug, Clone)]
+pub struct TrashEntry {
+    /// 编号（`sftp trash restore` 的参数）
+    pub id: String,
+    /// 删除前的绝对路径
+    pub original: String,
+    pub is_dir: bool,
+    /// 文件大小，目录为 0
+    pub size: u64,
+}
+
+/// 一个连接上的回收站
+pub struct Trash<'s, 'a> {
+    client: &'s SshClient,
+    sftp: &'s SftpClient<'a>,
+    /// 回收站目录的绝对路径
+    root: String,
+}
+
+impl<'s, 'a> Trash<'s, 'a> {
+    /// 使用配置中的回收站目录，开头的 `~` 按远程登录目录展开
+    pub fn open(client: &'s SshClient, sftp: &'s SftpClient<'a>, config: &AppConfig) -> Result<Self> {
+        let dir = config.defaults.trash_dir.as_deref().unwrap_or(DEFAULT_DIR);
+        Self::at(client, sftp, dir)
+    }
+
+    /// 使用指定的回收站目录
+    pub fn at(client: &'s SshClient, sftp: &'s SftpClient<'a>, dir: &str) -> Result<Self> {
+        let root = sftp.resolve_path(dir, None)?;
+        if root == "/" {
+            anyhow::bail!("回收站目录不能是根目录");
+        }
+        Ok(Self { client, sftp, root: root.trim_end_matches('/').to_string() })
+    }
+
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// 把 `path`（绝对路径）移到回收站；符号链接移动链接本身
+    pub fn move_to_trash(&self, path: &str) -> Result<TrashEntry> {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() || self.root.starts_with(&format!("{}/", path)) {
+            anyhow::bail!("不能把回收站所在的目录 {} 移到回收站", if path.is_empty() { "/" } else { path });
+        }
+        if path == self.root || path.starts_with(&format!("{}/", self.root)) {
+            anyhow::bail!("{} 已在回收站中，永久删除请使用 sftp trash empty", path);
+        }
+        let info = self.sftp.lstat(path)?;
+
+        self.ensure_root()?;
+        let id = self.new_id()?;
+        let dest = self.item_path(&id, path);
+        let placed = self.write_origin(&id, path)
+            .and_then(|()| match remote_parent(&dest) {
+                Some(parent) => self.sftp.mkdir_p(parent),
+                None => Ok(()),
+            })
+            .and_then(|()| self.move_path(&info, path, &dest));
+        if let Err(e) = placed {
+            // 编号下只有空目录和原路径记录，清理失败不影响原路径
+            let _ = self.remove_id(&id);
+            return Err(e).context(format!("无法把 {} 移到回收站", path));
+        }
+        info!("已移到回收站: {} -> {}", path, dest);
+        Ok(TrashEntry { id, original: path.to_string(), is_dir: info.is_dir && !info.is_symlink, size: info.size })
+    }
+
+    /// 回收站中的所有项，按编号（即删除时间）排序；回收站不存在时为空
+    pub fn list(&self) -> Result<Vec<TrashEntry>> {
+        if self.sftp.lstat(&self.root).is_err() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for dir in self.sftp.list_dir(&self.root)? {
+            if !dir.is_dir || dir.is_symlink {
+                continue;
+            }
+            let Ok(original) = self.read_origin(&dir.name) else {
+                warn!("回收站中的 {} 缺少原路径记录，已跳过", dir.path.display());
+                continue;
+            };
+            let Ok(info) = self.sftp.lstat(&self.item_path(&dir.name, &original)) else {
+                warn!("回收站中的 {} 已不存在（原路径 {}），已跳过", dir.name, original);
+                continue;
+            };
+            let is_dir = info.is_dir && !info.is_symlink;
+            entries.push(TrashEntry { id: dir.name, original, is_dir, size: if is_dir { 0 } else { info.size } });
+        }
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(entries)
+    }
+
+    /// 把编号为 `id` 的项移回原路径；原路径已存在时拒绝，不覆盖
+    pub fn restore(&self, id: &str) -> Result<TrashEntry> {
+        check_id(id)?;
+        let original = self.read_origin(id).with_context(|| format!("回收站中没有编号为 {} 的项", id))?;
+        let trashed = self.item_path(id, &original);
+        let info = self.sftp.lstat(&trashed).with_context(|| format!("回收站中编号为 {} 的项已不存在", id))?;
+        if self.sftp.lstat(&original).is_ok() {
+            anyhow::bail!("原路径已存在: {}（请先移走或删除后再恢复）", original);
+        }
+
+        if let Some(parent) = remote_parent(&original) {
+            self.sftp.mkdir_p(parent)?;
+        }
+        self.move_path(&info, &trashed, &original)
+            .with_context(|| format!("无法恢复 {}", original))?;
+        info!("已从回收站恢复: {} -> {}", trashed, original);
+        if let Err(e) = self.remove_id(id) {
+            warn!("无法清理回收站中的 {}: {:#}", id, e);
+        }
+        let is_dir = info.is_dir && !info.is_symlink;
+        Ok(TrashEntry { id: id.to_string(), original, is_dir, size: if is_dir { 0 } else { info.size } })
+    }
+
+    /// 永久删除回收站中的所有内容，保留回收站目录本身，返回删除的项数
+    pub fn empty(&self) -> Result<usize> {
+        if self.sftp.lstat(&self.root).is_err() {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for entry in self.sftp.list_dir(&self.root)? {
+            let path = entry.path.to_string_lossy().into_owned();
+            if entry.is_dir && !entry.is_symlink {
+                removed += 1;
+            }
+            remove_tree(self.sftp, &path)?;
+        }
+        Ok(removed)
+    }
+
+    /// 回收站不存在时创建，权限为 0700
+    fn ensure_root(&self) -> Result<()> {
+        match self.sftp.stat(&self.root) {
+            Ok(info) if info.is_dir => return Ok(()),
+            Ok(_) => anyhow::bail!("回收站路径 {} 已存在且不是目录", self.root),
+            Err(_) => {}
+        }
+        if let Some(parent) = remote_parent(&self.root) {
+            self.sftp.mkdir_p(parent)?;
+        }
+        self.sftp.mkdir(&self.root)?;
+        self.sftp.set_metadata(&self.root, Some(DIR_MODE), None)
+            .context(format!("无法把回收站 {} 的权限设为 {:o}", self.root, DIR_MODE))
+    }
+
+    /// 创建新的编号目录；同一秒内已有编号时加后缀
+    fn new_id(&self) -> Result<String> {
+        let base = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        for n in 1..=MAX_ID_SUFFIX {
+            let id = if n == 1 { base.clone() } else { format!("{}-{}", base, n) };
+            let dir = format!("{}/{}", self.root, id);
+            if self.sftp.lstat(&dir).is_ok() || self.sftp.lstat(&self.origin_path(&id)).is_ok() {
+                continue;
+            }
+            self.sftp.mkdir(&dir)?;
+            return Ok(id);
+        }
+        anyhow::bail!("同一秒内移到回收站的项过多")
+    }
+
+    /// 回收站中保存 `original` 的路径
+    fn item_path(&self, id: &str, original: &str) -> String {
+        join_remote(&format!("{}/{}", self.root, id), original.trim_start_matches('/'))
+    }
+
+    fn origin_path(&self, id: &str) -> String {
+        format!("{}/{}{}", self.root, id, ORIGIN_SUFFIX)
+    }
+
+    fn write_origin(&self, id: &str, original: &str) -> Result<()> {
+        let path = self.origin_path(id);
+        let mut writer = self.sftp.create_writer(&path)?;
+        writer.write_all(format!("{}\n", original).as_bytes())
+            .context(format!("无法写入 {}", path))
+    }
+
+    fn read_origin(&self, id: &str) -> Result<String> {
+        let path = self.origin_path(id);
+        let mut content = String::new();
+        self.sftp.open_reader(Path::new(&path))?
+            .read_to_string(&mut content)
+            .context(format!("无法读取 {}", path))?;
+        let original = content.trim_end_matches('\n');
+        if !original.starts_with('/') {
+            anyhow::bail!("{} 中的原路径无效: {}", path, original);
+        }
+        Ok(original.to_string())
+    }
+
+    /// 删除编号目录下剩余的空目录和原路径记录；目录中还有文件时失败
+    fn remove_id(&self, id: &str) -> Result<()> {
+        let dir = format!("{}/{}", self.root, id);
+        if self.sftp.lstat(&dir).is_ok() {
+            remove_empty_dirs(self.sftp, &dir)?;
+        }
+        let origin = self.origin_path(id);
+        if self.sftp.lstat(&origin).is_ok() {
+            self.sftp.remove_file(&origin)?;
+        }
+        Ok(())
+    }
+
+    /// 重命名 `from` 为 `to`；失败时（通常是跨文件系统）给出警告，改为复制后删除 `from`
+    fn move_path(&self, info: &FileInfo, from: &str, to: &str) -> Result<()> {
+        let Err(e) = self.sftp.rename(from, to) else {
+            return Ok(());
+        };
+        if info.is_symlink {
+            return Err(e).context("符号链接无法通过复制移动");
+        }
+        warn!("无法重命名 {} -> {}（可能不在同一文件系统）: {:#}，改为复制后删除原路径", from, to, e);
+        let options = CopyOptions { recursive: info.is_dir, ..Default::default() };
+        remote_copy::copy(self.client, self.sftp, from, to, options, None)
+            .context(format!("无法复制 {} -> {}", from, to))?;
+        remove_tree(self.sftp, from).context(format!("已复制到 {}，但无法删除原路径 {}", to, from))
+    }
+}
+
+/// 编号只能是回收站目录下的一级名称
+fn check_id(id: &str) -> Result<()> {
+    if id.is_empty() || id == "." || id == ".." || id.contains('/') || id.ends_with(ORIGIN_SUFFIX) {
+        anyhow::bail!("无效的回收站编号: {}", id);
+    }
+    Ok(())
+}
+
+/// 递归删除文件或目录，不跟随符号链接
+fn remove_tree(sftp: &SftpClient, path: &str) -> Result<()> {
+    let info = sftp.lstat(path)?;
+    if info.is_dir && !info.is_symlink {
+        for entry in sftp.list_dir(path)? {
+            remove_tree(sftp, &entry.path.to_string_lossy())?;
+        }
+        sftp.remove_dir(path)
+    } else {
+        sftp.remove_file(path)
+    }
+}
+
+/// 自下而上删除空目录
+fn remove_empty_dirs(sftp: &SftpClient, path: &str) -> Result<()> {
+    for entry in sftp.list_dir(path)? {
+        if entry.is_dir && !entry.is_symlink {
+            remove_empty_dirs(sftp, &entry.path.to_string_lossy())?;
+        }
+    }
+    sftp.remove_dir(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_check_id() {
+        assert!(check_id("20261016-093000").is_ok());
+        assert!(check_id("20261016-093000-2").is_ok());
+        assert!(check_id("").is_err());
+        assert!(check_id("..").is_err());
+        assert!(check_id("a/b").is_err());
+        assert!(check_id("20261016-093000.path").is_err());
+    }
+
+    #[test]
+    fn test_trash_and_restore() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        std::fs::create_dir_all(server.local_path("/srv/app/logs")).unwrap();
+        std::fs::write(server.local_path("/srv/app/app.conf"), b"port = 80").unwrap();
+        std::fs::write(server.local_path("/srv/app/logs/a.log"), b"log").unwrap();
+        let trash = Trash::at(&client, &sftp, "/.trash").unwrap();
+        assert!(trash.list().unwrap().is_empty());
+
+        // 保留原来的路径结构，回收站目录为 0700
+        let file = trash.move_to_trash("/srv/app/app.conf").unwrap();
+        assert!(!server.local_path("/srv/app/app.conf").exists());
+        let trashed = server.local_path(&format!("/.trash/{}/srv/app/app.conf", file.id));
+        assert_eq!(std::fs::read(trashed).unwrap(), b"port = 80");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(server.local_path("/.trash")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, DIR_MODE);
+        }
+
+        // 同一秒内再删除得到不同的编号
+        let dir = trash.move_to_trash("/srv/app/logs").unwrap();
+        assert_ne!(dir.id, file.id);
+        let listed = trash.list().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].original, "/srv/app/app.conf");
+        assert_eq!(listed[0].size, 9);
+        assert!(listed[1].is_dir);
+        assert!(trash.move_to_trash("/.trash").is_err());
+        assert!(trash.move_to_trash("/").is_err());
+
+        // 原路径已存在时不覆盖
+        std::fs::write(server.local_path("/srv/app/app.conf"), b"new").unwrap();
+        assert!(trash.restore(&file.id).is_err());
+        std::fs::remove_file(server.local_path("/srv/app/app.conf")).unwrap();
+        trash.restore(&file.id).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/srv/app/app.conf")).unwrap(), b"port = 80");
+        assert!(!server.local_path(&format!("/.trash/{}", file.id)).exists());
+        assert!(!server.local_path(&format!("/.trash/{}.path", file.id)).exists());
+        assert!(trash.restore(&file.id).is_err());
+
+        assert_eq!(trash.empty().unwrap(), 1);
+        assert!(trash.list().unwrap().is_empty());
+        assert!(server.local_path("/.trash").is_dir());
+        assert!(!server.local_path("/srv/app/logs").exists());
+    }
+}
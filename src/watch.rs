@@ -0,0 +1,420 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::{debug, info, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::diag;
+use crate::file_list;
+use crate::glyphs;
+use crate::interrupt::Cancelled;
+use crate::openssh_config::wildcard_match;
+use crate::session::{self, RemoteSession};
+use crate::sftp::{self, Links, SftpClient};
+
+/// 检查停止标志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 监听选项
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub local_dir: PathBuf,
+    pub remote_dir: String,
+    /// 排除的通配符模式（不含 `/` 时匹配任意一级路径名）
+    pub excludes: Vec<String>,
+    /// 本地删除时同步删除远程文件
+    pub delete: bool,
+    /// 同一路径最后一次变化后等待的时间，合并编辑器的多次写入
+    pub debounce: Duration,
+    /// 判断远程文件是否已是最新的方式
+    pub compare: Compare,
+    /// 符号链接的处理方式
+    pub links: Links,
+}
+
+/// 判断远程文件与本地文件相同（无需上传）的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    /// 大小相同且修改时间相差不超过窗口（秒），容忍时钟不准或时间精度低的文件系统
+    ModifyWindow(u64),
+    /// 只比较大小，用于修改时间不可信的文件系统
+    SizeOnly,
+}
+
+/// 远程文件是否与本地文件相同；远程没有修改时间时只有 [`Compare::SizeOnly`] 视为相同
+pub fn is_unchanged(compare: Compare, local_size: u64, local_mtime: u64, remote_size: u64, remote_mtime: Option<u64>) -> bool {
+    if local_size != remote_size {
+        return false;
+    }
+    match compare {
+        Compare::SizeOnly => true,
+        Compare::ModifyWindow(window) => remote_mtime.is_some_and(|remote| remote.abs_diff(local_mtime) <= window),
+    }
+}
+
+/// 路径是否被排除
+pub fn is_excluded(relative: &str, excludes: &[String]) -> bool {
+    excludes.iter().any(|pattern| {
+        if pattern.contains('/') {
+            wildcard_match(pattern.trim_start_matches('/'), relative)
+        } else {
+            relative.split('/').any(|component| wildcard_match(pattern, component))
+        }
+    })
+}
+
+/// 监听本地目录并把变化同步到远程，直到 `stop` 被设置
+///
+/// 连接断开时自动重连，未同步的变化保留到重连后继续处理。
+pub fn run(session: &mut RemoteSession, options: &WatchOptions, stop: Arc<AtomicBool>) -> Result<()> {
+    let local_dir = options
+        .local_dir
+        .canonicalize()
+        .context(format!("无法访问本地目录: {}", options.local_dir.display()))?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("无法创建文件监听器")?;
+    watcher
+        .watch(&local_dir, RecursiveMode::Recursive)
+        .context(format!("无法监听目录: {}", local_dir.display()))?;
+
+    if let Compare::ModifyWindow(window) = options.compare {
+        warn_clock_skew(session, window);
+    }
+
+    info!("开始监听 {} -> {}", local_dir.display(), options.remote_dir);
+    println!(
+        "{} 正在监听 {} -> {}（按 Ctrl+C 停止）",
+        glyphs::arrow().cyan(),
+        local_dir.display(),
+        options.remote_dir
+    );
+
+    // 相对路径 -> 最后一次变化的时间
+    let mut pending: HashMap<String, Instant> = HashMap::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        let sftp = SftpClient::new(session.client())?.with_cancel_flag(Arc::clone(&stop));
+        let mut failed = None;
+
+        while !stop.load(Ordering::SeqCst) {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(event)) => {
+                    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+                        continue;
+                    }
+                    for path in event.paths {
+                        let Some(relative) = relative_path(&local_dir, &path) else {
+                            continue;
+                        };
+                        if is_excluded(&relative, &options.excludes) {
+                            debug!("已排除: {}", relative);
+                            continue;
+                        }
+                        pending.insert(relative, Instant::now());
+                    }
+                }
+                Ok(Err(e)) => warn!("文件监听错误: {}", e),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("文件监听已停止"),
+            }
+
+            let mut ready: Vec<String> = pending
+                .iter()
+                .filter(|(_, changed)| changed.elapsed() >= options.debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+            // 先处理父目录
+            ready.sort();
+
+            for relative in ready {
+                match sync_path(&sftp, &local_dir, &relative, options) {
+                    Ok(()) => {
+                        pending.remove(&relative);
+                    }
+                    Err(e) if e.downcast_ref::<Cancelled>().is_some() => return Ok(()),
+                    Err(e) if session::is_connection_error(&e) => {
+                        failed = Some(e);
+                        break;
+                    }
+                    Err(e) => {
+                        pending.remove(&relative);
+                        println!("{} {} {}: {:#}", timestamp(), glyphs::fail().red(), relative, e);
+                    }
+                }
+            }
+
+            if failed.is_some() {
+                break;
+            }
+        }
+
+        let Some(err) = failed else {
+            break;
+        };
+        drop(sftp);
+        warn!("同步时连接断开: {:#}", err);
+        session.reconnect()?;
+    }
+
+    println!("{} 已停止监听", glyphs::ok().green());
+    Ok(())
+}
+
+/// 同步一个路径：存在则上传（目录则创建），不存在则按需删除远程文件
+fn sync_path(sftp: &SftpClient, local_dir: &Path, relative: &str, options: &WatchOptions) -> Result<()> {
+    let local = local_dir.join(relative);
+    let remote = file_list::join(&options.remote_dir, relative);
+
+    let is_symlink = std::fs::symlink_metadata(&local).is_ok_and(|meta| meta.file_type().is_symlink());
+    if is_symlink {
+        match options.links {
+            Links::Skip => {
+                println!("{} {} 跳过符号链接 {}（可使用 --links preserve 或 follow）", timestamp(), glyphs::warn().yellow(), relative);
+                return Ok(());
+            }
+            Links::Preserve => return sync_link(sftp, &local, &remote, relative),
+            // 目标不存在时报告错误，不当作删除处理
+            Links::Follow if !local.exists() => anyhow::bail!("符号链接的目标不存在"),
+            Links::Follow => {}
+        }
+    }
+
+    if local.is_dir() {
+        sftp.mkdir_p(&remote)?;
+        // 新目录中的文件可能在监听生效前就已写入，一并上传
+        for entry in std::fs::read_dir(&local).context(format!("无法读取目录: {}", local.display()))? {
+            let name = entry?.file_name();
+            let child = format!("{}/{}", relative, name.to_string_lossy());
+            if !is_excluded(&child, &options.excludes) {
+                sync_path(sftp, local_dir, &child, options)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if local.is_file() {
+        let metadata = std::fs::metadata(&local).context(format!("无法读取文件信息: {}", local.display()))?;
+        let local_mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_secs());
+        if let Ok(info) = sftp.stat(&remote) {
+            if !info.is_dir && is_unchanged(options.compare, metadata.len(), local_mtime, info.size, info.modified) {
+                debug!("远程文件未变化，跳过: {}", relative);
+                return Ok(());
+            }
+        }
+
+        if let Some(parent) = sftp::remote_parent(&remote) {
+            sftp.mkdir_p(parent)?;
+        }
+        sftp.upload_file(&local.to_string_lossy(), &remote, None)?;
+        // 远程修改时间与本地一致，之后按修改时间比较才有意义
+        if let Err(e) = sftp.set_metadata(&remote, None, Some(local_mtime)) {
+            warn!("无法设置远程文件的修改时间: {:#}", e);
+        }
+        println!("{} {} 已上传 {}", timestamp(), glyphs::ok().green(), relative);
+        return Ok(());
+    }
+
+    // 本地已不存在（删除或重命名）
+    if !options.delete {
+        return Ok(());
+    }
+    match sftp.stat(&remote) {
+        Ok(info) if info.is_dir => sftp.remove_dir(&remote)?,
+        Ok(_) => sftp.remove_file(&remote)?,
+        // 远程也不存在（例如临时文件），无需处理
+        Err(_) => return Ok(()),
+    }
+    println!("{} {} 已删除 {}", timestamp(), glyphs::ok().green(), relative);
+    Ok(())
+}
+
+/// 在远程重建本地的符号链接，指向的路径原样保留；远程已是指向相同路径的链接时不做改动
+fn sync_link(sftp: &SftpClient, local: &Path, remote: &str, relative: &str) -> Result<()> {
+    let target = std::fs::read_link(local).context(format!("无法读取符号链接: {}", local.display()))?;
+    let target = target.to_string_lossy().replace('\\', "/");
+
+    if let Ok(existing) = sftp.lstat(remote) {
+        if existing.is_symlink && sftp.read_link(remote).is_ok_and(|current| current == target) {
+            debug!("远程链接未变化，跳过: {}", relative);
+            return Ok(());
+        }
+        if existing.is_dir {
+            anyhow::bail!("远程已存在同名目录: {}", remote);
+        }
+        sftp.remove_file(remote)?;
+    }
+
+    if let Some(parent) = sftp::remote_parent(remote) {
+        sftp.mkdir_p(parent)?;
+    }
+    sftp.symlink(&target, remote)?;
+    println!("{} {} 已创建链接 {} -> {}", timestamp(), glyphs::ok().green(), relative, target);
+    Ok(())
+}
+
+/// 远程时钟与本机相差较大时提示，按修改时间比较的结果可能不符合预期
+fn warn_clock_skew(session: &RemoteSession, window: u64) {
+    match diag::clock_skew(session.client()) {
+        Ok(Some(skew)) if skew.unsigned_abs() > window.max(diag::SKEW_WARN_SECS as u64) => println!(
+            "{} 远程时钟与本机相差 {:+} 秒，按修改时间判断是否上传可能不准确（可调大 --modify-window 或使用 --size-only）",
+            glyphs::warn().yellow(),
+            skew
+        ),
+        Ok(_) => {}
+        Err(e) => debug!("无法检查时钟偏差: {:#}", e),
+    }
+}
+
+/// 相对于监听目录的路径，统一使用 `/` 分隔
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if components.is_empty() {
+        return None;
+    }
+    Some(components.join("/"))
+}
+
+fn timestamp() -> String {
+    format!("[{}]", chrono::Local::now().format("%H:%M:%S"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use std::thread;
+
+    #[test]
+    fn test_is_excluded() {
+        let excludes = vec!["*.swp".to_string(), ".git".to_string(), "build/*.o".to_string()];
+        assert!(is_excluded("src/.main.rs.swp", &excludes));
+        assert!(is_excluded(".git/HEAD", &excludes));
+        assert!(is_excluded("build/a.o", &excludes));
+        assert!(!is_excluded("src/build/a.o", &excludes));
+        assert!(!is_excluded("src/main.rs", &excludes));
+    }
+
+    #[test]
+    fn test_is_unchanged() {
+        let window = Compare::ModifyWindow(2);
+        assert!(is_unchanged(window, 10, 1000, 10, Some(1000)));
+        assert!(is_unchanged(window, 10, 1000, 10, Some(1002)));
+        assert!(is_unchanged(window, 10, 1000, 10, Some(998)));
+        assert!(!is_unchanged(window, 10, 1000, 10, Some(1003)));
+        assert!(!is_unchanged(window, 10, 1000, 10, Some(997)));
+        assert!(!is_unchanged(window, 10, 1000, 11, Some(1000)));
+        assert!(!is_unchanged(window, 10, 1000, 10, None));
+        assert!(!is_unchanged(Compare::ModifyWindow(0), 10, 1000, 10, Some(1001)));
+        assert!(is_unchanged(Compare::ModifyWindow(0), 10, 1000, 10, Some(1000)));
+
+        assert!(is_unchanged(Compare::SizeOnly, 10, 1000, 10, Some(5)));
+        assert!(is_unchanged(Compare::SizeOnly, 10, 1000, 10, None));
+        assert!(!is_unchanged(Compare::SizeOnly, 10, 1000, 9, Some(1000)));
+    }
+
+    #[test]
+    fn test_sync_links() {
+        let server = TestServer::start();
+        let client = server.connect();
+        let sftp = SftpClient::new(&client).unwrap();
+        let local = tempfile::tempdir().unwrap();
+        std::fs::write(local.path().join("data.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink("data.txt", local.path().join("current")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", local.path().join("stale")).unwrap();
+        std::fs::create_dir(server.local_path("/site")).unwrap();
+
+        let with_links = |links| WatchOptions {
+            local_dir: local.path().to_path_buf(),
+            remote_dir: "/site".to_string(),
+            excludes: Vec::new(),
+            delete: true,
+            debounce: Duration::ZERO,
+            compare: Compare::ModifyWindow(2),
+            links,
+        };
+
+        // skip：不上传
+        sync_path(&sftp, local.path(), "current", &with_links(Links::Skip)).unwrap();
+        assert!(std::fs::symlink_metadata(server.local_path("/site/current")).is_err());
+
+        // follow：上传链接目标的内容；目标不存在时报错，不删除远程文件
+        sync_path(&sftp, local.path(), "current", &with_links(Links::Follow)).unwrap();
+        assert_eq!(std::fs::read(server.local_path("/site/current")).unwrap(), b"data");
+        std::fs::write(server.local_path("/site/stale"), b"old").unwrap();
+        assert!(sync_path(&sftp, local.path(), "stale", &with_links(Links::Follow)).is_err());
+        assert!(server.local_path("/site/stale").exists());
+
+        // preserve：替换为指向相同路径的链接，目标不存在也照样创建
+        let preserve = with_links(Links::Preserve);
+        for name in ["current", "stale"] {
+            sync_path(&sftp, local.path(), name, &preserve).unwrap();
+        }
+        assert_eq!(std::fs::read_link(server.local_path("/site/current")).unwrap(), Path::new("data.txt"));
+        assert_eq!(std::fs::read_link(server.local_path("/site/stale")).unwrap(), Path::new("missing.txt"));
+        sync_path(&sftp, local.path(), "current", &preserve).unwrap();
+        assert_eq!(sftp.read_link("/site/current").unwrap(), "data.txt");
+    }
+
+    /// 等待条件成立，超时返回 false
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_watch_uploads_and_deletes() {
+        let server = TestServer::start();
+        let config = server.password_config();
+        let local = tempfile::tempdir().unwrap();
+        let options = WatchOptions {
+            local_dir: local.path().to_path_buf(),
+            remote_dir: "/site".to_string(),
+            excludes: vec!["*.tmp".to_string()],
+            delete: true,
+            debounce: Duration::from_millis(50),
+            compare: Compare::ModifyWindow(2),
+            links: Links::Skip,
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let watcher_stop = Arc::clone(&stop);
+        let mut session = RemoteSession::connect(config, None).unwrap();
+        let handle = thread::spawn(move || run(&mut session, &options, watcher_stop));
+        // 等待监听器启动
+        thread::sleep(Duration::from_millis(500));
+
+        std::fs::create_dir(local.path().join("css")).unwrap();
+        std::fs::write(local.path().join("css/app.css"), b"body {}").unwrap();
+        std::fs::write(local.path().join("draft.tmp"), b"x").unwrap();
+        assert!(wait_for(|| {
+            std::fs::read(server.local_path("/site/css/app.css")).is_ok_and(|data| data == b"body {}")
+        }));
+
+        std::fs::remove_file(local.path().join("css/app.css")).unwrap();
+        assert!(wait_for(|| !server.local_path("/site/css/app.css").exists()));
+        assert!(!server.local_path("/site/draft.tmp").exists());
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap().unwrap();
+    }
+}